@@ -8,12 +8,13 @@ use std::{
     time::Duration,
 };
 
+use async_stream::stream;
 use dashmap::{mapref::one::Ref, DashMap};
-use futures::{stream::FuturesUnordered, StreamExt};
+use futures::{pin_mut, stream::FuturesUnordered, Stream, StreamExt};
 use itertools::Itertools;
 #[cfg(not(madsim))]
 use tonic::transport::ClientTlsConfig;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 #[cfg(madsim)]
 use utils::ClientTlsConfig;
 
@@ -428,6 +429,10 @@ impl ClusterInfo {
 }
 
 /// Get cluster info from remote servers
+///
+/// Only the clusters which the local member, identified by its peer urls, is
+/// a member of are accepted, so that a member is never started with a cluster
+/// that it has not been added to.
 #[inline]
 pub async fn get_cluster_info_from_remote(
     init_cluster_info: &ClusterInfo,
@@ -436,34 +441,64 @@ pub async fn get_cluster_info_from_remote(
     timeout: Duration,
     tls_config: Option<&ClientTlsConfig>,
 ) -> Option<ClusterInfo> {
-    let peers = init_cluster_info.peers_addrs();
     let self_client_urls = init_cluster_info.self_client_urls();
+    let sorted_self_addr = self_peer_urls.iter().sorted().collect_vec();
+    let responses = fetch_cluster_from_remote(init_cluster_info, timeout, tls_config);
+    pin_mut!(responses);
+    while let Some(cluster_res) = responses.next().await {
+        if !cluster_res.members.iter().any(|m| {
+            m.peer_urls()
+                .iter()
+                .sorted()
+                .eq(sorted_self_addr.iter().copied())
+        }) {
+            warn!(
+                "{self_name} with peer urls {self_peer_urls:?} is not a member of {:?}",
+                cluster_res.members
+            );
+            continue;
+        }
+        info!("get cluster info from remote success: {:?}", cluster_res);
+        return Some(ClusterInfo::from_cluster(
+            cluster_res,
+            self_peer_urls,
+            self_client_urls.as_slice(),
+            self_name,
+        ));
+    }
+    None
+}
+
+/// Fetch the cluster from the peers of the initial cluster info, yields the
+/// responses of the reachable peers
+#[inline]
+pub fn fetch_cluster_from_remote(
+    init_cluster_info: &ClusterInfo,
+    timeout: Duration,
+    tls_config: Option<&ClientTlsConfig>,
+) -> impl Stream<Item = FetchClusterResponse> {
+    let peers = init_cluster_info.peers_addrs();
     let connects = rpc::connects(peers, tls_config)
         .map(|pair| pair.1)
         .collect_vec();
-    let mut futs = connects
-        .iter()
-        .map(|c| {
-            c.fetch_cluster(
-                FetchClusterRequest {
-                    linearizable: false,
-                },
-                timeout,
-            )
-        })
-        .collect::<FuturesUnordered<_>>();
-    while let Some(result) = futs.next().await {
-        if let Ok(cluster_res) = result {
-            info!("get cluster info from remote success: {:?}", cluster_res);
-            return Some(ClusterInfo::from_cluster(
-                cluster_res.into_inner(),
-                self_peer_urls,
-                self_client_urls.as_slice(),
-                self_name,
-            ));
+    stream! {
+        let mut futs = connects
+            .iter()
+            .map(|c| {
+                c.fetch_cluster(
+                    FetchClusterRequest {
+                        linearizable: false,
+                    },
+                    timeout,
+                )
+            })
+            .collect::<FuturesUnordered<_>>();
+        while let Some(result) = futs.next().await {
+            if let Ok(cluster_res) = result {
+                yield cluster_res.into_inner();
+            }
         }
     }
-    None
 }
 
 #[cfg(test)]
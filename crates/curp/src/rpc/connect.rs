@@ -253,6 +253,7 @@ pub(crate) trait InnerConnectApi: Send + Sync + 'static {
     /// Send a snapshot
     async fn install_snapshot(
         &self,
+        cluster_id: u64,
         term: u64,
         leader_id: ServerId,
         snapshot: Snapshot,
@@ -585,6 +586,7 @@ impl InnerConnectApi for Connect<InnerProtocolClient<Channel>> {
 
     async fn install_snapshot(
         &self,
+        cluster_id: u64,
         term: u64,
         leader_id: ServerId,
         snapshot: Snapshot,
@@ -592,7 +594,7 @@ impl InnerConnectApi for Connect<InnerProtocolClient<Channel>> {
         #[cfg(feature = "client-metrics")]
         let start_at = self.before_rpc_with_size(snapshot.inner().size());
 
-        let stream = install_snapshot_stream(term, leader_id, snapshot);
+        let stream = install_snapshot_stream(cluster_id, term, leader_id, snapshot);
         let mut client = self.rpc_connect.clone();
         let result = client.install_snapshot(stream).await;
 
@@ -837,6 +839,7 @@ fn heartbeat_stream(client_id: u64, interval: Duration) -> impl Stream<Item = Le
 
 /// Generate install snapshot stream
 fn install_snapshot_stream(
+    cluster_id: u64,
     term: u64,
     leader_id: ServerId,
     snapshot: Snapshot,
@@ -866,6 +869,7 @@ fn install_snapshot_stream(
                 offset,
                 data: data.freeze(),
                 done: (offset + len) == snapshot.size(),
+                cluster_id,
             };
 
             offset += len;
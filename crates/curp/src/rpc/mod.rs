@@ -308,6 +308,7 @@ impl SyncedResponse {
 impl AppendEntriesRequest {
     /// Create a new `append_entries` request
     pub(crate) fn new<C: Command>(
+        cluster_id: u64,
         term: u64,
         leader_id: ServerId,
         prev_log_index: LogIndex,
@@ -325,6 +326,7 @@ impl AppendEntriesRequest {
                 .map(|e| bincode::serialize(&e))
                 .collect::<bincode::Result<Vec<Vec<u8>>>>()?,
             leader_commit,
+            cluster_id,
         })
     }
 
@@ -360,6 +362,7 @@ impl AppendEntriesResponse {
 impl VoteRequest {
     /// Create a new vote request
     pub(crate) fn new(
+        cluster_id: u64,
         term: u64,
         candidate_id: ServerId,
         last_log_index: LogIndex,
@@ -372,6 +375,7 @@ impl VoteRequest {
             last_log_index,
             last_log_term,
             is_pre_vote,
+            cluster_id,
         }
     }
 }
@@ -933,3 +937,30 @@ impl std::fmt::Display for ProposeId {
         write!(f, "{}#{}", self.0, self.1)
     }
 }
+
+/// Identity of the peer of an inner request, i.e. the ids of the members the
+/// certificate of the peer is issued for
+///
+/// It is attached to the extensions of the inner requests by the server, the
+/// requests sent in the name of other members are rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerIdentity {
+    /// Ids of the members
+    ids: Vec<ServerId>,
+}
+
+impl PeerIdentity {
+    /// New `PeerIdentity`
+    #[inline]
+    #[must_use]
+    pub fn new(ids: Vec<ServerId>) -> Self {
+        Self { ids }
+    }
+
+    /// Whether the peer is the member
+    #[inline]
+    #[must_use]
+    pub fn contains(&self, id: ServerId) -> bool {
+        self.ids.contains(&id)
+    }
+}
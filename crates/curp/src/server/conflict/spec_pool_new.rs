@@ -1,4 +1,7 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use curp_external_api::conflict::SpeculativePoolOp;
 use parking_lot::Mutex;
@@ -17,6 +20,11 @@ pub(crate) struct SpeculativePool<C> {
     command_sps: Vec<SpObject<C>>,
     /// propose id to entry mapping
     entries: HashMap<ProposeId, PoolEntry<C>>,
+    /// Propose ids of the entries inserted before the latest term change
+    stale_ids: HashSet<ProposeId>,
+    /// The stale entries can be dropped once an entry of this term or a later
+    /// term is committed
+    stale_term: Option<u64>,
 }
 
 impl<C> SpeculativePool<C> {
@@ -25,6 +33,8 @@ impl<C> SpeculativePool<C> {
         Self {
             command_sps,
             entries: HashMap::new(),
+            stale_ids: HashSet::new(),
+            stale_term: None,
         }
     }
 
@@ -49,6 +59,7 @@ impl<C> SpeculativePool<C> {
         }
 
         let _ignore = self.entries.remove(&entry.id);
+        let _ignore = self.stale_ids.remove(&entry.id);
     }
 
     /// Removes an entry from the pool by it's propose id
@@ -58,6 +69,46 @@ impl<C> SpeculativePool<C> {
                 csp.remove(&entry);
             }
         }
+        let _ignore = self.stale_ids.remove(id);
+    }
+
+    /// Marks all entries in the pool as stale when the server moves to `term`
+    ///
+    /// The leader of `term` recovers every entry that may have been committed on
+    /// the fast path when it is elected, so once an entry of `term` is committed,
+    /// a stale entry that is still not in the log will never be synced.
+    pub(crate) fn mark_stale(&mut self, term: u64) {
+        self.stale_ids.extend(self.entries.keys().copied());
+        self.stale_term = Some(term);
+    }
+
+    /// Drops the stale entries that are not in the log after an entry of
+    /// `committed_term` is committed, `log_ids` is only called if there are
+    /// stale entries to check
+    ///
+    /// Returns the number of dropped entries
+    pub(crate) fn gc_stale(
+        &mut self,
+        committed_term: u64,
+        log_ids: impl FnOnce() -> HashSet<ProposeId>,
+    ) -> usize {
+        if self.stale_term.map_or(true, |term| committed_term < term) {
+            return 0;
+        }
+        self.stale_term = None;
+        if self.stale_ids.is_empty() {
+            return 0;
+        }
+        let log_ids = log_ids();
+        let dropped: Vec<_> = self
+            .stale_ids
+            .drain()
+            .filter(|id| !log_ids.contains(id))
+            .collect();
+        for id in &dropped {
+            self.remove_by_id(id);
+        }
+        dropped.len()
     }
 
     /// Returns all entries in the pool
@@ -133,6 +133,34 @@ fn sp_should_returns_all_entries() {
     assert_eq!(sp.len(), 10);
 }
 
+#[test]
+fn sp_should_drop_stale_entries_not_in_log() {
+    let mut sp = SpeculativePool::new(vec![Box::new(TestSp::default())]);
+    let synced = PoolEntry::new(ProposeId(1, 1), Arc::new(0));
+    let orphan = PoolEntry::new(ProposeId(1, 2), Arc::new(1));
+    let fresh = PoolEntry::new(ProposeId(1, 3), Arc::new(2));
+    assert!(sp.insert(synced.clone()).is_none());
+    assert!(sp.insert(orphan.clone()).is_none());
+    sp.mark_stale(2);
+    assert!(sp.insert(fresh.clone()).is_none());
+
+    // entries of the previous term are committed first
+    assert_eq!(
+        sp.gc_stale(1, || unreachable!("no stale entry can be dropped")),
+        0
+    );
+    assert_eq!(sp.len(), 3);
+
+    assert_eq!(sp.gc_stale(2, || [synced.id].into_iter().collect()), 1);
+    assert_eq!(sp.all(), vec![synced, fresh]);
+    // the orphan has been dropped so it does not conflict any more
+    assert!(sp.insert(orphan).is_none());
+    assert_eq!(
+        sp.gc_stale(3, || unreachable!("stale entries are dropped once")),
+        0
+    );
+}
+
 #[test]
 fn conflict_should_be_detected_in_ucp() {
     let mut ucp = UncommittedPool::new(vec![Box::new(TestUcp::default())]);
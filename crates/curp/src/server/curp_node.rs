@@ -412,6 +412,7 @@ impl<C: Command, CE: CommandExecutor<C>, RC: RoleChange> CurpNode<C, CE, RC> {
         &self,
         req: &AppendEntriesRequest,
     ) -> Result<AppendEntriesResponse, CurpError> {
+        self.check_cluster_id(req.cluster_id)?;
         let entries = req.entries()?;
 
         let result = self.curp.handle_append_entries(
@@ -436,6 +437,7 @@ impl<C: Command, CE: CommandExecutor<C>, RC: RoleChange> CurpNode<C, CE, RC> {
 
     /// Handle `Vote` requests
     pub(super) fn vote(&self, req: &VoteRequest) -> Result<VoteResponse, CurpError> {
+        self.check_cluster_id(req.cluster_id)?;
         let result = if req.is_pre_vote {
             self.curp.handle_pre_vote(
                 req.term,
@@ -517,6 +519,7 @@ impl<C: Command, CE: CommandExecutor<C>, RC: RoleChange> CurpNode<C, CE, RC> {
             })?;
         while let Some(req) = req_stream.next().await {
             let req = req?;
+            self.check_cluster_id(req.cluster_id)?;
             if !self.curp.verify_install_snapshot(
                 req.term,
                 req.leader_id,
@@ -980,6 +983,7 @@ impl<C: Command, CE: CommandExecutor<C>, RC: RoleChange> CurpNode<C, CE, RC> {
             .into_iter()
             .map(|connect| {
                 let req = VoteRequest::new(
+                    curp.cluster().cluster_id(),
                     vote.term,
                     vote.candidate_id,
                     vote.last_log_index,
@@ -1051,6 +1055,7 @@ impl<C: Command, CE: CommandExecutor<C>, RC: RoleChange> CurpNode<C, CE, RC> {
             .then(|| ae.prev_log_index + ae.entries.len().numeric_cast::<u64>());
         let is_heartbeat = ae.entries.is_empty();
         let req = AppendEntriesRequest::new(
+            curp.cluster().cluster_id(),
             ae.term,
             ae.leader_id,
             ae.prev_log_index,
@@ -1093,7 +1098,12 @@ impl<C: Command, CE: CommandExecutor<C>, RC: RoleChange> CurpNode<C, CE, RC> {
     ) -> Result<bool, CurpError> {
         let meta = snapshot.meta;
         let resp = connect
-            .install_snapshot(curp.term(), curp.id(), snapshot)
+            .install_snapshot(
+                curp.cluster().cluster_id(),
+                curp.term(),
+                curp.id(),
+                snapshot,
+            )
             .await?
             .into_inner();
         Ok(curp
@@ -1114,6 +1124,21 @@ impl<C: Command, CE: CommandExecutor<C>, RC: RoleChange> CurpNode<C, CE, RC> {
         Ok(())
     }
 
+    /// Check the cluster id of a peer request, so that members of different
+    /// clusters never take part in the consensus of each other
+    ///
+    /// A zero cluster id is sent by members that do not attach it yet
+    fn check_cluster_id(&self, peer_cluster_id: u64) -> Result<(), CurpError> {
+        let cluster_id = self.curp.cluster().cluster_id();
+        if peer_cluster_id != 0 && peer_cluster_id != cluster_id {
+            warn!("reject a request from cluster {peer_cluster_id}, local cluster is {cluster_id}");
+            return Err(CurpError::internal(format!(
+                "cluster id mismatch, local: {cluster_id}, remote: {peer_cluster_id}"
+            )));
+        }
+        Ok(())
+    }
+
     /// Get `RawCurp`
     pub(super) fn raw_curp(&self) -> Arc<RawCurp<C, RC>> {
         Arc::clone(&self.curp)
@@ -1245,10 +1270,12 @@ mod tests {
             mock_role_change(),
             Arc::clone(&task_manager),
         ));
+        let cluster_id = curp.cluster().cluster_id();
         let mut mock_connect1 = MockInnerConnectApi::default();
         mock_connect1
             .expect_append_entries()
             .times(1..)
+            .withf(move |req, _| req.cluster_id == cluster_id)
             .returning(|_, _| Ok(tonic::Response::new(AppendEntriesResponse::new_accept(0))));
         let s1_id = curp.cluster().get_id_by_name("S1").unwrap();
         mock_connect1.expect_id().return_const(s1_id);
@@ -40,6 +40,14 @@ define_metrics! {
     client_id_revokes: Counter<u64> = meter()
         .u64_counter("client_id_renews")
         .with_description("The total number of client id revokes times.")
+        .init(),
+    wal_fsync_duration_seconds: Histogram<f64> = meter()
+        .f64_histogram("wal_fsync_duration_seconds")
+        .with_description("The latency distributions of fsync called by WAL.")
+        .init(),
+    wal_fsync_batch_size: Histogram<u64> = meter()
+        .u64_histogram("wal_fsync_batch_size")
+        .with_description("The number of log appends synced by a single fsync of WAL.")
         .init()
 }
 
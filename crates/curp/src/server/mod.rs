@@ -24,10 +24,10 @@ use crate::{
         connect::Bypass, AppendEntriesRequest, AppendEntriesResponse, FetchClusterRequest,
         FetchClusterResponse, FetchReadStateRequest, FetchReadStateResponse,
         InstallSnapshotRequest, InstallSnapshotResponse, LeaseKeepAliveMsg, MoveLeaderRequest,
-        MoveLeaderResponse, ProposeConfChangeRequest, ProposeConfChangeResponse, ProposeRequest,
-        PublishRequest, PublishResponse, ShutdownRequest, ShutdownResponse, TriggerShutdownRequest,
-        TriggerShutdownResponse, TryBecomeLeaderNowRequest, TryBecomeLeaderNowResponse,
-        VoteRequest, VoteResponse,
+        MoveLeaderResponse, PeerIdentity, ProposeConfChangeRequest, ProposeConfChangeResponse,
+        ProposeRequest, PublishRequest, PublishResponse, ShutdownRequest, ShutdownResponse,
+        TriggerShutdownRequest, TriggerShutdownResponse, TryBecomeLeaderNowRequest,
+        TryBecomeLeaderNowResponse, VoteRequest, VoteResponse,
     },
 };
 use crate::{
@@ -208,6 +208,7 @@ impl<C: Command, CE: CommandExecutor<C>, RC: RoleChange> crate::rpc::InnerProtoc
         &self,
         request: tonic::Request<AppendEntriesRequest>,
     ) -> Result<tonic::Response<AppendEntriesResponse>, tonic::Status> {
+        check_peer_identity(&request, request.get_ref().leader_id)?;
         Ok(tonic::Response::new(
             self.inner.append_entries(request.get_ref())?,
         ))
@@ -218,6 +219,7 @@ impl<C: Command, CE: CommandExecutor<C>, RC: RoleChange> crate::rpc::InnerProtoc
         &self,
         request: tonic::Request<VoteRequest>,
     ) -> Result<tonic::Response<VoteResponse>, tonic::Status> {
+        check_peer_identity(&request, request.get_ref().candidate_id)?;
         Ok(tonic::Response::new(
             self.inner.vote(&request.into_inner())?,
         ))
@@ -255,6 +257,21 @@ impl<C: Command, CE: CommandExecutor<C>, RC: RoleChange> crate::rpc::InnerProtoc
     }
 }
 
+/// Check that the request is sent by the member, if the identity of the peer is
+/// verified by its certificate
+fn check_peer_identity<T>(request: &tonic::Request<T>, id: ServerId) -> Result<(), tonic::Status> {
+    if request
+        .extensions()
+        .get::<PeerIdentity>()
+        .is_some_and(|identity| !identity.contains(id))
+    {
+        return Err(tonic::Status::permission_denied(format!(
+            "the certificate of the peer is not issued for member {id}"
+        )));
+    }
+    Ok(())
+}
+
 impl<C: Command, CE: CommandExecutor<C>, RC: RoleChange> Rpc<C, CE, RC> {
     /// New `Rpc`
     ///
@@ -93,6 +93,9 @@ const CHANGE_CHANNEL_SIZE: usize = 128;
 /// Max gap between leader and learner when promoting a learner
 const MAX_PROMOTE_GAP: u64 = 500;
 
+/// Max gap between leader and the transferee when moving the leadership
+const MAX_TRANSFER_GAP: u64 = 500;
+
 /// The curp state machine
 pub struct RawCurp<C: Command, RC: RoleChange> {
     /// Curp state
@@ -297,6 +300,31 @@ enum Role {
     Leader,
 }
 
+/// The reason a conf change is rejected by the strict reconfiguration check
+///
+/// It is reported to the clients as `CurpError::InvalidConfig`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum ReconfigError {
+    /// The change leaves no voter in the cluster
+    NoVoters,
+    /// Less than a quorum of the voters after the change is active
+    NoActiveQuorum {
+        /// Number of the active voters after the change
+        active: usize,
+        /// Number of the voters after the change
+        voters: usize,
+        /// Quorum of the voters after the change
+        quorum: usize,
+    },
+}
+
+impl From<ReconfigError> for CurpError {
+    #[inline]
+    fn from(_err: ReconfigError) -> Self {
+        CurpError::invalid_config()
+    }
+}
+
 /// Relevant context for Curp
 ///
 /// WARN: To avoid deadlock, the lock order should be:
@@ -844,6 +872,7 @@ impl<C: Command, RC: RoleChange> RawCurp<C, RC> {
         if cur_role != Role::Leader {
             return Err(());
         }
+        self.lst.mark_active(follower_id);
 
         if !success {
             self.lst.update_next_index(follower_id, hint_index);
@@ -1194,17 +1223,24 @@ impl<C: Command, RC: RoleChange> RawCurp<C, RC> {
             self.lst.reset_transferee();
             return Ok(false);
         }
+        let match_index = self
+            .lst
+            .get_match_index(target_id)
+            .unwrap_or_else(|| unreachable!("node should exist,checked before"));
+        let last_log_index = self.log.read().last_log_index();
+        // an unreachable or far behind node cannot take over the leadership in time
+        if last_log_index.saturating_sub(match_index) > MAX_TRANSFER_GAP {
+            return Err(CurpError::LeaderTransfer(
+                "target node is lagging too far behind".to_owned(),
+            ));
+        }
         let last_leader_transferee = self.lst.swap_transferee(target_id);
         if last_leader_transferee.is_some_and(|id| id == target_id) {
             // Already transferring.
             return Ok(false);
         }
         self.reset_election_tick();
-        let match_index = self
-            .lst
-            .get_match_index(target_id)
-            .unwrap_or_else(|| unreachable!("node should exist,checked before"));
-        if match_index == self.log.read().last_log_index() {
+        if match_index == last_log_index {
             Ok(true)
         } else {
             let _ignore = self.sync_event(target_id).notify(1);
@@ -1460,6 +1496,43 @@ impl<C: Command, RC: RoleChange> RawCurp<C, RC> {
         if all_nodes != statuses_ids || !config.voters().is_disjoint(&config.learners) {
             return Err(CurpError::invalid_config());
         }
+        if self.cfg().strict_reconfig_check {
+            if let Err(err) = self.check_active_quorum(config.voters()) {
+                warn!("reject the conf change: {err:?}");
+                return Err(err.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that a quorum of the new voters is active, a voter is active if
+    /// the leader has heard from it within an election timeout
+    ///
+    /// The check is skipped for a single voter cluster, which has no quorum to
+    /// lose and whose new members can not be active before they are added.
+    fn check_active_quorum(&self, voters: &HashSet<ServerId>) -> Result<(), ReconfigError> {
+        if voters.is_empty() {
+            return Err(ReconfigError::NoVoters);
+        }
+        if self.cst.map_lock(|cst_l| cst_l.config.voters().len()) <= 1 {
+            return Ok(());
+        }
+        let timeout = self
+            .cfg()
+            .heartbeat_interval
+            .saturating_mul(self.cfg().follower_timeout_ticks.into());
+        let active = voters
+            .iter()
+            .filter(|&&id| id == self.id() || self.lst.is_active(id, timeout))
+            .count();
+        let required = quorum(voters.len());
+        if active < required {
+            return Err(ReconfigError::NoActiveQuorum {
+                active,
+                voters: voters.len(),
+                quorum: required,
+            });
+        }
         Ok(())
     }
 
@@ -1694,6 +1767,7 @@ impl<C: Command, RC: RoleChange> RawCurp<C, RC> {
         assert_ne!(prev_role, Role::Leader, "leader can't start election");
 
         st.term += 1;
+        self.ctx.spec_pool.lock().mark_stale(st.term);
         st.role = Role::Candidate;
         st.voted_for = Some(self.id());
         st.leader_id = None;
@@ -1738,6 +1812,9 @@ impl<C: Command, RC: RoleChange> RawCurp<C, RC> {
         metrics::get().leader_changes.add(1, &[]);
         st.role = Role::Leader;
         st.leader_id = Some(self.id());
+        // the followers are inactive until they respond to the new leader, so a
+        // conf change can't break the quorum before any of them is heard from
+        self.lst.reset_active();
         let _ig = self.ctx.leader_tx.send(Some(self.id())).ok();
         let _ignore = self.ctx.leader_event.notify(usize::MAX);
         self.ctx.role_change.on_election_win();
@@ -1759,6 +1836,9 @@ impl<C: Command, RC: RoleChange> RawCurp<C, RC> {
             // a leader fallback into the follower
             metrics::get().leader_changes.add(1, &[]);
         }
+        if term > st.term {
+            self.ctx.spec_pool.lock().mark_stale(term);
+        }
         st.term = term;
         self.lst.reset_transferee();
         st.role = Role::Follower;
@@ -1883,6 +1963,7 @@ impl<C: Command, RC: RoleChange> RawCurp<C, RC> {
     fn apply(&self, log: &mut Log<C>) {
         let mut entries = Vec::new();
         let mut resp_txs_l = self.ctx.resp_txs.lock();
+        let mut committed_term = None;
         for i in (log.last_as + 1)..=log.commit_index {
             let entry = log.get(i).unwrap_or_else(|| {
                 unreachable!(
@@ -1890,6 +1971,7 @@ impl<C: Command, RC: RoleChange> RawCurp<C, RC> {
                     log.last_log_index()
                 )
             });
+            committed_term = Some(entry.term);
             let tx = resp_txs_l.remove(&i);
             entries.push((Arc::clone(entry), tx));
             log.last_as = i;
@@ -1903,6 +1985,19 @@ impl<C: Command, RC: RoleChange> RawCurp<C, RC> {
                 i
             );
         }
+        if let Some(term) = committed_term {
+            let dropped = self
+                .ctx
+                .spec_pool
+                .lock()
+                .gc_stale(term, || log.get_cmd_ids());
+            if dropped > 0 {
+                debug!(
+                    "{} drops {dropped} stale entries from the spec pool",
+                    self.id()
+                );
+            }
+        }
         debug!("sending {} entries to after sync task", entries.len());
         let _ignore = self.ctx.as_tx.send(TaskType::Entries(entries));
         log.compact();
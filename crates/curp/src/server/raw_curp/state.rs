@@ -2,6 +2,7 @@ use std::{
     collections::{HashMap, HashSet},
     pin::Pin,
     sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::Duration,
 };
 
 use dashmap::{
@@ -14,6 +15,7 @@ use dashmap::{
 use event_listener::Event;
 use futures::{future, Future};
 use madsim::rand::{thread_rng, Rng};
+use tokio::time::Instant;
 use tracing::{debug, warn};
 
 use super::Role;
@@ -65,6 +67,8 @@ pub(super) struct FollowerStatus {
     pub(super) match_index: LogIndex,
     /// This node is a learner or not
     pub(super) is_learner: bool,
+    /// When the leader received the last response from that follower
+    pub(super) last_active: Option<Instant>,
 }
 
 impl Default for FollowerStatus {
@@ -73,6 +77,7 @@ impl Default for FollowerStatus {
             next_index: 1,
             match_index: 0,
             is_learner: false,
+            last_active: None,
         }
     }
 }
@@ -84,6 +89,7 @@ impl FollowerStatus {
             next_index,
             match_index,
             is_learner,
+            last_active: None,
         }
     }
 }
@@ -218,6 +224,28 @@ impl LeaderState {
         status.next_index = index;
     }
 
+    /// Record that the server has just responded
+    pub(super) fn mark_active(&self, id: ServerId) {
+        if let Some(mut status) = self.get_status_mut(id) {
+            status.last_active = Some(Instant::now());
+        }
+    }
+
+    /// Forget when the servers responded, they are inactive until they respond
+    /// again
+    pub(super) fn reset_active(&self) {
+        for mut status in self.statuses.iter_mut() {
+            status.last_active = None;
+        }
+    }
+
+    /// Check if the server has responded within the timeout
+    pub(super) fn is_active(&self, id: ServerId, timeout: Duration) -> bool {
+        self.get_status(id)
+            .and_then(|s| s.last_active)
+            .is_some_and(|last_active| last_active.elapsed() < timeout)
+    }
+
     /// Update `match_index` for server, will update `next_index` if possible
     pub(super) fn update_match_index(&self, id: ServerId, index: LogIndex) {
         let Some(mut status) = self.get_status_mut(id) else {
@@ -79,8 +79,9 @@ impl RawCurp<TestCommand, TestRoleChange> {
         std::mem::forget(as_rx);
         let resp_txs = Arc::new(Mutex::default());
         let id_barrier = Arc::new(IdBarrier::new());
+        let peers_ids = cluster_info.peers_ids();
 
-        Self::builder()
+        let curp = Self::builder()
             .cluster_info(cluster_info)
             .is_leader(true)
             .cmd_board(cmd_board)
@@ -97,7 +98,11 @@ impl RawCurp<TestCommand, TestRoleChange> {
             .resp_txs(resp_txs)
             .id_barrier(id_barrier)
             .build_raw_curp()
-            .unwrap()
+            .unwrap();
+        for id in peers_ids {
+            curp.lst.mark_active(id);
+        }
+        curp
     }
 
     /// Set connect for a server
@@ -783,6 +788,79 @@ fn remove_non_exists_node_should_return_node_not_exists_error() {
     assert!(matches!(resp, Err(CurpError::NodeNotExists(()))));
 }
 
+#[traced_test]
+#[test]
+fn remove_node_should_be_rejected_without_active_quorum() {
+    let task_manager = Arc::new(TaskManager::new());
+    let curp = { Arc::new(RawCurp::new_test(3, mock_role_change(), task_manager)) };
+    let s1_id = curp.cluster().get_id_by_name("S1").unwrap();
+    let s2_id = curp.cluster().get_id_by_name("S2").unwrap();
+    // S2 has never responded to the leader
+    curp.lst.remove(s2_id);
+    curp.lst.insert(s2_id, false);
+
+    let changes = vec![ConfChange::remove(s1_id)];
+    let resp = curp.check_new_config(&changes);
+    assert!(matches!(resp, Err(CurpError::InvalidConfig(()))));
+
+    curp.lst.mark_active(s2_id);
+    assert!(curp.check_new_config(&changes).is_ok());
+}
+
+#[traced_test]
+#[test]
+fn check_active_quorum_should_report_the_inactive_voters() {
+    let task_manager = Arc::new(TaskManager::new());
+    let curp = { Arc::new(RawCurp::new_test(3, mock_role_change(), task_manager)) };
+    let s1_id = curp.cluster().get_id_by_name("S1").unwrap();
+    let s2_id = curp.cluster().get_id_by_name("S2").unwrap();
+    curp.lst.remove(s2_id);
+    curp.lst.insert(s2_id, false);
+
+    let voters = HashSet::from([curp.id(), s2_id]);
+    assert_eq!(
+        curp.check_active_quorum(&voters),
+        Err(ReconfigError::NoActiveQuorum {
+            active: 1,
+            voters: 2,
+            quorum: 2
+        })
+    );
+    assert_eq!(
+        curp.check_active_quorum(&HashSet::new()),
+        Err(ReconfigError::NoVoters)
+    );
+    assert!(curp
+        .check_active_quorum(&HashSet::from([curp.id(), s1_id]))
+        .is_ok());
+}
+
+#[traced_test]
+#[test]
+fn followers_should_be_inactive_until_they_respond_after_election() {
+    let task_manager = Arc::new(TaskManager::new());
+    let curp = { Arc::new(RawCurp::new_test(3, mock_role_change(), task_manager)) };
+    let s1_id = curp.cluster().get_id_by_name("S1").unwrap();
+    let s2_id = curp.cluster().get_id_by_name("S2").unwrap();
+    let changes = vec![ConfChange::remove(s1_id)];
+    assert!(curp.check_new_config(&changes).is_ok());
+
+    // the new leader has not heard from S2 since it was elected
+    curp.become_leader(&mut *curp.st.write());
+    assert!(curp.check_new_config(&changes).is_err());
+    curp.lst.mark_active(s2_id);
+    assert!(curp.check_new_config(&changes).is_ok());
+}
+
+#[traced_test]
+#[test]
+fn add_node_to_single_voter_cluster_should_skip_the_active_quorum_check() {
+    let task_manager = Arc::new(TaskManager::new());
+    let curp = { Arc::new(RawCurp::new_test(1, mock_role_change(), task_manager)) };
+    let changes = vec![ConfChange::add(1, vec!["http://127.0.0.1:4567".to_owned()])];
+    assert!(curp.check_new_config(&changes).is_ok());
+}
+
 #[traced_test]
 #[test]
 fn update_node_should_update_the_address_of_node() {
@@ -891,6 +969,28 @@ fn leader_handle_move_leader() {
     assert!(res.is_ok_and(|b| !b));
 }
 
+#[traced_test]
+#[test]
+fn leader_handle_move_leader_to_lagging_node() {
+    let task_manager = Arc::new(TaskManager::new());
+    let curp = { Arc::new(RawCurp::new_test(3, mock_role_change(), task_manager)) };
+    for i in 1..=MAX_TRANSFER_GAP.overflow_add(1) {
+        let cmd = Arc::new(TestCommand::default());
+        let _index = curp.push_cmd(ProposeId(TEST_CLIENT_ID, i), cmd);
+    }
+    let target_id = curp.cluster().get_id_by_name("S1").unwrap();
+
+    let res = curp.handle_move_leader(target_id);
+    assert!(matches!(res, Err(CurpError::LeaderTransfer(_))));
+    assert!(curp.get_transferee().is_none());
+
+    curp.lst.update_match_index(target_id, 1);
+    let res = curp.handle_move_leader(target_id);
+    // the target is not up to date, so the leader syncs it first
+    assert!(res.is_ok_and(|b| !b));
+    assert_eq!(curp.get_transferee(), Some(target_id));
+}
+
 #[traced_test]
 #[test]
 fn follower_handle_move_leader() {
@@ -1,4 +1,4 @@
-use std::ops::Deref;
+use std::{ops::Deref, time::Duration};
 
 use engine::{Engine, EngineType, StorageEngine, StorageOps, WriteOperation};
 use parking_lot::Mutex;
@@ -6,7 +6,9 @@ use prost::Message;
 use utils::config::EngineConfig;
 
 use super::{
-    wal::{codec::DataFrame, config::WALConfig, WALStorage, WALStorageOps},
+    wal::{
+        codec::DataFrame, config::WALConfig, group_commit::GroupCommit, WALStorage, WALStorageOps,
+    },
     RecoverData, StorageApi, StorageError,
 };
 use crate::{
@@ -39,6 +41,8 @@ const WAL_SUB_DIR: &str = "wal";
 pub struct DB<C> {
     /// The WAL storage
     wal: Mutex<WALStorage<C>>,
+    /// Batches the syncs of the log entries
+    group_commit: GroupCommit,
     /// DB handle
     db: Engine,
 }
@@ -58,15 +62,16 @@ impl<C: Command> StorageApi for DB<C> {
 
     #[inline]
     fn put_log_entries(&self, entry: &[&LogEntry<Self::Command>]) -> Result<(), StorageError> {
-        self.wal
-            .lock()
-            .send_sync(
-                entry
-                    .iter()
-                    .map(Deref::deref)
-                    .map(DataFrame::Entry)
-                    .collect(),
-            )
+        self.group_commit.check_poisoned()?;
+        let seq = self.wal.lock().send(
+            entry
+                .iter()
+                .map(Deref::deref)
+                .map(DataFrame::Entry)
+                .collect(),
+        )?;
+        self.group_commit
+            .wait_synced(seq, || self.wal.lock().sync_point())
             .map_err(Into::into)
     }
 
@@ -186,9 +191,19 @@ impl<C> DB<C> {
 
         Ok(Self {
             wal: Mutex::new(wal),
+            group_commit: GroupCommit::new(Duration::ZERO),
             db,
         })
     }
+
+    /// Wait up to `max_delay` for more log entries before syncing the log, so
+    /// that more of them share a fsync
+    #[inline]
+    #[must_use]
+    pub fn with_wal_sync_max_delay(mut self, max_delay: Duration) -> Self {
+        self.group_commit = GroupCommit::new(max_delay);
+        self
+    }
 }
 
 #[cfg(test)]
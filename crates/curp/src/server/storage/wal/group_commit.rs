@@ -0,0 +1,196 @@
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use clippy_utilities::{NumericCast, OverflowArithmetic};
+use parking_lot::{Condvar, Mutex, MutexGuard};
+
+use super::SyncPoint;
+use crate::server::metrics;
+
+/// Batches the syncs of the WAL sends
+///
+/// A send waits until one sync covers it. The first waiter becomes the syncer,
+/// it syncs all the frames sent before, including those of the other waiters,
+/// while the sends arriving during the sync wait for the next one. So the
+/// concurrent sends share a single fsync.
+///
+/// A failed sync poisons the WAL. The kernel may have dropped the dirty pages
+/// it failed to write, so a later sync could succeed without them, and every
+/// pending and future send fails instead.
+#[derive(Debug)]
+pub(crate) struct GroupCommit {
+    /// The sync state
+    state: Mutex<SyncState>,
+    /// Notifies the waiters when a sync finishes
+    synced: Condvar,
+    /// The max delay of a sync to wait for more sends
+    max_delay: Duration,
+}
+
+/// The sync state of the WAL
+#[derive(Debug, Default)]
+struct SyncState {
+    /// The sequence number of the last synced send
+    synced_seq: u64,
+    /// Whether a sync is in progress
+    syncing: bool,
+    /// The error of the failed sync that poisoned the WAL
+    poisoned: Option<(io::ErrorKind, String)>,
+}
+
+impl SyncState {
+    /// Check if the WAL is poisoned by a failed sync
+    fn check_poisoned(&self) -> io::Result<()> {
+        match self.poisoned {
+            Some((kind, ref msg)) => Err(io::Error::new(
+                kind,
+                format!("WAL is poisoned by a failed sync: {msg}"),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+impl GroupCommit {
+    /// Creates a new `GroupCommit`
+    pub(crate) fn new(max_delay: Duration) -> Self {
+        Self {
+            state: Mutex::new(SyncState::default()),
+            synced: Condvar::new(),
+            max_delay,
+        }
+    }
+
+    /// Check if the WAL is poisoned, nothing should be sent to a poisoned WAL
+    ///
+    /// # Errors
+    ///
+    /// Return `io::Error` if a sync has failed
+    pub(crate) fn check_poisoned(&self) -> io::Result<()> {
+        self.state.lock().check_poisoned()
+    }
+
+    /// Waits until the send of `seq` is synced, the frames sent before are
+    /// synced at `sync_point` if no sync is in progress
+    ///
+    /// # Errors
+    ///
+    /// Return `io::Error` if the send is not synced after a sync failed, the
+    /// WAL is poisoned by the first failed sync
+    pub(crate) fn wait_synced<F>(&self, seq: u64, sync_point: F) -> io::Result<()>
+    where
+        F: FnOnce() -> io::Result<SyncPoint>,
+    {
+        let mut state = self.state.lock();
+        loop {
+            if state.synced_seq >= seq {
+                return Ok(());
+            }
+            state.check_poisoned()?;
+            if !state.syncing {
+                break;
+            }
+            self.synced.wait(&mut state);
+        }
+        state.syncing = true;
+        let result = MutexGuard::unlocked(&mut state, || {
+            if !self.max_delay.is_zero() {
+                std::thread::sleep(self.max_delay);
+            }
+            let point = sync_point()?;
+            let start = Instant::now();
+            point.sync()?;
+            metrics::get()
+                .wal_fsync_duration_seconds
+                .record(start.elapsed().as_secs_f64(), &[]);
+            Ok(point.seq())
+        });
+        state.syncing = false;
+        match result {
+            Ok(synced_seq) => {
+                if synced_seq > state.synced_seq {
+                    metrics::get().wal_fsync_batch_size.record(
+                        synced_seq.overflow_sub(state.synced_seq).numeric_cast(),
+                        &[],
+                    );
+                    state.synced_seq = synced_seq;
+                }
+            }
+            Err(ref e) => state.poisoned = Some((e.kind(), e.to_string())),
+        }
+        let _woken = self.synced.notify_all();
+        result.map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, Barrier,
+        },
+        thread,
+    };
+
+    use super::*;
+
+    #[test]
+    fn concurrent_sends_should_share_syncs() {
+        const SENDS: u64 = 8;
+        let group_commit = Arc::new(GroupCommit::new(Duration::from_millis(100)));
+        let sent = Arc::new(Mutex::new(0_u64));
+        let syncs = Arc::new(AtomicU64::new(0));
+        let barrier = Arc::new(Barrier::new(SENDS.numeric_cast()));
+        let handles: Vec<_> = (0..SENDS)
+            .map(|_| {
+                let group_commit = Arc::clone(&group_commit);
+                let sent = Arc::clone(&sent);
+                let syncs = Arc::clone(&syncs);
+                let barrier = Arc::clone(&barrier);
+                thread::spawn(move || {
+                    let _leader = barrier.wait();
+                    let seq = {
+                        let mut sent = sent.lock();
+                        *sent = sent.overflow_add(1);
+                        *sent
+                    };
+                    group_commit.wait_synced(seq, || {
+                        let _prev = syncs.fetch_add(1, Ordering::Relaxed);
+                        Ok(SyncPoint::new(*sent.lock(), None))
+                    })
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap().unwrap();
+        }
+        assert_eq!(group_commit.state.lock().synced_seq, SENDS);
+        assert!(syncs.load(Ordering::Relaxed) < SENDS);
+    }
+
+    #[test]
+    fn failed_sync_should_poison_the_wal() {
+        let group_commit = GroupCommit::new(Duration::ZERO);
+        group_commit
+            .wait_synced(1, || Ok(SyncPoint::new(1, None)))
+            .unwrap();
+        let result = group_commit.wait_synced(2, || Err(io::Error::from(io::ErrorKind::Other)));
+        assert!(result.is_err());
+        assert_eq!(group_commit.state.lock().synced_seq, 1);
+        // the sync is never retried
+        assert!(group_commit
+            .wait_synced(2, || unreachable!("the wal is poisoned"))
+            .is_err());
+        assert!(group_commit
+            .wait_synced(3, || unreachable!("the wal is poisoned"))
+            .is_err());
+        assert!(group_commit.check_poisoned().is_err());
+        // the send synced before the failure is still durable
+        group_commit
+            .wait_synced(1, || unreachable!("the send has been synced"))
+            .unwrap();
+    }
+}
@@ -5,7 +5,7 @@ use serde::{de::DeserializeOwned, Serialize};
 
 use crate::log_entry::LogEntry;
 
-use super::{codec::DataFrame, config::WALConfig, WALStorageOps};
+use super::{codec::DataFrame, config::WALConfig, SyncPoint, WALStorageOps};
 
 /// The mock WAL storage
 #[derive(Debug)]
@@ -41,6 +41,16 @@ where
         Ok(())
     }
 
+    fn send(&mut self, item: Vec<DataFrame<'_, C>>) -> io::Result<u64> {
+        self.send_sync(item)?;
+        // nothing needs to be synced
+        Ok(0)
+    }
+
+    fn sync_point(&self) -> io::Result<SyncPoint> {
+        Ok(SyncPoint::new(0, None))
+    }
+
     fn truncate_head(&mut self, compact_index: LogIndex) -> io::Result<()> {
         while self
             .entries
@@ -9,6 +9,9 @@ pub(super) mod config;
 /// WAL errors
 mod error;
 
+/// Batches the syncs of the concurrent sends
+pub(super) mod group_commit;
+
 /// File pipeline
 mod pipeline;
 
@@ -38,7 +41,7 @@ mod mock;
 /// WAL storage
 mod storage;
 
-use std::io;
+use std::{fs::File, io};
 
 use codec::DataFrame;
 use config::WALConfig;
@@ -58,6 +61,15 @@ pub(crate) trait WALStorageOps<C> {
     /// Send frames with fsync
     fn send_sync(&mut self, item: Vec<DataFrame<'_, C>>) -> io::Result<()>;
 
+    /// Send frames without fsync, returns the sequence number of the send
+    ///
+    /// The frames are durable after a `SyncPoint` whose sequence number is not
+    /// less than it is synced, a storage that needs no sync returns 0
+    fn send(&mut self, item: Vec<DataFrame<'_, C>>) -> io::Result<u64>;
+
+    /// Get the point to sync all the frames sent before
+    fn sync_point(&self) -> io::Result<SyncPoint>;
+
     /// Tuncate all the logs whose index is less than or equal to `compact_index`
     ///
     /// `compact_index` should be the smallest index required in CURP
@@ -104,6 +116,20 @@ where
         }
     }
 
+    fn send(&mut self, item: Vec<DataFrame<'_, C>>) -> io::Result<u64> {
+        match *self {
+            WALStorage::Persistent(ref mut s) => s.send(item),
+            WALStorage::Memory(ref mut s) => s.send(item),
+        }
+    }
+
+    fn sync_point(&self) -> io::Result<SyncPoint> {
+        match *self {
+            WALStorage::Persistent(ref s) => s.sync_point(),
+            WALStorage::Memory(ref s) => s.sync_point(),
+        }
+    }
+
     fn truncate_head(&mut self, compact_index: LogIndex) -> io::Result<()> {
         match *self {
             WALStorage::Persistent(ref mut s) => s.truncate_head(compact_index),
@@ -118,3 +144,29 @@ where
         }
     }
 }
+
+/// A point of the WAL to sync the frames sent before it
+#[derive(Debug)]
+pub(crate) struct SyncPoint {
+    /// The sequence number of the last send before the point
+    seq: u64,
+    /// The file to sync, `None` if nothing needs to be synced
+    file: Option<File>,
+}
+
+impl SyncPoint {
+    /// Creates a new `SyncPoint`
+    pub(super) fn new(seq: u64, file: Option<File>) -> Self {
+        Self { seq, file }
+    }
+
+    /// The sequence number of the last send covered by the point
+    pub(crate) fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Syncs the frames sent before the point to disk
+    pub(crate) fn sync(&self) -> io::Result<()> {
+        self.file.as_ref().map_or(Ok(()), File::sync_data)
+    }
+}
@@ -147,19 +147,38 @@ impl WALSegment {
     }
 
     /// Writes an item to the segment
-    pub(super) fn write_sync<U, Item>(&mut self, item: Item, mut encoder: U) -> io::Result<()>
+    pub(super) fn write_sync<U, Item>(&mut self, item: Item, encoder: U) -> io::Result<()>
+    where
+        U: Encoder<Item, Error = io::Error>,
+    {
+        self.write(item, encoder)?;
+        self.sync()
+    }
+
+    /// Writes an item to the segment without syncing it to disk
+    pub(super) fn write<U, Item>(&mut self, item: Item, mut encoder: U) -> io::Result<()>
     where
         U: Encoder<Item, Error = io::Error>,
     {
         let encoded_bytes = encoder.encode(item)?;
         self.file.write_all(&encoded_bytes)?;
         self.file.flush()?;
-        self.file.sync_data()?;
         self.update_size(encoded_bytes.len().numeric_cast());
 
         Ok(())
     }
 
+    /// Syncs the written items to disk
+    pub(super) fn sync(&self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+
+    /// Gets a handle of the segment file to sync the written items without
+    /// borrowing the segment
+    pub(super) fn sync_handle(&self) -> io::Result<File> {
+        self.file.try_clone()
+    }
+
     /// Read all items from the segment
     #[allow(clippy::indexing_slicing)]
     #[allow(clippy::arithmetic_side_effects)] // only used for slice indices
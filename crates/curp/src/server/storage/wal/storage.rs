@@ -18,7 +18,7 @@ use super::{
     remover::SegmentRemover,
     segment::WALSegment,
     util::{self, LockedFile},
-    WALStorageOps, WAL_FILE_EXT,
+    SyncPoint, WALStorageOps, WAL_FILE_EXT,
 };
 
 /// The WAL storage
@@ -34,6 +34,8 @@ pub(crate) struct WALStorage<C> {
     next_segment_id: u64,
     /// The next log index
     next_log_index: LogIndex,
+    /// The number of sends
+    sent: u64,
     /// The phantom data
     _phantom: PhantomData<C>,
 }
@@ -51,6 +53,7 @@ impl<C> WALStorage<C> {
             segments: vec![],
             next_segment_id: 0,
             next_log_index: 0,
+            sent: 0,
             _phantom: PhantomData,
         })
     }
@@ -130,6 +133,34 @@ where
         Ok(())
     }
 
+    fn send(&mut self, item: Vec<DataFrame<'_, C>>) -> io::Result<u64> {
+        let last_segment = self
+            .segments
+            .last_mut()
+            .unwrap_or_else(|| unreachable!("there should be at least on segment"));
+        if let Some(DataFrame::Entry(entry)) = item.last() {
+            self.next_log_index = entry.index.overflow_add(1);
+        }
+        last_segment.write(item, WAL::new())?;
+        self.sent = self.sent.overflow_add(1);
+
+        if last_segment.is_full() {
+            // a sync point only covers the last segment
+            last_segment.sync()?;
+            self.open_new_segment()?;
+        }
+
+        Ok(self.sent)
+    }
+
+    fn sync_point(&self) -> io::Result<SyncPoint> {
+        let last_segment = self
+            .segments
+            .last()
+            .unwrap_or_else(|| unreachable!("there should be at least on segment"));
+        Ok(SyncPoint::new(self.sent, Some(last_segment.sync_handle()?)))
+    }
+
     /// Truncate all the logs whose index is less than or equal to
     /// `compact_index`
     ///
@@ -1,6 +1,12 @@
 use std::path::Path;
 
-use crate::{api::snapshot_api::SnapshotApi, error::EngineError, TransactionApi};
+use utils::config::{CompressionConfig, DurabilityConfig};
+
+use crate::{
+    api::{snapshot_api::SnapshotApi, view_api::ViewApi},
+    error::EngineError,
+    TransactionApi,
+};
 
 /// The `StorageEngine` trait
 #[async_trait::async_trait]
@@ -9,10 +15,15 @@ pub trait StorageEngine: Send + Sync + 'static + std::fmt::Debug {
     type Snapshot: SnapshotApi;
     /// The transaction type
     type Transaction<'db>: TransactionApi;
+    /// The point-in-time view type
+    type View<'db>: ViewApi;
 
     /// Creates a transaction
     fn transaction(&self) -> Self::Transaction<'_>;
 
+    /// Creates a consistent view of the current state of all the tables
+    fn view(&self) -> Self::View<'_>;
+
     /// Get all the values of the given table
     ///
     /// # Errors
@@ -22,6 +33,16 @@ pub trait StorageEngine: Send + Sync + 'static + std::fmt::Debug {
     #[allow(clippy::type_complexity)] // it's clear that (Vec<u8>, Vec<u8>) is a key-value pair
     fn get_all(&self, table: &str) -> Result<Vec<(Vec<u8>, Vec<u8>)>, EngineError>;
 
+    /// Hint the engine that the keys of the given table are looked up by their
+    /// first `len` bytes, so it may build prefix bloom filters to skip the data
+    /// without the prefix in the seeks of `get_prefix`
+    ///
+    /// # Errors
+    ///
+    /// Return `EngineError::TableNotFound` if the given table does not exist
+    /// Return `EngineError` if the engine rejects the prefix
+    fn set_prefix_len(&self, table: &str, len: usize) -> Result<(), EngineError>;
+
     /// Get a snapshot of the current state of the database
     ///
     /// # Errors
@@ -53,4 +74,88 @@ pub trait StorageEngine: Send + Sync + 'static + std::fmt::Debug {
     ///
     /// Return `EngineError` if met some errors when get file size
     fn file_size(&self) -> Result<u64, EngineError>;
+
+    /// Get the estimated size of the live data of the engine (Measured in bytes),
+    /// the rest of the file size is taken by stale data not yet compacted
+    ///
+    /// # Errors
+    ///
+    /// Return `EngineError` if met some errors when get live data size
+    fn live_data_size(&self) -> Result<u64, EngineError>;
+
+    /// Get the estimated size of the given table (Measured in bytes), it's
+    /// cheap to call as the data in memory is not flushed
+    ///
+    /// # Errors
+    ///
+    /// Return `EngineError::TableNotFound` if the given table does not exist
+    /// Return `EngineError` if met some errors when get table size
+    fn table_size(&self, table: &str) -> Result<u64, EngineError>;
+
+    /// Compact all the data of the engine to reclaim the space taken by stale
+    /// data, it may take a long time and blocks the current thread
+    ///
+    /// # Errors
+    ///
+    /// Return `EngineError` if met some errors when defragment
+    fn defragment(&self) -> Result<(), EngineError>;
+
+    /// Compact the data of the given table in the key range `[from, to]` to
+    /// reclaim the space taken by the deleted records, `None` means unbounded.
+    /// Returns the bytes reclaimed, it blocks the current thread
+    ///
+    /// # Errors
+    ///
+    /// Return `EngineError::TableNotFound` if the given table does not exist
+    /// Return `EngineError` if met some errors when compact
+    fn compact_range(
+        &self,
+        table: &str,
+        from: Option<&[u8]>,
+        to: Option<&[u8]>,
+    ) -> Result<u64, EngineError>;
+
+    /// Get the statistics of the engine since it's opened
+    ///
+    /// # Errors
+    ///
+    /// Return `EngineError` if met some errors when get the statistics
+    fn stats(&self) -> Result<EngineStats, EngineError>;
+
+    /// Change the block compression of the given table online, the data
+    /// written afterward are compressed with it, and the existing data are
+    /// compressed again when they are compacted
+    ///
+    /// # Errors
+    ///
+    /// Return `EngineError::TableNotFound` if the given table does not exist
+    /// Return `EngineError::InvalidArgument` if the codec is not supported
+    /// Return `EngineError` if the engine rejects the compression
+    fn set_compression(
+        &self,
+        table: &str,
+        compression: CompressionConfig,
+    ) -> Result<(), EngineError>;
+
+    /// Change the durability of the writes that don't ask to be synced, the
+    /// writes asking to be synced are always synced
+    ///
+    /// # Errors
+    ///
+    /// Return `EngineError` if the engine fails to apply the durability
+    fn set_durability(&self, durability: DurabilityConfig) -> Result<(), EngineError>;
+}
+
+/// The statistics of a storage engine
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct EngineStats {
+    /// The total time the writes are stalled, in microseconds
+    pub write_stall_micros: u64,
+    /// The number of the finished compactions
+    pub compactions: u64,
+    /// The total time taken by the compactions, in microseconds
+    pub compaction_micros: u64,
+    /// The number of the files opened by the engine
+    pub open_files: u64,
 }
@@ -6,3 +6,5 @@ pub(crate) mod operation;
 pub(crate) mod snapshot_api;
 /// Transaction trait definition;
 pub(crate) mod transaction_api;
+/// Point-in-time view trait definition
+pub(crate) mod view_api;
@@ -38,6 +38,20 @@ pub trait StorageOps {
         table: &str,
         keys: &[impl AsRef<[u8]>],
     ) -> Result<Vec<Option<Vec<u8>>>, EngineError>;
+
+    /// Get the key-value pairs of the given table whose keys start with the
+    /// prefix, in the order of the keys
+    ///
+    /// # Errors
+    ///
+    /// Return `EngineError::TableNotFound` if the given table does not exist
+    /// Return `EngineError` if met some errors
+    #[allow(clippy::type_complexity)] // it's clear that (Vec<u8>, Vec<u8>) is a key-value pair
+    fn get_prefix(
+        &self,
+        table: &str,
+        prefix: &[u8],
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, EngineError>;
 }
 
 /// Write operation
@@ -0,0 +1,37 @@
+use crate::EngineError;
+
+/// Api for the consistent point-in-time views of the engine
+///
+/// A view sees all the tables as of its creation. The writes after the creation
+/// are neither visible to it nor blocked by it.
+pub trait ViewApi {
+    /// Get the value of the key in the given table
+    ///
+    /// # Errors
+    ///
+    /// - Return `EngineError::TableNotFound` if the given table does not exist
+    /// - Return `EngineError` if met some errors
+    fn get(&self, table: &str, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>, EngineError>;
+
+    /// Iterate over the key-value pairs of the given table in the order of the
+    /// keys, the pairs are read lazily
+    ///
+    /// # Errors
+    ///
+    /// - Return `EngineError::TableNotFound` if the given table does not exist
+    /// - Return `EngineError` if met some errors
+    #[allow(clippy::type_complexity)] // it's clear that (Vec<u8>, Vec<u8>) is a key-value pair
+    fn iter(
+        &self,
+        table: &str,
+    ) -> Result<Box<dyn Iterator<Item = Result<(Vec<u8>, Vec<u8>), EngineError>> + '_>, EngineError>;
+
+    /// Get the last key-value pair of the given table in the order of the keys,
+    /// it's found by a reverse seek
+    ///
+    /// # Errors
+    ///
+    /// - Return `EngineError::TableNotFound` if the given table does not exist
+    /// - Return `EngineError` if met some errors
+    fn last(&self, table: &str) -> Result<Option<(Vec<u8>, Vec<u8>)>, EngineError>;
+}
@@ -0,0 +1,349 @@
+#![cfg_attr(madsim, allow(dead_code))] // the cipher is only used by the rocksdb engine
+
+use std::{collections::BTreeMap, fmt, fs, path::Path, sync::Arc};
+
+use clippy_utilities::OverflowArithmetic;
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    rand::{SecureRandom, SystemRandom},
+};
+
+use crate::error::EngineError;
+
+/// Length of the AES-256 keys
+pub const KEY_LEN: usize = 32;
+
+/// Leading bytes of the encrypted values
+///
+/// The values written before encryption is enabled are read as is, a protobuf
+/// encoded value never starts with `0xFF` since wire type 7 is invalid.
+const MAGIC: [u8; 5] = [0xFF, b'X', b'E', b'N', b'C'];
+
+/// Length of the key id of the encrypted values
+const KEY_ID_LEN: usize = 4;
+
+/// Length of the header of the encrypted values: the magic, the key id and the
+/// nonce
+const HEADER_LEN: usize = MAGIC.len() + KEY_ID_LEN + NONCE_LEN;
+
+/// Provider of the keys used to encrypt the values of the engine
+///
+/// Each key is identified by an id which is stored with the values encrypted by
+/// the key, so the keys can be rotated: new values are encrypted by the current
+/// key while the values encrypted by the former keys can still be decrypted
+/// as long as those keys are provided. The keys are looked up on every read
+/// and write, a provider backed by an external key management service should
+/// cache them.
+pub trait KeyProvider: Send + Sync + fmt::Debug + 'static {
+    /// Get the id and the content of the key to encrypt new values
+    ///
+    /// # Errors
+    ///
+    /// Return `EngineError::Encryption` if the key is not available
+    fn current_key(&self) -> Result<(u32, [u8; KEY_LEN]), EngineError>;
+
+    /// Get the content of the key with the given id to decrypt values
+    ///
+    /// # Errors
+    ///
+    /// Return `EngineError::Encryption` if the key is not available
+    fn key(&self, id: u32) -> Result<[u8; KEY_LEN], EngineError>;
+}
+
+/// Key provider of a static set of keys, the key with the largest id is the
+/// current key
+pub struct StaticKeyProvider {
+    /// Keys by their ids
+    keys: BTreeMap<u32, [u8; KEY_LEN]>,
+}
+
+impl StaticKeyProvider {
+    /// New `StaticKeyProvider`
+    ///
+    /// # Errors
+    ///
+    /// Return `EngineError::Encryption` if there is no key
+    #[inline]
+    pub fn new(keys: impl IntoIterator<Item = (u32, [u8; KEY_LEN])>) -> Result<Self, EngineError> {
+        let keys: BTreeMap<_, _> = keys.into_iter().collect();
+        if keys.is_empty() {
+            return Err(EngineError::Encryption("no encryption key".to_owned()));
+        }
+        Ok(Self { keys })
+    }
+
+    /// Read the keys from a key file, each line of the file is a key in the form
+    /// of `<id>:<hex encoded key>`, blank lines and lines starting with `#` are
+    /// ignored
+    ///
+    /// # Errors
+    ///
+    /// Return `EngineError` if the file cannot be read or is malformed
+    #[inline]
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, EngineError> {
+        let content = fs::read_to_string(path.as_ref())?;
+        let keys = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(parse_key_line)
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::new(keys)
+    }
+}
+
+impl fmt::Debug for StaticKeyProvider {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // never print the keys
+        f.debug_struct("StaticKeyProvider")
+            .field("key_ids", &self.keys.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl KeyProvider for StaticKeyProvider {
+    #[inline]
+    fn current_key(&self) -> Result<(u32, [u8; KEY_LEN]), EngineError> {
+        self.keys
+            .last_key_value()
+            .map(|(&id, &key)| (id, key))
+            .ok_or_else(|| EngineError::Encryption("no encryption key".to_owned()))
+    }
+
+    #[inline]
+    fn key(&self, id: u32) -> Result<[u8; KEY_LEN], EngineError> {
+        self.keys
+            .get(&id)
+            .copied()
+            .ok_or_else(|| EngineError::Encryption(format!("encryption key {id} not found")))
+    }
+}
+
+/// Parse a line of the key file
+fn parse_key_line(line: &str) -> Result<(u32, [u8; KEY_LEN]), EngineError> {
+    let invalid = |reason: &str| EngineError::Encryption(format!("invalid key line, {reason}"));
+    let (id, hex) = line
+        .split_once(':')
+        .ok_or_else(|| invalid("expect `<id>:<hex encoded key>`"))?;
+    let id = id
+        .trim()
+        .parse()
+        .map_err(|_e| invalid("the id is not an u32"))?;
+    let hex = hex.trim().as_bytes();
+    if hex.len() != KEY_LEN.overflow_mul(2) {
+        return Err(invalid("expect a 256 bits key"));
+    }
+    let mut key = [0; KEY_LEN];
+    for (byte, digits) in key.iter_mut().zip(hex.chunks_exact(2)) {
+        *byte = std::str::from_utf8(digits)
+            .ok()
+            .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| invalid("the key is not hex encoded"))?;
+    }
+    Ok((id, key))
+}
+
+/// Cipher of the values of an engine, values are encrypted by AES-256-GCM,
+/// the name of the table and the key of a value are authenticated with it so
+/// that a value cannot be moved to another key
+#[derive(Debug, Clone)]
+pub(crate) struct ValueCipher {
+    /// Provider of the keys
+    provider: Arc<dyn KeyProvider>,
+    /// Generator of the nonces
+    rng: SystemRandom,
+}
+
+impl ValueCipher {
+    /// New `ValueCipher`
+    pub(crate) fn new(provider: Arc<dyn KeyProvider>) -> Self {
+        Self {
+            provider,
+            rng: SystemRandom::new(),
+        }
+    }
+
+    /// Encrypt a value by the current key
+    pub(crate) fn encrypt(
+        &self,
+        table: &str,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<Vec<u8>, EngineError> {
+        let (key_id, content) = self.provider.current_key()?;
+        let mut nonce = [0; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_e| EngineError::Encryption("failed to generate nonce".to_owned()))?;
+        let mut in_out = value.to_vec();
+        sealing_key(&content)?
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(aad(table, key)),
+                &mut in_out,
+            )
+            .map_err(|_e| EngineError::Encryption("failed to encrypt value".to_owned()))?;
+        let mut encrypted = Vec::with_capacity(HEADER_LEN.overflow_add(in_out.len()));
+        encrypted.extend_from_slice(&MAGIC);
+        encrypted.extend_from_slice(&key_id.to_le_bytes());
+        encrypted.extend_from_slice(&nonce);
+        encrypted.extend_from_slice(&in_out);
+        Ok(encrypted)
+    }
+
+    /// Decrypt a value, values written before encryption is enabled are
+    /// returned as is
+    pub(crate) fn decrypt(
+        &self,
+        table: &str,
+        key: &[u8],
+        value: Vec<u8>,
+    ) -> Result<Vec<u8>, EngineError> {
+        let Some((key_id, nonce)) = header(&value) else {
+            return Ok(value);
+        };
+        let content = self.provider.key(key_id)?;
+        let mut in_out = value;
+        let plain_len = sealing_key(&content)?
+            .open_within(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(aad(table, key)),
+                &mut in_out,
+                HEADER_LEN..,
+            )
+            .map_err(|_e| {
+                EngineError::Encryption(format!(
+                    "failed to decrypt the value of key {key:?} in table {table}"
+                ))
+            })?
+            .len();
+        in_out.truncate(plain_len);
+        Ok(in_out)
+    }
+
+    /// Whether the value is encrypted by the current key, values encrypted by
+    /// the former keys or not encrypted should be encrypted again
+    pub(crate) fn is_current(&self, value: &[u8]) -> Result<bool, EngineError> {
+        let (current, _) = self.provider.current_key()?;
+        Ok(header(value).is_some_and(|(key_id, _)| key_id == current))
+    }
+}
+
+/// Build the AES-256-GCM key
+fn sealing_key(content: &[u8; KEY_LEN]) -> Result<LessSafeKey, EngineError> {
+    UnboundKey::new(&AES_256_GCM, content)
+        .map(LessSafeKey::new)
+        .map_err(|_e| EngineError::Encryption("invalid encryption key".to_owned()))
+}
+
+/// The additional authenticated data of a value
+fn aad(table: &str, key: &[u8]) -> Vec<u8> {
+    // table names never contain a zero byte
+    [table.as_bytes(), &[0], key].concat()
+}
+
+/// Get the key id and the nonce of an encrypted value, `None` if the value is
+/// not encrypted
+fn header(value: &[u8]) -> Option<(u32, [u8; NONCE_LEN])> {
+    let rest = value.strip_prefix(&MAGIC)?;
+    if rest.len()
+        < KEY_ID_LEN
+            .overflow_add(NONCE_LEN)
+            .overflow_add(AES_256_GCM.tag_len())
+    {
+        return None;
+    }
+    let (key_id, rest) = rest.split_at(KEY_ID_LEN);
+    let key_id = u32::from_le_bytes(key_id.try_into().ok()?);
+    let nonce = rest.get(..NONCE_LEN)?.try_into().ok()?;
+    Some((key_id, nonce))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn provider(ids: &[u32]) -> Arc<StaticKeyProvider> {
+        Arc::new(
+            StaticKeyProvider::new(
+                ids.iter()
+                    .map(|&id| (id, [u8::try_from(id).unwrap(); KEY_LEN])),
+            )
+            .unwrap(),
+        )
+    }
+
+    #[test]
+    fn encrypted_value_should_be_bound_to_its_key() {
+        let cipher = ValueCipher::new(provider(&[1]));
+        let encrypted = cipher.encrypt("kv", b"key", b"value").unwrap();
+        assert!(!encrypted.windows(5).any(|w| w == b"value"));
+        assert!(cipher.is_current(&encrypted).unwrap());
+        assert_eq!(
+            cipher.decrypt("kv", b"key", encrypted.clone()).unwrap(),
+            b"value"
+        );
+        assert!(cipher.decrypt("kv", b"other", encrypted.clone()).is_err());
+        assert!(cipher.decrypt("lease", b"key", encrypted.clone()).is_err());
+
+        let mut tampered = encrypted;
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(cipher.decrypt("kv", b"key", tampered).is_err());
+    }
+
+    #[test]
+    fn rotated_keys_should_decrypt_former_values() {
+        let old = ValueCipher::new(provider(&[1]));
+        let encrypted = old.encrypt("kv", b"key", b"value").unwrap();
+
+        let rotated = ValueCipher::new(provider(&[1, 2]));
+        assert!(!rotated.is_current(&encrypted).unwrap());
+        assert_eq!(
+            rotated.decrypt("kv", b"key", encrypted.clone()).unwrap(),
+            b"value"
+        );
+        let reencrypted = rotated.encrypt("kv", b"key", b"value").unwrap();
+        assert!(rotated.is_current(&reencrypted).unwrap());
+
+        let removed = ValueCipher::new(provider(&[2]));
+        assert!(matches!(
+            removed.decrypt("kv", b"key", encrypted),
+            Err(EngineError::Encryption(_))
+        ));
+    }
+
+    #[test]
+    fn plain_value_should_be_read_as_is() {
+        let cipher = ValueCipher::new(provider(&[1]));
+        assert_eq!(
+            cipher.decrypt("kv", b"key", b"plain".to_vec()).unwrap(),
+            b"plain"
+        );
+        assert!(!cipher.is_current(b"plain").unwrap());
+    }
+
+    #[test]
+    fn parse_key_file() {
+        let path = std::env::temp_dir().join(format!("key-file-{}", uuid::Uuid::new_v4()));
+        fs::write(
+            &path,
+            format!(
+                "# rotated on 2024-01-01\n1:{}\n\n2: {}\n",
+                "00".repeat(KEY_LEN),
+                "aB".repeat(KEY_LEN)
+            ),
+        )
+        .unwrap();
+        let provider = StaticKeyProvider::from_file(&path).unwrap();
+        assert_eq!(provider.current_key().unwrap(), (2, [0xAB; KEY_LEN]));
+        assert_eq!(provider.key(1).unwrap(), [0; KEY_LEN]);
+        assert!(!format!("{provider:?}").contains("171"));
+
+        for content in ["", "1:00", "x:00", &format!("1:{}", "zz".repeat(KEY_LEN))] {
+            fs::write(&path, content).unwrap();
+            assert!(StaticKeyProvider::from_file(&path).is_err(), "{content}");
+        }
+        fs::remove_file(path).unwrap();
+    }
+}
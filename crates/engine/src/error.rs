@@ -23,4 +23,7 @@ pub enum EngineError {
     /// The Snapshot is invalid
     #[error("The Snapshot is invalid")]
     InvalidSnapshot,
+    /// Failed to encrypt or decrypt a value
+    #[error("Encryption Error: {0}")]
+    Encryption(String),
 }
@@ -1,4 +1,9 @@
 //! Storage
+//!
+//! The storage layer only talks to an engine through [`StorageEngine`],
+//! [`StorageOps`], [`TransactionApi`], [`ViewApi`] and [`SnapshotApi`]. [`MemoryEngine`] is a
+//! complete in-memory implementation of them, it serves as the reference engine
+//! for tests and embedders implementing their own engines.
 #![deny(
     // The following are allowed by default lints according to
     // https://doc.rust-lang.org/rustc/lints/listing/allowed-by-default.html
@@ -171,9 +176,11 @@
 
 /// Engine and Snapshot API Definition
 mod api;
+/// Values Encryption and Key Providers
+mod encryption;
 /// Engine Error Definition
 mod error;
-/// Memory Storage Engine, it's test only
+/// Memory Storage Engine, the reference implementation of the engine traits
 mod memory_engine;
 /// Metrics for engine
 mod metrics;
@@ -190,12 +197,15 @@ mod snapshot_allocator;
 
 pub use crate::{
     api::{
-        engine_api::StorageEngine,
+        engine_api::{EngineStats, StorageEngine},
         operation::{StorageOps, WriteOperation},
         snapshot_api::{SnapshotAllocator, SnapshotApi},
         transaction_api::TransactionApi,
+        view_api::ViewApi,
     },
+    encryption::{KeyProvider, StaticKeyProvider, KEY_LEN},
     error::EngineError,
-    proxy::{Engine, EngineType, Snapshot, Transaction},
+    memory_engine::{MemoryEngine, MemorySnapshot, MemoryTransaction, MemoryView},
+    proxy::{Engine, EngineType, Snapshot, Transaction, View},
     snapshot_allocator::{MemorySnapshotAllocator, RocksSnapshotAllocator},
 };
@@ -10,23 +10,33 @@ use std::{
 };
 
 use bytes::{Bytes, BytesMut};
-use clippy_utilities::NumericCast;
+use clippy_utilities::{NumericCast, OverflowArithmetic};
 use parking_lot::{RwLock, RwLockWriteGuard};
 use tokio::io::AsyncWriteExt;
 use tokio_util::io::read_buf;
+use utils::config::{CompressionConfig, DurabilityConfig};
 
 use crate::{
-    api::{engine_api::StorageEngine, snapshot_api::SnapshotApi},
+    api::{
+        engine_api::{EngineStats, StorageEngine},
+        snapshot_api::SnapshotApi,
+        view_api::ViewApi,
+    },
     error::EngineError,
     StorageOps, WriteOperation,
 };
 
-pub(super) use self::transaction::MemoryTransaction;
+pub use self::transaction::MemoryTransaction;
 
 /// A helper type to store the key-value pairs for the `MemoryEngine`
 type MemoryTable = HashMap<Vec<u8>, Vec<u8>>;
 
 /// Memory Storage Engine Implementation
+///
+/// All tables live in a single lock protected map, writes are visible as soon
+/// as they return and nothing is persisted, so `sync` is ignored. Transactions
+/// buffer their writes and apply them at once on commit. It is cheap to clone,
+/// the clones share the same data.
 #[derive(Clone, Debug, Default)]
 pub struct MemoryEngine {
     /// The inner storage engine of `MemoryStorage`
@@ -34,9 +44,10 @@ pub struct MemoryEngine {
 }
 
 impl MemoryEngine {
-    /// New `MemoryEngine`
+    /// New `MemoryEngine` with the given tables
     #[inline]
-    pub(crate) fn new(tables: &[&'static str]) -> Self {
+    #[must_use]
+    pub fn new(tables: &[&'static str]) -> Self {
         let mut inner: HashMap<String, HashMap<Vec<u8>, Vec<u8>>> = HashMap::new();
         for table in tables {
             let _ignore = inner.entry((*table).to_owned()).or_default();
@@ -98,6 +109,15 @@ impl MemoryEngine {
 impl StorageEngine for MemoryEngine {
     type Snapshot = MemorySnapshot;
     type Transaction<'db> = MemoryTransaction;
+    type View<'db> = MemoryView;
+
+    /// The view of the memory engine is a copy of its tables
+    #[inline]
+    fn view(&self) -> MemoryView {
+        MemoryView {
+            tables: self.inner.read().clone(),
+        }
+    }
 
     #[inline]
     fn transaction(&self) -> MemoryTransaction {
@@ -126,6 +146,16 @@ impl StorageEngine for MemoryEngine {
         Ok(values)
     }
 
+    /// The memory engine has no filters to build
+    #[inline]
+    fn set_prefix_len(&self, table: &str, _len: usize) -> Result<(), EngineError> {
+        if self.inner.read().contains_key(table) {
+            Ok(())
+        } else {
+            Err(EngineError::TableNotFound(table.to_owned()))
+        }
+    }
+
     #[inline]
     fn get_snapshot(
         &self,
@@ -156,16 +186,84 @@ impl StorageEngine for MemoryEngine {
         Ok(())
     }
 
+    #[inline]
     fn estimated_file_size(&self) -> u64 {
         0
     }
 
+    #[inline]
     fn file_size(&self) -> Result<u64, EngineError> {
         Ok(0)
     }
+
+    #[inline]
+    fn live_data_size(&self) -> Result<u64, EngineError> {
+        Ok(0)
+    }
+
+    /// The size of the memory engine tables is the total length of their keys
+    /// and values
+    #[inline]
+    fn table_size(&self, table: &str) -> Result<u64, EngineError> {
+        let inner = self.inner.read();
+        let table = inner
+            .get(table)
+            .ok_or_else(|| EngineError::TableNotFound(table.to_owned()))?;
+        Ok(table
+            .iter()
+            .map(|(key, value)| key.len().overflow_add(value.len()).numeric_cast::<u64>())
+            .fold(0, u64::overflow_add))
+    }
+
+    #[inline]
+    fn defragment(&self) -> Result<(), EngineError> {
+        Ok(())
+    }
+
+    /// The deleted records are removed immediately, nothing to reclaim
+    #[inline]
+    fn compact_range(
+        &self,
+        table: &str,
+        _from: Option<&[u8]>,
+        _to: Option<&[u8]>,
+    ) -> Result<u64, EngineError> {
+        if self.inner.read().contains_key(table) {
+            Ok(0)
+        } else {
+            Err(EngineError::TableNotFound(table.to_owned()))
+        }
+    }
+
+    /// The memory engine never stalls, compacts or opens files
+    #[inline]
+    fn stats(&self) -> Result<EngineStats, EngineError> {
+        Ok(EngineStats::default())
+    }
+
+    /// The memory engine never compresses its data
+    #[inline]
+    fn set_compression(
+        &self,
+        table: &str,
+        _compression: CompressionConfig,
+    ) -> Result<(), EngineError> {
+        if self.inner.read().contains_key(table) {
+            Ok(())
+        } else {
+            Err(EngineError::TableNotFound(table.to_owned()))
+        }
+    }
+
+    /// The memory engine never persists its data
+    #[inline]
+    fn set_durability(&self, _durability: DurabilityConfig) -> Result<(), EngineError> {
+        Ok(())
+    }
 }
 
 impl StorageOps for MemoryEngine {
+    #[inline]
     fn write(&self, op: WriteOperation<'_>, _sync: bool) -> Result<(), EngineError> {
         let mut inner = self.inner.write();
         Self::write_op(&mut inner, op)
@@ -183,6 +281,7 @@ impl StorageOps for MemoryEngine {
         Ok(())
     }
 
+    #[inline]
     fn get(&self, table: &str, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>, EngineError> {
         let inner = self.inner.read();
         let table = inner
@@ -191,6 +290,7 @@ impl StorageOps for MemoryEngine {
         Ok(table.get(&key.as_ref().to_vec()).cloned())
     }
 
+    #[inline]
     fn get_multi(
         &self,
         table: &str,
@@ -206,6 +306,72 @@ impl StorageOps for MemoryEngine {
             .map(|key| table.get(&key.as_ref().to_vec()).cloned())
             .collect())
     }
+
+    #[inline]
+    fn get_prefix(
+        &self,
+        table: &str,
+        prefix: &[u8],
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, EngineError> {
+        let inner = self.inner.read();
+        let table = inner
+            .get(table)
+            .ok_or_else(|| EngineError::TableNotFound(table.to_owned()))?;
+        let mut values = table
+            .iter()
+            .filter(|&(key, _)| key.starts_with(prefix))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect::<Vec<_>>();
+        values.sort_by(|v1, v2| v1.0.cmp(&v2.0));
+        Ok(values)
+    }
+}
+
+/// A point-in-time view of the `MemoryEngine`
+#[derive(Debug)]
+pub struct MemoryView {
+    /// The copied tables
+    tables: HashMap<String, MemoryTable>,
+}
+
+impl MemoryView {
+    /// Get the table of the view
+    fn table(&self, table: &str) -> Result<&MemoryTable, EngineError> {
+        self.tables
+            .get(table)
+            .ok_or_else(|| EngineError::TableNotFound(table.to_owned()))
+    }
+}
+
+impl ViewApi for MemoryView {
+    #[inline]
+    fn get(&self, table: &str, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>, EngineError> {
+        Ok(self.table(table)?.get(key.as_ref()).cloned())
+    }
+
+    #[inline]
+    fn iter(
+        &self,
+        table: &str,
+    ) -> Result<Box<dyn Iterator<Item = Result<(Vec<u8>, Vec<u8>), EngineError>> + '_>, EngineError>
+    {
+        let mut pairs = self.table(table)?.iter().collect::<Vec<_>>();
+        pairs.sort_by(|p1, p2| p1.0.cmp(p2.0));
+        Ok(Box::new(
+            pairs
+                .into_iter()
+                .map(|(key, value)| Ok((key.clone(), value.clone()))),
+        ))
+    }
+
+    #[inline]
+    fn last(&self, table: &str) -> Result<Option<(Vec<u8>, Vec<u8>)>, EngineError> {
+        Ok(self
+            .table(table)?
+            .iter()
+            .max_by(|p1, p2| p1.0.cmp(p2.0))
+            .map(|(key, value)| (key.clone(), value.clone())))
+    }
 }
 
 /// A snapshot of the `MemoryEngine`
@@ -257,3 +423,166 @@ impl SnapshotApi for MemorySnapshot {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use test_macros::abort_on_panic;
+
+    use super::*;
+    use crate::TransactionApi;
+
+    const TESTTABLES: [&'static str; 2] = ["kv", "lease"];
+
+    #[test]
+    fn view_should_not_see_later_writes() {
+        let engine = MemoryEngine::new(&TESTTABLES);
+        engine
+            .write_multi(
+                [
+                    WriteOperation::new_put("kv", b"b".to_vec(), b"2".to_vec()),
+                    WriteOperation::new_put("kv", b"a".to_vec(), b"1".to_vec()),
+                ],
+                false,
+            )
+            .unwrap();
+        let view = engine.view();
+        engine
+            .write_multi(
+                [
+                    WriteOperation::new_put("kv", b"c".to_vec(), b"3".to_vec()),
+                    WriteOperation::new_delete("kv", b"a"),
+                ],
+                false,
+            )
+            .unwrap();
+        assert_eq!(view.get("kv", b"a").unwrap(), Some(b"1".to_vec()));
+        assert_eq!(view.get("kv", b"c").unwrap(), None);
+        let pairs = view
+            .iter("kv")
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            pairs,
+            vec![
+                (b"a".to_vec(), b"1".to_vec()),
+                (b"b".to_vec(), b"2".to_vec())
+            ]
+        );
+        assert!(view.iter("lease").unwrap().next().is_none());
+        assert_eq!(
+            view.last("kv").unwrap(),
+            Some((b"b".to_vec(), b"2".to_vec()))
+        );
+        assert_eq!(view.last("lease").unwrap(), None);
+        assert!(matches!(
+            view.iter("auth"),
+            Err(EngineError::TableNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn transaction_should_be_isolated_until_commit() {
+        let engine = MemoryEngine::new(&TESTTABLES);
+        engine
+            .write(
+                WriteOperation::new_put("kv", b"a".to_vec(), b"1".to_vec()),
+                false,
+            )
+            .unwrap();
+
+        let txn = engine.transaction();
+        txn.write_multi(
+            [
+                WriteOperation::new_put("kv", b"b".to_vec(), b"2".to_vec()),
+                WriteOperation::new_delete("kv", b"a"),
+            ],
+            false,
+        )
+        .unwrap();
+        assert_eq!(txn.get("kv", b"a").unwrap(), None);
+        assert_eq!(txn.get("kv", b"b").unwrap(), Some(b"2".to_vec()));
+        assert_eq!(engine.get("kv", b"a").unwrap(), Some(b"1".to_vec()));
+        assert_eq!(engine.get("kv", b"b").unwrap(), None);
+
+        txn.rollback().unwrap();
+        assert_eq!(txn.get("kv", b"a").unwrap(), Some(b"1".to_vec()));
+        txn.write(
+            WriteOperation::new_put("kv", b"c".to_vec(), b"3".to_vec()),
+            false,
+        )
+        .unwrap();
+        txn.commit().unwrap();
+        assert_eq!(
+            engine.get_all("kv").unwrap(),
+            vec![
+                (b"a".to_vec(), b"1".to_vec()),
+                (b"c".to_vec(), b"3".to_vec())
+            ]
+        );
+        assert!(matches!(
+            engine.transaction().get("auth", b"a"),
+            Err(EngineError::TableNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn delete_range_should_exclude_the_end() {
+        let engine = MemoryEngine::new(&TESTTABLES);
+        let puts = [b"a", b"b", b"c", b"d"]
+            .map(|key| WriteOperation::new_put("kv", key.to_vec(), key.to_vec()));
+        engine.write_multi(puts, false).unwrap();
+        engine
+            .write(WriteOperation::new_delete_range("kv", b"b", b"d"), false)
+            .unwrap();
+        let keys: Vec<_> = engine
+            .get_all("kv")
+            .unwrap()
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(keys, vec![b"a".to_vec(), b"d".to_vec()]);
+
+        // clones share the same data
+        let cloned = engine.clone();
+        cloned
+            .write(WriteOperation::new_delete("kv", b"a"), false)
+            .unwrap();
+        assert_eq!(engine.get("kv", b"a").unwrap(), None);
+    }
+
+    #[tokio::test]
+    #[abort_on_panic]
+    async fn snapshot_should_restore_all_tables() {
+        let engine = MemoryEngine::new(&TESTTABLES);
+        engine
+            .write_multi(
+                [
+                    WriteOperation::new_put("kv", b"key".to_vec(), b"value".to_vec()),
+                    WriteOperation::new_put("lease", b"1".to_vec(), b"ttl".to_vec()),
+                ],
+                false,
+            )
+            .unwrap();
+        let mut snapshot = engine.get_snapshot("", &TESTTABLES).unwrap();
+        let mut buf = BytesMut::with_capacity(snapshot.size().numeric_cast());
+        snapshot.read_buf_exact(&mut buf).await.unwrap();
+
+        let mut received = MemorySnapshot::default();
+        received.write_all(buf.freeze()).await.unwrap();
+        let restored = MemoryEngine::new(&TESTTABLES);
+        restored
+            .write(
+                WriteOperation::new_put("kv", b"stale".to_vec(), b"value".to_vec()),
+                false,
+            )
+            .unwrap();
+        restored
+            .apply_snapshot(received, &TESTTABLES)
+            .await
+            .unwrap();
+        assert_eq!(restored.get("kv", b"stale").unwrap(), None);
+        assert_eq!(restored.get("kv", b"key").unwrap(), Some(b"value".to_vec()));
+        assert_eq!(restored.get("lease", b"1").unwrap(), Some(b"ttl".to_vec()));
+    }
+}
@@ -23,11 +23,13 @@ pub struct MemoryTransaction {
 }
 
 impl StorageOps for MemoryTransaction {
+    #[inline]
     fn write(&self, op: WriteOperation<'_>, _sync: bool) -> Result<(), EngineError> {
         let mut state_w = self.state.write();
         self.write_op(&mut state_w, op)
     }
 
+    #[inline]
     fn write_multi<'a, Ops>(&self, ops: Ops, _sync: bool) -> Result<(), EngineError>
     where
         Ops: IntoIterator<Item = WriteOperation<'a>>,
@@ -39,6 +41,7 @@ impl StorageOps for MemoryTransaction {
         Ok(())
     }
 
+    #[inline]
     fn get(&self, table: &str, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>, EngineError> {
         let state_r = self.state.read();
         let state_table = state_r
@@ -57,6 +60,7 @@ impl StorageOps for MemoryTransaction {
         Ok(db_table.get(key.as_ref()).cloned())
     }
 
+    #[inline]
     fn get_multi(
         &self,
         table: &str,
@@ -64,9 +68,35 @@ impl StorageOps for MemoryTransaction {
     ) -> Result<Vec<Option<Vec<u8>>>, EngineError> {
         keys.iter().map(|key| self.get(table, key)).collect()
     }
+
+    #[inline]
+    fn get_prefix(
+        &self,
+        table: &str,
+        prefix: &[u8],
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, EngineError> {
+        let state_r = self.state.read();
+        let state_table = state_r
+            .get(table)
+            .ok_or_else(|| EngineError::TableNotFound(table.to_owned()))?;
+        let mut kvs: HashMap<_, _> = self.db.get_prefix(table, prefix)?.into_iter().collect();
+        for (key, val) in state_table
+            .iter()
+            .filter(|&(key, _)| key.starts_with(prefix))
+        {
+            let _prev = match *val {
+                Some(ref val) => kvs.insert(key.clone(), val.clone()),
+                None => kvs.remove(key),
+            };
+        }
+        let mut kvs: Vec<_> = kvs.into_iter().collect();
+        kvs.sort_by(|kv1, kv2| kv1.0.cmp(&kv2.0));
+        Ok(kvs)
+    }
 }
 
 impl TransactionApi for MemoryTransaction {
+    #[inline]
     fn commit(self) -> Result<(), EngineError> {
         let mut state_w = self.state.write();
         let mut db_inner_w = self.db.inner.write();
@@ -87,6 +117,7 @@ impl TransactionApi for MemoryTransaction {
         Ok(())
     }
 
+    #[inline]
     fn rollback(&self) -> Result<(), EngineError> {
         let mut state_w = self.state.write();
         for table in state_w.values_mut() {
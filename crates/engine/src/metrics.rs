@@ -2,14 +2,23 @@ use std::{io, path::Path, time::Instant};
 
 use async_trait::async_trait;
 use bytes::{Bytes, BytesMut};
-use opentelemetry::metrics::Histogram;
-use utils::define_metrics;
+use opentelemetry::{
+    metrics::{Counter, Histogram},
+    KeyValue,
+};
+use utils::{
+    config::{CompressionConfig, DurabilityConfig},
+    define_metrics,
+};
 
 #[cfg(madsim)]
-use crate::mock_rocksdb_engine::RocksEngine;
+use crate::mock_rocksdb_engine::{RocksEngine, RocksSnapshot};
 #[cfg(not(madsim))]
-use crate::rocksdb_engine::RocksEngine;
-use crate::{EngineError, SnapshotApi, StorageEngine, StorageOps, TransactionApi, WriteOperation};
+use crate::rocksdb_engine::{RocksEngine, RocksSnapshot};
+use crate::{
+    EngineError, EngineStats, SnapshotApi, StorageEngine, StorageOps, TransactionApi,
+    WriteOperation,
+};
 
 define_metrics! {
     "engine",
@@ -17,6 +26,14 @@ define_metrics! {
         .u64_histogram("engine_apply_snapshot_duration_seconds")
         .with_description("The backend engine apply snapshot duration in seconds.")
         .init(),
+    engine_defragment_duration_seconds: Histogram<u64> = meter()
+        .u64_histogram("engine_defragment_duration_seconds")
+        .with_description("The backend engine defragment duration in seconds.")
+        .init(),
+    engine_reclaimed_bytes_total: Counter<u64> = meter()
+        .u64_counter("engine_reclaimed_bytes_total")
+        .with_description("The bytes reclaimed by the ranged compactions of the backend engine.")
+        .init(),
     engine_write_batch_duration_seconds: Histogram<u64> = meter()
         .u64_histogram("engine_write_batch_duration_seconds")
         .with_description("The backend engine write batch engine, `batch_size` refer to the size and `sync` if sync option is on.")
@@ -53,6 +70,39 @@ impl Layer<RocksEngine> {
             .apply_snapshot_from_file(snap_path, tables)
             .await
     }
+
+    /// Add the tables to a snapshot of another engine, only works for
+    /// `RocksEngine`
+    ///
+    /// # Errors
+    ///
+    /// Return `EngineError` when `RocksDB` returns an error.
+    #[inline]
+    pub fn add_to_snapshot(
+        &self,
+        snapshot: &mut Layer<RocksSnapshot>,
+        tables: &[&'static str],
+        prefix: &str,
+    ) -> Result<(), EngineError> {
+        self.engine
+            .add_to_snapshot(&mut snapshot.engine, tables, prefix)
+    }
+
+    /// Ingest the tables added to a snapshot, only works for `RocksEngine`
+    ///
+    /// # Errors
+    ///
+    /// Return `EngineError` when `RocksDB` returns an error.
+    #[inline]
+    pub fn ingest_snapshot(
+        &self,
+        snapshot: &Layer<RocksSnapshot>,
+        tables: &[&'static str],
+        prefix: &str,
+    ) -> Result<(), EngineError> {
+        self.engine
+            .ingest_snapshot(&snapshot.engine, tables, prefix)
+    }
 }
 
 #[async_trait]
@@ -63,12 +113,19 @@ where
     /// The snapshot type
     type Snapshot = Layer<E::Snapshot>;
     type Transaction<'db> = Layer<E::Transaction<'db>>;
+    /// The view type
+    type View<'db> = E::View<'db>;
 
     /// Creates a transaction
     fn transaction(&self) -> Self::Transaction<'_> {
         Layer::new(self.engine.transaction())
     }
 
+    /// Creates a consistent view
+    fn view(&self) -> Self::View<'_> {
+        self.engine.view()
+    }
+
     /// Get all the values of the given table
     ///
     /// # Errors
@@ -80,6 +137,10 @@ where
         self.engine.get_all(table)
     }
 
+    fn set_prefix_len(&self, table: &str, len: usize) -> Result<(), EngineError> {
+        self.engine.set_prefix_len(table, len)
+    }
+
     /// Get a snapshot of the current state of the database
     ///
     /// # Errors
@@ -120,6 +181,57 @@ where
     fn file_size(&self) -> Result<u64, EngineError> {
         self.engine.file_size()
     }
+
+    /// Get the estimated size of the live data of the engine (Measured in bytes)
+    fn live_data_size(&self) -> Result<u64, EngineError> {
+        self.engine.live_data_size()
+    }
+
+    /// Get the estimated size of the given table (Measured in bytes)
+    fn table_size(&self, table: &str) -> Result<u64, EngineError> {
+        self.engine.table_size(table)
+    }
+
+    /// Compact all the data of the engine
+    fn defragment(&self) -> Result<(), EngineError> {
+        let start = Instant::now();
+        let res = self.engine.defragment();
+        get()
+            .engine_defragment_duration_seconds
+            .record(start.elapsed().as_secs(), &[]);
+        res
+    }
+
+    /// Compact the key range of the table
+    fn compact_range(
+        &self,
+        table: &str,
+        from: Option<&[u8]>,
+        to: Option<&[u8]>,
+    ) -> Result<u64, EngineError> {
+        let reclaimed = self.engine.compact_range(table, from, to)?;
+        get()
+            .engine_reclaimed_bytes_total
+            .add(reclaimed, &[KeyValue::new("table", table.to_owned())]);
+        Ok(reclaimed)
+    }
+
+    /// Get the statistics of the engine
+    fn stats(&self) -> Result<EngineStats, EngineError> {
+        self.engine.stats()
+    }
+
+    fn set_compression(
+        &self,
+        table: &str,
+        compression: CompressionConfig,
+    ) -> Result<(), EngineError> {
+        self.engine.set_compression(table, compression)
+    }
+
+    fn set_durability(&self, durability: DurabilityConfig) -> Result<(), EngineError> {
+        self.engine.set_durability(durability)
+    }
 }
 
 impl<E> StorageOps for Layer<E>
@@ -148,6 +260,14 @@ where
     ) -> Result<Vec<Option<Vec<u8>>>, EngineError> {
         self.engine.get_multi(table, keys)
     }
+
+    fn get_prefix(
+        &self,
+        table: &str,
+        prefix: &[u8],
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, EngineError> {
+        self.engine.get_prefix(table, prefix)
+    }
 }
 
 #[async_trait]
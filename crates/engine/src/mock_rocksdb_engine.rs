@@ -1,15 +1,21 @@
 use std::{
     fs,
+    marker::PhantomData,
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
 use bytes::{Bytes, BytesMut};
+use utils::config::{CompressionConfig, DurabilityConfig};
 
 use crate::{
-    api::{engine_api::StorageEngine, snapshot_api::SnapshotApi},
+    api::{
+        engine_api::{EngineStats, StorageEngine},
+        snapshot_api::SnapshotApi,
+    },
     error::EngineError,
-    memory_engine::{MemoryEngine, MemorySnapshot, MemoryTransaction},
-    StorageOps, TransactionApi, WriteOperation,
+    memory_engine::{MemoryEngine, MemorySnapshot, MemoryTransaction, MemoryView},
+    KeyProvider, StorageOps, TransactionApi, ViewApi, WriteOperation,
 };
 
 /// Mock `RocksDB` Storage Engine
@@ -50,6 +56,13 @@ impl RocksEngine {
         }
     }
 
+    /// The mock engine keeps the values as is
+    #[inline]
+    #[must_use]
+    pub fn with_key_provider(self, _key_provider: Arc<dyn KeyProvider>) -> Self {
+        self
+    }
+
     /// Sync the memory engine to file
     ///
     /// # Errors
@@ -72,12 +85,54 @@ impl RocksEngine {
     ) -> Result<(), EngineError> {
         unreachable!("mock engine does not support apply snapshot from file")
     }
+
+    /// The mock engine does not support adding tables to a snapshot
+    ///
+    /// # Errors
+    ///
+    /// Return `EngineError::InvalidArgument` always.
+    #[inline]
+    pub fn add_to_snapshot(
+        &self,
+        _snapshot: &mut RocksSnapshot,
+        _tables: &[&'static str],
+        _prefix: &str,
+    ) -> Result<(), EngineError> {
+        Err(EngineError::InvalidArgument(
+            "mock engine does not support adding tables to a snapshot".to_owned(),
+        ))
+    }
+
+    /// The mock snapshots only have the tables of the engine they are taken
+    /// from, there is nothing to ingest
+    ///
+    /// # Errors
+    ///
+    /// Never return an error.
+    #[inline]
+    pub fn ingest_snapshot(
+        &self,
+        _snapshot: &RocksSnapshot,
+        _tables: &[&'static str],
+        _prefix: &str,
+    ) -> Result<(), EngineError> {
+        Ok(())
+    }
 }
 
 #[async_trait::async_trait]
 impl StorageEngine for RocksEngine {
     type Snapshot = RocksSnapshot;
     type Transaction<'db> = RocksTransaction<'db>;
+    type View<'db> = RocksView<'db>;
+
+    #[inline]
+    fn view(&self) -> RocksView<'_> {
+        RocksView {
+            inner: self.inner.view(),
+            _db: PhantomData,
+        }
+    }
 
     #[inline]
     fn transaction(&self) -> RocksTransaction<'_> {
@@ -92,6 +147,11 @@ impl StorageEngine for RocksEngine {
         self.inner.get_all(table)
     }
 
+    #[inline]
+    fn set_prefix_len(&self, table: &str, len: usize) -> Result<(), EngineError> {
+        self.inner.set_prefix_len(table, len)
+    }
+
     #[inline]
     fn get_snapshot(
         &self,
@@ -122,6 +182,50 @@ impl StorageEngine for RocksEngine {
     fn file_size(&self) -> Result<u64, EngineError> {
         Ok(0)
     }
+
+    #[inline]
+    fn live_data_size(&self) -> Result<u64, EngineError> {
+        Ok(0)
+    }
+
+    #[inline]
+    fn table_size(&self, table: &str) -> Result<u64, EngineError> {
+        self.inner.table_size(table)
+    }
+
+    #[inline]
+    fn defragment(&self) -> Result<(), EngineError> {
+        Ok(())
+    }
+
+    #[inline]
+    fn compact_range(
+        &self,
+        table: &str,
+        from: Option<&[u8]>,
+        to: Option<&[u8]>,
+    ) -> Result<u64, EngineError> {
+        self.inner.compact_range(table, from, to)
+    }
+
+    #[inline]
+    fn stats(&self) -> Result<EngineStats, EngineError> {
+        self.inner.stats()
+    }
+
+    #[inline]
+    fn set_compression(
+        &self,
+        table: &str,
+        compression: CompressionConfig,
+    ) -> Result<(), EngineError> {
+        self.inner.set_compression(table, compression)
+    }
+
+    #[inline]
+    fn set_durability(&self, durability: DurabilityConfig) -> Result<(), EngineError> {
+        self.inner.set_durability(durability)
+    }
 }
 
 impl StorageOps for RocksEngine {
@@ -148,6 +252,14 @@ impl StorageOps for RocksEngine {
     ) -> Result<Vec<Option<Vec<u8>>>, EngineError> {
         self.inner.get_multi(table, keys)
     }
+
+    fn get_prefix(
+        &self,
+        table: &str,
+        prefix: &[u8],
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, EngineError> {
+        self.inner.get_prefix(table, prefix)
+    }
 }
 
 /// A mock snapshot of the `RocksEngine`
@@ -214,6 +326,36 @@ impl SnapshotApi for RocksSnapshot {
     }
 }
 
+/// Mock `RocksView`
+#[derive(Debug)]
+pub struct RocksView<'db> {
+    /// The memory view
+    inner: MemoryView,
+    /// The view borrows the engine as the real one
+    _db: PhantomData<&'db RocksEngine>,
+}
+
+impl ViewApi for RocksView<'_> {
+    #[inline]
+    fn get(&self, table: &str, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>, EngineError> {
+        self.inner.get(table, key)
+    }
+
+    #[inline]
+    fn iter(
+        &self,
+        table: &str,
+    ) -> Result<Box<dyn Iterator<Item = Result<(Vec<u8>, Vec<u8>), EngineError>> + '_>, EngineError>
+    {
+        self.inner.iter(table)
+    }
+
+    #[inline]
+    fn last(&self, table: &str) -> Result<Option<(Vec<u8>, Vec<u8>)>, EngineError> {
+        self.inner.last(table)
+    }
+}
+
 /// Mock `RocksTransaction`
 #[derive(Debug)]
 pub struct RocksTransaction<'db> {
@@ -246,6 +388,14 @@ impl StorageOps for RocksTransaction<'_> {
     ) -> Result<Vec<Option<Vec<u8>>>, EngineError> {
         self.inner.get_multi(table, keys)
     }
+
+    fn get_prefix(
+        &self,
+        table: &str,
+        prefix: &[u8],
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, EngineError> {
+        self.inner.get_prefix(table, prefix)
+    }
 }
 
 #[async_trait::async_trait]
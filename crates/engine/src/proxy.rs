@@ -1,15 +1,20 @@
-use std::path::{Path, PathBuf};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use bytes::{Bytes, BytesMut};
+use utils::config::{CompressionConfig, DurabilityConfig};
 
 #[cfg(madsim)]
-use crate::mock_rocksdb_engine::{RocksEngine, RocksSnapshot, RocksTransaction};
+use crate::mock_rocksdb_engine::{RocksEngine, RocksSnapshot, RocksTransaction, RocksView};
 #[cfg(not(madsim))]
-use crate::rocksdb_engine::{RocksEngine, RocksSnapshot, RocksTransaction};
+use crate::rocksdb_engine::{RocksEngine, RocksSnapshot, RocksTransaction, RocksView};
 use crate::{
     error::EngineError,
-    memory_engine::{MemoryEngine, MemorySnapshot, MemoryTransaction},
-    metrics, SnapshotApi, StorageEngine, StorageOps, TransactionApi, WriteOperation,
+    memory_engine::{MemoryEngine, MemorySnapshot, MemoryTransaction, MemoryView},
+    metrics, EngineStats, KeyProvider, SnapshotApi, StorageEngine, StorageOps, TransactionApi,
+    ViewApi, WriteOperation,
 };
 
 #[derive(Debug)]
@@ -49,6 +54,29 @@ impl Engine {
         }
     }
 
+    /// Create a new `Engine` instance whose values are encrypted with the keys
+    /// of the provider, only the `RocksEngine` supports encryption
+    ///
+    /// # Errors
+    ///
+    /// Return `EngineError` when DB open failed or the engine type does not
+    /// support encryption.
+    #[inline]
+    pub fn new_encrypted(
+        engine_type: EngineType,
+        tables: &[&'static str],
+        key_provider: Arc<dyn KeyProvider>,
+    ) -> Result<Self, EngineError> {
+        match engine_type {
+            EngineType::Memory => Err(EngineError::InvalidArgument(
+                "memory engine does not support encryption".to_owned(),
+            )),
+            EngineType::Rocks(path) => Ok(Engine::Rocks(metrics::Layer::new(
+                RocksEngine::new(path, tables)?.with_key_provider(key_provider),
+            ))),
+        }
+    }
+
     /// Apply snapshot from file, only works for `RocksEngine`
     ///
     /// # Errors
@@ -67,12 +95,59 @@ impl Engine {
             }
         }
     }
+
+    /// Add the tables to a snapshot of another engine, the files of the tables
+    /// are named with the prefix so that they are apart from the ones of the
+    /// snapshot, only works for `RocksEngine`
+    ///
+    /// # Errors
+    ///
+    /// Return `EngineError::InvalidSnapshot` if the snapshot is not of a
+    /// `RocksEngine`, or `EngineError` when `RocksDB` returns an error.
+    #[inline]
+    pub fn add_to_snapshot(
+        &self,
+        snapshot: &mut Snapshot,
+        tables: &[&'static str],
+        prefix: &str,
+    ) -> Result<(), EngineError> {
+        match (self, snapshot) {
+            (&Engine::Rocks(ref e), &mut Snapshot::Rocks(ref mut s)) => {
+                e.add_to_snapshot(s, tables, prefix)
+            }
+            _ => Err(EngineError::InvalidSnapshot),
+        }
+    }
+
+    /// Ingest the tables added to a snapshot with the prefix, the snapshot is
+    /// kept to be applied, a memory snapshot has nothing to ingest
+    ///
+    /// # Errors
+    ///
+    /// Return `EngineError::InvalidSnapshot` if the snapshot does not match
+    /// the engine, or `EngineError` when `RocksDB` returns an error.
+    #[inline]
+    pub fn ingest_snapshot(
+        &self,
+        snapshot: &Snapshot,
+        tables: &[&'static str],
+        prefix: &str,
+    ) -> Result<(), EngineError> {
+        match (self, snapshot) {
+            (&Engine::Rocks(ref e), &Snapshot::Rocks(ref s)) => {
+                e.ingest_snapshot(s, tables, prefix)
+            }
+            (&Engine::Memory(_), &Snapshot::Memory(_)) => Ok(()),
+            _ => Err(EngineError::InvalidSnapshot),
+        }
+    }
 }
 
 #[async_trait::async_trait]
 impl StorageEngine for Engine {
     type Snapshot = Snapshot;
     type Transaction<'db> = Transaction<'db>;
+    type View<'db> = View<'db>;
 
     #[inline]
     fn transaction(&self) -> Transaction<'_> {
@@ -82,6 +157,14 @@ impl StorageEngine for Engine {
         }
     }
 
+    #[inline]
+    fn view(&self) -> View<'_> {
+        match *self {
+            Engine::Memory(ref e) => View::Memory(e.view()),
+            Engine::Rocks(ref e) => View::Rocks(e.view()),
+        }
+    }
+
     #[inline]
     fn get_all(&self, table: &str) -> Result<Vec<(Vec<u8>, Vec<u8>)>, EngineError> {
         match *self {
@@ -90,6 +173,14 @@ impl StorageEngine for Engine {
         }
     }
 
+    #[inline]
+    fn set_prefix_len(&self, table: &str, len: usize) -> Result<(), EngineError> {
+        match *self {
+            Engine::Memory(ref e) => e.set_prefix_len(table, len),
+            Engine::Rocks(ref e) => e.set_prefix_len(table, len),
+        }
+    }
+
     #[inline]
     fn get_snapshot(
         &self,
@@ -135,6 +226,71 @@ impl StorageEngine for Engine {
             Engine::Rocks(ref e) => e.file_size(),
         }
     }
+
+    #[inline]
+    fn live_data_size(&self) -> Result<u64, EngineError> {
+        match *self {
+            Engine::Memory(ref e) => e.live_data_size(),
+            Engine::Rocks(ref e) => e.live_data_size(),
+        }
+    }
+
+    #[inline]
+    fn table_size(&self, table: &str) -> Result<u64, EngineError> {
+        match *self {
+            Engine::Memory(ref e) => e.table_size(table),
+            Engine::Rocks(ref e) => e.table_size(table),
+        }
+    }
+
+    #[inline]
+    fn defragment(&self) -> Result<(), EngineError> {
+        match *self {
+            Engine::Memory(ref e) => e.defragment(),
+            Engine::Rocks(ref e) => e.defragment(),
+        }
+    }
+
+    #[inline]
+    fn compact_range(
+        &self,
+        table: &str,
+        from: Option<&[u8]>,
+        to: Option<&[u8]>,
+    ) -> Result<u64, EngineError> {
+        match *self {
+            Engine::Memory(ref e) => e.compact_range(table, from, to),
+            Engine::Rocks(ref e) => e.compact_range(table, from, to),
+        }
+    }
+
+    #[inline]
+    fn stats(&self) -> Result<EngineStats, EngineError> {
+        match *self {
+            Engine::Memory(ref e) => e.stats(),
+            Engine::Rocks(ref e) => e.stats(),
+        }
+    }
+
+    #[inline]
+    fn set_compression(
+        &self,
+        table: &str,
+        compression: CompressionConfig,
+    ) -> Result<(), EngineError> {
+        match *self {
+            Engine::Memory(ref e) => e.set_compression(table, compression),
+            Engine::Rocks(ref e) => e.set_compression(table, compression),
+        }
+    }
+
+    #[inline]
+    fn set_durability(&self, durability: DurabilityConfig) -> Result<(), EngineError> {
+        match *self {
+            Engine::Memory(ref e) => e.set_durability(durability),
+            Engine::Rocks(ref e) => e.set_durability(durability),
+        }
+    }
 }
 
 impl StorageOps for Engine {
@@ -176,6 +332,18 @@ impl StorageOps for Engine {
             Engine::Rocks(ref e) => e.get_multi(table, keys),
         }
     }
+
+    #[inline]
+    fn get_prefix(
+        &self,
+        table: &str,
+        prefix: &[u8],
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, EngineError> {
+        match *self {
+            Engine::Memory(ref e) => e.get_prefix(table, prefix),
+            Engine::Rocks(ref e) => e.get_prefix(table, prefix),
+        }
+    }
 }
 
 /// `Transaction` is designed to mask the different type of `MemoryTransaction` and `RocksTransaction`
@@ -229,6 +397,18 @@ impl StorageOps for Transaction<'_> {
             Transaction::Rocks(ref t) => t.get_multi(table, keys),
         }
     }
+
+    #[inline]
+    fn get_prefix(
+        &self,
+        table: &str,
+        prefix: &[u8],
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, EngineError> {
+        match *self {
+            Transaction::Memory(ref t) => t.get_prefix(table, prefix),
+            Transaction::Rocks(ref t) => t.get_prefix(table, prefix),
+        }
+    }
 }
 
 impl TransactionApi for Transaction<'_> {
@@ -249,6 +429,47 @@ impl TransactionApi for Transaction<'_> {
     }
 }
 
+/// `View` is designed to mask the different type of `MemoryView` and `RocksView`
+/// and provides an uniform type to the upper layer.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum View<'a> {
+    /// Memory view
+    Memory(MemoryView),
+    /// Rocks view
+    Rocks(RocksView<'a>),
+}
+
+impl ViewApi for View<'_> {
+    #[inline]
+    fn get(&self, table: &str, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>, EngineError> {
+        match *self {
+            View::Memory(ref v) => v.get(table, key),
+            View::Rocks(ref v) => v.get(table, key),
+        }
+    }
+
+    #[inline]
+    fn iter(
+        &self,
+        table: &str,
+    ) -> Result<Box<dyn Iterator<Item = Result<(Vec<u8>, Vec<u8>), EngineError>> + '_>, EngineError>
+    {
+        match *self {
+            View::Memory(ref v) => v.iter(table),
+            View::Rocks(ref v) => v.iter(table),
+        }
+    }
+
+    #[inline]
+    fn last(&self, table: &str) -> Result<Option<(Vec<u8>, Vec<u8>)>, EngineError> {
+        match *self {
+            View::Memory(ref v) => v.last(table),
+            View::Rocks(ref v) => v.last(table),
+        }
+    }
+}
+
 /// `Snapshot` is designed to mask the different type of `MemorySnapshot` and `RocksSnapshot`
 /// and provides an uniform type to the upper layer.
 #[derive(Debug)]
@@ -453,6 +674,118 @@ mod test {
         dir.close().unwrap();
     }
 
+    #[test]
+    fn get_prefix_should_only_return_the_keys_with_the_prefix() {
+        let dir =
+            TempDir::with_prefix("/tmp/get_prefix_should_only_return_the_keys_with_the_prefix")
+                .unwrap();
+        let rocks_engine_path = dir.path().join("rocks_engine");
+        let engines = vec![
+            Engine::new(EngineType::Memory, &TESTTABLES).unwrap(),
+            Engine::new(EngineType::Rocks(rocks_engine_path), &TESTTABLES).unwrap(),
+        ];
+        for engine in engines {
+            assert!(engine.set_prefix_len("hello", 2).is_err());
+            engine.set_prefix_len("kv", 2).unwrap();
+            let keys = [
+                vec![1, 1, 0],
+                vec![1, 1, 1],
+                vec![1, 2, 0],
+                vec![1, 0xff],
+                vec![2, 0],
+            ];
+            for key in &keys {
+                engine
+                    .write(
+                        WriteOperation::new_put("kv", key.clone(), key.clone()),
+                        false,
+                    )
+                    .unwrap();
+                // every key is flushed to a file of its own
+                let _size = engine.file_size().unwrap();
+            }
+            let get_keys = |prefix: &[u8]| -> Vec<Vec<u8>> {
+                engine
+                    .get_prefix("kv", prefix)
+                    .unwrap()
+                    .into_iter()
+                    .map(|(key, _)| key)
+                    .collect()
+            };
+            assert_eq!(get_keys(&[1, 1]), keys[..2].to_vec());
+            assert_eq!(get_keys(&[1, 0xff]), keys[3..4].to_vec());
+            assert_eq!(get_keys(&[1]), keys[..4].to_vec());
+            assert!(get_keys(&[3, 0]).is_empty());
+
+            // the iteration of a range deletion crosses the prefixes
+            engine
+                .write(
+                    WriteOperation::new_delete_range("kv", &[1, 1], &[2, 0]),
+                    false,
+                )
+                .unwrap();
+            assert_eq!(get_keys(&[]), keys[4..].to_vec());
+        }
+        dir.close().unwrap();
+    }
+
+    #[test]
+    fn table_size_should_grow_with_the_table() {
+        let dir = TempDir::with_prefix("/tmp/table_size_should_grow_with_the_table").unwrap();
+        let rocks_engine_path = dir.path().join("rocks_engine");
+        let engines = vec![
+            Engine::new(EngineType::Memory, &TESTTABLES).unwrap(),
+            Engine::new(EngineType::Rocks(rocks_engine_path), &TESTTABLES).unwrap(),
+        ];
+        for engine in engines {
+            let kv_size = engine.table_size("kv").unwrap();
+            let lease_size = engine.table_size("lease").unwrap();
+            let puts = (0u8..100u8).map(|i| WriteOperation::new_put("kv", vec![i], vec![i; 1024]));
+            engine.write_multi(puts, false).unwrap();
+
+            assert!(engine.table_size("kv").unwrap() > kv_size);
+            assert_eq!(engine.table_size("lease").unwrap(), lease_size);
+            assert!(engine.table_size("hello").is_err());
+        }
+        dir.close().unwrap();
+    }
+
+    #[test]
+    fn compact_range_should_reclaim_deleted_records() {
+        let dir =
+            TempDir::with_prefix("/tmp/compact_range_should_reclaim_deleted_records").unwrap();
+        let rocks_engine_path = dir.path().join("rocks_engine");
+        let memory_engine = Engine::new(EngineType::Memory, &TESTTABLES).unwrap();
+        let rocks_engine = Engine::new(EngineType::Rocks(rocks_engine_path), &TESTTABLES).unwrap();
+        for engine in [&memory_engine, &rocks_engine] {
+            let puts = (0u8..100u8).map(|i| WriteOperation::new_put("kv", vec![i], vec![i; 1024]));
+            engine.write_multi(puts, false).unwrap();
+            let _size = engine.file_size().unwrap();
+            let keys: Vec<_> = (0u8..100u8).map(|i| [i]).collect();
+            let deletes = keys.iter().map(|key| WriteOperation::new_delete("kv", key));
+            engine.write_multi(deletes, false).unwrap();
+            assert!(engine.compact_range("hello", None, None).is_err());
+        }
+        assert_eq!(
+            memory_engine
+                .compact_range("kv", Some(&[0]), Some(&[99]))
+                .unwrap(),
+            0
+        );
+        assert!(
+            rocks_engine
+                .compact_range("kv", Some(&[0]), Some(&[99]))
+                .unwrap()
+                > 0
+        );
+        assert!(rocks_engine
+            .get_multi("kv", &[[0], [99]])
+            .unwrap()
+            .iter()
+            .all(Option::is_none));
+        dir.close().unwrap();
+    }
+
     #[tokio::test]
     #[abort_on_panic]
     async fn snapshot_should_work() {
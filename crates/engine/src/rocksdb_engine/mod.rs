@@ -1,5 +1,7 @@
 /// `RocksDB` transaction implementation
 mod transaction;
+/// `RocksDB` point-in-time view implementation
+mod view;
 
 use std::{
     cmp::Ordering,
@@ -8,30 +10,85 @@ use std::{
     io::{Cursor, Error as IoError, ErrorKind},
     iter::repeat,
     path::{Path, PathBuf},
-    sync::{atomic::AtomicU64, Arc},
+    sync::{atomic::AtomicU64, Arc, Weak},
 };
 
 use bytes::{Buf, Bytes, BytesMut};
 use clippy_utilities::{NumericCast, OverflowArithmetic};
+use parking_lot::Mutex;
 use rocksdb::{
+    statistics::{Histogram, Ticker},
+    BlockBasedOptions, BottommostLevelCompaction, ColumnFamilyDescriptor, CompactOptions,
     Direction, Error as RocksError, ErrorKind as RocksErrorKind, IteratorMode,
-    OptimisticTransactionDB, Options, SstFileWriter, Transaction,
+    OptimisticTransactionDB, OptimisticTransactionOptions, Options, ReadOptions, SstFileWriter,
+    Transaction, WriteOptions,
 };
 use serde::{Deserialize, Serialize};
 use tokio::{fs::File, io::AsyncWriteExt};
 use tokio_util::io::read_buf;
-use tracing::warn;
+use tracing::{error, info, warn};
+use utils::config::{CompressionCodec, CompressionConfig, DurabilityConfig};
 
 use crate::{
-    api::{engine_api::StorageEngine, snapshot_api::SnapshotApi},
+    api::{
+        engine_api::{EngineStats, StorageEngine},
+        snapshot_api::SnapshotApi,
+    },
+    encryption::{KeyProvider, ValueCipher},
     error::EngineError,
     StorageOps, WriteOperation,
 };
 
-pub(super) use self::transaction::RocksTransaction;
+pub(super) use self::{transaction::RocksTransaction, view::RocksView};
 
 /// Install snapshot chunk size: 64KB
 const SNAPSHOT_CHUNK_SIZE: usize = 64 * 1024;
+/// Sync the written data files incrementally every 1MB, to avoid a stall
+/// when a large file is synced at once
+const BYTES_PER_SYNC: u64 = 1024 * 1024;
+/// Number of the values encrypted again in a transaction when defragment
+const REENCRYPT_BATCH_SIZE: usize = 1024;
+/// The level that makes `RocksDB` use the default level of a codec
+const DEFAULT_COMPRESSION_LEVEL: i32 = 32767;
+/// Bits per key of the bloom filters of the tables
+const BLOOM_FILTER_BITS_PER_KEY: f64 = 10.0;
+/// Ratio of the memtable size used by the prefix bloom filters of the memtables
+const MEMTABLE_PREFIX_BLOOM_RATIO: f64 = 0.1;
+
+/// Get the smallest key greater than all the keys with the prefix, `None` if
+/// there is no such key
+fn prefix_upper_bound(prefix: &[u8]) -> Option<Vec<u8>> {
+    let end = prefix.iter().rposition(|b| *b != u8::MAX)?;
+    let mut upper_bound = prefix.get(..=end)?.to_vec();
+    if let Some(last) = upper_bound.last_mut() {
+        *last = last.overflow_add(1);
+    }
+    Some(upper_bound)
+}
+
+/// Get the path of the sst file of a table in a snapshot directory, named with
+/// the prefix
+fn sst_path(dir: &Path, prefix: &str, table: &str) -> PathBuf {
+    dir.join(format!("{prefix}{table}.sst"))
+}
+
+/// Read options of the seeks of the keys with the prefix, the iterations stop
+/// at the end of the prefix
+pub(super) fn prefix_read_opts(prefix: &[u8]) -> ReadOptions {
+    let mut read_opts = ReadOptions::default();
+    if let Some(upper_bound) = prefix_upper_bound(prefix) {
+        read_opts.set_iterate_upper_bound(upper_bound);
+    }
+    read_opts
+}
+
+/// Read options of the seeks whose iterations cross the prefixes of the keys,
+/// which must not skip the files by the prefix bloom filters
+pub(super) fn total_order_read_opts() -> ReadOptions {
+    let mut read_opts = ReadOptions::default();
+    read_opts.set_total_order_seek(true);
+    read_opts
+}
 
 /// Translate a `RocksError` into a `EngineError`
 impl From<RocksError> for EngineError {
@@ -66,6 +123,53 @@ pub struct RocksEngine {
     tables: Vec<String>,
     /// The size cache of the engine
     size: AtomicU64,
+    /// Cipher of the values, `None` if encryption is disabled
+    cipher: Option<ValueCipher>,
+    /// The statistics collected by `RocksDB`
+    statistics: Statistics,
+    /// The durability of the writes, shared with the syncer thread
+    durability: Arc<Mutex<Durability>>,
+}
+
+/// The durability of the writes of a `RocksEngine`
+#[derive(Debug, Default)]
+struct Durability {
+    /// The durability level
+    level: DurabilityConfig,
+    /// Whether the thread syncing the writes once per interval is running
+    syncer_running: bool,
+}
+
+/// Sync the writes of the engine to the disk once per interval, until the
+/// durability level is changed or the engine is dropped
+fn sync_periodically(db: &Weak<OptimisticTransactionDB>, durability: &Mutex<Durability>) {
+    loop {
+        let interval = {
+            let mut durability = durability.lock();
+            let DurabilityConfig::FsyncInterval(interval) = durability.level else {
+                durability.syncer_running = false;
+                return;
+            };
+            interval
+        };
+        std::thread::sleep(interval);
+        let Some(db) = db.upgrade() else {
+            return;
+        };
+        if let Err(e) = db.flush_wal(true) {
+            error!("failed to sync the writes of the engine: {e}");
+        }
+    }
+}
+
+/// The options of an opened `RocksDB`, which share the statistics with it
+struct Statistics(Options);
+
+impl std::fmt::Debug for Statistics {
+    #[inline]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Statistics").finish_non_exhaustive()
+    }
 }
 
 impl RocksEngine {
@@ -79,17 +183,48 @@ impl RocksEngine {
         let mut db_opts = Options::default();
         db_opts.create_missing_column_families(true);
         db_opts.create_if_missing(true);
-        let db = Arc::new(OptimisticTransactionDB::open_cf(
-            &db_opts, data_dir, tables,
+        // flushes and compactions use all the cores instead of a single thread
+        let parallelism = std::thread::available_parallelism().map_or(1, usize::from);
+        db_opts.increase_parallelism(i32::try_from(parallelism).unwrap_or(i32::MAX));
+        db_opts.set_bytes_per_sync(BYTES_PER_SYNC);
+        db_opts.enable_statistics();
+        // the bloom filters of the whole keys make the point gets of the
+        // missing keys skip the files
+        let mut table_opts = BlockBasedOptions::default();
+        table_opts.set_bloom_filter(BLOOM_FILTER_BITS_PER_KEY, false);
+        let mut cf_opts = Options::default();
+        cf_opts.set_block_based_table_factory(&table_opts);
+        cf_opts.set_memtable_prefix_bloom_ratio(MEMTABLE_PREFIX_BLOOM_RATIO);
+        let cfs = tables
+            .iter()
+            .map(|table| ColumnFamilyDescriptor::new(*table, cf_opts.clone()));
+        let db = Arc::new(OptimisticTransactionDB::open_cf_descriptors(
+            &db_opts, data_dir, cfs,
         )?);
         let size = Self::get_db_size(&db, tables)?;
         Ok(Self {
             inner: db,
             tables: tables.iter().map(|s| (*s).to_owned()).collect(),
             size: AtomicU64::new(size),
+            cipher: None,
+            statistics: Statistics(db_opts),
+            durability: Arc::default(),
         })
     }
 
+    /// Encrypt the values written to the engine with the keys of the provider
+    ///
+    /// The values written before are still readable, they are encrypted when
+    /// the engine is defragmented or their key range is compacted, as well as
+    /// the values encrypted by the keys rotated out. All members of a cluster must be provided the same keys
+    /// since the snapshots contain the encrypted values.
+    #[inline]
+    #[must_use]
+    pub fn with_key_provider(mut self, key_provider: Arc<dyn KeyProvider>) -> Self {
+        self.cipher = Some(ValueCipher::new(key_provider));
+        self
+    }
+
     /// Get the total sst file size of all tables
     ///
     /// # WARNING
@@ -150,18 +285,113 @@ impl RocksEngine {
         Ok(())
     }
 
+    /// Add the tables to a snapshot of another engine, the files of the tables
+    /// are named with the prefix so that they are apart from the ones of the
+    /// snapshot
+    ///
+    /// # Errors
+    ///
+    /// Return `EngineError` when `RocksDB` returns an error.
+    #[inline]
+    pub fn add_to_snapshot(
+        &self,
+        snapshot: &mut RocksSnapshot,
+        tables: &[&'static str],
+        prefix: &str,
+    ) -> Result<(), EngineError> {
+        self.write_sst_files(&snapshot.dir, tables, prefix)?;
+        *snapshot = RocksSnapshot::new_for_sending(snapshot.dir.clone())?;
+        Ok(())
+    }
+
+    /// Ingest the tables added to a snapshot by [`RocksEngine::add_to_snapshot`]
+    /// with the prefix, the tables missing from the snapshot are skipped and
+    /// the snapshot is kept to be applied
+    ///
+    /// # Errors
+    ///
+    /// Return `EngineError` when `RocksDB` returns an error.
+    #[inline]
+    pub fn ingest_snapshot(
+        &self,
+        snapshot: &RocksSnapshot,
+        tables: &[&'static str],
+        prefix: &str,
+    ) -> Result<(), EngineError> {
+        self.ingest_sst_files(snapshot, tables, prefix)
+    }
+
+    /// Write the key-values of the tables to a sst file per table in the
+    /// directory, the files are named with the prefix and empty tables have
+    /// no files
+    fn write_sst_files(
+        &self,
+        dir: &Path,
+        tables: &[&'static str],
+        prefix: &str,
+    ) -> Result<(), EngineError> {
+        let snap = self.inner.snapshot();
+        let opts = Options::default();
+        let mut sst_writer_option: Option<SstFileWriter<'_>> = None;
+        for cf_name in tables {
+            let Some(cf_handle) = self.inner.cf_handle(cf_name) else {
+                return Err(EngineError::TableNotFound((*cf_name).to_owned()));
+            };
+            let iter = snap.iterator_cf(&cf_handle, IteratorMode::Start);
+            for r in iter {
+                let (key, value) = r?;
+                if let Some(ref mut sst_writer) = sst_writer_option {
+                    sst_writer.put(key, value)?;
+                } else {
+                    let mut sst_writer = SstFileWriter::create(&opts);
+                    sst_writer.open(sst_path(dir, prefix, cf_name))?;
+                    sst_writer.put(key, value)?;
+                    sst_writer_option = Some(sst_writer);
+                }
+            }
+            if let Some(ref mut sst_writer) = sst_writer_option {
+                sst_writer.finish()?;
+                sst_writer_option = None;
+            }
+        }
+        Ok(())
+    }
+
+    /// Ingest the sst files of the tables in the snapshot named with the prefix
+    fn ingest_sst_files(
+        &self,
+        snapshot: &RocksSnapshot,
+        tables: &[&'static str],
+        prefix: &str,
+    ) -> Result<(), EngineError> {
+        for cf_name in tables {
+            let file_path = sst_path(&snapshot.dir, prefix, cf_name);
+            if file_path.exists() {
+                let Some(cf_handle) = self.inner.cf_handle(cf_name) else {
+                    return Err(EngineError::TableNotFound((*cf_name).to_owned()));
+                };
+                self.inner
+                    .ingest_external_file_cf(&cf_handle, vec![file_path])?;
+            }
+        }
+        Ok(())
+    }
+
     /// Executes a function with a transaction
     /// FIXME: Removes the retry logic after curp command execution has reimplemented
     /// FIXME: Also removes the `Clone` impl of `WriteOperation`
-    fn with_transaction<F>(&self, f: F) -> Result<(), EngineError>
+    fn with_transaction<F>(&self, sync: bool, f: F) -> Result<(), EngineError>
     where
         F: Fn(&Transaction<'_, OptimisticTransactionDB>) -> Result<usize, EngineError>,
     {
         let mut retry_interval = 10;
         let max_retry_count = 5;
         let mut retry_count = 0;
+        let write_opts = self.write_opts(sync);
         loop {
-            let transaction = self.inner.transaction();
+            let transaction = self
+                .inner
+                .transaction_opt(&write_opts, &OptimisticTransactionOptions::default());
             let size = f(&transaction)?;
             match transaction.commit() {
                 Ok(()) => {
@@ -188,6 +418,19 @@ impl RocksEngine {
         }
     }
 
+    /// Get the options of a write, it's synced if it asks to be or if every
+    /// batch is synced
+    fn write_opts(&self, sync: bool) -> WriteOptions {
+        let mut write_opts = WriteOptions::default();
+        write_opts.set_sync(
+            sync || matches!(
+                self.durability.lock().level,
+                DurabilityConfig::FsyncPerBatch
+            ),
+        );
+        write_opts
+    }
+
     /// Writes an op to the db
     #[inline]
     fn write_op(
@@ -202,6 +445,7 @@ impl RocksEngine {
                     .inner
                     .cf_handle(table)
                     .ok_or(EngineError::TableNotFound(table.to_owned()))?;
+                let value = encrypt_value(self.cipher.as_ref(), table, &key, value)?;
                 let size = Self::max_write_size(table.len(), key.len(), value.len());
                 transaction.put_cf(&cf, key, value)?;
                 Ok(size)
@@ -221,7 +465,7 @@ impl RocksEngine {
                     .ok_or(EngineError::TableNotFound(table.to_owned()))?;
                 let mode = IteratorMode::From(from, Direction::Forward);
                 let kvs: Vec<_> = transaction
-                    .iterator_cf(&cf, mode)
+                    .iterator_cf_opt(&cf, total_order_read_opts(), mode)
                     .take_while(|res| res.as_ref().is_ok_and(|(key, _)| key.as_ref() < to))
                     .collect::<Result<Vec<_>, _>>()?;
                 for (key, _) in kvs {
@@ -231,17 +475,113 @@ impl RocksEngine {
             }
         }
     }
+
+    /// Encrypt the values of a table in the key range `[from, to]` that are not
+    /// encrypted by the current key again, `None` means unbounded. The values
+    /// are checked again in the transaction to not overwrite the concurrent
+    /// writes
+    fn reencrypt(
+        &self,
+        table: &str,
+        cipher: &ValueCipher,
+        from: Option<&[u8]>,
+        to: Option<&[u8]>,
+    ) -> Result<(), EngineError> {
+        let cf = self
+            .inner
+            .cf_handle(table)
+            .ok_or_else(|| EngineError::TableNotFound(table.to_owned()))?;
+        let mode = from.map_or(IteratorMode::Start, |from| {
+            IteratorMode::From(from, Direction::Forward)
+        });
+        let iter = self
+            .inner
+            .iterator_cf_opt(&cf, total_order_read_opts(), mode);
+        let mut stale = Vec::new();
+        for res in iter {
+            let (key, value) = res?;
+            if to.is_some_and(|to| key.as_ref() > to) {
+                break;
+            }
+            if !cipher.is_current(&value)? {
+                stale.push(key.into_vec());
+            }
+        }
+        for keys in stale.chunks(REENCRYPT_BATCH_SIZE) {
+            self.with_transaction(false, |txn| {
+                let mut size = 0;
+                for key in keys {
+                    let Some(value) = txn.get_for_update_cf(&cf, key, true)? else {
+                        continue;
+                    };
+                    if cipher.is_current(&value)? {
+                        continue;
+                    }
+                    let value = cipher.decrypt(table, key, value)?;
+                    let value = cipher.encrypt(table, key, &value)?;
+                    size = Self::max_write_size(table.len(), key.len(), value.len())
+                        .overflow_add(size);
+                    txn.put_cf(&cf, key, value)?;
+                }
+                Ok(size)
+            })?;
+        }
+        if !stale.is_empty() {
+            info!("encrypted {} values of table {table} again", stale.len());
+        }
+        Ok(())
+    }
+}
+
+/// Encrypt a value written to a table if encryption is enabled
+fn encrypt_value(
+    cipher: Option<&ValueCipher>,
+    table: &str,
+    key: &[u8],
+    value: Vec<u8>,
+) -> Result<Vec<u8>, EngineError> {
+    match cipher {
+        Some(cipher) => cipher.encrypt(table, key, &value),
+        None => Ok(value),
+    }
+}
+
+/// Decrypt a value read from a table if encryption is enabled
+fn decrypt_value(
+    cipher: Option<&ValueCipher>,
+    table: &str,
+    key: &[u8],
+    value: Vec<u8>,
+) -> Result<Vec<u8>, EngineError> {
+    match cipher {
+        Some(cipher) => cipher.decrypt(table, key, value),
+        None => Ok(value),
+    }
 }
 
 #[async_trait::async_trait]
 impl StorageEngine for RocksEngine {
     type Snapshot = RocksSnapshot;
     type Transaction<'db> = RocksTransaction<'db>;
+    type View<'db> = RocksView<'db>;
+
+    #[inline]
+    fn view(&self) -> RocksView<'_> {
+        RocksView::new(&self.inner, self.cipher.as_ref())
+    }
 
     #[inline]
     fn transaction(&self) -> RocksTransaction<'_> {
-        let txn = self.inner.transaction();
-        RocksTransaction::new(Arc::clone(&self.inner), txn, &self.size)
+        let txn = self.inner.transaction_opt(
+            &self.write_opts(false),
+            &OptimisticTransactionOptions::default(),
+        );
+        RocksTransaction::new(
+            Arc::clone(&self.inner),
+            txn,
+            &self.size,
+            self.cipher.as_ref(),
+        )
     }
 
     #[inline]
@@ -250,8 +590,9 @@ impl StorageEngine for RocksEngine {
             self.inner
                 .iterator_cf(&cf, IteratorMode::Start)
                 .map(|v| {
-                    v.map(|(key, value)| (key.to_vec(), value.to_vec()))
-                        .map_err(EngineError::from)
+                    let (key, value) = v?;
+                    let value = decrypt_value(self.cipher.as_ref(), table, &key, value.into_vec())?;
+                    Ok((key.into_vec(), value))
                 })
                 .collect()
         } else {
@@ -259,6 +600,21 @@ impl StorageEngine for RocksEngine {
         }
     }
 
+    /// Set a fixed length prefix extractor of the column family, the files
+    /// written afterward have the prefix bloom filters
+    #[inline]
+    fn set_prefix_len(&self, table: &str, len: usize) -> Result<(), EngineError> {
+        let cf = self
+            .inner
+            .cf_handle(table)
+            .ok_or_else(|| EngineError::TableNotFound(table.to_owned()))?;
+        self.inner.set_options_cf(
+            &cf,
+            &[("prefix_extractor", &format!("rocksdb.FixedPrefix.{len}"))],
+        )?;
+        Ok(())
+    }
+
     #[inline]
     fn get_snapshot(
         &self,
@@ -269,32 +625,7 @@ impl StorageEngine for RocksEngine {
             fs::remove_dir_all(&path)?;
         }
         fs::create_dir_all(path.as_ref())?;
-
-        let snap = self.inner.snapshot();
-        let opts = Options::default();
-        let mut sst_writer_option: Option<SstFileWriter<'_>> = None;
-        for cf_name in tables {
-            let Some(cf_handle) = self.inner.cf_handle(cf_name) else {
-                return Err(EngineError::TableNotFound((*cf_name).to_owned()));
-            };
-            let iter = snap.iterator_cf(&cf_handle, IteratorMode::Start);
-            for r in iter {
-                let (key, value) = r?;
-                if let Some(ref mut sst_writer) = sst_writer_option {
-                    sst_writer.put(key, value)?;
-                } else {
-                    let mut sst_writer = SstFileWriter::create(&opts);
-                    let file_name = format!("{cf_name}.sst");
-                    sst_writer.open(path.as_ref().join(file_name))?;
-                    sst_writer.put(key, value)?;
-                    sst_writer_option = Some(sst_writer);
-                }
-            }
-            if let Some(ref mut sst_writer) = sst_writer_option {
-                sst_writer.finish()?;
-                sst_writer_option = None;
-            }
-        }
+        self.write_sst_files(path.as_ref(), tables, "")?;
         RocksSnapshot::new_for_sending(path.as_ref())
     }
 
@@ -304,16 +635,7 @@ impl StorageEngine for RocksEngine {
         mut snapshot: Self::Snapshot,
         tables: &[&'static str],
     ) -> Result<(), EngineError> {
-        for cf_name in tables {
-            let file_path = snapshot.sst_path(cf_name);
-            if file_path.exists() {
-                let Some(cf_handle) = self.inner.cf_handle(cf_name) else {
-                    return Err(EngineError::TableNotFound((*cf_name).to_owned()));
-                };
-                self.inner
-                    .ingest_external_file_cf(&cf_handle, vec![file_path])?;
-            }
-        }
+        self.ingest_sst_files(&snapshot, tables, "")?;
         snapshot.clean().await?;
         Ok(())
     }
@@ -328,13 +650,182 @@ impl StorageEngine for RocksEngine {
         self.size.store(size, std::sync::atomic::Ordering::Relaxed);
         Ok(size)
     }
+
+    /// Get the estimated live data size of the sst files, call `file_size` first to
+    /// count the data in memtables
+    fn live_data_size(&self) -> Result<u64, EngineError> {
+        let mut size = 0_u64;
+        for table in &self.tables {
+            let cf = self
+                .inner
+                .cf_handle(table)
+                .ok_or_else(|| EngineError::TableNotFound(table.clone()))?;
+            size = self
+                .inner
+                .property_int_value_cf(&cf, rocksdb::properties::ESTIMATE_LIVE_DATA_SIZE)?
+                .ok_or(EngineError::UnderlyingError(
+                    "Got None when read ESTIMATE_LIVE_DATA_SIZE".to_owned(),
+                ))?
+                .overflow_add(size);
+        }
+        Ok(size)
+    }
+
+    /// Get the size of the sst files and the memtables of the table
+    fn table_size(&self, table: &str) -> Result<u64, EngineError> {
+        let cf = self
+            .inner
+            .cf_handle(table)
+            .ok_or_else(|| EngineError::TableNotFound(table.to_owned()))?;
+        let mut size = 0_u64;
+        for property in [
+            rocksdb::properties::TOTAL_SST_FILES_SIZE,
+            rocksdb::properties::CUR_SIZE_ALL_MEM_TABLES,
+        ] {
+            size = self
+                .inner
+                .property_int_value_cf(&cf, property)?
+                .ok_or_else(|| {
+                    EngineError::UnderlyingError(format!("Got None when read {property:?}"))
+                })?
+                .overflow_add(size);
+        }
+        Ok(size)
+    }
+
+    /// Flush and compact every table, the cached size is refreshed when finished
+    fn defragment(&self) -> Result<(), EngineError> {
+        for (i, table) in self.tables.iter().enumerate() {
+            let cf = self
+                .inner
+                .cf_handle(table)
+                .ok_or_else(|| EngineError::TableNotFound(table.clone()))?;
+            if let Some(ref cipher) = self.cipher {
+                self.reencrypt(table, cipher, None, None)?;
+            }
+            self.inner.flush_cf(&cf)?;
+            // the files of the last level are rewritten as well, so all the
+            // data are compressed with the current codec
+            let mut compact_opts = CompactOptions::default();
+            compact_opts.set_bottommost_level_compaction(BottommostLevelCompaction::Force);
+            self.inner
+                .compact_range_cf_opt(&cf, None::<&[u8]>, None::<&[u8]>, &compact_opts);
+            info!(
+                "defragment table {table} finished, {}/{} tables",
+                i.overflow_add(1),
+                self.tables.len()
+            );
+        }
+        let _size = self.file_size()?;
+        Ok(())
+    }
+
+    /// Compact the key range of the table, the deleted records in the memtables
+    /// are flushed before compacting. The values of the range are encrypted
+    /// again with the current key, so that the rotated keys are retired as the
+    /// key range is compacted
+    fn compact_range(
+        &self,
+        table: &str,
+        from: Option<&[u8]>,
+        to: Option<&[u8]>,
+    ) -> Result<u64, EngineError> {
+        let cf = self
+            .inner
+            .cf_handle(table)
+            .ok_or_else(|| EngineError::TableNotFound(table.to_owned()))?;
+        if let Some(ref cipher) = self.cipher {
+            self.reencrypt(table, cipher, from, to)?;
+        }
+        let before = self.table_size(table)?;
+        self.inner.flush_cf(&cf)?;
+        self.inner.compact_range_cf(&cf, from, to);
+        let reclaimed = before.saturating_sub(self.table_size(table)?);
+        let _prev = self.size.fetch_update(
+            std::sync::atomic::Ordering::Relaxed,
+            std::sync::atomic::Ordering::Relaxed,
+            |size| Some(size.saturating_sub(reclaimed)),
+        );
+        Ok(reclaimed)
+    }
+
+    /// Get the statistics collected by `RocksDB`, the open files are the live
+    /// sst files kept open by the table cache
+    fn stats(&self) -> Result<EngineStats, EngineError> {
+        let compaction = self
+            .statistics
+            .0
+            .get_histogram_data(Histogram::CompactionTime);
+        Ok(EngineStats {
+            write_stall_micros: self.statistics.0.get_ticker_count(Ticker::StallMicros),
+            compactions: compaction.count(),
+            compaction_micros: compaction.sum(),
+            open_files: self.inner.live_files()?.len().numeric_cast(),
+        })
+    }
+
+    /// Change the compression options of the column family, the new files
+    /// written by flushes and compactions use the new codec
+    fn set_compression(
+        &self,
+        table: &str,
+        compression: CompressionConfig,
+    ) -> Result<(), EngineError> {
+        let cf = self
+            .inner
+            .cf_handle(table)
+            .ok_or_else(|| EngineError::TableNotFound(table.to_owned()))?;
+        let codec = match *compression.codec() {
+            CompressionCodec::None => "kNoCompression",
+            CompressionCodec::Snappy => "kSnappyCompression",
+            CompressionCodec::Lz4 => "kLZ4Compression",
+            CompressionCodec::Zstd => "kZSTD",
+            _ => {
+                return Err(EngineError::InvalidArgument(format!(
+                    "unsupported compression codec {:?}",
+                    compression.codec()
+                )))
+            }
+        };
+        let level = compression.level().unwrap_or(DEFAULT_COMPRESSION_LEVEL);
+        self.inner.set_options_cf(
+            &cf,
+            &[
+                ("compression", codec),
+                ("compression_opts", &format!("{{level={level}}}")),
+            ],
+        )?;
+        info!("compression of table {table} is set to {codec} at level {level}");
+        Ok(())
+    }
+
+    /// The writes are synced by a thread once per interval in the
+    /// `fsync-interval` level
+    fn set_durability(&self, durability: DurabilityConfig) -> Result<(), EngineError> {
+        let mut state = self.durability.lock();
+        state.level = durability;
+        if matches!(durability, DurabilityConfig::FsyncInterval(_)) && !state.syncer_running {
+            let db = Arc::downgrade(&self.inner);
+            let shared = Arc::clone(&self.durability);
+            let _handle = std::thread::Builder::new()
+                .name("engine-syncer".to_owned())
+                .spawn(move || sync_periodically(&db, &shared))?;
+            state.syncer_running = true;
+        }
+        info!("durability of the engine is set to {durability}");
+        Ok(())
+    }
 }
 
 impl StorageOps for RocksEngine {
     #[inline]
     fn get(&self, table: &str, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>, EngineError> {
         if let Some(cf) = self.inner.cf_handle(table) {
-            Ok(self.inner.get_cf(&cf, key)?)
+            let key = key.as_ref();
+            self.inner
+                .get_cf(&cf, key)?
+                .map(|value| decrypt_value(self.cipher.as_ref(), table, key, value))
+                .transpose()
         } else {
             Err(EngineError::TableNotFound(table.to_owned()))
         }
@@ -350,24 +841,60 @@ impl StorageOps for RocksEngine {
             self.inner
                 .multi_get_cf(repeat(&cf).zip(keys.iter()))
                 .into_iter()
-                .map(|res| res.map_err(EngineError::from))
+                .zip(keys)
+                .map(|(res, key)| {
+                    res?.map(|value| {
+                        decrypt_value(self.cipher.as_ref(), table, key.as_ref(), value)
+                    })
+                    .transpose()
+                })
                 .collect::<Result<Vec<_>, EngineError>>()
         } else {
             Err(EngineError::TableNotFound(table.to_owned()))
         }
     }
 
-    fn write(&self, op: WriteOperation<'_>, _sync: bool) -> Result<(), EngineError> {
-        self.with_transaction(|txn| self.write_op(txn, op.clone()))
+    /// The seek is bounded by the prefix, and the files without the prefix are
+    /// skipped by the prefix bloom filters if the prefix length is set
+    #[inline]
+    fn get_prefix(
+        &self,
+        table: &str,
+        prefix: &[u8],
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, EngineError> {
+        let cf = self
+            .inner
+            .cf_handle(table)
+            .ok_or_else(|| EngineError::TableNotFound(table.to_owned()))?;
+        self.inner
+            .iterator_cf_opt(
+                &cf,
+                prefix_read_opts(prefix),
+                IteratorMode::From(prefix, Direction::Forward),
+            )
+            .take_while(|res| {
+                res.as_ref()
+                    .map_or(true, |(key, _)| key.starts_with(prefix))
+            })
+            .map(|res| {
+                let (key, value) = res?;
+                let value = decrypt_value(self.cipher.as_ref(), table, &key, value.into_vec())?;
+                Ok((key.into_vec(), value))
+            })
+            .collect()
+    }
+
+    fn write(&self, op: WriteOperation<'_>, sync: bool) -> Result<(), EngineError> {
+        self.with_transaction(sync, |txn| self.write_op(txn, op.clone()))
     }
 
     #[inline]
-    fn write_multi<'a, Ops>(&self, ops: Ops, _sync: bool) -> Result<(), EngineError>
+    fn write_multi<'a, Ops>(&self, ops: Ops, sync: bool) -> Result<(), EngineError>
     where
         Ops: IntoIterator<Item = WriteOperation<'a>>,
     {
         let ops: Vec<_> = ops.into_iter().collect();
-        self.with_transaction(|txn| {
+        self.with_transaction(sync, |txn| {
             let mut size = 0;
             for op in ops.clone() {
                 size = size.overflow_add(self.write_op(txn, op)?);
@@ -503,11 +1030,6 @@ impl RocksSnapshot {
         Ok(s)
     }
 
-    /// Get sst file path of the table
-    pub(crate) fn sst_path(&self, table: &str) -> PathBuf {
-        self.dir.join(table).with_extension("sst")
-    }
-
     /// Apply snapshot meta
     fn apply_snap_meta(&mut self, meta: SnapMeta) {
         self.snap_files = meta
@@ -725,6 +1247,8 @@ impl SnapshotApi for RocksSnapshot {
 
 #[cfg(test)]
 mod test {
+    use std::time::Duration;
+
     use tempfile::TempDir;
     use test_macros::abort_on_panic;
 
@@ -776,6 +1300,357 @@ mod test {
             .unwrap();
         let size2 = engine.file_size().unwrap();
         assert!(size2 > size1);
+        let live_size = engine.live_data_size().unwrap();
+        assert!(live_size > 0 && live_size <= size2);
+        dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_write_should_persist_after_reopen() {
+        let dir = TempDir::with_prefix("/tmp/test_write_persist").unwrap();
+        let engine_path = dir.path().join("engine");
+        {
+            let engine = RocksEngine::new(&engine_path, &TEST_TABLES).unwrap();
+            engine
+                .write(
+                    WriteOperation::new_put("t1", b"sync".to_vec(), b"1".to_vec()),
+                    true,
+                )
+                .unwrap();
+            engine
+                .write_multi(
+                    vec![
+                        WriteOperation::new_put("t2", b"no_sync".to_vec(), b"2".to_vec()),
+                        WriteOperation::new_put("t3", b"no_sync".to_vec(), b"3".to_vec()),
+                    ],
+                    false,
+                )
+                .unwrap();
+        }
+        let engine = RocksEngine::new(&engine_path, &TEST_TABLES).unwrap();
+        assert_eq!(engine.get("t1", b"sync").unwrap(), Some(b"1".to_vec()));
+        assert_eq!(engine.get("t2", b"no_sync").unwrap(), Some(b"2".to_vec()));
+        assert_eq!(engine.get("t3", b"no_sync").unwrap(), Some(b"3".to_vec()));
+        dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_encrypted_values_should_be_rotated_by_defragment() {
+        use crate::{StaticKeyProvider, TransactionApi};
+
+        let dir = TempDir::with_prefix("/tmp/test_encryption").unwrap();
+        let engine_path = dir.path().join("engine");
+        let provider = |ids: &[u8]| -> Arc<dyn KeyProvider> {
+            Arc::new(
+                StaticKeyProvider::new(ids.iter().map(|&id| (u32::from(id), [id; 32]))).unwrap(),
+            )
+        };
+        let raw_value = |key: &[u8]| {
+            RocksEngine::new(&engine_path, &TEST_TABLES)
+                .unwrap()
+                .get("t1", key)
+                .unwrap()
+                .unwrap()
+        };
+        {
+            let plain = RocksEngine::new(&engine_path, &TEST_TABLES).unwrap();
+            plain
+                .write(
+                    WriteOperation::new_put("t1", b"plain".to_vec(), b"0".to_vec()),
+                    false,
+                )
+                .unwrap();
+        }
+        {
+            let engine = RocksEngine::new(&engine_path, &TEST_TABLES)
+                .unwrap()
+                .with_key_provider(provider(&[1]));
+            engine
+                .write(
+                    WriteOperation::new_put("t1", b"key".to_vec(), b"value".to_vec()),
+                    false,
+                )
+                .unwrap();
+            let txn = engine.transaction();
+            txn.write(
+                WriteOperation::new_put("t1", b"txn".to_vec(), b"value".to_vec()),
+                false,
+            )
+            .unwrap();
+            assert_eq!(txn.get("t1", b"txn").unwrap(), Some(b"value".to_vec()));
+            txn.commit().unwrap();
+            assert_eq!(engine.get("t1", b"plain").unwrap(), Some(b"0".to_vec()));
+            assert_eq!(
+                engine.get_multi("t1", &[b"key", b"txn"]).unwrap(),
+                vec![Some(b"value".to_vec()), Some(b"value".to_vec())]
+            );
+        }
+        let encrypted = raw_value(b"key");
+        assert_ne!(encrypted, b"value");
+        assert_eq!(raw_value(b"plain"), b"0");
+
+        let engine = RocksEngine::new(&engine_path, &TEST_TABLES)
+            .unwrap()
+            .with_key_provider(provider(&[1, 2]));
+        assert_eq!(engine.get("t1", b"key").unwrap(), Some(b"value".to_vec()));
+        engine.defragment().unwrap();
+        let all = engine.get_all("t1").unwrap();
+        assert_eq!(
+            all,
+            vec![
+                (b"key".to_vec(), b"value".to_vec()),
+                (b"plain".to_vec(), b"0".to_vec()),
+                (b"txn".to_vec(), b"value".to_vec()),
+            ]
+        );
+        drop(engine);
+        assert_ne!(raw_value(b"key"), encrypted);
+        assert_ne!(raw_value(b"plain"), b"0");
+
+        // the former key is no longer needed
+        let engine = RocksEngine::new(&engine_path, &TEST_TABLES)
+            .unwrap()
+            .with_key_provider(provider(&[2]));
+        assert_eq!(engine.get_all("t1").unwrap(), all);
+        dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_encrypted_values_should_be_rotated_by_compact_range() {
+        use crate::StaticKeyProvider;
+
+        let dir = TempDir::with_prefix("/tmp/test_encryption_compact").unwrap();
+        let engine_path = dir.path().join("engine");
+        let provider = |ids: &[u8]| -> Arc<dyn KeyProvider> {
+            Arc::new(
+                StaticKeyProvider::new(ids.iter().map(|&id| (u32::from(id), [id; 32]))).unwrap(),
+            )
+        };
+        {
+            let engine = RocksEngine::new(&engine_path, &TEST_TABLES)
+                .unwrap()
+                .with_key_provider(provider(&[1]));
+            for key in [b"a", b"b", b"c"] {
+                engine
+                    .write(
+                        WriteOperation::new_put("t1", key.to_vec(), b"value".to_vec()),
+                        false,
+                    )
+                    .unwrap();
+            }
+        }
+        {
+            let engine = RocksEngine::new(&engine_path, &TEST_TABLES)
+                .unwrap()
+                .with_key_provider(provider(&[1, 2]));
+            let _reclaimed = engine.compact_range("t1", Some(b"a"), Some(b"b")).unwrap();
+        }
+
+        let engine = RocksEngine::new(&engine_path, &TEST_TABLES)
+            .unwrap()
+            .with_key_provider(provider(&[2]));
+        assert_eq!(
+            engine.get_multi("t1", &[b"a", b"b"]).unwrap(),
+            vec![Some(b"value".to_vec()), Some(b"value".to_vec())]
+        );
+        // out of the compacted range, still encrypted by the former key
+        assert!(engine.get("t1", b"c").is_err());
+        dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_get_prefix_should_stop_at_the_end_of_the_prefix() {
+        let dir = TempDir::with_prefix("/tmp/test_get_prefix").unwrap();
+        let engine = RocksEngine::new(dir.path().join("engine"), &TEST_TABLES).unwrap();
+        let keys: [&[u8]; 8] = [
+            &[0, 1],
+            &[0, 1, 0],
+            &[0, 1, u8::MAX],
+            &[0, 2],
+            &[1, u8::MAX],
+            &[1, u8::MAX, 1],
+            &[2],
+            &[u8::MAX, u8::MAX, 1],
+        ];
+        let ops = keys
+            .iter()
+            .map(|key| WriteOperation::new_put("t1", key.to_vec(), key.to_vec()));
+        engine.write_multi(ops, false).unwrap();
+
+        let found = |prefix: &[u8]| -> Vec<Vec<u8>> {
+            engine
+                .get_prefix("t1", prefix)
+                .unwrap()
+                .into_iter()
+                .map(|(key, _)| key)
+                .collect()
+        };
+        assert_eq!(
+            found(&[0, 1]),
+            [vec![0, 1], vec![0, 1, 0], vec![0, 1, u8::MAX]]
+        );
+        assert_eq!(
+            found(&[1, u8::MAX]),
+            [vec![1, u8::MAX], vec![1, u8::MAX, 1]]
+        );
+        // there is no upper bound of a prefix of `u8::MAX`s
+        assert_eq!(found(&[u8::MAX, u8::MAX]), [vec![u8::MAX, u8::MAX, 1]]);
+        assert!(found(&[3]).is_empty());
+        assert_eq!(prefix_upper_bound(&[1, u8::MAX]), Some(vec![2]));
+        assert_eq!(prefix_upper_bound(&[u8::MAX, u8::MAX]), None);
+        dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_view_should_not_see_later_writes() {
+        use crate::ViewApi;
+
+        let dir = TempDir::with_prefix("/tmp/test_view").unwrap();
+        let engine = RocksEngine::new(dir.path().join("engine"), &TEST_TABLES).unwrap();
+        engine
+            .write_multi(
+                vec![
+                    WriteOperation::new_put("t1", b"b".to_vec(), b"2".to_vec()),
+                    WriteOperation::new_put("t1", b"a".to_vec(), b"1".to_vec()),
+                    WriteOperation::new_put("t2", b"a".to_vec(), b"1".to_vec()),
+                ],
+                false,
+            )
+            .unwrap();
+        let view = engine.view();
+        engine
+            .write_multi(
+                vec![
+                    WriteOperation::new_put("t1", b"c".to_vec(), b"3".to_vec()),
+                    WriteOperation::new_delete("t1", b"a"),
+                    WriteOperation::new_delete("t2", b"a"),
+                ],
+                false,
+            )
+            .unwrap();
+        assert_eq!(view.get("t1", b"a").unwrap(), Some(b"1".to_vec()));
+        assert_eq!(view.get("t1", b"c").unwrap(), None);
+        assert_eq!(
+            view.iter("t1")
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap(),
+            vec![
+                (b"a".to_vec(), b"1".to_vec()),
+                (b"b".to_vec(), b"2".to_vec())
+            ]
+        );
+        assert_eq!(view.iter("t2").unwrap().count(), 1);
+        assert!(view.iter("t4").is_err());
+        assert_eq!(
+            view.last("t1").unwrap(),
+            Some((b"b".to_vec(), b"2".to_vec()))
+        );
+        assert_eq!(view.last("t3").unwrap(), None);
+        assert!(view.last("t4").is_err());
+        assert!(engine.get_all("t2").unwrap().is_empty());
+        drop(view);
+        dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_defragment() {
+        let dir = TempDir::with_prefix("/tmp/test_defragment").unwrap();
+        let engine_path = dir.path().join("engine");
+        let engine = RocksEngine::new(engine_path, &TEST_TABLES).unwrap();
+        let puts = (0..1000_u32)
+            .map(|i| WriteOperation::new_put("t1", i.to_be_bytes().to_vec(), vec![0; 128]))
+            .collect::<Vec<_>>();
+        engine.write_multi(puts, true).unwrap();
+        let _size = engine.file_size().unwrap();
+        let start = 0_u32.to_be_bytes();
+        let end = 1000_u32.to_be_bytes();
+        engine
+            .write_multi(
+                vec![WriteOperation::new_delete_range("t1", &start, &end)],
+                true,
+            )
+            .unwrap();
+        let fragmented_size = engine.file_size().unwrap();
+
+        engine.defragment().unwrap();
+        assert!(engine.estimated_file_size() < fragmented_size);
+        assert!(engine.get_all("t1").unwrap().is_empty());
+        dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_stats_should_count_files_and_compactions() {
+        let dir = TempDir::with_prefix("/tmp/test_stats").unwrap();
+        let engine = RocksEngine::new(dir.path().join("engine"), &TEST_TABLES).unwrap();
+        assert_eq!(engine.stats().unwrap().compactions, 0);
+        // two overlapping files, so that the compaction can't just move them
+        for value in [0_u8, 1] {
+            let puts = (0..100_u32)
+                .map(|i| WriteOperation::new_put("t1", i.to_be_bytes().to_vec(), vec![value; 128]))
+                .collect::<Vec<_>>();
+            engine.write_multi(puts, false).unwrap();
+            let _size = engine.file_size().unwrap();
+        }
+        assert!(engine.stats().unwrap().open_files >= 2);
+
+        engine.defragment().unwrap();
+        let stats = engine.stats().unwrap();
+        assert!(stats.compactions > 0);
+        assert!(stats.compaction_micros > 0);
+        dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_set_compression_should_recompress_on_defragment() {
+        let dir = TempDir::with_prefix("/tmp/test_set_compression").unwrap();
+        let engine = RocksEngine::new(dir.path().join("engine"), &TEST_TABLES).unwrap();
+        let none = CompressionConfig::new(CompressionCodec::None, None);
+        assert!(engine.set_compression("not_exist", none).is_err());
+        engine.set_compression("t1", none).unwrap();
+        let puts = (0..100_u32)
+            .map(|i| WriteOperation::new_put("t1", i.to_be_bytes().to_vec(), vec![0; 4096]))
+            .collect::<Vec<_>>();
+        engine.write_multi(puts, false).unwrap();
+        engine.defragment().unwrap();
+        let uncompressed = engine.table_size("t1").unwrap();
+
+        engine
+            .set_compression(
+                "t1",
+                CompressionConfig::new(CompressionCodec::Zstd, Some(3)),
+            )
+            .unwrap();
+        engine.defragment().unwrap();
+        let compressed = engine.table_size("t1").unwrap();
+        assert!(compressed.overflow_mul(4) < uncompressed);
+        assert_eq!(
+            engine.get("t1", 7_u32.to_be_bytes()).unwrap(),
+            Some(vec![0; 4096])
+        );
+        dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_syncer_should_stop_when_durability_changed() {
+        let dir = TempDir::with_prefix("/tmp/test_set_durability").unwrap();
+        let engine = RocksEngine::new(dir.path().join("engine"), &TEST_TABLES).unwrap();
+        engine
+            .set_durability(DurabilityConfig::FsyncInterval(Duration::from_millis(10)))
+            .unwrap();
+        assert!(engine.durability.lock().syncer_running);
+        engine
+            .write(
+                WriteOperation::new_put("t1", b"key".to_vec(), b"value".to_vec()),
+                false,
+            )
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(30));
+
+        engine.set_durability(DurabilityConfig::OsBuffered).unwrap();
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(!engine.durability.lock().syncer_running);
+        assert_eq!(engine.get("t1", b"key").unwrap(), Some(b"value".to_vec()));
         dir.close().unwrap();
     }
 }
@@ -12,9 +12,12 @@ use clippy_utilities::NumericCast;
 use parking_lot::Mutex;
 use rocksdb::{Direction, IteratorMode, OptimisticTransactionDB, Transaction};
 
-use crate::{api::transaction_api::TransactionApi, error::EngineError, StorageOps, WriteOperation};
+use crate::{
+    api::transaction_api::TransactionApi, encryption::ValueCipher, error::EngineError, StorageOps,
+    WriteOperation,
+};
 
-use super::RocksEngine;
+use super::{decrypt_value, encrypt_value, prefix_read_opts, total_order_read_opts, RocksEngine};
 
 /// Transaction type for `RocksDB`
 pub struct RocksTransaction<'db> {
@@ -28,6 +31,8 @@ pub struct RocksTransaction<'db> {
     engine_size: &'db AtomicU64,
     /// The size of the txn
     txn_size: AtomicUsize,
+    /// Cipher of the values, `None` if encryption is disabled
+    cipher: Option<&'db ValueCipher>,
 }
 
 /// Write operation
@@ -68,12 +73,14 @@ impl<'db> RocksTransaction<'db> {
         db: Arc<OptimisticTransactionDB>,
         txn: Transaction<'db, OptimisticTransactionDB>,
         engine_size: &'db AtomicU64,
+        cipher: Option<&'db ValueCipher>,
     ) -> Self {
         Self {
             db,
             txn: Mutex::new(Some(txn)),
             engine_size,
             txn_size: AtomicUsize::new(0),
+            cipher,
         }
     }
 }
@@ -87,6 +94,7 @@ impl StorageOps for RocksTransaction<'_> {
                     .db
                     .cf_handle(table.as_ref())
                     .ok_or_else(|| EngineError::TableNotFound(table.clone()))?;
+                let value = encrypt_value(self.cipher, &table, &key, value)?;
                 self.txn
                     .lock()
                     .as_ref()
@@ -120,7 +128,7 @@ impl StorageOps for RocksTransaction<'_> {
                 let txn_ref = txn_l.as_ref().unwrap();
                 #[allow(clippy::pattern_type_mismatch)] // can't be fixed
                 let kvs = txn_ref
-                    .iterator_cf(&cf, mode)
+                    .iterator_cf_opt(&cf, total_order_read_opts(), mode)
                     .take_while(|res| {
                         res.as_ref()
                             .is_ok_and(|(key, _)| key.as_ref() < to.as_slice())
@@ -150,12 +158,14 @@ impl StorageOps for RocksTransaction<'_> {
             .db
             .cf_handle(table.as_ref())
             .ok_or_else(|| EngineError::TableNotFound(table.to_owned()))?;
+        let key = key.as_ref();
         self.txn
             .lock()
             .as_ref()
             .unwrap()
-            .get_cf(&cf, key)
-            .map_err(EngineError::from)
+            .get_cf(&cf, key)?
+            .map(|value| decrypt_value(self.cipher, table, key, value))
+            .transpose()
     }
 
     fn get_multi(
@@ -173,8 +183,41 @@ impl StorageOps for RocksTransaction<'_> {
             .unwrap()
             .multi_get_cf(repeat(&cf).zip(keys.iter()))
             .into_iter()
-            .collect::<Result<_, _>>()
-            .map_err(EngineError::from)
+            .zip(keys)
+            .map(|(res, key)| {
+                res?.map(|value| decrypt_value(self.cipher, table, key.as_ref(), value))
+                    .transpose()
+            })
+            .collect()
+    }
+
+    fn get_prefix(
+        &self,
+        table: &str,
+        prefix: &[u8],
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, EngineError> {
+        let cf = self
+            .db
+            .cf_handle(table.as_ref())
+            .ok_or_else(|| EngineError::TableNotFound(table.to_owned()))?;
+        let mode = IteratorMode::From(prefix, Direction::Forward);
+        let txn_l = self.txn.lock();
+        #[allow(clippy::pattern_type_mismatch)] // can't be fixed
+        let kvs = txn_l
+            .as_ref()
+            .unwrap()
+            .iterator_cf_opt(&cf, prefix_read_opts(prefix), mode)
+            .take_while(|res| {
+                res.as_ref()
+                    .map_or(true, |(key, _)| key.starts_with(prefix))
+            })
+            .map(|res| {
+                let (key, value) = res?;
+                let value = decrypt_value(self.cipher, table, &key, value.into_vec())?;
+                Ok((key.into_vec(), value))
+            })
+            .collect();
+        kvs
     }
 }
 
@@ -206,6 +249,7 @@ impl std::fmt::Debug for RocksTransaction<'_> {
             .field("db", &self.db)
             .field("engine_size", &self.engine_size)
             .field("txn_size", &self.txn_size)
+            .field("cipher", &self.cipher)
             .finish()
     }
 }
@@ -0,0 +1,92 @@
+use rocksdb::{IteratorMode, OptimisticTransactionDB, SnapshotWithThreadMode};
+
+use super::decrypt_value;
+use crate::{api::view_api::ViewApi, encryption::ValueCipher, error::EngineError};
+
+/// Point-in-time view of `RocksDB`, backed by a `RocksDB` snapshot
+///
+/// A snapshot only pins the data of the sequence number it's created at, the
+/// writes after it go on and nothing is copied.
+pub struct RocksView<'db> {
+    /// The inner DB
+    db: &'db OptimisticTransactionDB,
+    /// The snapshot of the DB
+    snapshot: SnapshotWithThreadMode<'db, OptimisticTransactionDB>,
+    /// Cipher of the values, `None` if encryption is disabled
+    cipher: Option<&'db ValueCipher>,
+}
+
+impl<'db> RocksView<'db> {
+    /// Creates a new `RocksView`
+    pub(super) fn new(db: &'db OptimisticTransactionDB, cipher: Option<&'db ValueCipher>) -> Self {
+        Self {
+            db,
+            snapshot: db.snapshot(),
+            cipher,
+        }
+    }
+}
+
+impl ViewApi for RocksView<'_> {
+    #[inline]
+    fn get(&self, table: &str, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>, EngineError> {
+        let cf = self
+            .db
+            .cf_handle(table)
+            .ok_or_else(|| EngineError::TableNotFound(table.to_owned()))?;
+        self.snapshot
+            .get_cf(&cf, key.as_ref())?
+            .map(|value| decrypt_value(self.cipher, table, key.as_ref(), value))
+            .transpose()
+    }
+
+    #[inline]
+    fn iter(
+        &self,
+        table: &str,
+    ) -> Result<Box<dyn Iterator<Item = Result<(Vec<u8>, Vec<u8>), EngineError>> + '_>, EngineError>
+    {
+        let cf = self
+            .db
+            .cf_handle(table)
+            .ok_or_else(|| EngineError::TableNotFound(table.to_owned()))?;
+        let table = table.to_owned();
+        Ok(Box::new(
+            self.snapshot
+                .iterator_cf(&cf, IteratorMode::Start)
+                .map(move |item| {
+                    let (key, value) = item?;
+                    let value = decrypt_value(self.cipher, &table, &key, value.into_vec())?;
+                    Ok((key.into_vec(), value))
+                }),
+        ))
+    }
+
+    #[inline]
+    fn last(&self, table: &str) -> Result<Option<(Vec<u8>, Vec<u8>)>, EngineError> {
+        let cf = self
+            .db
+            .cf_handle(table)
+            .ok_or_else(|| EngineError::TableNotFound(table.to_owned()))?;
+        self.snapshot
+            .iterator_cf(&cf, IteratorMode::End)
+            .next()
+            .transpose()?
+            .map(|(key, value)| {
+                let value = decrypt_value(self.cipher, table, &key, value.into_vec())?;
+                Ok((key.into_vec(), value))
+            })
+            .transpose()
+    }
+}
+
+#[allow(clippy::missing_fields_in_debug)]
+impl std::fmt::Debug for RocksView<'_> {
+    #[inline]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RocksView")
+            .field("db", &self.db)
+            .field("cipher", &self.cipher)
+            .finish()
+    }
+}
@@ -99,7 +99,13 @@ pub struct ClusterConfig {
     client_advertise_urls: Vec<String>,
     /// All the nodes in the xline cluster
     #[getset(get = "pub")]
+    #[serde(default)]
     peers: HashMap<String, Vec<String>>,
+    /// Domain whose SRV records list the peer urls of the nodes, the nodes are
+    /// discovered from it at startup instead of `peers`
+    #[getset(get = "pub")]
+    #[serde(default)]
+    discovery_srv: Option<String>,
     /// Leader node.
     #[getset(get = "pub")]
     is_leader: bool,
@@ -119,6 +125,11 @@ pub struct ClusterConfig {
     #[getset(get = "pub")]
     #[serde(with = "state_format", default = "InitialClusterState::default")]
     initial_cluster_state: InitialClusterState,
+    /// Max size in bytes of the event responses of a watch stream that are not
+    /// taken by the transport yet, the events of the stream are paused beyond it
+    #[getset(get = "pub")]
+    #[serde(default = "default_watch_flow_control_window")]
+    watch_flow_control_window: usize,
 }
 
 impl Default for ClusterConfig {
@@ -134,11 +145,13 @@ impl Default for ClusterConfig {
                 "default".to_owned(),
                 vec!["http://127.0.0.1:2379".to_owned()],
             )]),
+            discovery_srv: None,
             is_leader: false,
             curp_config: CurpConfig::default(),
             client_config: ClientConfig::default(),
             server_timeout: ServerTimeout::default(),
             initial_cluster_state: InitialClusterState::default(),
+            watch_flow_control_window: default_watch_flow_control_window(),
         }
     }
 }
@@ -197,13 +210,39 @@ impl ClusterConfig {
             client_listen_urls,
             client_advertise_urls,
             peers,
+            discovery_srv: None,
             is_leader,
             curp_config: curp,
             client_config,
             server_timeout,
             initial_cluster_state,
+            watch_flow_control_window: default_watch_flow_control_window(),
         }
     }
+
+    /// Discover the nodes from the SRV records of the domain at startup
+    #[must_use]
+    #[inline]
+    pub fn with_discovery_srv(mut self, discovery_srv: Option<String>) -> Self {
+        self.discovery_srv = discovery_srv;
+        self
+    }
+
+    /// Set the nodes in the xline cluster, e.g. the discovered ones
+    #[must_use]
+    #[inline]
+    pub fn with_peers(mut self, peers: HashMap<String, Vec<String>>) -> Self {
+        self.peers = peers;
+        self
+    }
+
+    /// Set the flow control window of the event responses of a watch stream
+    #[must_use]
+    #[inline]
+    pub fn with_watch_flow_control_window(mut self, watch_flow_control_window: usize) -> Self {
+        self.watch_flow_control_window = watch_flow_control_window;
+        self
+    }
 }
 
 /// Compaction configuration
@@ -340,6 +379,20 @@ pub struct CurpConfig {
     #[builder(default = "default_log_entries_cap()")]
     #[serde(default = "default_log_entries_cap")]
     pub log_entries_cap: usize,
+
+    /// Reject the membership changes that leave the cluster without a quorum
+    /// of active voters
+    ///
+    /// It can be disabled to reconfigure a cluster which has lost its quorum
+    #[builder(default = "default_strict_reconfig_check()")]
+    #[serde(default = "default_strict_reconfig_check")]
+    pub strict_reconfig_check: bool,
+
+    /// The max delay to wait for more log entries to be appended before the
+    /// log is synced to disk, zero only batches the concurrent appends
+    #[builder(default = "default_wal_sync_max_delay()")]
+    #[serde(with = "duration_format", default = "default_wal_sync_max_delay")]
+    pub wal_sync_max_delay: Duration,
 }
 
 /// default heartbeat interval
@@ -493,6 +546,20 @@ pub const fn default_log_entries_cap() -> usize {
     5000
 }
 
+/// default strict reconfig check
+#[must_use]
+#[inline]
+pub const fn default_strict_reconfig_check() -> bool {
+    true
+}
+
+/// default max delay of the log sync, the concurrent appends are still batched
+#[must_use]
+#[inline]
+pub const fn default_wal_sync_max_delay() -> Duration {
+    Duration::ZERO
+}
+
 /// default watch progress notify interval
 #[must_use]
 #[inline]
@@ -500,6 +567,49 @@ pub const fn default_watch_progress_notify_interval() -> Duration {
     Duration::from_secs(600)
 }
 
+/// default watch batch interval, zero disables the batching
+#[must_use]
+#[inline]
+pub const fn default_watch_batch_interval() -> Duration {
+    Duration::ZERO
+}
+
+/// default min lease ttl, zero means the election timeout is the only bound
+#[must_use]
+#[inline]
+pub const fn default_min_lease_ttl() -> Duration {
+    Duration::ZERO
+}
+
+/// default max lease ttl
+#[must_use]
+#[inline]
+pub const fn default_max_lease_ttl() -> Duration {
+    Duration::from_secs(9_000_000_000)
+}
+
+/// default flow control window of a watch stream: 64MB
+#[must_use]
+#[inline]
+pub const fn default_watch_flow_control_window() -> usize {
+    // 64 * 1024 * 1024
+    0x400_0000
+}
+
+/// default corrupt check interval, zero disables the periodic check
+#[must_use]
+#[inline]
+pub const fn default_corrupt_check_interval() -> Duration {
+    Duration::ZERO
+}
+
+/// default lease checkpoint interval: 5min, zero disables the checkpoints
+#[must_use]
+#[inline]
+pub const fn default_lease_checkpoint_interval() -> Duration {
+    Duration::from_secs(300)
+}
+
 impl Default for CurpConfig {
     #[inline]
     fn default() -> Self {
@@ -516,6 +626,8 @@ impl Default for CurpConfig {
             cmd_workers: default_cmd_workers(),
             gc_interval: default_gc_interval(),
             log_entries_cap: default_log_entries_cap(),
+            strict_reconfig_check: default_strict_reconfig_check(),
+            wal_sync_max_delay: default_wal_sync_max_delay(),
         }
     }
 }
@@ -636,23 +748,57 @@ pub struct ServerTimeout {
         default = "default_watch_progress_notify_interval"
     )]
     watch_progress_notify_interval: Duration,
+    /// How long the events of a watcher are coalesced into one response
+    #[getset(get = "pub")]
+    #[serde(with = "duration_format", default = "default_watch_batch_interval")]
+    watch_batch_interval: Duration,
+    /// Min ttl of a granted lease, it never goes below the election timeout
+    #[getset(get = "pub")]
+    #[serde(with = "duration_format", default = "default_min_lease_ttl")]
+    min_lease_ttl: Duration,
+    /// Max ttl of a granted lease
+    #[getset(get = "pub")]
+    #[serde(with = "duration_format", default = "default_max_lease_ttl")]
+    max_lease_ttl: Duration,
+    /// How often the leader compares the kv hashes of the members
+    #[getset(get = "pub")]
+    #[serde(with = "duration_format", default = "default_corrupt_check_interval")]
+    corrupt_check_interval: Duration,
+    /// How often the leader checkpoints the remaining ttls of the leases
+    #[getset(get = "pub")]
+    #[serde(
+        with = "duration_format",
+        default = "default_lease_checkpoint_interval"
+    )]
+    lease_checkpoint_interval: Duration,
 }
 
 impl ServerTimeout {
     /// Create a new server timeout
     #[must_use]
     #[inline]
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         range_retry_timeout: Duration,
         compact_timeout: Duration,
         sync_victims_interval: Duration,
         watch_progress_notify_interval: Duration,
+        watch_batch_interval: Duration,
+        min_lease_ttl: Duration,
+        max_lease_ttl: Duration,
+        corrupt_check_interval: Duration,
+        lease_checkpoint_interval: Duration,
     ) -> Self {
         Self {
             range_retry_timeout,
             compact_timeout,
             sync_victims_interval,
             watch_progress_notify_interval,
+            watch_batch_interval,
+            min_lease_ttl,
+            max_lease_ttl,
+            corrupt_check_interval,
+            lease_checkpoint_interval,
         }
     }
 }
@@ -665,6 +811,11 @@ impl Default for ServerTimeout {
             compact_timeout: default_compact_timeout(),
             sync_victims_interval: default_sync_victims_interval(),
             watch_progress_notify_interval: default_watch_progress_notify_interval(),
+            watch_batch_interval: default_watch_batch_interval(),
+            min_lease_ttl: default_min_lease_ttl(),
+            max_lease_ttl: default_max_lease_ttl(),
+            corrupt_check_interval: default_corrupt_check_interval(),
+            lease_checkpoint_interval: default_lease_checkpoint_interval(),
         }
     }
 }
@@ -720,14 +871,163 @@ pub struct StorageConfig {
     /// Quota
     #[serde(default = "default_quota")]
     pub quota: u64,
+    /// Values whose encoded size reaches this threshold are compressed, compression
+    /// is disabled when it is not set
+    #[serde(default)]
+    pub value_compression_threshold: Option<usize>,
+    /// Codec to compress the values reaching the threshold
+    #[serde(default)]
+    pub value_compression_codec: ValueCompressionCodec,
+    /// Max size of a key in bytes
+    #[serde(default = "default_max_key_bytes")]
+    pub max_key_bytes: usize,
+    /// Max size of a value in bytes
+    #[serde(default = "default_max_value_bytes")]
+    pub max_value_bytes: usize,
+    /// Number of hot keys whose latest value is cached in memory, 0 disables the cache
+    #[serde(default)]
+    pub kv_cache_capacity: usize,
+    /// Max size of the kvs of a range result in bytes
+    #[serde(default = "default_max_range_bytes")]
+    pub max_range_bytes: usize,
+    /// Only the members whose data are corrupted reject requests when the `CORRUPT`
+    /// alarm is activated, instead of the whole cluster
+    #[serde(default)]
+    pub isolate_corrupted_member: bool,
+    /// Path of the file of the keys to encrypt the stored values, the values are
+    /// stored in plain when it is not set
+    #[serde(default)]
+    pub encryption_key_file: Option<PathBuf>,
+    /// Backup configuration, the storage is not backed up when it is not set
+    #[serde(default)]
+    pub backup: Option<BackupConfig>,
+    /// Path of the snapshot to restore the storage from at startup, the snapshot
+    /// is only restored when the storage is empty
+    #[serde(default)]
+    pub restore_from: Option<PathBuf>,
+    /// Restore from the snapshot without checking its hash, e.g. a snapshot
+    /// copied from a data dir
+    #[serde(default)]
+    pub skip_restore_hash_check: bool,
+    /// Block compression of the tables of the storage engine by the table names,
+    /// the tables not in it use the default compression of the engine
+    #[serde(default)]
+    pub compression: HashMap<String, CompressionConfig>,
+    /// Cold tier configuration, the old history is kept with the latest values
+    /// when it is not set
+    #[serde(default)]
+    pub cold_tier: Option<ColdTierConfig>,
+    /// Durability of the writes to the storage engine
+    #[serde(default)]
+    pub durability: DurabilityConfig,
 }
 
 impl StorageConfig {
     /// Create a new storage config
     #[inline]
     #[must_use]
-    pub fn new(engine: EngineConfig, quota: u64) -> Self {
-        Self { engine, quota }
+    pub fn new(
+        engine: EngineConfig,
+        quota: u64,
+        value_compression_threshold: Option<usize>,
+        max_key_bytes: usize,
+        max_value_bytes: usize,
+        kv_cache_capacity: usize,
+        isolate_corrupted_member: bool,
+    ) -> Self {
+        Self {
+            engine,
+            quota,
+            value_compression_threshold,
+            value_compression_codec: ValueCompressionCodec::default(),
+            max_key_bytes,
+            max_value_bytes,
+            kv_cache_capacity,
+            max_range_bytes: default_max_range_bytes(),
+            isolate_corrupted_member,
+            encryption_key_file: None,
+            backup: None,
+            restore_from: None,
+            skip_restore_hash_check: false,
+            compression: HashMap::new(),
+            cold_tier: None,
+            durability: DurabilityConfig::default(),
+        }
+    }
+
+    /// Encrypt the stored values with the keys in the file
+    #[must_use]
+    #[inline]
+    pub fn with_encryption_key_file(mut self, encryption_key_file: Option<PathBuf>) -> Self {
+        self.encryption_key_file = encryption_key_file;
+        self
+    }
+
+    /// Back up the storage periodically
+    #[must_use]
+    #[inline]
+    pub fn with_backup(mut self, backup: Option<BackupConfig>) -> Self {
+        self.backup = backup;
+        self
+    }
+
+    /// Restore the storage from the snapshot at startup
+    #[must_use]
+    #[inline]
+    pub fn with_restore_from(mut self, restore_from: Option<PathBuf>) -> Self {
+        self.restore_from = restore_from;
+        self
+    }
+
+    /// Skip the hash check of the snapshot restored at startup
+    #[must_use]
+    #[inline]
+    pub fn with_skip_restore_hash_check(mut self, skip_restore_hash_check: bool) -> Self {
+        self.skip_restore_hash_check = skip_restore_hash_check;
+        self
+    }
+
+    /// Compress the blocks of the tables with the given codecs
+    #[must_use]
+    #[inline]
+    pub fn with_compression(mut self, compression: HashMap<String, CompressionConfig>) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Migrate the old history to the cold tier
+    #[must_use]
+    #[inline]
+    pub fn with_cold_tier(mut self, cold_tier: Option<ColdTierConfig>) -> Self {
+        self.cold_tier = cold_tier;
+        self
+    }
+
+    /// Trade the durability of the writes for their latency
+    #[must_use]
+    #[inline]
+    pub fn with_durability(mut self, durability: DurabilityConfig) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    /// Compress the values reaching the threshold with the codec
+    #[must_use]
+    #[inline]
+    pub fn with_value_compression_codec(
+        mut self,
+        value_compression_codec: ValueCompressionCodec,
+    ) -> Self {
+        self.value_compression_codec = value_compression_codec;
+        self
+    }
+
+    /// Limit the size of the kvs of a range result
+    #[must_use]
+    #[inline]
+    pub fn with_max_range_bytes(mut self, max_range_bytes: usize) -> Self {
+        self.max_range_bytes = max_range_bytes;
+        self
     }
 }
 
@@ -737,8 +1037,284 @@ impl Default for StorageConfig {
         Self {
             engine: EngineConfig::default(),
             quota: default_quota(),
+            value_compression_threshold: None,
+            value_compression_codec: ValueCompressionCodec::default(),
+            max_key_bytes: default_max_key_bytes(),
+            max_value_bytes: default_max_value_bytes(),
+            kv_cache_capacity: 0,
+            max_range_bytes: default_max_range_bytes(),
+            isolate_corrupted_member: false,
+            encryption_key_file: None,
+            backup: None,
+            restore_from: None,
+            skip_restore_hash_check: false,
+            compression: HashMap::new(),
+            cold_tier: None,
+            durability: DurabilityConfig::default(),
+        }
+    }
+}
+
+/// Backup configuration
+#[allow(clippy::module_name_repetitions)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Getters)]
+pub struct BackupConfig {
+    /// The store of the backups
+    #[getset(get = "pub")]
+    #[serde(flatten)]
+    target: BackupTarget,
+    /// Interval between two backups
+    #[getset(get = "pub")]
+    #[serde(with = "duration_format", default = "default_backup_interval")]
+    interval: Duration,
+    /// Number of the latest full backups to keep, the older ones are removed
+    /// with the incremental backups taken before them
+    #[getset(get = "pub")]
+    #[serde(default = "default_backup_retention")]
+    retention: usize,
+    /// Number of the incremental backups taken between two full backups, all
+    /// the backups are full if it's 0
+    #[getset(get = "pub")]
+    #[serde(default)]
+    increments: usize,
+}
+
+impl BackupConfig {
+    /// Create a new `BackupConfig`
+    #[must_use]
+    #[inline]
+    pub fn new(target: BackupTarget, interval: Duration, retention: usize) -> Self {
+        Self {
+            target,
+            interval,
+            retention,
+            increments: 0,
         }
     }
+
+    /// Set the number of the incremental backups between two full backups
+    #[must_use]
+    #[inline]
+    pub fn with_increments(mut self, increments: usize) -> Self {
+        self.increments = increments;
+        self
+    }
+}
+
+/// The store of the backups
+#[allow(clippy::module_name_repetitions)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all(deserialize = "lowercase"))]
+pub enum BackupTarget {
+    /// A directory, e.g. a bucket of an object storage mounted by `s3fs`
+    Dir(PathBuf),
+    /// A bucket of an S3-compatible object storage
+    S3(S3BackupConfig),
+}
+
+/// Configuration of the bucket of the backups in an S3-compatible object
+/// storage, the credentials are read from the `AWS_ACCESS_KEY_ID` and
+/// `AWS_SECRET_ACCESS_KEY` environment variables
+#[allow(clippy::module_name_repetitions)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Getters)]
+pub struct S3BackupConfig {
+    /// The name of the bucket
+    #[getset(get = "pub")]
+    bucket: String,
+    /// The endpoint of the object storage, e.g. `http://minio:9000`, AWS S3 is
+    /// used if it's not set
+    #[getset(get = "pub")]
+    #[serde(default)]
+    endpoint: Option<String>,
+    /// The region of the bucket
+    #[getset(get = "pub")]
+    #[serde(default = "default_s3_region")]
+    region: String,
+    /// The prefix of the paths of the backups in the bucket
+    #[getset(get = "pub")]
+    #[serde(default)]
+    prefix: String,
+}
+
+impl S3BackupConfig {
+    /// Create a new `S3BackupConfig`
+    #[must_use]
+    #[inline]
+    pub fn new(bucket: String, endpoint: Option<String>, region: String, prefix: String) -> Self {
+        Self {
+            bucket,
+            endpoint,
+            region,
+            prefix,
+        }
+    }
+}
+
+/// Default region of the backup bucket
+#[must_use]
+#[inline]
+pub fn default_s3_region() -> String {
+    "us-east-1".to_owned()
+}
+
+/// Default backup interval: 1 hour
+#[must_use]
+#[inline]
+pub const fn default_backup_interval() -> Duration {
+    Duration::from_secs(3600)
+}
+
+/// Default number of the backups to keep
+#[must_use]
+#[inline]
+pub const fn default_backup_retention() -> usize {
+    24
+}
+
+/// Cold tier configuration
+#[allow(clippy::module_name_repetitions)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Getters)]
+pub struct ColdTierConfig {
+    /// The directory of the cold tier, e.g. a slower disk or a bucket of an
+    /// object storage mounted by `s3fs`
+    #[getset(get = "pub")]
+    dir: PathBuf,
+    /// The revisions superseded more than this number of revisions ago are
+    /// migrated to the cold tier
+    #[getset(get = "pub")]
+    #[serde(default = "default_cold_tier_threshold")]
+    threshold: i64,
+    /// Interval between two migrations
+    #[getset(get = "pub")]
+    #[serde(with = "duration_format", default = "default_cold_tier_interval")]
+    interval: Duration,
+}
+
+impl ColdTierConfig {
+    /// Create a new `ColdTierConfig`
+    #[must_use]
+    #[inline]
+    pub fn new(dir: PathBuf, threshold: i64, interval: Duration) -> Self {
+        Self {
+            dir,
+            threshold,
+            interval,
+        }
+    }
+}
+
+/// Default number of revisions before the superseded revisions are migrated
+#[must_use]
+#[inline]
+pub const fn default_cold_tier_threshold() -> i64 {
+    100_000
+}
+
+/// Default cold tier migration interval: 10 minutes
+#[must_use]
+#[inline]
+pub const fn default_cold_tier_interval() -> Duration {
+    Duration::from_secs(600)
+}
+
+/// Durability of the writes to the storage engine
+///
+/// The commands are durable in the log of curp once they are committed, the
+/// level decides how many of the applied ones may be lost by the engine and
+/// applied again from the log after a failure. The writes asking to be synced
+/// are synced whatever the level is.
+#[allow(clippy::module_name_repetitions)]
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(
+    tag = "level",
+    content = "interval",
+    rename_all(deserialize = "kebab-case")
+)]
+pub enum DurabilityConfig {
+    /// Every batch of writes is synced to the disk before it's done, nothing
+    /// is lost on a power failure
+    FsyncPerBatch,
+    /// The writes are synced to the disk once per interval, the writes of the
+    /// last interval may be lost on a power failure
+    #[serde(with = "duration_format")]
+    FsyncInterval(Duration),
+    /// The writes are left to the OS to flush, they survive a crash of the
+    /// process but not a power failure, which is how the engine always wrote
+    #[default]
+    OsBuffered,
+}
+
+impl std::fmt::Display for DurabilityConfig {
+    #[inline]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            DurabilityConfig::FsyncPerBatch => write!(f, "fsync-per-batch"),
+            DurabilityConfig::FsyncInterval(interval) => {
+                write!(f, "fsync-interval:{}ms", interval.as_millis())
+            }
+            DurabilityConfig::OsBuffered => write!(f, "os-buffered"),
+        }
+    }
+}
+
+/// Default interval between two syncs of the `fsync-interval` durability: 100ms
+#[must_use]
+#[inline]
+pub const fn default_durability_sync_interval() -> Duration {
+    Duration::from_millis(100)
+}
+
+/// Codec to compress the blocks of a table of the storage engine
+#[non_exhaustive]
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all(deserialize = "lowercase"))]
+pub enum CompressionCodec {
+    /// No compression
+    None,
+    /// Snappy compression
+    Snappy,
+    /// LZ4 compression
+    Lz4,
+    /// Zstandard compression
+    Zstd,
+}
+
+/// Codec to compress the values of the kv table
+///
+/// Every compressed value records its codec, so the values compressed by
+/// another codec are still read after the codec is changed.
+#[non_exhaustive]
+#[derive(Copy, Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all(deserialize = "lowercase"))]
+pub enum ValueCompressionCodec {
+    /// LZ4 compression, fast with a lower ratio
+    #[default]
+    Lz4,
+    /// Zstandard compression, a higher ratio at the cost of more cpu
+    Zstd,
+}
+
+/// Block compression configuration of a table
+#[allow(clippy::module_name_repetitions)]
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq, Getters)]
+pub struct CompressionConfig {
+    /// Compression codec
+    #[getset(get = "pub")]
+    codec: CompressionCodec,
+    /// Compression level, the default level of the codec is used when it is not set
+    #[getset(get = "pub")]
+    #[serde(default)]
+    level: Option<i32>,
+}
+
+impl CompressionConfig {
+    /// Create a new `CompressionConfig`
+    #[must_use]
+    #[inline]
+    pub fn new(codec: CompressionCodec, level: Option<i32>) -> Self {
+        Self { codec, level }
+    }
 }
 
 /// Default quota: 8GB
@@ -749,6 +1325,30 @@ pub fn default_quota() -> u64 {
     0x0002_0000_0000
 }
 
+/// Default max key size: 64KB
+#[inline]
+#[must_use]
+pub fn default_max_key_bytes() -> usize {
+    // 64 * 1024
+    0x0001_0000
+}
+
+/// Default max value size: 1.5MB, the same as the default max request size of etcd
+#[inline]
+#[must_use]
+pub fn default_max_value_bytes() -> usize {
+    // 1536 * 1024
+    0x0018_0000
+}
+
+/// Default max size of the kvs of a range result: 256MB
+#[inline]
+#[must_use]
+pub fn default_max_range_bytes() -> usize {
+    // 256 * 1024 * 1024
+    0x1000_0000
+}
+
 /// Log configuration object
 #[allow(clippy::module_name_repetitions)]
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq, Getters)]
@@ -950,20 +1550,60 @@ pub struct AuthConfig {
     /// The private key file
     #[getset(get = "pub")]
     auth_private_key: Option<PathBuf>,
+    /// What to do with a watch or lease keep alive stream once its token expires
+    #[getset(get = "pub")]
+    #[serde(with = "stream_token_expiry_format", default)]
+    stream_token_expiry: StreamTokenExpiry,
 }
 
 impl AuthConfig {
     /// Generate a new `AuthConfig` object
     #[must_use]
     #[inline]
-    pub fn new(auth_public_key: Option<PathBuf>, auth_private_key: Option<PathBuf>) -> Self {
+    pub fn new(
+        auth_public_key: Option<PathBuf>,
+        auth_private_key: Option<PathBuf>,
+        stream_token_expiry: StreamTokenExpiry,
+    ) -> Self {
         Self {
             auth_public_key,
             auth_private_key,
+            stream_token_expiry,
         }
     }
 }
 
+/// Policy of the watch and lease keep alive streams whose tokens have expired
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[non_exhaustive]
+pub enum StreamTokenExpiry {
+    /// Keep the stream, the watch permissions of its user are validated again
+    /// whenever the auth store changes
+    Revalidate,
+    /// Cancel the stream when the token expires, the clients authenticated
+    /// with a user authenticate again and resume the stream
+    #[default]
+    Cancel,
+}
+
+/// `StreamTokenExpiry` deserialization formatter
+pub mod stream_token_expiry_format {
+    use serde::{Deserialize, Deserializer};
+
+    use super::StreamTokenExpiry;
+    use crate::parse_stream_token_expiry;
+
+    /// deserializes a stream token expiry policy
+    #[allow(single_use_lifetimes)]
+    pub(crate) fn deserialize<'de, D>(deserializer: D) -> Result<StreamTokenExpiry, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        parse_stream_token_expiry(&s).map_err(serde::de::Error::custom)
+    }
+}
+
 /// Xline tls configuration object
 #[allow(clippy::module_name_repetitions)]
 #[non_exhaustive]
@@ -987,6 +1627,22 @@ pub struct TlsConfig {
     /// The private key file used by client
     #[getset(get = "pub")]
     pub client_key_path: Option<PathBuf>,
+    /// The CA certificate file used by the peer-to-peer CURP traffic to verify
+    /// the certificates of the other members
+    #[getset(get = "pub")]
+    pub curp_ca_cert_path: Option<PathBuf>,
+    /// The public key file used by the peer-to-peer CURP traffic, both by the
+    /// peer listener and by the connections to the other members
+    #[getset(get = "pub")]
+    pub curp_cert_path: Option<PathBuf>,
+    /// The private key file used by the peer-to-peer CURP traffic
+    #[getset(get = "pub")]
+    pub curp_key_path: Option<PathBuf>,
+    /// Whether the peer listener requires the certificates of the other members,
+    /// a certificate must be issued for the host of the peer urls of a member
+    #[getset(get = "pub")]
+    #[serde(default)]
+    pub curp_client_cert_auth: bool,
 }
 
 impl TlsConfig {
@@ -1008,15 +1664,51 @@ impl TlsConfig {
             client_ca_cert_path,
             client_cert_path,
             client_key_path,
+            curp_ca_cert_path: None,
+            curp_cert_path: None,
+            curp_key_path: None,
+            curp_client_cert_auth: false,
         }
     }
 
+    /// Use a separate tls configuration for the peer-to-peer CURP traffic
+    #[must_use]
+    #[inline]
+    pub fn with_curp_tls(
+        mut self,
+        curp_ca_cert_path: Option<PathBuf>,
+        curp_cert_path: Option<PathBuf>,
+        curp_key_path: Option<PathBuf>,
+        curp_client_cert_auth: bool,
+    ) -> Self {
+        self.curp_ca_cert_path = curp_ca_cert_path;
+        self.curp_cert_path = curp_cert_path;
+        self.curp_key_path = curp_key_path;
+        self.curp_client_cert_auth = curp_client_cert_auth;
+        self
+    }
+
     /// Whether the server tls is enabled
     #[must_use]
     #[inline]
     pub fn server_tls_enabled(&self) -> bool {
         self.peer_cert_path.is_some() && self.peer_key_path.is_some()
     }
+
+    /// Whether the separate tls of the peer-to-peer CURP traffic is enabled
+    #[must_use]
+    #[inline]
+    pub fn curp_tls_enabled(&self) -> bool {
+        self.curp_cert_path.is_some() && self.curp_key_path.is_some()
+    }
+
+    /// Whether the peer listener serves tls, it falls back to the server tls
+    /// if the separate tls of the CURP traffic is not enabled
+    #[must_use]
+    #[inline]
+    pub fn peer_tls_enabled(&self) -> bool {
+        self.curp_tls_enabled() || self.server_tls_enabled()
+    }
 }
 
 /// Xline metrics push protocol
@@ -1194,6 +1886,14 @@ impl XlineServerConfig {
             metrics,
         }
     }
+
+    /// Replace the cluster configuration
+    #[must_use]
+    #[inline]
+    pub fn with_cluster(mut self, cluster: ClusterConfig) -> Self {
+        self.cluster = cluster;
+        self
+    }
 }
 
 #[cfg(test)]
@@ -1218,6 +1918,11 @@ mod tests {
             compact_timeout = '5s'
             sync_victims_interval = '20ms'
             watch_progress_notify_interval = '1s'
+            watch_batch_interval = '5ms'
+            min_lease_ttl = '5s'
+            max_lease_ttl = '1h'
+            corrupt_check_interval = '1m'
+            lease_checkpoint_interval = '30s'
 
             [cluster.peers]
             node1 = ['127.0.0.1:2378', '127.0.0.1:2379']
@@ -1236,6 +1941,10 @@ mod tests {
 
             [storage]
             engine = { type = 'memory'}
+            backup = { dir = '/var/backup/xline', interval = '30m' }
+            compression = { kv = { codec = 'zstd', level = 3 }, lease = { codec = 'lz4' } }
+            cold_tier = { dir = '/mnt/cold/xline', threshold = 5000 }
+            durability = { level = 'fsync-interval', interval = '50ms' }
 
             [compact]
             compact_batch_size = 123
@@ -1259,6 +1968,7 @@ mod tests {
             [auth]
             auth_public_key = './public_key.pem'
             auth_private_key = './private_key.pem'
+            stream_token_expiry = 'revalidate'
 
             [tls]
             peer_cert_path = './cert.pem'
@@ -1298,6 +2008,11 @@ mod tests {
             Duration::from_secs(5),
             Duration::from_millis(20),
             Duration::from_secs(1),
+            Duration::from_millis(5),
+            Duration::from_secs(5),
+            Duration::from_secs(3600),
+            Duration::from_secs(60),
+            Duration::from_secs(30),
         );
 
         assert_eq!(
@@ -1326,7 +2041,36 @@ mod tests {
 
         assert_eq!(
             config.storage,
-            StorageConfig::new(EngineConfig::Memory, default_quota())
+            StorageConfig::new(
+                EngineConfig::Memory,
+                default_quota(),
+                None,
+                default_max_key_bytes(),
+                default_max_value_bytes(),
+                0,
+                false
+            )
+            .with_backup(Some(BackupConfig::new(
+                BackupTarget::Dir(PathBuf::from("/var/backup/xline")),
+                Duration::from_secs(1800),
+                default_backup_retention()
+            )))
+            .with_compression(HashMap::from_iter([
+                (
+                    "kv".to_owned(),
+                    CompressionConfig::new(CompressionCodec::Zstd, Some(3))
+                ),
+                (
+                    "lease".to_owned(),
+                    CompressionConfig::new(CompressionCodec::Lz4, None)
+                ),
+            ]))
+            .with_cold_tier(Some(ColdTierConfig::new(
+                PathBuf::from("/mnt/cold/xline"),
+                5000,
+                default_cold_tier_interval()
+            )))
+            .with_durability(DurabilityConfig::FsyncInterval(Duration::from_millis(50)))
         );
 
         assert_eq!(
@@ -1363,6 +2107,7 @@ mod tests {
             AuthConfig {
                 auth_private_key: Some(PathBuf::from("./private_key.pem")),
                 auth_public_key: Some(PathBuf::from("./public_key.pem")),
+                stream_token_expiry: StreamTokenExpiry::Revalidate,
             }
         );
 
@@ -1454,6 +2199,8 @@ mod tests {
         } else {
             unreachable!();
         }
+        // the writes are synced only when fsync is opted in
+        assert_eq!(config.storage.durability, DurabilityConfig::OsBuffered);
 
         assert_eq!(
             config.log,
@@ -1478,6 +2225,77 @@ mod tests {
         assert_eq!(config.metrics, MetricsConfig::default());
     }
 
+    #[test]
+    fn test_discovery_srv_config_should_be_loaded() {
+        let config: XlineServerConfig = toml::from_str(
+            "[cluster]
+                name = 'node1'
+                is_leader = true
+                peer_listen_urls = ['127.0.0.1:2380']
+                peer_advertise_urls = ['127.0.0.1:2380']
+                client_listen_urls = ['127.0.0.1:2379']
+                client_advertise_urls = ['127.0.0.1:2379']
+                discovery_srv = 'xline.default.svc.cluster.local'
+
+                [log]
+                path = '/var/log/xline'
+
+                [storage]
+                engine = { type = 'memory' }
+
+                [compact]
+
+                [trace]
+                jaeger_online = false
+                jaeger_offline = false
+                jaeger_output_dir = './jaeger_jsons'
+                jaeger_level = 'info'
+
+                [auth]
+
+                [tls]
+                ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.cluster.discovery_srv().as_deref(),
+            Some("xline.default.svc.cluster.local")
+        );
+        assert!(config.cluster.peers().is_empty());
+    }
+
+    #[test]
+    fn test_s3_backup_config_should_be_loaded() {
+        let config: StorageConfig = toml::from_str(
+            "[backup]
+                increments = 5
+
+                [backup.s3]
+                bucket = 'xline-backup'
+                endpoint = 'http://minio:9000'
+                ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.backup,
+            Some(
+                BackupConfig::new(
+                    BackupTarget::S3(S3BackupConfig::new(
+                        "xline-backup".to_owned(),
+                        Some("http://minio:9000".to_owned()),
+                        default_s3_region(),
+                        String::new(),
+                    )),
+                    default_backup_interval(),
+                    default_backup_retention(),
+                )
+                .with_increments(5)
+            )
+        );
+    }
+
     #[test]
     fn test_auto_revision_compactor_config_should_be_loaded() {
         let config: XlineServerConfig = toml::from_str(
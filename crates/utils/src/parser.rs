@@ -5,7 +5,9 @@ use regex::Regex;
 use thiserror::Error;
 
 use crate::config::{
-    ClusterRange, InitialClusterState, LevelConfig, MetricsPushProtocol, RotationConfig,
+    default_durability_sync_interval, ClusterRange, CompressionCodec, CompressionConfig,
+    DurabilityConfig, InitialClusterState, LevelConfig, MetricsPushProtocol, RotationConfig,
+    StreamTokenExpiry, ValueCompressionCodec,
 };
 
 /// seconds per minute
@@ -171,6 +173,22 @@ pub fn parse_state(s: &str) -> Result<InitialClusterState, ConfigParseError> {
     }
 }
 
+/// Parse `StreamTokenExpiry` from string
+///
+/// # Errors
+///
+/// Return error when parsing the given string to `StreamTokenExpiry` failed
+#[inline]
+pub fn parse_stream_token_expiry(s: &str) -> Result<StreamTokenExpiry, ConfigParseError> {
+    match s {
+        "revalidate" => Ok(StreamTokenExpiry::Revalidate),
+        "cancel" => Ok(StreamTokenExpiry::Cancel),
+        _ => Err(ConfigParseError::InvalidValue(format!(
+            "the stream token expiry should be one of 'revalidate' or 'cancel' ({s})"
+        ))),
+    }
+}
+
 /// Parse `LOG_PATH` from string
 ///
 /// # Errors
@@ -296,6 +314,85 @@ pub fn parse_metrics_push_protocol(s: &str) -> Result<MetricsPushProtocol, Confi
     }
 }
 
+/// Parse the compression of the tables from string like "kv=zstd:3,lease=lz4", the
+/// level of a codec is optional
+///
+/// # Errors
+///
+/// Return error when parsing the given string to the compression of the tables failed
+#[inline]
+pub fn parse_compression(s: &str) -> Result<HashMap<String, CompressionConfig>, ConfigParseError> {
+    let mut map = HashMap::new();
+    for item in s.split(',') {
+        let Some((table, compression)) = item.split_once('=') else {
+            return Err(ConfigParseError::InvalidValue(format!(
+                "the compression should be like 'table=codec[:level]' ({item})"
+            )));
+        };
+        let (codec, level) = match compression.split_once(':') {
+            Some((codec, level)) => (codec, Some(level.parse()?)),
+            None => (compression, None),
+        };
+        let codec = match codec {
+            "none" => CompressionCodec::None,
+            "snappy" => CompressionCodec::Snappy,
+            "lz4" => CompressionCodec::Lz4,
+            "zstd" => CompressionCodec::Zstd,
+            _ => {
+                return Err(ConfigParseError::InvalidValue(format!(
+                    "the compression codec should be one of 'none', 'snappy', 'lz4' or 'zstd' ({codec})"
+                )))
+            }
+        };
+        if table.is_empty() {
+            return Err(ConfigParseError::InvalidValue(format!(
+                "the table of the compression should not be empty ({item})"
+            )));
+        }
+        let _prev = map.insert(table.to_owned(), CompressionConfig::new(codec, level));
+    }
+    Ok(map)
+}
+
+/// Parse the codec of the value compression from string, "lz4" or "zstd"
+///
+/// # Errors
+///
+/// Return error when parsing the given string to the codec failed
+#[inline]
+pub fn parse_value_compression_codec(s: &str) -> Result<ValueCompressionCodec, ConfigParseError> {
+    match s {
+        "lz4" => Ok(ValueCompressionCodec::Lz4),
+        "zstd" => Ok(ValueCompressionCodec::Zstd),
+        _ => Err(ConfigParseError::InvalidValue(format!(
+            "the value compression codec should be one of 'lz4' or 'zstd' ({s})"
+        ))),
+    }
+}
+
+/// Parse the durability from string like "fsync-per-batch", "os-buffered" or
+/// "fsync-interval:100ms", the interval is optional
+///
+/// # Errors
+///
+/// Return error when parsing the given string to the durability failed
+#[inline]
+pub fn parse_durability(s: &str) -> Result<DurabilityConfig, ConfigParseError> {
+    match s.split_once(':') {
+        Some(("fsync-interval", interval)) => {
+            Ok(DurabilityConfig::FsyncInterval(parse_duration(interval)?))
+        }
+        None if s == "fsync-interval" => Ok(DurabilityConfig::FsyncInterval(
+            default_durability_sync_interval(),
+        )),
+        None if s == "fsync-per-batch" => Ok(DurabilityConfig::FsyncPerBatch),
+        None if s == "os-buffered" => Ok(DurabilityConfig::OsBuffered),
+        _ => Err(ConfigParseError::InvalidValue(format!(
+            "the durability should be one of 'fsync-per-batch', 'fsync-interval[:interval]' or 'os-buffered' ({s})"
+        ))),
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -443,4 +540,61 @@ mod test {
         assert!(parse_log_file(".../path/with-spaces/log_file.log-123.456-789").is_err());
         assert!(parse_log_file("~~/path/with-spaces/log_file.log-123.456-789").is_err());
     }
+
+    #[test]
+    fn test_parse_compression() {
+        assert_eq!(
+            parse_compression("kv=zstd:3,lease=lz4").unwrap(),
+            HashMap::from([
+                (
+                    "kv".to_owned(),
+                    CompressionConfig::new(CompressionCodec::Zstd, Some(3))
+                ),
+                (
+                    "lease".to_owned(),
+                    CompressionConfig::new(CompressionCodec::Lz4, None)
+                ),
+            ])
+        );
+        assert!(parse_compression("kv").is_err());
+        assert!(parse_compression("=zstd").is_err());
+        assert!(parse_compression("kv=gzip").is_err());
+        assert!(parse_compression("kv=zstd:high").is_err());
+    }
+
+    #[test]
+    fn test_parse_durability() {
+        assert_eq!(
+            parse_durability("fsync-per-batch").unwrap(),
+            DurabilityConfig::FsyncPerBatch
+        );
+        assert_eq!(
+            parse_durability("fsync-interval").unwrap(),
+            DurabilityConfig::FsyncInterval(default_durability_sync_interval())
+        );
+        assert_eq!(
+            parse_durability("fsync-interval:20ms").unwrap(),
+            DurabilityConfig::FsyncInterval(Duration::from_millis(20))
+        );
+        assert_eq!(
+            parse_durability("os-buffered").unwrap(),
+            DurabilityConfig::OsBuffered
+        );
+        assert!(parse_durability("fsync-interval:soon").is_err());
+        assert!(parse_durability("os-buffered:20ms").is_err());
+        assert!(parse_durability("never").is_err());
+    }
+
+    #[test]
+    fn test_parse_value_compression_codec() {
+        assert_eq!(
+            parse_value_compression_codec("lz4").unwrap(),
+            ValueCompressionCodec::Lz4
+        );
+        assert_eq!(
+            parse_value_compression_codec("zstd").unwrap(),
+            ValueCompressionCodec::Zstd
+        );
+        assert!(parse_value_compression_codec("snappy").is_err());
+    }
 }
@@ -4,8 +4,9 @@
 //       \        /      |
 //      WATCH_TASK  CONF_CHANGE
 //
-// Other tasks like `CompactBg`, `GcSpecPool`, `GcCmdBoard`, `RevokeExpiredLeases`, `SyncVictims`,
-// `Election`, and `AutoCompactor` do not have dependent tasks.
+// Other tasks like `CompactBg`, `GcSpecPool`, `GcCmdBoard`, `RevokeExpiredLeases`,
+// `LeaseCheckpoint`, `SyncVictims`, `Election`, `AutoCompactor`, `CorruptCheck`, `MonitorVersion`,
+// `Backup` and `ColdTier` do not have dependent tasks.
 
 // NOTE: In integration tests, we use bottom tasks, like `WatchTask` and `ConfChange`,
 // which are not dependent on other tasks to detect the curp group is closed or not. If you want
@@ -46,10 +47,15 @@ enum_with_iter! {
     ConfChange,
     GcClientLease,
     RevokeExpiredLeases,
+    LeaseCheckpoint,
     SyncVictims,
     AutoCompactor,
     AfterSync,
     HandlePropose,
+    CorruptCheck,
+    MonitorVersion,
+    Backup,
+    ColdTier,
 }
 
 impl TaskName {
@@ -67,8 +73,13 @@ impl TaskName {
             | TaskName::ConfChange
             | TaskName::GcClientLease
             | TaskName::RevokeExpiredLeases
+            | TaskName::LeaseCheckpoint
             | TaskName::SyncVictims
-            | TaskName::AutoCompactor => false,
+            | TaskName::AutoCompactor
+            | TaskName::CorruptCheck
+            | TaskName::MonitorVersion
+            | TaskName::Backup
+            | TaskName::ColdTier => false,
         }
     }
 }
@@ -2,10 +2,13 @@ use std::sync::Arc;
 
 use tonic::transport::Channel;
 
-use crate::{error::Result, AuthService};
-use xlineapi::{
-    MemberAddResponse, MemberListResponse, MemberPromoteResponse, MemberRemoveResponse,
-    MemberUpdateResponse,
+use crate::{
+    error::Result,
+    types::cluster::{
+        MemberAddResponse, MemberListResponse, MemberPromoteResponse, MemberRemoveResponse,
+        MemberUpdateResponse,
+    },
+    AuthService,
 };
 
 /// Client for Cluster operations.
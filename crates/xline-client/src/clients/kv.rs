@@ -8,7 +8,9 @@ use xlineapi::{
 
 use crate::{
     error::Result,
-    types::kv::{DeleteRangeOptions, PutOptions, RangeOptions, TxnRequest},
+    types::kv::{
+        Compare, CompareResult, DeleteRangeOptions, PutOptions, RangeOptions, TxnOp, TxnRequest,
+    },
     AuthService, CurpClient,
 };
 
@@ -244,6 +246,108 @@ impl KvClient {
         Ok(res_wrapper.into())
     }
 
+    /// Atomically replaces the value of `key` with `new` if its current value is
+    /// `expected`
+    ///
+    /// The swap is a txn comparing the value of the key. If it succeeds, the only
+    /// response is the put response carrying the previous key-value, otherwise it
+    /// is the range response carrying the current key-value, so a failed swap can
+    /// be retried without reading the key again. A missing key never matches,
+    /// use [`KvClient::cas_mod_revision`] with revision 0 to create a key.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the inner CURP client encountered a propose failure
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use xline_client::{Client, ClientOptions};
+    /// use anyhow::Result;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<()> {
+    ///     let curp_members = ["10.0.0.1:2379", "10.0.0.2:2379", "10.0.0.3:2379"];
+    ///
+    ///     let client = Client::connect(curp_members, ClientOptions::default())
+    ///         .await?
+    ///         .kv_client();
+    ///
+    ///     client.put("key", "value1", None).await?;
+    ///     let resp = client.cas("key", "value1", "value2").await?;
+    ///     assert!(resp.succeeded);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[inline]
+    pub async fn cas(
+        &self,
+        key: impl Into<Vec<u8>>,
+        expected: impl Into<Vec<u8>>,
+        new: impl Into<Vec<u8>>,
+    ) -> Result<TxnResponse> {
+        let key = key.into();
+        let compare = Compare::value(key.clone(), CompareResult::Equal, expected);
+        self.swap(compare, key, new.into()).await
+    }
+
+    /// Atomically replaces the value of `key` with `new` if the key was last
+    /// modified at `mod_revision`
+    ///
+    /// A `mod_revision` of 0 only matches a missing key. The responses are the
+    /// same as the ones of [`KvClient::cas`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the inner CURP client encountered a propose failure
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use xline_client::{Client, ClientOptions};
+    /// use anyhow::Result;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<()> {
+    ///     let curp_members = ["10.0.0.1:2379", "10.0.0.2:2379", "10.0.0.3:2379"];
+    ///
+    ///     let client = Client::connect(curp_members, ClientOptions::default())
+    ///         .await?
+    ///         .kv_client();
+    ///
+    ///     // create the key only if it does not exist
+    ///     let resp = client.cas_mod_revision("key", 0, "value").await?;
+    ///     println!("created: {}", resp.succeeded);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[inline]
+    pub async fn cas_mod_revision(
+        &self,
+        key: impl Into<Vec<u8>>,
+        mod_revision: i64,
+        new: impl Into<Vec<u8>>,
+    ) -> Result<TxnResponse> {
+        let key = key.into();
+        let compare = Compare::mod_revision(key.clone(), CompareResult::Equal, mod_revision);
+        self.swap(compare, key, new.into()).await
+    }
+
+    /// Put `value` to `key` if `compare` succeeds, otherwise get the key
+    async fn swap(&self, compare: Compare, key: Vec<u8>, value: Vec<u8>) -> Result<TxnResponse> {
+        let request = TxnRequest::new()
+            .when([compare])
+            .and_then([TxnOp::put(
+                key.clone(),
+                value,
+                Some(PutOptions::default().with_prev_kv(true)),
+            )])
+            .or_else([TxnOp::range(key, None)]);
+        self.txn(request).await
+    }
+
     /// Compacts the key-value store up to a given revision.
     /// All keys with revisions less than the given revision will be compacted.
     /// The compaction process will remove all historical versions of these keys, except for the most recent one.
@@ -155,7 +155,8 @@ impl LeaseClient {
     ///
     /// # Errors
     ///
-    /// This function will return an error if the inner RPC client encountered a propose failure
+    /// This function will return an error if the inner RPC client encountered a propose failure,
+    /// or the lease does not exist
     ///
     /// # Examples
     ///
@@ -202,6 +203,12 @@ impl LeaseClient {
             .into_inner();
 
         let resp_id = match stream.message().await? {
+            // the server responds a non-positive ttl if the lease does not exist
+            Some(resp) if resp.ttl <= 0 => {
+                return Err(XlineClientError::LeaseError(format!(
+                    "lease {id} not found or has expired"
+                )));
+            }
             Some(resp) => resp.id,
             None => {
                 return Err(XlineClientError::LeaseError(String::from(
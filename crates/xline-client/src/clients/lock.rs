@@ -1,9 +1,8 @@
-use std::{fmt::Debug, sync::Arc, time::Duration};
+use std::{fmt::Debug, sync::Arc};
 
 use async_dropper::{AsyncDrop, AsyncDropper};
 use async_trait::async_trait;
 use clippy_utilities::OverflowArithmetic;
-use tokio::{task::JoinHandle, time::sleep};
 use tonic::transport::Channel;
 use xlineapi::{
     command::{Command, CommandResponse, KeyRange, SyncResponse},
@@ -13,36 +12,20 @@ use xlineapi::{
 };
 
 use crate::{
-    clients::{lease::LeaseClient, watch::WatchClient, DEFAULT_SESSION_TTL},
+    clients::{lease::LeaseClient, watch::WatchClient},
     error::{Result, XlineClientError},
     lease_gen::LeaseIdGenerator,
     types::kv::TxnRequest as KvTxnRequest,
     CurpClient,
 };
 
-/// Session represents a lease kept alive for the lifetime of a client.
-#[derive(Debug)]
-pub struct Session {
-    /// The lock client that used to create the session
-    client: LockClient,
-    /// lease id
-    lease_id: i64,
-    /// `keep_alive` task will auto-renew the lease
-    keep_alive: Option<JoinHandle<Result<()>>>,
-}
-
-impl Drop for Session {
-    #[inline]
-    fn drop(&mut self) {
-        if let Some(keep_alive) = self.keep_alive.take() {
-            keep_alive.abort();
-        }
-    }
-}
+pub use crate::clients::session::Session;
 
 /// Xutex（Xline Mutex） implements the sync lock with xline
 #[derive(Debug)]
 pub struct Xutex {
+    /// The lock client that used to create the Xutex
+    client: LockClient,
     /// Lock session
     session: Session,
     /// Lock
@@ -122,41 +105,10 @@ impl Xutex {
         ttl: Option<i64>,
         lease_id: Option<i64>,
     ) -> Result<Self> {
-        let ttl = ttl.unwrap_or(DEFAULT_SESSION_TTL);
-        let lease_id = if let Some(id) = lease_id {
-            id
-        } else {
-            let lease_response = client.lease_client.grant(ttl, None).await?;
-            lease_response.id
-        };
-        let mut lease_client = client.lease_client.clone();
-        let keep_alive = Some(tokio::spawn(async move {
-            /// The renew interval factor of which value equals 60% of one second.
-            const RENEW_INTERVAL_FACTOR: u64 = 600;
-            let (mut keeper, mut stream) = lease_client.keep_alive(lease_id).await?;
-            loop {
-                keeper.keep_alive()?;
-                if let Some(resp) = stream.message().await? {
-                    if resp.ttl < 0 {
-                        return Err(XlineClientError::InvalidArgs(String::from(
-                            "lease keepalive response has negative ttl",
-                        )));
-                    }
-                    sleep(Duration::from_millis(
-                        resp.ttl.unsigned_abs().overflow_mul(RENEW_INTERVAL_FACTOR),
-                    ))
-                    .await;
-                }
-            }
-        }));
-
-        let session = Session {
-            client,
-            lease_id,
-            keep_alive,
-        };
+        let session = Session::new(client.lease_client.clone(), ttl, lease_id).await?;
 
         Ok(Self {
+            client,
             session,
             prefix: format!("{prefix}/"),
             key: String::new(),
@@ -167,7 +119,7 @@ impl Xutex {
 
     /// try to acquire lock
     async fn try_acquire(&mut self) -> Result<TxnResponse> {
-        let lease_id = self.session.lease_id;
+        let lease_id = self.session.lease_id();
         let prefix = self.prefix.as_str();
         self.key = format!("{prefix}{lease_id:x}");
         #[allow(clippy::as_conversions)] // this cast is always safe
@@ -209,7 +161,7 @@ impl Xutex {
             success: vec![put, get_owner.clone()],
             failure: vec![get, get_owner],
         };
-        let (cmd_res, sync_res) = self.session.client.propose(acquire_txn, false).await?;
+        let (cmd_res, sync_res) = self.client.propose(acquire_txn, false).await?;
         let resp = Into::<TxnResponse>::into(cmd_res.into_inner());
         self.rev = if resp.succeeded {
             sync_res
@@ -284,12 +236,7 @@ impl Xutex {
     /// ```
     #[inline]
     pub async fn lock_unsafe(&mut self) -> Result<AsyncDropper<XutexGuard>> {
-        if self
-            .session
-            .keep_alive
-            .as_ref()
-            .is_some_and(JoinHandle::is_finished)
-        {
+        if self.session.is_closed() {
             return Err(XlineClientError::LeaseError(String::from(
                 "Lock renew task exists unexpectedly",
             )));
@@ -304,17 +251,13 @@ impl Xutex {
                 .map_or(false, |kv| kv.create_revision == self.rev)
             {
                 self.header = resp.header;
-                return Ok(XutexGuard::new(
-                    self.session.client.clone(),
-                    self.key.clone(),
-                ));
+                return Ok(XutexGuard::new(self.client.clone(), self.key.clone()));
             }
         } else {
             unreachable!("owner_resp should be a Get response")
         }
 
-        self.session
-            .client
+        self.client
             .wait_delete(self.prefix.clone(), self.rev)
             .await?;
         // make sure the session is no expired, and the owner key still exists.
@@ -322,20 +265,17 @@ impl Xutex {
             key: self.key.as_bytes().to_vec(),
             ..Default::default()
         };
-        match self.session.client.propose(range_req, true).await {
+        match self.client.propose(range_req, true).await {
             Ok((cmd_res, _sync_res)) => {
                 let res = Into::<RangeResponse>::into(cmd_res.into_inner());
                 if res.kvs.is_empty() {
                     return Err(XlineClientError::RpcError(String::from("session expired")));
                 }
                 self.header = res.header;
-                Ok(XutexGuard::new(
-                    self.session.client.clone(),
-                    self.key.clone(),
-                ))
+                Ok(XutexGuard::new(self.client.clone(), self.key.clone()))
             }
             Err(e) => {
-                self.session.client.delete_key(self.key.as_bytes()).await?;
+                self.client.delete_key(self.key.as_bytes()).await?;
                 Err(e)
             }
         }
@@ -1,9 +1,11 @@
-use std::{fmt::Debug, sync::Arc};
+use std::{fmt::Debug, io, sync::Arc};
 
+use futures::{AsyncRead, TryStreamExt};
 use tonic::{transport::Channel, Streaming};
 use xlineapi::{
-    AlarmAction, AlarmRequest, AlarmResponse, AlarmType, SnapshotRequest, SnapshotResponse,
-    StatusRequest, StatusResponse,
+    AlarmAction, AlarmRequest, AlarmResponse, AlarmType, DefragmentRequest, DefragmentResponse,
+    HashKvRequest, HashKvResponse, HashRequest, HashResponse, MoveLeaderRequest,
+    MoveLeaderResponse, SnapshotRequest, SnapshotResponse, StatusRequest, StatusResponse,
 };
 
 use crate::{error::Result, AuthService};
@@ -74,6 +76,47 @@ impl MaintenanceClient {
         Ok(self.inner.snapshot(SnapshotRequest {}).await?.into_inner())
     }
 
+    /// Gets a snapshot as an async reader of the snapshot file, the blobs of the snapshot
+    /// stream are concatenated
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the inner RPC client encountered a propose failure,
+    /// the errors of the stream are returned by the reader as `io::Error`s
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use futures::AsyncReadExt;
+    /// use xline_client::{Client, ClientOptions};
+    /// use anyhow::Result;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<()> {
+    ///     // the name and address of all curp members
+    ///     let curp_members = ["10.0.0.1:2379", "10.0.0.2:2379", "10.0.0.3:2379"];
+    ///
+    ///     let mut client = Client::connect(curp_members, ClientOptions::default())
+    ///         .await?
+    ///         .maintenance_client();
+    ///
+    ///     let mut snapshot = vec![];
+    ///     client.snapshot_reader().await?.read_to_end(&mut snapshot).await?;
+    ///     println!("snapshot size: {}", snapshot.len());
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[inline]
+    pub async fn snapshot_reader(&mut self) -> Result<impl AsyncRead + Send + Unpin> {
+        Ok(self
+            .snapshot()
+            .await?
+            .map_ok(|resp| resp.blob)
+            .map_err(|status| io::Error::new(io::ErrorKind::Other, status))
+            .into_async_read())
+    }
+
     /// Sends a alarm request
     ///
     /// # Errors
@@ -153,4 +196,147 @@ impl MaintenanceClient {
             .await?
             .into_inner())
     }
+
+    /// Defragments the storage backend of the connected member, it may take a
+    /// long time and other maintenance requests to the member are blocked
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the inner RPC client encountered a propose failure
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use xline_client::{Client, ClientOptions};
+    /// use anyhow::Result;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<()> {
+    ///     // the name and address of all curp members
+    ///     let curp_members = ["10.0.0.1:2379", "10.0.0.2:2379", "10.0.0.3:2379"];
+    ///
+    ///     let mut client = Client::connect(curp_members, ClientOptions::default())
+    ///         .await?
+    ///         .maintenance_client();
+    ///
+    ///     client.defragment().await?;
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[inline]
+    pub async fn defragment(&mut self) -> Result<DefragmentResponse> {
+        Ok(self
+            .inner
+            .defragment(DefragmentRequest::default())
+            .await?
+            .into_inner())
+    }
+
+    /// Gets the hash of the whole storage backend of the connected member
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the inner RPC client encountered a propose failure
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use xline_client::{Client, ClientOptions};
+    /// use anyhow::Result;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<()> {
+    ///     // the name and address of all curp members
+    ///     let curp_members = ["10.0.0.1:2379", "10.0.0.2:2379", "10.0.0.3:2379"];
+    ///
+    ///     let mut client = Client::connect(curp_members, ClientOptions::default())
+    ///         .await?
+    ///         .maintenance_client();
+    ///
+    ///     println!("hash: {}", client.hash().await?.hash);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[inline]
+    pub async fn hash(&mut self) -> Result<HashResponse> {
+        Ok(self.inner.hash(HashRequest::default()).await?.into_inner())
+    }
+
+    /// Gets the hash of the key-value store of the connected member up to the
+    /// given revision, the latest revision is used if `revision` is not positive.
+    /// Members that have applied the revision respond the same hash unless
+    /// their stores diverge.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the inner RPC client encountered a propose failure
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use xline_client::{Client, ClientOptions};
+    /// use anyhow::Result;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<()> {
+    ///     // the name and address of all curp members
+    ///     let curp_members = ["10.0.0.1:2379", "10.0.0.2:2379", "10.0.0.3:2379"];
+    ///
+    ///     let mut client = Client::connect(curp_members, ClientOptions::default())
+    ///         .await?
+    ///         .maintenance_client();
+    ///
+    ///     let resp = client.hash_kv(0).await?;
+    ///     println!("hash: {}, compact revision: {}", resp.hash, resp.compact_revision);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[inline]
+    pub async fn hash_kv(&mut self, revision: i64) -> Result<HashKvResponse> {
+        Ok(self
+            .inner
+            .hash_kv(HashKvRequest { revision })
+            .await?
+            .into_inner())
+    }
+
+    /// Transfers the leadership to the given voting member, returns after the
+    /// target becomes the leader
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the target is not a voting member
+    /// or the leadership transfer is aborted
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use xline_client::{Client, ClientOptions};
+    /// use anyhow::Result;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<()> {
+    ///     // the name and address of all curp members
+    ///     let curp_members = ["10.0.0.1:2379", "10.0.0.2:2379", "10.0.0.3:2379"];
+    ///
+    ///     let mut client = Client::connect(curp_members, ClientOptions::default())
+    ///         .await?
+    ///         .maintenance_client();
+    ///
+    ///     client.move_leader(2).await?;
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[inline]
+    pub async fn move_leader(&mut self, target_id: u64) -> Result<MoveLeaderResponse> {
+        Ok(self
+            .inner
+            .move_leader(MoveLeaderRequest { target_id })
+            .await?
+            .into_inner())
+    }
 }
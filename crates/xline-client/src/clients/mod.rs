@@ -3,8 +3,9 @@ pub use cluster::ClusterClient;
 pub use election::ElectionClient;
 pub use kv::KvClient;
 pub use lease::LeaseClient;
-pub use lock::{LockClient, Session, Xutex};
+pub use lock::{LockClient, Xutex};
 pub use maintenance::MaintenanceClient;
+pub use session::Session;
 pub use watch::WatchClient;
 
 /// Auth client.
@@ -21,6 +22,8 @@ mod lease;
 pub mod lock;
 /// Maintenance client.
 mod maintenance;
+/// Lease session.
+mod session;
 /// Watch client.
 mod watch;
 
@@ -0,0 +1,193 @@
+use std::time::Duration;
+
+use clippy_utilities::OverflowArithmetic;
+use tokio::{
+    sync::watch,
+    task::JoinHandle,
+    time::{sleep, Instant},
+};
+
+use crate::{
+    clients::{lease::LeaseClient, DEFAULT_SESSION_TTL},
+    error::{Result, XlineClientError},
+};
+
+/// Session represents a lease kept alive by a background task for the lifetime
+/// of the session, it is the building block of the lock and election recipes.
+///
+/// The keep alive stream is reopened if it breaks by a transient error before
+/// the lease expires. The lease is lost once the keep alive task exits, e.g. the
+/// lease expired or was revoked, which can be observed by [`Session::closed`].
+#[derive(Debug)]
+pub struct Session {
+    /// The lease client that used to create the session
+    lease_client: LeaseClient,
+    /// lease id
+    lease_id: i64,
+    /// `keep_alive` task will auto-renew the lease
+    keep_alive: JoinHandle<Result<()>>,
+    /// Closed by the `keep_alive` task when it exits
+    closed: watch::Receiver<()>,
+}
+
+impl Session {
+    /// Create a session, grant a new lease with the given ttl in seconds or
+    /// [`DEFAULT_SESSION_TTL`] if `lease_id` is `None`, else keep the given
+    /// lease alive
+    ///
+    /// # Errors
+    ///
+    /// Return errors when the lease client failed to grant a lease
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use anyhow::Result;
+    /// use xline_client::{clients::Session, Client, ClientOptions};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<()> {
+    ///     // the name and address of all curp members
+    ///     let curp_members = ["10.0.0.1:2379", "10.0.0.2:2379", "10.0.0.3:2379"];
+    ///
+    ///     let client = Client::connect(curp_members, ClientOptions::default()).await?;
+    ///
+    ///     let session = Session::new(client.lease_client(), Some(10), None).await?;
+    ///     println!("lease id: {}", session.lease_id());
+    ///
+    ///     session.closed().await;
+    ///     println!("lease lost");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[inline]
+    pub async fn new(
+        lease_client: LeaseClient,
+        ttl: Option<i64>,
+        lease_id: Option<i64>,
+    ) -> Result<Self> {
+        let lease_id = if let Some(id) = lease_id {
+            id
+        } else {
+            let ttl = ttl.unwrap_or(DEFAULT_SESSION_TTL);
+            lease_client.grant(ttl, None).await?.id
+        };
+        let (closed_tx, closed) = watch::channel(());
+        let keep_alive = tokio::spawn(Self::keep_alive(lease_client.clone(), lease_id, closed_tx));
+
+        Ok(Self {
+            lease_client,
+            lease_id,
+            keep_alive,
+            closed,
+        })
+    }
+
+    /// Keep the lease alive until it is lost, the keep alive stream is reopened if it breaks
+    /// before the lease expires
+    async fn keep_alive(
+        mut lease_client: LeaseClient,
+        lease_id: i64,
+        // dropped when the task exits, so that the receivers will be notified
+        _closed_tx: watch::Sender<()>,
+    ) -> Result<()> {
+        /// Initial backoff of reopening a broken keep alive stream
+        const MIN_RETRY_BACKOFF: Duration = Duration::from_millis(50);
+        /// Max backoff of reopening a broken keep alive stream
+        const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(1);
+        let mut backoff = MIN_RETRY_BACKOFF;
+        let mut expires_at = None;
+        loop {
+            let renewed_at = expires_at;
+            match Self::keep_alive_stream(&mut lease_client, lease_id, &mut expires_at).await {
+                // the stream is broken by a transient error, the lease may be still alive
+                Err(XlineClientError::RpcError(_)) => {
+                    if expires_at != renewed_at {
+                        backoff = MIN_RETRY_BACKOFF;
+                    }
+                    if expires_at.is_some_and(|deadline| Instant::now() >= deadline) {
+                        return Err(XlineClientError::LeaseError(format!(
+                            "lease {lease_id} has expired"
+                        )));
+                    }
+                    sleep(backoff).await;
+                    backoff = backoff.saturating_mul(2).min(MAX_RETRY_BACKOFF);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Keep the lease alive on a keep alive stream, `expires_at` is updated to the time the
+    /// lease expires after each renewal
+    async fn keep_alive_stream(
+        lease_client: &mut LeaseClient,
+        lease_id: i64,
+        expires_at: &mut Option<Instant>,
+    ) -> Result<()> {
+        /// The renew interval factor of which value equals 60% of one second.
+        const RENEW_INTERVAL_FACTOR: u64 = 600;
+        let (mut keeper, mut stream) = lease_client.keep_alive(lease_id).await?;
+        loop {
+            keeper.keep_alive()?;
+            let Some(resp) = stream.message().await? else {
+                return Err(XlineClientError::RpcError(String::from(
+                    "lease keep alive stream closed unexpectedly",
+                )));
+            };
+            if resp.ttl <= 0 {
+                return Err(XlineClientError::LeaseError(format!(
+                    "lease {lease_id} has expired"
+                )));
+            }
+            let ttl = Duration::from_secs(resp.ttl.unsigned_abs());
+            *expires_at = Some(Instant::now().checked_add(ttl).unwrap_or_else(Instant::now));
+            sleep(Duration::from_millis(
+                resp.ttl.unsigned_abs().overflow_mul(RENEW_INTERVAL_FACTOR),
+            ))
+            .await;
+        }
+    }
+
+    /// Get the lease id of the session
+    #[inline]
+    #[must_use]
+    pub fn lease_id(&self) -> i64 {
+        self.lease_id
+    }
+
+    /// Return if the lease of the session is lost
+    #[inline]
+    #[must_use]
+    pub fn is_closed(&self) -> bool {
+        self.closed.has_changed().is_err()
+    }
+
+    /// Wait until the lease of the session is lost, return immediately if it
+    /// is already lost
+    #[inline]
+    pub async fn closed(&self) {
+        let mut closed = self.closed.clone();
+        while closed.changed().await.is_ok() {}
+    }
+
+    /// Close the session, stop keeping the lease alive and revoke it
+    ///
+    /// # Errors
+    ///
+    /// Return errors when the lease client failed to revoke the lease
+    #[inline]
+    pub async fn close(mut self) -> Result<()> {
+        self.keep_alive.abort();
+        let _resp = self.lease_client.revoke(self.lease_id).await?;
+        Ok(())
+    }
+}
+
+impl Drop for Session {
+    #[inline]
+    fn drop(&mut self) {
+        self.keep_alive.abort();
+    }
+}
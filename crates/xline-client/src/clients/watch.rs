@@ -1,12 +1,18 @@
-use std::{fmt::Debug, sync::Arc};
+use std::{
+    fmt::{self, Debug},
+    io::Write,
+    sync::Arc,
+};
 
-use futures::channel::mpsc::channel;
+use clippy_utilities::OverflowArithmetic;
+use futures::channel::mpsc::{channel, Sender};
+use prost::Message;
 use tonic::transport::Channel;
-use xlineapi::{self, RequestUnion};
+use xlineapi::{self, key_util::UNBOUNDED, RequestUnion, WatchResponse};
 
 use crate::{
     error::{Result, XlineClientError},
-    types::watch::{WatchOptions, WatchStreaming, Watcher},
+    types::watch::{WatchOptions, WatchResume, WatchStreaming, Watcher},
     AuthService,
 };
 
@@ -22,6 +28,31 @@ pub struct WatchClient {
     /// The watch RPC client, only communicate with one server at a time
     #[cfg(madsim)]
     inner: xlineapi::WatchClient<Channel>,
+    /// Whether to resume the watchers of a broken watch stream
+    auto_resume: bool,
+    /// Credentials to refresh the token of the client when a stream is canceled for the expired
+    /// token, the stream is terminated then if it's not set
+    token_refresh: Option<TokenRefresh>,
+}
+
+/// Credentials to authenticate the client again
+#[derive(Clone)]
+struct TokenRefresh {
+    /// The channel to authenticate through
+    channel: Channel,
+    /// The user name
+    name: String,
+    /// The password of the user
+    password: String,
+}
+
+impl Debug for TokenRefresh {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TokenRefresh")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
 }
 
 impl WatchClient {
@@ -34,21 +65,80 @@ impl WatchClient {
                 channel,
                 token.and_then(|t| t.parse().ok().map(Arc::new)),
             )),
+            auto_resume: true,
+            token_refresh: None,
         }
     }
 
+    /// Sets the credentials to authenticate again when a watch stream is canceled for the
+    /// expired token, the watchers are then resumed on a new stream with the refreshed token.
+    #[inline]
+    #[must_use]
+    pub(crate) fn with_token_refresh(
+        mut self,
+        channel: Channel,
+        name: String,
+        password: String,
+    ) -> Self {
+        self.token_refresh = Some(TokenRefresh {
+            channel,
+            name,
+            password,
+        });
+        self
+    }
+
+    /// Whether the token of the client can be refreshed
+    pub(crate) fn can_refresh_token(&self) -> bool {
+        self.token_refresh.is_some()
+    }
+
+    /// Authenticates again and uses the new token for the following streams
+    pub(crate) async fn refresh_token(&mut self) -> std::result::Result<(), tonic::Status> {
+        let Some(refresh) = self.token_refresh.clone() else {
+            return Err(tonic::Status::unauthenticated(
+                "no credentials to refresh the token",
+            ));
+        };
+        let token = xlineapi::AuthClient::new(refresh.channel.clone())
+            .authenticate(xlineapi::AuthenticateRequest {
+                name: refresh.name,
+                password: refresh.password,
+            })
+            .await?
+            .into_inner()
+            .token;
+        self.inner = xlineapi::WatchClient::new(AuthService::new(
+            refresh.channel,
+            token.parse().ok().map(Arc::new),
+        ));
+        Ok(())
+    }
+
+    /// Sets whether to resume the watchers of a broken watch stream, it's enabled by default.
+    ///
+    /// When enabled, the watchers are re-created on a new watch stream from the next revision
+    /// that has not been delivered yet, the consumer of the `WatchStreaming` won't notice the
+    /// disconnection. When disabled, the error of the stream is returned as is.
+    #[inline]
+    #[must_use]
+    pub fn with_auto_resume(mut self, auto_resume: bool) -> Self {
+        self.auto_resume = auto_resume;
+        self
+    }
+
     /// Watches for events happening or that have happened. Both input and output
     /// are streams; the input stream is for creating and canceling watcher and the output
     /// stream sends events. The entire event history can be watched starting from the
     /// last compaction revision.
     ///
-    /// # Errors
-    ///
-    /// This function will return an error if the RPC client fails to send request
+    /// The watchers of the stream are resumed transparently if the stream breaks, unless the
+    /// auto resume is disabled by [`WatchClient::with_auto_resume`].
     ///
-    /// # Panics
+    /// # Errors
     ///
-    /// This function will panic if the RPC server doesn't return a create watch response
+    /// This function will return an error if the RPC client fails to send request,
+    /// or the RPC server doesn't return a create watch response
     ///
     /// # Examples
     ///
@@ -88,25 +178,28 @@ impl WatchClient {
         key: impl Into<Vec<u8>>,
         options: Option<WatchOptions>,
     ) -> Result<(Watcher, WatchStreaming)> {
-        let (mut request_sender, request_receiver) =
-            channel::<xlineapi::WatchRequest>(CHANNEL_SIZE);
-
+        let create_request: xlineapi::WatchCreateRequest =
+            options.unwrap_or_default().with_key(key.into()).into();
         let request = xlineapi::WatchRequest {
-            request_union: Some(RequestUnion::CreateRequest(
-                options.unwrap_or_default().with_key(key.into()).into(),
-            )),
+            request_union: Some(RequestUnion::CreateRequest(create_request.clone())),
         };
 
-        request_sender
-            .try_send(request)
-            .map_err(|e| XlineClientError::WatchError(e.to_string()))?;
+        let (request_sender, mut response_stream) = self.open_stream(vec![request]).await?;
 
-        let mut response_stream = self.inner.watch(request_receiver).await?.into_inner();
-
-        let watch_id = match response_stream.message().await? {
+        let created = match response_stream.message().await? {
+            // a watch rejected by the server is created and canceled at once
+            Some(resp) if resp.created && resp.canceled => {
+                return Err(XlineClientError::WatchError(format!(
+                    "failed to create watch: {}",
+                    resp.cancel_reason
+                )));
+            }
+            Some(resp) if resp.created => resp,
             Some(resp) => {
-                assert!(resp.created, "not a create watch response");
-                resp.watch_id
+                return Err(XlineClientError::WatchError(format!(
+                    "failed to create watch, got a response of watch {} instead",
+                    resp.watch_id
+                )));
             }
             None => {
                 return Err(XlineClientError::WatchError(String::from(
@@ -115,9 +208,117 @@ impl WatchClient {
             }
         };
 
+        if !self.auto_resume {
+            return Ok((
+                Watcher::new(created.watch_id, request_sender.clone()),
+                WatchStreaming::new(response_stream, request_sender),
+            ));
+        }
+
+        // requests of the watchers are forwarded by the stream, so they can be sent again on a
+        // new stream after reconnecting
+        let (watcher_sender, watcher_receiver) = channel::<xlineapi::WatchRequest>(CHANNEL_SIZE);
+        let resume = WatchResume::new(self.clone(), watcher_receiver, create_request, &created);
         Ok((
-            Watcher::new(watch_id, request_sender.clone()),
-            WatchStreaming::new(response_stream, request_sender),
+            Watcher::new(created.watch_id, watcher_sender),
+            WatchStreaming::new_resumable(response_stream, request_sender, resume),
         ))
     }
+
+    /// Writes an incremental backup of the revisions in `(since, end]` to `writer`, `end` is
+    /// usually the revision in the header of a response received before.
+    ///
+    /// The backup is a stream of length-delimited protobuf encoded `KeyValue`s of the events
+    /// on the whole keyspace in revision order, deletions only have the key and the mod
+    /// revision. It can be applied onto a data directory restored from a snapshot no older
+    /// than `since`.
+    ///
+    /// Returns the number of the written key-values.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the revisions after `since` have been compacted,
+    /// the watch stream fails before reaching `end` or fails to write to `writer`
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use xline_client::{Client, ClientOptions};
+    /// use anyhow::Result;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<()> {
+    ///     let curp_members = ["10.0.0.1:2379", "10.0.0.2:2379", "10.0.0.3:2379"];
+    ///
+    ///     let client = Client::connect(curp_members, ClientOptions::default()).await?;
+    ///     let end = client.kv_client().put("key", "value", None).await?.header.unwrap().revision;
+    ///
+    ///     let mut backup = Vec::new();
+    ///     let count = client.watch_client().backup(1, end, &mut backup).await?;
+    ///     println!("backed up {count} key-values");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[inline]
+    pub async fn backup(&mut self, since: i64, end: i64, writer: &mut impl Write) -> Result<u64> {
+        let mut count = 0;
+        if end <= since {
+            return Ok(count);
+        }
+        let options = WatchOptions::default()
+            .with_range_end(UNBOUNDED)
+            .with_start_revision(since.overflow_add(1));
+        let (_watcher, mut stream) = self.watch(UNBOUNDED, Some(options)).await?;
+        let mut buf = Vec::new();
+        while let Some(resp) = stream.message().await? {
+            if resp.compact_revision != 0 {
+                return Err(XlineClientError::InvalidArgs(format!(
+                    "revision {since} has been compacted, compacted revision: {}",
+                    resp.compact_revision
+                )));
+            }
+            for kv in resp.events.into_iter().filter_map(|event| event.kv) {
+                if kv.mod_revision > end {
+                    return Ok(count);
+                }
+                kv.encode_length_delimited(&mut buf)
+                    .map_err(|e| XlineClientError::EncodeDecode(e.to_string()))?;
+                writer
+                    .write_all(&buf)
+                    .map_err(|e| XlineClientError::IoError(e.to_string()))?;
+                buf.clear();
+                count = count.overflow_add(1);
+            }
+            if resp.header.is_some_and(|header| header.revision >= end) {
+                return Ok(count);
+            }
+        }
+        Err(XlineClientError::WatchError(format!(
+            "watch stream closed before revision {end}"
+        )))
+    }
+
+    /// Opens a new watch stream with the initial requests
+    pub(crate) async fn open_stream(
+        &mut self,
+        requests: Vec<xlineapi::WatchRequest>,
+    ) -> std::result::Result<
+        (
+            Sender<xlineapi::WatchRequest>,
+            tonic::Streaming<WatchResponse>,
+        ),
+        tonic::Status,
+    > {
+        let (mut request_sender, request_receiver) =
+            channel::<xlineapi::WatchRequest>(CHANNEL_SIZE.max(requests.len()));
+        for request in requests {
+            request_sender
+                .try_send(request)
+                .map_err(|e| tonic::Status::internal(e.to_string()))?;
+        }
+
+        let response_stream = self.inner.watch(request_receiver).await?.into_inner();
+        Ok((request_sender, response_stream))
+    }
 }
@@ -161,21 +161,28 @@
     )
 )]
 use std::{
+    collections::HashSet,
     fmt::Debug,
     sync::Arc,
     task::{Context, Poll},
+    time::Duration,
 };
 
 use curp::client::ClientBuilder as CurpClientBuilder;
+use futures::future::join_all;
 use http::{header::AUTHORIZATION, HeaderValue, Request};
-use tonic::transport::Channel;
+use tokio::{sync::mpsc::Sender, task::JoinHandle};
 #[cfg(not(madsim))]
 use tonic::transport::ClientTlsConfig;
-use tower::Service;
+use tonic::transport::{Channel, Endpoint};
+use tower::{discover::Change, Service};
 #[cfg(madsim)]
 use utils::ClientTlsConfig;
 use utils::{build_endpoint, config::ClientConfig};
-use xlineapi::command::{Command, CurpClient};
+use xlineapi::{
+    command::{Command, CurpClient},
+    AlarmAction, AlarmType, Member, StatusResponse,
+};
 
 use crate::{
     clients::{
@@ -183,6 +190,7 @@ use crate::{
         MaintenanceClient, WatchClient,
     },
     error::XlineClientBuildError,
+    types::maintenance::ClusterHealth,
 };
 
 /// Sub-clients for each type of API
@@ -214,6 +222,23 @@ pub struct Client {
     cluster: ClusterClient,
     /// Election client
     election: ElectionClient,
+    /// Auth token used by the clients
+    token: Option<String>,
+    /// Client tls config
+    tls_config: Option<ClientTlsConfig>,
+    /// Task syncing the endpoints of the channel with the members
+    sync_task: Option<Arc<SyncTask>>,
+}
+
+/// Abort the endpoints sync task when the last `Client` is dropped
+#[derive(Debug)]
+struct SyncTask(JoinHandle<()>);
+
+impl Drop for SyncTask {
+    #[inline]
+    fn drop(&mut self) {
+        self.0.abort();
+    }
 }
 
 impl Client {
@@ -238,21 +263,22 @@ impl Client {
             .into_iter()
             .map(|addr| addr.as_ref().to_owned())
             .collect();
-        let channel = Self::build_channel(addrs.clone(), options.tls_config.as_ref()).await?;
+        let (channel, endpoints_tx) =
+            Self::build_channel(addrs.clone(), options.tls_config.as_ref()).await?;
         let curp_client = Arc::new(
             CurpClientBuilder::new(options.client_config, false)
-                .tls_config(options.tls_config)
-                .discover_from(addrs)
+                .tls_config(options.tls_config.clone())
+                .discover_from(addrs.clone())
                 .await?
                 .build::<Command>()?,
         ) as Arc<CurpClient>;
         let id_gen = Arc::new(lease_gen::LeaseIdGenerator::new());
 
         let token = match options.user {
-            Some((username, password)) => {
+            Some((ref username, ref password)) => {
                 let mut tmp_auth = AuthClient::new(Arc::clone(&curp_client), channel.clone(), None);
                 let resp = tmp_auth
-                    .authenticate(username, password)
+                    .authenticate(username.clone(), password.clone())
                     .await
                     .map_err(|err| XlineClientBuildError::AuthError(err.to_string()))?;
 
@@ -277,8 +303,21 @@ impl Client {
         let auth = AuthClient::new(curp_client, channel.clone(), token.clone());
         let maintenance = MaintenanceClient::new(channel.clone(), token.clone());
         let cluster = ClusterClient::new(channel.clone(), token.clone());
-        let watch = WatchClient::new(channel, token);
+        let watch = match options.user {
+            Some((username, password)) => WatchClient::new(channel.clone(), token.clone())
+                .with_token_refresh(channel, username, password),
+            None => WatchClient::new(channel, token.clone()),
+        };
         let election = ElectionClient::new();
+        let sync_task = options.auto_sync_interval.map(|interval| {
+            Arc::new(SyncTask(tokio::spawn(Self::sync_endpoints(
+                cluster.clone(),
+                endpoints_tx,
+                addrs.into_iter().collect(),
+                interval,
+                options.tls_config.clone(),
+            ))))
+        });
 
         Ok(Self {
             kv,
@@ -289,24 +328,68 @@ impl Client {
             watch,
             cluster,
             election,
+            token,
+            tls_config: options.tls_config,
+            sync_task,
         })
     }
 
+    /// Sync the endpoints of the channel with the client urls of the voting
+    /// members periodically, so that the clients follow the membership changes
+    async fn sync_endpoints(
+        mut cluster: ClusterClient,
+        endpoints_tx: Sender<Change<String, Endpoint>>,
+        mut endpoints: HashSet<String>,
+        interval: Duration,
+        tls_config: Option<ClientTlsConfig>,
+    ) {
+        loop {
+            tokio::time::sleep(interval).await;
+            let Ok(resp) = cluster.member_list(false).await else {
+                continue;
+            };
+            let new_endpoints: HashSet<_> = resp
+                .members
+                .into_iter()
+                .filter(|m| !m.name.is_empty() && !m.is_learner)
+                .flat_map(|m| m.client_ur_ls)
+                .collect();
+            if new_endpoints.is_empty() {
+                continue;
+            }
+            let mut changes: Vec<_> = endpoints
+                .difference(&new_endpoints)
+                .map(|addr| Change::Remove(addr.clone()))
+                .collect();
+            for addr in new_endpoints.difference(&endpoints) {
+                if let Ok(endpoint) = build_endpoint(addr, tls_config.as_ref()) {
+                    changes.push(Change::Insert(addr.clone(), endpoint));
+                }
+            }
+            for change in changes {
+                if endpoints_tx.send(change).await.is_err() {
+                    return;
+                }
+            }
+            endpoints = new_endpoints;
+        }
+    }
+
     /// Build a tonic load balancing channel.
     async fn build_channel(
         addrs: Vec<String>,
         tls_config: Option<&ClientTlsConfig>,
-    ) -> Result<Channel, XlineClientBuildError> {
+    ) -> Result<(Channel, Sender<Change<String, Endpoint>>), XlineClientBuildError> {
         let (channel, tx) = Channel::balance_channel(64);
 
         for addr in addrs {
             let endpoint = build_endpoint(&addr, tls_config)?;
-            tx.send(tower::discover::Change::Insert(addr, endpoint))
+            tx.send(Change::Insert(addr, endpoint))
                 .await
                 .unwrap_or_else(|_| unreachable!("The channel will not closed"));
         }
 
-        Ok(channel)
+        Ok((channel, tx))
     }
 
     /// Gets a KV client.
@@ -351,6 +434,98 @@ impl Client {
         self.maintenance.clone()
     }
 
+    /// Gets a maintenance client that only communicates with the member at `endpoint`.
+    ///
+    /// The maintenance requests such as status, hash and defragment are served by the member
+    /// itself, use this client to target a specific member instead of a random one.
+    ///
+    /// # Errors
+    ///
+    /// If the endpoint is invalid.
+    #[inline]
+    pub async fn maintenance_client_for(
+        &self,
+        endpoint: impl AsRef<str>,
+    ) -> Result<MaintenanceClient, XlineClientBuildError> {
+        let (channel, _endpoints_tx) =
+            Self::build_channel(vec![endpoint.as_ref().to_owned()], self.tls_config.as_ref())
+                .await?;
+        Ok(MaintenanceClient::new(channel, self.token.clone()))
+    }
+
+    /// Checks the health of all members of the cluster in one call.
+    ///
+    /// The status of every member is requested from the member itself within `timeout`, the
+    /// members that cannot be reached are reported as unhealthy instead of failing the call.
+    ///
+    /// # Errors
+    ///
+    /// If the members or the alarms of the cluster cannot be listed.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// use xline_client::{Client, ClientOptions};
+    /// use anyhow::Result;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<()> {
+    ///     // the name and address of all curp members
+    ///     let curp_members = ["10.0.0.1:2379", "10.0.0.2:2379", "10.0.0.3:2379"];
+    ///
+    ///     let client = Client::connect(curp_members, ClientOptions::default()).await?;
+    ///
+    ///     let health = client.cluster_health(Duration::from_secs(5)).await?;
+    ///     for member in &health.members {
+    ///         println!("{}: healthy: {}", member.name, member.is_healthy());
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[inline]
+    pub async fn cluster_health(&self, timeout: Duration) -> error::Result<ClusterHealth> {
+        let members = self.cluster.clone().member_list(true).await?.members;
+        let alarms = self
+            .maintenance
+            .clone()
+            .alarm(AlarmAction::Get, 0, AlarmType::None)
+            .await?
+            .alarms;
+        let statuses = join_all(
+            members
+                .iter()
+                .map(|member| self.member_status(member, timeout)),
+        )
+        .await;
+        Ok(ClusterHealth::new(
+            members.into_iter().zip(statuses).collect(),
+            &alarms,
+        ))
+    }
+
+    /// Gets the status of a member from itself
+    async fn member_status(
+        &self,
+        member: &Member,
+        timeout: Duration,
+    ) -> Result<StatusResponse, String> {
+        let endpoint = member
+            .client_ur_ls
+            .first()
+            .ok_or_else(|| "no client urls of the member".to_owned())?;
+        let mut client = self
+            .maintenance_client_for(endpoint)
+            .await
+            .map_err(|e| e.to_string())?;
+        tokio::time::timeout(timeout, client.status())
+            .await
+            .map_err(|_elapsed| "request timed out".to_owned())?
+            .map_err(|e| e.to_string())
+    }
+
     /// Gets a cluster client.
     #[inline]
     #[must_use]
@@ -375,6 +550,8 @@ pub struct ClientOptions {
     tls_config: Option<ClientTlsConfig>,
     /// config for the curp client
     client_config: ClientConfig,
+    /// Interval of syncing the endpoints with the members, disabled if `None`
+    auto_sync_interval: Option<Duration>,
 }
 
 impl ClientOptions {
@@ -390,6 +567,7 @@ impl ClientOptions {
             user,
             tls_config,
             client_config,
+            auto_sync_interval: None,
         }
     }
 
@@ -443,6 +621,25 @@ impl ClientOptions {
             ..self
         }
     }
+
+    /// Get `auto_sync_interval`
+    #[inline]
+    #[must_use]
+    pub fn auto_sync_interval(&self) -> Option<Duration> {
+        self.auto_sync_interval
+    }
+
+    /// Sync the endpoints with the client urls of the members every
+    /// `interval`, so that the client follows the members added or removed at
+    /// runtime. The sync stops when all the clones of the `Client` are dropped.
+    #[inline]
+    #[must_use]
+    pub fn with_auto_sync_interval(self, interval: Duration) -> Self {
+        Self {
+            auto_sync_interval: Some(interval),
+            ..self
+        }
+    }
 }
 
 /// Authentication service.
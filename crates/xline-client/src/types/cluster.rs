@@ -0,0 +1,4 @@
+pub use xlineapi::{
+    Member, MemberAddResponse, MemberListResponse, MemberPromoteResponse, MemberRemoveResponse,
+    MemberUpdateResponse,
+};
@@ -1,4 +1,4 @@
-use xlineapi::command::KeyRange;
+use xlineapi::key_util;
 pub use xlineapi::{
     CompactionResponse, CompareResult, CompareTarget, DeleteRangeResponse, PutResponse,
     RangeResponse, Response, ResponseOp, SortOrder, SortTarget, TargetUnion, TxnResponse,
@@ -510,7 +510,7 @@ impl Compare {
     #[inline]
     #[must_use]
     pub fn with_prefix(mut self) -> Self {
-        self.0.range_end = KeyRange::get_prefix(&self.0.key);
+        self.0.range_end = key_util::prefix_end(&self.0.key);
         self
     }
 }
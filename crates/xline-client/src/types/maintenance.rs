@@ -0,0 +1,150 @@
+pub use xlineapi::{AlarmMember, AlarmType, Member, StatusResponse};
+
+/// Health of a member of the cluster
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct MemberHealth {
+    /// Id of the member
+    pub id: u64,
+    /// Name of the member
+    pub name: String,
+    /// Whether the member responds to the status request
+    pub reachable: bool,
+    /// Whether the member knows the leader of the cluster
+    pub leader_reachable: bool,
+    /// Whether the storage of the member accepts writes, i.e. no `NOSPACE` or
+    /// `CORRUPT` alarm of the member is raised
+    pub storage_writable: bool,
+    /// Number of the entries committed by the cluster that are not applied by
+    /// the member yet
+    pub applied_index_lag: u64,
+    /// Errors reported by the member, or the error of the status request if the
+    /// member is unreachable
+    pub errors: Vec<String>,
+}
+
+impl MemberHealth {
+    /// Whether the member is healthy
+    #[inline]
+    #[must_use]
+    pub fn is_healthy(&self) -> bool {
+        self.reachable && self.leader_reachable && self.storage_writable && self.errors.is_empty()
+    }
+}
+
+/// Consolidated health report of all members of the cluster
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ClusterHealth {
+    /// Health of the members, in the order of the member list
+    pub members: Vec<MemberHealth>,
+}
+
+impl ClusterHealth {
+    /// Build the report from the status of each member and the alarms of the
+    /// cluster, the applied index lag is relative to the highest index committed
+    /// by the reachable members
+    pub(crate) fn new(
+        members: Vec<(Member, Result<StatusResponse, String>)>,
+        alarms: &[AlarmMember],
+    ) -> Self {
+        let committed = members
+            .iter()
+            .filter_map(|(_, status)| status.as_ref().ok())
+            .map(|status| status.raft_index)
+            .max()
+            .unwrap_or_default();
+        let members = members
+            .into_iter()
+            .map(|(member, status)| {
+                let storage_writable = !alarms.iter().any(|alarm| {
+                    alarm.member_id == member.id
+                        && matches!(
+                            AlarmType::try_from(alarm.alarm),
+                            Ok(AlarmType::Nospace | AlarmType::Corrupt)
+                        )
+                });
+                match status {
+                    Ok(status) => MemberHealth {
+                        id: member.id,
+                        name: member.name,
+                        reachable: true,
+                        leader_reachable: status.leader != 0,
+                        storage_writable,
+                        applied_index_lag: committed.saturating_sub(status.raft_applied_index),
+                        errors: status.errors,
+                    },
+                    Err(err) => MemberHealth {
+                        id: member.id,
+                        name: member.name,
+                        reachable: false,
+                        leader_reachable: false,
+                        storage_writable,
+                        applied_index_lag: 0,
+                        errors: vec![err],
+                    },
+                }
+            })
+            .collect();
+        Self { members }
+    }
+
+    /// Whether all members are healthy
+    #[inline]
+    #[must_use]
+    pub fn is_healthy(&self) -> bool {
+        self.members.iter().all(MemberHealth::is_healthy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(id: u64) -> Member {
+        Member {
+            id,
+            name: format!("node{id}"),
+            ..Default::default()
+        }
+    }
+
+    fn status(raft_index: u64, raft_applied_index: u64) -> StatusResponse {
+        StatusResponse {
+            leader: 1,
+            raft_index,
+            raft_applied_index,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn cluster_health_should_report_each_member() {
+        let alarms = [AlarmMember {
+            member_id: 2,
+            alarm: AlarmType::Nospace.into(),
+        }];
+        let health = ClusterHealth::new(
+            vec![
+                (member(1), Ok(status(10, 10))),
+                (member(2), Ok(status(10, 7))),
+                (member(3), Err("timeout".to_owned())),
+            ],
+            &alarms,
+        );
+        assert!(!health.is_healthy());
+
+        let [first, second, third] = health.members.as_slice() else {
+            panic!("unexpected members: {:?}", health.members);
+        };
+        assert!(first.is_healthy());
+        assert_eq!(first.applied_index_lag, 0);
+        assert!(!second.storage_writable);
+        assert_eq!(second.applied_index_lag, 3);
+        assert!(!third.reachable);
+        assert_eq!(third.errors, vec!["timeout".to_owned()]);
+
+        let health = ClusterHealth::new(vec![(member(1), Ok(status(10, 10)))], &[]);
+        assert!(health.is_healthy());
+    }
+}
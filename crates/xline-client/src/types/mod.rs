@@ -1,9 +1,13 @@
 /// Auth type definitions.
 pub mod auth;
+/// Cluster type definitions.
+pub mod cluster;
 /// Kv type definitions.
 pub mod kv;
 /// Lease type definitions
 pub mod lease;
+/// Maintenance type definitions
+pub mod maintenance;
 /// Range Option definitions, to build a `range_end` from key.
 pub mod range_end;
 /// Watch type definitions.
@@ -1,4 +1,4 @@
-use xlineapi::command::KeyRange;
+use xlineapi::key_util::{self, UNBOUNDED};
 
 /// Range end options, indicates how to set `range_end` from a key.
 #[derive(Clone, Debug, PartialEq, Eq, Default)]
@@ -23,17 +23,17 @@ impl RangeOption {
             RangeOption::SingleKey => vec![],
             RangeOption::Prefix => {
                 if key.is_empty() {
-                    key.push(0);
-                    vec![0]
+                    key.extend_from_slice(UNBOUNDED);
+                    UNBOUNDED.to_vec()
                 } else {
-                    KeyRange::get_prefix(key)
+                    key_util::prefix_end(key)
                 }
             }
             RangeOption::FromKey => {
                 if key.is_empty() {
-                    key.push(0);
+                    key.extend_from_slice(UNBOUNDED);
                 }
-                vec![0]
+                UNBOUNDED.to_vec()
             }
             RangeOption::RangeEnd(range_end) => range_end,
         }
@@ -53,7 +53,7 @@ mod tests {
         assert!(key.first() == Some(&0));
         assert_eq!(
             RangeOption::Prefix.get_range_end(&mut key),
-            KeyRange::get_prefix(&key)
+            key_util::prefix_end(&key)
         );
         assert_eq!(
             RangeOption::RangeEnd(vec![1, 2, 3]).get_range_end(&mut key),
@@ -1,13 +1,36 @@
 use std::{
-    fmt::Debug,
-    ops::{Deref, DerefMut},
+    collections::{HashMap, HashSet, VecDeque},
+    error::Error,
+    fmt::{self, Debug},
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
 };
 
 use super::range_end::RangeOption;
-use crate::error::{Result, XlineClientError};
-use futures::channel::mpsc::Sender;
+use crate::{
+    clients::WatchClient,
+    error::{Result, XlineClientError},
+};
+use clippy_utilities::OverflowArithmetic;
+use futures::{
+    channel::mpsc::{Receiver, Sender},
+    future::BoxFuture,
+    FutureExt, Stream, StreamExt,
+};
 pub use xlineapi::{Event, EventType, KeyValue, WatchResponse};
-use xlineapi::{RequestUnion, WatchCancelRequest, WatchProgressRequest};
+use xlineapi::{
+    PbKeyRange, RequestUnion, WatchCancelRequest, WatchCreateRequest, WatchProgressRequest,
+};
+
+/// Watch id of the progress notifications of all watchers on a stream
+const ALL_WATCHERS: i64 = -1;
+
+/// Initial backoff of reopening a broken watch stream
+const MIN_RESUME_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Max backoff of reopening a broken watch stream
+const MAX_RESUME_BACKOFF: Duration = Duration::from_secs(3);
 
 /// The watching handle.
 #[derive(Debug)]
@@ -191,13 +214,34 @@ impl WatchOptions {
         self
     }
 
-    /// fragment enables splitting large revisions into multiple watch responses.
+    /// fragment enables splitting large responses into multiple watch responses, the events of
+    /// a revision are never split.
     #[inline]
     #[must_use]
     pub const fn with_fragment(mut self) -> Self {
         self.inner.fragment = true;
         self
     }
+
+    /// Also watches the range [key, `range_end`) by the same watcher, an empty `range_end`
+    /// watches the single key. It's an Xline extension of the watch API.
+    ///
+    /// All the ranges are watched atomically from the same revision: the events of one revision
+    /// in all the ranges are delivered in one response, like the events of one range, and the
+    /// ranges are canceled together.
+    #[inline]
+    #[must_use]
+    pub fn with_extra_range(
+        mut self,
+        key: impl Into<Vec<u8>>,
+        range_end: impl Into<Vec<u8>>,
+    ) -> Self {
+        self.inner.extra_ranges.push(PbKeyRange {
+            key: key.into(),
+            range_end: range_end.into(),
+        });
+        self
+    }
 }
 
 impl From<WatchOptions> for xlineapi::WatchCreateRequest {
@@ -231,12 +275,23 @@ impl From<WatchFilterType> for i32 {
 }
 
 /// Watch response stream
-#[derive(Debug)]
 pub struct WatchStreaming {
     /// Inner tonic stream
     inner: tonic::Streaming<WatchResponse>,
     /// A sender of WatchResponse, used to keep response stream alive
-    _sender: Sender<xlineapi::WatchRequest>,
+    sender: Sender<xlineapi::WatchRequest>,
+    /// States to resume the watchers if the stream breaks, `None` if auto resume is disabled
+    resume: Option<WatchResume>,
+}
+
+impl Debug for WatchStreaming {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WatchStreaming")
+            .field("inner", &self.inner)
+            .field("resume", &self.resume.is_some())
+            .finish_non_exhaustive()
+    }
 }
 
 impl WatchStreaming {
@@ -249,24 +304,287 @@ impl WatchStreaming {
     ) -> Self {
         Self {
             inner,
-            _sender: sender,
+            sender,
+            resume: None,
         }
     }
-}
 
-impl Deref for WatchStreaming {
-    type Target = tonic::Streaming<WatchResponse>;
+    /// Create a new watch streaming that resumes its watchers after disconnecting
+    pub(crate) fn new_resumable(
+        inner: tonic::Streaming<WatchResponse>,
+        sender: Sender<xlineapi::WatchRequest>,
+        resume: WatchResume,
+    ) -> Self {
+        Self {
+            inner,
+            sender,
+            resume: Some(resume),
+        }
+    }
 
+    /// Fetch the next watch response, the watchers are resumed if the stream breaks and auto
+    /// resume is enabled
+    ///
+    /// # Errors
+    ///
+    /// If the stream returns an error that can not be resumed
     #[inline]
-    fn deref(&self) -> &Self::Target {
-        &self.inner
+    pub async fn message(&mut self) -> Result<Option<WatchResponse>> {
+        self.next().await.transpose()
     }
 }
 
-impl DerefMut for WatchStreaming {
+/// Yields the watch responses of all the watchers created on the stream
+impl Stream for WatchStreaming {
+    type Item = Result<WatchResponse>;
+
     #[inline]
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.inner
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        let Some(resume) = this.resume.as_mut() else {
+            return this
+                .inner
+                .poll_next_unpin(cx)
+                .map(|resp| resp.map(|r| r.map_err(Into::into)));
+        };
+        loop {
+            if let Some(reopening) = resume.reopening.as_mut() {
+                let Poll::Ready(reopened) = reopening.poll_unpin(cx) else {
+                    return Poll::Pending;
+                };
+                resume.reopening = None;
+                match reopened {
+                    Ok((sender, inner)) => {
+                        this.sender = sender;
+                        this.inner = inner;
+                    }
+                    Err(status) => {
+                        this.resume = None;
+                        return Poll::Ready(Some(Err(status.into())));
+                    }
+                }
+            }
+            resume.forward_requests(&mut this.sender, cx);
+            let Poll::Ready(resp) = this.inner.poll_next_unpin(cx) else {
+                return Poll::Pending;
+            };
+            match resp {
+                Some(Ok(resp)) => {
+                    if resume.state.on_response(&resp) {
+                        return Poll::Ready(Some(Ok(resp)));
+                    }
+                }
+                // the stream is canceled for the expired token of the client
+                Some(Err(status))
+                    if status.code() == tonic::Code::Unauthenticated
+                        && resume.client.can_refresh_token() =>
+                {
+                    resume.reopen(true);
+                }
+                Some(Err(status)) if !is_resumable(&status) => {
+                    this.resume = None;
+                    return Poll::Ready(Some(Err(status.into())));
+                }
+                // the stream is broken or closed by the server
+                Some(Err(_)) | None => resume.reopen(false),
+            }
+        }
+    }
+}
+
+/// Return if the watch stream can be reopened after the error, i.e. the server is unavailable
+/// or the connection is broken
+///
+/// An error of the transport carries its cause as the source, while an error returned by the
+/// server is never retried.
+fn is_resumable(status: &tonic::Status) -> bool {
+    status.code() == tonic::Code::Unavailable || status.source().is_some()
+}
+
+/// States to resume the watchers of a watch stream
+pub(crate) struct WatchResume {
+    /// The client used to reopen the stream
+    client: WatchClient,
+    /// Requests sent by `Watcher`s, they are forwarded to the current stream
+    requests: Receiver<xlineapi::WatchRequest>,
+    /// Progress of the watchers
+    state: ResumeState,
+    /// The future of reopening the stream
+    reopening: Option<ReopenFuture>,
+}
+
+/// Future of reopening a watch stream
+type ReopenFuture = BoxFuture<
+    'static,
+    std::result::Result<
+        (
+            Sender<xlineapi::WatchRequest>,
+            tonic::Streaming<WatchResponse>,
+        ),
+        tonic::Status,
+    >,
+>;
+
+impl WatchResume {
+    /// Create a new `WatchResume` with the first watcher created on the stream
+    pub(crate) fn new(
+        client: WatchClient,
+        requests: Receiver<xlineapi::WatchRequest>,
+        create_request: WatchCreateRequest,
+        created: &WatchResponse,
+    ) -> Self {
+        let mut state = ResumeState::default();
+        state.pending.push_back(create_request);
+        let _ignore = state.on_response(created);
+        Self {
+            client,
+            requests,
+            state,
+            reopening: None,
+        }
+    }
+
+    /// Forward the requests of the watchers to the current stream
+    fn forward_requests(
+        &mut self,
+        sender: &mut Sender<xlineapi::WatchRequest>,
+        cx: &mut Context<'_>,
+    ) {
+        while let Poll::Ready(Ok(())) = sender.poll_ready(cx) {
+            let Poll::Ready(Some(request)) = self.requests.poll_next_unpin(cx) else {
+                return;
+            };
+            self.state.on_request(&request);
+            // if the stream is broken, the error will be returned by the response stream
+            if sender.start_send(request).is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Start reopening the stream with the watchers to resume, the token of the client is
+    /// refreshed first if `refresh` is set
+    fn reopen(&mut self, mut refresh: bool) {
+        let mut client = self.client.clone();
+        let requests = self.state.resume_requests();
+        self.reopening = Some(
+            async move {
+                let mut backoff = MIN_RESUME_BACKOFF;
+                loop {
+                    if refresh {
+                        match client.refresh_token().await {
+                            Ok(()) => refresh = false,
+                            Err(status) if is_resumable(&status) => {
+                                tokio::time::sleep(backoff).await;
+                                backoff = backoff.saturating_mul(2).min(MAX_RESUME_BACKOFF);
+                                continue;
+                            }
+                            Err(status) => break Err(status),
+                        }
+                    }
+                    match client.open_stream(requests.clone()).await {
+                        Err(status) if is_resumable(&status) => {
+                            tokio::time::sleep(backoff).await;
+                            backoff = backoff.saturating_mul(2).min(MAX_RESUME_BACKOFF);
+                        }
+                        result => break result,
+                    }
+                }
+            }
+            .boxed(),
+        );
+    }
+}
+
+/// Progress of the watchers on a watch stream
+#[derive(Debug, Default)]
+struct ResumeState {
+    /// Create requests of the created watchers, the start revision of a request is the next
+    /// revision that has not been delivered
+    watchers: HashMap<i64, WatchCreateRequest>,
+    /// Create requests that are sent but not responded yet, in the order of sending
+    pending: VecDeque<WatchCreateRequest>,
+    /// Watchers that are being re-created on a new stream, their created responses are hidden
+    resuming: HashSet<i64>,
+}
+
+impl ResumeState {
+    /// Track a request sent to the stream
+    fn on_request(&mut self, request: &xlineapi::WatchRequest) {
+        match request.request_union {
+            Some(RequestUnion::CreateRequest(ref create)) => {
+                self.pending.push_back(create.clone());
+            }
+            // the watcher should not be resumed even if the cancel response is lost
+            Some(RequestUnion::CancelRequest(ref cancel)) => {
+                let _ignore = self.watchers.remove(&cancel.watch_id);
+            }
+            Some(RequestUnion::ProgressRequest(_)) | None => {}
+        }
+    }
+
+    /// Track a response received from the stream, returns `false` if the response should not be
+    /// yielded to the consumer
+    fn on_response(&mut self, resp: &WatchResponse) -> bool {
+        let revision = resp.header.as_ref().map_or(0, |h| h.revision);
+        if resp.created {
+            let Some(mut request) = self.pending.pop_front() else {
+                return true;
+            };
+            let resumed = self.resuming.remove(&resp.watch_id);
+            if resp.canceled {
+                return true;
+            }
+            if request.start_revision <= 0 && revision > 0 {
+                request.start_revision = revision.overflow_add(1);
+            }
+            let _prev = self.watchers.insert(resp.watch_id, request);
+            return !resumed;
+        }
+        if resp.canceled {
+            let _ignore = self.watchers.remove(&resp.watch_id);
+            return true;
+        }
+        if let Some(last) = resp.events.last().and_then(|ev| ev.kv.as_ref()) {
+            if let Some(request) = self.watchers.get_mut(&resp.watch_id) {
+                // the server never splits a revision across responses, even the fragmented ones
+                request.start_revision = last.mod_revision.overflow_add(1);
+            }
+        } else if revision > 0 {
+            // a progress notification, all the events before the revision have been delivered
+            for (_, request) in self
+                .watchers
+                .iter_mut()
+                .filter(|&(&id, _)| resp.watch_id == ALL_WATCHERS || id == resp.watch_id)
+            {
+                request.start_revision = request.start_revision.max(revision.overflow_add(1));
+            }
+        }
+        true
+    }
+
+    /// Get the requests to send on a new stream, the created watchers are re-created with their
+    /// ids from the next revision to deliver, followed by the requests not responded yet
+    fn resume_requests(&mut self) -> Vec<xlineapi::WatchRequest> {
+        let mut pending: VecDeque<_> = self
+            .watchers
+            .drain()
+            .map(|(watch_id, request)| {
+                let _ignore = self.resuming.insert(watch_id);
+                WatchCreateRequest {
+                    watch_id,
+                    ..request
+                }
+            })
+            .collect();
+        pending.append(&mut self.pending);
+        self.pending = pending;
+        self.pending
+            .iter()
+            .map(|request| xlineapi::WatchRequest {
+                request_union: Some(RequestUnion::CreateRequest(request.clone())),
+            })
+            .collect()
     }
 }
 
@@ -287,4 +605,149 @@ mod tests {
         let request = xlineapi::WatchCreateRequest::from(options2.clone());
         assert_eq!(request.range_end, KeyRange::get_prefix("key"));
     }
+
+    #[test]
+    fn test_extra_ranges_are_watched_by_one_request() {
+        let options = WatchOptions::default()
+            .with_key("a")
+            .with_extra_range("x", "z")
+            .with_extra_range("m", "");
+        let request = xlineapi::WatchCreateRequest::from(options);
+        assert_eq!(request.key, b"a");
+        assert_eq!(
+            request.extra_ranges,
+            [
+                PbKeyRange {
+                    key: b"x".to_vec(),
+                    range_end: b"z".to_vec(),
+                },
+                PbKeyRange {
+                    key: b"m".to_vec(),
+                    range_end: vec![],
+                },
+            ]
+        );
+
+        let mut state = ResumeState::default();
+        state.on_request(&xlineapi::WatchRequest {
+            request_union: Some(RequestUnion::CreateRequest(request.clone())),
+        });
+        assert!(state.on_response(&WatchResponse {
+            created: true,
+            ..response(1, 5)
+        }));
+        assert!(state.on_response(&event_response(1, &[6])));
+        let resumed = state.resume_requests();
+        let Some(RequestUnion::CreateRequest(ref resumed)) = resumed[0].request_union else {
+            unreachable!("a create request is resumed");
+        };
+        assert_eq!(resumed.start_revision, 7);
+        assert_eq!(resumed.extra_ranges, request.extra_ranges);
+    }
+
+    fn response(watch_id: i64, revision: i64) -> WatchResponse {
+        WatchResponse {
+            header: Some(xlineapi::ResponseHeader {
+                revision,
+                ..Default::default()
+            }),
+            watch_id,
+            ..Default::default()
+        }
+    }
+
+    fn event_response(watch_id: i64, revisions: &[i64]) -> WatchResponse {
+        let events = revisions
+            .iter()
+            .map(|&mod_revision| Event {
+                kv: Some(KeyValue {
+                    mod_revision,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .collect();
+        WatchResponse {
+            events,
+            ..response(watch_id, revisions.last().copied().unwrap_or_default())
+        }
+    }
+
+    fn create_request(id: i64) -> xlineapi::WatchRequest {
+        xlineapi::WatchRequest {
+            request_union: Some(RequestUnion::CreateRequest(
+                WatchOptions::default().with_key(format!("key{id}")).into(),
+            )),
+        }
+    }
+
+    fn resumed_revisions(requests: &[xlineapi::WatchRequest]) -> Vec<(i64, i64)> {
+        let mut revisions: Vec<_> = requests
+            .iter()
+            .filter_map(|r| match r.request_union {
+                Some(RequestUnion::CreateRequest(ref c)) => Some((c.watch_id, c.start_revision)),
+                _ => None,
+            })
+            .collect();
+        revisions.sort_unstable();
+        revisions
+    }
+
+    #[test]
+    fn test_resume_watchers_from_next_undelivered_revision() {
+        let mut state = ResumeState::default();
+        for id in 1..=3 {
+            state.on_request(&create_request(id));
+            assert!(state.on_response(&WatchResponse {
+                created: true,
+                ..response(id, 5)
+            }));
+        }
+        assert!(state.on_response(&event_response(1, &[6, 7])));
+        state.on_request(&xlineapi::WatchRequest {
+            request_union: Some(RequestUnion::CancelRequest(WatchCancelRequest {
+                watch_id: 3,
+            })),
+        });
+        assert!(state.on_response(&response(2, 10)));
+
+        let requests = state.resume_requests();
+        assert_eq!(resumed_revisions(&requests), vec![(1, 8), (2, 11)]);
+
+        // the created response of a resumed watcher is not yielded
+        let first = state.pending.front().unwrap().watch_id;
+        assert!(!state.on_response(&WatchResponse {
+            created: true,
+            ..response(first, 12)
+        }));
+        // the stream breaks again before the other watcher is created
+        let requests = state.resume_requests();
+        assert_eq!(resumed_revisions(&requests), vec![(1, 8), (2, 11)]);
+        assert!(state.resuming.contains(&1) && state.resuming.contains(&2));
+    }
+
+    #[test]
+    fn test_resume_fragmented_response_from_next_revision() {
+        let mut state = ResumeState::default();
+        state.on_request(&create_request(0));
+        assert!(state.on_response(&WatchResponse {
+            created: true,
+            ..response(1, 5)
+        }));
+        assert!(state.on_response(&WatchResponse {
+            fragment: true,
+            ..event_response(1, &[6, 7])
+        }));
+        assert_eq!(resumed_revisions(&state.resume_requests()), vec![(1, 8)]);
+    }
+
+    #[test]
+    fn test_only_unavailable_or_transport_errors_are_resumable() {
+        assert!(is_resumable(&tonic::Status::unavailable("no leader")));
+        let broken = std::io::Error::new(std::io::ErrorKind::BrokenPipe, "broken pipe");
+        assert!(is_resumable(&tonic::Status::from_error(Box::new(broken))));
+        assert!(!is_resumable(&tonic::Status::unknown("unknown")));
+        assert!(!is_resumable(&tonic::Status::internal("internal")));
+        assert!(!is_resumable(&tonic::Status::invalid_argument("bad")));
+    }
 }
@@ -0,0 +1,51 @@
+use xline_client::{error::Result, types::cluster::Member};
+
+use super::common::get_cluster_client;
+
+fn find_member(members: &[Member], id: u64) -> Option<&Member> {
+    members.iter().find(|m| m.id == id)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn member_add_update_remove_should_change_topology() -> Result<()> {
+    let (_cluster, client) = get_cluster_client().await.unwrap();
+    let mut client = client.cluster_client();
+
+    let add_resp = client.member_add(["http://127.0.0.1:32380"], true).await?;
+    let added = add_resp.member.unwrap();
+    assert!(added.is_learner);
+    assert_eq!(added.peer_ur_ls, vec!["http://127.0.0.1:32380".to_owned()]);
+    assert_eq!(add_resp.members.len(), 4);
+
+    let list_resp = client.member_list(true).await?;
+    let listed = find_member(&list_resp.members, added.id).unwrap();
+    assert!(listed.is_learner);
+
+    let update_resp = client
+        .member_update(added.id, ["http://127.0.0.1:32381"])
+        .await?;
+    let updated = find_member(&update_resp.members, added.id).unwrap();
+    assert_eq!(
+        updated.peer_ur_ls,
+        vec!["http://127.0.0.1:32381".to_owned()]
+    );
+
+    let remove_resp = client.member_remove(added.id).await?;
+    assert!(find_member(&remove_resp.members, added.id).is_none());
+    let list_resp = client.member_list(true).await?;
+    assert_eq!(list_resp.members.len(), 3);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn member_promote_should_reject_unknown_members() -> Result<()> {
+    let (_cluster, client) = get_cluster_client().await.unwrap();
+    let mut client = client.cluster_client();
+
+    let members = client.member_list(false).await?.members;
+    let unknown = members.iter().map(|m| m.id).max().unwrap().wrapping_add(1);
+    assert!(client.member_promote(unknown).await.is_err());
+
+    Ok(())
+}
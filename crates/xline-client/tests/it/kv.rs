@@ -218,7 +218,7 @@ async fn txn_should_execute_as_expected() -> Result<()> {
             xlineapi::Response::ResponsePut(resp) => {
                 assert_eq!(resp.prev_kv.as_ref().unwrap().value, b"01")
             }
-            _ => panic!("expect put response)"),
+            _ => panic!("expect put response"),
         }
 
         let resp = client.range("txn01", None).await?;
@@ -245,7 +245,7 @@ async fn txn_should_execute_as_expected() -> Result<()> {
             xlineapi::Response::ResponseRange(resp) => {
                 assert_eq!(resp.kvs[0].value, b"02")
             }
-            _ => panic!("expect range response)"),
+            _ => panic!("expect range response"),
         }
     }
 
@@ -288,3 +288,35 @@ async fn compact_should_remove_previous_revision() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test(flavor = "multi_thread")]
+#[abort_on_panic]
+async fn cas_should_swap_only_expected_value() -> Result<()> {
+    let (_cluster, client) = get_cluster_client().await.unwrap();
+    let client = client.kv_client();
+
+    let resp = client.cas_mod_revision("cas", 0, "01").await?;
+    assert!(resp.succeeded);
+    let resp = client.cas_mod_revision("cas", 0, "02").await?;
+    assert!(!resp.succeeded);
+
+    let resp = client.cas("cas", "01", "02").await?;
+    assert!(resp.succeeded);
+    match resp.responses[0].response.as_ref().unwrap() {
+        xlineapi::Response::ResponsePut(resp) => {
+            assert_eq!(resp.prev_kv.as_ref().unwrap().value, b"01")
+        }
+        _ => panic!("expect put response"),
+    }
+
+    let resp = client.cas("cas", "01", "03").await?;
+    assert!(!resp.succeeded);
+    match resp.responses[0].response.as_ref().unwrap() {
+        xlineapi::Response::ResponseRange(resp) => {
+            assert_eq!(resp.kvs[0].value, b"02")
+        }
+        _ => panic!("expect range response"),
+    }
+
+    Ok(())
+}
@@ -1,4 +1,6 @@
-use xline_client::error::Result;
+use std::time::Duration;
+
+use xline_client::{clients::Session, error::Result};
 
 use super::common::get_cluster_client;
 
@@ -36,6 +38,38 @@ async fn keep_alive_should_success_in_normal_path() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn keep_alive_should_fail_for_revoked_lease() -> Result<()> {
+    let (_cluster, client) = get_cluster_client().await.unwrap();
+    let mut client = client.lease_client();
+
+    let id = client.grant(60, None).await?.id;
+    client.revoke(id).await?;
+
+    assert!(client.keep_alive(id).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn session_should_be_closed_when_lease_is_lost() -> Result<()> {
+    let (_cluster, client) = get_cluster_client().await.unwrap();
+    let mut client = client.lease_client();
+
+    let session = Session::new(client.clone(), Some(2), None).await?;
+    let id = session.lease_id();
+    assert!(client.time_to_live(id, false).await?.ttl > 0);
+    assert!(!session.is_closed());
+
+    client.revoke(id).await?;
+    tokio::time::timeout(Duration::from_secs(10), session.closed())
+        .await
+        .expect("session should be closed after its lease is revoked");
+    assert!(session.is_closed());
+
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn time_to_live_ttl_is_consistent_in_normal_path() -> Result<()> {
     let (_cluster, client) = get_cluster_client().await.unwrap();
@@ -1,4 +1,5 @@
 mod auth;
+mod cluster;
 mod common;
 mod kv;
 mod lease;
@@ -1,3 +1,6 @@
+use std::{collections::HashSet, time::Duration};
+
+use futures::AsyncReadExt;
 use xline_client::error::Result;
 
 use super::common::get_cluster_client;
@@ -19,3 +22,50 @@ async fn snapshot_should_get_valid_data() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn snapshot_reader_should_read_the_whole_snapshot() -> Result<()> {
+    let (_cluster, client) = get_cluster_client().await.unwrap();
+    let mut client = client.maintenance_client();
+
+    let mut snapshot = vec![];
+    let _n = client
+        .snapshot_reader()
+        .await?
+        .read_to_end(&mut snapshot)
+        .await
+        .unwrap();
+    assert!(!snapshot.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn maintenance_client_for_should_target_the_member() -> Result<()> {
+    let (cluster, client) = get_cluster_client().await.unwrap();
+
+    let mut member_ids = HashSet::new();
+    for addr in cluster.all_client_addrs() {
+        let resp = client
+            .maintenance_client_for(addr)
+            .await
+            .unwrap()
+            .status()
+            .await?;
+        assert!(member_ids.insert(resp.header.unwrap().member_id));
+    }
+    assert_eq!(member_ids.len(), 3);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn cluster_health_should_report_every_member() -> Result<()> {
+    let (_cluster, client) = get_cluster_client().await.unwrap();
+
+    let health = client.cluster_health(Duration::from_secs(3)).await?;
+    assert_eq!(health.members.len(), 3);
+    assert!(health.is_healthy(), "{health:?}");
+
+    Ok(())
+}
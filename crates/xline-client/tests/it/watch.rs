@@ -1,5 +1,11 @@
 //! The following tests are originally from `etcd-client`
-use xline_client::{error::Result, types::watch::EventType};
+use futures::StreamExt;
+use prost::Message;
+use xline_client::{
+    error::Result,
+    types::watch::{EventType, WatchOptions},
+};
+use xlineapi::KeyValue;
 
 use super::common::get_cluster_client;
 
@@ -52,3 +58,69 @@ async fn watch_stream_should_work_after_watcher_dropped() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn watch_stream_should_yield_responses_of_all_watchers() -> Result<()> {
+    let (_cluster, client) = get_cluster_client().await.unwrap();
+    let mut watch_client = client.watch_client();
+    let kv_client = client.kv_client();
+
+    let (mut watcher, mut stream) = watch_client.watch("watch01", None).await?;
+    watcher.watch(WatchOptions::default().with_key("watch02"))?;
+    let resp = stream.next().await.unwrap()?;
+    assert!(resp.created);
+    let second_id = resp.watch_id;
+
+    kv_client.put("watch01", "01", None).await?;
+    kv_client.put("watch02", "02", None).await?;
+
+    let resp = stream.next().await.unwrap()?;
+    assert_eq!(resp.watch_id, watcher.watch_id());
+    assert_eq!(resp.events[0].kv.as_ref().unwrap().key, b"watch01");
+    let resp = stream.next().await.unwrap()?;
+    assert_eq!(resp.watch_id, second_id);
+    assert_eq!(resp.events[0].kv.as_ref().unwrap().key, b"watch02");
+
+    watcher.cancel_by_id(second_id)?;
+    let resp = stream.next().await.unwrap()?;
+    assert_eq!(resp.watch_id, second_id);
+    assert!(resp.canceled);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn backup_should_export_revisions_after_since() -> Result<()> {
+    let (_cluster, client) = get_cluster_client().await.unwrap();
+    let mut watch_client = client.watch_client();
+    let kv_client = client.kv_client();
+
+    let since = kv_client
+        .put("backup01", "01", None)
+        .await?
+        .header
+        .unwrap()
+        .revision;
+    kv_client.put("backup02", "02", None).await?;
+    let end = kv_client
+        .delete("backup01", None)
+        .await?
+        .header
+        .unwrap()
+        .revision;
+    kv_client.put("backup03", "03", None).await?;
+
+    let mut backup = Vec::new();
+    assert_eq!(watch_client.backup(since, end, &mut backup).await?, 2);
+
+    let mut buf = backup.as_slice();
+    let put = KeyValue::decode_length_delimited(&mut buf).unwrap();
+    assert_eq!(put.key, b"backup02");
+    assert_eq!(put.value, b"02");
+    let deletion = KeyValue::decode_length_delimited(&mut buf).unwrap();
+    assert_eq!(deletion.key, b"backup01");
+    assert_eq!((deletion.mod_revision, deletion.version), (end, 0));
+    assert!(buf.is_empty());
+
+    Ok(())
+}
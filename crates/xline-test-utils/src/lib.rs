@@ -10,8 +10,9 @@ use tokio::{
 };
 use tonic::transport::ClientTlsConfig;
 use utils::config::{
-    default_quota, AuthConfig, ClusterConfig, CompactConfig, EngineConfig, InitialClusterState,
-    LogConfig, MetricsConfig, StorageConfig, TlsConfig, TraceConfig, XlineServerConfig,
+    default_max_key_bytes, default_max_value_bytes, default_quota, AuthConfig, ClusterConfig,
+    CompactConfig, EngineConfig, InitialClusterState, LogConfig, MetricsConfig, StorageConfig,
+    TlsConfig, TraceConfig, XlineServerConfig,
 };
 use xline::server::XlineServer;
 use xline_client::types::{auth::PermissionType, range_end::RangeOption};
@@ -64,13 +65,15 @@ impl Cluster {
         }
         let server_tls_enabled = configs.iter().any(|c| c.tls().server_tls_enabled());
         let scheme = if server_tls_enabled { "https" } else { "http" };
+        let peer_tls_enabled = configs.iter().any(|c| c.tls().peer_tls_enabled());
+        let peer_scheme = if peer_tls_enabled { "https" } else { "http" };
         let all_members_client_urls = listeners
             .iter()
             .map(|l| format!("{scheme}://{}", l.0.local_addr().unwrap()))
             .collect();
         let all_members_peer_urls = listeners
             .iter()
-            .map(|l| format!("{scheme}://{}", l.1.local_addr().unwrap()))
+            .map(|l| format!("{peer_scheme}://{}", l.1.local_addr().unwrap()))
             .collect();
         Self {
             listeners,
@@ -148,8 +151,13 @@ impl Cluster {
         let name = format!("server{}", idx);
         let server_tls_enabled = base_config.tls().server_tls_enabled();
         let scheme = if server_tls_enabled { "https" } else { "http" };
+        let peer_scheme = if base_config.tls().peer_tls_enabled() {
+            "https"
+        } else {
+            "http"
+        };
         let self_client_url = format!("{scheme}://{}", xline_listener.local_addr().unwrap());
-        let self_peer_url = format!("{scheme}://{}", curp_listener.local_addr().unwrap());
+        let self_peer_url = format!("{peer_scheme}://{}", curp_listener.local_addr().unwrap());
         self.all_members_client_urls.push(self_client_url.clone());
         self.all_members_peer_urls.push(self_peer_url.clone());
 
@@ -241,7 +249,15 @@ impl Cluster {
         quota: u64,
     ) -> XlineServerConfig {
         let cluster = ClusterConfig::default();
-        let storage = StorageConfig::new(EngineConfig::RocksDB(path), quota);
+        let storage = StorageConfig::new(
+            EngineConfig::RocksDB(path),
+            quota,
+            None,
+            default_max_key_bytes(),
+            default_max_value_bytes(),
+            0,
+            false,
+        );
         let log = LogConfig::default();
         let trace = TraceConfig::default();
         let auth = AuthConfig::default();
@@ -0,0 +1,245 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+use clippy_utilities::{NumericCast, OverflowArithmetic};
+use prost::Message;
+use utils::{config::EngineConfig, table_names::KV_TABLE};
+
+use crate::{
+    rpc::KeyValue,
+    storage::{
+        db::{WriteOp, DB},
+        storage_api::XlineStorageOps,
+        Revision,
+    },
+};
+
+/// Default number of key-value pairs that share one revision
+pub const DEFAULT_IMPORT_BATCH_SIZE: usize = 0x400; // 1024
+
+/// Max length in bytes of a varint encoded `u64`
+const MAX_VARINT_LEN: usize = 10;
+
+/// Summary of a bulk import
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ImportSummary {
+    /// Number of imported key-value pairs
+    pub count: u64,
+    /// Last revision written by the import
+    pub revision: i64,
+}
+
+/// Import key-value pairs from a file into the data dir of a new member
+///
+/// The file is a stream of length-delimited protobuf encoded `KeyValue`s sorted
+/// by the keys, only the keys and values are imported. Every `batch_size` pairs
+/// are written with one revision like a txn. Revisions are assigned
+/// deterministically, so importing the same file into every member of a new
+/// cluster produces the same store on all of them.
+///
+/// # Errors
+///
+/// - return an error if the data dir already contains key-values
+/// - return an error if meet io errors or the file is malformed
+/// - return an error if the keys are not sorted or a key appears twice
+/// - return `ExecuteError::DbError` if meet db errors
+#[inline]
+pub fn bulk_import<P: AsRef<Path>, D: Into<PathBuf>>(
+    source: P,
+    data_dir: D,
+    batch_size: usize,
+) -> Result<ImportSummary> {
+    let db = DB::open(&EngineConfig::RocksDB(data_dir.into()))?;
+    let reader = LengthDelimited(BufReader::new(File::open(source)?));
+    import_kvs(&db, reader, batch_size)
+}
+
+/// Write key-value pairs sorted by the keys into an empty db with batched
+/// revisions
+///
+/// The pairs are streamed into the db batch by batch, only the last imported key
+/// is kept to check the order, so the import takes no memory for the imported
+/// keys.
+pub(crate) fn import_kvs<I>(db: &DB, kvs: I, batch_size: usize) -> Result<ImportSummary>
+where
+    I: IntoIterator<Item = io::Result<KeyValue>>,
+{
+    if !db.is_empty(KV_TABLE)? {
+        bail!("bulk import requires an empty data dir");
+    }
+    let batch_size = batch_size.max(1);
+    let mut last_key: Option<Vec<u8>> = None;
+    let mut summary = ImportSummary {
+        count: 0,
+        // the revision of an empty store
+        revision: 1,
+    };
+    let mut ops = Vec::with_capacity(batch_size);
+    for kv in kvs {
+        let KeyValue { key, value, .. } = kv?;
+        if key.is_empty() {
+            bail!("cannot import an empty key");
+        }
+        if last_key.as_ref().is_some_and(|last| *last >= key) {
+            bail!("the imported keys must be sorted and unique, found {key:?} after {last_key:?}");
+        }
+        last_key = Some(key.clone());
+        if ops.len() >= batch_size {
+            db.write_ops(std::mem::take(&mut ops))?;
+        }
+        if ops.is_empty() {
+            summary.revision = summary.revision.overflow_add(1);
+        }
+        let revision = summary.revision;
+        let sub_revision = ops.len().numeric_cast();
+        ops.push(WriteOp::PutKeyValue(
+            Revision::new(revision, sub_revision),
+            KeyValue {
+                key,
+                create_revision: revision,
+                mod_revision: revision,
+                version: 1,
+                value,
+                lease: 0,
+            },
+        ));
+        summary.count = summary.count.overflow_add(1);
+    }
+    db.write_ops(ops)?;
+
+    Ok(summary)
+}
+
+/// Iterator over the length-delimited messages of a reader
+pub(crate) struct LengthDelimited<R>(pub(crate) R);
+
+impl<R: Read> LengthDelimited<R> {
+    /// Read the varint length prefix, returns `None` at the end of the stream
+    fn read_len(&mut self) -> io::Result<Option<usize>> {
+        let mut len = 0_u64;
+        let mut shift = 0_u32;
+        for i in 0..MAX_VARINT_LEN {
+            let mut byte = [0_u8];
+            if self.0.read(&mut byte)? == 0 {
+                if i == 0 {
+                    return Ok(None);
+                }
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            let [b] = byte;
+            len |= u64::from(b & 0x7F).overflow_shl(shift);
+            shift = shift.overflow_add(7);
+            if b & 0x80 == 0 {
+                return usize::try_from(len)
+                    .map(Some)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "length prefix is too long",
+        ))
+    }
+}
+
+impl<R: Read> Iterator for LengthDelimited<R> {
+    type Item = io::Result<KeyValue>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let len = match self.read_len() {
+            Ok(len) => len?,
+            Err(e) => return Some(Err(e)),
+        };
+        let mut buf = vec![0; len];
+        Some(self.0.read_exact(&mut buf).and_then(|()| {
+            KeyValue::decode(buf.as_slice())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::storage::compression::decode_kv;
+
+    fn kv(key: &str, value: &str) -> KeyValue {
+        KeyValue {
+            key: key.into(),
+            value: value.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_read_length_delimited_kvs() {
+        let kvs = vec![kv("a", "1"), kv("b", &"2".repeat(300))];
+        let mut buf = vec![];
+        for kv in &kvs {
+            kv.encode_length_delimited(&mut buf).unwrap();
+        }
+        let decoded: Vec<_> = LengthDelimited(buf.as_slice())
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(decoded, kvs);
+
+        buf.pop();
+        assert!(LengthDelimited(buf.as_slice()).last().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_import_kvs_with_batched_revisions() -> Result<()> {
+        let db = DB::open(&EngineConfig::Memory)?;
+        let kvs = ["a", "b", "c", "d", "e"].map(|k| Ok(kv(k, k)));
+        let summary = import_kvs(&db, kvs, 2)?;
+        assert_eq!(
+            summary,
+            ImportSummary {
+                count: 5,
+                revision: 4
+            }
+        );
+
+        let stored: Vec<_> = db
+            .get_all(KV_TABLE)?
+            .into_iter()
+            .map(|(k, v)| (Revision::decode(&k), decode_kv(&v).unwrap()))
+            .map(|(rev, kv)| {
+                (
+                    rev.revision(),
+                    rev.sub_revision(),
+                    kv.key,
+                    kv.create_revision,
+                    kv.version,
+                )
+            })
+            .collect();
+        assert_eq!(
+            stored,
+            vec![
+                (2, 0, b"a".to_vec(), 2, 1),
+                (2, 1, b"b".to_vec(), 2, 1),
+                (3, 0, b"c".to_vec(), 3, 1),
+                (3, 1, b"d".to_vec(), 3, 1),
+                (4, 0, b"e".to_vec(), 4, 1),
+            ]
+        );
+
+        assert!(import_kvs(&db, [Ok(kv("f", "f"))], 2).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_unsorted_kvs_should_fail() -> Result<()> {
+        for keys in [["b", "a"], ["x", "x"]] {
+            let db = DB::open(&EngineConfig::Memory)?;
+            assert!(import_kvs(&db, keys.map(|k| Ok(kv(k, k))), 8).is_err());
+        }
+        Ok(())
+    }
+}
@@ -89,6 +89,8 @@ fn is_exclusive_cmd(cmd: &Command) -> bool {
             | RequestWrapper::AuthUserRevokeRoleRequest(_)
             | RequestWrapper::AuthenticateRequest(_)
             | RequestWrapper::AlarmRequest(_)
+            | RequestWrapper::DowngradeRequest(_)
+            | RequestWrapper::ClusterVersionSetRequest(_)
     )
 }
 
@@ -111,6 +111,25 @@ fn lease_sp_operations_are_ok() {
     assert_eq!(sp.len(), 0);
 }
 
+#[test]
+fn sp_ignores_unattached_leases_and_empty_ranges() {
+    let mut lease_sp = LeaseSpecPool::default();
+    let mut kv_sp = KvSpecPool::default();
+    let mut gen = EntryGenerator::default();
+    let entry1 = gen.gen_put("a");
+    let entry2 = gen.gen_put("b");
+    let entry3 = gen.gen_delete_range("c", "a");
+    // puts without a lease do not conflict in the lease pool
+    assert!(lease_sp.insert_if_not_conflict(entry1.clone()).is_none());
+    assert!(lease_sp.insert_if_not_conflict(entry2.clone()).is_none());
+    assert!(kv_sp.insert_if_not_conflict(entry1).is_none());
+    assert!(kv_sp.insert_if_not_conflict(entry2).is_none());
+    // an empty range conflicts with nothing
+    assert!(kv_sp.insert_if_not_conflict(entry3.clone()).is_none());
+    kv_sp.remove(&entry3);
+    assert_eq!(kv_sp.len(), 2);
+}
+
 #[test]
 fn lease_ucp_operations_are_ok() {
     let mut ucp = LeaseUncomPool::default();
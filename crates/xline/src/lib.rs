@@ -174,11 +174,13 @@ mod revision_number;
 mod rpc {
     pub(crate) use xlineapi::*;
 }
+/// Bulk import of key-values into a new member
+pub mod bulk_import;
 /// Command conflict implementation
 mod conflict;
 /// Xline metrics
 pub mod metrics;
-/// restore module, only for test
+/// Offline restore of a snapshot into a new member
 pub mod restore;
 /// Revision check
 mod revision_check;
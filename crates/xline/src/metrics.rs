@@ -1,10 +1,14 @@
+use std::{sync::Arc, time::Duration};
+
 use clippy_utilities::NumericCast;
 use opentelemetry::{
     metrics::{Counter, MetricsError},
     KeyValue,
 };
 use tracing::error;
-use utils::define_metrics;
+use utils::{define_metrics, table_names::XLINE_TABLES};
+
+use crate::storage::db::DB;
 
 define_metrics! {
     "xline",
@@ -19,12 +23,16 @@ define_metrics! {
     lease_expired_total: Counter<u64> = meter()
         .u64_counter("lease_expired")
         .with_description("The total number of expired leases.")
+        .init(),
+    compaction_reclaimed_bytes_total: Counter<u64> = meter()
+        .u64_counter("compaction_reclaimed_bytes")
+        .with_description("The total number of bytes of compacted revisions and tombstones removed from the kv table.")
         .init()
 }
 
 impl Metrics {
     /// Register metrics
-    pub(super) fn register_callback() -> Result<(), MetricsError> {
+    pub(super) fn register_callback(db: Arc<DB>) -> Result<(), MetricsError> {
         let meter = meter();
         let (fd_used, fd_limit, current_version, current_rust_version) = (
             meter
@@ -90,6 +98,95 @@ impl Metrics {
             },
         )?;
 
+        let (db_size, db_size_in_use, db_table_size) = (
+            meter
+                .u64_observable_gauge("db_total_size_in_bytes")
+                .with_description("Total size of the underlying database physically allocated in bytes.")
+                .init(),
+            meter
+                .u64_observable_gauge("db_total_size_in_use_in_bytes")
+                .with_description("Total size of the revisions kept in the kv table in bytes, the gap to the total size can be reclaimed by defragment.")
+                .init(),
+            meter
+                .u64_observable_gauge("db_table_size_in_bytes")
+                .with_description("Estimated size of each table of the underlying database in bytes.")
+                .init(),
+        );
+
+        let db_stats = Arc::clone(&db);
+        _ = meter.register_callback(
+            &[
+                db_size.as_any(),
+                db_size_in_use.as_any(),
+                db_table_size.as_any(),
+            ],
+            move |observer| {
+                observer.observe_u64(&db_size, db.estimated_file_size(), &[]);
+                observer.observe_u64(&db_size_in_use, db.size_in_use(), &[]);
+                for table in XLINE_TABLES {
+                    match db.table_size(table) {
+                        Ok(size) => observer.observe_u64(
+                            &db_table_size,
+                            size,
+                            &[KeyValue::new("table", table)],
+                        ),
+                        Err(err) => error!("{err}"),
+                    }
+                }
+            },
+        )?;
+
+        let (db_write_stall, db_compactions, db_compaction_duration, db_open_files) = (
+            meter
+                .f64_observable_counter("db_write_stall_duration_seconds")
+                .with_description(
+                    "Total time the writes to the underlying database are stalled in seconds.",
+                )
+                .init(),
+            meter
+                .u64_observable_counter("db_compactions")
+                .with_description(
+                    "The total number of compactions finished by the underlying database.",
+                )
+                .init(),
+            meter
+                .f64_observable_counter("db_compaction_duration_seconds")
+                .with_description(
+                    "Total time taken by the compactions of the underlying database in seconds.",
+                )
+                .init(),
+            meter
+                .u64_observable_gauge("db_open_files")
+                .with_description("The number of files opened by the underlying database.")
+                .init(),
+        );
+
+        _ = meter.register_callback(
+            &[
+                db_write_stall.as_any(),
+                db_compactions.as_any(),
+                db_compaction_duration.as_any(),
+                db_open_files.as_any(),
+            ],
+            move |observer| match db_stats.engine_stats() {
+                Ok(stats) => {
+                    observer.observe_f64(
+                        &db_write_stall,
+                        Duration::from_micros(stats.write_stall_micros).as_secs_f64(),
+                        &[],
+                    );
+                    observer.observe_u64(&db_compactions, stats.compactions, &[]);
+                    observer.observe_f64(
+                        &db_compaction_duration,
+                        Duration::from_micros(stats.compaction_micros).as_secs_f64(),
+                        &[],
+                    );
+                    observer.observe_u64(&db_open_files, stats.open_files, &[]);
+                }
+                Err(err) => error!("{err}"),
+            },
+        )?;
+
         Ok(())
     }
 }
@@ -1,27 +1,187 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use bytes::BytesMut;
-use clippy_utilities::NumericCast;
-use engine::{Engine, EngineType, Snapshot, SnapshotApi, StorageEngine};
+use clippy_utilities::{NumericCast, OverflowArithmetic};
+use curp::{
+    members::ClusterInfo,
+    server::{StorageApi as _, DB as CurpDB},
+};
+use engine::{EngineType, KeyProvider, Snapshot, SnapshotApi, ViewApi};
+use prost::Message;
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, SeekFrom};
 use tokio_util::io::read_buf;
-use utils::table_names::XLINE_TABLES;
+use utils::{
+    config::EngineConfig,
+    table_names::{ALARM_TABLE, KV_TABLE, LEASE_TABLE, META_TABLE},
+};
+use xlineapi::{command::Command, key_util::UNBOUNDED, AlarmMember};
 
-use crate::server::MAINTENANCE_SNAPSHOT_CHUNK_SIZE;
+use crate::{
+    bulk_import::LengthDelimited,
+    rpc::KeyValue,
+    server::{command::APPLIED_INDEX_KEY, MAINTENANCE_SNAPSHOT_CHUNK_SIZE, MIN_PAGE_SIZE},
+    storage::{
+        compression::{decode_record, KvRecord},
+        db::{WriteOp, DB},
+        index::{Index, IndexOperate},
+        storage_api::XlineStorageOps,
+        Revision,
+    },
+};
 
-/// Restore snapshot to data dir
+/// Summary of a restore
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RestoreSummary {
+    /// Number of keys alive at the restored revision
+    pub count: u64,
+    /// Revision of the restored store
+    pub revision: i64,
+}
+
+/// Membership of a new cluster, written to the curp storage of a restored
+/// member
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Membership {
+    /// Name of the restored member
+    pub name: String,
+    /// Peer urls of all the members of the new cluster, by their names
+    pub initial_cluster: HashMap<String, Vec<String>>,
+    /// Client urls advertised by the restored member
+    pub client_urls: Vec<String>,
+}
+
+impl Membership {
+    /// New `Membership`
+    #[inline]
+    #[must_use]
+    pub fn new(
+        name: String,
+        initial_cluster: HashMap<String, Vec<String>>,
+        client_urls: Vec<String>,
+    ) -> Self {
+        Self {
+            name,
+            initial_cluster,
+            client_urls,
+        }
+    }
+}
+
+/// Restore snapshot to the data dir of a member of a new cluster
+///
+/// The state bound to the old cluster, i.e. the applied index of its log and
+/// the alarms raised by its members, is reset. The member joins the new
+/// topology written by [`write_membership`], or else the one given by its own
+/// configuration. The index is rebuilt from the restored key-values to make
+/// sure the member is able to recover from it.
+///
+/// The snapshot of an encrypted store must be restored with a `key_provider`
+/// of the keys its values are encrypted with, the same keys the member is
+/// configured with.
 ///
 /// # Errors
 ///
-/// - return `ClientError::IoError` if meet io errors
-/// - return `ClientError::EngineError` if meet engine errors
+/// - return an error if the data dir already contains key-values
+/// - return an error if meet io errors or engine errors
+/// - return an error if the snapshot contains undecodable key-values, or
+///   values that can not be decrypted by the key provider
 #[inline]
 #[allow(clippy::indexing_slicing)] // safe operation
 pub async fn restore<P: AsRef<Path>, D: Into<PathBuf>>(
     snapshot_path: P,
     data_dir: D,
-) -> Result<()> {
+    key_provider: Option<Arc<dyn KeyProvider>>,
+) -> Result<RestoreSummary> {
+    let rocks_snapshot = receive_snapshot(snapshot_path).await?;
+    let db = DB::open_with_key_provider(&EngineConfig::RocksDB(data_dir.into()), key_provider)?;
+    if !db.is_empty(KV_TABLE)? {
+        bail!("restore requires an empty data dir");
+    }
+    db.reset(Some(rocks_snapshot)).await?;
+    seed_new_member(&db)
+}
+
+/// Write the membership of the new cluster to the curp storage of a restored
+/// member, which replaces the membership given by its configuration
+///
+/// # Errors
+///
+/// - return an error if the curp storage already holds a membership, its log
+///   belongs to another cluster
+/// - return an error if the member is not in the initial cluster
+/// - return an error if meet storage errors
+#[inline]
+pub fn write_membership<D: Into<PathBuf>>(curp_dir: D, membership: &Membership) -> Result<()> {
+    if !membership.initial_cluster.contains_key(&membership.name) {
+        bail!("member {} is not in the initial cluster", membership.name);
+    }
+    let storage = CurpDB::<Command>::open(&EngineConfig::RocksDB(curp_dir.into()))?;
+    if storage.recover_cluster_info()?.is_some() {
+        bail!("restore requires an empty curp dir");
+    }
+    let cluster_info = ClusterInfo::from_members_map(
+        membership.initial_cluster.clone(),
+        membership.client_urls.clone(),
+        &membership.name,
+    );
+    storage.put_cluster_info(&cluster_info)?;
+    Ok(())
+}
+
+/// Restore snapshot to the opened storage of a starting member, returns `None`
+/// if the storage is not empty, e.g. it has been restored at a previous startup
+///
+/// The hash of the snapshot is not checked if `skip_hash_check` is set, e.g.
+/// for a snapshot copied from a data dir rather than saved from the
+/// maintenance server.
+///
+/// # Errors
+///
+/// - return an error if the snapshot fails the hash check
+/// - return an error if meet io errors or engine errors
+pub(crate) async fn restore_on_startup<P: AsRef<Path>>(
+    snapshot_path: P,
+    db: &DB,
+    skip_hash_check: bool,
+) -> Result<Option<RestoreSummary>> {
+    if !db.is_empty(KV_TABLE)? || db.get_value(META_TABLE, APPLIED_INDEX_KEY)?.is_some() {
+        return Ok(None);
+    }
+    let rocks_snapshot = if skip_hash_check {
+        receive_unchecked_snapshot(snapshot_path).await?
+    } else {
+        receive_snapshot(snapshot_path).await?
+    };
+    db.reset(Some(rocks_snapshot)).await?;
+    seed_new_member(db).map(Some)
+}
+
+/// Check the hash of the snapshot and receive it into a temporary rocksdb
+/// snapshot
+async fn receive_snapshot<P: AsRef<Path>>(snapshot_path: P) -> Result<Snapshot> {
     let mut snapshot_f = tokio::fs::File::open(snapshot_path).await?;
+    verify_snapshot_hash(&mut snapshot_f).await?;
+    receive_snapshot_file(snapshot_f).await
+}
+
+/// Receive the snapshot into a temporary rocksdb snapshot without checking its
+/// hash
+async fn receive_unchecked_snapshot<P: AsRef<Path>>(snapshot_path: P) -> Result<Snapshot> {
+    receive_snapshot_file(tokio::fs::File::open(snapshot_path).await?).await
+}
+
+/// Receive the opened snapshot file into a temporary rocksdb snapshot
+async fn receive_snapshot_file(mut snapshot_f: tokio::fs::File) -> Result<Snapshot> {
     let tmp_path = format!("/tmp/snapshot-{}", uuid::Uuid::new_v4());
     let mut rocks_snapshot = Snapshot::new_for_receiving(EngineType::Rocks((&tmp_path).into()))?;
     let mut buf = BytesMut::with_capacity(MAINTENANCE_SNAPSHOT_CHUNK_SIZE.numeric_cast());
@@ -31,10 +191,347 @@ pub async fn restore<P: AsRef<Path>, D: Into<PathBuf>>(
         }
         rocks_snapshot.write_all(buf.split().freeze()).await?;
     }
+    Ok(rocks_snapshot)
+}
+
+/// Check the sha256 hash following the snapshot, then rewind the file
+///
+/// A snapshot of the maintenance server is padded to pages and followed by the
+/// hash of the padded data, so the hash is found by the size of the file.
+async fn verify_snapshot_hash(snapshot_f: &mut tokio::fs::File) -> Result<()> {
+    let hash_size: u64 = Sha256::output_size().numeric_cast();
+    let size = snapshot_f.metadata().await?.len();
+    if size.overflow_rem(MIN_PAGE_SIZE) != hash_size {
+        bail!("snapshot does not end with a sha256 hash");
+    }
+    let mut hasher = Sha256::new();
+    let mut data = (&mut *snapshot_f).take(size.overflow_sub(hash_size));
+    let mut buf = BytesMut::with_capacity(MAINTENANCE_SNAPSHOT_CHUNK_SIZE.numeric_cast());
+    while read_buf(&mut data, &mut buf).await? != 0 {
+        hasher.update(buf.split());
+    }
+    let mut hash = vec![0; hash_size.numeric_cast()];
+    let _n = snapshot_f.read_exact(&mut hash).await?;
+    if hasher.finalize().as_slice() != hash.as_slice() {
+        bail!("snapshot hash mismatch, the snapshot may be corrupted");
+    }
+    let _pos = snapshot_f.seek(SeekFrom::Start(0)).await?;
+    Ok(())
+}
+
+/// Rebuild the index of the restored store and reset the state of the old
+/// cluster
+pub(crate) fn seed_new_member(db: &DB) -> Result<RestoreSummary> {
+    let index = Index::new();
+    let mut revision = 1;
+    let view = db.view();
+    let cold_view = db.cold_view();
+    for pair in DB::iter_kv_view(&view, cold_view.as_ref())? {
+        let (key, value) = pair?;
+        let rev = Revision::decode(&key);
+        // the index keeps a deletion as a revision without version
+        let (key, mod_revision, create_revision, version) = match decode_record(&value)? {
+            KvRecord::Put(kv) => (kv.key, kv.mod_revision, kv.create_revision, kv.version),
+            KvRecord::Tombstone(tombstone) => (tombstone.key, tombstone.revision, 0, 0),
+        };
+        if mod_revision != rev.revision() {
+            bail!(
+                "key-value at revision {} has a mismatched mod revision {mod_revision}",
+                rev.revision(),
+            );
+        }
+        index.restore(
+            key,
+            rev.revision(),
+            rev.sub_revision(),
+            create_revision,
+            version,
+        );
+        // key-values are sorted by revision
+        revision = rev.revision();
+    }
+    drop(cold_view);
+    drop(view);
+
+    // the log of the new cluster starts from scratch, the alarms of the old
+    // cluster are cleared along with it
+    let mut ops = vec![WriteOp::PutAppliedIndex(0)];
+    for (alarm, _) in db.get_all(ALARM_TABLE)? {
+        ops.push(WriteOp::DeleteAlarm(AlarmMember::decode(alarm.as_slice())?));
+    }
+    db.write_ops(ops)?;
 
-    let restore_rocks_engine = Engine::new(EngineType::Rocks(data_dir.into()), &XLINE_TABLES)?;
-    restore_rocks_engine
-        .apply_snapshot(rocks_snapshot, &XLINE_TABLES)
-        .await?;
+    Ok(RestoreSummary {
+        count: index.count_range(UNBOUNDED, UNBOUNDED, 0).numeric_cast(),
+        revision,
+    })
+}
+
+/// Apply an incremental backup onto the data dir restored from a base snapshot
+///
+/// The increment is a stream of length-delimited protobuf encoded `KeyValue`s
+/// in revision order, as the events of a watch on the whole keyspace, where
+/// deletions only have the key and the mod revision. Revisions already in the
+/// store are skipped, so an increment may overlap the base, but it must not
+/// leave a gap after it. Only the keyspace is covered by increments, keys
+/// attached to leases missing from the base are applied without a lease. The
+/// `key_provider` must be the one the base is restored with.
+///
+/// # Errors
+///
+/// - return an error if the increment does not follow the store
+/// - return an error if meet io errors or the file is malformed
+/// - return `ExecuteError::DbError` if meet db errors
+#[inline]
+pub fn apply_increment<P: AsRef<Path>, D: Into<PathBuf>>(
+    source: P,
+    data_dir: D,
+    key_provider: Option<Arc<dyn KeyProvider>>,
+) -> Result<RestoreSummary> {
+    let db = DB::open_with_key_provider(&EngineConfig::RocksDB(data_dir.into()), key_provider)?;
+    let reader = LengthDelimited(BufReader::new(File::open(source)?));
+    layer_kvs(&db, reader)?;
+    seed_new_member(&db)
+}
+
+/// Write the key-values of the revisions after the last revision of the store
+pub(crate) fn layer_kvs<I>(db: &DB, kvs: I) -> Result<()>
+where
+    I: IntoIterator<Item = io::Result<KeyValue>>,
+{
+    let base = db
+        .view()
+        .last(KV_TABLE)?
+        .map_or(1, |(key, _)| Revision::decode(&key).revision());
+    let mut revision = base;
+    let mut sub_revision = 0;
+    let mut ops = Vec::new();
+    for kv in kvs {
+        let mut kv = kv?;
+        if kv.mod_revision <= base {
+            continue;
+        }
+        if kv.mod_revision == revision {
+            sub_revision = sub_revision.overflow_add(1);
+        } else if kv.mod_revision == revision.overflow_add(1) {
+            db.write_ops(std::mem::take(&mut ops))?;
+            revision = kv.mod_revision;
+            sub_revision = 0;
+        } else {
+            bail!(
+                "increment does not follow the store, expect revision {}, got {}",
+                revision.overflow_add(1),
+                kv.mod_revision
+            );
+        }
+        if kv.lease != 0
+            && db
+                .get_value(LEASE_TABLE, kv.lease.encode_to_vec())?
+                .is_none()
+        {
+            kv.lease = 0;
+        }
+        ops.push(WriteOp::PutKeyValue(
+            Revision::new(revision, sub_revision),
+            kv,
+        ));
+    }
+    db.write_ops(ops)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use tempfile::TempDir;
+    use xlineapi::AlarmType;
+
+    use super::*;
+    use crate::storage::compression::decode_kv;
+
+    fn put(db: &DB, revision: i64, key: &str, version: i64) -> Result<()> {
+        db.write_op(WriteOp::PutKeyValue(
+            Revision::new(revision, 0),
+            KeyValue {
+                key: key.into(),
+                create_revision: revision.overflow_sub(version).overflow_add(1),
+                mod_revision: revision,
+                version,
+                value: b"value".to_vec(),
+                lease: 0,
+            },
+        ))?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_seed_new_member() -> Result<()> {
+        let db = DB::open(&EngineConfig::Memory)?;
+        put(&db, 2, "a", 1)?;
+        put(&db, 3, "a", 2)?;
+        put(&db, 4, "b", 1)?;
+        db.write_op(WriteOp::PutAppliedIndex(10))?;
+        db.write_ops(vec![
+            WriteOp::PutAlarm(AlarmMember::new(1, AlarmType::Nospace)),
+            WriteOp::PutAlarm(AlarmMember::new(2, AlarmType::Corrupt)),
+        ])?;
+
+        let summary = seed_new_member(&db)?;
+        assert_eq!(
+            summary,
+            RestoreSummary {
+                count: 2,
+                revision: 4
+            }
+        );
+        assert_eq!(
+            db.get_value(META_TABLE, APPLIED_INDEX_KEY)?,
+            Some(0_u64.to_le_bytes().to_vec())
+        );
+        assert!(db.get_all(ALARM_TABLE)?.is_empty());
+
+        db.write_op(WriteOp::PutKeyValue(
+            Revision::new(6, 0),
+            KeyValue {
+                key: b"c".to_vec(),
+                mod_revision: 5,
+                ..Default::default()
+            },
+        ))?;
+        assert!(seed_new_member(&db).is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_restore_on_startup_should_skip_non_empty_storage() -> Result<()> {
+        let db = DB::open(&EngineConfig::Memory)?;
+        put(&db, 2, "a", 1)?;
+        // the snapshot is never opened
+        let summary = restore_on_startup("/tmp/no_such_snapshot", &db, false).await?;
+        assert!(summary.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_layer_kvs() -> Result<()> {
+        let db = DB::open(&EngineConfig::Memory)?;
+        put(&db, 2, "a", 1)?;
+        put(&db, 3, "b", 1)?;
+        let deletion = |key: &str, mod_revision| KeyValue {
+            key: key.into(),
+            mod_revision,
+            ..Default::default()
+        };
+        let increment = [
+            KeyValue {
+                key: b"b".to_vec(),
+                create_revision: 3,
+                mod_revision: 3,
+                version: 1,
+                ..Default::default()
+            },
+            KeyValue {
+                key: b"b".to_vec(),
+                create_revision: 3,
+                mod_revision: 4,
+                version: 2,
+                ..Default::default()
+            },
+            deletion("a", 5),
+            deletion("b", 5),
+            KeyValue {
+                key: b"c".to_vec(),
+                create_revision: 6,
+                mod_revision: 6,
+                version: 1,
+                lease: 7,
+                ..Default::default()
+            },
+        ];
+        layer_kvs(&db, increment.map(Ok))?;
+
+        let stored: Vec<_> = db
+            .get_all(KV_TABLE)?
+            .into_iter()
+            .map(|(k, v)| (Revision::decode(&k), decode_kv(&v).unwrap()))
+            .map(|(rev, kv)| (rev.revision(), rev.sub_revision(), kv.key, kv.lease))
+            .collect();
+        assert_eq!(
+            stored,
+            vec![
+                (2, 0, b"a".to_vec(), 0),
+                (3, 0, b"b".to_vec(), 0),
+                (4, 0, b"b".to_vec(), 0),
+                (5, 0, b"a".to_vec(), 0),
+                (5, 1, b"b".to_vec(), 0),
+                (6, 0, b"c".to_vec(), 0),
+            ]
+        );
+        assert_eq!(
+            seed_new_member(&db)?,
+            RestoreSummary {
+                count: 1,
+                revision: 6
+            }
+        );
+
+        assert!(layer_kvs(&db, [Ok(deletion("c", 8))]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_membership() -> Result<()> {
+        let dir = TempDir::with_prefix("/tmp/test_write_membership")?;
+        let initial_cluster = HashMap::from([
+            ("node1".to_owned(), vec!["http://10.0.0.1:2380".to_owned()]),
+            ("node2".to_owned(), vec!["http://10.0.0.2:2380".to_owned()]),
+        ]);
+        let client_urls = vec!["http://10.0.0.1:2379".to_owned()];
+        let stranger = Membership::new(
+            "node3".to_owned(),
+            initial_cluster.clone(),
+            client_urls.clone(),
+        );
+        assert!(write_membership(dir.path(), &stranger).is_err());
+
+        let membership = Membership::new("node1".to_owned(), initial_cluster, client_urls);
+        write_membership(dir.path(), &membership)?;
+        {
+            let storage = CurpDB::<Command>::open(&EngineConfig::RocksDB(dir.path().into()))?;
+            let cluster_info = storage.recover_cluster_info()?.unwrap();
+            let member = cluster_info.self_member();
+            assert_eq!(member.name, "node1");
+            assert_eq!(member.client_urls, membership.client_urls);
+            assert_eq!(cluster_info.all_members_vec().len(), 2);
+        }
+        // the membership of another cluster is never overwritten
+        assert!(write_membership(dir.path(), &membership).is_err());
+        dir.close()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_verify_snapshot_hash() -> Result<()> {
+        let dir = TempDir::with_prefix("/tmp/test_verify_snapshot_hash")?;
+        let path = dir.path().join("snapshot");
+        let path = path.as_path();
+        let data = vec![1; MIN_PAGE_SIZE.numeric_cast::<usize>().overflow_mul(3)];
+        let hash = Sha256::digest(&data);
+        let verify = |content: Vec<u8>| async move {
+            tokio::fs::write(path, content).await?;
+            let mut snapshot_f = tokio::fs::File::open(path).await?;
+            verify_snapshot_hash(&mut snapshot_f).await?;
+            let mut rest = Vec::new();
+            let _n = snapshot_f.read_to_end(&mut rest).await?;
+            Ok::<_, anyhow::Error>(rest.len())
+        };
+
+        let snapshot = [data.as_slice(), hash.as_slice()].concat();
+        assert_eq!(verify(snapshot.clone()).await?, snapshot.len());
+        let mut corrupted = snapshot.clone();
+        corrupted[1] = 0;
+        assert!(verify(corrupted).await.is_err());
+        assert!(verify(data).await.is_err());
+        dir.close()?;
+        Ok(())
+    }
+}
@@ -38,6 +38,13 @@ pub(crate) trait RevisionCheck {
         compacted_revision: i64,
         current_revision: i64,
     ) -> Result<(), ExecuteError>;
+
+    /// check if the request reads or compacts a compacted revision
+    ///
+    /// The current revision of a node may lag behind the cluster, so only the
+    /// compacted revision is checked before a request is proposed, the future
+    /// revision is checked when the request is executed.
+    fn check_compacted(self, compacted_revision: i64) -> Result<(), ExecuteError>;
 }
 
 impl<'a, T> RevisionCheck for &'a T
@@ -98,4 +105,8 @@ where
             }
         }
     }
+
+    fn check_compacted(self, compacted_revision: i64) -> Result<(), ExecuteError> {
+        self.check_revision(compacted_revision, i64::MAX)
+    }
 }
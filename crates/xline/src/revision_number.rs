@@ -15,14 +15,15 @@ impl RevisionNumberGenerator {
         }
     }
 
-    /// Get the current revision number
+    /// Get the current revision number, the writes committed before the
+    /// revision are visible after getting it
     pub(crate) fn get(&self) -> i64 {
-        self.current.load(Ordering::Relaxed)
+        self.current.load(Ordering::Acquire)
     }
 
     /// Set the revision number
     pub(crate) fn set(&self, rev: i64) {
-        self.current.store(rev, Ordering::Relaxed);
+        self.current.store(rev, Ordering::Release);
     }
 
     /// Gets a temporary state
@@ -63,6 +64,6 @@ impl RevisionNumberGeneratorState<'_> {
     /// Commit the revision number
     pub(crate) fn commit(&self) {
         self.current
-            .store(self.next.load(Ordering::Relaxed), Ordering::Relaxed);
+            .store(self.next.load(Ordering::Relaxed), Ordering::Release);
     }
 }
@@ -8,6 +8,7 @@ use xlineapi::{
     request_validation::RequestValidator,
 };
 
+use super::command::QuotaGuard;
 use crate::{
     rpc::{
         Auth, AuthDisableRequest, AuthDisableResponse, AuthEnableRequest, AuthEnableResponse,
@@ -31,6 +32,8 @@ pub(crate) struct AuthServer {
     client: Arc<CurpClient>,
     /// Auth Store
     auth_store: Arc<AuthStore>,
+    /// Guards the storage before the requests are proposed
+    quota_guard: QuotaGuard,
 }
 
 /// Get token from metadata
@@ -43,8 +46,16 @@ pub(crate) fn get_token(metadata: &MetadataMap) -> Option<String> {
 
 impl AuthServer {
     /// New `AuthServer`
-    pub(crate) fn new(client: Arc<CurpClient>, auth_store: Arc<AuthStore>) -> Self {
-        Self { client, auth_store }
+    pub(crate) fn new(
+        client: Arc<CurpClient>,
+        auth_store: Arc<AuthStore>,
+        quota_guard: QuotaGuard,
+    ) -> Self {
+        Self {
+            client,
+            auth_store,
+            quota_guard,
+        }
     }
 
     /// Propose request and get result with fast/slow path
@@ -58,6 +69,7 @@ impl AuthServer {
         let auth_info = self.auth_store.try_get_auth_info_from_request(&request)?;
         let request = request.into_inner().into();
         let cmd = Command::new_with_auth_info(request, auth_info);
+        self.quota_guard.check(&cmd)?;
         let res = self.client.propose(&cmd, None, false).await??;
         Ok(res)
     }
@@ -0,0 +1,569 @@
+use std::{
+    fmt::Debug,
+    io, mem,
+    path::PathBuf,
+    pin::Pin,
+    sync::Arc,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use clippy_utilities::{NumericCast, OverflowArithmetic};
+use curp::{members::ClusterInfo, server::RawCurp};
+use engine::ViewApi;
+use futures::{stream, Stream, StreamExt};
+use object_store::{aws::AmazonS3Builder, path::Path as ObjectPath, ObjectStore, WriteMultipart};
+use tokio::{fs, io::AsyncWriteExt, time::sleep};
+use tracing::{info, warn};
+use utils::{
+    config::{BackupConfig, S3BackupConfig},
+    table_names::{KV_TABLE, META_TABLE},
+    task_manager::Listener,
+};
+use xlineapi::{
+    command::{Command, CurpClient},
+    execute_error::ExecuteError,
+};
+
+use super::maintenance::snapshot_stream;
+use crate::{
+    header_gen::HeaderGenerator,
+    state::State,
+    storage::{
+        compression,
+        db::{DB, SCHEDULED_COMPACT_REVISION},
+        Revision,
+    },
+};
+
+/// Prefix of the names of the backups
+const BACKUP_PREFIX: &str = "xline-";
+/// Suffix of the names of the full backups
+const SNAPSHOT_SUFFIX: &str = ".snapshot";
+/// Suffix of the names of the incremental backups
+const INCREMENT_SUFFIX: &str = ".increment";
+/// Size of the chunks of the incremental backups
+const INCREMENT_CHUNK_SIZE: usize = 64 * 1024;
+/// Number of the parts of a backup uploaded to the object storage concurrently
+const MAX_CONCURRENT_PARTS: usize = 8;
+
+/// The chunks of a backup
+pub(crate) type BackupStream<'a> = Pin<Box<dyn Stream<Item = io::Result<Vec<u8>>> + Send + 'a>>;
+
+/// The store of the backups, e.g. a bucket of an object storage
+#[async_trait]
+pub(crate) trait BackupStore: Send + Sync + Debug {
+    /// Upload a backup, a failed upload must not leave a partial backup
+    async fn put(&self, name: &str, data: BackupStream<'_>) -> io::Result<()>;
+
+    /// List the names of all the backups
+    async fn list(&self) -> io::Result<Vec<String>>;
+
+    /// Remove a backup
+    async fn remove(&self, name: &str) -> io::Result<()>;
+}
+
+/// The store of the backups in a directory, the backups are written to a
+/// temporary file first and renamed when finished
+#[derive(Debug)]
+pub(crate) struct DirBackupStore {
+    /// The directory of the backups
+    dir: PathBuf,
+}
+
+impl DirBackupStore {
+    /// New `DirBackupStore`
+    pub(crate) fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+}
+
+#[async_trait]
+impl BackupStore for DirBackupStore {
+    async fn put(&self, name: &str, mut data: BackupStream<'_>) -> io::Result<()> {
+        fs::create_dir_all(&self.dir).await?;
+        let tmp_path = self.dir.join(format!("{name}.tmp"));
+        let result = async {
+            let mut file = fs::File::create(&tmp_path).await?;
+            while let Some(chunk) = data.next().await {
+                file.write_all(&chunk?).await?;
+            }
+            file.sync_all().await
+        }
+        .await;
+        if let Err(e) = result {
+            let _ignore = fs::remove_file(&tmp_path).await;
+            return Err(e);
+        }
+        fs::rename(tmp_path, self.dir.join(name)).await
+    }
+
+    async fn list(&self) -> io::Result<Vec<String>> {
+        let mut names = Vec::new();
+        let mut entries = match fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(names),
+            Err(e) => return Err(e),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            if let Ok(name) = entry.file_name().into_string() {
+                names.push(name);
+            }
+        }
+        Ok(names)
+    }
+
+    async fn remove(&self, name: &str) -> io::Result<()> {
+        fs::remove_file(self.dir.join(name)).await
+    }
+}
+
+/// The store of the backups in a bucket of an S3-compatible object storage, the
+/// backups are uploaded in parts and the upload is aborted on failure
+#[derive(Debug)]
+pub(crate) struct ObjectBackupStore {
+    /// The bucket of the backups
+    bucket: Box<dyn ObjectStore>,
+    /// The prefix of the paths of the backups in the bucket
+    prefix: ObjectPath,
+}
+
+impl ObjectBackupStore {
+    /// New `ObjectBackupStore` of the configured bucket
+    pub(crate) fn new(config: &S3BackupConfig) -> io::Result<Self> {
+        let mut builder = AmazonS3Builder::from_env()
+            .with_bucket_name(config.bucket())
+            .with_region(config.region());
+        if let Some(ref endpoint) = *config.endpoint() {
+            builder = builder
+                .with_endpoint(endpoint)
+                .with_allow_http(endpoint.starts_with("http://"));
+        }
+        let bucket = builder.build().map_err(object_store_err)?;
+        Ok(Self::with_bucket(Box::new(bucket), config.prefix()))
+    }
+
+    /// New `ObjectBackupStore` of a bucket
+    fn with_bucket(bucket: Box<dyn ObjectStore>, prefix: &str) -> Self {
+        Self {
+            bucket,
+            prefix: ObjectPath::from(prefix),
+        }
+    }
+}
+
+#[async_trait]
+impl BackupStore for ObjectBackupStore {
+    async fn put(&self, name: &str, mut data: BackupStream<'_>) -> io::Result<()> {
+        let upload = self
+            .bucket
+            .put_multipart(&self.prefix.child(name))
+            .await
+            .map_err(object_store_err)?;
+        let mut writer = WriteMultipart::new(upload);
+        let result = async {
+            while let Some(chunk) = data.next().await {
+                let chunk = chunk?;
+                writer
+                    .wait_for_capacity(MAX_CONCURRENT_PARTS)
+                    .await
+                    .map_err(object_store_err)?;
+                writer.write(&chunk);
+            }
+            Ok::<_, io::Error>(())
+        }
+        .await;
+        if let Err(e) = result {
+            let _ignore = writer.abort().await;
+            return Err(e);
+        }
+        let _result = writer.finish().await.map_err(object_store_err)?;
+        Ok(())
+    }
+
+    async fn list(&self) -> io::Result<Vec<String>> {
+        let mut names = Vec::new();
+        let mut objects = self.bucket.list(Some(&self.prefix));
+        while let Some(object) = objects.next().await {
+            if let Some(name) = object.map_err(object_store_err)?.location.filename() {
+                names.push(name.to_owned());
+            }
+        }
+        Ok(names)
+    }
+
+    async fn remove(&self, name: &str) -> io::Result<()> {
+        self.bucket
+            .delete(&self.prefix.child(name))
+            .await
+            .map_err(object_store_err)
+    }
+}
+
+/// Convert an error of the object storage to an io error
+fn object_store_err(e: object_store::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e)
+}
+
+/// Convert an error of the storage to an io error
+fn execute_err(e: ExecuteError) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e)
+}
+
+/// The progress of the backups taken by the leader
+#[derive(Debug, Default)]
+pub(crate) struct BackupProgress {
+    /// The last revision of the previous backup, the next backup is a full
+    /// one if it's `None`
+    last_revision: Option<i64>,
+    /// Number of the incremental backups since the last full backup
+    increments: usize,
+}
+
+/// Take a backup of the storage, then remove the oldest full backups beyond
+/// the retention, returns the name of the backup
+///
+/// A full backup has the same content as a snapshot of the maintenance server,
+/// so it can be restored like a snapshot. An incremental backup has the
+/// revisions after the previous backup as length-delimited `KeyValue`s, the
+/// same as the ones saved by `xlinectl snapshot save --since`, and is applied
+/// onto the restored full backup and the incremental backups before it. The
+/// backups are named by the time they are taken, from the oldest to the newest.
+///
+/// A full backup is taken instead of an incremental one after the configured
+/// number of increments, or if a compaction may have removed some of the
+/// revisions after the previous backup.
+pub(crate) async fn backup(
+    store: &dyn BackupStore,
+    header_gen: &HeaderGenerator,
+    db: &DB,
+    config: &BackupConfig,
+    progress: &mut BackupProgress,
+) -> io::Result<String> {
+    let since = progress
+        .last_revision
+        .filter(|_| progress.increments < *config.increments());
+    if let Some(since) = since {
+        if let Some((chunks, revision)) = increment(db, since).map_err(execute_err)? {
+            let name = backup_name(INCREMENT_SUFFIX);
+            store
+                .put(&name, Box::pin(stream::iter(chunks.into_iter().map(Ok))))
+                .await?;
+            progress.last_revision = Some(revision);
+            progress.increments = progress.increments.overflow_add(1);
+            return Ok(name);
+        }
+        info!("revisions after {since} may have been compacted, take a full backup instead");
+    }
+
+    // the revisions in the snapshot are no less than the last revision before it,
+    // the ones in both the snapshot and the next increment are skipped by the restore
+    let revision = last_revision(&db.view()).map_err(execute_err)?;
+    let name = backup_name(SNAPSHOT_SUFFIX);
+    let status_to_io =
+        |status: tonic::Status| io::Error::new(io::ErrorKind::Other, status.message());
+    let stream = snapshot_stream(header_gen, db)
+        .map_err(status_to_io)?
+        .map(move |res| res.map(|resp| resp.blob).map_err(status_to_io));
+    store.put(&name, Box::pin(stream)).await?;
+    progress.last_revision = Some(revision);
+    progress.increments = 0;
+
+    let mut backups: Vec<_> = store
+        .list()
+        .await?
+        .into_iter()
+        .filter(|n| {
+            n.starts_with(BACKUP_PREFIX)
+                && (n.ends_with(SNAPSHOT_SUFFIX) || n.ends_with(INCREMENT_SUFFIX))
+        })
+        .collect();
+    backups.sort_unstable();
+    // the new backup is always kept, the increments are removed with the full
+    // backup they are applied onto
+    let expired = retention_start(&backups, (*config.retention()).max(1));
+    for old in backups.iter().take(expired) {
+        store.remove(old).await?;
+    }
+    Ok(name)
+}
+
+/// Get the name of a new backup
+fn backup_name(suffix: &str) -> String {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis());
+    format!("{BACKUP_PREFIX}{timestamp:020}{suffix}")
+}
+
+/// Get the index of the oldest backup to keep in the sorted backups, which is
+/// the oldest of the latest `retention` full backups
+fn retention_start(backups: &[String], retention: usize) -> usize {
+    backups
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|&(_, name)| name.ends_with(SNAPSHOT_SUFFIX))
+        .take(retention)
+        .last()
+        .map_or(0, |(i, _)| i)
+}
+
+/// Get the last revision of the kv table of a view
+fn last_revision(view: &impl ViewApi) -> Result<i64, ExecuteError> {
+    let last = view
+        .last(KV_TABLE)
+        .map_err(|e| ExecuteError::DbError(format!("Failed to get the last revision: {e}")))?;
+    Ok(last.map_or(0, |(key, _)| Revision::decode(&key).revision()))
+}
+
+/// Encode the revisions after `since` to chunks of length-delimited
+/// `KeyValue`s in the order of the revisions, returns the chunks and the last
+/// revision in them
+///
+/// The revisions are read from a view of both tiers, returns `None` if a
+/// compaction after `since` is scheduled in the view, which may have removed
+/// some of the revisions.
+fn increment(db: &DB, since: i64) -> Result<Option<(Vec<Vec<u8>>, i64)>, ExecuteError> {
+    let view = db.view();
+    let cold_view = db.cold_view();
+    let scheduled = view
+        .get(META_TABLE, SCHEDULED_COMPACT_REVISION)
+        .map_err(|e| ExecuteError::DbError(format!("Failed to get compact revision: {e}")))?;
+    if let Some(bytes) = scheduled {
+        let bytes = bytes
+            .try_into()
+            .map_err(|e| ExecuteError::DbError(format!("cannot decode compact revision: {e:?}")))?;
+        if i64::from_le_bytes(bytes) > since {
+            return Ok(None);
+        }
+    }
+    let lower = Revision::new(since.overflow_add(1), 0);
+    let mut revision = since;
+    let mut chunks = Vec::new();
+    let mut chunk = Vec::new();
+    for pair in DB::iter_kv_view(&view, cold_view.as_ref())? {
+        let (key, value) = pair?;
+        let rev = Revision::decode(&key);
+        if rev < lower {
+            continue;
+        }
+        let kv = compression::decompress(&value)?;
+        prost::encoding::encode_varint(kv.len().numeric_cast(), &mut chunk);
+        chunk.extend_from_slice(&kv);
+        revision = rev.revision();
+        if chunk.len() >= INCREMENT_CHUNK_SIZE {
+            chunks.push(mem::take(&mut chunk));
+        }
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+    Ok(Some((chunks, revision)))
+}
+
+/// Back up the storage to the store periodically, only the leader takes the
+/// backups, and its first backup is a full one
+#[allow(clippy::arithmetic_side_effects, clippy::ignored_unit_patterns)] // Introduced by tokio::select!
+pub(crate) async fn backup_task(
+    store: Arc<dyn BackupStore>,
+    header_gen: Arc<HeaderGenerator>,
+    db: Arc<DB>,
+    cluster_info: Arc<ClusterInfo>,
+    raw_curp: Arc<RawCurp<Command, State<Arc<CurpClient>>>>,
+    config: BackupConfig,
+    shutdown_listener: Listener,
+) {
+    let mut progress = BackupProgress::default();
+    loop {
+        tokio::select! {
+            _ = shutdown_listener.wait() => return,
+            _ = sleep(*config.interval()) => {}
+        }
+        if raw_curp.leader().0 != Some(cluster_info.self_id()) {
+            progress = BackupProgress::default();
+            continue;
+        }
+        let start = Instant::now();
+        match backup(store.as_ref(), &header_gen, &db, &config, &mut progress).await {
+            Ok(name) => info!("backup {name} finished in {:?}", start.elapsed()),
+            Err(e) => warn!("backup failed: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use object_store::memory::InMemory;
+    use tempfile::TempDir;
+    use test_macros::abort_on_panic;
+    use utils::config::{BackupTarget, EngineConfig};
+
+    use super::*;
+    use crate::{
+        bulk_import::LengthDelimited,
+        rpc::KeyValue,
+        storage::{db::WriteOp, storage_api::XlineStorageOps},
+    };
+
+    fn put(db: &DB, revision: i64, key: &str) {
+        let kv = KeyValue {
+            key: key.into(),
+            value: b"value".to_vec(),
+            create_revision: revision,
+            mod_revision: revision,
+            version: 1,
+            ..Default::default()
+        };
+        db.write_ops(vec![WriteOp::PutKeyValue(Revision::new(revision, 0), kv)])
+            .unwrap();
+    }
+
+    async fn sorted_names(store: &dyn BackupStore) -> Vec<String> {
+        let mut names = store.list().await.unwrap();
+        names.sort_unstable();
+        names
+    }
+
+    #[tokio::test]
+    #[abort_on_panic]
+    async fn backups_beyond_retention_should_be_removed() {
+        let dir = TempDir::with_prefix("/tmp/test_backup").unwrap();
+        let db = DB::open(&EngineConfig::RocksDB(dir.path().join("db"))).unwrap();
+        let header_gen = HeaderGenerator::new(0, 0);
+        let backup_dir = dir.path().join("backup");
+        let store = DirBackupStore::new(backup_dir.clone());
+        let config = BackupConfig::new(
+            BackupTarget::Dir(backup_dir.clone()),
+            Duration::from_secs(1),
+            2,
+        );
+        let mut progress = BackupProgress::default();
+        let mut names = Vec::new();
+        for _ in 0..3 {
+            names.push(
+                backup(&store, &header_gen, &db, &config, &mut progress)
+                    .await
+                    .unwrap(),
+            );
+            tokio::time::sleep(Duration::from_millis(2)).await;
+        }
+        fs::write(backup_dir.join("other"), b"not a backup")
+            .await
+            .unwrap();
+
+        let mut expected = vec!["other".to_owned()];
+        expected.extend_from_slice(&names[1..]);
+        assert_eq!(sorted_names(&store).await, expected);
+        // a snapshot padded to pages, followed by its checksum
+        let data = fs::read(backup_dir.join(&names[2])).await.unwrap();
+        assert_eq!(data.len() % 512, 32);
+        dir.close().unwrap();
+    }
+
+    #[tokio::test]
+    #[abort_on_panic]
+    async fn increments_should_contain_the_revisions_after_the_previous_backup() {
+        let dir = TempDir::with_prefix("/tmp/test_incremental_backup").unwrap();
+        let db = DB::open(&EngineConfig::RocksDB(dir.path().join("db"))).unwrap();
+        let header_gen = HeaderGenerator::new(0, 0);
+        let backup_dir = dir.path().join("backup");
+        let store = DirBackupStore::new(backup_dir.clone());
+        let config = BackupConfig::new(
+            BackupTarget::Dir(backup_dir.clone()),
+            Duration::from_secs(1),
+            1,
+        )
+        .with_increments(1);
+        let mut progress = BackupProgress::default();
+        put(&db, 2, "a");
+        let full = backup(&store, &header_gen, &db, &config, &mut progress)
+            .await
+            .unwrap();
+        assert!(full.ends_with(SNAPSHOT_SUFFIX));
+        tokio::time::sleep(Duration::from_millis(2)).await;
+
+        put(&db, 3, "b");
+        put(&db, 4, "c");
+        let inc = backup(&store, &header_gen, &db, &config, &mut progress)
+            .await
+            .unwrap();
+        assert!(inc.ends_with(INCREMENT_SUFFIX));
+        let data = fs::read(backup_dir.join(&inc)).await.unwrap();
+        let kvs: Vec<_> = LengthDelimited(data.as_slice())
+            .map(|kv| kv.unwrap())
+            .collect();
+        assert_eq!(
+            kvs.iter().map(|kv| kv.mod_revision).collect::<Vec<_>>(),
+            [3, 4]
+        );
+        assert_eq!(kvs[1].key, b"c");
+        tokio::time::sleep(Duration::from_millis(2)).await;
+
+        // the next one is a full backup, which removes the previous chain
+        let next = backup(&store, &header_gen, &db, &config, &mut progress)
+            .await
+            .unwrap();
+        assert!(next.ends_with(SNAPSHOT_SUFFIX));
+        assert_eq!(sorted_names(&store).await, [next]);
+        dir.close().unwrap();
+    }
+
+    #[tokio::test]
+    #[abort_on_panic]
+    async fn compaction_after_the_previous_backup_should_take_a_full_backup() {
+        let dir = TempDir::with_prefix("/tmp/test_backup_compaction").unwrap();
+        let db = DB::open(&EngineConfig::RocksDB(dir.path().join("db"))).unwrap();
+        put(&db, 2, "a");
+        assert!(increment(&db, 1).unwrap().is_some());
+        db.write_ops(vec![WriteOp::PutScheduledCompactRevision(2)])
+            .unwrap();
+        assert!(increment(&db, 1).unwrap().is_none());
+        let (chunks, revision) = increment(&db, 2).unwrap().unwrap();
+        assert!(chunks.is_empty());
+        assert_eq!(revision, 2);
+        dir.close().unwrap();
+    }
+
+    #[test]
+    fn retention_should_keep_the_increments_after_the_kept_full_backups() {
+        let backups: Vec<_> = [
+            "xline-1.increment",
+            "xline-2.snapshot",
+            "xline-3.increment",
+            "xline-4.snapshot",
+            "xline-5.increment",
+            "xline-6.snapshot",
+        ]
+        .map(str::to_owned)
+        .into();
+        assert_eq!(retention_start(&backups, 1), 5);
+        assert_eq!(retention_start(&backups, 2), 3);
+        assert_eq!(retention_start(&backups, 5), 1);
+    }
+
+    #[tokio::test]
+    #[abort_on_panic]
+    async fn failed_uploads_should_not_leave_objects() {
+        let store = ObjectBackupStore::with_bucket(Box::new(InMemory::new()), "backup/xline");
+        let chunks = vec![Ok(vec![1; 16]), Ok(vec![2; 16])];
+        store
+            .put("xline-1.snapshot", Box::pin(stream::iter(chunks)))
+            .await
+            .unwrap();
+        let chunks = vec![
+            Ok(vec![1; 16]),
+            Err(io::Error::new(io::ErrorKind::Other, "broken")),
+        ];
+        assert!(store
+            .put("xline-2.snapshot", Box::pin(stream::iter(chunks)))
+            .await
+            .is_err());
+        assert_eq!(sorted_names(&store).await, ["xline-1.snapshot"]);
+
+        store.remove("xline-1.snapshot").await.unwrap();
+        assert!(sorted_names(&store).await.is_empty());
+    }
+}
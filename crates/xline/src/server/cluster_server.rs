@@ -1,10 +1,11 @@
-use std::sync::Arc;
+use std::{collections::HashSet, sync::Arc};
 
 use curp::{
     members::ClusterInfo,
     rpc::{
         ConfChange,
         ConfChangeType::{Add, AddLearner, Promote, Remove, Update},
+        Member as CurpMember,
     },
 };
 use itertools::Itertools;
@@ -48,6 +49,46 @@ impl ClusterServer {
             })
             .collect())
     }
+
+    /// Check the peer urls of the member `id`, or of a new member if `id` is
+    /// `None`, against the current members of the cluster
+    async fn check_peer_urls(&self, id: Option<u64>, peer_urls: &[String]) -> Result<(), Status> {
+        let members = self.client.fetch_cluster(true).await?.members;
+        check_peer_urls(&members, id, peer_urls)
+    }
+}
+
+/// Peer urls must be non-empty, distinct, and not used by other members
+fn check_peer_urls(
+    members: &[CurpMember],
+    id: Option<u64>,
+    peer_urls: &[String],
+) -> Result<(), Status> {
+    let mut urls = HashSet::new();
+    if peer_urls.is_empty()
+        || !peer_urls
+            .iter()
+            .all(|url| !url.is_empty() && urls.insert(url))
+    {
+        return Err(Status::invalid_argument(
+            "etcdserver: given member URLs are invalid",
+        ));
+    }
+    if let Some(id) = id {
+        if members.iter().all(|m| m.id != id) {
+            return Err(Status::not_found("etcdserver: member not found"));
+        }
+    }
+    if members
+        .iter()
+        .filter(|m| Some(m.id) != id)
+        .any(|m| m.peer_urls.iter().any(|url| urls.contains(url)))
+    {
+        return Err(Status::failed_precondition(
+            "etcdserver: Peer URLs already exists",
+        ));
+    }
+    Ok(())
 }
 
 #[tonic::async_trait]
@@ -63,6 +104,7 @@ impl Cluster for ClusterServer {
             i32::from(Add)
         };
         let peer_url_ls = req.peer_ur_ls.into_iter().sorted().collect_vec();
+        self.check_peer_urls(None, &peer_url_ls).await?;
         // calculate node id based on addresses and current timestamp
         let node_id = ClusterInfo::calculate_member_id(peer_url_ls.clone(), "", Some(timestamp()));
         let members = self
@@ -104,6 +146,7 @@ impl Cluster for ClusterServer {
         request: Request<MemberUpdateRequest>,
     ) -> Result<Response<MemberUpdateResponse>, Status> {
         let req = request.into_inner();
+        self.check_peer_urls(Some(req.id), &req.peer_ur_ls).await?;
         let members = self
             .propose_conf_change(vec![ConfChange {
                 change_type: i32::from(Update),
@@ -160,3 +203,43 @@ impl Cluster for ClusterServer {
         Ok(Response::new(resp))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use tonic::Code;
+
+    use super::*;
+
+    fn member(id: u64, peer_url: &str) -> CurpMember {
+        CurpMember::new(id, format!("node{id}"), [peer_url.to_owned()], [], false)
+    }
+
+    #[test]
+    fn test_check_peer_urls() {
+        let members = [member(1, "http://a:2380"), member(2, "http://b:2380")];
+        let urls = |urls: &[&str]| urls.iter().map(|&url| url.to_owned()).collect_vec();
+        let code = |id, peer_urls: &[&str]| {
+            check_peer_urls(&members, id, &urls(peer_urls)).map_err(|e| e.code())
+        };
+
+        assert_eq!(code(None, &["http://c:2380"]), Ok(()));
+        assert_eq!(code(None, &[]), Err(Code::InvalidArgument));
+        assert_eq!(code(None, &[""]), Err(Code::InvalidArgument));
+        assert_eq!(
+            code(None, &["http://c:2380", "http://c:2380"]),
+            Err(Code::InvalidArgument)
+        );
+        assert_eq!(
+            code(None, &["http://a:2380"]),
+            Err(Code::FailedPrecondition)
+        );
+
+        // a member may keep its own urls
+        assert_eq!(code(Some(1), &["http://a:2380", "http://c:2380"]), Ok(()));
+        assert_eq!(
+            code(Some(1), &["http://b:2380"]),
+            Err(Code::FailedPrecondition)
+        );
+        assert_eq!(code(Some(3), &["http://c:2380"]), Err(Code::NotFound));
+    }
+}
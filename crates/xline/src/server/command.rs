@@ -1,4 +1,11 @@
-use std::{fmt::Debug, iter, sync::Arc};
+use std::{
+    fmt::Debug,
+    iter,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
 use clippy_utilities::OverflowArithmetic;
 use curp::{
@@ -13,11 +20,13 @@ use engine::{Snapshot, TransactionApi};
 use event_listener::Event;
 use parking_lot::RwLock;
 use tracing::warn;
-use utils::{barrier::IdBarrier, table_names::META_TABLE};
+use utils::barrier::IdBarrier;
 use xlineapi::{
     classifier::RequestClassifier,
     command::{Command, CurpClient, SyncResponse},
     execute_error::ExecuteError,
+    key_util,
+    request_validation::{SizeLimits, SizeValidator, ValidationError},
     AlarmAction, AlarmRequest, AlarmType,
 };
 
@@ -35,11 +44,6 @@ use crate::{
 /// Key of applied index
 pub(crate) const APPLIED_INDEX_KEY: &str = "applied_index";
 
-/// Range start and end to get all keys
-const UNBOUNDED: &[u8] = &[0_u8];
-/// Range end to get one key
-const ONE_KEY: &[u8] = &[];
-
 /// Type of `KeyRange`
 pub(crate) enum RangeType {
     /// `KeyRange` contains only one key
@@ -54,9 +58,9 @@ impl RangeType {
     /// Get `RangeType` by given `key` and `range_end`
     #[inline]
     pub(crate) fn get_range_type(key: &[u8], range_end: &[u8]) -> Self {
-        if range_end == ONE_KEY {
+        if key_util::is_single_key(range_end) {
             RangeType::OneKey
-        } else if key == UNBOUNDED && range_end == UNBOUNDED {
+        } else if key_util::is_whole_keyspace(key, range_end) {
             RangeType::AllKeys
         } else {
             RangeType::Range
@@ -85,6 +89,10 @@ pub(crate) struct CommandExecutor {
     quota_checker: Arc<dyn QuotaChecker>,
     /// Alarmer
     alarmer: RwLock<Option<Alarmer>>,
+    /// Whether the read-only storage is reported
+    read_only_reported: AtomicBool,
+    /// Size limits of keys and values
+    size_limits: SizeLimits,
 }
 
 /// Quota checker
@@ -212,6 +220,56 @@ impl Alarmer {
         let _ig = self.client.propose(&cmd, None, true).await?;
         Ok(())
     }
+
+    /// Activate the alarm of this node in background
+    fn activate(&self, alarm: AlarmType) {
+        let alarmer = self.clone();
+        let _ig = tokio::spawn(async move {
+            if let Err(e) = alarmer.alarm(AlarmAction::Activate, alarm).await {
+                warn!("{} propose alarm failed: {:?}", alarmer.id, e);
+            }
+        });
+    }
+}
+
+/// Guards the quota of the storage before the requests are proposed
+///
+/// Like the quota of etcd, a request that takes more space is rejected with
+/// `ExecuteError::Nospace` if the quota would be exceeded, and the `NOSPACE`
+/// alarm is activated, so that the cluster only serves the reads, deletes and
+/// compactions until the alarm is deactivated. No request other than the reads
+/// is proposed if the storage of this node is read-only.
+#[derive(Debug, Clone)]
+pub(crate) struct QuotaGuard {
+    /// Quota checker
+    quota_checker: Arc<dyn QuotaChecker>,
+    /// persistent storage
+    db: Arc<DB>,
+    /// Alarmer
+    alarmer: Alarmer,
+}
+
+impl QuotaGuard {
+    /// Create a new `QuotaGuard`
+    pub(crate) fn new(quota_checker: Arc<dyn QuotaChecker>, db: Arc<DB>, alarmer: Alarmer) -> Self {
+        Self {
+            quota_checker,
+            db,
+            alarmer,
+        }
+    }
+
+    /// Check if there is enough quota to propose the command
+    pub(crate) fn check(&self, cmd: &Command) -> Result<(), ExecuteError> {
+        if !cmd.request().is_read_only() {
+            self.db.check_writable()?;
+        }
+        if self.quota_checker.check(cmd) {
+            return Ok(());
+        }
+        self.alarmer.activate(AlarmType::Nospace);
+        Err(ExecuteError::Nospace)
+    }
 }
 
 impl CommandExecutor {
@@ -226,6 +284,7 @@ impl CommandExecutor {
         id_barrier: Arc<IdBarrier<InflightId>>,
         compact_events: Arc<DashMap<u64, Arc<Event>>>,
         quota: u64,
+        size_limits: SizeLimits,
     ) -> Self {
         let alarmer = RwLock::new(None);
         let quota_checker = Arc::new(CommandQuotaChecker::new(quota, Arc::clone(&db)));
@@ -239,6 +298,8 @@ impl CommandExecutor {
             compact_events,
             quota_checker,
             alarmer,
+            read_only_reported: AtomicBool::new(false),
+            size_limits,
         }
     }
 
@@ -247,27 +308,84 @@ impl CommandExecutor {
         *self.alarmer.write() = Some(alarmer);
     }
 
+    /// Get the quota checker
+    pub(crate) fn quota_checker(&self) -> Arc<dyn QuotaChecker> {
+        Arc::clone(&self.quota_checker)
+    }
+
     /// Check if the alarm is activated
     fn check_alarm(&self, cmd: &Command) -> Result<(), ExecuteError> {
         #[allow(clippy::wildcard_enum_match_arm)]
         match *cmd.request() {
-            RequestWrapper::PutRequest(_)
-            | RequestWrapper::TxnRequest(_)
-            | RequestWrapper::LeaseGrantRequest(_) => match self.alarm_storage.current_alarm() {
-                AlarmType::Corrupt => Err(ExecuteError::DbError("Corrupt".to_owned())),
-                AlarmType::Nospace => Err(ExecuteError::Nospace),
-                AlarmType::None => Ok(()),
-            },
-
-            RequestWrapper::RangeRequest(_)
+            RequestWrapper::PutRequest(_) | RequestWrapper::LeaseGrantRequest(_) => {
+                self.check_write_alarm()
+            }
+            // a txn without puts does not take more space, like a delete range
+            RequestWrapper::TxnRequest(ref req) if req.has_put() => self.check_write_alarm(),
+
+            RequestWrapper::TxnRequest(_)
+            | RequestWrapper::RangeRequest(_)
             | RequestWrapper::DeleteRangeRequest(_)
             | RequestWrapper::LeaseRevokeRequest(_)
-            | RequestWrapper::CompactionRequest(_) => match self.alarm_storage.current_alarm() {
-                AlarmType::Corrupt => Err(ExecuteError::DbError("Corrupt".to_owned())),
-                AlarmType::Nospace | AlarmType::None => Ok(()),
-            },
+            | RequestWrapper::CompactionRequest(_) => self.alarm_storage.check_corrupt(),
+
+            _ => Ok(()),
+        }
+    }
 
+    /// Check the size of the keys and values written by the request, so that
+    /// the proposals skipping the validation of the kv server are rejected
+    /// before they are committed
+    fn check_size(&self, wrapper: &RequestWrapper) -> Result<(), ExecuteError> {
+        #[allow(clippy::wildcard_enum_match_arm)]
+        let result = match *wrapper {
+            RequestWrapper::PutRequest(ref req) => req.check_size(self.size_limits),
+            RequestWrapper::TxnRequest(ref req) => req.check_size(self.size_limits),
             _ => Ok(()),
+        };
+        #[allow(clippy::wildcard_enum_match_arm)] // only the size errors are checked
+        result.map_err(|e| match e {
+            ValidationError::KeyTooLarge => ExecuteError::KeyTooLarge,
+            _ => ExecuteError::RequestTooLarge,
+        })
+    }
+
+    /// Check if the alarm is activated for requests that take more space
+    fn check_write_alarm(&self) -> Result<(), ExecuteError> {
+        self.alarm_storage.check_corrupt()?;
+        match self.alarm_storage.current_alarm() {
+            AlarmType::Nospace => Err(ExecuteError::Nospace),
+            AlarmType::Corrupt | AlarmType::None => Ok(()),
+        }
+    }
+
+    /// Check if the storage is writable
+    ///
+    /// The committed commands can't be applied to a read-only storage, this
+    /// node keeps serving the reads in a degraded state, which is reported by
+    /// its status, and the commands are applied again from the log after it's
+    /// restarted. The storage of other nodes is not affected, so no alarm is
+    /// activated for it.
+    fn check_writable(&self) -> Result<(), ExecuteError> {
+        let result = self.db.check_writable();
+        if result.is_err() && !self.read_only_reported.swap(true, Ordering::Relaxed) {
+            warn!("storage is read-only, the commands are rejected until restart");
+        }
+        result
+    }
+
+    /// Activate the alarm of this node in background
+    fn activate_alarm(&self, alarm: AlarmType) {
+        if let Some(ref alarmer) = *self.alarmer.read() {
+            alarmer.activate(alarm);
+        }
+    }
+
+    /// Activate the `CORRUPT` alarm if the error is caused by a corrupted value
+    fn check_corrupt<T>(&self, result: &Result<T, ExecuteError>) {
+        if let Err(ExecuteError::Corrupt(ref reason)) = *result {
+            warn!("corrupted data detected: {reason}");
+            self.activate_alarm(AlarmType::Corrupt);
         }
     }
 
@@ -328,12 +446,14 @@ impl CommandExecutor {
             x if x.is_auth_backend() => self.auth_storage.after_sync(wrapper, auth_revision)?,
             x if x.is_lease_backend() => {
                 self.lease_storage
-                    .after_sync(wrapper, general_revision, txn_db, index)?
+                    .after_sync(wrapper, general_revision, index)?
             }
             x if x.is_alarm_backend() => self.alarm_storage.after_sync(wrapper, general_revision),
             _ => unreachable!("Should not sync kv commands"),
         };
 
+        // the tombstones of the keys attached to revoked leases
+        self.db.account_puts(&wr_ops);
         txn_db.write_ops(wr_ops)?;
 
         Ok((asr, er))
@@ -423,7 +543,12 @@ impl CurpCommandExecutor<Command> for CommandExecutor {
         let wrapper = cmd.request();
         self.auth_storage.check_permission(wrapper, auth_info)?;
         match &wrapper {
-            x if x.is_kv_backend() => self.kv_storage.execute(wrapper, None),
+            x if x.is_kv_backend() => {
+                self.check_size(wrapper)?;
+                let result = self.kv_storage.execute(wrapper, None);
+                self.check_corrupt(&result);
+                result
+            }
             x if x.is_auth_backend() => self.auth_storage.execute(wrapper),
             x if x.is_lease_backend() => self.lease_storage.execute(wrapper),
             x if x.is_alarm_backend() => Ok(self.alarm_storage.execute(wrapper)),
@@ -464,6 +589,11 @@ impl CurpCommandExecutor<Command> for CommandExecutor {
             .all(|c| self.quota_checker.check(c));
 
         let mut states = ASResults::new(cmds);
+        // nothing is applied on a read-only storage, the commands are applied
+        // again from the log after it's restarted
+        if let Err(e) = self.check_writable() {
+            return states.into_errors(e);
+        }
         states.update_err(|c| self.check_alarm(c.cmd()));
         states.update_err(|c| {
             self.auth_storage
@@ -487,14 +617,18 @@ impl CurpCommandExecutor<Command> for CommandExecutor {
         states.update_result(|c| {
             let (cmd, to_execute) = c.into_parts();
             let wrapper = cmd.request();
-            let (asr, er) = match wrapper {
-                x if x.is_kv_backend() => self.after_sync_kv(
-                    wrapper,
-                    &txn_db,
-                    &index_state,
-                    &general_revision_state,
-                    to_execute,
-                ),
+            let result = match wrapper {
+                x if x.is_kv_backend() => {
+                    let result = self.after_sync_kv(
+                        wrapper,
+                        &txn_db,
+                        &index_state,
+                        &general_revision_state,
+                        to_execute,
+                    );
+                    self.check_corrupt(&result);
+                    result
+                }
                 x if x.is_auth_backend() || x.is_lease_backend() || x.is_alarm_backend() => self
                     .after_sync_others(
                         wrapper,
@@ -505,7 +639,11 @@ impl CurpCommandExecutor<Command> for CommandExecutor {
                         to_execute,
                     ),
                 _ => unreachable!("Must be one of kv, auth, lease, alarm"),
-            }?;
+            };
+            // a failed lease request is marked as synced too, so that its lease id
+            // can be granted or waited for again
+            self.lease_storage.mark_lease_synced(wrapper);
+            let (asr, er) = result?;
 
             if let RequestWrapper::CompactionRequest(ref compact_req) = *wrapper {
                 if compact_req.physical {
@@ -515,29 +653,23 @@ impl CurpCommandExecutor<Command> for CommandExecutor {
                 }
             };
 
-            self.lease_storage.mark_lease_synced(wrapper);
-
             Ok(AfterSyncOk::new(asr, er))
         });
 
         if let Err(e) = txn_db.commit() {
-            return states.into_errors(ExecuteError::DbError(e.to_string()));
+            self.db.record_write_error(&e);
+            let err = self
+                .check_writable()
+                .err()
+                .unwrap_or_else(|| ExecuteError::DbError(e.to_string()));
+            return states.into_errors(err);
         }
         index_state.commit();
         general_revision_state.commit();
         auth_revision_state.commit();
 
         if !quota_enough {
-            if let Some(alarmer) = self.alarmer.read().clone() {
-                let _ig = tokio::spawn(async move {
-                    if let Err(e) = alarmer
-                        .alarm(AlarmAction::Activate, AlarmType::Nospace)
-                        .await
-                    {
-                        warn!("{} propose alarm failed: {:?}", alarmer.id, e);
-                    }
-                });
-            }
+            self.activate_alarm(AlarmType::Nospace);
         }
 
         states.into_results()
@@ -554,6 +686,9 @@ impl CurpCommandExecutor<Command> for CommandExecutor {
             None
         };
         self.db.reset(s).await?;
+        self.lease_storage.recover()?;
+        // the alarms and the downgrade job are shipped in the snapshot too
+        self.alarm_storage.recover()?;
         self.kv_storage.recover().await
     }
 
@@ -568,13 +703,9 @@ impl CurpCommandExecutor<Command> for CommandExecutor {
     }
 
     fn last_applied(&self) -> Result<LogIndex, <Command as CurpCommand>::Error> {
-        let Some(index_bytes) = self.db.get_value(META_TABLE, APPLIED_INDEX_KEY)? else {
-            return Ok(0);
-        };
-        let buf: [u8; 8] = index_bytes
-            .try_into()
-            .unwrap_or_else(|e| panic!("cannot decode index from backend, {e:?}"));
-        Ok(u64::from_le_bytes(buf))
+        // the applied index of the batches not persisted yet is not reported, or
+        // curp may compact the log entries of them
+        self.db.persisted_applied_index()
     }
 
     fn trigger(&self, id: InflightId) {
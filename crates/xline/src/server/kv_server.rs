@@ -13,10 +13,11 @@ use tokio::time::timeout;
 use tracing::{debug, instrument};
 use xlineapi::{
     command::{Command, CurpClient},
-    request_validation::RequestValidator,
+    request_validation::{RequestValidator, SizeLimits, SizeValidator},
     AuthInfo, ResponseWrapper,
 };
 
+use super::command::QuotaGuard;
 use crate::{
     revision_check::RevisionCheck,
     rpc::{
@@ -24,15 +25,21 @@ use crate::{
         PutRequest, PutResponse, RangeRequest, RangeResponse, RequestWrapper, Response, ResponseOp,
         TxnRequest, TxnResponse,
     },
-    storage::{AuthStore, KvStore},
+    storage::{AlarmStore, AuthStore, KvStore},
 };
 
+/// Metadata key of the bytes reclaimed by a physical compaction in the
+/// compaction response, the response of etcd has no field for it
+pub(crate) const RECLAIMED_BYTES_METADATA_KEY: &str = "reclaimed-bytes";
+
 /// KV Server
 pub(crate) struct KvServer {
     /// KV storage
     kv_storage: Arc<KvStore>,
     /// Auth storage
     auth_storage: Arc<AuthStore>,
+    /// Alarm storage
+    alarm_storage: Arc<AlarmStore>,
     /// Compact timeout
     compact_timeout: Duration,
     /// Consensus client
@@ -41,6 +48,10 @@ pub(crate) struct KvServer {
     compact_events: Arc<DashMap<u64, Arc<Event>>>,
     /// Next compact_id
     next_compact_id: AtomicU64,
+    /// Size limits of keys and values
+    size_limits: SizeLimits,
+    /// Quota guard
+    quota_guard: QuotaGuard,
 }
 
 impl KvServer {
@@ -49,17 +60,23 @@ impl KvServer {
     pub(crate) fn new(
         kv_storage: Arc<KvStore>,
         auth_storage: Arc<AuthStore>,
+        alarm_storage: Arc<AlarmStore>,
         compact_timeout: Duration,
         client: Arc<CurpClient>,
         compact_events: Arc<DashMap<u64, Arc<Event>>>,
+        size_limits: SizeLimits,
+        quota_guard: QuotaGuard,
     ) -> Self {
         Self {
             kv_storage,
             auth_storage,
+            alarm_storage,
             compact_timeout,
             client,
             compact_events,
             next_compact_id: AtomicU64::new(0),
+            size_limits,
+            quota_guard,
         }
     }
 
@@ -73,7 +90,12 @@ impl KvServer {
     }
 
     /// serializable execute request in current node
+    ///
+    /// The command is not executed by the leader, so the `CORRUPT` alarm
+    /// limiting this member is checked here, which keeps an isolated member
+    /// from serving its diverged data.
     fn do_serializable(&self, command: &Command) -> Result<Response, tonic::Status> {
+        self.alarm_storage.check_corrupt()?;
         self.auth_storage
             .check_permission(command.request(), command.auth_info())?;
         let cmd_res = self.kv_storage.execute(command.request(), None)?;
@@ -91,6 +113,7 @@ impl KvServer {
     {
         let request = request.into();
         let cmd = Command::new_with_auth_info(request, auth_info);
+        self.quota_guard.check(&cmd)?;
         let (cmd_res, sync_res) = self.client.propose(&cmd, None, false).await??;
         let revision = sync_res
             .unwrap_or_else(|| unreachable!("sync response should always exist in slow path"))
@@ -144,12 +167,16 @@ impl Kv for KvServer {
         let range_req = request.get_ref();
         range_req.validation()?;
         debug!("Receive grpc request: {}", range_req);
-        range_req.check_revision(
-            self.kv_storage.compacted_revision(),
-            self.kv_storage.revision(),
-        )?;
-        let auth_info = self.auth_storage.try_get_auth_info_from_request(&request)?;
         let is_serializable = range_req.serializable;
+        if is_serializable {
+            range_req.check_revision(
+                self.kv_storage.compacted_revision(),
+                self.kv_storage.revision(),
+            )?;
+        } else {
+            range_req.check_compacted(self.kv_storage.compacted_revision())?;
+        }
+        let auth_info = self.auth_storage.try_get_auth_info_from_request(&request)?;
         let res = if is_serializable {
             let cmd = Command::new_with_auth_info(request.into_inner().into(), auth_info);
             self.do_serializable(&cmd)?
@@ -175,6 +202,7 @@ impl Kv for KvServer {
     ) -> Result<tonic::Response<PutResponse>, tonic::Status> {
         let put_req: &PutRequest = request.get_ref();
         put_req.validation()?;
+        put_req.check_size(self.size_limits)?;
         debug!("Receive grpc request: {:?}", put_req);
         let auth_info = self.auth_storage.try_get_auth_info_from_request(&request)?;
         let res = self.propose(request.into_inner(), auth_info).await?;
@@ -218,11 +246,9 @@ impl Kv for KvServer {
     ) -> Result<tonic::Response<TxnResponse>, tonic::Status> {
         let txn_req = request.get_ref();
         txn_req.validation()?;
+        txn_req.check_size(self.size_limits)?;
         debug!("Receive grpc request: {}", txn_req);
-        txn_req.check_revision(
-            self.kv_storage.compacted_revision(),
-            self.kv_storage.revision(),
-        )?;
+        txn_req.check_compacted(self.kv_storage.compacted_revision())?;
         let auth_info = self.auth_storage.try_get_auth_info_from_request(&request)?;
         let res = self.propose(request.into_inner(), auth_info).await?;
         if let Response::ResponseTxn(response) = res {
@@ -241,12 +267,11 @@ impl Kv for KvServer {
         request: tonic::Request<CompactionRequest>,
     ) -> Result<tonic::Response<CompactionResponse>, tonic::Status> {
         debug!("Receive CompactionRequest {:?}", request);
-        let compacted_revision = self.kv_storage.compacted_revision();
-        let current_revision = self.kv_storage.revision();
         let req = request.get_ref();
-        req.check_revision(compacted_revision, current_revision)?;
+        req.check_compacted(self.kv_storage.compacted_revision())?;
         let auth_info = self.auth_storage.try_get_auth_info_from_request(&request)?;
         let physical = req.physical;
+        let revision = req.revision;
         let request = RequestWrapper::from(request.into_inner());
         let cmd = Command::new_with_auth_info(request, auth_info);
         let compact_id = self.next_compact_id.fetch_add(1, Ordering::Relaxed);
@@ -268,7 +293,19 @@ impl Kv for KvServer {
         }
 
         if let ResponseWrapper::CompactionResponse(response) = resp {
-            Ok(tonic::Response::new(response))
+            let mut response = tonic::Response::new(response);
+            if let Some(reclaimed) = physical
+                .then(|| self.kv_storage.reclaimed_bytes(revision))
+                .flatten()
+            {
+                let value = reclaimed.to_string().parse().map_err(|e| {
+                    tonic::Status::internal(format!("metadata value parse error: {e}"))
+                })?;
+                let _prev = response
+                    .metadata_mut()
+                    .insert(RECLAIMED_BYTES_METADATA_KEY, value);
+            }
+            Ok(response)
         } else {
             panic!("Receive wrong response {resp:?} for CompactionRequest");
         }
@@ -277,6 +314,8 @@ impl Kv for KvServer {
 
 #[cfg(test)]
 mod test {
+    use xlineapi::execute_error::ExecuteError;
+
     use super::*;
     use crate::rpc::{Request, RequestOp};
 
@@ -356,6 +395,28 @@ mod test {
         assert_eq!(expected_tonic_status.code(), tonic::Code::OutOfRange);
     }
 
+    #[tokio::test]
+    async fn test_check_compacted_ignores_future_revision() {
+        let compacted_revision = 5;
+        let range_request_with_future_rev = RangeRequest {
+            key: b"foo".to_vec(),
+            revision: 20,
+            ..Default::default()
+        };
+        assert!(range_request_with_future_rev
+            .check_compacted(compacted_revision)
+            .is_ok());
+
+        let compaction_request_with_compacted_rev = CompactionRequest {
+            revision: 5,
+            ..Default::default()
+        };
+        assert!(matches!(
+            compaction_request_with_compacted_rev.check_compacted(compacted_revision),
+            Err(ExecuteError::RevisionCompacted(5, 5))
+        ));
+    }
+
     #[tokio::test]
     async fn test_txn_invalid_revision() {
         let current_revision = 10;
@@ -1,10 +1,18 @@
-use std::{pin::Pin, sync::Arc, time::Duration};
+use std::{
+    collections::BTreeSet,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
 use async_stream::{stream, try_stream};
 use clippy_utilities::NumericCast;
 use curp::members::ClusterInfo;
-use futures::stream::Stream;
-use tokio::time;
+use futures::{future, stream::Stream, StreamExt};
+use tokio::{sync::Mutex, time};
 #[cfg(not(madsim))]
 use tonic::transport::ClientTlsConfig;
 use tonic::transport::Endpoint;
@@ -13,6 +21,7 @@ use tracing::{debug, warn};
 use utils::ClientTlsConfig;
 use utils::{
     build_endpoint,
+    config::StreamTokenExpiry,
     task_manager::{tasks::TaskName, Listener, TaskManager},
 };
 use xlineapi::{
@@ -20,19 +29,30 @@ use xlineapi::{
     execute_error::ExecuteError,
 };
 
+use super::{
+    command::QuotaGuard,
+    version::{ClusterVersion, Feature},
+};
 use crate::{
     id_gen::IdGenerator,
     metrics,
     rpc::{
-        Lease, LeaseClient, LeaseGrantRequest, LeaseGrantResponse, LeaseKeepAliveRequest,
-        LeaseKeepAliveResponse, LeaseLeasesRequest, LeaseLeasesResponse, LeaseRevokeRequest,
-        LeaseRevokeResponse, LeaseTimeToLiveRequest, LeaseTimeToLiveResponse, RequestWrapper,
+        Lease, LeaseCheckpoint, LeaseCheckpointRequest, LeaseClient, LeaseGrantRequest,
+        LeaseGrantResponse, LeaseKeepAliveRequest, LeaseKeepAliveResponse, LeaseLeasesRequest,
+        LeaseLeasesResponse, LeaseRevokeRequest, LeaseRevokeResponse, LeaseTimeToLiveRequest,
+        LeaseTimeToLiveResponse, RequestWrapper,
     },
     storage::{AuthStore, LeaseStore},
 };
 
-/// Default Lease Request Time
-const DEFAULT_LEASE_REQUEST_TIME: Duration = Duration::from_millis(500);
+/// Min backoff of forwarding the keep alive requests again after a failure
+const MIN_REDIRECT_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Max backoff of forwarding the keep alive requests again after a failure
+const MAX_REDIRECT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Max number of leases checkpointed by one proposal
+const MAX_LEASE_CHECKPOINT_BATCH: usize = 1000;
 
 /// Lease Server
 pub(crate) struct LeaseServer {
@@ -40,6 +60,8 @@ pub(crate) struct LeaseServer {
     lease_storage: Arc<LeaseStore>,
     /// Auth storage
     auth_storage: Arc<AuthStore>,
+    /// Policy of the keep alive streams whose tokens have expired
+    stream_token_expiry: StreamTokenExpiry,
     /// Consensus client
     client: Arc<CurpClient>,
     /// Id generator
@@ -50,6 +72,11 @@ pub(crate) struct LeaseServer {
     client_tls_config: Option<ClientTlsConfig>,
     /// Task manager
     task_manager: Arc<TaskManager>,
+    /// Quota guard
+    quota_guard: QuotaGuard,
+    /// Cluster version, the leases are checkpointed only if every member
+    /// supports the checkpoints
+    cluster_version: Arc<ClusterVersion>,
 }
 
 /// A lease keep alive stream
@@ -58,30 +85,101 @@ type KeepAliveStream =
 
 impl LeaseServer {
     /// New `LeaseServer`
+    #[allow(clippy::too_many_arguments)] // Consistent with other servers
     pub(crate) fn new(
         lease_storage: Arc<LeaseStore>,
         auth_storage: Arc<AuthStore>,
+        stream_token_expiry: StreamTokenExpiry,
         client: Arc<CurpClient>,
         id_gen: Arc<IdGenerator>,
         cluster_info: Arc<ClusterInfo>,
         client_tls_config: Option<ClientTlsConfig>,
         task_manager: &Arc<TaskManager>,
+        quota_guard: QuotaGuard,
+        cluster_version: Arc<ClusterVersion>,
+        lease_checkpoint_interval: Duration,
     ) -> Arc<Self> {
         let lease_server = Arc::new(Self {
             lease_storage,
             auth_storage,
+            stream_token_expiry,
             client,
             id_gen,
             cluster_info,
             client_tls_config,
             task_manager: Arc::clone(task_manager),
+            quota_guard,
+            cluster_version,
         });
         task_manager.spawn(TaskName::RevokeExpiredLeases, |n| {
             Self::revoke_expired_leases_task(Arc::clone(&lease_server), n)
         });
+        if !lease_checkpoint_interval.is_zero() {
+            task_manager.spawn(TaskName::LeaseCheckpoint, |n| {
+                Self::checkpoint_leases_task(
+                    Arc::clone(&lease_server),
+                    lease_checkpoint_interval,
+                    n,
+                )
+            });
+        }
         lease_server
     }
 
+    /// Task of checkpointing the remaining ttls of leases, so that the leases
+    /// do not restart from their ttls after a leader change
+    #[allow(clippy::arithmetic_side_effects, clippy::ignored_unit_patterns)] // Introduced by tokio::select!
+    async fn checkpoint_leases_task(
+        lease_server: Arc<LeaseServer>,
+        interval: Duration,
+        shutdown_listener: Listener,
+    ) {
+        let mut ticker = time::interval(interval);
+        loop {
+            tokio::select! {
+                _ = shutdown_listener.wait() => return,
+                _ = ticker.tick() => {}
+            }
+            if !lease_server.lease_storage.is_primary()
+                || !lease_server
+                    .cluster_version
+                    .is_enabled(Feature::LeaseCheckpoints)
+            {
+                continue;
+            }
+            let checkpoints = lease_server.lease_storage.checkpoints();
+            for batch in checkpoints.chunks(MAX_LEASE_CHECKPOINT_BATCH) {
+                if let Err(e) = Self::propose_checkpoints(
+                    &lease_server.client,
+                    &lease_server.auth_storage,
+                    batch.to_vec(),
+                )
+                .await
+                {
+                    warn!("Failed to checkpoint leases: {}", e);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Propose the checkpoints of leases as the root user
+    async fn propose_checkpoints(
+        client: &CurpClient,
+        auth_storage: &AuthStore,
+        checkpoints: Vec<LeaseCheckpoint>,
+    ) -> Result<(), tonic::Status> {
+        let auth_info = if auth_storage.is_enabled() {
+            Some(auth_storage.verify(&auth_storage.root_token()?)?)
+        } else {
+            None
+        };
+        let request = LeaseCheckpointRequest { checkpoints }.into();
+        let cmd = Command::new_with_auth_info(request, auth_info);
+        let _res = client.propose(&cmd, None, false).await??;
+        Ok(())
+    }
+
     /// Task of revoke expired leases
     #[allow(clippy::arithmetic_side_effects, clippy::ignored_unit_patterns)] // Introduced by tokio::select!
     async fn revoke_expired_leases_task(
@@ -89,13 +187,19 @@ impl LeaseServer {
         shutdown_listener: Listener,
     ) {
         loop {
+            // listen before getting the nearest expiry, so that no earlier expiry is missed
+            let expiry_listener = lease_server.lease_storage.listen_expiry();
+            let next_expiry = lease_server.lease_storage.next_expiry();
             tokio::select! {
                 _ = shutdown_listener.wait() => return ,
-                _ = time::sleep(DEFAULT_LEASE_REQUEST_TIME) => {}
+                _ = Self::sleep_until(next_expiry) => {}
+                _ = expiry_listener => {}
             }
-            // only leader will check expired lease
+            // the expired leases are always popped so that the task never spins on
+            // them, but only leader will revoke them
+            let expired_leases = lease_server.lease_storage.find_expired_leases();
             if lease_server.lease_storage.is_primary() {
-                for id in lease_server.lease_storage.find_expired_leases() {
+                for id in expired_leases {
                     let _handle = tokio::spawn({
                         let s = Arc::clone(&lease_server);
                         let token_option = lease_server.auth_storage.root_token();
@@ -109,8 +213,9 @@ impl LeaseServer {
                                     }),
                                 );
                             }
-                            if let Err(e) = s.lease_revoke(request).await {
-                                warn!("Failed to revoke expired leases: {}", e);
+                            match s.lease_revoke(request).await {
+                                Ok(_) => metrics::get().lease_expired_total.add(1, &[]),
+                                Err(e) => warn!("Failed to revoke expired leases: {}", e),
                             }
                         }
                     });
@@ -119,6 +224,14 @@ impl LeaseServer {
         }
     }
 
+    /// Sleep until the given deadline, or forever if there is no deadline
+    async fn sleep_until(deadline: Option<Instant>) {
+        match deadline {
+            Some(deadline) => time::sleep(deadline.saturating_duration_since(Instant::now())).await,
+            None => future::pending().await,
+        }
+    }
+
     /// Propose request and get result with fast/slow path
     async fn propose<T>(
         &self,
@@ -130,6 +243,7 @@ impl LeaseServer {
         let auth_info = self.auth_storage.try_get_auth_info_from_request(&request)?;
         let request = request.into_inner().into();
         let cmd = Command::new_with_auth_info(request, auth_info);
+        self.quota_guard.check(&cmd)?;
         let res = self.client.propose(&cmd, None, false).await??;
         Ok(res)
     }
@@ -145,6 +259,9 @@ impl LeaseServer {
             .get_shutdown_listener(TaskName::LeaseKeepAlive)
             .ok_or(tonic::Status::cancelled("The cluster is shutting down"))?;
         let lease_storage = Arc::clone(&self.lease_storage);
+        let auth_storage = Arc::clone(&self.auth_storage);
+        let client = Arc::clone(&self.client);
+        let cluster_version = Arc::clone(&self.cluster_version);
         let stream = try_stream! {
            loop {
                 let keep_alive_req: LeaseKeepAliveRequest = tokio::select! {
@@ -170,7 +287,42 @@ impl LeaseServer {
                         _ = lease_storage.wait_synced(keep_alive_req.id) => {
                         }
                     };
-                    lease_storage.keep_alive(keep_alive_req.id).map_err(Into::into)
+                    // the renewed lease restarts from its ttl, so its checkpoint is cleared
+                    // on the other members as well
+                    let checkpointed = lease_storage
+                        .look_up(keep_alive_req.id)
+                        .is_some_and(|lease| lease.has_checkpoint());
+                    if checkpointed && cluster_version.is_enabled(Feature::LeaseCheckpoints) {
+                        let checkpoints = vec![LeaseCheckpoint {
+                            id: keep_alive_req.id,
+                            remaining_ttl: 0,
+                        }];
+                        let proposal =
+                            Self::propose_checkpoints(&client, &auth_storage, checkpoints);
+                        let res = tokio::select! {
+                            _ = shutdown_listener.wait() => {
+                                debug!("Lease keep alive shutdown");
+                                break;
+                            }
+                            res = proposal => res,
+                        };
+                        // the lease is checkpointed again by the periodic checkpoints
+                        if let Err(e) = res {
+                            warn!(
+                                "Failed to clear the checkpoint of lease {}: {}",
+                                keep_alive_req.id, e
+                            );
+                        }
+                    }
+                    match lease_storage.keep_alive(keep_alive_req.id) {
+                        Ok(ttl) => Ok(ttl),
+                        // other leases may be kept alive on the same stream, so a lost
+                        // lease is reported by a zero ttl instead of closing the stream
+                        Err(ExecuteError::LeaseNotFound(_) | ExecuteError::LeaseExpired(_)) => {
+                            Ok(0)
+                        }
+                        Err(e) => Err(e.into()),
+                    }
                 } else {
                     Err(tonic::Status::failed_precondition("current node is not a leader"))
                 }?;
@@ -184,47 +336,166 @@ impl LeaseServer {
         Ok(Box::pin(stream))
     }
 
-    /// Handle keep alive at follower
+    /// Handle keep alive at follower, the requests are forwarded to the leader,
+    /// and to the new leader once the former one steps down
+    ///
+    /// The requests the former leader did not respond to are sent again to the
+    /// new leader. After a failure the leader is fetched again through
+    /// consensus, with a backoff, as the cached leader may be stale.
     #[allow(clippy::arithmetic_side_effects, clippy::ignored_unit_patterns)] // Introduced by tokio::select!
-    async fn follower_keep_alive(
+    fn follower_keep_alive(
         &self,
-        mut request_stream: tonic::Streaming<LeaseKeepAliveRequest>,
-        leader_addrs: &[String],
+        request_stream: tonic::Streaming<LeaseKeepAliveRequest>,
     ) -> Result<KeepAliveStream, tonic::Status> {
         let shutdown_listener = self
             .task_manager
             .get_shutdown_listener(TaskName::LeaseKeepAlive)
             .ok_or(tonic::Status::cancelled("The cluster is shutting down"))?;
-        let endpoints = build_endpoints(leader_addrs, self.client_tls_config.as_ref())?;
-        let channel = tonic::transport::Channel::balance_list(endpoints.into_iter());
-        let mut lease_client = LeaseClient::new(channel);
+        let client = Arc::clone(&self.client);
+        let cluster_info = Arc::clone(&self.cluster_info);
+        let client_tls_config = self.client_tls_config.clone();
+        // The requests are shared by the redirect streams to every leader in turn
+        let request_stream = Arc::new(Mutex::new(request_stream));
+        let requests_closed = Arc::new(AtomicBool::new(false));
+        // The ids of the forwarded requests that are not responded yet
+        let pending = Arc::new(parking_lot::Mutex::new(BTreeSet::new()));
 
-        let redirect_stream = stream! {
-            loop {
-                tokio::select! {
-                    _ = shutdown_listener.wait() => {
+        let stream = try_stream! {
+            let mut backoff = None;
+            'forward: loop {
+                if let Some(delay) = backoff {
+                    let shutdown = tokio::select! {
+                        _ = shutdown_listener.wait() => true,
+                        _ = time::sleep(delay) => false,
+                    };
+                    if shutdown {
                         debug!("Lease keep alive shutdown");
                         break;
                     }
-                    res = request_stream.message() => {
-                        if let Ok(Some(keep_alive_req)) = res {
-                            yield keep_alive_req;
-                        } else {
-                            break;
+                }
+                let leader_id = client.fetch_leader_id(backoff.is_some()).await?;
+                let Some(leader_addrs) = cluster_info.client_urls(leader_id) else {
+                    debug!("The address of leader {leader_id} is unknown");
+                    backoff = Some(next_backoff(backoff));
+                    continue;
+                };
+                let endpoints = build_endpoints(&leader_addrs, client_tls_config.as_ref())?;
+                let channel = tonic::transport::Channel::balance_list(endpoints.into_iter());
+                let mut lease_client = LeaseClient::new(channel);
+                let redirect_stream = Self::redirect_stream(
+                    Arc::clone(&request_stream),
+                    Arc::clone(&requests_closed),
+                    Arc::clone(&pending),
+                    shutdown_listener.clone(),
+                );
+                let res = lease_client.lease_keep_alive(redirect_stream).await;
+                let mut response_stream = match res {
+                    Ok(response_stream) => response_stream.into_inner(),
+                    Err(status) if is_leader_unavailable(&status) => {
+                        backoff = Some(next_backoff(backoff));
+                        continue;
+                    }
+                    Err(status) => Err(status)?,
+                };
+                loop {
+                    match response_stream.message().await {
+                        Ok(Some(resp)) => {
+                            backoff = None;
+                            let _ignore = pending.lock().remove(&resp.id);
+                            yield resp;
                         }
+                        Ok(None) if requests_closed.load(Ordering::Acquire) => break 'forward,
+                        // the former leader stepped down
+                        Ok(None) => break,
+                        Err(status) if is_leader_unavailable(&status) => break,
+                        Err(status) => Err(status)?,
                     }
                 }
+                backoff = Some(next_backoff(backoff));
+                debug!("Lease keep alive is forwarded to the new leader");
             }
-
         };
 
-        let stream = lease_client
-            .lease_keep_alive(redirect_stream)
-            .await?
-            .into_inner();
-
         Ok(Box::pin(stream))
     }
+
+    /// Terminate a keep alive stream once the token it's opened with expires,
+    /// the clients then authenticate again and open a new stream
+    #[allow(clippy::arithmetic_side_effects, clippy::ignored_unit_patterns)] // Introduced by tokio::select!
+    fn until_token_expires(
+        mut stream: KeepAliveStream,
+        expires_at: Option<time::Instant>,
+    ) -> KeepAliveStream {
+        let Some(expires_at) = expires_at else {
+            return stream;
+        };
+        Box::pin(try_stream! {
+            loop {
+                let resp = tokio::select! {
+                    resp = stream.next() => resp,
+                    _ = time::sleep_until(expires_at) => {
+                        debug!("token of the keep alive stream has expired");
+                        Some(Err(tonic::Status::from(ExecuteError::InvalidAuthToken)))
+                    }
+                };
+                let Some(resp) = resp else {
+                    break;
+                };
+                yield resp?;
+            }
+        })
+    }
+
+    /// Stream of the keep alive requests forwarded to the leader, it starts with
+    /// the pending requests the former leader did not respond to
+    #[allow(clippy::arithmetic_side_effects, clippy::ignored_unit_patterns)] // Introduced by tokio::select!
+    fn redirect_stream(
+        request_stream: Arc<Mutex<tonic::Streaming<LeaseKeepAliveRequest>>>,
+        requests_closed: Arc<AtomicBool>,
+        pending: Arc<parking_lot::Mutex<BTreeSet<i64>>>,
+        shutdown_listener: Listener,
+    ) -> impl Stream<Item = LeaseKeepAliveRequest> {
+        stream! {
+            // held until the stream to the former leader is dropped
+            let mut request_stream = request_stream.lock().await;
+            let resent: Vec<_> = pending.lock().iter().copied().collect();
+            for id in resent {
+                yield LeaseKeepAliveRequest { id };
+            }
+            loop {
+                let res = tokio::select! {
+                    _ = shutdown_listener.wait() => {
+                        debug!("Lease keep alive shutdown");
+                        None
+                    }
+                    res = request_stream.message() => res.ok().flatten(),
+                };
+                let Some(keep_alive_req) = res else {
+                    requests_closed.store(true, Ordering::Release);
+                    break;
+                };
+                let _ignore = pending.lock().insert(keep_alive_req.id);
+                yield keep_alive_req;
+            }
+        }
+    }
+}
+
+/// Check if the keep alive stream to the leader is broken because the leader
+/// stepped down or can not be reached
+fn is_leader_unavailable(status: &tonic::Status) -> bool {
+    matches!(
+        status.code(),
+        tonic::Code::FailedPrecondition | tonic::Code::Unavailable
+    )
+}
+
+/// Get the next backoff of forwarding the keep alive requests, it's doubled
+/// after every failure in a row
+fn next_backoff(backoff: Option<Duration>) -> Duration {
+    backoff.map_or(MIN_REDIRECT_BACKOFF, |delay| {
+        delay.saturating_mul(2).min(MAX_REDIRECT_BACKOFF)
+    })
 }
 
 /// Build endpoints from addresses
@@ -254,7 +525,11 @@ impl Lease for LeaseServer {
         debug!("Receive LeaseGrantRequest {:?}", request);
         let lease_grant_req = request.get_mut();
         if lease_grant_req.id == 0 {
+            // skip the ids requested by clients explicitly
             lease_grant_req.id = self.id_gen.next();
+            while self.lease_storage.contains_lease(lease_grant_req.id) {
+                lease_grant_req.id = self.id_gen.next();
+            }
         }
 
         let (res, sync_res) = self.propose(request).await?;
@@ -286,7 +561,6 @@ impl Lease for LeaseServer {
             if let Some(header) = res.header.as_mut() {
                 header.revision = revision;
             }
-            metrics::get().lease_expired_total.add(1, &[]);
         }
         Ok(tonic::Response::new(res))
     }
@@ -302,28 +576,19 @@ impl Lease for LeaseServer {
         request: tonic::Request<tonic::Streaming<LeaseKeepAliveRequest>>,
     ) -> Result<tonic::Response<Self::LeaseKeepAliveStream>, tonic::Status> {
         debug!("Receive LeaseKeepAliveRequest {:?}", request);
+        let expires_at = (self.stream_token_expiry == StreamTokenExpiry::Cancel)
+            .then(|| self.auth_storage.token_expiration(&request))
+            .flatten();
         let request_stream = request.into_inner();
-        let stream = loop {
-            if self.lease_storage.is_primary() {
-                break self.leader_keep_alive(request_stream)?;
-            }
-            let leader_id = self.client.fetch_leader_id(false).await?;
-            // Given that a candidate server may become a leader when it won the election or
-            // a follower when it lost the election. Therefore we need to double check here.
-            // We can directly invoke leader_keep_alive when a candidate becomes a leader.
-            if !self.lease_storage.is_primary() {
-                let leader_addrs = self.cluster_info.client_urls(leader_id).unwrap_or_else(|| {
-                    unreachable!(
-                        "The address of leader {} not found in all_members {:?}",
-                        leader_id, self.cluster_info
-                    )
-                });
-                break self
-                    .follower_keep_alive(request_stream, &leader_addrs)
-                    .await?;
-            }
+        // a candidate forwards the requests to itself once it wins the election
+        let stream = if self.lease_storage.is_primary() {
+            self.leader_keep_alive(request_stream)?
+        } else {
+            self.follower_keep_alive(request_stream)?
         };
-        Ok(tonic::Response::new(stream))
+        Ok(tonic::Response::new(Self::until_token_expires(
+            stream, expires_at,
+        )))
     }
 
     /// LeaseTimeToLive retrieves lease information.
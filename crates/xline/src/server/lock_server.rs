@@ -10,9 +10,9 @@ use utils::build_endpoint;
 #[cfg(madsim)]
 use utils::ClientTlsConfig;
 use xlineapi::{
-    command::{Command, CommandResponse, CurpClient, KeyRange, SyncResponse},
+    command::{Command, CommandResponse, CurpClient, SyncResponse},
     execute_error::ExecuteError,
-    AuthInfo, EventType,
+    key_util, AuthInfo, EventType,
 };
 
 use crate::{
@@ -106,7 +106,7 @@ impl LockServer {
                 ..Default::default()
             })),
         };
-        let range_end = KeyRange::get_prefix(prefix);
+        let range_end = key_util::prefix_end(prefix.as_bytes());
         #[allow(clippy::as_conversions)] // this cast is always safe
         let get_owner = RequestOp {
             request: Some(Request::RequestRange(RangeRequest {
@@ -136,7 +136,7 @@ impl LockServer {
         let mut watch_client =
             WatchClient::new(Channel::balance_list(self.addrs.clone().into_iter()));
         loop {
-            let range_end = KeyRange::get_prefix(&pfx);
+            let range_end = key_util::prefix_end(pfx.as_bytes());
             #[allow(clippy::as_conversions)] // this cast is always safe
             let get_req = RangeRequest {
                 key: pfx.as_bytes().to_vec(),
@@ -1,35 +1,53 @@
-use std::{fmt::Debug, pin::Pin, sync::Arc};
+use std::{fmt::Debug, pin::Pin, sync::Arc, time::Duration};
 
 use async_stream::try_stream;
 use bytes::BytesMut;
 use clippy_utilities::{NumericCast, OverflowArithmetic};
-use curp::{cmd::CommandExecutor as _, members::ClusterInfo, server::RawCurp};
+use curp::{
+    cmd::CommandExecutor as _,
+    members::{ClusterInfo, ServerId},
+    server::RawCurp,
+};
 use engine::SnapshotApi;
 use futures::stream::Stream;
 use sha2::{Digest, Sha256};
-use tracing::{debug, error};
+use tokio::time::{sleep, timeout};
+use tonic::transport::Channel;
+#[cfg(not(madsim))]
+use tonic::transport::ClientTlsConfig;
+use tracing::{debug, error, warn};
+#[cfg(madsim)]
+use utils::ClientTlsConfig;
+use utils::{build_endpoint, task_manager::Listener};
 use xlineapi::{
     command::{Command, CommandResponse, CurpClient, SyncResponse},
-    RequestWrapper,
+    execute_error::ExecuteError,
+    AlarmAction, AlarmType, RequestWrapper,
 };
 
-use super::command::CommandExecutor;
+use super::{
+    command::CommandExecutor,
+    version::{ClusterVersion, Version},
+};
 use crate::{
     header_gen::HeaderGenerator,
     rpc::{
-        AlarmRequest, AlarmResponse, DefragmentRequest, DefragmentResponse, DowngradeRequest,
-        DowngradeResponse, HashKvRequest, HashKvResponse, HashRequest, HashResponse, Maintenance,
-        MoveLeaderRequest, MoveLeaderResponse, SnapshotRequest, SnapshotResponse, StatusRequest,
-        StatusResponse,
+        AlarmRequest, AlarmResponse, DefragmentRequest, DefragmentResponse, DowngradeAction,
+        DowngradeRequest, DowngradeResponse, HashKvRequest, HashKvResponse, HashRequest,
+        HashResponse, Maintenance, MaintenanceClient, MoveLeaderRequest, MoveLeaderResponse,
+        SnapshotRequest, SnapshotResponse, StatusRequest, StatusResponse,
     },
     state::State,
     storage::{db::DB, AlarmStore, AuthStore, KvStore},
 };
 
 /// Minimum page size
-const MIN_PAGE_SIZE: u64 = 512;
+pub(crate) const MIN_PAGE_SIZE: u64 = 512;
 /// Snapshot chunk size
 pub(crate) const MAINTENANCE_SNAPSHOT_CHUNK_SIZE: u64 = 64 * 1024;
+/// Metadata key of the durability of the storage in the status response, the
+/// response of etcd has no field for it
+pub(crate) const DURABILITY_METADATA_KEY: &str = "durability";
 
 /// Maintenance Server
 pub(crate) struct MaintenanceServer {
@@ -51,6 +69,10 @@ pub(crate) struct MaintenanceServer {
     ce: Arc<CommandExecutor>,
     /// Alarm store
     alarm_store: Arc<AlarmStore>,
+    /// Client tls config, used to connect to the peers
+    client_tls_config: Option<ClientTlsConfig>,
+    /// Cluster version
+    cluster_version: Arc<ClusterVersion>,
 }
 
 impl MaintenanceServer {
@@ -66,6 +88,8 @@ impl MaintenanceServer {
         raw_curp: Arc<RawCurp<Command, State<Arc<CurpClient>>>>,
         ce: Arc<CommandExecutor>,
         alarm_store: Arc<AlarmStore>,
+        client_tls_config: Option<ClientTlsConfig>,
+        cluster_version: Arc<ClusterVersion>,
     ) -> Self {
         Self {
             kv_store,
@@ -77,6 +101,8 @@ impl MaintenanceServer {
             raw_curp,
             ce,
             alarm_store,
+            client_tls_config,
+            cluster_version,
         }
     }
 
@@ -94,6 +120,14 @@ impl MaintenanceServer {
         let res = self.client.propose(&cmd, None, false).await??;
         Ok(res)
     }
+
+    /// Get the current cluster version as a string, empty if it's not decided
+    fn cluster_version_string(&self) -> String {
+        self.cluster_version
+            .version()
+            .map(|version| version.to_string())
+            .unwrap_or_default()
+    }
 }
 
 #[tonic::async_trait]
@@ -136,6 +170,9 @@ impl Maintenance for MaintenanceServer {
         for a in self.alarm_store.get_all_alarms() {
             errors.push(a.to_string());
         }
+        if let Some(reason) = self.db.read_only_reason() {
+            errors.push(format!("storage is read-only: {reason}"));
+        }
         let response = StatusResponse {
             header: Some(self.header_gen.gen_header()),
             version: env!("CARGO_PKG_VERSION").to_owned(),
@@ -145,19 +182,35 @@ impl Maintenance for MaintenanceServer {
             raft_term: term,
             raft_applied_index: last_applied,
             errors,
-            db_size_in_use: size.numeric_cast(),
+            db_size_in_use: self.db.size_in_use().min(size).numeric_cast(),
             is_learner,
         };
-        Ok(tonic::Response::new(response))
+        let mut response = tonic::Response::new(response);
+        let _prev = response.metadata_mut().insert(
+            DURABILITY_METADATA_KEY,
+            self.db
+                .durability()
+                .to_string()
+                .parse()
+                .unwrap_or_else(|e| panic!("metadata value parse error: {e}")),
+        );
+        Ok(response)
     }
 
     async fn defragment(
         &self,
         _request: tonic::Request<DefragmentRequest>,
     ) -> Result<tonic::Response<DefragmentResponse>, tonic::Status> {
-        Err(tonic::Status::unimplemented(
-            "defragment is unimplemented".to_owned(),
-        ))
+        let db = Arc::clone(&self.db);
+        tokio::task::spawn_blocking(move || db.defragment())
+            .await
+            .map_err(|e| {
+                error!("defragment task failed, {e}");
+                tonic::Status::internal("defragment failed")
+            })??;
+        Ok(tonic::Response::new(DefragmentResponse {
+            header: Some(self.header_gen.gen_header()),
+        }))
     }
 
     async fn hash(
@@ -201,6 +254,16 @@ impl Maintenance for MaintenanceServer {
         request: tonic::Request<MoveLeaderRequest>,
     ) -> Result<tonic::Response<MoveLeaderResponse>, tonic::Status> {
         let node_id = request.into_inner().target_id;
+        // only a voting member can be elected as the leader
+        if !self
+            .cluster_info
+            .get(&node_id)
+            .is_some_and(|member| !member.is_learner)
+        {
+            return Err(tonic::Status::failed_precondition(
+                "etcdserver: bad leader transferee",
+            ));
+        }
         self.client.move_leader(node_id).await?;
         Ok(tonic::Response::new(MoveLeaderResponse {
             header: Some(self.header_gen.gen_header()),
@@ -209,21 +272,52 @@ impl Maintenance for MaintenanceServer {
 
     async fn downgrade(
         &self,
-        _request: tonic::Request<DowngradeRequest>,
+        request: tonic::Request<DowngradeRequest>,
     ) -> Result<tonic::Response<DowngradeResponse>, tonic::Status> {
-        Err(tonic::Status::unimplemented(
-            "downgrade is unimplemented".to_owned(),
-        ))
+        let action = request.get_ref().action();
+        if action == DowngradeAction::Cancel {
+            if self.cluster_version.downgrade_target().is_none() {
+                return Err(tonic::Status::failed_precondition(
+                    "etcdserver: no inflight downgrade job",
+                ));
+            }
+            let _res = self.propose(request).await?;
+            return Ok(tonic::Response::new(DowngradeResponse {
+                header: Some(self.header_gen.gen_header()),
+                version: self.cluster_version_string(),
+            }));
+        }
+        if self.cluster_version.downgrade_target().is_some() {
+            return Err(tonic::Status::failed_precondition(
+                "etcdserver: cluster has a downgrade job in progress",
+            ));
+        }
+        let cluster_version = self
+            .cluster_version
+            .refresh(&self.cluster_info, self.client_tls_config.as_ref())
+            .await?;
+        let _target = check_downgrade_target(cluster_version, &request.get_ref().version)?;
+        if action == DowngradeAction::Enable {
+            // the job is set on every member once the request is committed
+            let _res = self.propose(request).await?;
+        }
+        Ok(tonic::Response::new(DowngradeResponse {
+            header: Some(self.header_gen.gen_header()),
+            version: cluster_version.to_string(),
+        }))
     }
 }
 
 /// Generate snapshot stream
-fn snapshot_stream(
+pub(super) fn snapshot_stream(
     header_gen: &HeaderGenerator,
     db: &DB,
 ) -> Result<impl Stream<Item = Result<SnapshotResponse, tonic::Status>>, tonic::Status> {
     let tmp_path = format!("/tmp/snapshot-{}", uuid::Uuid::new_v4());
-    let mut snapshot = db.get_snapshot(tmp_path).map_err(|e| {
+    let mut snapshot = db.try_get_snapshot(tmp_path).map_err(|e| {
+        if matches!(e, ExecuteError::StorageBusy) {
+            return e.into();
+        }
         error!("get snapshot failed, {e}");
         tonic::Status::internal("get snapshot failed")
     })?;
@@ -231,23 +325,27 @@ fn snapshot_stream(
     let header = header_gen.gen_header();
 
     let stream = try_stream! {
-        if let Err(e) = snapshot.rewind() {
-            error!("snapshot rewind failed, {e}");
-            return;
-        }
-
         let mut remain_size = snapshot.size();
         let mut checksum_gen = Sha256::new();
-        while remain_size > 0 {
+        let mut result = snapshot.rewind().map_err(|e| {
+            error!("snapshot rewind failed, {e}");
+            tonic::Status::internal("snapshot rewind failed")
+        });
+        while result.is_ok() && remain_size > 0 {
             let buf_size = std::cmp::min(MAINTENANCE_SNAPSHOT_CHUNK_SIZE, remain_size);
             let mut buf = BytesMut::with_capacity(buf_size.numeric_cast());
             remain_size = remain_size.overflow_sub(buf_size);
-            snapshot.read_buf_exact(&mut buf).await.map_err(|_e| {tonic::Status::internal("snapshot read failed")})?;
+            if let Err(e) = snapshot.read_buf_exact(&mut buf).await {
+                error!("snapshot read failed, {e}");
+                result = Err(tonic::Status::internal("snapshot read failed"));
+                break;
+            }
             // etcd client will use the size of the snapshot to determine whether checksum is included,
             // and the check method size % 512 == sha256.size, So we need to pad snapshots to multiples
-            // of 512 bytes
-            let padding = MIN_PAGE_SIZE.overflow_sub(buf_size.overflow_rem(MIN_PAGE_SIZE));
-            if padding != 0 {
+            // of 512 bytes. Only the last chunk may be unaligned.
+            let unaligned = buf_size.overflow_rem(MIN_PAGE_SIZE);
+            if unaligned != 0 {
+                let padding = MIN_PAGE_SIZE.overflow_sub(unaligned);
                 buf.extend_from_slice(&vec![0; padding.numeric_cast()]);
             }
             checksum_gen.update(&buf);
@@ -257,31 +355,187 @@ fn snapshot_stream(
                 blob: Vec::from(buf)
             };
         }
+        if let Err(e) = snapshot.clean().await {
+            error!("snapshot clean failed, {e}");
+        }
+        result?;
         let checksum = checksum_gen.finalize().to_vec();
         yield SnapshotResponse {
             header: Some(header),
             remaining_bytes: 0,
             blob: checksum,
         };
-        if let Err(e) = snapshot.clean().await {
-            error!("snapshot clean failed, {e}");
-        }
     };
 
     Ok(stream)
 }
 
+/// Compare the kv hashes of the peers with the local one periodically, the
+/// leader activates the `CORRUPT` alarm of the peers whose hashes differ
+#[allow(clippy::arithmetic_side_effects, clippy::ignored_unit_patterns)] // Introduced by tokio::select!
+pub(crate) async fn corrupt_check_task(
+    kv_store: Arc<KvStore>,
+    client: Arc<CurpClient>,
+    cluster_info: Arc<ClusterInfo>,
+    raw_curp: Arc<RawCurp<Command, State<Arc<CurpClient>>>>,
+    tls_config: Option<ClientTlsConfig>,
+    interval: Duration,
+    shutdown_listener: Listener,
+) {
+    loop {
+        tokio::select! {
+            _ = shutdown_listener.wait() => return,
+            _ = sleep(interval) => {}
+        }
+        let self_id = cluster_info.self_id();
+        if raw_curp.leader().0 != Some(self_id) {
+            continue;
+        }
+        let corrupted =
+            corrupted_peers(&kv_store, &cluster_info, tls_config.as_ref(), interval).await;
+        for id in corrupted {
+            error!("kv hash of member {id} differs from the leader {self_id}");
+            let request = AlarmRequest::new(AlarmAction::Activate, id, AlarmType::Corrupt);
+            let cmd = Command::new(RequestWrapper::from(request));
+            match client.propose(&cmd, None, true).await {
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => warn!("activate corrupt alarm of member {id} failed: {e}"),
+                Err(e) => warn!("propose corrupt alarm of member {id} failed: {e:?}"),
+            }
+        }
+    }
+}
+
+/// Get the ids of the peers whose kv hashes differ from the local one
+///
+/// The stores are hashed at the compacted revision, or the current revision if
+/// nothing is compacted yet, so that the peers lagging behind can be checked
+/// too. The hashes are only comparable when the stores are compacted to the
+/// same revision, the peers that are unreachable or compacted to another
+/// revision are checked next time.
+async fn corrupted_peers(
+    kv_store: &KvStore,
+    cluster_info: &ClusterInfo,
+    tls_config: Option<&ClientTlsConfig>,
+    timeout_dur: Duration,
+) -> Vec<ServerId> {
+    // a non-positive revision means the current revision
+    let at_rev = kv_store.compacted_revision();
+    let (hash, compact_revision, revision) = match kv_store.hash_kv(at_rev) {
+        Ok(res) => res,
+        Err(e) => {
+            warn!("hash local kv store failed, {e}");
+            return vec![];
+        }
+    };
+    let mut corrupted = vec![];
+    for id in cluster_info.peers_ids() {
+        let resp = match timeout(
+            timeout_dur,
+            peer_hash_kv(cluster_info, id, revision, tls_config),
+        )
+        .await
+        {
+            Ok(Ok(resp)) => resp,
+            Ok(Err(e)) => {
+                debug!("get kv hash of member {id} failed, {e}");
+                continue;
+            }
+            Err(_elapsed) => {
+                debug!("get kv hash of member {id} timeout");
+                continue;
+            }
+        };
+        if resp.compact_revision == compact_revision && resp.hash != hash {
+            corrupted.push(id);
+        }
+    }
+    corrupted
+}
+
+/// Get the kv hash of a peer at the given revision
+async fn peer_hash_kv(
+    cluster_info: &ClusterInfo,
+    id: ServerId,
+    revision: i64,
+    tls_config: Option<&ClientTlsConfig>,
+) -> Result<HashKvResponse, tonic::Status> {
+    let resp = peer_client(cluster_info, id, tls_config)?
+        .hash_kv(HashKvRequest { revision })
+        .await?;
+    Ok(resp.into_inner())
+}
+
+/// Build a maintenance client connected to the client urls of a peer
+pub(super) fn peer_client(
+    cluster_info: &ClusterInfo,
+    id: ServerId,
+    tls_config: Option<&ClientTlsConfig>,
+) -> Result<MaintenanceClient<Channel>, tonic::Status> {
+    let addrs = cluster_info
+        .client_urls(id)
+        .filter(|addrs| !addrs.is_empty())
+        .ok_or_else(|| tonic::Status::unavailable(format!("no client urls of member {id}")))?;
+    let endpoints = addrs
+        .iter()
+        .map(|addr| build_endpoint(addr, tls_config))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| tonic::Status::internal(e.to_string()))?;
+    let channel = Channel::balance_list(endpoints.into_iter());
+    Ok(MaintenanceClient::new(channel))
+}
+
+/// Check the target version of a downgrade, a cluster can only be downgraded
+/// to the previous minor version of the same major version
+fn check_downgrade_target(
+    cluster_version: Version,
+    target: &str,
+) -> Result<Version, tonic::Status> {
+    let target = Version::parse(target).ok_or_else(|| {
+        tonic::Status::invalid_argument("etcdserver: wrong downgrade target version format")
+    })?;
+    if !target.is_previous_minor_of(cluster_version) {
+        return Err(tonic::Status::invalid_argument(
+            "etcdserver: invalid downgrade target version",
+        ));
+    }
+    Ok(target)
+}
+
 #[cfg(test)]
 mod test {
     use std::error::Error;
 
+    use rand::RngCore;
     use tempfile::TempDir;
     use test_macros::abort_on_panic;
     use tokio_stream::StreamExt;
     use utils::config::EngineConfig;
 
     use super::*;
-    use crate::storage::db::DB;
+    use crate::{
+        rpc::KeyValue,
+        storage::{
+            db::{WriteOp, DB},
+            Revision,
+        },
+    };
+
+    #[test]
+    fn test_check_downgrade_target() {
+        let cluster_version = Version::parse("3.5").unwrap();
+        assert_eq!(
+            check_downgrade_target(cluster_version, "3.4").unwrap(),
+            Version::parse("3.4").unwrap()
+        );
+        assert!(check_downgrade_target(cluster_version, "3.4.2").is_ok());
+        for target in ["3.5", "3.3", "2.4", "three"] {
+            let err = check_downgrade_target(cluster_version, target).unwrap_err();
+            assert_eq!(err.code(), tonic::Code::InvalidArgument, "{target}");
+        }
+        let first_minor = Version::parse("1.0").unwrap();
+        assert!(check_downgrade_target(first_minor, "0.9").is_err());
+    }
 
     #[tokio::test]
     #[abort_on_panic]
@@ -314,4 +568,54 @@ mod test {
         dir.close().unwrap();
         Ok(())
     }
+    #[tokio::test]
+    #[abort_on_panic]
+    async fn test_snapshot_stream_with_multiple_chunks() -> Result<(), Box<dyn Error>> {
+        let dir = TempDir::with_prefix("/tmp/test_snapshot_rpc_chunks").unwrap();
+        let db = DB::open(&EngineConfig::RocksDB(dir.path().join("db")))?;
+        let mut rng = rand::thread_rng();
+        let ops = (1..=64)
+            .map(|rev| {
+                let mut value = vec![0; 4096];
+                rng.fill_bytes(&mut value);
+                WriteOp::PutKeyValue(
+                    Revision::new(rev, 0),
+                    KeyValue {
+                        key: format!("key{rev}").into_bytes(),
+                        value,
+                        ..KeyValue::default()
+                    },
+                )
+            })
+            .collect();
+        db.write_ops(ops)?;
+
+        let header_gen = HeaderGenerator::new(0, 0);
+        let stream = snapshot_stream(&header_gen, db.as_ref())?;
+        tokio::pin!(stream);
+        let mut chunks = Vec::new();
+        while let Some(resp) = stream.next().await {
+            chunks.push(resp?.blob);
+        }
+        assert!(
+            chunks.len() > 2,
+            "the snapshot should be sent in multiple chunks"
+        );
+        let checksum = chunks.pop().unwrap();
+        let data = chunks.concat();
+        assert_eq!(data.len() % MIN_PAGE_SIZE.numeric_cast::<usize>(), 0);
+        assert_eq!(Sha256::digest(&data).to_vec(), checksum);
+
+        let mut snapshot = db.get_snapshot(dir.path().join("snapshot")).unwrap();
+        let size = snapshot.size().numeric_cast();
+        let mut snapshot_data = BytesMut::with_capacity(size);
+        snapshot.read_buf_exact(&mut snapshot_data).await.unwrap();
+        assert!(data.len() < size.overflow_add(MIN_PAGE_SIZE.numeric_cast()));
+        assert_eq!(data[..size], snapshot_data[..]);
+        assert!(data[size..].iter().all(|b| *b == 0));
+
+        snapshot.clean().await.unwrap();
+        dir.close().unwrap();
+        Ok(())
+    }
 }
@@ -2,6 +2,9 @@
 mod auth_server;
 /// Auth Wrapper
 mod auth_wrapper;
+/// Periodic backups of the storage
+#[cfg(not(madsim))]
+mod backup;
 /// Cluster server
 mod cluster_server;
 /// Command to be executed
@@ -14,10 +17,18 @@ mod lease_server;
 mod lock_server;
 /// Xline maintenance client
 mod maintenance;
+/// Identity of the peers verified by their certificates
+#[cfg(not(madsim))]
+mod peer_identity;
 /// Xline watch server
 mod watch_server;
+/// Cluster version and feature gates
+pub(crate) mod version;
 /// Xline server
 mod xline_server;
 
 pub use self::xline_server::XlineServer;
-pub(crate) use self::{auth_server::get_token, maintenance::MAINTENANCE_SNAPSHOT_CHUNK_SIZE};
+pub(crate) use self::{
+    auth_server::get_token,
+    maintenance::{MAINTENANCE_SNAPSHOT_CHUNK_SIZE, MIN_PAGE_SIZE},
+};
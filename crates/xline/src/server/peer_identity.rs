@@ -0,0 +1,235 @@
+use std::{net::IpAddr, sync::Arc};
+
+use bcder::{decode::Content, Mode, Tag};
+use curp::{
+    members::{ClusterInfo, ServerId},
+    rpc::PeerIdentity,
+};
+use tonic::{service::Interceptor, Request, Status};
+use tracing::debug;
+use x509_certificate::{rfc5280, X509Certificate};
+
+/// Encoded object identifier of the subject alternative name extension,
+/// 2.5.29.17
+const OID_SUBJECT_ALT_NAME: [u8; 3] = [0x55, 0x1D, 0x11];
+
+/// Interceptor of the inner requests, which attaches the identity of the peer
+/// verified by its certificate to the requests
+///
+/// A peer is a member if its certificate is issued for a host of the peer urls
+/// of the member, i.e. a DNS name or an IP address of the subject alternative
+/// names, or the common name if there are no alternative names. The hosts must
+/// match exactly, wildcard names are never matched. Peers that are not a
+/// member of the cluster are rejected.
+#[derive(Debug, Clone)]
+pub(super) struct PeerIdentityInterceptor {
+    /// Cluster information
+    cluster_info: Arc<ClusterInfo>,
+}
+
+impl PeerIdentityInterceptor {
+    /// New `PeerIdentityInterceptor`
+    pub(super) fn new(cluster_info: Arc<ClusterInfo>) -> Self {
+        Self { cluster_info }
+    }
+}
+
+impl Interceptor for PeerIdentityInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let hosts = request
+            .peer_certs()
+            .and_then(|chain| chain.first().map(|cert| cert_hosts(cert.as_ref())))
+            .ok_or_else(|| Status::unauthenticated("the peer has no certificate"))?;
+        let ids = member_ids(&self.cluster_info, &hosts);
+        if ids.is_empty() {
+            debug!("reject peer with certificate hosts {hosts:?}");
+            return Err(Status::permission_denied(
+                "the certificate of the peer is not issued for any member",
+            ));
+        }
+        let _prev = request.extensions_mut().insert(PeerIdentity::new(ids));
+        Ok(request)
+    }
+}
+
+/// Get the ids of the members that one of their peer urls has one of the hosts
+fn member_ids(cluster_info: &ClusterInfo, hosts: &[String]) -> Vec<ServerId> {
+    let mut ids: Vec<_> = cluster_info
+        .all_members_peer_urls()
+        .into_iter()
+        .filter(|(_, urls)| {
+            urls.iter()
+                .any(|url| hosts.iter().any(|host| host_matches(host, url_host(url))))
+        })
+        .map(|(id, _)| id)
+        .collect();
+    ids.sort_unstable();
+    ids
+}
+
+/// Get the host of an url, without the scheme, the port and the brackets of
+/// an IPv6 address
+fn url_host(url: &str) -> &str {
+    let authority = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = authority.split('/').next().unwrap_or(authority);
+    if let Some(rest) = authority.strip_prefix('[') {
+        return rest.split(']').next().unwrap_or(rest);
+    }
+    authority
+        .rsplit_once(':')
+        .map_or(authority, |(host, _port)| host)
+}
+
+/// Whether the host of a certificate matches the host of an url, wildcard
+/// names are rejected as they may be shared by hosts outside the cluster
+fn host_matches(cert_host: &str, url_host: &str) -> bool {
+    if let (Ok(cert_ip), Ok(url_ip)) = (cert_host.parse::<IpAddr>(), url_host.parse::<IpAddr>()) {
+        return cert_ip == url_ip;
+    }
+    !cert_host.contains('*') && cert_host.eq_ignore_ascii_case(url_host)
+}
+
+/// Get the hosts a DER encoded certificate is issued for
+fn cert_hosts(cert_der: &[u8]) -> Vec<String> {
+    let Ok(cert) = X509Certificate::from_der(cert_der) else {
+        return vec![];
+    };
+    let hosts = subject_alt_names(&cert).unwrap_or_default();
+    if !hosts.is_empty() {
+        return hosts;
+    }
+    cert.subject_common_name().into_iter().collect()
+}
+
+/// Get the DNS names and IP addresses of the subject alternative names of a
+/// certificate, returns `None` if the extension is malformed
+fn subject_alt_names(cert: &X509Certificate) -> Option<Vec<String>> {
+    let cert: &rfc5280::Certificate = cert.as_ref();
+    let Some(extension) = cert
+        .tbs_certificate
+        .extensions
+        .as_ref()
+        .and_then(|extensions| {
+            extensions
+                .iter()
+                .find(|extension| extension.id.as_ref() == OID_SUBJECT_ALT_NAME.as_slice())
+        })
+    else {
+        return Some(vec![]);
+    };
+    Mode::Der
+        .decode(extension.value.to_bytes(), |cons| {
+            cons.take_sequence(|cons| {
+                let mut hosts = Vec::new();
+                while let Some(host) = cons.take_opt_value(|tag, content| match *content {
+                    Content::Primitive(ref mut name) => {
+                        let name = name.take_all()?;
+                        Ok(general_name_host(tag, &name))
+                    }
+                    Content::Constructed(ref mut name) => {
+                        name.skip_all()?;
+                        Ok(None)
+                    }
+                })? {
+                    hosts.extend(host);
+                }
+                Ok(hosts)
+            })
+        })
+        .ok()
+}
+
+/// Get the host of a primitive general name, only the `dNSName` and the
+/// `iPAddress` are hosts
+fn general_name_host(tag: Tag, name: &[u8]) -> Option<String> {
+    if tag == Tag::CTX_2 {
+        return Some(String::from_utf8_lossy(name).into_owned());
+    }
+    if tag != Tag::CTX_7 {
+        return None;
+    }
+    if let Ok(octets) = <[u8; 4]>::try_from(name) {
+        Some(IpAddr::from(octets).to_string())
+    } else if let Ok(octets) = <[u8; 16]>::try_from(name) {
+        Some(IpAddr::from(octets).to_string())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn test_cert_hosts() {
+        let der = |pem: &[u8]| {
+            X509Certificate::from_pem(pem)
+                .unwrap()
+                .encode_der()
+                .unwrap()
+        };
+        assert_eq!(
+            cert_hosts(&der(include_bytes!("../../../../fixtures/server.crt"))),
+            vec![
+                "localhost".to_owned(),
+                "127.0.0.1".to_owned(),
+                "0.0.0.0".to_owned()
+            ]
+        );
+        // the common name is used if there are no alternative names
+        assert_eq!(
+            cert_hosts(&der(include_bytes!("../../../../fixtures/ca.crt"))),
+            vec!["ca".to_owned()]
+        );
+        assert!(cert_hosts(b"not a certificate").is_empty());
+    }
+
+    #[test]
+    fn test_url_host() {
+        assert_eq!(url_host("https://node1.xline.svc:2380"), "node1.xline.svc");
+        assert_eq!(url_host("http://127.0.0.1:2380/"), "127.0.0.1");
+        assert_eq!(url_host("https://[::1]:2380"), "::1");
+        assert_eq!(url_host("node1"), "node1");
+    }
+
+    #[test]
+    fn test_member_ids_should_match_peer_urls() {
+        let cluster_info = ClusterInfo::from_members_map(
+            HashMap::from([
+                (
+                    "node1".to_owned(),
+                    vec!["https://node1.xline.svc:2380".to_owned()],
+                ),
+                ("node2".to_owned(), vec!["https://10.0.0.2:2380".to_owned()]),
+                (
+                    "node3".to_owned(),
+                    vec!["https://node3.other.svc:2380".to_owned()],
+                ),
+            ]),
+            [],
+            "node1",
+        );
+        let id = |name| cluster_info.get_id_by_name(name).unwrap();
+        assert_eq!(
+            member_ids(&cluster_info, &["NODE1.xline.svc".to_owned()]),
+            vec![id("node1")]
+        );
+        assert_eq!(
+            member_ids(&cluster_info, &["10.0.0.2".to_owned()]),
+            vec![id("node2")]
+        );
+        // wildcard names are never matched
+        assert_eq!(
+            member_ids(
+                &cluster_info,
+                &["*.other.svc".to_owned(), "10.0.0.2".to_owned()]
+            ),
+            vec![id("node2")]
+        );
+        assert!(member_ids(&cluster_info, &["*.xline.svc".to_owned()]).is_empty());
+        assert!(member_ids(&cluster_info, &["xline.svc".to_owned()]).is_empty());
+    }
+}
@@ -0,0 +1,471 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use curp::members::{ClusterInfo, ServerId};
+use parking_lot::RwLock;
+use tokio::time::{sleep, timeout};
+#[cfg(not(madsim))]
+use tonic::transport::ClientTlsConfig;
+use tracing::{debug, info, warn};
+use utils::task_manager::Listener;
+#[cfg(madsim)]
+use utils::ClientTlsConfig;
+use xlineapi::command::{Command, CurpClient};
+
+use super::maintenance::peer_client;
+use crate::{
+    rpc::{
+        ClusterVersionSetRequest, DowngradeAction, DowngradeRequest, RequestWrapper, StatusRequest,
+    },
+    storage::AuthStore,
+};
+
+/// Timeout of the maintenance requests to the peers
+const PEER_REQUEST_TIMEOUT: Duration = Duration::from_secs(3);
+/// Interval to refresh the versions of the members
+const MONITOR_VERSION_INTERVAL: Duration = Duration::from_secs(4);
+
+/// Major and minor version of a server, the patch version is ignored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct Version {
+    /// Major version
+    major: u64,
+    /// Minor version
+    minor: u64,
+}
+
+impl Version {
+    /// Version of the current server
+    pub(crate) fn current() -> Self {
+        Self::parse(env!("CARGO_PKG_VERSION"))
+            .unwrap_or_else(|| unreachable!("the package version should be valid"))
+    }
+
+    /// Parse a version in the form of `major.minor[.patch]`
+    pub(crate) fn parse(version: &str) -> Option<Self> {
+        let mut parts = version.split('.');
+        let major = parts.next()?.parse().ok()?;
+        let minor = parts.next()?.parse().ok()?;
+        let patch_valid = parts
+            .next()
+            .map_or(true, |patch| patch.parse::<u64>().is_ok());
+        (patch_valid && parts.next().is_none()).then_some(Self { major, minor })
+    }
+
+    /// Whether it is the previous minor version of the same major version
+    pub(crate) fn is_previous_minor_of(self, other: Self) -> bool {
+        self.major == other.major && self.minor.checked_add(1) == Some(other.minor)
+    }
+}
+
+impl std::fmt::Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.0", self.major, self.minor)
+    }
+}
+
+/// Features that are only enabled if every member supports them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub(crate) enum Feature {
+    /// Progress requests of watch streams
+    WatchProgressRequest,
+    /// Explicit tombstones of the deleted keys in the kv table
+    ExplicitTombstones,
+    /// Checkpoints of the remaining ttls of leases
+    LeaseCheckpoints,
+}
+
+impl Feature {
+    /// The lowest cluster version that supports the feature
+    fn min_version(self) -> Version {
+        match self {
+            Feature::WatchProgressRequest => Version { major: 0, minor: 6 },
+            // the patch versions are not told apart, so it waits for the next minor
+            Feature::ExplicitTombstones | Feature::LeaseCheckpoints => {
+                Version { major: 0, minor: 7 }
+            }
+        }
+    }
+}
+
+/// The cluster version, which is the lowest server version of the members,
+/// or the target version of the downgrade job in progress
+#[derive(Debug)]
+pub(crate) struct ClusterVersion {
+    /// Versions of the peers, learned from their status
+    member_versions: RwLock<HashMap<ServerId, Version>>,
+    /// The lowest version of the members, `None` if the version of some member
+    /// is unknown
+    version: RwLock<Option<Version>>,
+    /// Target version of the downgrade job in progress
+    downgrade_target: RwLock<Option<Version>>,
+    /// The cluster version committed through consensus, which switches the
+    /// storage formats of every member at the same log entry
+    committed: RwLock<Option<Version>>,
+}
+
+impl ClusterVersion {
+    /// New `ClusterVersion`
+    pub(crate) fn new(version: Option<Version>) -> Self {
+        Self {
+            member_versions: RwLock::new(HashMap::new()),
+            version: RwLock::new(version),
+            downgrade_target: RwLock::new(None),
+            committed: RwLock::new(None),
+        }
+    }
+
+    /// Get the cluster version, `None` if it's not decided yet
+    pub(crate) fn version(&self) -> Option<Version> {
+        let version = *self.version.read();
+        match *self.downgrade_target.read() {
+            Some(target) => version.map(|version| version.min(target)),
+            None => version,
+        }
+    }
+
+    /// Get the target version of the downgrade job in progress
+    pub(crate) fn downgrade_target(&self) -> Option<Version> {
+        *self.downgrade_target.read()
+    }
+
+    /// Start a downgrade job, the cluster version is lowered to the target
+    /// version at once, so that the features the target version lacks are
+    /// disabled before any member is downgraded
+    pub(crate) fn enable_downgrade(&self, target: Version) {
+        info!("downgrade to {target} is enabled");
+        *self.downgrade_target.write() = Some(target);
+    }
+
+    /// Cancel the downgrade job in progress, returns `false` if there is none
+    pub(crate) fn cancel_downgrade(&self) -> bool {
+        let canceled = self.downgrade_target.write().take();
+        if let Some(target) = canceled {
+            info!("downgrade to {target} is canceled");
+        }
+        canceled.is_some()
+    }
+
+    /// Whether the feature is enabled
+    ///
+    /// Before the cluster version is decided, the feature is enabled unless it
+    /// is lacked by a member whose version is already known, or by the target
+    /// version of the downgrade job, so that it is not held back by the
+    /// members that are not reached yet.
+    pub(crate) fn is_enabled(&self, feature: Feature) -> bool {
+        let min_version = feature.min_version();
+        match self.version() {
+            Some(version) => version >= min_version,
+            None => self
+                .member_versions
+                .read()
+                .values()
+                .chain(self.downgrade_target.read().as_ref())
+                .all(|&version| version >= min_version),
+        }
+    }
+
+    /// Set the cluster version committed through consensus
+    pub(crate) fn set_committed(&self, version: Option<Version>) {
+        let prev = std::mem::replace(&mut *self.committed.write(), version);
+        if prev != version {
+            if let Some(version) = version {
+                info!("cluster version {version} is committed");
+            }
+        }
+    }
+
+    /// Whether the feature is enabled by the committed cluster version and the
+    /// committed downgrade job, which are the same on every member after the
+    /// same log entry, so it gates how the commands are applied
+    pub(crate) fn is_committed_enabled(&self, feature: Feature) -> bool {
+        let min_version = feature.min_version();
+        self.committed
+            .read()
+            .is_some_and(|version| version >= min_version)
+            && self
+                .downgrade_target
+                .read()
+                .map_or(true, |target| target >= min_version)
+    }
+
+    /// Get the cluster version decided by the versions of the members if it's
+    /// not committed yet
+    fn uncommitted_version(&self) -> Option<Version> {
+        let version = (*self.version.read())?;
+        (*self.committed.read() != Some(version)).then_some(version)
+    }
+
+    /// Query the versions of the peers and update the cluster version, fails
+    /// if any peer is unreachable
+    ///
+    /// The last known versions of the unreachable peers are still counted,
+    /// a member never downgrades without the cluster version being lowered.
+    pub(crate) async fn refresh(
+        &self,
+        cluster_info: &ClusterInfo,
+        tls_config: Option<&ClientTlsConfig>,
+    ) -> Result<Version, tonic::Status> {
+        let mut result = Ok(());
+        for id in cluster_info.peers_ids() {
+            match peer_version(cluster_info, id, tls_config).await {
+                Ok(version) => {
+                    let _prev = self.member_versions.write().insert(id, version);
+                }
+                Err(e) => {
+                    debug!("get version of member {id} failed, {e}");
+                    if result.is_ok() {
+                        result = Err(e);
+                    }
+                }
+            }
+        }
+        let _version = self.update(&cluster_info.peers_ids());
+        result?;
+        self.version()
+            .ok_or_else(|| tonic::Status::unavailable("etcdserver: cluster version not found"))
+    }
+
+    /// Recompute the lowest version of the current peers
+    fn update(&self, peers: &[ServerId]) -> Option<Version> {
+        let version = {
+            let mut member_versions = self.member_versions.write();
+            member_versions.retain(|id, _| peers.contains(id));
+            peers.iter().try_fold(Version::current(), |min, id| {
+                member_versions.get(id).map(|&version| min.min(version))
+            })
+        };
+        let prev = std::mem::replace(&mut *self.version.write(), version);
+        if prev != version {
+            if let Some(version) = version {
+                info!("cluster version is set to {version}");
+            }
+        }
+        version
+    }
+
+    /// Whether the downgrade job in progress is finished, which is once every
+    /// member runs the target version
+    fn is_downgrade_finished(&self) -> bool {
+        let Some(target) = self.downgrade_target() else {
+            return false;
+        };
+        self.version.read().is_some()
+            && Version::current() <= target
+            && self
+                .member_versions
+                .read()
+                .values()
+                .all(|&version| version <= target)
+    }
+}
+
+/// Cancel the finished downgrade job through consensus, so that it's removed
+/// from every member
+async fn finish_downgrade(
+    client: &CurpClient,
+    auth_storage: &AuthStore,
+) -> Result<(), tonic::Status> {
+    let request = DowngradeRequest {
+        action: DowngradeAction::Cancel.into(),
+        version: String::new(),
+    };
+    propose_as_root(client, auth_storage, request.into()).await
+}
+
+/// Commit the cluster version decided by the versions of the members through
+/// consensus, only the leader proposes it
+async fn commit_version(
+    client: &CurpClient,
+    cluster_info: &ClusterInfo,
+    auth_storage: &AuthStore,
+    version: Version,
+) -> Result<(), tonic::Status> {
+    if client.fetch_leader_id(false).await? != cluster_info.self_id() {
+        return Ok(());
+    }
+    let request = ClusterVersionSetRequest {
+        version: version.to_string(),
+    };
+    propose_as_root(client, auth_storage, request.into()).await
+}
+
+/// Propose a request as the root user
+async fn propose_as_root(
+    client: &CurpClient,
+    auth_storage: &AuthStore,
+    request: RequestWrapper,
+) -> Result<(), tonic::Status> {
+    let auth_info = if auth_storage.is_enabled() {
+        Some(auth_storage.verify(&auth_storage.root_token()?)?)
+    } else {
+        None
+    };
+    let cmd = Command::new_with_auth_info(request, auth_info);
+    let _res = client.propose(&cmd, None, false).await??;
+    Ok(())
+}
+
+/// Get the server version of a peer
+async fn peer_version(
+    cluster_info: &ClusterInfo,
+    id: ServerId,
+    tls_config: Option<&ClientTlsConfig>,
+) -> Result<Version, tonic::Status> {
+    let mut client = peer_client(cluster_info, id, tls_config)?;
+    let resp = timeout(
+        PEER_REQUEST_TIMEOUT,
+        client.status(StatusRequest::default()),
+    )
+    .await
+    .map_err(|_elapsed| tonic::Status::unavailable(format!("get status of member {id} timeout")))??
+    .into_inner();
+    Version::parse(&resp.version)
+        .ok_or_else(|| tonic::Status::internal(format!("invalid version of member {id}")))
+}
+
+/// Refresh the cluster version periodically
+///
+/// The storage formats are gated by the committed cluster version, which the
+/// leader proposes only after a successful refresh, so they are switched by
+/// every member at the same log entry, and never before every member is known
+/// to read them.
+#[allow(clippy::arithmetic_side_effects, clippy::ignored_unit_patterns)] // Introduced by tokio::select!
+pub(crate) async fn monitor_version_task(
+    cluster_version: Arc<ClusterVersion>,
+    cluster_info: Arc<ClusterInfo>,
+    tls_config: Option<ClientTlsConfig>,
+    client: Arc<CurpClient>,
+    auth_storage: Arc<AuthStore>,
+    shutdown_listener: Listener,
+) {
+    loop {
+        match cluster_version
+            .refresh(&cluster_info, tls_config.as_ref())
+            .await
+        {
+            Ok(_version) => {
+                if let Some(version) = cluster_version.uncommitted_version() {
+                    if let Err(e) =
+                        commit_version(&client, &cluster_info, &auth_storage, version).await
+                    {
+                        warn!("commit cluster version {version} failed, {e}");
+                    }
+                }
+                if cluster_version.is_downgrade_finished() {
+                    info!("downgrade is finished");
+                    if let Err(e) = finish_downgrade(&client, &auth_storage).await {
+                        warn!("finish downgrade failed, {e}");
+                    }
+                }
+            }
+            Err(e) => debug!("refresh cluster version failed, {e}"),
+        }
+        tokio::select! {
+            _ = shutdown_listener.wait() => return,
+            _ = sleep(MONITOR_VERSION_INTERVAL) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(
+            Version::parse("3.5.1"),
+            Some(Version { major: 3, minor: 5 })
+        );
+        assert_eq!(Version::parse("0.7"), Some(Version { major: 0, minor: 7 }));
+        for invalid in ["", "3", "3.x", "3.5.1.0", "v3.5"] {
+            assert!(Version::parse(invalid).is_none(), "{invalid}");
+        }
+        assert_eq!(Version { major: 3, minor: 5 }.to_string(), "3.5.0");
+    }
+
+    #[test]
+    fn test_cluster_version_is_the_lowest_member_version() {
+        let current = Version::current();
+        let cluster_version = ClusterVersion::new(None);
+        assert!(cluster_version.is_enabled(Feature::WatchProgressRequest));
+
+        // the version of a peer is unknown
+        assert_eq!(cluster_version.update(&[1, 2]), None);
+        let old = Version { major: 0, minor: 5 };
+        let _prev = cluster_version.member_versions.write().insert(1, old);
+        assert_eq!(cluster_version.update(&[1, 2]), None);
+        assert!(!cluster_version.is_enabled(Feature::WatchProgressRequest));
+
+        let _prev = cluster_version.member_versions.write().insert(2, current);
+        assert_eq!(cluster_version.update(&[1, 2]), Some(old));
+        assert!(!cluster_version.is_enabled(Feature::WatchProgressRequest));
+
+        // the old member is removed
+        assert_eq!(cluster_version.update(&[2]), Some(current));
+        assert!(cluster_version.is_enabled(Feature::WatchProgressRequest));
+        assert!(!cluster_version.member_versions.read().contains_key(&1));
+
+        assert_eq!(cluster_version.update(&[]), Some(current));
+    }
+
+    #[test]
+    fn test_downgrade_lowers_cluster_version() {
+        let target = Version { major: 0, minor: 5 };
+        let cluster_version = ClusterVersion::new(Some(Version::current()));
+        assert!(!cluster_version.cancel_downgrade());
+
+        cluster_version.enable_downgrade(target);
+        assert_eq!(cluster_version.downgrade_target(), Some(target));
+        assert_eq!(cluster_version.version(), Some(target));
+        assert!(!cluster_version.is_enabled(Feature::WatchProgressRequest));
+
+        // the job is still in progress while a member runs a newer version
+        assert_eq!(cluster_version.update(&[]), Some(Version::current()));
+        assert_eq!(cluster_version.version(), Some(target));
+        assert!(cluster_version.cancel_downgrade());
+        assert_eq!(cluster_version.downgrade_target(), None);
+        assert_eq!(cluster_version.version(), Some(Version::current()));
+        assert!(cluster_version.is_enabled(Feature::WatchProgressRequest));
+
+        // the job is not finished until every member runs the target version
+        cluster_version.enable_downgrade(target);
+        let _prev = cluster_version.member_versions.write().insert(1, target);
+        assert_eq!(cluster_version.update(&[1]), Some(target));
+        assert!(!cluster_version.is_downgrade_finished());
+        cluster_version.enable_downgrade(Version::current());
+        assert_eq!(cluster_version.update(&[1]), Some(target));
+        assert!(cluster_version.is_downgrade_finished());
+        // it's finished through consensus, not by the refresh
+        assert_eq!(cluster_version.downgrade_target(), Some(Version::current()));
+    }
+
+    #[test]
+    fn test_committed_version_gates_the_features() {
+        let cluster_version = ClusterVersion::new(Some(Version::current()));
+        assert_eq!(
+            cluster_version.uncommitted_version(),
+            Some(Version::current())
+        );
+        // the refreshed version alone does not enable the features
+        assert!(!cluster_version.is_committed_enabled(Feature::WatchProgressRequest));
+
+        cluster_version.set_committed(Some(Version::current()));
+        assert_eq!(cluster_version.uncommitted_version(), None);
+        assert!(cluster_version.is_committed_enabled(Feature::WatchProgressRequest));
+
+        // the committed downgrade job disables them before any member is downgraded
+        cluster_version.enable_downgrade(Version { major: 0, minor: 5 });
+        assert!(!cluster_version.is_committed_enabled(Feature::WatchProgressRequest));
+        assert!(cluster_version.cancel_downgrade());
+        assert!(cluster_version.is_committed_enabled(Feature::WatchProgressRequest));
+
+        cluster_version.set_committed(Version::parse("0.5"));
+        assert!(!cluster_version.is_committed_enabled(Feature::WatchProgressRequest));
+        assert_eq!(
+            cluster_version.uncommitted_version(),
+            Some(Version::current())
+        );
+    }
+}
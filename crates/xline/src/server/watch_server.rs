@@ -1,28 +1,48 @@
 use std::{
     collections::{HashMap, HashSet},
+    future::Future,
+    pin::Pin,
     sync::Arc,
+    task::{Context, Poll},
     time::Duration,
 };
 
-use event_listener::Event;
-use tokio::sync::mpsc;
+use clippy_utilities::{NumericCast, OverflowArithmetic};
+use event_listener::{Event, EventListener};
+use prost::Message;
+use tokio::{
+    sync::{mpsc, AcquireError, Semaphore, SemaphorePermit},
+    time::{sleep_until, Instant},
+};
 use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
 use tracing::{debug, warn};
-use utils::task_manager::{tasks::TaskName, Listener, TaskManager};
-use xlineapi::command::KeyRange;
+use utils::{
+    config::StreamTokenExpiry,
+    task_manager::{tasks::TaskName, Listener, TaskManager},
+};
+use xlineapi::{command::KeyRange, execute_error::ExecuteError, AuthInfo};
 
+use super::version::{ClusterVersion, Feature};
 use crate::{
     header_gen::HeaderGenerator,
     rpc::{
         RequestUnion, ResponseHeader, Watch, WatchCancelRequest, WatchCreateRequest,
         WatchProgressRequest, WatchRequest, WatchResponse,
     },
-    storage::kvwatcher::{KvWatcher, KvWatcherOps, WatchEvent, WatchId, WatchIdGenerator},
+    storage::{
+        kv_store::PrevKvWatcher,
+        kvwatcher::{KvWatcher, KvWatcherOps, WatchEvent, WatchId, WatchIdGenerator},
+        AuthStore,
+    },
 };
 
 /// Default channel size
 pub(crate) const CHANNEL_SIZE: usize = 1024;
 
+/// Max encoded size of the fragments of a watch response, it leaves room below
+/// the default 4 MiB message limit of gRPC clients
+const MAX_FRAGMENT_SIZE: usize = 0x30_0000; // 3 MiB
+
 /// Watch Server
 #[derive(Debug)]
 pub(crate) struct WatchServer {
@@ -32,25 +52,47 @@ pub(crate) struct WatchServer {
     next_id_gen: Arc<WatchIdGenerator>,
     /// Header Generator
     header_gen: Arc<HeaderGenerator>,
+    /// Auth store
+    auth_store: Arc<AuthStore>,
+    /// Policy of the streams whose tokens have expired
+    stream_token_expiry: StreamTokenExpiry,
     /// Watch progress notify interval
     watch_progress_notify_interval: Duration,
+    /// Interval to coalesce the events of a watcher, zero disables the batching
+    watch_batch_interval: Duration,
+    /// Cluster version, gates the features of watch streams
+    cluster_version: Arc<ClusterVersion>,
+    /// Max encoded size of the event responses of a stream that are not taken
+    /// by the transport yet
+    flow_control_window: usize,
     /// Task manager
     task_manager: Arc<TaskManager>,
 }
 
 impl WatchServer {
     /// New `WatchServer`
+    #[allow(clippy::too_many_arguments)] // Consistent with other servers
     pub(crate) fn new(
         watcher: Arc<KvWatcher>,
         header_gen: Arc<HeaderGenerator>,
+        auth_store: Arc<AuthStore>,
+        stream_token_expiry: StreamTokenExpiry,
         watch_progress_notify_interval: Duration,
+        watch_batch_interval: Duration,
+        cluster_version: Arc<ClusterVersion>,
+        flow_control_window: usize,
         task_manager: Arc<TaskManager>,
     ) -> Self {
         Self {
             watcher,
             next_id_gen: Arc::new(WatchIdGenerator::new(1)), // watch_id starts from 1, 0 means auto-generating
             header_gen,
+            auth_store,
+            stream_token_expiry,
             watch_progress_notify_interval,
+            watch_batch_interval,
+            cluster_version,
+            flow_control_window,
             task_manager,
         }
     }
@@ -61,9 +103,13 @@ impl WatchServer {
         next_id_gen: Arc<WatchIdGenerator>,
         kv_watcher: Arc<W>,
         res_tx: mpsc::Sender<Result<WatchResponse, tonic::Status>>,
+        flow_control: Arc<FlowControl>,
         mut req_rx: ST,
         header_gen: Arc<HeaderGenerator>,
+        auth: Option<WatchAuth>,
         watch_progress_notify_interval: Duration,
+        watch_batch_interval: Duration,
+        cluster_version: Arc<ClusterVersion>,
         shutdown_listener: Listener,
     ) where
         ST: Stream<Item = Result<WatchRequest, tonic::Status>> + Unpin,
@@ -71,17 +117,23 @@ impl WatchServer {
     {
         let (event_tx, mut event_rx) = mpsc::channel(CHANNEL_SIZE);
         let stop_notify = Arc::new(Event::new());
+        let token_expires_at = auth.as_ref().and_then(|a| a.expires_at);
         let mut watch_handle = WatchHandle::new(
             kv_watcher,
             res_tx,
+            flow_control,
             event_tx,
             Arc::clone(&stop_notify),
             next_id_gen,
             header_gen,
+            auth,
+            cluster_version,
         );
         let mut ticker = tokio::time::interval(watch_progress_notify_interval);
         let stop_listener = stop_notify.listen();
         tokio::pin!(stop_listener);
+        // the deadline to flush the batched events
+        let mut flush_at = None;
         loop {
             tokio::select! {
                 _ = shutdown_listener.wait() => break,
@@ -89,7 +141,17 @@ impl WatchServer {
                     if let Some(req) = req {
                         match req {
                             Ok(req) => {
-                                watch_handle.handle_watch_request(req).await;
+                                let step = async {
+                                    // a progress response must not overtake the pending events
+                                    watch_handle.catch_up(&mut event_rx).await;
+                                    watch_handle.handle_watch_request(req).await;
+                                };
+                                if !until_stopped(step, &shutdown_listener, stop_listener.as_mut())
+                                    .await
+                                {
+                                    break;
+                                }
+                                flush_at = None;
                             }
                             Err(e) => {
                                 warn!("Receive WatchRequest error {:?}", e);
@@ -103,13 +165,48 @@ impl WatchServer {
                 }
                 event = event_rx.recv() => {
                     if let Some(event) = event {
-                        watch_handle.handle_watch_event(event).await;
+                        if watch_batch_interval.is_zero() {
+                            let step = watch_handle.handle_watch_event(event);
+                            if !until_stopped(step, &shutdown_listener, stop_listener.as_mut())
+                                .await
+                            {
+                                break;
+                            }
+                        } else {
+                            watch_handle.batch_watch_event(event);
+                            let _ignore = flush_at
+                                .get_or_insert_with(|| Instant::now() + watch_batch_interval);
+                        }
                     } else {
                         panic!("Watch event sender is closed");
                     }
                 }
+                _ = sleep_until(flush_at.unwrap_or_else(Instant::now)), if flush_at.is_some() => {
+                    let step = watch_handle.flush_batched_events();
+                    if !until_stopped(step, &shutdown_listener, stop_listener.as_mut())
+                        .await
+                    {
+                        break;
+                    }
+                    flush_at = None;
+                }
+                _ = sleep_until(token_expires_at.unwrap_or_else(Instant::now)), if token_expires_at.is_some() => {
+                    debug!("token of the watch stream has expired");
+                    let status = tonic::Status::from(ExecuteError::InvalidAuthToken);
+                    let _ignore = watch_handle.response_tx.send(Err(status)).await;
+                    break;
+                }
                 _ = ticker.tick() => {
-                    watch_handle.handle_tick_progress().await;
+                    let step = async {
+                        watch_handle.catch_up(&mut event_rx).await;
+                        watch_handle.handle_tick_progress().await;
+                    };
+                    if !until_stopped(step, &shutdown_listener, stop_listener.as_mut())
+                        .await
+                    {
+                        break;
+                    }
+                    flush_at = None;
                 }
                 // To ensure that each iteration invokes the same `stop_listener` and keeps
                 // events losing due to the cancellation of `stop_listener` at bay.
@@ -121,6 +218,83 @@ impl WatchServer {
     }
 }
 
+/// Run a step of a watch stream that may wait for the flow control window or
+/// the transport, returns `false` if the stream is stopped or the server shuts
+/// down meanwhile, so a stalled client never holds up them
+#[allow(clippy::arithmetic_side_effects, clippy::ignored_unit_patterns)] // Introduced by tokio::select!
+async fn until_stopped(
+    step: impl Future<Output = ()>,
+    shutdown_listener: &Listener,
+    stop_listener: Pin<&mut EventListener>,
+) -> bool {
+    tokio::select! {
+        () = step => true,
+        _ = shutdown_listener.wait() => false,
+        _ = stop_listener => false,
+    }
+}
+
+/// Permission checks of the watchers on one watch connection
+#[derive(Debug)]
+struct WatchAuth {
+    /// Auth store
+    auth_store: Arc<AuthStore>,
+    /// Auth info of the connection, `None` if no token is provided
+    auth_info: Option<AuthInfo>,
+    /// Watched ranges of the watchers, their permissions are checked again
+    /// once the permissions of users may have changed
+    key_ranges: HashMap<WatchId, Vec<KeyRange>>,
+    /// Auth revision and enabled status when the permissions are checked
+    checked: (i64, bool),
+    /// When the connection is terminated because its token expires, `None` if
+    /// it's kept after the token expires
+    expires_at: Option<Instant>,
+}
+
+impl WatchAuth {
+    /// New `WatchAuth`
+    fn new(auth_store: Arc<AuthStore>, auth_info: Option<AuthInfo>) -> Self {
+        let checked = (auth_store.revision(), auth_store.is_enabled());
+        Self {
+            auth_store,
+            auth_info,
+            key_ranges: HashMap::new(),
+            checked,
+            expires_at: None,
+        }
+    }
+
+    /// Terminate the connection when its token expires
+    fn with_expiration(mut self, expires_at: Option<Instant>) -> Self {
+        self.expires_at = expires_at;
+        self
+    }
+
+    /// Check if the connection is permitted to watch all the ranges
+    fn check(&self, key_ranges: &[KeyRange]) -> Result<(), ExecuteError> {
+        key_ranges.iter().try_for_each(|key_range| {
+            self.auth_store
+                .check_watch_permission(self.auth_info.as_ref(), key_range)
+        })
+    }
+
+    /// Get the watchers that are no longer permitted to read their ranges,
+    /// the permissions are only checked again if the auth store has changed
+    fn revoked_watchers(&mut self) -> Vec<(WatchId, ExecuteError)> {
+        let current = (self.auth_store.revision(), self.auth_store.is_enabled());
+        if current == self.checked {
+            return vec![];
+        }
+        self.checked = current;
+        self.key_ranges
+            .iter()
+            .filter_map(|(&watch_id, key_ranges)| {
+                self.check(key_ranges).err().map(|err| (watch_id, err))
+            })
+            .collect()
+    }
+}
+
 /// Handler for one watch connection
 #[derive(Debug)]
 struct WatchHandle<W>
@@ -131,24 +305,44 @@ where
     kv_watcher: Arc<W>,
     /// `WatchResponse` Sender
     response_tx: mpsc::Sender<Result<WatchResponse, tonic::Status>>,
+    /// Flow control window of the event responses
+    flow_control: Arc<FlowControl>,
     /// Event sender
     event_tx: mpsc::Sender<WatchEvent>,
-    /// Watch ID to watcher map
-    active_watch_ids: HashSet<WatchId>,
+    /// Watch ID to watcher map, the ids of the watchers in `KvWatcher` are
+    /// generated by the server, so the ids requested on different streams never
+    /// collide with each other
+    active_watch_ids: HashMap<WatchId, WatchId>,
+    /// Id of watchers in `KvWatcher` to the watch ids of this stream
+    watcher_ids: HashMap<WatchId, WatchId>,
     /// Next available `WatchId`
     next_id_gen: Arc<WatchIdGenerator>,
     /// Stop Event
     stop_notify: Arc<Event>,
     /// Header Generator
     header_gen: Arc<HeaderGenerator>,
-    /// Previous KV status
-    prev_kv: HashSet<WatchId>,
+    /// Previous KV status, the registrations keep the previous kvs captured
+    /// while syncing requests
+    prev_kv: HashMap<WatchId, PrevKvWatcher>,
+    /// Watchers whose large responses are split into fragments
+    fragment: HashSet<WatchId>,
     /// Progress status
     ///
     /// `true` means the next tick should be notified
     ///
     /// `false` means the next tick should be skipped
     progress: HashMap<WatchId, bool>,
+    /// Whether a requested progress is deferred until all watchers are synced
+    pending_progress: bool,
+    /// Events waiting to be flushed, the events of a watcher are coalesced
+    batched_events: Vec<WatchEvent>,
+    /// Revision of progress notifications, the synced watchers of this stream
+    /// have delivered all the events no later than it
+    progress_revision: i64,
+    /// Permission checks of the watchers, `None` disables the checks
+    auth: Option<WatchAuth>,
+    /// Cluster version
+    cluster_version: Arc<ClusterVersion>,
 }
 
 impl<W> WatchHandle<W>
@@ -159,22 +353,47 @@ where
     fn new(
         kv_watcher: Arc<W>,
         response_tx: mpsc::Sender<Result<WatchResponse, tonic::Status>>,
+        flow_control: Arc<FlowControl>,
         event_tx: mpsc::Sender<WatchEvent>,
         stop_notify: Arc<Event>,
         next_id_gen: Arc<WatchIdGenerator>,
         header_gen: Arc<HeaderGenerator>,
+        auth: Option<WatchAuth>,
+        cluster_version: Arc<ClusterVersion>,
     ) -> Self {
         Self {
             kv_watcher,
             response_tx,
+            flow_control,
             event_tx,
-            active_watch_ids: HashSet::new(),
+            active_watch_ids: HashMap::new(),
+            watcher_ids: HashMap::new(),
             next_id_gen,
             stop_notify,
             header_gen,
-            prev_kv: HashSet::new(),
+            prev_kv: HashMap::new(),
+            fragment: HashSet::new(),
             progress: HashMap::new(),
+            pending_progress: false,
+            batched_events: Vec::new(),
+            progress_revision: 0,
+            auth,
+            cluster_version,
+        }
+    }
+
+    /// Remove the states of a watcher that is canceled, return the id of the
+    /// watcher in `KvWatcher`
+    fn remove_watcher(&mut self, watch_id: WatchId) -> Option<WatchId> {
+        let _ignore = self.prev_kv.remove(&watch_id);
+        let _ignore = self.fragment.remove(&watch_id);
+        let _ignore = self.progress.remove(&watch_id);
+        if let Some(ref mut auth) = self.auth {
+            let _ignore = auth.key_ranges.remove(&watch_id);
         }
+        let watcher_id = self.active_watch_ids.remove(&watch_id)?;
+        let _ignore = self.watcher_ids.remove(&watcher_id);
+        Some(watcher_id)
     }
 
     /// Validate the given `watch_id`, return None if the given id is not available, will generate a new one if the given one equals 0
@@ -183,11 +402,11 @@ where
         if watch_id == 0 {
             loop {
                 let next = self.next_id_gen.next();
-                if !self.active_watch_ids.contains(&next) {
+                if !self.active_watch_ids.contains_key(&next) {
                     break Some(next);
                 }
             }
-        } else if self.active_watch_ids.contains(&watch_id) {
+        } else if self.active_watch_ids.contains_key(&watch_id) {
             None
         } else {
             Some(watch_id)
@@ -207,19 +426,51 @@ where
             return;
         };
 
-        let key_range = KeyRange::new(req.key, req.range_end);
-        self.kv_watcher.watch(
-            watch_id,
-            key_range,
+        // the extra ranges are watched by the same watcher, so the events of a
+        // revision in all the ranges are sent in one response
+        let key_ranges: Vec<_> = std::iter::once(KeyRange::new(req.key, req.range_end))
+            .chain(req.extra_ranges.into_iter().map(KeyRange::from))
+            .collect();
+        if let Some(ref mut auth) = self.auth {
+            if let Err(err) = auth.check(&key_ranges) {
+                let response = WatchResponse {
+                    header: Some(self.header_gen.gen_header()),
+                    watch_id,
+                    created: true,
+                    canceled: true,
+                    cancel_reason: err.to_string(),
+                    ..WatchResponse::default()
+                };
+                if self.response_tx.send(Ok(response)).await.is_err() {
+                    let _ignore = self.stop_notify.notify(1);
+                }
+                return;
+            }
+            let _prev = auth.key_ranges.insert(watch_id, key_ranges.clone());
+        }
+        if req.prev_kv {
+            // registered before the watcher, so that the events it receives from
+            // the dispatcher carry the captured previous kvs
+            assert!(
+                self.prev_kv
+                    .insert(watch_id, self.kv_watcher.register_prev_kv())
+                    .is_none(),
+                "WatchId {watch_id} already exists in prev_kv",
+            );
+        }
+        let watcher_id = self.next_id_gen.next();
+        let revision = self.kv_watcher.watch(
+            watcher_id,
+            key_ranges,
             req.start_revision,
             req.filters,
             Arc::clone(&self.stop_notify),
             self.event_tx.clone(),
         );
-        if req.prev_kv {
+        if req.fragment {
             assert!(
-                self.prev_kv.insert(watch_id),
-                "WatchId {watch_id} already exists in prev_kv",
+                self.fragment.insert(watch_id),
+                "WatchId {watch_id} already exists in fragment",
             );
         }
         if req.progress_notify {
@@ -229,12 +480,19 @@ where
             );
         }
         assert!(
-            self.active_watch_ids.insert(watch_id),
+            self.active_watch_ids.insert(watch_id, watcher_id).is_none(),
             "WatchId {watch_id} already exists in active_watch_ids",
         );
+        assert!(
+            self.watcher_ids.insert(watcher_id, watch_id).is_none(),
+            "Watcher {watcher_id} already exists in watcher_ids",
+        );
 
         let response = WatchResponse {
-            header: Some(self.header_gen.gen_header()),
+            header: Some(ResponseHeader {
+                revision,
+                ..self.header_gen.gen_header()
+            }),
             watch_id,
             created: true,
             ..WatchResponse::default()
@@ -247,9 +505,8 @@ where
     /// Handle `WatchCancelRequest`
     async fn handle_watch_cancel(&mut self, req: WatchCancelRequest) {
         let watch_id = req.watch_id;
-        let result = if self.active_watch_ids.remove(&watch_id) {
-            self.kv_watcher.cancel(watch_id);
-            let _prev = self.active_watch_ids.remove(&watch_id);
+        let result = if let Some(watcher_id) = self.remove_watcher(watch_id) {
+            self.kv_watcher.cancel(watcher_id);
             let response = WatchResponse {
                 header: Some(self.header_gen.gen_header()),
                 watch_id,
@@ -285,9 +542,37 @@ where
         }
     }
 
+    /// Cancel the watchers that are no longer permitted to read their ranges,
+    /// so they stop receiving events once the permissions are revoked
+    async fn cancel_revoked_watchers(&mut self) {
+        let Some(ref mut auth) = self.auth else {
+            return;
+        };
+        for (watch_id, err) in auth.revoked_watchers() {
+            let Some(watcher_id) = self.remove_watcher(watch_id) else {
+                continue;
+            };
+            self.kv_watcher.cancel(watcher_id);
+            let response = WatchResponse {
+                header: Some(self.header_gen.gen_header()),
+                watch_id,
+                canceled: true,
+                cancel_reason: err.to_string(),
+                ..WatchResponse::default()
+            };
+            if self.response_tx.send(Ok(response)).await.is_err() {
+                let _ignore = self.stop_notify.notify(1);
+            }
+        }
+    }
+
     /// Handle watch event
     async fn handle_watch_event(&mut self, mut watch_event: WatchEvent) {
-        let watch_id = watch_event.watch_id();
+        self.cancel_revoked_watchers().await;
+        let Some(&watch_id) = self.watcher_ids.get(&watch_event.watch_id()) else {
+            // the watch has been canceled
+            return;
+        };
         let mut response = WatchResponse {
             header: Some(ResponseHeader {
                 revision: watch_event.revision(),
@@ -299,15 +584,20 @@ where
         if watch_event.compacted() {
             response.compact_revision = self.kv_watcher.compacted_revision();
             response.canceled = true;
+            // the watcher is canceled by the server, so its id can be reused
+            let _ignore = self.remove_watcher(watch_id);
         } else {
             let mut events = watch_event.take_events();
             if events.is_empty() {
                 return;
             }
 
-            if self.prev_kv.contains(&watch_id) {
+            if self.prev_kv.contains_key(&watch_id) {
+                // the previous kv is captured while syncing requests, it is only
+                // looked up for the replayed events, the lease revocations and the
+                // events synced before the watcher was registered
                 for ev in &mut events {
-                    if !ev.is_create() {
+                    if !ev.is_create() && ev.prev_kv.is_none() {
                         let kv = ev
                             .kv
                             .as_ref()
@@ -315,24 +605,111 @@ where
                         ev.prev_kv = self.kv_watcher.get_prev_kv(kv);
                     }
                 }
+            } else {
+                for ev in &mut events {
+                    ev.prev_kv = None;
+                }
             }
             response.events = events;
         };
 
-        if self.response_tx.send(Ok(response)).await.is_err() {
-            let _ignore = self.stop_notify.notify(1);
+        let responses = if self.fragment.contains(&watch_id) {
+            fragment_response(response, MAX_FRAGMENT_SIZE)
+        } else {
+            vec![response]
+        };
+        for response in responses {
+            // the events of this stream are paused until the transport takes
+            // enough responses, the permits can't be acquired once the stream is closed
+            if self.flow_control.acquire(&response).await.is_err() {
+                let _ignore = self.stop_notify.notify(1);
+                break;
+            }
+            if self.response_tx.send(Ok(response)).await.is_err() {
+                let _ignore = self.stop_notify.notify(1);
+                break;
+            }
         }
         if let Some(progress) = self.progress.get_mut(&watch_id) {
             *progress = false;
         }
     }
 
+    /// Batch a watch event, it is coalesced with the batched event of the same
+    /// watcher
+    fn batch_watch_event(&mut self, watch_event: WatchEvent) {
+        let batched = self
+            .batched_events
+            .iter_mut()
+            .rev()
+            .find(|e| e.watch_id() == watch_event.watch_id());
+        let rest = match batched {
+            Some(batched) => batched.merge(watch_event).err(),
+            None => Some(watch_event),
+        };
+        self.batched_events.extend(rest);
+    }
+
+    /// Send all the batched events
+    async fn flush_batched_events(&mut self) {
+        for watch_event in std::mem::take(&mut self.batched_events) {
+            self.handle_watch_event(watch_event).await;
+        }
+    }
+
+    /// Send all the events dispatched to this stream so far, so a following
+    /// progress notification won't overtake any of them
+    async fn catch_up(&mut self, event_rx: &mut mpsc::Receiver<WatchEvent>) {
+        // events no later than the dispatched revision are already queued, a
+        // watcher is only synced after this stream has handled its queued events
+        let revision = self.kv_watcher.dispatched_revision();
+        self.flush_batched_events().await;
+        while let Ok(event) = event_rx.try_recv() {
+            self.handle_watch_event(event).await;
+        }
+        self.progress_revision = self.progress_revision.max(revision);
+    }
+
+    /// Generate the header of progress notifications
+    fn progress_header(&self) -> ResponseHeader {
+        ResponseHeader {
+            revision: self.progress_revision,
+            ..self.header_gen.gen_header()
+        }
+    }
+
     /// Handle progress for request
+    ///
+    /// The progress is only sent if all watchers of the stream are synced,
+    /// otherwise it is deferred to the following ticks. The request is ignored
+    /// until every member supports it, as the servers of older versions do.
     async fn handle_watch_progress(&mut self, _req: WatchProgressRequest) {
+        if !self
+            .cluster_version
+            .is_enabled(Feature::WatchProgressRequest)
+        {
+            debug!("ignore the progress request, it's not supported by the cluster version");
+            return;
+        }
+        self.pending_progress = true;
+        self.send_pending_progress().await;
+    }
+
+    /// Send the requested progress if all watchers are synced
+    async fn send_pending_progress(&mut self) {
+        if !self.pending_progress
+            || !self
+                .active_watch_ids
+                .values()
+                .all(|id| self.kv_watcher.is_synced(*id))
+        {
+            return;
+        }
+        self.pending_progress = false;
         if self
             .response_tx
             .send(Ok(WatchResponse {
-                header: Some(self.header_gen.gen_header()),
+                header: Some(self.progress_header()),
                 watch_id: -1,
                 ..Default::default()
             }))
@@ -344,35 +721,176 @@ where
     }
 
     /// Handle progress from tick
+    ///
+    /// Watchers that still have events to send are skipped, the revision in the
+    /// progress would be ahead of them.
     async fn handle_tick_progress(&mut self) {
+        self.send_pending_progress().await;
+        let header = self.progress_header();
         for (watch_id, progress) in &mut self.progress {
-            if *progress {
-                if self
-                    .response_tx
-                    .send(Ok(WatchResponse {
-                        header: Some(self.header_gen.gen_header()),
-                        watch_id: *watch_id,
-                        ..Default::default()
-                    }))
-                    .await
-                    .is_err()
-                {
-                    let _ignore = self.stop_notify.notify(1);
-                }
-            } else {
+            if !*progress {
                 *progress = true;
+                continue;
+            }
+            if !self
+                .active_watch_ids
+                .get(watch_id)
+                .map_or(true, |watcher_id| self.kv_watcher.is_synced(*watcher_id))
+            {
+                continue;
+            }
+            if self
+                .response_tx
+                .send(Ok(WatchResponse {
+                    header: Some(header.clone()),
+                    watch_id: *watch_id,
+                    ..Default::default()
+                }))
+                .await
+                .is_err()
+            {
+                let _ignore = self.stop_notify.notify(1);
             }
         }
     }
 }
 
+/// Split a response whose encoded size exceeds `max_size` into fragments, every
+/// fragment but the last one has the `fragment` flag set. Fragments are only
+/// split between revisions, all the events of a revision are always in the
+/// same fragment, so a single revision larger than `max_size` is still sent as
+/// a whole.
+fn fragment_response(mut response: WatchResponse, max_size: usize) -> Vec<WatchResponse> {
+    if response.encoded_len() <= max_size {
+        return vec![response];
+    }
+    let events = std::mem::take(&mut response.events);
+    response.fragment = true;
+    let base_size = response.encoded_len();
+    let mut fragments = vec![];
+    let mut events_in_fragment = vec![];
+    let mut size = base_size;
+    let mut events = events.into_iter().peekable();
+    while let Some(event) = events.next() {
+        let revision = event.kv.as_ref().map_or(0, |kv| kv.mod_revision);
+        let mut revision_events = vec![event];
+        while let Some(event) =
+            events.next_if(|e| e.kv.as_ref().map_or(0, |kv| kv.mod_revision) == revision)
+        {
+            revision_events.push(event);
+        }
+        let revision_size = revision_events.iter().fold(0_usize, |total, event| {
+            let len = event.encoded_len();
+            // one byte for the tag of `events`
+            total
+                .overflow_add(prost::length_delimiter_len(len))
+                .overflow_add(len)
+                .overflow_add(1)
+        });
+        if !events_in_fragment.is_empty() && size.overflow_add(revision_size) > max_size {
+            fragments.push(WatchResponse {
+                events: std::mem::take(&mut events_in_fragment),
+                ..response.clone()
+            });
+            size = base_size;
+        }
+        size = size.overflow_add(revision_size);
+        events_in_fragment.append(&mut revision_events);
+    }
+    fragments.push(WatchResponse {
+        events: events_in_fragment,
+        fragment: false,
+        ..response
+    });
+    fragments
+}
+
+/// Flow control window of the event responses of a watch stream, the events of
+/// the stream are paused once the responses not taken by the transport yet
+/// exceed the window
+#[derive(Debug)]
+pub(crate) struct FlowControl {
+    /// Permits of the bytes left in the window
+    permits: Semaphore,
+    /// Size of the window in bytes
+    window: usize,
+}
+
+impl FlowControl {
+    /// New `FlowControl` of the window size
+    pub(crate) fn new(window: usize) -> Self {
+        let window = window.min(Semaphore::MAX_PERMITS);
+        Self {
+            permits: Semaphore::new(window),
+            window,
+        }
+    }
+
+    /// Get the size of a response in the window, only the responses carrying
+    /// events are counted
+    fn cost(&self, response: &WatchResponse) -> u32 {
+        if response.events.is_empty() {
+            return 0;
+        }
+        response.encoded_len().min(self.window).numeric_cast()
+    }
+
+    /// Wait until the response fits in the window, fails once the window is
+    /// closed
+    async fn acquire(&self, response: &WatchResponse) -> Result<(), AcquireError> {
+        self.permits
+            .acquire_many(self.cost(response))
+            .await
+            .map(SemaphorePermit::forget)
+    }
+
+    /// Release a response taken by the transport from the window
+    fn release(&self, response: &WatchResponse) {
+        self.permits.add_permits(self.cost(response).numeric_cast());
+    }
+
+    /// Close the window
+    fn close(&self) {
+        self.permits.close();
+    }
+}
+
+/// Response stream of a watch connection, the event responses taken by the
+/// transport are released from the flow control window
+#[derive(Debug)]
+pub(crate) struct WatchResponseStream {
+    /// Responses sent by the watch task
+    inner: ReceiverStream<Result<WatchResponse, tonic::Status>>,
+    /// Flow control window of the event responses
+    flow_control: Arc<FlowControl>,
+}
+
+impl Stream for WatchResponseStream {
+    type Item = Result<WatchResponse, tonic::Status>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let item = self.inner.poll_next_unpin(cx);
+        if let Poll::Ready(Some(Ok(ref response))) = item {
+            self.flow_control.release(response);
+        }
+        item
+    }
+}
+
+impl Drop for WatchResponseStream {
+    fn drop(&mut self) {
+        // wake up the watch task waiting for the window
+        self.flow_control.close();
+    }
+}
+
 impl<W> Drop for WatchHandle<W>
 where
     W: KvWatcherOps,
 {
     fn drop(&mut self) {
-        for watch_id in &self.active_watch_ids {
-            self.kv_watcher.cancel(*watch_id);
+        for watcher_id in self.active_watch_ids.values() {
+            self.kv_watcher.cancel(*watcher_id);
         }
     }
 }
@@ -380,7 +898,7 @@ where
 #[tonic::async_trait]
 impl Watch for WatchServer {
     ///Server streaming response type for the Watch method.
-    type WatchStream = ReceiverStream<Result<WatchResponse, tonic::Status>>;
+    type WatchStream = WatchResponseStream;
 
     /// Watch watches for events happening or that have happened. Both input and output
     /// are streams; the input stream is for creating and canceling watchers and the output
@@ -392,20 +910,36 @@ impl Watch for WatchServer {
         request: tonic::Request<tonic::Streaming<WatchRequest>>,
     ) -> Result<tonic::Response<Self::WatchStream>, tonic::Status> {
         debug!("Receive Watch Connection {:?}", request);
+        let auth_info = self.auth_store.try_get_auth_info_from_request(&request)?;
+        // a stream kept after its token expires has the permissions validated
+        // again once the auth store changes
+        let expires_at = (self.stream_token_expiry == StreamTokenExpiry::Cancel)
+            .then(|| self.auth_store.token_expiration(&request))
+            .flatten();
+        let auth =
+            WatchAuth::new(Arc::clone(&self.auth_store), auth_info).with_expiration(expires_at);
         let req_stream = request.into_inner();
         let (tx, rx) = mpsc::channel(CHANNEL_SIZE);
+        let flow_control = Arc::new(FlowControl::new(self.flow_control_window));
         self.task_manager.spawn(TaskName::WatchTask, |n| {
             Self::task(
                 Arc::clone(&self.next_id_gen),
                 Arc::clone(&self.watcher),
                 tx,
+                Arc::clone(&flow_control),
                 req_stream,
                 Arc::clone(&self.header_gen),
+                Some(auth),
                 self.watch_progress_notify_interval,
+                self.watch_batch_interval,
+                Arc::clone(&self.cluster_version),
                 n,
             )
         });
-        Ok(tonic::Response::new(ReceiverStream::new(rx)))
+        Ok(tonic::Response::new(WatchResponseStream {
+            inner: ReceiverStream::new(rx),
+            flow_control,
+        }))
     }
 }
 
@@ -424,18 +958,26 @@ mod test {
         sync::mpsc,
         time::{sleep, timeout},
     };
-    use utils::config::{default_watch_progress_notify_interval, EngineConfig};
+    use utils::config::{
+        default_watch_flow_control_window, default_watch_progress_notify_interval, EngineConfig,
+    };
     use xlineapi::RequestWrapper;
 
     use super::*;
     use crate::{
         rpc::{PutRequest, WatchProgressRequest},
+        server::version::Version,
         storage::{
-            compact::COMPACT_CHANNEL_SIZE, db::DB, index::Index, kv_store::KvStoreInner,
-            kvwatcher::MockKvWatcherOps, lease_store::LeaseCollection, KvStore,
+            compact::COMPACT_CHANNEL_SIZE, compression::ValueCompression, db::DB, index::Index,
+            kv_store::KvStoreInner, kvwatcher::MockKvWatcherOps, lease_store::LeaseCollection,
+            KvStore,
         },
     };
 
+    fn cluster_version() -> Arc<ClusterVersion> {
+        Arc::new(ClusterVersion::new(Some(Version::current())))
+    }
+
     fn is_progress_notify(wr: &WatchResponse) -> bool {
         wr.events.is_empty()
             && !wr.canceled
@@ -444,6 +986,43 @@ mod test {
             && wr.header.as_ref().map_or(false, |h| h.revision != 0)
     }
 
+    /// Create a kv store and a kv watcher of it, along with the header generator
+    /// and the watch id generator of the watch server
+    fn init_kv_watcher(
+        task_manager: &TaskManager,
+    ) -> (
+        Arc<KvStore>,
+        Arc<KvWatcher>,
+        Arc<HeaderGenerator>,
+        Arc<WatchIdGenerator>,
+    ) {
+        let (compact_tx, _compact_rx) = flume::bounded(COMPACT_CHANNEL_SIZE);
+        let index = Arc::new(Index::new());
+        let db = DB::open(&EngineConfig::Memory).unwrap();
+        let header_gen = Arc::new(HeaderGenerator::new(0, 0));
+        let lease_collection = Arc::new(LeaseCollection::new(0));
+        let next_id_gen = Arc::new(WatchIdGenerator::new(1));
+        let (kv_update_tx, kv_update_rx) = flume::bounded(CHANNEL_SIZE);
+        let kv_store_inner = Arc::new(KvStoreInner::new(index, db));
+        let kv_store = Arc::new(KvStore::new(
+            Arc::clone(&kv_store_inner),
+            Arc::clone(&header_gen),
+            kv_update_tx,
+            compact_tx,
+            lease_collection,
+            ValueCompression::default(),
+            0,
+        ));
+        let kv_watcher = KvWatcher::new_arc(
+            kv_store_inner,
+            header_gen.general_revision_arc(),
+            kv_update_rx,
+            Duration::from_millis(10),
+            task_manager,
+        );
+        (kv_store, kv_watcher, header_gen, next_id_gen)
+    }
+
     fn put(store: &KvStore, key: impl Into<Vec<u8>>, value: impl Into<Vec<u8>>) {
         let req = RequestWrapper::from(PutRequest {
             key: key.into(),
@@ -474,11 +1053,14 @@ mod test {
             ReceiverStream::new(req_rx);
         let header_gen = Arc::new(HeaderGenerator::new(0, 0));
         let mut mock_watcher = MockKvWatcherOps::new();
-        let _ = mock_watcher.expect_watch().times(1).return_const(());
+        let _ = mock_watcher.expect_watch().times(1).return_const(0_i64);
         let _ = mock_watcher.expect_cancel().times(1).return_const(());
         let _ = mock_watcher
             .expect_compacted_revision()
             .return_const(-1_i64);
+        let _ = mock_watcher
+            .expect_dispatched_revision()
+            .return_const(0_i64);
         let watcher = Arc::new(mock_watcher);
         let next_id = Arc::new(WatchIdGenerator::new(1));
         let n = task_manager
@@ -488,9 +1070,13 @@ mod test {
             next_id,
             Arc::clone(&watcher),
             res_tx,
+            Arc::new(FlowControl::new(default_watch_flow_control_window())),
             req_stream,
             header_gen,
+            None,
             default_watch_progress_notify_interval(),
+            Duration::ZERO,
+            cluster_version(),
             n,
         ));
         req_tx
@@ -524,12 +1110,16 @@ mod test {
                 let mut c = collection_c.lock();
                 let e = c.entry(x).or_insert(0);
                 *e += 1;
+                0
             }
         });
         let _ = mock_watcher.expect_cancel().return_const(());
         let _ = mock_watcher
             .expect_compacted_revision()
             .return_const(-1_i64);
+        let _ = mock_watcher
+            .expect_dispatched_revision()
+            .return_const(0_i64);
         let kv_watcher = Arc::new(mock_watcher);
         let next_id_gen = Arc::new(WatchIdGenerator::new(1));
         let header_gen = Arc::new(HeaderGenerator::new(0, 0));
@@ -543,9 +1133,13 @@ mod test {
                 Arc::clone(&next_id_gen),
                 Arc::clone(&kv_watcher),
                 res_tx1,
+                Arc::new(FlowControl::new(default_watch_flow_control_window())),
                 req_stream1,
                 Arc::clone(&header_gen),
+                None,
                 default_watch_progress_notify_interval(),
+                Duration::ZERO,
+                cluster_version(),
                 n,
             )
         });
@@ -559,9 +1153,13 @@ mod test {
                 next_id_gen,
                 kv_watcher,
                 res_tx2,
+                Arc::new(FlowControl::new(default_watch_flow_control_window())),
                 req_stream2,
                 header_gen,
+                None,
                 default_watch_progress_notify_interval(),
+                Duration::ZERO,
+                cluster_version(),
                 n,
             )
         });
@@ -587,27 +1185,7 @@ mod test {
     #[abort_on_panic]
     async fn test_watch_prev_kv() {
         let task_manager = Arc::new(TaskManager::new());
-        let (compact_tx, _compact_rx) = flume::bounded(COMPACT_CHANNEL_SIZE);
-        let index = Arc::new(Index::new());
-        let db = DB::open(&EngineConfig::Memory).unwrap();
-        let header_gen = Arc::new(HeaderGenerator::new(0, 0));
-        let lease_collection = Arc::new(LeaseCollection::new(0));
-        let next_id_gen = Arc::new(WatchIdGenerator::new(1));
-        let (kv_update_tx, kv_update_rx) = flume::bounded(CHANNEL_SIZE);
-        let kv_store_inner = Arc::new(KvStoreInner::new(index, Arc::clone(&db)));
-        let kv_store = Arc::new(KvStore::new(
-            Arc::clone(&kv_store_inner),
-            Arc::clone(&header_gen),
-            kv_update_tx,
-            compact_tx,
-            lease_collection,
-        ));
-        let kv_watcher = KvWatcher::new_arc(
-            kv_store_inner,
-            kv_update_rx,
-            Duration::from_millis(10),
-            &task_manager,
-        );
+        let (kv_store, kv_watcher, header_gen, next_id_gen) = init_kv_watcher(&task_manager);
         put(&kv_store, "foo", "old_bar");
         put(&kv_store, "foo", "bar");
 
@@ -630,9 +1208,13 @@ mod test {
                 Arc::clone(&next_id_gen),
                 Arc::clone(&kv_watcher),
                 res_tx,
+                Arc::new(FlowControl::new(default_watch_flow_control_window())),
                 req_stream,
                 Arc::clone(&header_gen),
+                None,
                 default_watch_progress_notify_interval(),
+                Duration::ZERO,
+                cluster_version(),
                 n,
             )
         });
@@ -657,82 +1239,509 @@ mod test {
 
     #[tokio::test]
     #[abort_on_panic]
-    async fn test_watch_progress() -> Result<(), Box<dyn std::error::Error>> {
+    async fn events_should_be_paused_when_flow_control_window_is_full() {
         let task_manager = Arc::new(TaskManager::new());
+        let (kv_store, kv_watcher, header_gen, next_id_gen) = init_kv_watcher(&task_manager);
+
+        let window = default_watch_flow_control_window();
+        let flow_control = Arc::new(FlowControl::new(window));
+        // the responses not taken by the transport leave only one byte in the window
+        flow_control
+            .permits
+            .acquire_many((window - 1).numeric_cast())
+            .await
+            .unwrap()
+            .forget();
         let (req_tx, req_rx) = mpsc::channel(CHANNEL_SIZE);
-        let (res_tx, mut res_rx) = mpsc::channel(CHANNEL_SIZE);
-        let req_stream: ReceiverStream<Result<WatchRequest, tonic::Status>> =
-            ReceiverStream::new(req_rx);
-        let header_gen = Arc::new(HeaderGenerator::new(0, 0));
-        let mut mock_watcher = MockKvWatcherOps::new();
-        let _ = mock_watcher.expect_watch().times(1).return_const(());
-        let _ = mock_watcher.expect_cancel().times(1).return_const(());
-        let _ = mock_watcher
-            .expect_compacted_revision()
-            .return_const(-1_i64);
-        let watcher = Arc::new(mock_watcher);
-        let next_id = Arc::new(WatchIdGenerator::new(1));
+        let (res_tx, res_rx) = mpsc::channel(CHANNEL_SIZE);
         task_manager.spawn(TaskName::WatchTask, |n| {
             WatchServer::task(
-                next_id,
-                Arc::clone(&watcher),
+                next_id_gen,
+                kv_watcher,
                 res_tx,
-                req_stream,
+                Arc::clone(&flow_control),
+                ReceiverStream::new(req_rx),
                 header_gen,
-                Duration::from_millis(100),
+                None,
+                default_watch_progress_notify_interval(),
+                Duration::ZERO,
+                cluster_version(),
                 n,
             )
         });
+        let mut res_stream = WatchResponseStream {
+            inner: ReceiverStream::new(res_rx),
+            flow_control: Arc::clone(&flow_control),
+        };
         req_tx
             .send(Ok(WatchRequest {
                 request_union: Some(RequestUnion::CreateRequest(WatchCreateRequest {
                     key: "foo".into(),
-                    progress_notify: true,
-                    watch_id: 1,
                     ..Default::default()
                 })),
             }))
-            .await?;
-        req_tx
-            .send(Ok(WatchRequest {
-                request_union: Some(RequestUnion::ProgressRequest(WatchProgressRequest {})),
-            }))
-            .await?;
-        let cnt = Arc::new(AtomicI32::new(0));
+            .await
+            .unwrap();
+        assert!(res_stream.next().await.unwrap().unwrap().created);
 
-        let _ignore = timeout(Duration::from_secs(1), {
-            let cnt = Arc::clone(&cnt);
-            async move {
-                while let Some(Ok(res)) = res_rx.recv().await {
-                    if is_progress_notify(&res) {
-                        cnt.fetch_add(1, Ordering::Release);
-                    }
-                }
-            }
-        })
-        .await;
-        let c = cnt.load(Ordering::Acquire);
-        assert!(c >= 9);
+        put(&kv_store, "foo", "bar");
+        assert!(timeout(Duration::from_millis(500), res_stream.next())
+            .await
+            .is_err());
+
+        flow_control.permits.add_permits(window - 1);
+        let res = res_stream.next().await.unwrap().unwrap();
+        assert_eq!(res.events.len(), 1);
+        // the response taken by the transport is released from the window
+        assert_eq!(flow_control.permits.available_permits(), window);
         drop(req_tx);
+        drop(kv_store);
         task_manager.shutdown(true).await;
-        Ok(())
     }
 
     #[tokio::test]
-    async fn watch_task_should_terminate_when_response_tx_closed(
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    #[abort_on_panic]
+    async fn stream_paused_by_flow_control_should_not_block_shutdown() {
         let task_manager = Arc::new(TaskManager::new());
+        let (kv_store, kv_watcher, header_gen, next_id_gen) = init_kv_watcher(&task_manager);
+
+        // the window is too small for any event response
+        let flow_control = Arc::new(FlowControl::new(1));
+        flow_control.permits.acquire().await.unwrap().forget();
         let (req_tx, req_rx) = mpsc::channel(CHANNEL_SIZE);
         let (res_tx, res_rx) = mpsc::channel(CHANNEL_SIZE);
-        let req_stream: ReceiverStream<Result<WatchRequest, tonic::Status>> =
-            ReceiverStream::new(req_rx);
+        task_manager.spawn(TaskName::WatchTask, |n| {
+            WatchServer::task(
+                next_id_gen,
+                kv_watcher,
+                res_tx,
+                Arc::clone(&flow_control),
+                ReceiverStream::new(req_rx),
+                header_gen,
+                None,
+                default_watch_progress_notify_interval(),
+                Duration::ZERO,
+                cluster_version(),
+                n,
+            )
+        });
+        let mut res_stream = WatchResponseStream {
+            inner: ReceiverStream::new(res_rx),
+            flow_control: Arc::clone(&flow_control),
+        };
+        req_tx
+            .send(Ok(WatchRequest {
+                request_union: Some(RequestUnion::CreateRequest(WatchCreateRequest {
+                    key: "foo".into(),
+                    ..Default::default()
+                })),
+            }))
+            .await
+            .unwrap();
+        assert!(res_stream.next().await.unwrap().unwrap().created);
+
+        put(&kv_store, "foo", "bar");
+        assert!(timeout(Duration::from_millis(500), res_stream.next())
+            .await
+            .is_err());
+        // the stream is still open, but the task waiting for the window stops
+        drop(kv_store);
+        timeout(Duration::from_secs(3), task_manager.shutdown(true))
+            .await
+            .unwrap();
+        drop(req_tx);
+    }
+
+    #[tokio::test]
+    #[abort_on_panic]
+    async fn same_watch_id_on_different_streams_should_not_collide() {
+        let task_manager = Arc::new(TaskManager::new());
+        let (kv_store, kv_watcher, header_gen, next_id_gen) = init_kv_watcher(&task_manager);
+
+        let mut streams = vec![];
+        for key in ["foo", "bar"] {
+            let (req_tx, req_rx) = mpsc::channel(CHANNEL_SIZE);
+            let (res_tx, res_rx) = mpsc::channel(CHANNEL_SIZE);
+            task_manager.spawn(TaskName::WatchTask, |n| {
+                WatchServer::task(
+                    Arc::clone(&next_id_gen),
+                    Arc::clone(&kv_watcher),
+                    res_tx,
+                    Arc::new(FlowControl::new(default_watch_flow_control_window())),
+                    ReceiverStream::new(req_rx),
+                    Arc::clone(&header_gen),
+                    None,
+                    default_watch_progress_notify_interval(),
+                    Duration::ZERO,
+                    cluster_version(),
+                    n,
+                )
+            });
+            req_tx
+                .send(Ok(WatchRequest {
+                    request_union: Some(RequestUnion::CreateRequest(WatchCreateRequest {
+                        watch_id: 1,
+                        key: key.into(),
+                        ..Default::default()
+                    })),
+                }))
+                .await
+                .unwrap();
+            streams.push((key, req_tx, res_rx));
+        }
+        for &mut (_, _, ref mut res_rx) in &mut streams {
+            let res = res_rx.recv().await.unwrap().unwrap();
+            assert!(res.created);
+            assert_eq!(res.watch_id, 1);
+        }
+
+        put(&kv_store, "foo", "1");
+        put(&kv_store, "bar", "2");
+        for &mut (key, _, ref mut res_rx) in &mut streams {
+            let res = res_rx.recv().await.unwrap().unwrap();
+            assert_eq!(res.watch_id, 1);
+            assert_eq!(res.events.len(), 1);
+            assert_eq!(res.events[0].kv.as_ref().unwrap().key, key.as_bytes());
+        }
+
+        // canceling the watch of one stream keeps the other one
+        let (_, ref req_tx, ref mut res_rx) = streams[0];
+        req_tx
+            .send(Ok(WatchRequest {
+                request_union: Some(RequestUnion::CancelRequest(WatchCancelRequest {
+                    watch_id: 1,
+                })),
+            }))
+            .await
+            .unwrap();
+        assert!(res_rx.recv().await.unwrap().unwrap().canceled);
+        put(&kv_store, "foo", "3");
+        put(&kv_store, "bar", "4");
+        let (_, _, ref mut res_rx) = streams[1];
+        let res = res_rx.recv().await.unwrap().unwrap();
+        assert_eq!(res.watch_id, 1);
+        assert_eq!(res.events[0].kv.as_ref().unwrap().value, b"4");
+        assert!(timeout(Duration::from_millis(100), streams[0].2.recv())
+            .await
+            .is_err());
+        drop(kv_store);
+        task_manager.shutdown(true).await;
+    }
+
+    #[tokio::test]
+    #[abort_on_panic]
+    async fn events_within_batch_interval_should_be_coalesced() {
+        let task_manager = Arc::new(TaskManager::new());
+        let (kv_store, kv_watcher, header_gen, next_id_gen) = init_kv_watcher(&task_manager);
+
+        let (req_tx, req_rx) = mpsc::channel(CHANNEL_SIZE);
+        let (res_tx, mut res_rx) = mpsc::channel(CHANNEL_SIZE);
+        task_manager.spawn(TaskName::WatchTask, |n| {
+            WatchServer::task(
+                next_id_gen,
+                kv_watcher,
+                res_tx,
+                Arc::new(FlowControl::new(default_watch_flow_control_window())),
+                ReceiverStream::new(req_rx),
+                Arc::clone(&header_gen),
+                None,
+                default_watch_progress_notify_interval(),
+                Duration::from_millis(200),
+                cluster_version(),
+                n,
+            )
+        });
+        req_tx
+            .send(Ok(WatchRequest {
+                request_union: Some(RequestUnion::CreateRequest(WatchCreateRequest {
+                    watch_id: 1,
+                    key: "foo".into(),
+                    ..Default::default()
+                })),
+            }))
+            .await
+            .unwrap();
+        assert!(res_rx.recv().await.unwrap().unwrap().created);
+
+        for i in 0..3 {
+            put(&kv_store, "foo", format!("{i}"));
+        }
+        let res = res_rx.recv().await.unwrap().unwrap();
+        assert_eq!(res.watch_id, 1);
+        let revisions: Vec<_> = res
+            .events
+            .iter()
+            .map(|e| e.kv.as_ref().unwrap().mod_revision)
+            .collect();
+        assert_eq!(revisions, [2, 3, 4]);
+        assert_eq!(res.header.unwrap().revision, 4);
+        drop(kv_store);
+        task_manager.shutdown(true).await;
+    }
+
+    #[tokio::test]
+    #[abort_on_panic]
+    async fn test_watch_progress() -> Result<(), Box<dyn std::error::Error>> {
+        let task_manager = Arc::new(TaskManager::new());
+        let (req_tx, req_rx) = mpsc::channel(CHANNEL_SIZE);
+        let (res_tx, mut res_rx) = mpsc::channel(CHANNEL_SIZE);
+        let req_stream: ReceiverStream<Result<WatchRequest, tonic::Status>> =
+            ReceiverStream::new(req_rx);
+        let header_gen = Arc::new(HeaderGenerator::new(0, 0));
+        let mut mock_watcher = MockKvWatcherOps::new();
+        let _ = mock_watcher.expect_watch().times(1).return_const(0_i64);
+        let _ = mock_watcher.expect_cancel().times(1).return_const(());
+        let _ = mock_watcher
+            .expect_compacted_revision()
+            .return_const(-1_i64);
+        let _ = mock_watcher
+            .expect_dispatched_revision()
+            .return_const(0_i64);
+        let _ = mock_watcher.expect_is_synced().return_const(true);
+        let watcher = Arc::new(mock_watcher);
+        let next_id = Arc::new(WatchIdGenerator::new(1));
+        task_manager.spawn(TaskName::WatchTask, |n| {
+            WatchServer::task(
+                next_id,
+                Arc::clone(&watcher),
+                res_tx,
+                Arc::new(FlowControl::new(default_watch_flow_control_window())),
+                req_stream,
+                header_gen,
+                None,
+                Duration::from_millis(100),
+                Duration::ZERO,
+                cluster_version(),
+                n,
+            )
+        });
+        req_tx
+            .send(Ok(WatchRequest {
+                request_union: Some(RequestUnion::CreateRequest(WatchCreateRequest {
+                    key: "foo".into(),
+                    progress_notify: true,
+                    watch_id: 1,
+                    ..Default::default()
+                })),
+            }))
+            .await?;
+        req_tx
+            .send(Ok(WatchRequest {
+                request_union: Some(RequestUnion::ProgressRequest(WatchProgressRequest {})),
+            }))
+            .await?;
+        let cnt = Arc::new(AtomicI32::new(0));
+
+        let _ignore = timeout(Duration::from_secs(1), {
+            let cnt = Arc::clone(&cnt);
+            async move {
+                while let Some(Ok(res)) = res_rx.recv().await {
+                    if is_progress_notify(&res) {
+                        cnt.fetch_add(1, Ordering::Release);
+                    }
+                }
+            }
+        })
+        .await;
+        let c = cnt.load(Ordering::Acquire);
+        assert!(c >= 9);
+        drop(req_tx);
+        task_manager.shutdown(true).await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[abort_on_panic]
+    async fn progress_should_carry_dispatched_revision() -> Result<(), Box<dyn std::error::Error>> {
+        let task_manager = Arc::new(TaskManager::new());
+        let (req_tx, req_rx) = mpsc::channel(CHANNEL_SIZE);
+        let (res_tx, mut res_rx) = mpsc::channel(CHANNEL_SIZE);
+        let req_stream: ReceiverStream<Result<WatchRequest, tonic::Status>> =
+            ReceiverStream::new(req_rx);
+        let header_gen = Arc::new(HeaderGenerator::new(0, 0));
+        let mut mock_watcher = MockKvWatcherOps::new();
+        let _ = mock_watcher.expect_is_synced().return_const(true);
+        // the store may be ahead of the updates dispatched to the watchers
+        let _ = mock_watcher
+            .expect_dispatched_revision()
+            .return_const(3_i64);
+        let watcher = Arc::new(mock_watcher);
+        let next_id = Arc::new(WatchIdGenerator::new(1));
+        task_manager.spawn(TaskName::WatchTask, |n| {
+            WatchServer::task(
+                next_id,
+                Arc::clone(&watcher),
+                res_tx,
+                Arc::new(FlowControl::new(default_watch_flow_control_window())),
+                req_stream,
+                header_gen,
+                None,
+                default_watch_progress_notify_interval(),
+                Duration::ZERO,
+                cluster_version(),
+                n,
+            )
+        });
+        req_tx
+            .send(Ok(WatchRequest {
+                request_union: Some(RequestUnion::ProgressRequest(WatchProgressRequest {})),
+            }))
+            .await?;
+        let res = res_rx.recv().await.unwrap()?;
+        assert!(is_progress_notify(&res));
+        assert_eq!(res.watch_id, -1);
+        assert_eq!(res.header.unwrap().revision, 3);
+        drop(req_tx);
+        task_manager.shutdown(true).await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[abort_on_panic]
+    async fn progress_request_should_be_ignored_by_old_cluster_version(
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let task_manager = Arc::new(TaskManager::new());
+        let (req_tx, req_rx) = mpsc::channel(CHANNEL_SIZE);
+        let (res_tx, mut res_rx) = mpsc::channel(CHANNEL_SIZE);
+        let req_stream: ReceiverStream<Result<WatchRequest, tonic::Status>> =
+            ReceiverStream::new(req_rx);
         let header_gen = Arc::new(HeaderGenerator::new(0, 0));
         let mut mock_watcher = MockKvWatcherOps::new();
-        let _ = mock_watcher.expect_watch().times(1).return_const(());
+        let _ = mock_watcher.expect_is_synced().return_const(true);
+        let _ = mock_watcher
+            .expect_dispatched_revision()
+            .return_const(3_i64);
+        let watcher = Arc::new(mock_watcher);
+        let next_id = Arc::new(WatchIdGenerator::new(1));
+        // a member of the cluster does not support progress requests
+        let cluster_version = Arc::new(ClusterVersion::new(Version::parse("0.5")));
+        task_manager.spawn(TaskName::WatchTask, |n| {
+            WatchServer::task(
+                next_id,
+                Arc::clone(&watcher),
+                res_tx,
+                Arc::new(FlowControl::new(default_watch_flow_control_window())),
+                req_stream,
+                header_gen,
+                None,
+                default_watch_progress_notify_interval(),
+                Duration::ZERO,
+                cluster_version,
+                n,
+            )
+        });
+        req_tx
+            .send(Ok(WatchRequest {
+                request_union: Some(RequestUnion::ProgressRequest(WatchProgressRequest {})),
+            }))
+            .await?;
+        assert!(timeout(Duration::from_millis(100), res_rx.recv())
+            .await
+            .is_err());
+        drop(req_tx);
+        task_manager.shutdown(true).await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[abort_on_panic]
+    async fn progress_should_wait_for_unsynced_watchers() -> Result<(), Box<dyn std::error::Error>>
+    {
+        let task_manager = Arc::new(TaskManager::new());
+        let (req_tx, req_rx) = mpsc::channel(CHANNEL_SIZE);
+        let (res_tx, mut res_rx) = mpsc::channel(CHANNEL_SIZE);
+        let req_stream: ReceiverStream<Result<WatchRequest, tonic::Status>> =
+            ReceiverStream::new(req_rx);
+        let header_gen = Arc::new(HeaderGenerator::new(0, 0));
+        let mut mock_watcher = MockKvWatcherOps::new();
+        let _ = mock_watcher.expect_watch().times(2).return_const(0_i64);
+        let _ = mock_watcher.expect_cancel().return_const(());
+        let _ = mock_watcher
+            .expect_compacted_revision()
+            .return_const(-1_i64);
+        let _ = mock_watcher
+            .expect_dispatched_revision()
+            .return_const(0_i64);
+        let _ = mock_watcher.expect_is_synced().return_const(false);
+        let watcher = Arc::new(mock_watcher);
+        let next_id = Arc::new(WatchIdGenerator::new(1));
+        task_manager.spawn(TaskName::WatchTask, |n| {
+            WatchServer::task(
+                next_id,
+                Arc::clone(&watcher),
+                res_tx,
+                Arc::new(FlowControl::new(default_watch_flow_control_window())),
+                req_stream,
+                header_gen,
+                None,
+                Duration::from_millis(100),
+                Duration::ZERO,
+                cluster_version(),
+                n,
+            )
+        });
+        let create_req = WatchRequest {
+            request_union: Some(RequestUnion::CreateRequest(WatchCreateRequest {
+                key: "foo".into(),
+                progress_notify: true,
+                watch_id: 1,
+                ..Default::default()
+            })),
+        };
+        req_tx.send(Ok(create_req.clone())).await?;
+        req_tx
+            .send(Ok(WatchRequest {
+                request_union: Some(RequestUnion::ProgressRequest(WatchProgressRequest {})),
+            }))
+            .await?;
+        assert!(res_rx.recv().await.unwrap()?.created);
+        // the watcher is not synced, so neither progress is sent
+        assert!(timeout(Duration::from_millis(500), res_rx.recv())
+            .await
+            .is_err());
+
+        req_tx
+            .send(Ok(WatchRequest {
+                request_union: Some(RequestUnion::CancelRequest(WatchCancelRequest {
+                    watch_id: 1,
+                })),
+            }))
+            .await?;
+        assert!(res_rx.recv().await.unwrap()?.canceled);
+        // the deferred progress is sent once no watcher is behind
+        let res = res_rx.recv().await.unwrap()?;
+        assert!(is_progress_notify(&res));
+        assert_eq!(res.watch_id, -1);
+
+        // the canceled id can be reused
+        req_tx.send(Ok(create_req)).await?;
+        let res = res_rx.recv().await.unwrap()?;
+        assert!(res.created);
+        assert_eq!(res.watch_id, 1);
+        drop(req_tx);
+        task_manager.shutdown(true).await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn watch_task_should_terminate_when_response_tx_closed(
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let task_manager = Arc::new(TaskManager::new());
+        let (req_tx, req_rx) = mpsc::channel(CHANNEL_SIZE);
+        let (res_tx, res_rx) = mpsc::channel(CHANNEL_SIZE);
+        let req_stream: ReceiverStream<Result<WatchRequest, tonic::Status>> =
+            ReceiverStream::new(req_rx);
+        let header_gen = Arc::new(HeaderGenerator::new(0, 0));
+        let mut mock_watcher = MockKvWatcherOps::new();
+        let _ = mock_watcher.expect_watch().times(1).return_const(0_i64);
         let _ = mock_watcher.expect_cancel().times(1).return_const(());
         let _ = mock_watcher
             .expect_compacted_revision()
             .return_const(-1_i64);
+        let _ = mock_watcher
+            .expect_dispatched_revision()
+            .return_const(0_i64);
+        let _ = mock_watcher.expect_is_synced().return_const(true);
         let watcher = Arc::new(mock_watcher);
         let next_id = Arc::new(WatchIdGenerator::new(1));
         let n = task_manager
@@ -742,9 +1751,13 @@ mod test {
             next_id,
             Arc::clone(&watcher),
             res_tx,
+            Arc::new(FlowControl::new(default_watch_flow_control_window())),
             req_stream,
             header_gen,
+            None,
             Duration::from_millis(100),
+            Duration::ZERO,
+            cluster_version(),
             n,
         ));
 
@@ -773,29 +1786,128 @@ mod test {
     }
 
     #[tokio::test]
-    async fn watch_compacted_revision_should_fail() {
+    #[abort_on_panic]
+    async fn watch_task_should_terminate_when_token_expires(
+    ) -> Result<(), Box<dyn std::error::Error>> {
         let task_manager = Arc::new(TaskManager::new());
-        let (compact_tx, _compact_rx) = flume::bounded(COMPACT_CHANNEL_SIZE);
-        let index = Arc::new(Index::new());
-        let db = DB::open(&EngineConfig::Memory).unwrap();
+        let (_req_tx, req_rx) = mpsc::channel(CHANNEL_SIZE);
+        let (res_tx, mut res_rx) = mpsc::channel(CHANNEL_SIZE);
+        let req_stream: ReceiverStream<Result<WatchRequest, tonic::Status>> =
+            ReceiverStream::new(req_rx);
         let header_gen = Arc::new(HeaderGenerator::new(0, 0));
-        let lease_collection = Arc::new(LeaseCollection::new(0));
-        let next_id_gen = Arc::new(WatchIdGenerator::new(1));
-        let (kv_update_tx, kv_update_rx) = flume::bounded(CHANNEL_SIZE);
-        let kv_store_inner = Arc::new(KvStoreInner::new(index, Arc::clone(&db)));
-        let kv_store = Arc::new(KvStore::new(
-            Arc::clone(&kv_store_inner),
+        let auth_store = Arc::new(AuthStore::new(
+            Arc::new(LeaseCollection::new(0)),
+            None,
             Arc::clone(&header_gen),
-            kv_update_tx,
-            compact_tx,
-            lease_collection,
+            DB::open(&EngineConfig::Memory)?,
         ));
-        let kv_watcher = KvWatcher::new_arc(
-            kv_store_inner,
-            kv_update_rx,
-            Duration::from_millis(10),
-            &task_manager,
-        );
+        let auth = WatchAuth::new(auth_store, None)
+            .with_expiration(Some(Instant::now() + Duration::from_millis(100)));
+        let mut mock_watcher = MockKvWatcherOps::new();
+        let _ = mock_watcher
+            .expect_dispatched_revision()
+            .return_const(0_i64);
+        let n = task_manager
+            .get_shutdown_listener(TaskName::WatchTask)
+            .unwrap();
+        let handle = tokio::spawn(WatchServer::task(
+            Arc::new(WatchIdGenerator::new(1)),
+            Arc::new(mock_watcher),
+            res_tx,
+            Arc::new(FlowControl::new(default_watch_flow_control_window())),
+            req_stream,
+            header_gen,
+            Some(auth),
+            default_watch_progress_notify_interval(),
+            Duration::ZERO,
+            cluster_version(),
+            n,
+        ));
+
+        let res = timeout(Duration::from_secs(3), res_rx.recv())
+            .await?
+            .unwrap();
+        assert_eq!(res.unwrap_err().code(), tonic::Code::Unauthenticated);
+        assert!(timeout(Duration::from_secs(3), handle).await.is_ok());
+        task_manager.shutdown(true).await;
+        Ok(())
+    }
+
+    #[test]
+    fn large_response_should_be_fragmented() {
+        let event = |(key, mod_revision): (&str, i64)| crate::rpc::Event {
+            kv: Some(crate::rpc::KeyValue {
+                key: key.into(),
+                mod_revision,
+                value: vec![0; 100],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let response = WatchResponse {
+            watch_id: 1,
+            events: [("a", 2), ("b", 3), ("c", 4), ("d", 5), ("e", 6)]
+                .map(event)
+                .to_vec(),
+            ..Default::default()
+        };
+        let max_size = response.events.first().unwrap().encoded_len() * 2 + 32;
+
+        let fragments = fragment_response(response.clone(), max_size);
+        assert_eq!(fragments.len(), 3);
+        assert!(fragments.iter().all(|f| f.encoded_len() <= max_size));
+        assert!(fragments.iter().all(|f| f.watch_id == 1));
+        let flags: Vec<_> = fragments.iter().map(|f| f.fragment).collect();
+        assert_eq!(flags, [true, true, false]);
+        let events: Vec<_> = fragments.into_iter().flat_map(|f| f.events).collect();
+        assert_eq!(events, response.events);
+
+        // a single event larger than the limit is still sent
+        let fragments = fragment_response(response.clone(), 1);
+        assert_eq!(fragments.len(), 5);
+        assert_eq!(fragment_response(response.clone(), usize::MAX), [response]);
+    }
+
+    #[test]
+    fn fragments_should_not_split_a_revision() {
+        let event = |(key, mod_revision): (&str, i64)| crate::rpc::Event {
+            kv: Some(crate::rpc::KeyValue {
+                key: key.into(),
+                mod_revision,
+                value: vec![0; 100],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let response = WatchResponse {
+            watch_id: 1,
+            events: [("a", 2), ("b", 2), ("c", 2), ("a", 3), ("b", 3)]
+                .map(event)
+                .to_vec(),
+            ..Default::default()
+        };
+        let max_size = response.events.first().unwrap().encoded_len() * 2 + 32;
+
+        let fragments = fragment_response(response.clone(), max_size);
+        let revisions: Vec<Vec<_>> = fragments
+            .iter()
+            .map(|f| {
+                f.events
+                    .iter()
+                    .map(|e| e.kv.as_ref().unwrap().mod_revision)
+                    .collect()
+            })
+            .collect();
+        assert_eq!(revisions, [vec![2, 2, 2], vec![3, 3]]);
+        let flags: Vec<_> = fragments.iter().map(|f| f.fragment).collect();
+        assert_eq!(flags, [true, false]);
+        assert_eq!(fragment_response(response.clone(), 1).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn watch_compacted_revision_should_fail() {
+        let task_manager = Arc::new(TaskManager::new());
+        let (kv_store, kv_watcher, header_gen, next_id_gen) = init_kv_watcher(&task_manager);
         put(&kv_store, "foo", "old_bar");
         put(&kv_store, "foo", "bar");
         put(&kv_store, "foo", "new_bar");
@@ -819,9 +1931,13 @@ mod test {
                 Arc::clone(&next_id_gen),
                 Arc::clone(&kv_watcher),
                 res_tx,
+                Arc::new(FlowControl::new(default_watch_flow_control_window())),
                 req_stream,
                 Arc::clone(&header_gen),
+                None,
                 default_watch_progress_notify_interval(),
+                Duration::ZERO,
+                cluster_version(),
                 n,
             )
         });
@@ -845,6 +1961,15 @@ mod test {
         assert!(!watch_event_res.canceled);
         assert_eq!(watch_event_res.compact_revision, 0);
         assert_eq!(watch_event_res.watch_id, 2);
+
+        req_tx.send(Ok(create_watch_req(3, -1))).await.unwrap();
+        let watch_create_success_res = res_rx.recv().await.unwrap().unwrap();
+        assert!(watch_create_success_res.created);
+        assert_eq!(watch_create_success_res.watch_id, 3);
+        let watch_cancel_res = res_rx.recv().await.unwrap().unwrap();
+        assert!(watch_cancel_res.canceled);
+        assert_eq!(watch_cancel_res.watch_id, 3);
+        assert_eq!(watch_cancel_res.compact_revision, 3);
         drop(kv_store);
         task_manager.shutdown(true).await;
     }
@@ -1,17 +1,22 @@
 use std::{sync::Arc, time::Duration};
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use clippy_utilities::{NumericCast, OverflowArithmetic};
 use curp::{
     client::ClientBuilder as CurpClientBuilder,
-    members::{get_cluster_info_from_remote, ClusterInfo},
+    members::{fetch_cluster_from_remote, get_cluster_info_from_remote, ClusterInfo},
     rpc::{InnerProtocolServer, ProtocolServer},
     server::{Rpc, StorageApi as _, DB as CurpDB},
 };
 use dashmap::DashMap;
-use engine::{MemorySnapshotAllocator, RocksSnapshotAllocator, SnapshotAllocator};
+use engine::{
+    KeyProvider, MemorySnapshotAllocator, RocksSnapshotAllocator, SnapshotAllocator,
+    StaticKeyProvider,
+};
 #[cfg(not(madsim))]
 use futures::Stream;
+use futures::{pin_mut, StreamExt};
+use itertools::Itertools;
 use jsonwebtoken::{DecodingKey, EncodingKey};
 use tokio::fs;
 #[cfg(not(madsim))]
@@ -22,27 +27,37 @@ use tonic::transport::{
 };
 use tonic::transport::{server::Router, Server};
 use tracing::{info, warn};
+#[cfg(not(madsim))]
+use utils::config::BackupTarget;
 use utils::{
     barrier::IdBarrier,
     config::{
-        AuthConfig, ClusterConfig, CompactConfig, EngineConfig, InitialClusterState, StorageConfig,
-        TlsConfig,
+        AuthConfig, ClusterConfig, CompactConfig, EngineConfig, InitialClusterState, ServerTimeout,
+        StorageConfig, TlsConfig,
     },
     task_manager::{tasks::TaskName, TaskManager},
 };
 #[cfg(madsim)]
 use utils::{ClientTlsConfig, ServerTlsConfig};
-use xlineapi::command::{Command, CurpClient};
+use xlineapi::{
+    command::{Command, CurpClient},
+    request_validation::SizeLimits,
+};
 
+#[cfg(not(madsim))]
+use super::backup::{backup_task, BackupStore, DirBackupStore, ObjectBackupStore};
+#[cfg(not(madsim))]
+use super::peer_identity::PeerIdentityInterceptor;
 use super::{
     auth_server::AuthServer,
     auth_wrapper::AuthWrapper,
     cluster_server::ClusterServer,
-    command::{Alarmer, CommandExecutor},
+    command::{Alarmer, CommandExecutor, QuotaGuard},
     kv_server::KvServer,
     lease_server::LeaseServer,
     lock_server::LockServer,
-    maintenance::MaintenanceServer,
+    maintenance::{corrupt_check_task, MaintenanceServer},
+    version::{monitor_version_task, ClusterVersion},
     watch_server::{WatchServer, CHANNEL_SIZE},
 };
 use crate::{
@@ -50,6 +65,7 @@ use crate::{
     header_gen::HeaderGenerator,
     id_gen::IdGenerator,
     metrics::Metrics,
+    restore::restore_on_startup,
     rpc::{
         AuthServer as RpcAuthServer, ClusterServer as RpcClusterServer, KvServer as RpcKvServer,
         LeaseServer as RpcLeaseServer, LockServer as RpcLockServer,
@@ -57,7 +73,9 @@ use crate::{
     },
     state::State,
     storage::{
+        cold_tier::cold_tier_task,
         compact::{auto_compactor, compact_bg_task, COMPACT_CHANNEL_SIZE},
+        compression::ValueCompression,
         db::DB,
         index::Index,
         kv_store::KvStoreInner,
@@ -88,6 +106,14 @@ pub struct XlineServer {
     /// Server tls config
     #[cfg_attr(madsim, allow(unused))]
     server_tls_config: Option<ServerTlsConfig>,
+    /// Tls config of the connections to the peers
+    peer_client_tls_config: Option<ClientTlsConfig>,
+    /// Tls config of the listener of the peers
+    #[cfg_attr(madsim, allow(unused))]
+    peer_server_tls_config: Option<ServerTlsConfig>,
+    /// Whether to check the identity of the peers by their certificates
+    #[cfg_attr(madsim, allow(unused))]
+    verify_peer_identity: bool,
     /// Task Manager
     task_manager: Arc<TaskManager>,
     /// Curp storage
@@ -112,12 +138,25 @@ impl XlineServer {
         let (client_tls_config, server_tls_config) = Self::read_tls_config(&tls_config).await?;
         #[cfg(madsim)]
         let (client_tls_config, server_tls_config) = (None, None);
-        let curp_storage = Arc::new(CurpDB::open(&cluster_config.curp_config().engine_cfg)?);
+        #[cfg(not(madsim))]
+        let (peer_client_tls_config, peer_server_tls_config) =
+            match Self::read_curp_tls_config(&tls_config).await? {
+                Some((client, server)) => (Some(client), Some(server)),
+                None => (client_tls_config.clone(), server_tls_config.clone()),
+            };
+        #[cfg(madsim)]
+        let (peer_client_tls_config, peer_server_tls_config) = (None, None);
+        let verify_peer_identity =
+            tls_config.curp_tls_enabled() && *tls_config.curp_client_cert_auth();
+        let curp_storage = Arc::new(
+            CurpDB::open(&cluster_config.curp_config().engine_cfg)?
+                .with_wal_sync_max_delay(cluster_config.curp_config().wal_sync_max_delay),
+        );
         let cluster_info = Arc::new(
             Self::init_cluster_info(
                 &cluster_config,
                 curp_storage.as_ref(),
-                client_tls_config.as_ref(),
+                peer_client_tls_config.as_ref(),
             )
             .await?,
         );
@@ -129,6 +168,9 @@ impl XlineServer {
             auth_config,
             client_tls_config,
             server_tls_config,
+            peer_client_tls_config,
+            peer_server_tls_config,
+            verify_peer_identity,
             task_manager: Arc::new(TaskManager::new()),
             curp_storage,
         })
@@ -153,12 +195,38 @@ impl XlineServer {
         ) {
             (Some(cluster_info), _) => {
                 info!("get cluster_info from local");
+                Self::check_recovered_cluster_info(
+                    &cluster_info,
+                    cluster_config,
+                    *cluster_config.client_config().wait_synced_timeout(),
+                    tls_config,
+                )
+                .await?;
                 Ok(cluster_info)
             }
             (None, InitialClusterState::New) => {
                 info!("get cluster_info by args");
                 let cluster_info =
                     ClusterInfo::from_members_map(all_members, self_client_urls, &name);
+                // a member with a persistent storage is only bootstrapped once, a bootstrapped
+                // member without cluster info has lost its data
+                let persistent = !matches!(
+                    cluster_config.curp_config().engine_cfg,
+                    EngineConfig::Memory
+                );
+                if persistent
+                    && Self::is_bootstrapped(
+                        &cluster_info,
+                        *cluster_config.client_config().wait_synced_timeout(),
+                        tls_config,
+                    )
+                    .await
+                {
+                    return Err(anyhow!(
+                        "member {name} has already been bootstrapped, its data may have been \
+                        lost, remove and add it again with initial cluster state existing"
+                    ));
+                }
                 curp_storage.put_cluster_info(&cluster_info)?;
                 Ok(cluster_info)
             }
@@ -172,7 +240,12 @@ impl XlineServer {
                     tls_config,
                 )
                 .await
-                .ok_or_else(|| anyhow!("Failed to get cluster info from remote"))?;
+                .ok_or_else(|| {
+                    anyhow!(
+                        "failed to join the existing cluster, no peer is reachable or member \
+                        {name} with peer urls {self_peer_urls:?} has not been added to it"
+                    )
+                })?;
                 curp_storage.put_cluster_info(&cluster_info)?;
                 Ok(cluster_info)
             }
@@ -182,19 +255,105 @@ impl XlineServer {
         }
     }
 
+    /// Check that the cluster info recovered from the data dir belongs to the
+    /// local member, and that the reachable peers are still in the same cluster,
+    /// so a member never rejoins with the identity of another member or the
+    /// data of another cluster
+    async fn check_recovered_cluster_info(
+        cluster_info: &ClusterInfo,
+        cluster_config: &ClusterConfig,
+        timeout: Duration,
+        tls_config: Option<&ClientTlsConfig>,
+    ) -> Result<()> {
+        let name = cluster_config.name();
+        let cluster_id = cluster_info.cluster_id();
+        let Some(member) = cluster_info
+            .get(&cluster_info.self_id())
+            .map(|member| member.value().clone())
+        else {
+            return Err(anyhow!(
+                "member {name} has been removed from cluster {cluster_id:x}, remove its data dir \
+                and add it again with initial cluster state existing"
+            ));
+        };
+        if member.name != *name {
+            return Err(anyhow!(
+                "the data dir belongs to member {}, not {name}",
+                member.name
+            ));
+        }
+        let peer_urls = cluster_config.peer_advertise_urls();
+        if !member
+            .peer_urls
+            .iter()
+            .sorted()
+            .eq(peer_urls.iter().sorted())
+        {
+            return Err(anyhow!(
+                "the data dir belongs to member {name} with peer urls {:?}, but it advertises \
+                {peer_urls:?}, update the member before changing its peer urls",
+                member.peer_urls
+            ));
+        }
+        let responses = fetch_cluster_from_remote(cluster_info, timeout, tls_config);
+        pin_mut!(responses);
+        while let Some(cluster_res) = responses.next().await {
+            if cluster_res.cluster_id != cluster_id {
+                return Err(anyhow!(
+                    "the data dir of member {name} belongs to cluster {cluster_id:x}, but its \
+                    peers are in cluster {:x}",
+                    cluster_res.cluster_id
+                ));
+            }
+        }
+        info!(
+            "member {name} rejoins cluster {cluster_id:x} with id {}",
+            member.id
+        );
+        Ok(())
+    }
+
+    /// Check whether the local member has been published to the cluster by a
+    /// previous start, the peers may be unreachable when the cluster is being
+    /// bootstrapped
+    async fn is_bootstrapped(
+        cluster_info: &ClusterInfo,
+        timeout: Duration,
+        tls_config: Option<&ClientTlsConfig>,
+    ) -> bool {
+        let self_id = cluster_info.self_id();
+        let responses = fetch_cluster_from_remote(cluster_info, timeout, tls_config);
+        pin_mut!(responses);
+        while let Some(cluster_res) = responses.next().await {
+            if cluster_res
+                .members
+                .iter()
+                .any(|m| m.id == self_id && !m.client_urls.is_empty())
+            {
+                return true;
+            }
+        }
+        false
+    }
+
     /// Construct a `LeaseCollection`
     #[inline]
     #[allow(clippy::arithmetic_side_effects)] // never overflow
     fn construct_lease_collection(
         heartbeat_interval: Duration,
         candidate_timeout_ticks: u8,
+        server_timeout: &ServerTimeout,
     ) -> Arc<LeaseCollection> {
-        let min_ttl = 3 * heartbeat_interval * candidate_timeout_ticks.numeric_cast() / 2;
+        let min_ttl = (3 * heartbeat_interval * candidate_timeout_ticks.numeric_cast() / 2)
+            .max(*server_timeout.min_lease_ttl());
         // Safe ceiling
         let min_ttl_secs = min_ttl
             .as_secs()
             .overflow_add(u64::from(min_ttl.subsec_nanos() > 0));
-        Arc::new(LeaseCollection::new(min_ttl_secs.numeric_cast()))
+        Arc::new(
+            LeaseCollection::new(min_ttl_secs.numeric_cast())
+                .with_max_ttl(server_timeout.max_lease_ttl().as_secs().numeric_cast()),
+        )
     }
 
     /// Construct underlying storages, including `KvStore`, `LeaseStore`,
@@ -207,6 +366,7 @@ impl XlineServer {
         lease_collection: Arc<LeaseCollection>,
         header_gen: Arc<HeaderGenerator>,
         key_pair: Option<(EncodingKey, DecodingKey)>,
+        cluster_version: Arc<ClusterVersion>,
     ) -> Result<(
         Arc<KvStore>,
         Arc<LeaseStore>,
@@ -218,13 +378,21 @@ impl XlineServer {
         let index = Arc::new(Index::new());
         let (kv_update_tx, kv_update_rx) = flume::bounded(CHANNEL_SIZE);
         let kv_store_inner = Arc::new(KvStoreInner::new(Arc::clone(&index), Arc::clone(&db)));
-        let kv_storage = Arc::new(KvStore::new(
-            Arc::clone(&kv_store_inner),
-            Arc::clone(&header_gen),
-            kv_update_tx.clone(),
-            compact_task_tx,
-            Arc::clone(&lease_collection),
-        ));
+        let kv_storage = Arc::new(
+            KvStore::new(
+                Arc::clone(&kv_store_inner),
+                Arc::clone(&header_gen),
+                kv_update_tx.clone(),
+                compact_task_tx,
+                Arc::clone(&lease_collection),
+                ValueCompression::new(
+                    self.storage_config.value_compression_threshold,
+                    self.storage_config.value_compression_codec,
+                ),
+                self.storage_config.kv_cache_capacity,
+            )
+            .with_max_range_bytes(self.storage_config.max_range_bytes),
+        );
         self.task_manager.spawn(TaskName::CompactBg, |n| {
             compact_bg_task(
                 Arc::clone(&kv_storage),
@@ -248,10 +416,20 @@ impl XlineServer {
             Arc::clone(&header_gen),
             Arc::clone(&db),
         ));
-        let alarm_storage = Arc::new(AlarmStore::new(header_gen, db));
+        let isolated_member = self
+            .storage_config
+            .isolate_corrupted_member
+            .then(|| self.cluster_info.self_id());
+        let alarm_storage = Arc::new(AlarmStore::new(
+            Arc::clone(&header_gen),
+            db,
+            isolated_member,
+            cluster_version,
+        ));
 
         let watcher = KvWatcher::new_arc(
             kv_store_inner,
+            header_gen.general_revision_arc(),
             kv_update_rx,
             *self.cluster_config.server_timeout().sync_victims_interval(),
             &self.task_manager,
@@ -281,6 +459,14 @@ impl XlineServer {
         )
     }
 
+    /// Get the size limits of keys and values
+    fn size_limits(&self) -> SizeLimits {
+        SizeLimits::new(
+            self.storage_config.max_key_bytes,
+            self.storage_config.max_value_bytes,
+        )
+    }
+
     /// Init xline and curp router
     ///
     /// # Errors
@@ -309,8 +495,12 @@ impl XlineServer {
         if let Some(ref cfg) = self.server_tls_config {
             builder = builder.tls_config(cfg.clone())?;
         }
+        let mut peer_builder = Server::builder();
+        #[cfg(not(madsim))]
+        if let Some(ref cfg) = self.peer_server_tls_config {
+            peer_builder = peer_builder.tls_config(cfg.clone())?;
+        }
         let xline_router = builder
-            .clone()
             .add_service(RpcLockServer::new(lock_server))
             .add_service(RpcKvServer::new(kv_server))
             .add_service(RpcLeaseServer::from_arc(lease_server))
@@ -319,9 +509,18 @@ impl XlineServer {
             .add_service(RpcMaintenanceServer::new(maintenance_server))
             .add_service(RpcClusterServer::new(cluster_server))
             .add_service(ProtocolServer::new(auth_wrapper));
-        let curp_router = builder
-            .add_service(ProtocolServer::new(curp_server.clone()))
-            .add_service(InnerProtocolServer::new(curp_server));
+        let curp_router = peer_builder.add_service(ProtocolServer::new(curp_server.clone()));
+        #[cfg(not(madsim))]
+        let curp_router = if self.verify_peer_identity {
+            curp_router.add_service(InnerProtocolServer::with_interceptor(
+                curp_server,
+                PeerIdentityInterceptor::new(Arc::clone(&self.cluster_info)),
+            ))
+        } else {
+            curp_router.add_service(InnerProtocolServer::new(curp_server))
+        };
+        #[cfg(madsim)]
+        let curp_router = curp_router.add_service(InnerProtocolServer::new(curp_server));
         #[cfg(not(madsim))]
         let xline_router = {
             let (mut reporter, health_server) = tonic_health::server::health_reporter();
@@ -350,7 +549,7 @@ impl XlineServer {
             .get_shutdown_listener(TaskName::TonicServer)
             .unwrap_or_else(|| unreachable!("cluster should never shutdown before start"));
         let n2 = n1.clone();
-        let db = DB::open(&self.storage_config.engine)?;
+        let db = self.open_db().await?;
         let key_pair = Self::read_key_pair(&self.auth_config).await?;
         let (xline_router, curp_router, curp_client) = self.init_router(db, key_pair).await?;
         let handle = tokio::spawn(async move {
@@ -366,6 +565,63 @@ impl XlineServer {
         Ok(handle)
     }
 
+    /// Open the storage, whose values are encrypted if the encryption key file
+    /// is configured, and restore it from the snapshot if it is configured and
+    /// the storage is empty
+    async fn open_db(&self) -> Result<Arc<DB>> {
+        let key_provider = match self.storage_config.encryption_key_file {
+            Some(ref path) => {
+                let provider = StaticKeyProvider::from_file(path).map_err(|e| {
+                    anyhow!("cannot load encryption keys from {}: {e}", path.display())
+                })?;
+                Some(Arc::new(provider) as Arc<dyn KeyProvider>)
+            }
+            None => None,
+        };
+        let db = DB::open_with_key_provider(&self.storage_config.engine, key_provider.clone())?;
+        for (table, compression) in &self.storage_config.compression {
+            db.set_compression(table, *compression)?;
+        }
+        db.set_durability(self.storage_config.durability)?;
+        db.set_max_unpersisted_entries(
+            self.cluster_config
+                .curp_config()
+                .log_entries_cap
+                .numeric_cast(),
+        );
+        if let Some(ref cold_tier_config) = self.storage_config.cold_tier {
+            if !matches!(self.storage_config.engine, EngineConfig::RocksDB(_)) {
+                bail!("the cold tier requires the rocksdb engine");
+            }
+            db.attach_cold_tier(cold_tier_config.dir(), key_provider)
+                .map_err(|e| {
+                    anyhow!(
+                        "cannot attach the cold tier in {}: {e}",
+                        cold_tier_config.dir().display()
+                    )
+                })?;
+        }
+        if let Some(ref snapshot_path) = self.storage_config.restore_from {
+            if !matches!(self.storage_config.engine, EngineConfig::RocksDB(_)) {
+                bail!("restore from a snapshot requires the rocksdb engine");
+            }
+            let skip_hash_check = self.storage_config.skip_restore_hash_check;
+            match restore_on_startup(snapshot_path, &db, skip_hash_check)
+                .await
+                .map_err(|e| anyhow!("cannot restore from {}: {e}", snapshot_path.display()))?
+            {
+                Some(summary) => info!(
+                    "restored {} keys at revision {} from {}",
+                    summary.count,
+                    summary.revision,
+                    snapshot_path.display()
+                ),
+                None => info!("storage is not empty, skip restoring from the snapshot"),
+            }
+        }
+        Ok(db)
+    }
+
     /// inner start method shared by `start` and `start_from_listener`
     #[cfg(not(madsim))]
     async fn start_inner<I1, I2, IO, IE>(&self, xline_incoming: I1, curp_incoming: I2) -> Result<()>
@@ -376,7 +632,7 @@ impl XlineServer {
         IO::ConnectInfo: Clone + Send + Sync + 'static,
         IE: Into<Box<dyn std::error::Error + Send + Sync>> + Send,
     {
-        let db = DB::open(&self.storage_config.engine)?;
+        let db = self.open_db().await?;
         let key_pair = Self::read_key_pair(&self.auth_config).await?;
         let (xline_router, curp_router, curp_client) = self.init_router(db, key_pair).await?;
         self.task_manager
@@ -455,7 +711,9 @@ impl XlineServer {
         let lease_collection = Self::construct_lease_collection(
             self.cluster_config.curp_config().heartbeat_interval,
             self.cluster_config.curp_config().candidate_timeout_ticks,
+            self.cluster_config.server_timeout(),
         );
+        let cluster_version = Arc::new(ClusterVersion::new(None));
 
         let (kv_storage, lease_storage, auth_storage, alarm_storage, watcher) = self
             .construct_underlying_storages(
@@ -463,6 +721,7 @@ impl XlineServer {
                 Arc::clone(&lease_collection),
                 Arc::clone(&header_gen),
                 key_pair,
+                Arc::clone(&cluster_version),
             )
             .await?;
 
@@ -477,6 +736,7 @@ impl XlineServer {
             Arc::clone(&id_barrier),
             Arc::clone(&compact_events),
             self.storage_config.quota,
+            self.size_limits(),
         ));
         let snapshot_allocator: Box<dyn SnapshotAllocator> = match self.storage_config.engine {
             EngineConfig::Memory => Box::<MemorySnapshotAllocator>::default(),
@@ -502,10 +762,13 @@ impl XlineServer {
 
         let auto_compactor_c = auto_compactor.clone();
 
-        let state = State::new(Arc::clone(&lease_storage), auto_compactor);
-
         let curp_config = Arc::new(self.cluster_config.curp_config().clone());
 
+        let election_timeout = curp_config
+            .heartbeat_interval
+            .saturating_mul(curp_config.follower_timeout_ticks.into());
+        let state = State::new(Arc::clone(&lease_storage), auto_compactor, election_timeout);
+
         let curp_server = CurpServer::new(
             Arc::clone(&self.cluster_info),
             *self.cluster_config.is_leader(),
@@ -515,14 +778,14 @@ impl XlineServer {
             Arc::clone(&curp_config),
             Arc::clone(&self.curp_storage),
             Arc::clone(&self.task_manager),
-            self.client_tls_config.clone(),
+            self.peer_client_tls_config.clone(),
             XlineSpeculativePools::new(Arc::clone(&lease_collection)).into_inner(),
             XlineUncommittedPools::new(lease_collection).into_inner(),
         );
 
         let client = Arc::new(
             CurpClientBuilder::new(*self.cluster_config.client_config(), false)
-                .tls_config(self.client_tls_config.clone())
+                .tls_config(self.peer_client_tls_config.clone())
                 .cluster_version(self.cluster_info.cluster_version())
                 .all_members(self.cluster_info.all_members_peer_urls())
                 .bypass(self.cluster_info.self_id(), curp_server.clone())
@@ -532,22 +795,71 @@ impl XlineServer {
         if let Some(compactor) = auto_compactor_c {
             compactor.set_compactable(Arc::clone(&client)).await;
         }
-        ce.set_alarmer(Alarmer::new(
-            self.cluster_info.self_id(),
-            Arc::clone(&client),
-        ));
+        let alarmer = Alarmer::new(self.cluster_info.self_id(), Arc::clone(&client));
+        ce.set_alarmer(alarmer.clone());
+        let quota_guard = QuotaGuard::new(ce.quota_checker(), Arc::clone(&db), alarmer);
         let raw_curp = curp_server.raw_curp();
 
-        Metrics::register_callback()?;
+        Metrics::register_callback(Arc::clone(&db))?;
 
         let server_timeout = self.cluster_config.server_timeout();
+        let corrupt_check_interval = *server_timeout.corrupt_check_interval();
+        if !corrupt_check_interval.is_zero() {
+            self.task_manager.spawn(TaskName::CorruptCheck, |n| {
+                corrupt_check_task(
+                    Arc::clone(&kv_storage),
+                    Arc::clone(&client),
+                    Arc::clone(&self.cluster_info),
+                    Arc::clone(&raw_curp),
+                    self.client_tls_config.clone(),
+                    corrupt_check_interval,
+                    n,
+                )
+            });
+        }
+        #[cfg(not(madsim))]
+        if let Some(ref backup_config) = self.storage_config.backup {
+            let store: Arc<dyn BackupStore> = match *backup_config.target() {
+                BackupTarget::Dir(ref dir) => Arc::new(DirBackupStore::new(dir.clone())),
+                BackupTarget::S3(ref config) => Arc::new(ObjectBackupStore::new(config)?),
+            };
+            self.task_manager.spawn(TaskName::Backup, |n| {
+                backup_task(
+                    store,
+                    Arc::clone(&header_gen),
+                    Arc::clone(&db),
+                    Arc::clone(&self.cluster_info),
+                    Arc::clone(&raw_curp),
+                    backup_config.clone(),
+                    n,
+                )
+            });
+        }
+        if let Some(ref cold_tier_config) = self.storage_config.cold_tier {
+            self.task_manager.spawn(TaskName::ColdTier, |n| {
+                cold_tier_task(Arc::clone(&kv_storage), cold_tier_config.clone(), n)
+            });
+        }
+        self.task_manager.spawn(TaskName::MonitorVersion, |n| {
+            monitor_version_task(
+                Arc::clone(&cluster_version),
+                Arc::clone(&self.cluster_info),
+                self.client_tls_config.clone(),
+                Arc::clone(&client),
+                Arc::clone(&auth_storage),
+                n,
+            )
+        });
         Ok((
             KvServer::new(
                 Arc::clone(&kv_storage),
                 Arc::clone(&auth_storage),
+                Arc::clone(&alarm_storage),
                 *server_timeout.compact_timeout(),
                 Arc::clone(&client),
                 compact_events,
+                self.size_limits(),
+                quota_guard.clone(),
             ),
             LockServer::new(
                 Arc::clone(&client),
@@ -559,17 +871,30 @@ impl XlineServer {
             LeaseServer::new(
                 lease_storage,
                 Arc::clone(&auth_storage),
+                *self.auth_config.stream_token_expiry(),
                 Arc::clone(&client),
                 id_gen,
                 Arc::clone(&self.cluster_info),
                 self.client_tls_config.clone(),
                 &self.task_manager,
+                quota_guard.clone(),
+                Arc::clone(&cluster_version),
+                *server_timeout.lease_checkpoint_interval(),
+            ),
+            AuthServer::new(
+                Arc::clone(&client),
+                Arc::clone(&auth_storage),
+                quota_guard,
             ),
-            AuthServer::new(Arc::clone(&client), Arc::clone(&auth_storage)),
             WatchServer::new(
                 watcher,
                 Arc::clone(&header_gen),
+                Arc::clone(&auth_storage),
+                *self.auth_config.stream_token_expiry(),
                 *server_timeout.watch_progress_notify_interval(),
+                *server_timeout.watch_batch_interval(),
+                Arc::clone(&cluster_version),
+                *self.cluster_config.watch_flow_control_window(),
                 Arc::clone(&self.task_manager),
             ),
             MaintenanceServer::new(
@@ -582,6 +907,8 @@ impl XlineServer {
                 raw_curp,
                 ce,
                 alarm_storage,
+                self.client_tls_config.clone(),
+                cluster_version,
             ),
             ClusterServer::new(Arc::clone(&client), header_gen),
             curp_server.clone(),
@@ -683,6 +1010,50 @@ impl XlineServer {
         };
         Ok((client_tls_config, server_tls_config))
     }
+
+    /// Read the tls config of the peer communication, `None` if it is not
+    /// configured
+    #[cfg(not(madsim))]
+    async fn read_curp_tls_config(
+        tls_config: &TlsConfig,
+    ) -> Result<Option<(ClientTlsConfig, ServerTlsConfig)>> {
+        let (cert_path, key_path) = match (
+            tls_config.curp_cert_path().as_ref(),
+            tls_config.curp_key_path().as_ref(),
+        ) {
+            (Some(cert_path), Some(key_path)) => (cert_path, key_path),
+            (None, None) => {
+                if tls_config.curp_ca_cert_path().is_some() || *tls_config.curp_client_cert_auth() {
+                    return Err(anyhow!(
+                        "curp_cert_path and curp_key_path must be set to enable peer tls"
+                    ));
+                }
+                return Ok(None);
+            }
+            _ => return Err(anyhow!("curp_cert_path and curp_key_path must be both set")),
+        };
+        let cert = fs::read(cert_path).await?;
+        let key = fs::read(key_path).await?;
+        let identity = Identity::from_pem(cert, key);
+        let mut client_tls_config = ClientTlsConfig::new().identity(identity.clone());
+        let mut server_tls_config = ServerTlsConfig::new().identity(identity);
+        match tls_config.curp_ca_cert_path().as_ref() {
+            Some(ca_path) => {
+                let ca = Certificate::from_pem(fs::read(ca_path).await?);
+                client_tls_config = client_tls_config.ca_certificate(ca.clone());
+                if *tls_config.curp_client_cert_auth() {
+                    server_tls_config = server_tls_config.client_ca_root(ca);
+                }
+            }
+            None if *tls_config.curp_client_cert_auth() => {
+                return Err(anyhow!(
+                    "curp_ca_cert_path must be set to verify the certificates of the peers"
+                ));
+            }
+            None => {}
+        }
+        Ok(Some((client_tls_config, server_tls_config)))
+    }
 }
 
 /// Bind multiple addresses
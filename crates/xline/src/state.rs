@@ -13,6 +13,9 @@ pub(crate) struct State<C: Compactable> {
     lease_storage: Arc<LeaseStore>,
     /// auto compactor
     auto_compactor: Option<Arc<dyn Compactor<C>>>,
+    /// Election timeout, the deadlines of leases are extended by it when this
+    /// node becomes the leader
+    election_timeout: Duration,
 }
 
 impl<C: Compactable> Clone for State<C> {
@@ -20,13 +23,15 @@ impl<C: Compactable> Clone for State<C> {
         Self {
             lease_storage: Arc::clone(&self.lease_storage),
             auto_compactor: self.auto_compactor.clone(),
+            election_timeout: self.election_timeout,
         }
     }
 }
 
 impl<C: Compactable> RoleChange for State<C> {
     fn on_election_win(&self) {
-        self.lease_storage.promote(Duration::from_secs(1)); // TODO: extend should be election timeout
+        // give the clients a chance to find the new leader and renew their leases
+        self.lease_storage.promote(self.election_timeout);
         if let Some(auto_compactor) = self.auto_compactor.as_ref() {
             auto_compactor.resume();
         }
@@ -45,10 +50,12 @@ impl<C: Compactable> State<C> {
     pub(super) fn new(
         lease_storage: Arc<LeaseStore>,
         auto_compactor: Option<Arc<dyn Compactor<C>>>,
+        election_timeout: Duration,
     ) -> Self {
         Self {
             lease_storage,
             auto_compactor,
+            election_timeout,
         }
     }
 }
@@ -11,17 +11,25 @@ use std::{
 use curp::members::ServerId;
 use parking_lot::RwLock;
 use prost::Message;
-use utils::table_names::ALARM_TABLE;
+use tracing::warn;
+use utils::table_names::{ALARM_TABLE, META_TABLE};
 use xlineapi::{
     command::{CommandResponse, SyncResponse},
     execute_error::ExecuteError,
-    AlarmAction, AlarmMember, AlarmResponse, AlarmType, RequestWrapper, ResponseWrapper,
+    AlarmAction, AlarmMember, AlarmResponse, AlarmType, ClusterVersionSetRequest,
+    ClusterVersionSetResponse, DowngradeAction, DowngradeRequest, DowngradeResponse,
+    RequestWrapper, ResponseWrapper,
 };
 
-use super::db::{WriteOp, DB};
-use crate::{header_gen::HeaderGenerator, revision_number::RevisionNumberGeneratorState};
+use super::db::{WriteOp, CLUSTER_VERSION_KEY, DB, DOWNGRADE_TARGET_KEY};
+use crate::{
+    header_gen::HeaderGenerator,
+    revision_number::RevisionNumberGeneratorState,
+    server::version::{ClusterVersion, Feature, Version},
+};
 
-/// Alarm store
+/// Alarm store, which also keeps the committed cluster version and the
+/// downgrade job of the cluster
 #[derive(Debug)]
 pub(crate) struct AlarmStore {
     /// Header generator
@@ -32,11 +40,34 @@ pub(crate) struct AlarmStore {
     types: RwLock<HashMap<AlarmType, HashMap<ServerId, AlarmMember>>>,
     /// Current alarm
     current_alarm: AtomicI32,
+    /// Id of this member if only its own `CORRUPT` alarm limits the requests,
+    /// otherwise the `CORRUPT` alarm of any member does
+    isolated_member: Option<ServerId>,
+    /// Cluster version, whose committed version and downgrade job are set by
+    /// the committed requests
+    cluster_version: Arc<ClusterVersion>,
 }
 
 impl AlarmStore {
     /// execute a alarm request
     pub(crate) fn execute(&self, request: &RequestWrapper) -> CommandResponse {
+        if let RequestWrapper::ClusterVersionSetRequest(_) = *request {
+            return CommandResponse::new(ResponseWrapper::ClusterVersionSetResponse(
+                ClusterVersionSetResponse {
+                    header: Some(self.header_gen.gen_header()),
+                },
+            ));
+        }
+        if let RequestWrapper::DowngradeRequest(_) = *request {
+            return CommandResponse::new(ResponseWrapper::DowngradeResponse(DowngradeResponse {
+                header: Some(self.header_gen.gen_header()),
+                version: self
+                    .cluster_version
+                    .version()
+                    .map(|version| version.to_string())
+                    .unwrap_or_default(),
+            }));
+        }
         #[allow(clippy::wildcard_enum_match_arm)]
         let alarms = match *request {
             RequestWrapper::AlarmRequest(ref req) => match req.action() {
@@ -73,6 +104,8 @@ impl AlarmStore {
                 AlarmAction::Activate => self.sync_alarm_activate(req.member_id, req.alarm()),
                 AlarmAction::Deactivate => self.sync_alarm_deactivate(req.member_id, req.alarm()),
             },
+            RequestWrapper::DowngradeRequest(ref req) => self.sync_downgrade(req),
+            RequestWrapper::ClusterVersionSetRequest(ref req) => self.sync_cluster_version(req),
             _ => {
                 unreachable!("Other request should not be sent to this store");
             }
@@ -84,24 +117,64 @@ impl AlarmStore {
     pub(crate) fn recover(&self) -> Result<(), ExecuteError> {
         let alarms = self.get_all_alarms_from_db()?;
         let mut types_w = self.types.write();
+        types_w.clear();
         for alarm in alarms {
             _ = types_w
                 .entry(alarm.alarm())
                 .or_default()
                 .insert(alarm.member_id, alarm);
         }
+        self.refresh_current_alarm(&types_w);
+        match self.get_version(DOWNGRADE_TARGET_KEY)? {
+            Some(target) => self.cluster_version.enable_downgrade(target),
+            None => {
+                let _canceled = self.cluster_version.cancel_downgrade();
+            }
+        }
+        self.cluster_version
+            .set_committed(self.get_version(CLUSTER_VERSION_KEY)?);
+        self.refresh_storage_formats();
         Ok(())
     }
+
+    /// Get the version persisted in the meta table
+    fn get_version(&self, key: &'static str) -> Result<Option<Version>, ExecuteError> {
+        self.db
+            .get_value(META_TABLE, key)?
+            .map(|version| {
+                String::from_utf8(version)
+                    .ok()
+                    .and_then(|version| Version::parse(&version))
+                    .ok_or_else(|| ExecuteError::DbError(format!("cannot decode the {key}")))
+            })
+            .transpose()
+    }
+
+    /// Switch the storage formats gated by the committed cluster version, they
+    /// are switched at the same log entry on every member
+    fn refresh_storage_formats(&self) {
+        self.db.set_explicit_tombstones(
+            self.cluster_version
+                .is_committed_enabled(Feature::ExplicitTombstones),
+        );
+    }
 }
 
 impl AlarmStore {
     /// Create a new alarm store
-    pub(crate) fn new(header_gen: Arc<HeaderGenerator>, db: Arc<DB>) -> Self {
+    pub(crate) fn new(
+        header_gen: Arc<HeaderGenerator>,
+        db: Arc<DB>,
+        isolated_member: Option<ServerId>,
+        cluster_version: Arc<ClusterVersion>,
+    ) -> Self {
         Self {
             header_gen,
             db,
             types: RwLock::new(HashMap::new()),
             current_alarm: AtomicI32::new(i32::from(AlarmType::None)),
+            isolated_member,
+            cluster_version,
         }
     }
 
@@ -112,11 +185,23 @@ impl AlarmStore {
             .unwrap_or_else(|e| unreachable!("current alarm should be valid, err: {e}"))
     }
 
+    /// Check if the requests are limited by the `CORRUPT` alarm, it's checked
+    /// by the local reads of this member too
+    pub(crate) fn check_corrupt(&self) -> Result<(), ExecuteError> {
+        if self.current_alarm() == AlarmType::Corrupt {
+            return Err(ExecuteError::Corrupt(
+                "the CORRUPT alarm is activated".to_owned(),
+            ));
+        }
+        Ok(())
+    }
+
     /// Refresh current alarm
     fn refresh_current_alarm(&self, types: &HashMap<AlarmType, HashMap<ServerId, AlarmMember>>) {
-        let corrupt_alarms = types
-            .get(&AlarmType::Corrupt)
-            .is_some_and(|e| !e.is_empty());
+        let corrupt_alarms = types.get(&AlarmType::Corrupt).is_some_and(|e| {
+            self.isolated_member
+                .map_or(!e.is_empty(), |id| e.contains_key(&id))
+        });
         if corrupt_alarms {
             self.current_alarm
                 .store(i32::from(AlarmType::Corrupt), Ordering::Relaxed);
@@ -213,4 +298,167 @@ impl AlarmStore {
         self.refresh_current_alarm(&types_w);
         ops
     }
+
+    /// Sync downgrade request, the target was validated by the member who
+    /// proposed it
+    fn sync_downgrade(&self, req: &DowngradeRequest) -> Vec<WriteOp> {
+        match req.action() {
+            DowngradeAction::Enable => {
+                let Some(target) = Version::parse(&req.version) else {
+                    warn!("invalid downgrade target {}", req.version);
+                    return vec![];
+                };
+                self.cluster_version.enable_downgrade(target);
+                self.refresh_storage_formats();
+                vec![WriteOp::PutDowngradeTarget(target.to_string())]
+            }
+            DowngradeAction::Cancel => {
+                let _canceled = self.cluster_version.cancel_downgrade();
+                self.refresh_storage_formats();
+                vec![WriteOp::DeleteDowngradeTarget]
+            }
+            DowngradeAction::Validate => vec![],
+        }
+    }
+
+    /// Sync cluster version set request, the version was decided by the
+    /// leader who proposed it
+    fn sync_cluster_version(&self, req: &ClusterVersionSetRequest) -> Vec<WriteOp> {
+        let Some(version) = Version::parse(&req.version) else {
+            warn!("invalid cluster version {}", req.version);
+            return vec![];
+        };
+        self.cluster_version.set_committed(Some(version));
+        self.refresh_storage_formats();
+        vec![WriteOp::PutClusterVersion(version.to_string())]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use utils::config::EngineConfig;
+
+    use super::*;
+    use crate::revision_number::RevisionNumberGenerator;
+
+    fn activate(store: &AlarmStore, member_id: ServerId, alarm: AlarmType) {
+        let request = RequestWrapper::from(xlineapi::AlarmRequest::new(
+            AlarmAction::Activate,
+            member_id,
+            alarm,
+        ));
+        let revision_gen = RevisionNumberGenerator::new(1);
+        let (_res, ops) = store.after_sync(&request, &revision_gen.state());
+        store.db.write_ops(ops).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_alarm_of_other_member_is_ignored_when_isolated() {
+        let db = DB::open(&EngineConfig::Memory).unwrap();
+        let header_gen = Arc::new(HeaderGenerator::new(0, 1));
+        let cluster_version = Arc::new(ClusterVersion::new(None));
+        let store = AlarmStore::new(
+            Arc::clone(&header_gen),
+            Arc::clone(&db),
+            Some(1),
+            Arc::clone(&cluster_version),
+        );
+        activate(&store, 2, AlarmType::Corrupt);
+        assert_eq!(store.current_alarm(), AlarmType::None);
+        activate(&store, 2, AlarmType::Nospace);
+        assert_eq!(store.current_alarm(), AlarmType::Nospace);
+        assert!(store.check_corrupt().is_ok());
+        activate(&store, 1, AlarmType::Corrupt);
+        assert_eq!(store.current_alarm(), AlarmType::Corrupt);
+        assert!(matches!(
+            store.check_corrupt(),
+            Err(ExecuteError::Corrupt(_))
+        ));
+
+        // the alarms are recovered, and other members are not isolated
+        let store = AlarmStore::new(header_gen, db, None, cluster_version);
+        store.recover().unwrap();
+        assert_eq!(store.current_alarm(), AlarmType::Corrupt);
+        assert_eq!(store.get_all_alarms().len(), 3);
+    }
+
+    fn downgrade(store: &AlarmStore, action: DowngradeAction, version: &str) {
+        let request = RequestWrapper::from(DowngradeRequest {
+            action: action.into(),
+            version: version.to_owned(),
+        });
+        let revision_gen = RevisionNumberGenerator::new(1);
+        let (_res, ops) = store.after_sync(&request, &revision_gen.state());
+        store.db.write_ops(ops).unwrap();
+    }
+
+    #[test]
+    fn test_downgrade_job_is_synced_and_recovered() {
+        let db = DB::open(&EngineConfig::Memory).unwrap();
+        let header_gen = Arc::new(HeaderGenerator::new(0, 1));
+        let new_store = |db: &Arc<DB>| {
+            let cluster_version = Arc::new(ClusterVersion::new(Some(Version::current())));
+            let store = AlarmStore::new(
+                Arc::clone(&header_gen),
+                Arc::clone(db),
+                None,
+                Arc::clone(&cluster_version),
+            );
+            store.recover().unwrap();
+            (store, cluster_version)
+        };
+        let target = Version::parse("0.1").unwrap();
+
+        let (store, cluster_version) = new_store(&db);
+        downgrade(&store, DowngradeAction::Enable, "0.1.0");
+        assert_eq!(cluster_version.downgrade_target(), Some(target));
+
+        // the downgrade job is kept across restarts
+        let (store, cluster_version) = new_store(&db);
+        assert_eq!(cluster_version.downgrade_target(), Some(target));
+        assert_eq!(cluster_version.version(), Some(target));
+
+        downgrade(&store, DowngradeAction::Cancel, "");
+        assert_eq!(cluster_version.downgrade_target(), None);
+        let (_store, cluster_version) = new_store(&db);
+        assert_eq!(cluster_version.downgrade_target(), None);
+        assert_eq!(cluster_version.version(), Some(Version::current()));
+    }
+
+    #[test]
+    fn test_committed_cluster_version_switches_the_tombstones() {
+        let db = DB::open(&EngineConfig::Memory).unwrap();
+        let header_gen = Arc::new(HeaderGenerator::new(0, 1));
+        let new_store = |db: &Arc<DB>| {
+            let cluster_version = Arc::new(ClusterVersion::new(Some(Version::current())));
+            let store = AlarmStore::new(
+                Arc::clone(&header_gen),
+                Arc::clone(db),
+                None,
+                cluster_version,
+            );
+            store.recover().unwrap();
+            store
+        };
+        let store = new_store(&db);
+        assert!(!db.explicit_tombstones());
+
+        let request = RequestWrapper::from(ClusterVersionSetRequest {
+            version: "0.7.0".to_owned(),
+        });
+        let revision_gen = RevisionNumberGenerator::new(1);
+        let (_res, ops) = store.after_sync(&request, &revision_gen.state());
+        store.db.write_ops(ops).unwrap();
+        assert!(db.explicit_tombstones());
+
+        downgrade(&store, DowngradeAction::Enable, "0.6.0");
+        assert!(!db.explicit_tombstones());
+        downgrade(&store, DowngradeAction::Cancel, "");
+        assert!(db.explicit_tombstones());
+
+        // the switch is recovered from the committed cluster version
+        db.set_explicit_tombstones(false);
+        let _store = new_store(&db);
+        assert!(db.explicit_tombstones());
+    }
 }
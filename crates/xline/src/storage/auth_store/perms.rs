@@ -20,8 +20,8 @@ pub(super) struct TokenClaims {
     pub(super) username: String,
     /// Revision
     pub(super) revision: i64,
-    /// Expiration
-    exp: u64,
+    /// Expiration, in seconds since the unix epoch
+    pub(super) exp: u64,
 }
 
 impl From<TokenClaims> for AuthInfo {
@@ -5,6 +5,7 @@ use std::{
         atomic::{AtomicBool, Ordering as AtomicOrdering},
         Arc,
     },
+    time::Duration,
 };
 
 use clippy_utilities::NumericCast;
@@ -16,7 +17,8 @@ use pbkdf2::{
     password_hash::{PasswordHash, PasswordVerifier},
     Pbkdf2,
 };
-use utils::parking_lot_lock::RwLockMap;
+use tokio::time::Instant;
+use utils::{parking_lot_lock::RwLockMap, timestamp};
 use xlineapi::{
     command::{CommandResponse, KeyRange, SyncResponse},
     execute_error::ExecuteError,
@@ -147,6 +149,23 @@ impl AuthStore {
         Ok(None)
     }
 
+    /// Get when the token of a tonic request expires, `None` if the request is
+    /// not authenticated by a valid token
+    pub(crate) fn token_expiration<T>(&self, request: &tonic::Request<T>) -> Option<Instant> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let token = get_token(request.metadata())?;
+        self.token_manager
+            .as_ref()?
+            .verify(&token)
+            .ok()
+            .and_then(|claims| {
+                let ttl = Duration::from_secs(claims.exp.saturating_sub(timestamp()));
+                Instant::now().checked_add(ttl)
+            })
+    }
+
     /// create permission cache
     fn create_permission_cache(&self) -> Result<(), ExecuteError> {
         let mut permission_cache = PermissionCache::new();
@@ -924,6 +943,9 @@ impl AuthStore {
                 | RequestWrapper::AuthRoleDeleteRequest(_)
                 | RequestWrapper::AuthUserListRequest(_)
                 | RequestWrapper::AuthRoleListRequest(_)
+                | RequestWrapper::LeaseCheckpointRequest(_)
+                | RequestWrapper::DowngradeRequest(_)
+                | RequestWrapper::ClusterVersionSetRequest(_)
         )
     }
 
@@ -1007,6 +1029,26 @@ impl AuthStore {
         Ok(())
     }
 
+    /// check if the user of a watch stream is permitted to read the watched range
+    pub(crate) fn check_watch_permission(
+        &self,
+        auth_info: Option<&AuthInfo>,
+        key_range: &KeyRange,
+    ) -> Result<(), ExecuteError> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        let Some(auth_info) = auth_info else {
+            return Err(ExecuteError::TokenNotProvided);
+        };
+        self.check_op_permission(
+            &auth_info.username,
+            key_range.range_start(),
+            key_range.range_end(),
+            Type::Read,
+        )
+    }
+
     /// check if range request is permitted
     fn check_range_permission(
         &self,
@@ -1183,7 +1225,7 @@ mod test {
         rpc::{
             AuthRoleAddRequest, AuthRoleDeleteRequest, AuthRoleGrantPermissionRequest,
             AuthRoleRevokePermissionRequest, AuthUserAddRequest, AuthUserDeleteRequest,
-            AuthUserGrantRoleRequest, Permission,
+            AuthUserGrantRoleRequest, Compare, Permission, TxnRequest,
         },
         storage::{
             auth_store::perms::{PermissionCache, UserPermissions},
@@ -1333,6 +1375,75 @@ mod test {
         assert!(!store.is_enabled());
     }
 
+    #[test]
+    fn test_check_watch_permission() -> Result<(), ExecuteError> {
+        let db = DB::open(&EngineConfig::Memory)?;
+        let store = init_auth_store(db);
+        let auth_info = AuthInfo {
+            username: "u".to_owned(),
+            auth_revision: store.revision(),
+        };
+        let foo = KeyRange::new("foo", "");
+        assert!(store.check_watch_permission(None, &foo).is_ok());
+
+        store.enabled.store(true, AtomicOrdering::Relaxed);
+        assert!(store.check_watch_permission(Some(&auth_info), &foo).is_ok());
+        assert!(matches!(
+            store.check_watch_permission(Some(&auth_info), &KeyRange::new("foo", "fop")),
+            Err(ExecuteError::PermissionDenied)
+        ));
+        assert!(matches!(
+            store.check_watch_permission(None, &foo),
+            Err(ExecuteError::TokenNotProvided)
+        ));
+
+        let req = RequestWrapper::from(AuthRoleRevokePermissionRequest {
+            role: "r".to_owned(),
+            key: "foo".into(),
+            range_end: "".into(),
+        });
+        let _ignore = exe_and_sync(&store, &req)?;
+        assert!(matches!(
+            store.check_watch_permission(Some(&auth_info), &foo),
+            Err(ExecuteError::PermissionDenied)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_txn_compare_permission() -> Result<(), ExecuteError> {
+        let db = DB::open(&EngineConfig::Memory)?;
+        let store = init_auth_store(db);
+        let auth_info = AuthInfo {
+            username: "u".to_owned(),
+            auth_revision: store.revision(),
+        };
+        let txn = |key: &str, range_end: &str| {
+            RequestWrapper::from(TxnRequest {
+                compare: vec![Compare {
+                    key: key.into(),
+                    range_end: range_end.into(),
+                    ..Default::default()
+                }],
+                success: vec![],
+                failure: vec![],
+            })
+        };
+
+        store.enabled.store(true, AtomicOrdering::Relaxed);
+        assert!(store
+            .check_permission(&txn("foo", ""), Some(&auth_info))
+            .is_ok());
+        assert!(
+            matches!(
+                store.check_permission(&txn("foo", "fop"), Some(&auth_info)),
+                Err(ExecuteError::PermissionDenied)
+            ),
+            "every key in the compare range should be readable"
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_recover() -> Result<(), ExecuteError> {
         let db = DB::open(&EngineConfig::Memory).unwrap();
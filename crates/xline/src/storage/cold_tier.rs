@@ -0,0 +1,180 @@
+use std::{path::Path, sync::Arc, time::Instant};
+
+use engine::{
+    Engine, EngineType, KeyProvider, Snapshot, StorageEngine, StorageOps, View, WriteOperation,
+};
+use parking_lot::{Mutex, MutexGuard};
+use tokio::time::sleep;
+use tracing::{debug, warn};
+use utils::{
+    config::ColdTierConfig,
+    table_names::{KV_TABLE, META_TABLE},
+    task_manager::Listener,
+};
+use xlineapi::execute_error::ExecuteError;
+
+use super::KvStore;
+
+/// Key of the revision before which the superseded revisions are migrated
+const MIGRATED_REVISION_KEY: &[u8] = b"migrated_revision";
+
+/// Tables of the cold tier
+const COLD_TIER_TABLES: [&str; 2] = [KV_TABLE, META_TABLE];
+
+/// Prefix of the files of the cold tier in the snapshots, which keeps them
+/// apart from the files of the hot engine
+pub(super) const SNAPSHOT_PREFIX: &str = "cold.";
+
+/// The cold tier of the kv table
+///
+/// It keeps the revisions superseded long ago in a separate engine, so the old
+/// history can live on a cheaper and slower backend while the latest values
+/// stay in the hot engine. The tier is local to a member, the revisions are
+/// the same whichever tier they are in.
+#[derive(Debug)]
+pub(crate) struct ColdTier {
+    /// The engine of the cold tier
+    engine: Engine,
+    /// Serializes the migrations with the removals of the revisions, so that a
+    /// revision being compacted is never migrated back
+    lock: Mutex<()>,
+}
+
+impl ColdTier {
+    /// Open the cold tier in the directory, the values are encrypted with the
+    /// keys of the key provider if it is given
+    pub(crate) fn open(
+        dir: &Path,
+        key_provider: Option<Arc<dyn KeyProvider>>,
+    ) -> Result<Self, ExecuteError> {
+        let engine_type = EngineType::Rocks(dir.to_path_buf());
+        let engine = match key_provider {
+            Some(key_provider) => {
+                Engine::new_encrypted(engine_type, &COLD_TIER_TABLES, key_provider)
+            }
+            None => Engine::new(engine_type, &COLD_TIER_TABLES),
+        }
+        .map_err(|e| ExecuteError::DbError(format!("Cannot open cold tier: {e}")))?;
+        Ok(Self {
+            engine,
+            lock: Mutex::new(()),
+        })
+    }
+
+    /// Lock the cold tier against the migrations
+    pub(super) fn lock(&self) -> MutexGuard<'_, ()> {
+        self.lock.lock()
+    }
+
+    /// Get the revision before which the superseded revisions are migrated
+    pub(crate) fn migrated_revision(&self) -> Result<i64, ExecuteError> {
+        let Some(bytes) = self
+            .engine
+            .get(META_TABLE, MIGRATED_REVISION_KEY)
+            .map_err(|e| ExecuteError::DbError(format!("Failed to get migrated revision: {e}")))?
+        else {
+            return Ok(0);
+        };
+        let bytes = bytes.try_into().map_err(|e| {
+            ExecuteError::DbError(format!("cannot decode migrated revision: {e:?}"))
+        })?;
+        Ok(i64::from_le_bytes(bytes))
+    }
+
+    /// Put the migrated revisions and the new migrated revision, the write is
+    /// synced so the revisions can be removed from the hot engine after it
+    pub(super) fn put(
+        &self,
+        kvs: Vec<(Vec<u8>, Vec<u8>)>,
+        migrated_revision: i64,
+    ) -> Result<(), ExecuteError> {
+        let ops = kvs
+            .into_iter()
+            .map(|(key, value)| WriteOperation::new_put(KV_TABLE, key, value))
+            .chain([WriteOperation::new_put(
+                META_TABLE,
+                MIGRATED_REVISION_KEY.to_vec(),
+                migrated_revision.to_le_bytes().to_vec(),
+            )]);
+        self.engine
+            .write_multi(ops, true)
+            .map_err(|e| ExecuteError::DbError(format!("Failed to migrate revisions: {e}")))
+    }
+
+    /// Get the values of the encoded revisions
+    pub(super) fn get_values(
+        &self,
+        keys: &[impl AsRef<[u8]>],
+    ) -> Result<Vec<Option<Vec<u8>>>, ExecuteError> {
+        self.engine
+            .get_multi(KV_TABLE, keys)
+            .map_err(|e| ExecuteError::DbError(format!("Failed to get cold revisions: {e}")))
+    }
+
+    /// Remove the encoded revisions
+    pub(super) fn remove(&self, keys: &[impl AsRef<[u8]>]) -> Result<(), ExecuteError> {
+        let ops = keys
+            .iter()
+            .map(|key| WriteOperation::new_delete(KV_TABLE, key.as_ref()));
+        self.engine
+            .write_multi(ops, false)
+            .map_err(|e| ExecuteError::DbError(format!("Failed to remove cold revisions: {e}")))
+    }
+
+    /// Remove all the revisions, it's called when the hot engine is reset
+    pub(super) fn clear(&self) -> Result<(), ExecuteError> {
+        let _guard = self.lock();
+        let ops = COLD_TIER_TABLES
+            .iter()
+            .map(|table| WriteOperation::new_delete_range(table, &[], &[0xff]));
+        self.engine
+            .write_multi(ops, true)
+            .map_err(|e| ExecuteError::DbError(format!("Failed to clear cold tier: {e}")))
+    }
+
+    /// Add the cold tier to a snapshot of the hot engine, the lock must be held
+    /// since the hot snapshot is taken so that no revision is migrated in
+    /// between
+    pub(super) fn add_to_snapshot(&self, snapshot: &mut Snapshot) -> Result<(), ExecuteError> {
+        self.engine
+            .add_to_snapshot(snapshot, &COLD_TIER_TABLES, SNAPSHOT_PREFIX)
+            .map_err(|e| ExecuteError::DbError(format!("Failed to snapshot cold tier: {e}")))
+    }
+
+    /// Ingest the cold tier of a snapshot, the snapshot is kept to be applied
+    /// to the hot engine
+    pub(super) fn ingest_snapshot(&self, snapshot: &Snapshot) -> Result<(), ExecuteError> {
+        self.engine
+            .ingest_snapshot(snapshot, &COLD_TIER_TABLES, SNAPSHOT_PREFIX)
+            .map_err(|e| ExecuteError::DbError(format!("Failed to reset cold tier: {e}")))
+    }
+
+    /// Get a consistent view of the cold tier
+    pub(super) fn view(&self) -> View<'_> {
+        self.engine.view()
+    }
+}
+
+/// Migrate the revisions superseded long ago to the cold tier periodically
+#[allow(clippy::arithmetic_side_effects, clippy::ignored_unit_patterns)] // Introduced by tokio::select!
+pub(crate) async fn cold_tier_task(
+    kv_store: Arc<KvStore>,
+    config: ColdTierConfig,
+    shutdown_listener: Listener,
+) {
+    loop {
+        tokio::select! {
+            _ = shutdown_listener.wait() => return,
+            _ = sleep(*config.interval()) => {}
+        }
+        let start = Instant::now();
+        match kv_store.migrate_to_cold_tier(*config.threshold()) {
+            Ok(0) => {}
+            Ok(n) => debug!(
+                "migrated {n} revisions to the cold tier in {:?}",
+                start.elapsed()
+            ),
+            Err(e) => warn!("migration to the cold tier failed: {e}"),
+        }
+    }
+}
@@ -1,11 +1,13 @@
 use std::{sync::Arc, time::Duration};
 
 use async_trait::async_trait;
+use clippy_utilities::OverflowArithmetic;
 use curp::client::ClientApi;
 use event_listener::Event;
 use periodic_compactor::PeriodicCompactor;
 use revision_compactor::RevisionCompactor;
 use tokio::time::sleep;
+use tracing::{debug, error, info, warn};
 use utils::{
     config::AutoCompactConfig,
     task_manager::{tasks::TaskName, Listener, TaskManager},
@@ -118,17 +120,51 @@ pub(crate) async fn compact_bg_task(
             .map(|key_rev| key_rev.as_revision().encode_to_vec())
             .collect::<Vec<Vec<_>>>();
         // Given that the Xline uses a lim-tree database with smaller write amplification as the storage backend ,  does using progressive compaction really good at improving performance?
+        let mut reclaimed = 0_u64;
+        let mut failed = false;
         for revision_chunk in target_revisions.chunks(batch_limit) {
-            if let Err(e) = kv_store.compact(revision_chunk) {
-                panic!("failed to compact revision chunk {revision_chunk:?} due to {e}");
+            match kv_store.compact(revision_chunk) {
+                Ok(bytes) => reclaimed = reclaimed.overflow_add(bytes),
+                // the compaction is scheduled again when the storage is recovered
+                Err(e) => {
+                    error!("failed to compact revision chunk {revision_chunk:?} due to {e}");
+                    failed = true;
+                    break;
+                }
             }
             sleep(interval).await;
         }
-        if let Err(e) = kv_store.compact_finished(revision) {
-            panic!("failed to set finished compact revision {revision:?} due to {e}");
+        if !failed {
+            debug!(
+                "compaction at revision {revision} removed {} revisions, reclaimed {reclaimed} bytes",
+                target_revisions.len()
+            );
+            if let Err(e) = kv_store.compact_finished(revision, reclaimed) {
+                error!("failed to set finished compact revision {revision:?} due to {e}");
+                failed = true;
+            }
         }
         if let Some(notifier) = listener {
             let _ignore = notifier.notify(usize::MAX);
         }
+        if failed {
+            continue;
+        }
+        // the revisions are deleted, compact their range in the engine to give
+        // back the disk space
+        if let (Some(from), Some(to)) =
+            (target_revisions.iter().min(), target_revisions.iter().max())
+        {
+            let kv_store = Arc::clone(&kv_store);
+            let (from, to) = (from.clone(), to.clone());
+            match tokio::task::spawn_blocking(move || kv_store.reclaim_compacted(&from, &to)).await
+            {
+                Ok(Ok(bytes)) => {
+                    info!("compaction at revision {revision} reclaimed {bytes} bytes in the engine")
+                }
+                Ok(Err(e)) => warn!("failed to reclaim the compacted revisions due to {e}"),
+                Err(e) => warn!("failed to reclaim the compacted revisions due to {e}"),
+            }
+        }
     }
 }
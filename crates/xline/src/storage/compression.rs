@@ -0,0 +1,396 @@
+use std::borrow::Cow;
+
+use clippy_utilities::OverflowArithmetic;
+use prost::Message;
+use utils::config::ValueCompressionCodec;
+use xlineapi::execute_error::ExecuteError;
+
+use crate::rpc::KeyValue;
+
+/// Leading byte of a compressed, checksummed or tombstone value
+///
+/// A protobuf encoded `KeyValue` never starts with a zero byte because field
+/// number 0 is illegal, so plain values written before compression was enabled
+/// can still be told apart from compressed ones.
+const COMPRESSED_MARKER: u8 = 0;
+
+/// Algorithm byte of lz4 compressed values
+const LZ4: u8 = 1;
+
+/// Algorithm byte of checksummed values, followed by the little endian crc32
+/// of the rest, which is a plain or compressed value
+const CRC32: u8 = 2;
+
+/// Kind byte of tombstones, followed by the protobuf encoded `KeyValue` of the
+/// deleted key, whose mod revision is the revision of the deletion, and whose
+/// create revision and version are of the last live revision of the key
+const TOMBSTONE: u8 = 3;
+
+/// Algorithm byte of zstd compressed values
+const ZSTD: u8 = 4;
+
+/// Level of the zstd compression, the default level of zstd
+const ZSTD_LEVEL: i32 = 3;
+
+/// Length of the checksum header prepended to the values of the kv table
+pub(crate) const CHECKSUM_HEADER_LEN: usize = 6;
+
+/// Compression of the values stored in the kv table
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct ValueCompression {
+    /// Values whose encoded size is no less than the threshold are compressed,
+    /// `None` disables compression
+    threshold: Option<usize>,
+    /// Codec of the compressed values
+    codec: ValueCompressionCodec,
+}
+
+impl ValueCompression {
+    /// New `ValueCompression`
+    pub(crate) fn new(threshold: Option<usize>, codec: ValueCompressionCodec) -> Self {
+        Self { threshold, codec }
+    }
+
+    /// Compress an encoded value, and get the algorithm byte of the codec
+    fn compress(self, encoded: &[u8]) -> Option<(u8, Vec<u8>)> {
+        #[allow(clippy::wildcard_enum_match_arm)] // the codecs are non-exhaustive
+        match self.codec {
+            ValueCompressionCodec::Lz4 => Some((LZ4, lz4_flex::compress_prepend_size(encoded))),
+            ValueCompressionCodec::Zstd => zstd::bulk::compress(encoded, ZSTD_LEVEL)
+                .map(|compressed| (ZSTD, compressed))
+                .ok(),
+            _ => None,
+        }
+    }
+
+    /// Encode a `KeyValue`, the encoded value is compressed if it reaches the
+    /// threshold and compression actually saves space
+    pub(crate) fn encode(self, kv: &KeyValue) -> Vec<u8> {
+        let encoded = kv.encode_to_vec();
+        match self.threshold {
+            Some(threshold) if encoded.len() >= threshold => {
+                let Some((algorithm, compressed)) = self.compress(&encoded) else {
+                    return encoded;
+                };
+                if compressed.len().overflow_add(2) >= encoded.len() {
+                    return encoded;
+                }
+                let mut value = Vec::with_capacity(compressed.len().overflow_add(2));
+                value.push(COMPRESSED_MARKER);
+                value.push(algorithm);
+                value.extend_from_slice(&compressed);
+                value
+            }
+            _ => encoded,
+        }
+    }
+}
+
+/// The tombstone of a key deleted at a revision, the key existed from its
+/// create revision until the revision of the deletion
+///
+/// The lifetime is only kept in the kv table, no request returns it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Tombstone {
+    /// The deleted key
+    pub(crate) key: Vec<u8>,
+    /// The revision of the deletion
+    pub(crate) revision: i64,
+    /// The create revision of the deleted key, 0 for the deletions written
+    /// before the tombstones were explicit
+    pub(crate) create_revision: i64,
+    /// The version of the deleted key before the deletion, 0 for the
+    /// deletions written before the tombstones were explicit
+    pub(crate) version: i64,
+}
+
+impl Tombstone {
+    /// Encode the tombstone to be written to the kv table
+    ///
+    /// The members before the tombstones were explicit can't decode it, so a
+    /// deletion is written as [`Tombstone::to_deletion_kv`] until every member
+    /// supports the tombstones.
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let kv = self.to_kv();
+        let mut value = Vec::with_capacity(kv.encoded_len().overflow_add(2));
+        value.push(COMPRESSED_MARKER);
+        value.push(TOMBSTONE);
+        value.extend_from_slice(&kv.encode_to_vec());
+        value
+    }
+
+    /// The `KeyValue` of the tombstone, with the create revision and the
+    /// version of the deleted key
+    pub(crate) fn to_kv(&self) -> KeyValue {
+        KeyValue {
+            key: self.key.clone(),
+            create_revision: self.create_revision,
+            mod_revision: self.revision,
+            version: self.version,
+            ..KeyValue::default()
+        }
+    }
+
+    /// The `KeyValue` of the deletion, as it's in the delete events of etcd
+    /// and in the deletions written before the tombstones were explicit
+    pub(crate) fn to_deletion_kv(&self) -> KeyValue {
+        KeyValue {
+            key: self.key.clone(),
+            mod_revision: self.revision,
+            ..KeyValue::default()
+        }
+    }
+}
+
+/// A record of the kv table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum KvRecord {
+    /// A revision of a live key
+    Put(KeyValue),
+    /// The tombstone of a deleted key
+    Tombstone(Tombstone),
+}
+
+impl KvRecord {
+    /// Get the `KeyValue` of the record, a tombstone is converted to its
+    /// `KeyValue` with the lifetime of the deleted key
+    pub(crate) fn into_kv(self) -> KeyValue {
+        match self {
+            KvRecord::Put(kv) => kv,
+            KvRecord::Tombstone(tombstone) => tombstone.to_kv(),
+        }
+    }
+}
+
+/// Prepend the checksum header to a plain or compressed value before it is
+/// written to the kv table
+pub(crate) fn add_checksum(value: &[u8]) -> Vec<u8> {
+    let mut checksummed = Vec::with_capacity(CHECKSUM_HEADER_LEN.overflow_add(value.len()));
+    checksummed.push(COMPRESSED_MARKER);
+    checksummed.push(CRC32);
+    checksummed.extend_from_slice(&crc32fast::hash(value).to_le_bytes());
+    checksummed.extend_from_slice(value);
+    checksummed
+}
+
+/// Get the protobuf encoded `KeyValue` of a value in the kv table, the
+/// checksum is verified if there is one, compressed values are decompressed
+/// and plain values are borrowed as is
+///
+/// A tombstone is encoded as the `KeyValue` of its deletion, the same as the
+/// deletions written before the tombstones were explicit, so the hash of the
+/// kv table doesn't depend on how the deletions are written.
+pub(crate) fn decompress(value: &[u8]) -> Result<Cow<'_, [u8]>, ExecuteError> {
+    let value = verify_checksum(value)?;
+    if let Some(tombstone) = decode_tombstone(value)? {
+        return Ok(Cow::Owned(tombstone.to_deletion_kv().encode_to_vec()));
+    }
+    decompress_unchecked(value)
+}
+
+/// Verify the checksum of a value in the kv table if there is one, and get the
+/// rest of the value
+fn verify_checksum(value: &[u8]) -> Result<&[u8], ExecuteError> {
+    match *value {
+        [COMPRESSED_MARKER, CRC32, c0, c1, c2, c3, ref rest @ ..] => {
+            let expected = u32::from_le_bytes([c0, c1, c2, c3]);
+            let actual = crc32fast::hash(rest);
+            if actual != expected {
+                return Err(corrupt_kv_error(format!(
+                    "checksum mismatch, expected: {expected:#010x}, actual: {actual:#010x}"
+                )));
+            }
+            Ok(rest)
+        }
+        [COMPRESSED_MARKER, CRC32, ..] => Err(corrupt_kv_error("checksummed value is truncated")),
+        _ => Ok(value),
+    }
+}
+
+/// Decode the tombstone of a value without the checksum, `None` if it's not a
+/// tombstone
+fn decode_tombstone(value: &[u8]) -> Result<Option<Tombstone>, ExecuteError> {
+    let [COMPRESSED_MARKER, TOMBSTONE, ref rest @ ..] = *value else {
+        return Ok(None);
+    };
+    let kv = decode_protobuf(rest)?;
+    Ok(Some(Tombstone {
+        key: kv.key,
+        revision: kv.mod_revision,
+        create_revision: kv.create_revision,
+        version: kv.version,
+    }))
+}
+
+/// Get the protobuf encoded `KeyValue` of a plain or compressed value
+fn decompress_unchecked(value: &[u8]) -> Result<Cow<'_, [u8]>, ExecuteError> {
+    let Some((&COMPRESSED_MARKER, rest)) = value.split_first() else {
+        return Ok(Cow::Borrowed(value));
+    };
+    match rest.split_first() {
+        Some((&LZ4, compressed)) => lz4_flex::decompress_size_prepended(compressed)
+            .map(Cow::Owned)
+            .map_err(|e| corrupt_kv_error(format!("failed to decompress, error: {e}"))),
+        Some((&ZSTD, compressed)) => zstd::decode_all(compressed)
+            .map(Cow::Owned)
+            .map_err(|e| corrupt_kv_error(format!("failed to decompress, error: {e}"))),
+        Some((algorithm, _)) => Err(corrupt_kv_error(format!(
+            "unknown compression algorithm: {algorithm}"
+        ))),
+        None => Err(corrupt_kv_error("compressed value is truncated")),
+    }
+}
+
+/// Decode a `KeyValue` from a value in the kv table, a tombstone is decoded as
+/// [`Tombstone::to_kv`]
+pub(crate) fn decode_kv(value: &[u8]) -> Result<KeyValue, ExecuteError> {
+    decode_record(value).map(KvRecord::into_kv)
+}
+
+/// Decode a record from a value in the kv table, the deletions written before
+/// the tombstones were explicit are decoded as tombstones as well
+pub(crate) fn decode_record(value: &[u8]) -> Result<KvRecord, ExecuteError> {
+    let value = verify_checksum(value)?;
+    if let Some(tombstone) = decode_tombstone(value)? {
+        return Ok(KvRecord::Tombstone(tombstone));
+    }
+    let kv = decode_protobuf(&decompress_unchecked(value)?)?;
+    if kv.create_revision == 0 && kv.version == 0 {
+        return Ok(KvRecord::Tombstone(Tombstone {
+            key: kv.key,
+            revision: kv.mod_revision,
+            create_revision: 0,
+            version: 0,
+        }));
+    }
+    Ok(KvRecord::Put(kv))
+}
+
+/// Decode a protobuf encoded `KeyValue`
+fn decode_protobuf(encoded: &[u8]) -> Result<KeyValue, ExecuteError> {
+    KeyValue::decode(encoded).map_err(|e| corrupt_kv_error(format!("failed to decode, error: {e}")))
+}
+
+/// Build the error of a value in the kv table that cannot be decoded, which
+/// means the data of this node is corrupted
+fn corrupt_kv_error(reason: impl std::fmt::Display) -> ExecuteError {
+    ExecuteError::Corrupt(format!("corrupt key-value in DB, {reason}"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn kv(value: Vec<u8>) -> KeyValue {
+        KeyValue {
+            key: b"key".to_vec(),
+            create_revision: 1,
+            mod_revision: 2,
+            version: 1,
+            value,
+            lease: 0,
+        }
+    }
+
+    #[test]
+    fn test_compress_large_value() -> Result<(), ExecuteError> {
+        let compression = ValueCompression::new(Some(64), ValueCompressionCodec::Lz4);
+        let large = kv(vec![b'a'; 1024]);
+        let encoded = compression.encode(&large);
+        assert_eq!(encoded.first(), Some(&COMPRESSED_MARKER));
+        assert!(encoded.len() < large.encoded_len());
+        assert_eq!(decode_kv(&encoded)?, large);
+
+        let small = kv(b"value".to_vec());
+        let encoded = compression.encode(&small);
+        assert_eq!(encoded, small.encode_to_vec());
+        assert_eq!(decode_kv(&encoded)?, small);
+        Ok(())
+    }
+
+    #[test]
+    fn test_values_compressed_by_another_codec_should_be_read() -> Result<(), ExecuteError> {
+        let large = kv(vec![b'a'; 1024]);
+        let lz4 = ValueCompression::new(Some(64), ValueCompressionCodec::Lz4).encode(&large);
+        let zstd = ValueCompression::new(Some(64), ValueCompressionCodec::Zstd).encode(&large);
+        assert_eq!(lz4.get(..2), Some([COMPRESSED_MARKER, LZ4].as_slice()));
+        assert_eq!(zstd.get(..2), Some([COMPRESSED_MARKER, ZSTD].as_slice()));
+        assert!(zstd.len() < large.encoded_len());
+        for value in [lz4, zstd] {
+            assert_eq!(decode_kv(&value)?, large);
+            assert_eq!(decode_kv(&add_checksum(&value))?, large);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_plain_value_written_without_compression() -> Result<(), ExecuteError> {
+        let large = kv(vec![b'a'; 1024]);
+        let encoded = ValueCompression::default().encode(&large);
+        assert_eq!(encoded, large.encode_to_vec());
+        assert_eq!(decode_kv(&encoded)?, large);
+        Ok(())
+    }
+
+    #[test]
+    fn test_checksum_should_detect_corruption() -> Result<(), ExecuteError> {
+        let compression = ValueCompression::new(Some(64), ValueCompressionCodec::Lz4);
+        for kv in [kv(vec![b'a'; 1024]), kv(b"value".to_vec())] {
+            let mut value = add_checksum(&compression.encode(&kv));
+            assert_eq!(decode_kv(&value)?, kv);
+
+            let last = value.last_mut().unwrap();
+            *last ^= 0x01;
+            let err = decode_kv(&value).unwrap_err();
+            assert!(matches!(err, ExecuteError::Corrupt(_)), "{err:?}");
+            assert!(err.to_string().contains("checksum mismatch"), "{err:?}");
+        }
+        let err = decode_kv(&[COMPRESSED_MARKER, CRC32, 0]).unwrap_err();
+        assert!(matches!(err, ExecuteError::Corrupt(_)), "{err:?}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_tombstone_should_keep_the_deleted_revisions() -> Result<(), ExecuteError> {
+        let tombstone = Tombstone {
+            key: b"key".to_vec(),
+            revision: 5,
+            create_revision: 2,
+            version: 3,
+        };
+        let value = add_checksum(&tombstone.encode());
+        assert_eq!(
+            decode_record(&value)?,
+            KvRecord::Tombstone(tombstone.clone())
+        );
+        assert_eq!(decode_kv(&value)?, tombstone.to_kv());
+
+        // the deletions written before are tombstones without the deleted revisions
+        let legacy = add_checksum(&tombstone.to_deletion_kv().encode_to_vec());
+        assert_eq!(
+            decode_record(&legacy)?,
+            KvRecord::Tombstone(Tombstone {
+                create_revision: 0,
+                version: 0,
+                ..tombstone
+            })
+        );
+        assert_eq!(decompress(&value)?, decompress(&legacy)?);
+
+        let err = decode_record(&[COMPRESSED_MARKER, TOMBSTONE, 0xff]).unwrap_err();
+        assert!(matches!(err, ExecuteError::Corrupt(_)), "{err:?}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_undecodable_value_is_corrupt() {
+        for value in [
+            &[COMPRESSED_MARKER, 0xff][..],
+            &[COMPRESSED_MARKER][..],
+            &[0xff][..],
+        ] {
+            let err = decode_kv(value).unwrap_err();
+            assert!(matches!(err, ExecuteError::Corrupt(_)), "{err:?}");
+        }
+    }
+}
@@ -1,13 +1,27 @@
 #![allow(clippy::multiple_inherent_impl)]
 
-use std::{collections::HashMap, path::Path, sync::Arc};
+use std::{
+    cmp,
+    collections::HashMap,
+    io, mem,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, OnceLock,
+    },
+};
 
+use clippy_utilities::{NumericCast, OverflowArithmetic};
 use engine::{
-    Engine, EngineType, Snapshot, StorageEngine, StorageOps, Transaction, WriteOperation,
+    Engine, EngineError, EngineStats, EngineType, KeyProvider, Snapshot, StorageEngine, StorageOps,
+    View, ViewApi, WriteOperation,
 };
+use itertools::{EitherOrBoth, Itertools};
+use parking_lot::{Mutex, RwLock};
 use prost::Message;
+use tracing::error;
 use utils::{
-    config::EngineConfig,
+    config::{CompressionConfig, DurabilityConfig, EngineConfig},
     table_names::{
         ALARM_TABLE, AUTH_TABLE, KV_TABLE, LEASE_TABLE, META_TABLE, ROLE_TABLE, USER_TABLE,
         XLINE_TABLES,
@@ -17,6 +31,9 @@ use xlineapi::{execute_error::ExecuteError, AlarmMember};
 
 use super::{
     auth_store::{AUTH_ENABLE_KEY, AUTH_REVISION_KEY},
+    cold_tier::{self, ColdTier},
+    compression::{add_checksum, CHECKSUM_HEADER_LEN},
+    pipeline::{block_in_place, Pipeline, PipelinedTransaction, PipelinedView},
     storage_api::XlineStorageOps,
 };
 use crate::{
@@ -29,6 +46,15 @@ use crate::{
 pub(crate) const FINISHED_COMPACT_REVISION: &str = "finished_compact_revision";
 /// Key of scheduled compact revision
 pub(crate) const SCHEDULED_COMPACT_REVISION: &str = "scheduled_compact_revision";
+/// Key of the target version of the downgrade job in progress
+pub(crate) const DOWNGRADE_TARGET_KEY: &str = "downgrade_target";
+/// Key of the cluster version committed through consensus
+pub(crate) const CLUSTER_VERSION_KEY: &str = "cluster_version";
+
+/// Length of the encoded `Revision` keys of the kv table
+const REVISION_KEY_LEN: usize = 16;
+/// Length of the main revision prefix of the encoded `Revision` keys
+const REVISION_PREFIX_LEN: usize = 8;
 
 /// Key and value pair
 type KeyValuePair = (Vec<u8>, Vec<u8>);
@@ -38,6 +64,27 @@ type KeyValuePair = (Vec<u8>, Vec<u8>);
 pub struct DB {
     /// internal storage of `DB`
     engine: Arc<Engine>,
+    /// Guards the maintenance operations on the whole engine, the snapshots
+    /// share it while defragment takes it exclusively
+    maintenance_lock: RwLock<()>,
+    /// Logical size in bytes of the revisions kept in the kv table
+    size_in_use: AtomicU64,
+    /// Size in bytes of each revision kept in the kv table, so that the bytes
+    /// of the removed revisions are known without reading them again
+    record_sizes: Mutex<HashMap<Revision, u64>>,
+    /// Sizes in bytes of the revisions put by the transaction not committed
+    /// yet, they are added to `record_sizes` once the transaction is committed
+    uncommitted_sizes: Mutex<Vec<(Revision, u64)>>,
+    /// The cold tier of the kv table, if it is attached
+    cold_tier: OnceLock<ColdTier>,
+    /// The persistent write error that turned the storage read-only
+    write_failure: Arc<OnceLock<String>>,
+    /// The persistence pipeline of the transactions
+    pipeline: Pipeline,
+    /// Durability of the writes to the engine
+    durability: Mutex<DurabilityConfig>,
+    /// Whether the deletions are written as explicit tombstones
+    explicit_tombstones: AtomicBool,
 }
 
 impl DB {
@@ -48,23 +95,69 @@ impl DB {
     /// Return `ExecuteError::DbError` when open db failed
     #[inline]
     pub fn open(config: &EngineConfig) -> Result<Arc<Self>, ExecuteError> {
+        Self::open_with_key_provider(config, None)
+    }
+
+    /// Create a new `DB`, whose values are encrypted with the keys of the key
+    /// provider if it is given
+    ///
+    /// # Errors
+    ///
+    /// Return `ExecuteError::DbError` when open db failed, or the engine does
+    /// not support encryption
+    #[inline]
+    pub fn open_with_key_provider(
+        config: &EngineConfig,
+        key_provider: Option<Arc<dyn KeyProvider>>,
+    ) -> Result<Arc<Self>, ExecuteError> {
         let engine_type = match *config {
             EngineConfig::Memory => EngineType::Memory,
             EngineConfig::RocksDB(ref path) => EngineType::Rocks(path.clone()),
             _ => unreachable!("Not supported storage type"),
         };
-        let engine = Engine::new(engine_type, &XLINE_TABLES)
+        let engine = match key_provider {
+            Some(key_provider) => Engine::new_encrypted(engine_type, &XLINE_TABLES, key_provider),
+            None => Engine::new(engine_type, &XLINE_TABLES),
+        }
+        .map_err(|e| ExecuteError::DbError(format!("Cannot open database: {e}")))?;
+        // the revisions of a main revision are read by a prefix seek
+        engine
+            .set_prefix_len(KV_TABLE, REVISION_PREFIX_LEN)
             .map_err(|e| ExecuteError::DbError(format!("Cannot open database: {e}")))?;
+        let engine = Arc::new(engine);
+        let write_failure = Arc::new(OnceLock::new());
+        let applied_index = engine
+            .get(META_TABLE, APPLIED_INDEX_KEY)
+            .map_err(|e| ExecuteError::DbError(format!("Cannot open database: {e}")))?
+            .map_or(0, |bytes| decode_applied_index(&bytes));
+        let pipeline = Pipeline::new(
+            Arc::clone(&engine),
+            Arc::clone(&write_failure),
+            applied_index,
+        )
+        .map_err(|e| ExecuteError::DbError(format!("Cannot start persistence: {e}")))?;
         Ok(Arc::new(Self {
-            engine: Arc::new(engine),
+            engine,
+            maintenance_lock: RwLock::new(()),
+            size_in_use: AtomicU64::new(0),
+            record_sizes: Mutex::new(HashMap::new()),
+            uncommitted_sizes: Mutex::new(Vec::new()),
+            cold_tier: OnceLock::new(),
+            write_failure,
+            pipeline,
+            durability: Mutex::new(DurabilityConfig::default()),
+            explicit_tombstones: AtomicBool::new(false),
         }))
     }
 }
 
+/// The writes to the `DB` bypass the pipeline, they are ordered after the
+/// pending batches, and the reads see the pending mutations first
 impl StorageOps for DB {
     #[inline]
     fn write(&self, op: WriteOperation<'_>, sync: bool) -> Result<(), engine::EngineError> {
-        self.engine.write(op, sync)
+        self.pipeline.wait_persisted();
+        self.guard_write(|| self.engine.write(op, sync))
     }
 
     #[inline]
@@ -72,7 +165,8 @@ impl StorageOps for DB {
     where
         Ops: IntoIterator<Item = WriteOperation<'a>>,
     {
-        self.engine.write_multi(ops, sync)
+        self.pipeline.wait_persisted();
+        self.guard_write(|| self.engine.write_multi(ops, sync))
     }
 
     #[inline]
@@ -81,24 +175,89 @@ impl StorageOps for DB {
         table: &str,
         key: impl AsRef<[u8]>,
     ) -> Result<Option<Vec<u8>>, engine::EngineError> {
-        self.engine.get(table, key)
+        match self.pipeline.get(table, key.as_ref()) {
+            Some(value) => Ok(value),
+            None => self.engine.get(table, key),
+        }
     }
 
     #[inline]
+    #[allow(clippy::indexing_slicing)] // the indexes are taken from the keys
     fn get_multi(
         &self,
         table: &str,
         keys: &[impl AsRef<[u8]>],
     ) -> Result<Vec<Option<Vec<u8>>>, engine::EngineError> {
-        self.engine.get_multi(table, keys)
+        let mut values = Vec::with_capacity(keys.len());
+        let mut missing = Vec::new();
+        for (i, key) in keys.iter().enumerate() {
+            let value = self.pipeline.get(table, key.as_ref());
+            if value.is_none() {
+                missing.push(i);
+            }
+            values.push(value.flatten());
+        }
+        if missing.len() == keys.len() {
+            return self.engine.get_multi(table, keys);
+        }
+        let missing_keys: Vec<_> = missing.iter().map(|&i| keys[i].as_ref()).collect();
+        for (i, value) in missing
+            .into_iter()
+            .zip(self.engine.get_multi(table, &missing_keys)?)
+        {
+            values[i] = value;
+        }
+        Ok(values)
+    }
+
+    #[inline]
+    fn get_prefix(
+        &self,
+        table: &str,
+        prefix: &[u8],
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, engine::EngineError> {
+        self.pipeline
+            .overlay_prefix(table, prefix, || self.engine.get_prefix(table, prefix))
     }
 }
 
 impl DB {
-    /// Creates a transaction
-    #[allow(unused)] // TODO: use this in the following refactor
-    pub(crate) fn transaction(&self) -> Transaction {
-        self.engine.transaction()
+    /// Creates a transaction, whose mutations are persisted by the pipeline
+    /// after it's committed
+    pub(crate) fn transaction(&self) -> PipelinedTransaction<'_> {
+        PipelinedTransaction::new(self)
+    }
+
+    /// Submit the mutations of a transaction to the pipeline
+    pub(super) fn submit(
+        &self,
+        mutations: HashMap<String, HashMap<Vec<u8>, Option<Vec<u8>>>>,
+    ) -> Result<(), EngineError> {
+        // only the revisions put by this transaction are recorded, the ones
+        // left by a transaction that is never committed are dropped
+        let sizes: Vec<_> = {
+            let kvs = mutations.get(KV_TABLE);
+            mem::take(&mut *self.uncommitted_sizes.lock())
+                .into_iter()
+                .filter(|&(rev, _)| {
+                    kvs.and_then(|kvs| kvs.get(&rev.encode_to_vec()))
+                        .is_some_and(Option::is_some)
+                })
+                .collect()
+        };
+        let applied_index = mutations
+            .get(META_TABLE)
+            .and_then(|kvs| kvs.get(APPLIED_INDEX_KEY.as_bytes()))
+            .and_then(Option::as_deref)
+            .map(decode_applied_index);
+        let result = self.guard_write(|| {
+            self.pipeline.submit(mutations, applied_index);
+            Ok(())
+        });
+        if result.is_ok() {
+            self.record_puts(sizes);
+        }
+        result
     }
 
     /// Get all values of the given table from storage
@@ -107,19 +266,89 @@ impl DB {
     ///
     /// if error occurs in storage, return `Err(error)`
     pub(crate) fn get_all(&self, table: &'static str) -> Result<Vec<KeyValuePair>, ExecuteError> {
-        self.engine.get_all(table).map_err(|e| {
-            ExecuteError::DbError(format!("Failed to get all keys from {table:?}: {e}"))
-        })
+        self.pipeline
+            .overlay_prefix(table, &[], || self.engine.get_all(table))
+            .map_err(|e| {
+                ExecuteError::DbError(format!("Failed to get all keys from {table:?}: {e}"))
+            })
+    }
+
+    /// Get the applied index persisted in the engine, the ones of the batches
+    /// still in the pipeline are not included, so the log entries after it are
+    /// always kept by curp
+    ///
+    /// # Errors
+    ///
+    /// if error occurs in storage, return `Err(error)`
+    pub(crate) fn persisted_applied_index(&self) -> Result<u64, ExecuteError> {
+        let bytes = self
+            .engine
+            .get(META_TABLE, APPLIED_INDEX_KEY)
+            .map_err(|e| ExecuteError::DbError(format!("Failed to get applied index: {e}")))?;
+        Ok(bytes.map_or(0, |bytes| decode_applied_index(&bytes)))
     }
 
-    /// Get the snapshot of the storage
+    /// Check if the given table has no key, only the first key of the table is
+    /// read
+    ///
+    /// # Errors
+    ///
+    /// if error occurs in storage, return `Err(error)`
+    pub(crate) fn is_empty(&self, table: &'static str) -> Result<bool, ExecuteError> {
+        let view = self.view();
+        let mut pairs = Self::iter_view(&view, table)?;
+        pairs.next().transpose().map(|pair| pair.is_none())
+    }
+
+    /// Get the snapshot of the storage, it waits for the running defragment
+    ///
+    /// # Errors
+    ///
+    /// if error occurs in storage, return `Err(error)`
     pub(crate) fn get_snapshot(
         &self,
         snap_path: impl AsRef<Path>,
     ) -> Result<Snapshot, ExecuteError> {
-        self.engine
+        self.pipeline.wait_persisted();
+        let _guard = self
+            .maintenance_lock
+            .try_read()
+            .unwrap_or_else(|| block_in_place(|| self.maintenance_lock.read()));
+        self.snapshot_engine(snap_path)
+    }
+
+    /// Get the snapshot of the storage without waiting for the running
+    /// defragment
+    ///
+    /// # Errors
+    ///
+    /// Return `ExecuteError::StorageBusy` if defragment is running, or other
+    /// errors if error occurs in storage
+    pub(crate) fn try_get_snapshot(
+        &self,
+        snap_path: impl AsRef<Path>,
+    ) -> Result<Snapshot, ExecuteError> {
+        self.pipeline.wait_persisted();
+        let Some(_guard) = self.maintenance_lock.try_read() else {
+            return Err(ExecuteError::StorageBusy);
+        };
+        self.snapshot_engine(snap_path)
+    }
+
+    /// Take the snapshot of the engine and the cold tier, the maintenance lock
+    /// must be held
+    fn snapshot_engine(&self, snap_path: impl AsRef<Path>) -> Result<Snapshot, ExecuteError> {
+        // the revisions migrated while the snapshot is taken would be in neither tier
+        let cold_tier = self.cold_tier.get();
+        let _cold_guard = cold_tier.map(ColdTier::lock);
+        let mut snapshot = self
+            .engine
             .get_snapshot(snap_path, &XLINE_TABLES)
-            .map_err(|e| ExecuteError::DbError(format!("Failed to get snapshot, error: {e}")))
+            .map_err(|e| ExecuteError::DbError(format!("Failed to get snapshot, error: {e}")))?;
+        if let Some(cold_tier) = cold_tier {
+            cold_tier.add_to_snapshot(&mut snapshot)?;
+        }
+        Ok(snapshot)
     }
 
     /// Reset the storage by given snapshot
@@ -128,7 +357,24 @@ impl DB {
     ///
     /// if error occurs in storage, return `Err(error)`
     pub(crate) async fn reset(&self, snapshot: Option<Snapshot>) -> Result<(), ExecuteError> {
+        // the pending batches must not be persisted over the new state
+        self.pipeline.wait_persisted();
+        // the history in the cold tier is replaced along with the hot engine
+        if let Some(cold_tier) = self.cold_tier.get() {
+            cold_tier.clear()?;
+        }
         if let Some(snap) = snapshot {
+            // the cold tier of the snapshot is put back to the kv table if this
+            // member has no cold tier
+            if let Some(cold_tier) = self.cold_tier.get() {
+                cold_tier.ingest_snapshot(&snap)?;
+            } else {
+                self.engine
+                    .ingest_snapshot(&snap, &[KV_TABLE], cold_tier::SNAPSHOT_PREFIX)
+                    .map_err(|e| {
+                        ExecuteError::DbError(format!("Failed to reset database, error: {e}"))
+                    })?;
+            }
             self.engine
                 .apply_snapshot(snap, &XLINE_TABLES)
                 .await
@@ -145,15 +391,238 @@ impl DB {
         }
     }
 
+    /// Get a consistent view of all the tables, the writes go on while it is
+    /// read
+    ///
+    /// The view sees the batches pending in the pipeline, so it's not behind
+    /// the reads of the `DB`, and it does not wait for them to be persisted.
+    pub(crate) fn view(&self) -> PipelinedView<'_> {
+        self.pipeline.view(|| self.engine.view())
+    }
+
+    /// Iterate over the key-value pairs of a table of the view
+    pub(crate) fn iter_view<'v>(
+        view: &'v impl ViewApi,
+        table: &'static str,
+    ) -> Result<impl Iterator<Item = Result<KeyValuePair, ExecuteError>> + 'v, ExecuteError> {
+        let db_err = move |e: EngineError| {
+            ExecuteError::DbError(format!("Failed to iterate over {table:?}: {e}"))
+        };
+        Ok(view
+            .iter(table)
+            .map_err(db_err)?
+            .map(move |pair| pair.map_err(db_err)))
+    }
+
+    /// Iterate over the key-value pairs of the kv table of the views of both
+    /// tiers, in the order of the revisions
+    ///
+    /// The view of the hot engine must be taken before the one of the cold
+    /// tier. A revision is put to the cold tier before it is removed from the hot
+    /// engine, so it's always in one of the views, and only the hot one is
+    /// yielded if it's in both.
+    pub(crate) fn iter_kv_view<'v>(
+        view: &'v PipelinedView<'_>,
+        cold_view: Option<&'v View<'_>>,
+    ) -> Result<impl Iterator<Item = Result<KeyValuePair, ExecuteError>> + 'v, ExecuteError> {
+        Ok(Self::iter_kv_tiers(view, cold_view)?.map(|pair| pair.map(|(pair, _is_hot)| pair)))
+    }
+
+    /// Iterate over the key-value pairs of the kv table of the views of both
+    /// tiers like [`DB::iter_kv_view`], along with whether each pair is in the
+    /// hot engine
+    pub(crate) fn iter_kv_tiers<'v>(
+        view: &'v PipelinedView<'_>,
+        cold_view: Option<&'v View<'_>>,
+    ) -> Result<impl Iterator<Item = Result<(KeyValuePair, bool), ExecuteError>> + 'v, ExecuteError>
+    {
+        let cold_pairs = cold_view
+            .map(|cold_view| Self::iter_view(cold_view, KV_TABLE))
+            .transpose()?
+            .into_iter()
+            .flatten();
+        #[allow(clippy::pattern_type_mismatch)] // can't be fixed
+        Ok(Self::iter_view(view, KV_TABLE)?
+            .merge_join_by(cold_pairs, |hot, cold| match (hot, cold) {
+                (Ok((hot_key, _)), Ok((cold_key, _))) => hot_key.cmp(cold_key),
+                (Err(_), _) => cmp::Ordering::Less,
+                (_, Err(_)) => cmp::Ordering::Greater,
+            })
+            .map(|pair| match pair {
+                EitherOrBoth::Both(hot, _) | EitherOrBoth::Left(hot) => hot.map(|p| (p, true)),
+                EitherOrBoth::Right(cold) => cold.map(|p| (p, false)),
+            }))
+    }
+
+    /// Attach the cold tier in the directory to the kv table, the values are
+    /// encrypted with the keys of the key provider if it is given
+    ///
+    /// # Errors
+    ///
+    /// Return `ExecuteError::DbError` if the cold tier cannot be opened or it's
+    /// already attached
+    pub(crate) fn attach_cold_tier(
+        &self,
+        dir: &Path,
+        key_provider: Option<Arc<dyn KeyProvider>>,
+    ) -> Result<(), ExecuteError> {
+        self.cold_tier
+            .set(ColdTier::open(dir, key_provider)?)
+            .map_err(|_tier| ExecuteError::DbError("cold tier is already attached".to_owned()))
+    }
+
+    /// Get the cold tier, `None` if it's not attached
+    pub(crate) fn cold_tier(&self) -> Option<&ColdTier> {
+        self.cold_tier.get()
+    }
+
+    /// Get a consistent view of the cold tier, `None` if it's not attached
+    pub(crate) fn cold_view(&self) -> Option<View<'_>> {
+        self.cold_tier.get().map(ColdTier::view)
+    }
+
+    /// Move the revisions from the kv table to the cold tier, and record that
+    /// the revisions superseded before `migrated_revision` are migrated, returns
+    /// the number of the revisions moved
+    ///
+    /// A crash after the revisions are put to the cold tier leaves them in both
+    /// tiers, which is harmless as the hot ones are read first and both are
+    /// removed by the compaction.
+    pub(crate) fn migrate_to_cold_tier(
+        &self,
+        revisions: &[Revision],
+        migrated_revision: i64,
+    ) -> Result<usize, ExecuteError> {
+        let Some(cold_tier) = self.cold_tier.get() else {
+            return Ok(0);
+        };
+        let _guard = cold_tier.lock();
+        let keys: Vec<_> = revisions.iter().map(Revision::encode_to_vec).collect();
+        let values = self
+            .get_multi(KV_TABLE, &keys)
+            .map_err(|e| ExecuteError::DbError(format!("Failed to get revisions: {e}")))?;
+        // the revisions compacted since they are selected are skipped
+        let kvs: Vec<_> = keys
+            .into_iter()
+            .zip(values)
+            .filter_map(|(key, value)| value.map(|v| (key, v)))
+            .collect();
+        let migrated_keys: Vec<_> = kvs.iter().map(|&(ref key, _)| key.clone()).collect();
+        cold_tier.put(kvs, migrated_revision)?;
+        let ops = migrated_keys
+            .iter()
+            .map(|key| WriteOperation::new_delete(KV_TABLE, key));
+        self.write_multi(ops, false).map_err(|e| {
+            ExecuteError::DbError(format!("Failed to remove migrated revisions: {e}"))
+        })?;
+        let _size = self.forget_revisions(&migrated_keys);
+        Ok(migrated_keys.len())
+    }
+
+    /// Fill the missing values of the encoded revisions from the cold tier
+    pub(crate) fn fill_cold_values(
+        &self,
+        keys: &[impl AsRef<[u8]>],
+        values: &mut [Option<Vec<u8>>],
+    ) -> Result<(), ExecuteError> {
+        let Some(cold_tier) = self.cold_tier.get() else {
+            return Ok(());
+        };
+        let (missing_keys, missing_values): (Vec<_>, Vec<_>) = keys
+            .iter()
+            .zip(values.iter_mut())
+            .filter(|&(_, ref value)| value.is_none())
+            .unzip();
+        if missing_keys.is_empty() {
+            return Ok(());
+        }
+        for (value, cold_value) in missing_values
+            .into_iter()
+            .zip(cold_tier.get_values(&missing_keys)?)
+        {
+            *value = cold_value;
+        }
+        Ok(())
+    }
+
+    /// Remove the encoded revisions from both tiers of the kv table, returns
+    /// the bytes of the removed hot revisions, which are recorded when they
+    /// are put
+    pub(crate) fn remove_revisions(&self, revisions: &[Vec<u8>]) -> Result<u64, ExecuteError> {
+        let ops = revisions
+            .iter()
+            .map(|rev| WriteOp::DeleteKeyValue(rev.as_ref()))
+            .collect();
+        let Some(cold_tier) = self.cold_tier.get() else {
+            self.write_ops(ops)?;
+            return Ok(self.forget_revisions(revisions));
+        };
+        let _guard = cold_tier.lock();
+        self.write_ops(ops)?;
+        cold_tier.remove(revisions)?;
+        Ok(self.forget_revisions(revisions))
+    }
+
+    /// Get the reason why the storage is read-only, `None` if it's writable
+    ///
+    /// The storage turns read-only on the first persistent write error, e.g.
+    /// the disk is full or broken, and stays read-only until it's restarted.
+    pub(crate) fn read_only_reason(&self) -> Option<&str> {
+        self.write_failure.get().map(String::as_str)
+    }
+
+    /// Check if the storage is writable
+    ///
+    /// # Errors
+    ///
+    /// Return `ExecuteError::ReadOnly` if the storage is read-only
+    pub(crate) fn check_writable(&self) -> Result<(), ExecuteError> {
+        match self.read_only_reason() {
+            Some(reason) => Err(ExecuteError::ReadOnly(reason.to_owned())),
+            None => Ok(()),
+        }
+    }
+
+    /// Turn the storage read-only if the write error is persistent, the error of
+    /// a write bypassing the `DB`, like the commit of a transaction, should be
+    /// recorded by it
+    pub(crate) fn record_write_error(&self, err: &EngineError) {
+        record_write_error(&self.write_failure, err);
+    }
+
+    /// Run a write to the engine, it fails fast if the storage is read-only, or
+    /// turns the storage read-only if it fails persistently
+    fn guard_write(
+        &self,
+        write: impl FnOnce() -> Result<(), EngineError>,
+    ) -> Result<(), EngineError> {
+        if let Some(reason) = self.read_only_reason() {
+            return Err(EngineError::IoError(io::Error::new(
+                io::ErrorKind::Other,
+                format!("storage is read-only: {reason}"),
+            )));
+        }
+        write().map_err(|e| {
+            self.record_write_error(&e);
+            e
+        })
+    }
+
     /// Calculate the hash of the storage
     pub(crate) fn hash(&self) -> Result<u32, ExecuteError> {
         let mut hasher = crc32fast::Hasher::new();
+        // all tables are hashed at the same point
+        let view = self.view();
+        let cold_view = self.cold_view();
         for table in XLINE_TABLES {
             hasher.update(table.as_bytes());
-            let kv_pairs = self.engine.get_all(table).map_err(|e| {
-                ExecuteError::DbError(format!("Failed to get all keys from {table:?}: {e}"))
-            })?;
-            for (k, v) in kv_pairs {
+            let pairs: Box<dyn Iterator<Item = _>> = if table == KV_TABLE {
+                Box::new(Self::iter_kv_view(&view, cold_view.as_ref())?)
+            } else {
+                Box::new(Self::iter_view(&view, table)?)
+            };
+            for pair in pairs {
+                let (k, v) = pair?;
                 hasher.update(&k);
                 hasher.update(&v);
             }
@@ -172,6 +641,174 @@ impl DB {
             .file_size()
             .map_err(|e| ExecuteError::DbError(format!("Failed to get file size, error: {e}")))
     }
+
+    /// Get the estimated size of a table, each store keeps its data in its own
+    /// tables so it's also the size taken by the store
+    pub(crate) fn table_size(&self, table: &'static str) -> Result<u64, ExecuteError> {
+        self.engine.table_size(table).map_err(|e| {
+            ExecuteError::DbError(format!("Failed to get size of {table:?}, error: {e}"))
+        })
+    }
+
+    /// Get the statistics of the engine
+    pub(crate) fn engine_stats(&self) -> Result<EngineStats, ExecuteError> {
+        self.engine.stats().map_err(|e| {
+            ExecuteError::DbError(format!("Failed to get engine statistics, error: {e}"))
+        })
+    }
+
+    /// Defragment the storage, it blocks the current thread until finished and
+    /// the snapshots taken meanwhile wait for it
+    pub(crate) fn defragment(&self) -> Result<(), ExecuteError> {
+        let _guard = self.maintenance_lock.write();
+        self.engine
+            .defragment()
+            .map_err(|e| ExecuteError::DbError(format!("Failed to defragment, error: {e}")))
+    }
+
+    /// Compact the key range `[from, to]` of a table in the engine to reclaim
+    /// the space of the deleted records, returns the bytes reclaimed. It blocks
+    /// the current thread until finished
+    ///
+    /// The maintenance lock is not held, the engine compacts a range safely
+    /// along with the snapshots and the defragment, and a long compaction must
+    /// not make them fail or wait
+    pub(crate) fn compact_range(
+        &self,
+        table: &'static str,
+        from: &[u8],
+        to: &[u8],
+    ) -> Result<u64, ExecuteError> {
+        self.engine
+            .compact_range(table, Some(from), Some(to))
+            .map_err(|e| {
+                ExecuteError::DbError(format!("Failed to compact range of {table:?}, error: {e}"))
+            })
+    }
+
+    /// Change the block compression of a table in the engine, the existing data
+    /// are compressed again when they are compacted
+    pub(crate) fn set_compression(
+        &self,
+        table: &str,
+        compression: CompressionConfig,
+    ) -> Result<(), ExecuteError> {
+        self.engine
+            .set_compression(table, compression)
+            .map_err(|e| {
+                ExecuteError::DbError(format!(
+                    "Failed to set compression of {table:?}, error: {e}"
+                ))
+            })
+    }
+
+    /// Change the durability of the writes to the engine, the batches of the
+    /// pipeline are persisted with it
+    pub(crate) fn set_durability(&self, durability: DurabilityConfig) -> Result<(), ExecuteError> {
+        let mut current = self.durability.lock();
+        self.engine
+            .set_durability(durability)
+            .map_err(|e| ExecuteError::DbError(format!("Failed to set durability, error: {e}")))?;
+        *current = durability;
+        Ok(())
+    }
+
+    /// Get the durability of the writes to the engine
+    pub(crate) fn durability(&self) -> DurabilityConfig {
+        *self.durability.lock()
+    }
+
+    /// Set the max number of the log entries applied but not persisted, it's
+    /// the `log_entries_cap` of curp, which keeps that many entries behind the
+    /// applied index
+    pub(crate) fn set_max_unpersisted_entries(&self, entries: u64) {
+        self.pipeline.set_max_lag(entries);
+    }
+
+    /// Enable or disable the explicit tombstones, they are disabled until the
+    /// committed cluster version supports them, so that every member encodes a
+    /// deletion the same way and the kv table can be sent to every member in a
+    /// snapshot
+    pub(crate) fn set_explicit_tombstones(&self, enabled: bool) {
+        self.explicit_tombstones.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Whether the deletions are written as explicit tombstones
+    pub(crate) fn explicit_tombstones(&self) -> bool {
+        self.explicit_tombstones.load(Ordering::Relaxed)
+    }
+
+    /// Get the logical size of the revisions kept in the kv table, the space of
+    /// the compacted revisions that is not reclaimed by the engine yet is
+    /// excluded
+    pub(crate) fn size_in_use(&self) -> u64 {
+        self.size_in_use.load(Ordering::Relaxed)
+    }
+
+    /// Set the sizes of the revisions kept in the kv table, it's called when
+    /// the kv store is recovered from the whole kv table
+    pub(crate) fn restore_record_sizes(&self, sizes: HashMap<Revision, u64>) {
+        let mut record_sizes = self.record_sizes.lock();
+        let size = sizes
+            .values()
+            .fold(0, |total, &size| total.overflow_add(size));
+        *record_sizes = sizes;
+        self.size_in_use.store(size, Ordering::Relaxed);
+    }
+
+    /// Account the revisions put into the kv table by `ops`, they are counted
+    /// in the size in use once the transaction is committed
+    pub(crate) fn account_puts(&self, ops: &[WriteOp<'_>]) {
+        #[allow(clippy::wildcard_enum_match_arm)] // only the puts of the kv table are counted
+        let sizes = ops.iter().filter_map(|op| match *op {
+            WriteOp::PutKeyValue(rev, ref kv) => Some((rev, kv.encoded_len())),
+            WriteOp::PutEncodedKeyValue(rev, ref value) => Some((rev, value.len())),
+            _ => None,
+        });
+        self.uncommitted_sizes
+            .lock()
+            .extend(sizes.map(|(rev, len)| {
+                let size = REVISION_KEY_LEN
+                    .overflow_add(CHECKSUM_HEADER_LEN)
+                    .overflow_add(len)
+                    .numeric_cast::<u64>();
+                (rev, size)
+            }));
+    }
+
+    /// Record the sizes of the revisions put into the kv table by a committed
+    /// transaction
+    fn record_puts(&self, sizes: Vec<(Revision, u64)>) {
+        let mut record_sizes = self.record_sizes.lock();
+        let (added, replaced) =
+            sizes
+                .into_iter()
+                .fold((0_u64, 0_u64), |(added, replaced), (rev, size)| {
+                    let prev = record_sizes.insert(rev, size).unwrap_or(0);
+                    (added.overflow_add(size), replaced.overflow_add(prev))
+                });
+        let _prev = self
+            .size_in_use
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |s| {
+                Some(s.overflow_add(added).saturating_sub(replaced))
+            });
+    }
+
+    /// Forget the encoded revisions removed from the kv table, returns their
+    /// size in bytes
+    fn forget_revisions(&self, revisions: &[impl AsRef<[u8]>]) -> u64 {
+        let mut record_sizes = self.record_sizes.lock();
+        let size = revisions
+            .iter()
+            .filter_map(|rev| record_sizes.remove(&Revision::decode(rev.as_ref())))
+            .fold(0, u64::overflow_add);
+        let _prev = self
+            .size_in_use
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |s| {
+                Some(s.saturating_sub(size))
+            });
+        size
+    }
 }
 
 impl<T> XlineStorageOps for T
@@ -186,11 +823,15 @@ where
         let mut wr_ops = Vec::new();
         let del_lease_key_buffer = get_del_lease_key_buffer(&ops);
         let del_alarm_buffer = get_del_alarm_buffer(&ops);
+        let mut del_alarm_keys = del_alarm_buffer.iter();
         for op in ops {
             let wop = match op {
                 WriteOp::PutKeyValue(rev, value) => {
                     let key = rev.encode_to_vec();
-                    WriteOperation::new_put(KV_TABLE, key, value.encode_to_vec())
+                    WriteOperation::new_put(KV_TABLE, key, add_checksum(&value.encode_to_vec()))
+                }
+                WriteOp::PutEncodedKeyValue(rev, value) => {
+                    WriteOperation::new_put(KV_TABLE, rev.encode_to_vec(), add_checksum(&value))
                 }
                 WriteOp::PutAppliedIndex(index) => WriteOperation::new_put(
                     META_TABLE,
@@ -212,6 +853,19 @@ where
                     SCHEDULED_COMPACT_REVISION.as_bytes().to_vec(),
                     rev.to_le_bytes().to_vec(),
                 ),
+                WriteOp::PutDowngradeTarget(target) => WriteOperation::new_put(
+                    META_TABLE,
+                    DOWNGRADE_TARGET_KEY.as_bytes().to_vec(),
+                    target.into_bytes(),
+                ),
+                WriteOp::DeleteDowngradeTarget => {
+                    WriteOperation::new_delete(META_TABLE, DOWNGRADE_TARGET_KEY.as_bytes())
+                }
+                WriteOp::PutClusterVersion(version) => WriteOperation::new_put(
+                    META_TABLE,
+                    CLUSTER_VERSION_KEY.as_bytes().to_vec(),
+                    version.into_bytes(),
+                ),
                 WriteOp::DeleteKeyValue(rev) => WriteOperation::new_delete(KV_TABLE, rev),
                 WriteOp::DeleteLease(lease_id) => {
                     let key = del_lease_key_buffer.get(&lease_id).unwrap_or_else(|| {
@@ -248,7 +902,10 @@ where
                     WriteOperation::new_put(ALARM_TABLE, key, vec![])
                 }
                 WriteOp::DeleteAlarm(_key) => {
-                    WriteOperation::new_delete(ALARM_TABLE, del_alarm_buffer.as_ref())
+                    let key = del_alarm_keys
+                        .next()
+                        .unwrap_or_else(|| unreachable!("every deleted alarm has a key"));
+                    WriteOperation::new_delete(ALARM_TABLE, key)
                 }
             };
             wr_ops.push(wop);
@@ -273,6 +930,9 @@ where
     where
         K: AsRef<[u8]> + std::fmt::Debug,
     {
+        if table == KV_TABLE {
+            return get_revision_values(self, keys);
+        }
         let values = self
             .get_multi(table, keys)
             .map_err(|e| ExecuteError::DbError(format!("Failed to get keys {keys:?}: {e}")))?
@@ -285,6 +945,72 @@ where
     }
 }
 
+/// Decode the applied index stored in the meta table
+fn decode_applied_index(bytes: &[u8]) -> u64 {
+    let buf: [u8; 8] = bytes
+        .try_into()
+        .unwrap_or_else(|e| panic!("cannot decode index from backend, {e:?}"));
+    u64::from_le_bytes(buf)
+}
+
+/// Record the write error if it's persistent, the storage turns read-only on
+/// the first one
+pub(super) fn record_write_error(write_failure: &OnceLock<String>, err: &EngineError) {
+    // only the I/O errors are persistent, retrying the write will not help
+    if matches!(*err, EngineError::IoError(_)) && write_failure.set(err.to_string()).is_ok() {
+        error!("storage turns read-only due to a persistent write error: {err}");
+    }
+}
+
+/// Get the values of the revisions in the kv table, the revisions sharing
+/// a main revision are read by a single prefix seek instead of point gets
+#[allow(clippy::indexing_slicing)] // the indexes are taken from the keys
+fn get_revision_values<T, K>(ops: &T, keys: &[K]) -> Result<Vec<Option<Vec<u8>>>, ExecuteError>
+where
+    T: StorageOps,
+    K: AsRef<[u8]> + std::fmt::Debug,
+{
+    let mut groups: HashMap<&[u8], Vec<usize>> = HashMap::new();
+    let mut point_gets = Vec::new();
+    for (i, key) in keys.iter().enumerate() {
+        match key.as_ref().get(..REVISION_PREFIX_LEN) {
+            Some(prefix) => groups.entry(prefix).or_default().push(i),
+            None => point_gets.push(i),
+        }
+    }
+    let mut values = vec![None; keys.len()];
+    for (prefix, indexes) in groups {
+        if indexes.len() < 2 {
+            point_gets.extend(indexes);
+            continue;
+        }
+        let pairs: HashMap<_, _> = ops
+            .get_prefix(KV_TABLE, prefix)
+            .map_err(|e| ExecuteError::DbError(format!("Failed to get prefix {prefix:?}: {e}")))?
+            .into_iter()
+            .collect();
+        for i in indexes {
+            values[i] = pairs.get(keys[i].as_ref()).cloned();
+        }
+    }
+    if !point_gets.is_empty() {
+        let point_keys: Vec<_> = point_gets.iter().map(|&i| keys[i].as_ref()).collect();
+        let point_values = ops.get_multi(KV_TABLE, &point_keys).map_err(|e| {
+            ExecuteError::DbError(format!("Failed to get keys {point_keys:?}: {e}"))
+        })?;
+        if point_values.len() != point_keys.len() {
+            return Err(ExecuteError::DbError(format!(
+                "Failed to get keys {point_keys:?}: got {} values",
+                point_values.len()
+            )));
+        }
+        for (i, value) in point_gets.into_iter().zip(point_values) {
+            values[i] = value;
+        }
+    }
+    Ok(values)
+}
+
 /// Get del lease key buffer
 #[inline]
 fn get_del_lease_key_buffer(ops: &[WriteOp]) -> HashMap<i64, Vec<u8>> {
@@ -299,18 +1025,18 @@ fn get_del_lease_key_buffer(ops: &[WriteOp]) -> HashMap<i64, Vec<u8>> {
         .collect::<HashMap<_, _>>()
 }
 
-/// get del alarm buffer
+/// Get the keys of the deleted alarms, in the order of the operations
 #[inline]
-fn get_del_alarm_buffer(ops: &[WriteOp]) -> Vec<u8> {
+fn get_del_alarm_buffer(ops: &[WriteOp]) -> Vec<Vec<u8>> {
     ops.iter()
-        .find_map(|op| {
+        .filter_map(|op| {
             if let WriteOp::DeleteAlarm(ref key) = *op {
                 Some(key.encode_to_vec())
             } else {
                 None
             }
         })
-        .unwrap_or_default()
+        .collect()
 }
 
 /// Buffered Write Operation
@@ -319,6 +1045,8 @@ fn get_del_alarm_buffer(ops: &[WriteOp]) -> Vec<u8> {
 pub enum WriteOp<'a> {
     /// Put a key-value pair to kv table
     PutKeyValue(Revision, KeyValue),
+    /// Put an encoded key-value pair to kv table
+    PutEncodedKeyValue(Revision, Vec<u8>),
     /// Put the applied index to meta table
     PutAppliedIndex(u64),
     /// Put a lease to lease table
@@ -327,6 +1055,12 @@ pub enum WriteOp<'a> {
     PutFinishedCompactRevision(i64),
     /// Put a scheduled compact revision into meta table
     PutScheduledCompactRevision(i64),
+    /// Put the target version of the downgrade job into meta table
+    PutDowngradeTarget(String),
+    /// Delete the target version of the downgrade job from meta table
+    DeleteDowngradeTarget,
+    /// Put the committed cluster version into meta table
+    PutClusterVersion(String),
     /// Delete a key-value pair from kv table
     DeleteKeyValue(&'a [u8]),
     /// Delete a lease from lease table
@@ -353,11 +1087,12 @@ pub enum WriteOp<'a> {
 mod test {
     use tempfile::TempDir;
 
-    use engine::SnapshotApi;
+    use engine::{SnapshotApi, TransactionApi};
     use test_macros::abort_on_panic;
+    use xlineapi::AlarmType;
 
     use super::*;
-    use crate::storage::Revision;
+    use crate::storage::{compression::decode_kv, Revision};
     #[tokio::test]
     #[abort_on_panic]
     async fn test_reset() -> Result<(), ExecuteError> {
@@ -374,7 +1109,8 @@ mod test {
         let ops = vec![WriteOp::PutKeyValue(revision, kv.clone())];
         db.write_ops(ops)?;
         let res = db.get_value(KV_TABLE, &key)?;
-        assert_eq!(res, Some(kv.encode_to_vec()));
+        assert_eq!(res.as_deref().map(decode_kv).transpose()?, Some(kv));
+        assert!(!db.is_empty(KV_TABLE)?);
 
         db.reset(None).await?;
 
@@ -382,6 +1118,65 @@ mod test {
         assert_eq!(res, vec![None]);
         let res = db.get_all(KV_TABLE)?;
         assert!(res.is_empty());
+        assert!(db.is_empty(KV_TABLE)?);
+
+        dir.close().unwrap();
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[abort_on_panic]
+    async fn get_values_should_keep_the_order_of_the_revisions() -> Result<(), ExecuteError> {
+        let dir =
+            TempDir::with_prefix("/tmp/get_values_should_keep_the_order_of_the_revisions").unwrap();
+        let db = DB::open(&EngineConfig::RocksDB(dir.path().join("engine")))?;
+        let revisions = [
+            Revision::new(5, 0),
+            Revision::new(5, 1),
+            Revision::new(5, 2),
+            Revision::new(6, 0),
+        ];
+        let ops = revisions
+            .iter()
+            .map(|rev| {
+                let kv = KeyValue {
+                    key: rev.encode_to_vec(),
+                    ..Default::default()
+                };
+                WriteOp::PutKeyValue(*rev, kv)
+            })
+            .collect();
+        db.write_ops(ops)?;
+
+        let keys: Vec<_> = [(5, 2), (6, 0), (5, 3), (5, 0), (7, 0), (5, 2)]
+            .into_iter()
+            .map(|(rev, sub)| Revision::new(rev, sub).encode_to_vec())
+            .collect();
+        let got: Vec<_> = db
+            .get_values(KV_TABLE, &keys)?
+            .into_iter()
+            .map(|value| value.as_deref().map(decode_kv).transpose())
+            .collect::<Result<_, _>>()?;
+        let expected: Vec<_> = [true, true, false, true, false, true]
+            .into_iter()
+            .zip(&keys)
+            .map(|(exists, key)| {
+                exists.then(|| KeyValue {
+                    key: key.clone(),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        assert_eq!(got, expected);
+
+        // the prefix seek of a transaction sees its own writes
+        let txn = db.transaction();
+        txn.write_op(WriteOp::DeleteKeyValue(&keys[0]))?;
+        let values = txn.get_values(KV_TABLE, &keys[..4])?;
+        assert_eq!(
+            values.iter().map(Option::is_some).collect::<Vec<_>>(),
+            [false, true, false, true]
+        );
 
         dir.close().unwrap();
         Ok(())
@@ -411,7 +1206,55 @@ mod test {
         new_db.reset(Some(snapshot)).await?;
 
         let res = new_db.get_values(KV_TABLE, &[&key])?;
-        assert_eq!(res, vec![Some(kv.encode_to_vec())]);
+        let res = res
+            .iter()
+            .map(|value| value.as_deref().map(decode_kv).transpose())
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(res, vec![Some(kv)]);
+
+        dir.close().unwrap();
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[abort_on_panic]
+    async fn test_db_snapshot_should_ship_the_cold_tier() -> Result<(), ExecuteError> {
+        let dir = TempDir::with_prefix("/tmp/test_db_snapshot_should_ship_the_cold_tier").unwrap();
+        let origin_db = DB::open(&EngineConfig::RocksDB(dir.path().join("origin_db")))?;
+        origin_db.attach_cold_tier(&dir.path().join("origin_cold"), None)?;
+        let ops = (1..=2)
+            .map(|rev| {
+                let kv = KeyValue {
+                    key: "key".into(),
+                    value: format!("value{rev}").into(),
+                    mod_revision: rev,
+                    ..Default::default()
+                };
+                WriteOp::PutKeyValue(Revision::new(rev, 0), kv)
+            })
+            .collect();
+        origin_db.write_ops(ops)?;
+        assert_eq!(
+            origin_db.migrate_to_cold_tier(&[Revision::new(1, 0)], 2)?,
+            1
+        );
+        let hash = origin_db.hash()?;
+
+        // the cold revisions go to the cold tier of the new member
+        let new_db = DB::open(&EngineConfig::RocksDB(dir.path().join("new_db")))?;
+        new_db.attach_cold_tier(&dir.path().join("new_cold"), None)?;
+        let snapshot = origin_db.get_snapshot(dir.path().join("snapshot"))?;
+        new_db.reset(Some(snapshot)).await?;
+        assert_eq!(new_db.hash()?, hash);
+        assert_eq!(new_db.get_all(KV_TABLE)?.len(), 1);
+        assert_eq!(new_db.cold_tier().unwrap().migrated_revision()?, 2);
+
+        // or back to the kv table of a member without a cold tier
+        let hot_db = DB::open(&EngineConfig::RocksDB(dir.path().join("hot_db")))?;
+        let snapshot = origin_db.get_snapshot(dir.path().join("snapshot"))?;
+        hot_db.reset(Some(snapshot)).await?;
+        assert_eq!(hot_db.hash()?, hash);
+        assert_eq!(hot_db.get_all(KV_TABLE)?.len(), 2);
 
         dir.close().unwrap();
         Ok(())
@@ -441,10 +1284,190 @@ mod test {
         let data_path = dir.path().join("data");
         let snapshot_path = dir.path().join("snapshot");
         let db = DB::open(&EngineConfig::RocksDB(data_path))?;
-        let mut res = db.get_snapshot(snapshot_path)?;
+        let mut res = db.get_snapshot(&snapshot_path)?;
         assert_ne!(res.size(), 0);
         res.clean().await.unwrap();
 
+        // the snapshots are taken along with each other
+        let guard = db.maintenance_lock.read();
+        let mut res = db.try_get_snapshot(dir.path().join("shared"))?;
+        res.clean().await.unwrap();
+        drop(guard);
+
+        // a running defragment rejects the snapshots that do not wait for it
+        let guard = db.maintenance_lock.write();
+        assert!(matches!(
+            db.try_get_snapshot(&snapshot_path),
+            Err(ExecuteError::StorageBusy)
+        ));
+        // compacting a range does not wait for the maintenance operations
+        let _reclaimed = db.compact_range(KV_TABLE, &[0], &[u8::MAX])?;
+        let waiting = std::thread::spawn({
+            let db = Arc::clone(&db);
+            move || db.get_snapshot(snapshot_path).map(|snap| snap.size())
+        });
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert!(!waiting.is_finished());
+        drop(guard);
+        assert_ne!(waiting.join().unwrap()?, 0);
+
+        dir.close().unwrap();
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[abort_on_panic]
+    async fn test_table_size_should_be_separated_by_store() -> Result<(), ExecuteError> {
+        let db = DB::open(&EngineConfig::Memory)?;
+        let kv = KeyValue {
+            key: b"key".to_vec(),
+            value: b"value".to_vec(),
+            ..Default::default()
+        };
+        db.write_ops(vec![WriteOp::PutKeyValue(Revision::new(1, 1), kv.clone())])?;
+        assert_eq!(
+            db.table_size(KV_TABLE)?,
+            REVISION_KEY_LEN
+                .overflow_add(CHECKSUM_HEADER_LEN)
+                .overflow_add(kv.encoded_len())
+                .numeric_cast::<u64>()
+        );
+        assert_eq!(db.table_size(LEASE_TABLE)?, 0);
+        assert_eq!(db.table_size(USER_TABLE)?, 0);
+
+        db.write_ops(vec![WriteOp::PutLease(PbLease {
+            id: 1,
+            ttl: 10,
+            remaining_ttl: 10,
+        })])?;
+        assert_ne!(db.table_size(LEASE_TABLE)?, 0);
+        assert_eq!(db.table_size(USER_TABLE)?, 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[abort_on_panic]
+    async fn test_size_in_use_is_accounted_after_commit() -> Result<(), ExecuteError> {
+        let db = DB::open(&EngineConfig::Memory)?;
+        let ops = |main| {
+            vec![WriteOp::PutKeyValue(
+                Revision::new(main, 0),
+                KeyValue {
+                    key: b"key".to_vec(),
+                    ..Default::default()
+                },
+            )]
+        };
+
+        let txn = db.transaction();
+        db.account_puts(&ops(1));
+        txn.write_ops(ops(1))?;
+        assert_eq!(db.size_in_use(), 0);
+        txn.commit()
+            .map_err(|e| ExecuteError::DbError(e.to_string()))?;
+        let size = db.size_in_use();
+        assert_ne!(size, 0);
+
+        db.record_write_error(&EngineError::IoError(io::Error::new(
+            io::ErrorKind::Other,
+            "disk failure",
+        )));
+        let txn = db.transaction();
+        db.account_puts(&ops(2));
+        txn.write_ops(ops(2))?;
+        assert!(txn.commit().is_err());
+        assert_eq!(db.size_in_use(), size);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[abort_on_panic]
+    async fn test_removed_revisions_should_report_their_recorded_size() -> Result<(), ExecuteError>
+    {
+        let db = DB::open(&EngineConfig::Memory)?;
+        let ops = || {
+            vec![WriteOp::PutKeyValue(
+                Revision::new(1, 0),
+                KeyValue {
+                    key: b"key".to_vec(),
+                    ..Default::default()
+                },
+            )]
+        };
+
+        // the revision accounted by a dropped transaction is reused
+        db.account_puts(&ops());
+        let txn = db.transaction();
+        db.account_puts(&ops());
+        txn.write_ops(ops())?;
+        txn.commit()
+            .map_err(|e| ExecuteError::DbError(e.to_string()))?;
+        let size = db.size_in_use();
+        assert_ne!(size, 0);
+
+        let revisions = [Revision::new(1, 0).encode_to_vec()];
+        assert_eq!(db.remove_revisions(&revisions)?, size);
+        assert_eq!(db.size_in_use(), 0);
+        assert_eq!(db.remove_revisions(&revisions)?, 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[abort_on_panic]
+    async fn test_get_revision_values_should_group_by_main_revision() -> Result<(), ExecuteError> {
+        let dir = TempDir::with_prefix("/tmp/test_get_revision_values").unwrap();
+        let db = DB::open(&EngineConfig::RocksDB(dir.path().join("engine")))?;
+        let revisions = [
+            Revision::new(5, 0),
+            Revision::new(5, 1),
+            Revision::new(5, 2),
+            Revision::new(6, 0),
+            // the main revisions whose prefixes end with `u8::MAX` and its successor
+            Revision::new(255, 0),
+            Revision::new(255, 1),
+            Revision::new(256, 0),
+            Revision::new(256, 1),
+        ];
+        let ops = revisions
+            .iter()
+            .map(|rev| {
+                let kv = KeyValue {
+                    key: rev.encode_to_vec(),
+                    mod_revision: rev.revision(),
+                    ..Default::default()
+                };
+                WriteOp::PutKeyValue(*rev, kv)
+            })
+            .collect();
+        db.write_ops(ops)?;
+
+        let keys: Vec<_> = [
+            Revision::new(5, 0),
+            Revision::new(5, 2),
+            Revision::new(5, 3),
+            Revision::new(6, 0),
+            Revision::new(255, 0),
+            Revision::new(255, 1),
+            Revision::new(255, 2),
+            Revision::new(256, 1),
+        ]
+        .iter()
+        .map(Revision::encode_to_vec)
+        .collect();
+        let found: Vec<_> = db
+            .get_values(KV_TABLE, &keys)?
+            .into_iter()
+            .map(|value| value.map(|v| decode_kv(&v).unwrap().key))
+            .collect();
+        let expected: Vec<_> = keys
+            .iter()
+            .map(|key| {
+                let rev = Revision::decode(key);
+                revisions.contains(&rev).then(|| key.clone())
+            })
+            .collect();
+        assert_eq!(found, expected);
+        assert_eq!(found.iter().filter(|value| value.is_none()).count(), 2);
         dir.close().unwrap();
         Ok(())
     }
@@ -487,8 +1510,12 @@ mod test {
         db.write_ops(write_ops).unwrap();
         assert_eq!(
             db.get_value(KV_TABLE, Revision::new(1, 2).encode_to_vec())
+                .unwrap()
+                .as_deref()
+                .map(decode_kv)
+                .transpose()
                 .unwrap(),
-            Some(kv.encode_to_vec())
+            Some(kv)
         );
         assert_eq!(
             db.get_value(META_TABLE, b"applied_index").unwrap(),
@@ -521,5 +1548,57 @@ mod test {
         );
         assert_eq!(db.get_value(USER_TABLE, b"user").unwrap(), None);
         assert_eq!(db.get_value(ROLE_TABLE, b"role").unwrap(), None);
+
+        let nospace1 = AlarmMember::new(1, AlarmType::Nospace);
+        let nospace2 = AlarmMember::new(2, AlarmType::Nospace);
+        let corrupt1 = AlarmMember::new(1, AlarmType::Corrupt);
+        db.write_ops(vec![
+            WriteOp::PutAlarm(nospace1.clone()),
+            WriteOp::PutAlarm(nospace2.clone()),
+            WriteOp::PutAlarm(corrupt1.clone()),
+        ])
+        .unwrap();
+        db.write_ops(vec![
+            WriteOp::DeleteAlarm(nospace1),
+            WriteOp::DeleteAlarm(corrupt1),
+        ])
+        .unwrap();
+        assert_eq!(
+            db.get_all(ALARM_TABLE).unwrap(),
+            vec![(nospace2.encode_to_vec(), vec![])]
+        );
+    }
+
+    #[tokio::test]
+    #[abort_on_panic]
+    async fn persistent_write_error_should_turn_db_read_only() -> Result<(), ExecuteError> {
+        let db = DB::open(&EngineConfig::Memory)?;
+        db.write_ops(vec![WriteOp::PutAppliedIndex(1)])?;
+
+        db.record_write_error(&EngineError::UnderlyingError("busy".to_owned()));
+        assert!(db.read_only_reason().is_none());
+        db.record_write_error(&EngineError::IoError(io::Error::new(
+            io::ErrorKind::Other,
+            "No space left on device",
+        )));
+        db.record_write_error(&EngineError::IoError(io::Error::new(
+            io::ErrorKind::Other,
+            "Input/output error",
+        )));
+        // the first error is kept
+        assert_eq!(
+            db.read_only_reason(),
+            Some("I/O Error: No space left on device")
+        );
+        assert!(matches!(
+            db.check_writable(),
+            Err(ExecuteError::ReadOnly(_))
+        ));
+        assert!(db.write_ops(vec![WriteOp::PutAppliedIndex(2)]).is_err());
+        assert_eq!(
+            db.get_value(META_TABLE, b"applied_index")?,
+            Some(1u64.to_le_bytes().to_vec())
+        );
+        Ok(())
     }
 }
@@ -1,9 +1,15 @@
 #![allow(clippy::multiple_inherent_impl)]
 #![allow(unused)] // Remove this when `IndexState` is used in xline
 
-use std::collections::{btree_map, BTreeMap, HashSet};
-
-use clippy_utilities::OverflowArithmetic;
+use std::{
+    cmp::Ordering,
+    collections::{btree_map, hash_map::DefaultHasher, BTreeMap, HashSet},
+    hash::{Hash, Hasher},
+    iter, mem,
+    ops::ControlFlow,
+};
+
+use clippy_utilities::{NumericCast, OverflowArithmetic};
 use crossbeam_skiplist::{map::Entry, SkipMap};
 use itertools::Itertools;
 use parking_lot::{Mutex, RwLock};
@@ -16,7 +22,25 @@ use crate::server::command::RangeType;
 /// Operations for `Index`
 pub(crate) trait IndexOperate {
     /// Get `Revision` of keys, get the latest `Revision` when revision <= 0
-    fn get(&self, key: &[u8], range_end: &[u8], revision: i64) -> Vec<Revision>;
+    fn get(&self, key: &[u8], range_end: &[u8], revision: i64) -> Vec<Revision> {
+        self.revisions(key, range_end, revision).collect()
+    }
+
+    /// Iterate over the `Revision` of keys in the order of the keys, iterate at
+    /// the latest `Revision` when revision <= 0
+    ///
+    /// The index is walked as the iterator advances, so a range read can stop
+    /// early without collecting the revisions of the whole range.
+    fn revisions<'a>(
+        &'a self,
+        key: &[u8],
+        range_end: &[u8],
+        revision: i64,
+    ) -> Box<dyn Iterator<Item = Revision> + 'a>;
+
+    /// Count the keys that are alive at the given revision, count at the latest
+    /// revision when revision <= 0
+    fn count_range(&self, key: &[u8], range_end: &[u8], revision: i64) -> usize;
 
     /// Register a new `KeyRevision` of the given key
     ///
@@ -34,37 +58,177 @@ pub(crate) trait IndexOperate {
     /// Insert or update `KeyRevision`
     fn insert(&self, key_revisions: Vec<(Vec<u8>, KeyRevision)>);
 
-    /// Mark keys as deleted and return latest revision before deletion and deletion revision
+    /// Mark keys as deleted, `op` is applied to every deleted key with its
+    /// latest `KeyRevision` before deletion and its deletion revision
+    ///
+    /// The keys are visited in key order while the range is walked, and the
+    /// deletions get dense sub revisions starting from `sub_revision`.
+    fn delete_each(
+        &self,
+        key: &[u8],
+        range_end: &[u8],
+        revision: i64,
+        sub_revision: i64,
+        op: &mut dyn FnMut(&[u8], KeyRevision, Revision),
+    );
+
+    /// Mark keys as deleted and return the latest `KeyRevision` before deletion and deletion revision
     /// return all revision pairs and all keys in range
+    #[cfg(test)]
     fn delete(
         &self,
         key: &[u8],
         range_end: &[u8],
         revision: i64,
         sub_revision: i64,
-    ) -> (Vec<(Revision, Revision)>, Vec<Vec<u8>>);
+    ) -> (Vec<(KeyRevision, Revision)>, Vec<Vec<u8>>) {
+        let mut pairs = Vec::new();
+        let mut keys = Vec::new();
+        self.delete_each(
+            key,
+            range_end,
+            revision,
+            sub_revision,
+            &mut |key, last_rev, del_rev| {
+                pairs.push((last_rev, del_rev));
+                keys.push(key.to_vec());
+            },
+        );
+        (pairs, keys)
+    }
+
+    /// Remove the `KeyRevision`s registered at `revision`, so that a request
+    /// failed halfway leaves no revision behind
+    fn rollback(&self, revision: i64);
+}
+
+/// Number of the shards of `Index`
+const INDEX_SHARDS: usize = 16;
+
+/// Number of the keys an `IndexState` deletes at a time in a range
+const DELETE_CHUNK_SIZE: usize = 1024;
+
+/// A shard of `Index`
+type Shard = SkipMap<Vec<u8>, RwLock<Vec<KeyRevision>>>;
+
+/// The keys given a `KeyRevision` at the latest revision
+///
+/// Only the request of the latest revision can fail halfway, so a rollback
+/// visits the keys it touched instead of every key. A range deletion records
+/// its range rather than every deleted key.
+#[derive(Debug, Default)]
+struct LatestKeys {
+    /// The latest revision
+    revision: i64,
+    /// Keys given a `KeyRevision` at the revision
+    keys: Vec<Vec<u8>>,
+    /// Ranges deleted at the revision
+    ranges: Vec<KeyRange>,
+}
+
+impl LatestKeys {
+    /// Forget the keys of the previous revision when a new revision comes
+    fn advance(&mut self, revision: i64) {
+        if revision != self.revision {
+            self.revision = revision;
+            self.keys.clear();
+            self.ranges.clear();
+        }
+    }
+
+    /// Record the keys given a `KeyRevision` at a revision
+    fn record<'a>(&mut self, revision: i64, keys: impl IntoIterator<Item = &'a [u8]>) {
+        self.advance(revision);
+        self.keys.extend(keys.into_iter().map(<[u8]>::to_vec));
+    }
+
+    /// Record a range deleted at a revision
+    fn record_range(&mut self, revision: i64, range: KeyRange) {
+        self.advance(revision);
+        self.ranges.push(range);
+    }
+
+    /// Take the keys and the ranges given a `KeyRevision` at the revision
+    fn take(&mut self, revision: i64) -> (Vec<Vec<u8>>, Vec<KeyRange>) {
+        if revision == self.revision {
+            (mem::take(&mut self.keys), mem::take(&mut self.ranges))
+        } else {
+            (Vec::new(), Vec::new())
+        }
+    }
 }
 
 /// Keys to revisions mapping
+///
+/// The keys are sharded by their hashes, so that the writes of different keys
+/// rarely meet in the same skiplist. A range is read by merging the ranges of
+/// every shard in the order of the keys.
 #[derive(Debug)]
 pub(crate) struct Index {
-    /// Inner struct of `Index`
-    inner: SkipMap<Vec<u8>, RwLock<Vec<KeyRevision>>>,
+    /// Shards of the keys
+    shards: Vec<Shard>,
+    /// The keys given a `KeyRevision` at the latest revision
+    latest: Mutex<LatestKeys>,
 }
 
 impl Index {
     /// New `Index`
     pub(crate) fn new() -> Self {
         Self {
-            inner: SkipMap::new(),
+            shards: iter::repeat_with(SkipMap::new).take(INDEX_SHARDS).collect(),
+            latest: Mutex::default(),
         }
     }
 
+    /// Get the shard of a key
+    fn shard(&self, key: &[u8]) -> &Shard {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let idx: usize = hasher
+            .finish()
+            .overflow_rem(INDEX_SHARDS.numeric_cast())
+            .numeric_cast();
+        self.shards
+            .get(idx)
+            .unwrap_or_else(|| unreachable!("shard {idx} should exist"))
+    }
+
+    /// Iterate over the entries of every shard in the order of the keys
+    fn iter(&self) -> impl Iterator<Item = Entry<'_, Vec<u8>, RwLock<Vec<KeyRevision>>>> {
+        self.shards
+            .iter()
+            .map(SkipMap::iter)
+            .kmerge_by(|a, b| a.key() < b.key())
+    }
+
+    /// Iterate over the entries in the range of every shard in the order of
+    /// the keys
+    fn range(
+        &self,
+        range: KeyRange,
+    ) -> impl Iterator<Item = Entry<'_, Vec<u8>, RwLock<Vec<KeyRevision>>>> {
+        self.shards
+            .iter()
+            .map(move |shard| shard.range(range.clone()))
+            .kmerge_by(|a, b| a.key() < b.key())
+    }
+
+    /// Get the entry of a key
+    fn get_entry(&self, key: &[u8]) -> Option<Entry<'_, Vec<u8>, RwLock<Vec<KeyRevision>>>> {
+        self.shard(key).get(key)
+    }
+
+    /// Remove the entry of a key
+    fn remove(&self, key: &[u8]) {
+        let _ignore = self.shard(key).remove(key);
+    }
+
     /// Creates a `IndexState`
     pub(crate) fn state(&self) -> IndexState<'_> {
         IndexState {
             index_ref: self,
             state: Mutex::default(),
+            latest: Mutex::default(),
         }
     }
 
@@ -96,13 +260,21 @@ impl Index {
             .map(KeyRevision::as_revision)
     }
 
-    /// Get all revisions that need to be kept after compact at the given revision
+    /// Get the last `KeyRevision` if the key is not deleted
+    fn last_live_revision(revs: &[KeyRevision]) -> Option<KeyRevision> {
+        revs.last().filter(|kr| !kr.is_deleted()).copied()
+    }
+
+    /// Get all revisions no greater than the given revision that need to be kept
+    /// after compact at it, which are the same as the ones `compact` keeps
     pub(crate) fn keep(&self, at_rev: i64) -> HashSet<Revision> {
         let mut revs = HashSet::new();
-        self.inner.iter().for_each(|entry| {
+        self.iter().for_each(|entry| {
             entry.value().map_read(|revisions| {
                 if let Some(revision) = revisions.first() {
-                    if revision.mod_revision < at_rev {
+                    if revision.mod_revision == at_rev {
+                        _ = revs.insert(revision.as_revision());
+                    } else if revision.mod_revision < at_rev {
                         let pivot = revisions.partition_point(|rev| rev.mod_revision <= at_rev);
                         let compacted_last_idx = pivot.overflow_sub(1);
                         let key_rev = revisions.get(compacted_last_idx).unwrap_or_else(|| {
@@ -120,13 +292,25 @@ impl Index {
         revs
     }
 
-    /// Insert `KeyRevision` of deleted and generate `Revision` pair of deleted
+    /// Remove the `KeyRevision`s registered at `revision` from an entry,
+    /// return true if no `KeyRevision` is left
+    fn pop_revisions(entry: &Entry<'_, Vec<u8>, RwLock<Vec<KeyRevision>>>, revision: i64) -> bool {
+        entry.value().map_write(|mut revs| {
+            while revs.last().is_some_and(|rev| rev.mod_revision == revision) {
+                let _rev = revs.pop();
+            }
+            revs.is_empty()
+        })
+    }
+
+    /// Insert `KeyRevision` of deleted and generate the pair of the last live
+    /// `KeyRevision` and the `Revision` of deleted
     fn gen_del_revision(
         revs: &mut Vec<KeyRevision>,
         revision: i64,
         sub_revision: i64,
-    ) -> Option<(Revision, Revision)> {
-        let last_available_rev = Self::get_revision(revs, 0)?;
+    ) -> Option<(KeyRevision, Revision)> {
+        let last_available_rev = Self::last_live_revision(revs)?;
         let del_rev = KeyRevision::new_deletion(revision, sub_revision);
         revs.push(del_rev);
         Some((last_available_rev, del_rev.as_revision()))
@@ -143,8 +327,7 @@ impl Index {
     ) -> Vec<Revision> {
         match RangeType::get_range_type(key, range_end) {
             RangeType::OneKey => self
-                .inner
-                .get(key)
+                .get_entry(key)
                 .map(|entry| {
                     entry
                         .value()
@@ -152,7 +335,6 @@ impl Index {
                 })
                 .unwrap_or_default(),
             RangeType::AllKeys => self
-                .inner
                 .iter()
                 .flat_map(|entry| {
                     entry
@@ -162,7 +344,6 @@ impl Index {
                 .sorted()
                 .collect(),
             RangeType::Range => self
-                .inner
                 .range(KeyRange::new(key, range_end))
                 .flat_map(|entry| {
                     entry
@@ -174,8 +355,24 @@ impl Index {
         }
     }
 
+    /// Get the revisions superseded by a newer revision of the same key whose
+    /// main revision is in `[from, to)`, in the order of the revisions
+    pub(super) fn superseded(&self, from: i64, to: i64) -> Vec<Revision> {
+        let mut superseded = Vec::new();
+        self.iter().for_each(fmap_value(|revs| {
+            superseded.extend(
+                revs.iter()
+                    .tuple_windows()
+                    .filter(|&(_, next)| (from..to).contains(&next.mod_revision))
+                    .map(|(rev, _)| rev.as_revision()),
+            );
+        }));
+        superseded.sort_unstable();
+        superseded
+    }
+
     /// Restore `KeyRevision` of a key
-    pub(super) fn restore(
+    pub(crate) fn restore(
         &self,
         key: Vec<u8>,
         revision: i64,
@@ -183,7 +380,7 @@ impl Index {
         create_revision: i64,
         version: i64,
     ) {
-        self.inner
+        self.shard(&key)
             .get_or_insert(key, RwLock::new(Vec::new()))
             .value()
             .map_write(|mut revisions| {
@@ -202,7 +399,7 @@ impl Index {
         let mut revs = Vec::new();
         let mut del_keys = Vec::new();
 
-        self.inner.iter().for_each(|entry| {
+        self.iter().for_each(|entry| {
             entry.value().map_write(|mut revisions| {
                 if let Some(revision) = revisions.first() {
                     if revision.mod_revision < at_rev {
@@ -230,7 +427,7 @@ impl Index {
             });
         });
         for key in del_keys {
-            let _ignore = self.inner.remove(&key);
+            self.remove(&key);
         }
         revs
     }
@@ -265,24 +462,43 @@ where
 }
 
 impl IndexOperate for Index {
-    fn get(&self, key: &[u8], range_end: &[u8], revision: i64) -> Vec<Revision> {
+    fn revisions<'a>(
+        &'a self,
+        key: &[u8],
+        range_end: &[u8],
+        revision: i64,
+    ) -> Box<dyn Iterator<Item = Revision> + 'a> {
+        match RangeType::get_range_type(key, range_end) {
+            RangeType::OneKey => Box::new(
+                self.get_entry(key)
+                    .and_then(fmap_value(|revs| Index::get_revision(revs, revision)))
+                    .into_iter(),
+            ),
+            RangeType::AllKeys => Box::new(
+                self.iter()
+                    .filter_map(fmap_value(move |revs| Index::get_revision(revs, revision))),
+            ),
+            RangeType::Range => Box::new(
+                self.range(KeyRange::new(key, range_end))
+                    .filter_map(fmap_value(move |revs| Index::get_revision(revs, revision))),
+            ),
+        }
+    }
+
+    fn count_range(&self, key: &[u8], range_end: &[u8], revision: i64) -> usize {
         match RangeType::get_range_type(key, range_end) {
             RangeType::OneKey => self
-                .inner
-                .get(key)
+                .get_entry(key)
                 .and_then(fmap_value(|revs| Index::get_revision(revs, revision)))
-                .map(|rev| vec![rev])
-                .unwrap_or_default(),
+                .map_or(0, |_rev| 1),
             RangeType::AllKeys => self
-                .inner
                 .iter()
                 .filter_map(fmap_value(|revs| Index::get_revision(revs, revision)))
-                .collect(),
+                .count(),
             RangeType::Range => self
-                .inner
                 .range(KeyRange::new(key, range_end))
                 .filter_map(fmap_value(|revs| Index::get_revision(revs, revision)))
-                .collect(),
+                .count(),
         }
     }
 
@@ -292,10 +508,12 @@ impl IndexOperate for Index {
         revision: i64,
         sub_revision: i64,
     ) -> (KeyRevision, Option<KeyRevision>) {
-        self.inner.get(&key).map_or_else(
+        self.latest.lock().record(revision, [key.as_slice()]);
+        let shard = self.shard(&key);
+        shard.get(&key).map_or_else(
             || {
                 let new_rev = KeyRevision::new(revision, 1, revision, sub_revision);
-                let _ignore = self.inner.insert(key, RwLock::new(vec![new_rev]));
+                let _ignore = shard.insert(key, RwLock::new(vec![new_rev]));
                 (new_rev, None)
             },
             fmap_value_mut(|revisions| {
@@ -319,16 +537,19 @@ impl IndexOperate for Index {
     }
 
     fn current_rev(&self, key: &[u8]) -> Option<KeyRevision> {
-        self.inner
-            .get(key)
+        self.get_entry(key)
             .and_then(fmap_value(|revs| revs.last().copied()))
     }
 
     fn insert(&self, key_revisions: Vec<(Vec<u8>, KeyRevision)>) {
         for (key, revision) in key_revisions {
-            self.inner.get(&key).map_or_else(
+            self.latest
+                .lock()
+                .record(revision.mod_revision, [key.as_slice()]);
+            let shard = self.shard(&key);
+            shard.get(&key).map_or_else(
                 || {
-                    let _ignore = self.inner.insert(key, RwLock::new(vec![revision]));
+                    let _ignore = shard.insert(key, RwLock::new(vec![revision]));
                 },
                 fmap_value_mut(|revs| {
                     revs.push(revision);
@@ -337,54 +558,51 @@ impl IndexOperate for Index {
         }
     }
 
-    fn delete(
+    fn delete_each(
         &self,
         key: &[u8],
         range_end: &[u8],
         revision: i64,
         sub_revision: i64,
-    ) -> (Vec<(Revision, Revision)>, Vec<Vec<u8>>) {
-        let (pairs, keys) = match RangeType::get_range_type(key, range_end) {
-            RangeType::OneKey => {
-                let pairs: Vec<(Revision, Revision)> = self
-                    .inner
-                    .get(key)
-                    .into_iter()
-                    .filter_map(fmap_value_mut(|revs| {
-                        Self::gen_del_revision(revs, revision, sub_revision)
-                    }))
-                    .collect();
-                let keys = if pairs.is_empty() {
-                    vec![]
-                } else {
-                    vec![key.to_vec()]
-                };
-                (pairs, keys)
+        op: &mut dyn FnMut(&[u8], KeyRevision, Revision),
+    ) {
+        let mut next_sub_revision = sub_revision;
+        let mut delete_entry = |entry: Entry<'_, Vec<u8>, RwLock<Vec<KeyRevision>>>| {
+            let deleted = entry.value().map_write(|mut revs| {
+                Self::gen_del_revision(&mut revs, revision, next_sub_revision)
+            });
+            if let Some((last_rev, del_rev)) = deleted {
+                next_sub_revision = next_sub_revision.overflow_add(1);
+                op(entry.key(), last_rev, del_rev);
             }
-            RangeType::AllKeys => self
-                .inner
-                .iter()
-                .zip(0..)
-                .filter_map(|(entry, i)| {
-                    entry.value().map_write(|mut revs| {
-                        Self::gen_del_revision(&mut revs, revision, sub_revision.overflow_add(i))
-                            .map(|pair| (pair, entry.key().clone()))
-                    })
-                })
-                .unzip(),
-            RangeType::Range => self
-                .inner
-                .range(KeyRange::new(key, range_end))
-                .zip(0..)
-                .filter_map(|(entry, i)| {
-                    entry.value().map_write(|mut revs| {
-                        Self::gen_del_revision(&mut revs, revision, sub_revision.overflow_add(i))
-                            .map(|pair| (pair, entry.key().clone()))
-                    })
-                })
-                .unzip(),
         };
-        (pairs, keys)
+        if let RangeType::OneKey = RangeType::get_range_type(key, range_end) {
+            self.latest.lock().record(revision, [key]);
+            self.get_entry(key).into_iter().for_each(&mut delete_entry);
+        } else {
+            let range = KeyRange::new(key, range_end);
+            self.latest.lock().record_range(revision, range.clone());
+            self.range(range).for_each(&mut delete_entry);
+        }
+    }
+
+    fn rollback(&self, revision: i64) {
+        let (keys, ranges) = self.latest.lock().take(revision);
+        for key in keys {
+            let Some(entry) = self.get_entry(&key) else {
+                continue;
+            };
+            if Self::pop_revisions(&entry, revision) {
+                self.remove(&key);
+            }
+        }
+        for range in ranges {
+            for entry in self.range(range) {
+                if Self::pop_revisions(&entry, revision) {
+                    let _removed = entry.remove();
+                }
+            }
+        }
     }
 }
 
@@ -395,14 +613,18 @@ pub(crate) struct IndexState<'a> {
     index_ref: &'a Index,
     /// State current modification
     state: Mutex<BTreeMap<Vec<u8>, Vec<KeyRevision>>>,
+    /// The keys given a `KeyRevision` at the latest revision of the state
+    latest: Mutex<LatestKeys>,
 }
 
 impl IndexState<'_> {
     /// Commits all changes
     pub(crate) fn commit(self) {
-        let index = &self.index_ref.inner;
         while let Some((key, state_revs)) = self.state.lock().pop_first() {
-            let entry = index.get_or_insert(key, RwLock::default());
+            let entry = self
+                .index_ref
+                .shard(&key)
+                .get_or_insert(key, RwLock::default());
             fmap_value_mut(|revs| {
                 revs.extend_from_slice(&state_revs);
             })(entry);
@@ -412,6 +634,7 @@ impl IndexState<'_> {
     /// Discards all changes
     pub(crate) fn discard(&self) {
         self.state.lock().clear();
+        *self.latest.lock() = LatestKeys::default();
     }
 
     /// Gets the revisions for a single key
@@ -420,9 +643,9 @@ impl IndexState<'_> {
         key: &[u8],
         state: &BTreeMap<Vec<u8>, Vec<KeyRevision>>,
     ) -> Vec<KeyRevision> {
-        let index = &self.index_ref.inner;
-        let mut result = index
-            .get(key)
+        let mut result = self
+            .index_ref
+            .get_entry(key)
             .map(fmap_value(<[KeyRevision]>::to_vec))
             .unwrap_or_default();
         if let Some(revs) = state.get(key) {
@@ -431,36 +654,99 @@ impl IndexState<'_> {
         result
     }
 
-    /// Gets the revisions for a range of keys
-    fn range_key_revisions(&self, range: KeyRange) -> BTreeMap<Vec<u8>, Vec<KeyRevision>> {
-        let mut map: BTreeMap<Vec<u8>, Vec<KeyRevision>> = BTreeMap::new();
-        let index = &self.index_ref.inner;
+    /// Applies `op` to the revisions of every key in `range` in key order,
+    /// merging the committed index with the uncommitted state on the fly,
+    /// until `op` breaks.
+    ///
+    /// Revisions of keys that are not touched by the state are borrowed from
+    /// the index instead of being cloned.
+    fn for_each_range<F>(&self, range: KeyRange, mut op: F)
+    where
+        F: FnMut(&[u8], &[KeyRevision]) -> ControlFlow<()>,
+    {
+        // `BTreeMap::range` panics when the start is greater than the end
+        if range.is_empty() {
+            return;
+        }
         let state = self.state.lock();
-        for (key, value) in index
-            .range(range.clone())
-            .map(fmap_entry(|(k, v)| (k.to_vec(), v.to_vec())))
-            .chain(state.range(range).map(|(k, v)| (k.clone(), v.clone())))
-        {
-            let entry = map.entry(key.clone()).or_default();
-            entry.extend(value);
+        let mut index_iter = self.index_ref.range(range.clone()).peekable();
+        let mut state_iter = state.range(range).peekable();
+        loop {
+            let order = match (index_iter.peek(), state_iter.peek()) {
+                (None, None) => break,
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(entry), Some(&(key, _))) => entry.key().cmp(key),
+            };
+            let flow = match order {
+                Ordering::Less => index_iter
+                    .next()
+                    .map(fmap_entry(|(key, revs)| op(key, revs))),
+                Ordering::Greater => state_iter.next().map(|(key, revs)| op(key, revs)),
+                Ordering::Equal => {
+                    let Some((entry, (key, state_revs))) = index_iter.next().zip(state_iter.next())
+                    else {
+                        continue;
+                    };
+                    let mut revs = entry.value().read().clone();
+                    revs.extend_from_slice(state_revs);
+                    Some(op(key, &revs))
+                }
+            };
+            if flow.is_some_and(|flow| flow.is_break()) {
+                break;
+            }
         }
-        map
     }
 
-    /// Gets the revisions for all keys
-    fn all_key_revisions(&self) -> BTreeMap<Vec<u8>, Vec<KeyRevision>> {
-        let mut map: BTreeMap<Vec<u8>, Vec<KeyRevision>> = BTreeMap::new();
-        let index = &self.index_ref.inner;
-        let state = self.state.lock();
-        for (key, value) in index
-            .iter()
-            .map(fmap_entry(|(k, v)| (k.to_vec(), v.to_vec())))
-            .chain(state.clone().into_iter())
-        {
-            let entry = map.entry(key.clone()).or_default();
-            entry.extend(value.clone());
-        }
-        map
+    /// Iterates over the revisions at `revision` of every key in `range` in key
+    /// order, see `for_each_range`
+    ///
+    /// The uncommitted state of the range is copied at first, while the
+    /// committed index is walked as the iterator advances.
+    fn range_revisions(
+        &self,
+        range: KeyRange,
+        revision: i64,
+    ) -> impl Iterator<Item = Revision> + '_ {
+        // `BTreeMap::range` panics when the start is greater than the end
+        let state: Vec<_> = if range.is_empty() {
+            Vec::new()
+        } else {
+            self.state
+                .lock()
+                .range(range.clone())
+                .map(|(key, revs)| (key.clone(), revs.clone()))
+                .collect()
+        };
+        let mut index_iter = self.index_ref.range(range).peekable();
+        let mut state_iter = state.into_iter().peekable();
+        iter::from_fn(move || loop {
+            let order = match (index_iter.peek(), state_iter.peek()) {
+                (None, None) => return None,
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(entry), Some(&(ref key, _))) => entry.key().cmp(key),
+            };
+            let rev = match order {
+                Ordering::Less => index_iter
+                    .next()
+                    .and_then(fmap_value(|revs| Index::get_revision(revs, revision))),
+                Ordering::Greater => state_iter
+                    .next()
+                    .and_then(|(_key, revs)| Index::get_revision(&revs, revision)),
+                Ordering::Equal => index_iter.next().zip(state_iter.next()).and_then(
+                    |(entry, (_key, state_revs))| {
+                        let mut revs = entry.value().read().clone();
+                        revs.extend(state_revs);
+                        Index::get_revision(&revs, revision)
+                    },
+                ),
+            };
+            if rev.is_some() {
+                return rev;
+            }
+        })
     }
 
     /// Deletes one key
@@ -469,43 +755,62 @@ impl IndexState<'_> {
         key: &[u8],
         revision: i64,
         sub_revision: i64,
-    ) -> Option<((Revision, Revision), Vec<u8>)> {
+        op: &mut dyn FnMut(&[u8], KeyRevision, Revision),
+    ) {
         let mut state = self.state.lock();
         let revs = self.one_key_revisions(key, &state);
-        let last_available_rev = Index::get_revision(&revs, 0)?;
-        let entry = state.entry(key.to_vec()).or_default();
+        let Some(last_available_rev) = Index::last_live_revision(&revs) else {
+            return;
+        };
         let del_rev = KeyRevision::new_deletion(revision, sub_revision);
-        entry.push(del_rev);
-        let pair = (last_available_rev, del_rev.as_revision());
-
-        Some((pair, key.to_vec()))
+        op(key, last_available_rev, del_rev.as_revision());
+        state.entry(key.to_vec()).or_default().push(del_rev);
     }
 
     /// Deletes a range of keys
+    ///
+    /// The range is walked in chunks of `DELETE_CHUNK_SIZE` live keys, the
+    /// tombstones of a chunk are registered in the state before the walk
+    /// resumes after the last key of the chunk.
     fn delete_range(
         &self,
-        range: KeyRange,
-        revision: i64,
-        sub_revision: i64,
-    ) -> (Vec<(Revision, Revision)>, Vec<Vec<u8>>) {
-        self.range_key_revisions(range)
-            .into_keys()
-            .zip(0..)
-            .filter_map(|(key, i)| self.delete_one(&key, revision, sub_revision.overflow_add(i)))
-            .unzip()
-    }
-
-    /// Deletes all keys
-    fn delete_all(
-        &self,
+        key: &[u8],
+        range_end: &[u8],
         revision: i64,
         sub_revision: i64,
-    ) -> (Vec<(Revision, Revision)>, Vec<Vec<u8>>) {
-        self.all_key_revisions()
-            .into_keys()
-            .zip(0..)
-            .filter_map(|(key, i)| self.delete_one(&key, revision, sub_revision.overflow_add(i)))
-            .unzip()
+        op: &mut dyn FnMut(&[u8], KeyRevision, Revision),
+    ) {
+        let mut next_sub_revision = sub_revision;
+        let mut start = key.to_vec();
+        loop {
+            let mut chunk = Vec::with_capacity(DELETE_CHUNK_SIZE);
+            self.for_each_range(KeyRange::new(start, range_end), |key, revs| {
+                if let Some(last_available_rev) = Index::last_live_revision(revs) {
+                    chunk.push((key.to_vec(), last_available_rev));
+                }
+                if chunk.len() < DELETE_CHUNK_SIZE {
+                    ControlFlow::Continue(())
+                } else {
+                    ControlFlow::Break(())
+                }
+            });
+            let is_last_chunk = chunk.len() < DELETE_CHUNK_SIZE;
+            let Some(mut next_start) = chunk.last().map(|&(ref key, _)| key.clone()) else {
+                return;
+            };
+            next_start.push(0);
+            let mut state = self.state.lock();
+            for (key, last_available_rev) in chunk {
+                let del_rev = KeyRevision::new_deletion(revision, next_sub_revision);
+                next_sub_revision = next_sub_revision.overflow_add(1);
+                op(&key, last_available_rev, del_rev.as_revision());
+                state.entry(key).or_default().push(del_rev);
+            }
+            if is_last_chunk {
+                return;
+            }
+            start = next_start;
+        }
     }
 
     /// Reads an entry
@@ -516,23 +821,39 @@ impl IndexState<'_> {
 }
 
 impl IndexOperate for IndexState<'_> {
-    fn get(&self, key: &[u8], range_end: &[u8], revision: i64) -> Vec<Revision> {
+    fn revisions<'a>(
+        &'a self,
+        key: &[u8],
+        range_end: &[u8],
+        revision: i64,
+    ) -> Box<dyn Iterator<Item = Revision> + 'a> {
+        match RangeType::get_range_type(key, range_end) {
+            RangeType::OneKey => Box::new(
+                Index::get_revision(&self.one_key_revisions(key, &self.state.lock()), revision)
+                    .into_iter(),
+            ),
+            RangeType::AllKeys | RangeType::Range => {
+                Box::new(self.range_revisions(KeyRange::new(key, range_end), revision))
+            }
+        }
+    }
+
+    fn count_range(&self, key: &[u8], range_end: &[u8], revision: i64) -> usize {
         match RangeType::get_range_type(key, range_end) {
             RangeType::OneKey => {
                 Index::get_revision(&self.one_key_revisions(key, &self.state.lock()), revision)
-                    .map(|rev| vec![rev])
-                    .unwrap_or_default()
+                    .map_or(0, |_rev| 1)
+            }
+            RangeType::AllKeys | RangeType::Range => {
+                let mut count = 0_usize;
+                self.for_each_range(KeyRange::new(key, range_end), |_key, revs| {
+                    if Index::get_revision(revs, revision).is_some() {
+                        count = count.overflow_add(1);
+                    }
+                    ControlFlow::Continue(())
+                });
+                count
             }
-            RangeType::AllKeys => self
-                .all_key_revisions()
-                .into_iter()
-                .filter_map(|(_, revs)| Index::get_revision(revs.as_ref(), revision))
-                .collect(),
-            RangeType::Range => self
-                .range_key_revisions(KeyRange::new(key, range_end))
-                .into_iter()
-                .filter_map(|(_, revs)| Index::get_revision(revs.as_ref(), revision))
-                .collect(),
         }
     }
 
@@ -542,7 +863,8 @@ impl IndexOperate for IndexState<'_> {
         revision: i64,
         sub_revision: i64,
     ) -> (KeyRevision, Option<KeyRevision>) {
-        let index = &self.index_ref.inner;
+        self.latest.lock().record(revision, [key.as_slice()]);
+        let index = self.index_ref;
         let mut state = self.state.lock();
 
         let next_rev = |revisions: &[KeyRevision]| {
@@ -562,7 +884,7 @@ impl IndexOperate for IndexState<'_> {
             (new_rev, Some(last))
         };
 
-        match (index.get(&key), state.entry(key)) {
+        match (index.get_entry(&key), state.entry(key)) {
             (None, btree_map::Entry::Vacant(e)) => {
                 let new_rev = KeyRevision::new(revision, 1, revision, sub_revision);
                 let _ignore = e.insert(vec![new_rev]);
@@ -582,10 +904,10 @@ impl IndexOperate for IndexState<'_> {
     }
 
     fn current_rev(&self, key: &[u8]) -> Option<KeyRevision> {
-        let index = &self.index_ref.inner;
+        let index = self.index_ref;
         let state = self.state.lock();
 
-        match (index.get(key), state.get(key)) {
+        match (index.get_entry(key), state.get(key)) {
             (None, None) => None,
             (None | Some(_), Some(revs)) => revs.last().copied(),
             (Some(e), None) => fmap_value(|revs| revs.last().copied())(e),
@@ -595,6 +917,9 @@ impl IndexOperate for IndexState<'_> {
     fn insert(&self, key_revisions: Vec<(Vec<u8>, KeyRevision)>) {
         let mut state = self.state.lock();
         for (key, revision) in key_revisions {
+            self.latest
+                .lock()
+                .record(revision.mod_revision, [key.as_slice()]);
             if let Some(revs) = state.get_mut::<[u8]>(key.as_ref()) {
                 revs.push(revision);
             } else {
@@ -603,25 +928,46 @@ impl IndexOperate for IndexState<'_> {
         }
     }
 
-    fn delete(
+    fn delete_each(
         &self,
         key: &[u8],
         range_end: &[u8],
         revision: i64,
         sub_revision: i64,
-    ) -> (Vec<(Revision, Revision)>, Vec<Vec<u8>>) {
-        let (pairs, keys) = match RangeType::get_range_type(key, range_end) {
-            RangeType::OneKey => self
-                .delete_one(key, revision, sub_revision)
-                .into_iter()
-                .unzip(),
-            RangeType::AllKeys => self.delete_all(revision, sub_revision),
-            RangeType::Range => {
-                self.delete_range(KeyRange::new(key, range_end), revision, sub_revision)
-            }
-        };
+        op: &mut dyn FnMut(&[u8], KeyRevision, Revision),
+    ) {
+        if let RangeType::OneKey = RangeType::get_range_type(key, range_end) {
+            self.latest.lock().record(revision, [key]);
+            self.delete_one(key, revision, sub_revision, op);
+        } else {
+            self.latest
+                .lock()
+                .record_range(revision, KeyRange::new(key, range_end));
+            self.delete_range(key, range_end, revision, sub_revision, op);
+        }
+    }
 
-        (pairs, keys)
+    fn rollback(&self, revision: i64) {
+        let (keys, ranges) = self.latest.lock().take(revision);
+        let mut state = self.state.lock();
+        for key in keys {
+            if let btree_map::Entry::Occupied(mut entry) = state.entry(key) {
+                entry.get_mut().retain(|rev| rev.mod_revision != revision);
+                if entry.get().is_empty() {
+                    let _revs = entry.remove();
+                }
+            }
+        }
+        for range in ranges {
+            // `BTreeMap::range_mut` panics when the start is greater than the end
+            if range.is_empty() {
+                continue;
+            }
+            for (_key, revs) in state.range_mut(range) {
+                revs.retain(|rev| rev.mod_revision != revision);
+            }
+            state.retain(|_key, revs| !revs.is_empty());
+        }
     }
 }
 
@@ -631,8 +977,7 @@ mod test {
     #[allow(clippy::expect_used)]
     fn match_values(index: &Index, key: impl AsRef<[u8]>, expected_values: &[KeyRevision]) {
         index
-            .inner
-            .get(key.as_ref())
+            .get_entry(key.as_ref())
             .map(fmap_value(|revs| assert_eq!(revs, expected_values)))
             .expect("index entry should not be None");
     }
@@ -720,6 +1065,173 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_state_range_merges_uncommitted_revisions() {
+        let index = init_and_test_insert();
+        let txn = index.state();
+        txn.register_revision(b"baz".to_vec(), 10, 0);
+        txn.register_revision(b"foo".to_vec(), 11, 0);
+
+        assert_eq!(
+            txn.get(b"a", b"g", 0),
+            vec![
+                Revision::new(9, 9),
+                Revision::new(10, 0),
+                Revision::new(11, 0)
+            ]
+        );
+        assert_eq!(
+            txn.get(b"\0", b"\0", 8),
+            vec![
+                Revision::new(7, 7),
+                Revision::new(8, 8),
+                Revision::new(3, 1)
+            ]
+        );
+        assert_eq!(
+            txn.delete(b"a", b"g", 12, 0),
+            (
+                vec![
+                    (Revision::new(9, 9), Revision::new(12, 0)),
+                    (Revision::new(10, 0), Revision::new(12, 1)),
+                    (Revision::new(11, 0), Revision::new(12, 2)),
+                ],
+                vec![b"bar".to_vec(), b"baz".to_vec(), b"foo".to_vec()]
+            )
+        );
+        assert_eq!(txn.get(b"\0", b"\0", 0), vec![Revision::new(3, 1)]);
+    }
+
+    #[test]
+    fn test_revisions_should_be_iterated_in_key_order() {
+        let index = init_and_test_insert();
+        let mut revisions = index.revisions(b"\0", b"\0", 0);
+        assert_eq!(revisions.next(), Some(Revision::new(9, 9)));
+        assert_eq!(revisions.count(), 2);
+
+        let txn = index.state();
+        txn.register_revision(b"baz".to_vec(), 10, 0);
+        _ = txn.delete(b"foo", b"", 11, 0);
+        assert_eq!(
+            txn.revisions(b"\0", b"\0", 0).take(2).collect::<Vec<_>>(),
+            vec![Revision::new(9, 9), Revision::new(10, 0)]
+        );
+        assert_eq!(
+            txn.revisions(b"baz", b"key", 10).collect::<Vec<_>>(),
+            vec![Revision::new(10, 0), Revision::new(8, 8)]
+        );
+    }
+
+    #[test]
+    fn test_rollback_should_only_remove_the_latest_revision() {
+        let index = init_and_test_insert();
+        let txn = index.state();
+        txn.register_revision(b"baz".to_vec(), 10, 0);
+        txn.register_revision(b"foo".to_vec(), 11, 0);
+        txn.register_revision(b"qux".to_vec(), 11, 1);
+        _ = txn.delete(b"key", b"", 11, 2);
+        txn.rollback(11);
+        let before = vec![
+            Revision::new(9, 9),
+            Revision::new(10, 0),
+            Revision::new(8, 8),
+            Revision::new(3, 1),
+        ];
+        assert_eq!(txn.get(b"\0", b"\0", 0), before);
+        txn.commit();
+
+        index.register_revision(b"qux".to_vec(), 12, 0);
+        _ = index.delete(b"bar", b"", 12, 1);
+        index.rollback(12);
+        assert_eq!(index.get(b"\0", b"\0", 0), before);
+        assert!(index.get_entry(b"qux").is_none());
+        match_values(
+            &index,
+            b"bar",
+            &[
+                KeyRevision::new(5, 1, 5, 4),
+                KeyRevision::new(5, 2, 7, 7),
+                KeyRevision::new(5, 3, 9, 9),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_sharded_ranges_should_be_merged_in_key_order() {
+        let index = Index::new();
+        let keys: Vec<Vec<u8>> = (0..100_u8).map(|i| vec![b'k', i]).collect();
+        for (rev, key) in (1..).zip(keys.iter().rev()) {
+            index.register_revision(key.clone(), rev, 0);
+        }
+        assert!(
+            index
+                .shards
+                .iter()
+                .filter(|shard| !shard.is_empty())
+                .count()
+                > 1
+        );
+
+        let in_key_order: Vec<_> = (1..=100).rev().map(|rev| Revision::new(rev, 0)).collect();
+        assert_eq!(index.get(b"k", b"l", 0), in_key_order);
+        assert_eq!(index.get(b"\0", b"\0", 0), in_key_order);
+        assert_eq!(index.count_range(b"k", b"l", 0), 100);
+        let txn = index.state();
+        assert_eq!(txn.get(b"k", b"l", 0), in_key_order);
+    }
+
+    #[test]
+    fn test_superseded() {
+        let index = init_and_test_insert();
+        assert_eq!(
+            index.superseded(0, 7),
+            vec![
+                Revision::new(1, 3),
+                Revision::new(2, 2),
+                Revision::new(4, 5)
+            ]
+        );
+        assert_eq!(
+            index.superseded(7, 9),
+            vec![Revision::new(5, 4), Revision::new(6, 6)]
+        );
+        // the latest revisions are never superseded
+        assert_eq!(index.superseded(9, 100), vec![Revision::new(7, 7)]);
+        assert!(index.superseded(10, 100).is_empty());
+    }
+
+    #[test]
+    fn test_count_range() {
+        let index = init_and_test_insert();
+        assert_eq!(index.count_range(b"key", b"", 0), 1);
+        assert_eq!(index.count_range(b"baz", b"", 0), 0);
+        assert_eq!(index.count_range(b"a", b"g", 0), 2);
+        assert_eq!(index.count_range(b"\0", b"\0", 0), 3);
+        assert_eq!(index.count_range(b"\0", b"\0", 2), 1);
+
+        let txn = index.state();
+        txn.register_revision(b"baz".to_vec(), 10, 0);
+        assert_eq!(txn.count_range(b"baz", b"", 0), 1);
+        assert_eq!(txn.count_range(b"a", b"g", 0), 3);
+        _ = txn.delete(b"foo", b"", 11, 0);
+        assert_eq!(txn.count_range(b"\0", b"\0", 0), 3);
+        assert_eq!(txn.count_range(b"\0", b"\0", 10), 4);
+        assert_eq!(index.count_range(b"\0", b"\0", 0), 3);
+    }
+
+    #[test]
+    fn test_empty_range_selects_nothing() {
+        let index = init_and_test_insert();
+        let txn = index.state();
+        txn.register_revision(b"baz".to_vec(), 10, 0);
+        assert!(index.get(b"g", b"a", 0).is_empty());
+        assert!(txn.get(b"g", b"a", 0).is_empty());
+        assert!(txn.get(b"foo", b"foo", 0).is_empty());
+        assert_eq!(txn.count_range(b"g", b"a", 0), 0);
+        assert_eq!(txn.delete(b"g", b"a", 11, 0), (vec![], vec![]));
+        assert_eq!(txn.count_range(b"\0", b"\0", 0), 4);
+    }
+
     #[test]
     fn test_delete() {
         let index = init_and_test_insert();
@@ -782,6 +1294,66 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_delete_range_has_dense_sub_revisions() {
+        let index = init_and_test_insert();
+        assert_eq!(
+            index.delete(b"foo", b"", 10, 0),
+            (
+                vec![(KeyRevision::new(4, 3, 8, 8), Revision::new(10, 0))],
+                vec![b"foo".to_vec()]
+            )
+        );
+        assert_eq!(
+            index.delete(b"\0", b"\0", 11, 0),
+            (
+                vec![
+                    (KeyRevision::new(5, 3, 9, 9), Revision::new(11, 0)),
+                    (KeyRevision::new(1, 3, 3, 1), Revision::new(11, 1)),
+                ],
+                vec![b"bar".to_vec(), b"key".to_vec()]
+            )
+        );
+        match_values(
+            &index,
+            b"key",
+            &[
+                KeyRevision::new(1, 1, 1, 3),
+                KeyRevision::new(1, 2, 2, 2),
+                KeyRevision::new(1, 3, 3, 1),
+                KeyRevision::new_deletion(11, 1),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_state_delete_range_over_chunks() {
+        let index = Index::new();
+        let size = DELETE_CHUNK_SIZE.overflow_add(1);
+        let keys: Vec<Vec<u8>> = (0..size).map(|i| format!("k{i:05}").into_bytes()).collect();
+        index.insert(
+            keys.iter()
+                .map(|key| (key.clone(), KeyRevision::new(1, 1, 1, 0)))
+                .collect(),
+        );
+        let txn = index.state();
+        let (pairs, deleted) = txn.delete(b"k", b"l", 2, 0);
+        assert_eq!(deleted, keys);
+        let sub_revisions: Vec<i64> = pairs
+            .iter()
+            .map(|&(_, del_rev)| del_rev.sub_revision())
+            .collect();
+        assert_eq!(
+            sub_revisions,
+            (0..size.numeric_cast()).collect::<Vec<i64>>()
+        );
+        assert_eq!(txn.count_range(b"k", b"l", 0), 0);
+
+        txn.rollback(2);
+        assert_eq!(txn.count_range(b"k", b"l", 0), size);
+        assert!(txn.state.lock().is_empty());
+    }
+
     #[test]
     fn test_restore() {
         let index = Index::new();
@@ -3,37 +3,47 @@
 use std::{
     cmp::Ordering,
     collections::HashMap,
+    num::NonZeroUsize,
     sync::{
-        atomic::{AtomicI64, Ordering::Relaxed},
+        atomic::{AtomicI64, AtomicUsize, Ordering::Relaxed},
         Arc,
     },
 };
 
 use clippy_utilities::{NumericCast, OverflowArithmetic};
-use engine::{Transaction, TransactionApi};
+use engine::TransactionApi;
+use itertools::Itertools;
+use lru::LruCache;
+use parking_lot::Mutex;
 use prost::Message;
 use tracing::{debug, warn};
-use utils::table_names::{KV_TABLE, META_TABLE};
+use utils::{
+    config::default_max_range_bytes,
+    table_names::{KV_TABLE, META_TABLE},
+};
 use xlineapi::{
     command::{CommandResponse, KeyRange, SyncResponse},
     execute_error::ExecuteError,
 };
 
 use super::{
+    compression::{self, KvRecord, Tombstone, ValueCompression},
     db::{DB, SCHEDULED_COMPACT_REVISION},
     index::{Index, IndexOperate},
     lease_store::LeaseCollection,
+    pipeline::PipelinedTransaction,
     revision::{KeyRevision, Revision},
 };
 use crate::{
     header_gen::HeaderGenerator,
+    metrics,
     revision_check::RevisionCheck,
     revision_number::{RevisionNumberGenerator, RevisionNumberGeneratorState},
     rpc::{
         CompactionRequest, CompactionResponse, Compare, CompareResult, CompareTarget,
         DeleteRangeRequest, DeleteRangeResponse, Event, EventType, KeyValue, PutRequest,
-        PutResponse, RangeRequest, RangeResponse, Request, RequestWrapper, ResponseWrapper,
-        SortOrder, SortTarget, TargetUnion, TxnRequest, TxnResponse,
+        PutResponse, RangeRequest, RangeResponse, Request, RequestOp, RequestWrapper,
+        ResponseWrapper, SortOrder, SortTarget, TargetUnion, TxnRequest, TxnResponse,
     },
     storage::{
         db::{WriteOp, FINISHED_COMPACT_REVISION},
@@ -41,6 +51,9 @@ use crate::{
     },
 };
 
+/// Number of revisions fetched from the DB at a time when assembling a range
+const RANGE_CHUNK_SIZE: usize = 1024;
+
 /// KV store
 #[derive(Debug)]
 pub(crate) struct KvStore {
@@ -56,6 +69,14 @@ pub(crate) struct KvStore {
     compact_task_tx: flume::Sender<(i64, Option<Arc<event_listener::Event>>)>,
     /// Lease collection
     lease_collection: Arc<LeaseCollection>,
+    /// Compression of the values written to the kv table
+    value_compression: ValueCompression,
+    /// Cache of the latest `KeyValue` of hot keys, `None` if disabled
+    kv_cache: Option<Mutex<LruCache<Vec<u8>, KeyValue>>>,
+    /// Revision and reclaimed bytes of the last finished compaction
+    last_compaction: Mutex<Option<(i64, u64)>>,
+    /// Max size of the kvs of a range result in bytes
+    max_range_bytes: usize,
 }
 
 /// KV store inner, shared by `KvStore` and `KvWatcher`
@@ -67,6 +88,19 @@ pub(crate) struct KvStoreInner {
     db: Arc<DB>,
     /// Compacted Revision
     compacted_rev: AtomicI64,
+    /// Number of the watchers asking for `prev_kv`
+    prev_kv_watchers: AtomicUsize,
+}
+
+/// Registration of a watcher asking for `prev_kv`, the previous kvs are captured
+/// while syncing requests as long as one is alive
+#[derive(Debug)]
+pub(crate) struct PrevKvWatcher(Arc<KvStoreInner>);
+
+impl Drop for PrevKvWatcher {
+    fn drop(&mut self) {
+        let _prev = self.0.prev_kv_watchers.fetch_sub(1, Relaxed);
+    }
 }
 
 impl KvStoreInner {
@@ -76,11 +110,44 @@ impl KvStoreInner {
             index,
             db,
             compacted_rev: AtomicI64::new(-1),
+            prev_kv_watchers: AtomicUsize::new(0),
         }
     }
 
     /// Get `KeyValue` from the `KvStore`
     fn get_values<T>(txn: &T, revisions: &[Revision]) -> Result<Vec<KeyValue>, ExecuteError>
+    where
+        T: XlineStorageOps,
+    {
+        Self::get_values_with_cold_tier(txn, None, revisions)
+    }
+
+    /// Get `KeyValue` from the `KvStore`, the values missing in `txn` are read
+    /// from the cold tier of `db`
+    ///
+    /// Only the historical revisions may be in the cold tier, the latest
+    /// revisions of the keys are always read by `get_values`.
+    fn get_values_with_cold_tier<T>(
+        txn: &T,
+        db: Option<&DB>,
+        revisions: &[Revision],
+    ) -> Result<Vec<KeyValue>, ExecuteError>
+    where
+        T: XlineStorageOps,
+    {
+        Ok(Self::get_records_with_cold_tier(txn, db, revisions)?
+            .into_iter()
+            .map(KvRecord::into_kv)
+            .collect())
+    }
+
+    /// Get the records of the revisions, the tombstones are kept as they are,
+    /// the values missing in `txn` are read from the cold tier of `db`
+    fn get_records_with_cold_tier<T>(
+        txn: &T,
+        db: Option<&DB>,
+        revisions: &[Revision],
+    ) -> Result<Vec<KvRecord>, ExecuteError>
     where
         T: XlineStorageOps,
     {
@@ -88,17 +155,21 @@ impl KvStoreInner {
             .iter()
             .map(Revision::encode_to_vec)
             .collect::<Vec<Vec<u8>>>();
-        let values = txn.get_values(KV_TABLE, &revisions)?;
-        let kvs: Vec<KeyValue> = values
+        let mut values = txn.get_values(KV_TABLE, &revisions)?;
+        if let Some(db) = db {
+            db.fill_cold_values(&revisions, &mut values)?;
+        }
+        let records: Vec<KvRecord> = values
             .into_iter()
             .flatten()
-            .map(|v| KeyValue::decode(v.as_slice()))
-            .collect::<Result<_, _>>()
-            .map_err(|e| {
-                ExecuteError::DbError(format!("Failed to decode key-value from DB, error: {e}"))
-            })?;
-        debug_assert_eq!(kvs.len(), revisions.len(), "index does not match with db");
-        Ok(kvs)
+            .map(|v| compression::decode_record(&v))
+            .collect::<Result<_, _>>()?;
+        debug_assert_eq!(
+            records.len(),
+            revisions.len(),
+            "index does not match with db"
+        );
+        Ok(records)
     }
 
     /// Get `KeyValue` of a range
@@ -121,61 +192,106 @@ impl KvStoreInner {
 
     /// Get `KeyValue` of a range with limit and count only, return kvs and
     /// total count
-    fn get_range_with_opts<T>(
+    ///
+    /// The revisions are streamed from the index and their values are fetched
+    /// from the DB chunk by chunk, `op` is applied to each chunk before it is
+    /// kept, so neither the revisions nor the values dropped by `op` pile up in
+    /// memory. Fetching stops as soon as `limit` kvs are kept, the rest of the
+    /// range is only counted. The values missing in `txn_db` are read from the
+    /// cold tier of `db`. The range fails once the kept kvs take more than
+    /// `max_bytes` bytes.
+    #[allow(clippy::too_many_arguments)] // the range options are passed as is
+    fn get_range_with_opts<T, F>(
         txn_db: &T,
+        db: Option<&DB>,
         index: &dyn IndexOperate,
         key: &[u8],
         range_end: &[u8],
         revision: i64,
         limit: usize,
         count_only: bool,
+        max_bytes: Option<usize>,
+        mut op: F,
     ) -> Result<(Vec<KeyValue>, usize), ExecuteError>
     where
         T: XlineStorageOps,
+        F: FnMut(&mut Vec<KeyValue>),
     {
-        let mut revisions = index.get(key, range_end, revision);
-        let total = revisions.len();
-        if count_only || total == 0 {
-            return Ok((vec![], total));
+        if count_only {
+            return Ok((vec![], index.count_range(key, range_end, revision)));
         }
-        if limit != 0 {
-            revisions.truncate(limit);
+        let mut revisions = index.revisions(key, range_end, revision);
+        let mut total = 0;
+        let mut bytes = 0_usize;
+        let mut kvs = Vec::new();
+        let mut chunk = Vec::new();
+        loop {
+            let chunk_size = if limit == 0 {
+                RANGE_CHUNK_SIZE
+            } else if kvs.len() < limit {
+                RANGE_CHUNK_SIZE.min(limit.overflow_sub(kvs.len()))
+            } else {
+                break;
+            };
+            chunk.clear();
+            chunk.extend(revisions.by_ref().take(chunk_size));
+            if chunk.is_empty() {
+                break;
+            }
+            total = total.overflow_add(chunk.len());
+            let mut chunk_kvs = Self::get_values_with_cold_tier(txn_db, db, &chunk)?;
+            op(&mut chunk_kvs);
+            if let Some(max_bytes) = max_bytes {
+                bytes = chunk_kvs
+                    .iter()
+                    .map(KeyValue::encoded_len)
+                    .fold(bytes, usize::overflow_add);
+                if bytes > max_bytes {
+                    return Err(ExecuteError::RangeTooLarge(max_bytes.numeric_cast()));
+                }
+            }
+            kvs.append(&mut chunk_kvs);
         }
-        let kvs = Self::get_values(txn_db, &revisions)?;
-        Ok((kvs, total))
+        // the rest of the range is only counted
+        Ok((kvs, total.overflow_add(revisions.count())))
     }
 
     /// Get previous `KeyValue` of a `KeyValue`
     pub(crate) fn get_prev_kv(&self, kv: &KeyValue) -> Option<KeyValue> {
-        Self::get_range(
-            self.db.as_ref(),
-            self.index.as_ref(),
-            &kv.key,
-            &[],
-            kv.mod_revision.overflow_sub(1),
-        )
-        .ok()?
-        .pop()
-    }
-
-    /// Get `KeyValue` start from a revision and convert to `Event`
+        let revisions = self
+            .index
+            .get(&kv.key, &[], kv.mod_revision.overflow_sub(1));
+        let db = self.db.as_ref();
+        Self::get_values_with_cold_tier(db, Some(db), &revisions)
+            .ok()?
+            .pop()
+    }
+
+    /// Get `KeyValue` of the key ranges start from a revision and convert to
+    /// `Event`, in the order of revisions
     pub(crate) fn get_event_from_revision(
         &self,
-        key_range: KeyRange,
+        key_ranges: &[KeyRange],
         revision: i64,
     ) -> Result<Vec<Event>, ExecuteError> {
-        let revisions =
-            self.index
-                .get_from_rev(key_range.range_start(), key_range.range_end(), revision);
-        let events = Self::get_values(self.db.as_ref(), &revisions)?
+        let revisions = key_ranges
+            .iter()
+            .flat_map(|key_range| {
+                self.index
+                    .get_from_rev(key_range.range_start(), key_range.range_end(), revision)
+            })
+            .sorted()
+            .dedup()
+            .collect_vec();
+        let db = self.db.as_ref();
+        let events = Self::get_records_with_cold_tier(db, Some(db), &revisions)?
             .into_iter()
-            .map(|kv| {
-                // Delete
-                #[allow(clippy::as_conversions)] // This cast is always valid
-                let event_type = if kv.version == 0 && kv.create_revision == 0 {
-                    EventType::Delete
-                } else {
-                    EventType::Put
+            .map(|record| {
+                let (event_type, kv) = match record {
+                    KvRecord::Put(kv) => (EventType::Put, kv),
+                    KvRecord::Tombstone(tombstone) => {
+                        (EventType::Delete, tombstone.to_deletion_kv())
+                    }
                 };
                 let mut event = Event {
                     kv: Some(kv),
@@ -193,6 +309,17 @@ impl KvStoreInner {
     pub(crate) fn compacted_revision(&self) -> i64 {
         self.compacted_rev.load(Relaxed)
     }
+
+    /// Register a watcher asking for `prev_kv`
+    pub(crate) fn register_prev_kv_watcher(self: &Arc<Self>) -> PrevKvWatcher {
+        let _prev = self.prev_kv_watchers.fetch_add(1, Relaxed);
+        PrevKvWatcher(Arc::clone(self))
+    }
+
+    /// Check if any watcher asks for `prev_kv`
+    fn has_prev_kv_watchers(&self) -> bool {
+        self.prev_kv_watchers.load(Relaxed) > 0
+    }
 }
 
 impl KvStore {
@@ -200,7 +327,7 @@ impl KvStore {
     pub(crate) fn execute(
         &self,
         request: &RequestWrapper,
-        as_ctx: Option<(&Transaction, &mut dyn IndexOperate)>,
+        as_ctx: Option<(&PipelinedTransaction<'_>, &mut dyn IndexOperate)>,
     ) -> Result<CommandResponse, ExecuteError> {
         if let Some((db, index)) = as_ctx {
             self.execute_request(request, db, index)
@@ -230,34 +357,66 @@ impl KvStore {
     }
 
     /// Recover data from persistent storage
+    ///
+    /// The index is only kept in memory and rebuilt from the key-values here,
+    /// which are committed in the same transaction as the applied index, so a
+    /// crash between the db write and the index update never leaves them
+    /// inconsistent, and the commands after the applied index are replayed
+    /// from the curp log.
     pub(crate) async fn recover(&self) -> Result<(), ExecuteError> {
         let mut key_to_lease: HashMap<Vec<u8>, i64> = HashMap::new();
-        let kvs = self.inner.db.get_all(KV_TABLE)?;
-
-        let current_rev = kvs
-            .last()
-            .map_or(1, |pair| Revision::decode(&pair.0).revision());
-        self.revision.set(current_rev);
-
-        for (key, value) in kvs {
-            let rev = Revision::decode(key.as_slice());
-            let kv = KeyValue::decode(value.as_slice())
-                .unwrap_or_else(|e| panic!("decode kv error: {e:?}"));
-
-            if kv.lease == 0 {
-                let _ignore = key_to_lease.remove(&kv.key);
-            } else {
-                let _ignore = key_to_lease.insert(kv.key.clone(), kv.lease);
+        // the index is rebuilt from the revisions of both tiers, while only the
+        // hot ones are in use of the hot engine
+        let (record_sizes, current_rev) = {
+            let view = self.inner.db.view();
+            let cold_view = self.inner.db.cold_view();
+            let mut record_sizes = HashMap::new();
+            let mut current_rev = 1;
+            for pair in DB::iter_kv_tiers(&view, cold_view.as_ref())? {
+                let ((key, value), is_hot) = pair?;
+                let rev = Revision::decode(key.as_slice());
+                if is_hot {
+                    let _prev = record_sizes.insert(
+                        rev,
+                        key.len().overflow_add(value.len()).numeric_cast::<u64>(),
+                    );
+                }
+                current_rev = rev.revision();
+                match compression::decode_record(&value)? {
+                    KvRecord::Put(kv) => {
+                        if kv.lease == 0 {
+                            let _ignore = key_to_lease.remove(&kv.key);
+                        } else {
+                            let _ignore = key_to_lease.insert(kv.key.clone(), kv.lease);
+                        }
+                        self.inner.index.restore(
+                            kv.key,
+                            rev.revision(),
+                            rev.sub_revision(),
+                            kv.create_revision,
+                            kv.version,
+                        );
+                    }
+                    KvRecord::Tombstone(tombstone) => {
+                        let _ignore = key_to_lease.remove(&tombstone.key);
+                        // the index keeps a deletion as a revision without version
+                        self.inner.index.restore(
+                            tombstone.key,
+                            rev.revision(),
+                            rev.sub_revision(),
+                            0,
+                            0,
+                        );
+                    }
+                }
             }
+            (record_sizes, current_rev)
+        };
 
-            self.inner.index.restore(
-                kv.key,
-                rev.revision(),
-                rev.sub_revision(),
-                kv.create_revision,
-                kv.version,
-            );
-        }
+        self.inner.db.restore_record_sizes(record_sizes);
+        self.revision.set(current_rev);
+        // no updates to dispatch, only let the watchers know the recovered revision
+        self.notify_updates(current_rev, vec![]);
 
         for (key, lease_id) in key_to_lease {
             self.attach(lease_id, key)?;
@@ -304,6 +463,8 @@ impl KvStore {
         kv_update_tx: flume::Sender<(i64, Vec<Event>)>,
         compact_task_tx: flume::Sender<(i64, Option<Arc<event_listener::Event>>)>,
         lease_collection: Arc<LeaseCollection>,
+        value_compression: ValueCompression,
+        kv_cache_capacity: usize,
     ) -> Self {
         Self {
             inner,
@@ -312,9 +473,22 @@ impl KvStore {
             kv_update_tx,
             compact_task_tx,
             lease_collection,
+            value_compression,
+            kv_cache: NonZeroUsize::new(kv_cache_capacity)
+                .map(|capacity| Mutex::new(LruCache::new(capacity))),
+            last_compaction: Mutex::new(None),
+            max_range_bytes: default_max_range_bytes(),
         }
     }
 
+    /// Limit the size of the kvs of a range result, so that a big range cannot
+    /// materialize the whole table in memory
+    #[must_use]
+    pub(crate) fn with_max_range_bytes(mut self, max_range_bytes: usize) -> Self {
+        self.max_range_bytes = max_range_bytes;
+        self
+    }
+
     /// Get revision of KV store
     pub(crate) fn revision(&self) -> i64 {
         self.revision.get()
@@ -457,7 +631,7 @@ impl KvStore {
                 } else {
                     0
                 };
-                Self::compare_i64(kv.mod_revision, les)
+                Self::compare_i64(kv.lease, les)
             }
         };
 
@@ -474,8 +648,29 @@ impl KvStore {
     where
         T: XlineStorageOps,
     {
-        let kvs =
-            KvStoreInner::get_range(txn_db, index, &cmp.key, &cmp.range_end, 0).unwrap_or_default();
+        // The range is compared chunk by chunk, so that a compare over a big
+        // range never holds all of its kvs and stops at the first key failing
+        let mut revisions = index.revisions(&cmp.key, &cmp.range_end, 0).peekable();
+        if revisions.peek().is_none() {
+            return Self::compare_kvs(cmp, &[]);
+        }
+        let mut chunk = Vec::with_capacity(RANGE_CHUNK_SIZE);
+        loop {
+            chunk.clear();
+            chunk.extend(revisions.by_ref().take(RANGE_CHUNK_SIZE));
+            if chunk.is_empty() {
+                return true;
+            }
+            let matched = KvStoreInner::get_values(txn_db, &chunk)
+                .is_ok_and(|kvs| kvs.iter().all(|kv| Self::compare_kv(cmp, kv)));
+            if !matched {
+                return false;
+            }
+        }
+    }
+
+    /// Check result of a `Compare` against the `KeyValue`s in its range
+    fn compare_kvs(cmp: &Compare, kvs: &[KeyValue]) -> bool {
         if kvs.is_empty() {
             if let Some(TargetUnion::Value(_)) = cmp.target_union {
                 false
@@ -487,6 +682,25 @@ impl KvStore {
         }
     }
 
+    /// Check results of the `Compare`s of a txn
+    ///
+    /// Single key compares, e.g. the one of a compare-and-swap, are served by the
+    /// kv cache, so the key put by the previous swap is not read from the DB again.
+    fn check_compares<T>(&self, txn_db: &T, index: &dyn IndexOperate, compares: &[Compare]) -> bool
+    where
+        T: XlineStorageOps,
+    {
+        compares.iter().all(|cmp| match self.kv_cache {
+            Some(ref cache) if cmp.range_end.is_empty() => {
+                let kv = self
+                    .get_latest_cached(cache, txn_db, index, &cmp.key)
+                    .unwrap_or_default();
+                Self::compare_kvs(cmp, kv.as_ref().map_or(&[][..], std::slice::from_ref))
+            }
+            _ => Self::check_compare(txn_db, index, cmp),
+        })
+    }
+
     /// Send get lease to lease store
     fn get_lease(&self, key: &[u8]) -> i64 {
         self.lease_collection.get_lease(key)
@@ -503,23 +717,65 @@ impl KvStore {
     }
 
     /// Compact kv storage
-    pub(crate) fn compact(&self, revisions: &[Vec<u8>]) -> Result<(), ExecuteError> {
-        let mut ops = Vec::new();
-        revisions
-            .iter()
-            .for_each(|rev| ops.push(WriteOp::DeleteKeyValue(rev.as_ref())));
-        self.inner.db.write_ops(ops)?;
-        Ok(())
+    ///
+    /// Returns the number of bytes reclaimed from the kv table
+    pub(crate) fn compact(&self, revisions: &[Vec<u8>]) -> Result<u64, ExecuteError> {
+        // the migrated revisions are removed from the cold tier as well, only
+        // the bytes of the hot ones are reclaimed from the kv table
+        let reclaimed = self.inner.db.remove_revisions(revisions)?;
+        metrics::get()
+            .compaction_reclaimed_bytes_total
+            .add(reclaimed, &[]);
+        Ok(reclaimed)
+    }
+
+    /// Compact the kv table between the encoded revisions `from` and `to` in the
+    /// engine, so that the space of the compacted revisions is given back
+    ///
+    /// Returns the number of bytes reclaimed by the engine
+    pub(crate) fn reclaim_compacted(&self, from: &[u8], to: &[u8]) -> Result<u64, ExecuteError> {
+        self.inner.db.compact_range(KV_TABLE, from, to)
     }
 
-    /// Compact kv storage
-    pub(crate) fn compact_finished(&self, revision: i64) -> Result<(), ExecuteError> {
+    /// Finish the compaction at `revision`, which reclaimed `reclaimed` bytes
+    pub(crate) fn compact_finished(
+        &self,
+        revision: i64,
+        reclaimed: u64,
+    ) -> Result<(), ExecuteError> {
         let ops = vec![WriteOp::PutFinishedCompactRevision(revision)];
         self.inner.db.write_ops(ops)?;
         self.update_compacted_revision(revision);
+        *self.last_compaction.lock() = Some((revision, reclaimed));
         Ok(())
     }
 
+    /// Get the bytes reclaimed by the compaction at `revision`, `None` if it is
+    /// not the last finished compaction
+    pub(crate) fn reclaimed_bytes(&self, revision: i64) -> Option<u64> {
+        self.last_compaction
+            .lock()
+            .and_then(|(rev, reclaimed)| (rev == revision).then_some(reclaimed))
+    }
+
+    /// Migrate the revisions superseded more than `threshold` revisions ago to
+    /// the cold tier, returns the number of the revisions migrated
+    ///
+    /// The revisions superseded since the last migration are selected, so each
+    /// run only looks at the new part of the history.
+    pub(crate) fn migrate_to_cold_tier(&self, threshold: i64) -> Result<usize, ExecuteError> {
+        let Some(cold_tier) = self.inner.db.cold_tier() else {
+            return Ok(0);
+        };
+        let before = self.revision().overflow_sub(threshold);
+        let from = cold_tier.migrated_revision()?;
+        if before <= from {
+            return Ok(0);
+        }
+        let revisions = self.inner.index.superseded(from, before);
+        self.inner.db.migrate_to_cold_tier(&revisions, before)
+    }
+
     /// Calculate hash of kv storage
     pub(crate) fn hash_kv(&self, mut rev: i64) -> Result<(u32, i64, i64), ExecuteError> {
         let (compact_rev, current_rev) = (self.compacted_revision(), self.revision());
@@ -532,13 +788,20 @@ impl KvStore {
         if rev <= 0 {
             rev = current_rev;
         }
-        let keep = self.inner.index.keep(rev);
+        // the revisions no greater than the compacted revision are hashed only if
+        // they survive the compaction, so that the hash does not depend on the
+        // progress of the compaction in background
+        let keep = self.inner.index.keep(compact_rev);
         let upper = Revision::new(rev.overflow_add(1), 0);
-        let lower = Revision::new(compact_rev, 0);
+        let lower = Revision::new(compact_rev.overflow_add(1), 0);
         let mut hasher = crc32fast::Hasher::new();
         hasher.update(KV_TABLE.as_bytes());
-        let kv_pairs = self.inner.db.get_all(KV_TABLE)?;
-        for (k, v) in kv_pairs {
+        // the key-values are read lazily from a view, instead of being loaded at once,
+        // the revisions in the cold tier are hashed as if they are in the kv table
+        let view = self.inner.db.view();
+        let cold_view = self.inner.db.cold_view();
+        for pair in DB::iter_kv_view(&view, cold_view.as_ref())? {
+            let (k, v) = pair?;
             let kr = Revision::decode(&k);
             if upper <= kr {
                 continue;
@@ -547,7 +810,8 @@ impl KvStore {
                 continue;
             }
             hasher.update(&k);
-            hasher.update(&v);
+            // hash the plain value so that nodes with different compression settings agree
+            hasher.update(&compression::decompress(&v)?);
         }
         let hash = hasher.finalize();
         Ok((hash, compact_rev, rev))
@@ -568,7 +832,7 @@ impl KvStore {
     fn execute_request(
         &self,
         wrapper: &RequestWrapper,
-        txn_db: &Transaction,
+        txn_db: &PipelinedTransaction<'_>,
         index: &mut dyn IndexOperate,
     ) -> Result<ResponseWrapper, ExecuteError> {
         debug!("Execute {:?}", wrapper);
@@ -613,26 +877,50 @@ impl KvStore {
     {
         req.check_revision(self.compacted_revision(), self.revision())?;
 
-        let storage_fetch_limit = if (req.sort_order() != SortOrder::None)
-            || (req.max_mod_revision != 0)
-            || (req.min_mod_revision != 0)
-            || (req.max_create_revision != 0)
-            || (req.min_create_revision != 0)
-            || (req.limit == 0)
-        {
-            0 // get all from storage then sort and filter
+        let is_sorted = req.sort_order() != SortOrder::None;
+        let storage_fetch_limit = if is_sorted || (req.limit == 0) {
+            0 // get all from storage then sort
         } else {
             req.limit.overflow_add(1) // get one extra for "more" flag
         };
-        let (mut kvs, total) = KvStoreInner::get_range_with_opts(
-            tnx_db,
-            index,
-            &req.key,
-            &req.range_end,
-            req.revision,
-            storage_fetch_limit.numeric_cast(),
-            req.count_only,
-        )?;
+        // values are still needed for sorting by value
+        let clear_values = req.keys_only && !(is_sorted && req.sort_target() == SortTarget::Value);
+        let process_chunk = |chunk: &mut Vec<KeyValue>| {
+            Self::filter_kvs(
+                chunk,
+                req.max_mod_revision,
+                req.min_mod_revision,
+                req.max_create_revision,
+                req.min_create_revision,
+            );
+            if clear_values {
+                chunk.iter_mut().for_each(|kv| kv.value.clear());
+            }
+        };
+        let is_latest_point_get = req.range_end.is_empty() && req.revision <= 0 && !req.count_only;
+        let (mut kvs, total) = match self.kv_cache {
+            Some(ref cache) if is_latest_point_get => {
+                let mut kvs: Vec<_> = self
+                    .get_latest_cached(cache, tnx_db, index, &req.key)?
+                    .into_iter()
+                    .collect();
+                let total = kvs.len();
+                process_chunk(&mut kvs);
+                (kvs, total)
+            }
+            _ => KvStoreInner::get_range_with_opts(
+                tnx_db,
+                Some(self.inner.db.as_ref()),
+                index,
+                &req.key,
+                &req.range_end,
+                req.revision,
+                storage_fetch_limit.numeric_cast(),
+                req.count_only,
+                Some(self.max_range_bytes),
+                process_chunk,
+            )?,
+        };
         let mut response = RangeResponse {
             header: Some(self.header_gen.gen_header()),
             count: total.numeric_cast(),
@@ -642,20 +930,13 @@ impl KvStore {
             return Ok(response);
         }
 
-        Self::filter_kvs(
-            &mut kvs,
-            req.max_mod_revision,
-            req.min_mod_revision,
-            req.max_create_revision,
-            req.min_create_revision,
-        );
         Self::sort_kvs(&mut kvs, req.sort_order(), req.sort_target());
 
         if (req.limit > 0) && (kvs.len() > req.limit.numeric_cast()) {
             response.more = true;
             kvs.truncate(req.limit.numeric_cast());
         }
-        if req.keys_only {
+        if req.keys_only && !clear_values {
             kvs.iter_mut().for_each(|kv| kv.value.clear());
         }
         response.kvs = kvs;
@@ -663,6 +944,54 @@ impl KvStore {
         Ok(response)
     }
 
+    /// Get the latest `KeyValue` of a key through the kv cache
+    ///
+    /// A cached `KeyValue` is only used if it is still the latest revision of the
+    /// key in the given index, and only committed `KeyValue`s are cached.
+    fn get_latest_cached<T>(
+        &self,
+        cache: &Mutex<LruCache<Vec<u8>, KeyValue>>,
+        txn_db: &T,
+        index: &dyn IndexOperate,
+        key: &[u8],
+    ) -> Result<Option<KeyValue>, ExecuteError>
+    where
+        T: XlineStorageOps,
+    {
+        let Some(rev) = index.get(key, &[], 0).pop() else {
+            return Ok(None);
+        };
+        if let Some(kv) = cache
+            .lock()
+            .get(key)
+            .filter(|kv| kv.mod_revision == rev.revision())
+        {
+            return Ok(Some(kv.clone()));
+        }
+        let kv = KvStoreInner::get_values(txn_db, &[rev])?.pop();
+        if let Some(ref kv) = kv {
+            let is_committed = self
+                .inner
+                .index
+                .current_rev(key)
+                .is_some_and(|key_rev| key_rev.as_revision() == rev);
+            if is_committed {
+                let _prev = cache.lock().put(key.to_vec(), kv.clone());
+            }
+        }
+        Ok(kv)
+    }
+
+    /// Evict the keys mutated by the events from the kv cache
+    fn evict_cached(&self, events: &[Event]) {
+        if let Some(ref cache) = self.kv_cache {
+            let mut cache = cache.lock();
+            for kv in events.iter().filter_map(|event| event.kv.as_ref()) {
+                let _prev = cache.pop(&kv.key);
+            }
+        }
+    }
+
     /// Generates `PutResponse`
     fn generate_put_resp<T>(
         &self,
@@ -677,7 +1006,14 @@ impl KvStore {
             header: Some(self.header_gen.gen_header()),
             ..Default::default()
         };
-        if req.lease != 0 && self.lease_collection.look_up(req.lease).is_none() {
+        // only the leader tracks the expiry of leases, an expired lease is
+        // about to be revoked so it cannot accept new keys
+        if req.lease != 0
+            && self
+                .lease_collection
+                .look_up(req.lease)
+                .map_or(true, |lease| lease.expired())
+        {
             return Err(ExecuteError::LeaseNotFound(req.lease));
         };
 
@@ -699,13 +1035,14 @@ impl KvStore {
     /// Execute `PutRequest`
     fn execute_put(
         &self,
-        txn_db: &Transaction,
+        txn_db: &PipelinedTransaction<'_>,
         index: &dyn IndexOperate,
         req: &PutRequest,
     ) -> Result<PutResponse, ExecuteError> {
         let prev_rev = (req.prev_kv || req.ignore_lease || req.ignore_value)
             .then(|| index.current_rev(&req.key))
-            .flatten();
+            .flatten()
+            .filter(|key_rev| !key_rev.is_deleted());
         let (response, _prev_kv) =
             self.generate_put_resp(req, txn_db, prev_rev.map(|key_rev| key_rev.as_revision()))?;
         Ok(response)
@@ -714,13 +1051,14 @@ impl KvStore {
     /// Execute `PutRequest` in Txn
     fn execute_txn_put(
         &self,
-        txn_db: &Transaction,
+        txn_db: &PipelinedTransaction<'_>,
         index: &dyn IndexOperate,
         req: &PutRequest,
         revision: i64,
         sub_revision: &mut i64,
     ) -> Result<PutResponse, ExecuteError> {
         let (new_rev, prev_rev) = index.register_revision(req.key.clone(), revision, *sub_revision);
+        let prev_rev = prev_rev.filter(|key_rev| !key_rev.is_deleted());
         let (response, prev_kv) =
             self.generate_put_resp(req, txn_db, prev_rev.map(|key_rev| key_rev.as_revision()))?;
         let mut kv = KeyValue {
@@ -764,14 +1102,20 @@ impl KvStore {
     where
         T: XlineStorageOps,
     {
-        let prev_kvs = KvStoreInner::get_range(txn_db, index, &req.key, &req.range_end, 0)?;
         let mut response = DeleteRangeResponse {
             header: Some(self.header_gen.gen_header()),
             ..DeleteRangeResponse::default()
         };
-        response.deleted = prev_kvs.len().numeric_cast();
+        // Only count the keys if the previous kvs are not required, so that
+        // deleting a large range does not load all of its values
         if req.prev_kv {
+            let prev_kvs = KvStoreInner::get_range(txn_db, index, &req.key, &req.range_end, 0)?;
+            response.deleted = prev_kvs.len().numeric_cast();
             response.prev_kvs = prev_kvs;
+        } else {
+            response.deleted = index
+                .count_range(&req.key, &req.range_end, 0)
+                .numeric_cast();
         }
         Ok(response)
     }
@@ -802,14 +1146,17 @@ impl KvStore {
         T: XlineStorageOps,
     {
         let response = self.generate_delete_range_resp(req, txn_db, index)?;
+        let mut ops = Vec::new();
         let _keys = Self::delete_keys(
-            txn_db,
             index,
             &req.key,
             &req.range_end,
             revision,
             sub_revision,
-        )?;
+            self.inner.db.explicit_tombstones(),
+            &mut ops,
+        );
+        txn_db.write_ops(ops)?;
 
         Ok(response)
     }
@@ -817,16 +1164,13 @@ impl KvStore {
     /// Execute `TxnRequest`
     fn execute_txn(
         &self,
-        txn_db: &Transaction,
+        txn_db: &PipelinedTransaction<'_>,
         index: &mut dyn IndexOperate,
         request: &TxnRequest,
         revision: i64,
         sub_revision: &mut i64,
     ) -> Result<TxnResponse, ExecuteError> {
-        let success = request
-            .compare
-            .iter()
-            .all(|compare| Self::check_compare(txn_db, index, compare));
+        let success = self.check_compares(txn_db, index, &request.compare);
         tracing::warn!("txn success in execute: {success}");
         let requests = if success {
             request.success.iter()
@@ -878,6 +1222,99 @@ impl KvStore {
     }
 }
 
+/// A read view of a transaction with the write operations buffered by the
+/// request being synced on top of it
+///
+/// The ops of a txn observe the writes of the preceding ops through it, while
+/// nothing is written to the transaction until the whole request is synced.
+struct BufferedView<'a, 'b, T> {
+    /// The transaction
+    txn_db: &'a T,
+    /// The buffered write operations
+    ops: &'a [WriteOp<'b>],
+}
+
+impl<'a, 'b, T> BufferedView<'a, 'b, T> {
+    /// Create a new `BufferedView`
+    fn new(txn_db: &'a T, ops: &'a [WriteOp<'b>]) -> Self {
+        Self { txn_db, ops }
+    }
+
+    /// Get the value of an encoded revision from the buffered ops
+    #[allow(clippy::wildcard_enum_match_arm)] // only the kv table puts are buffered by a request
+    fn buffered_value(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.ops.iter().rev().find_map(|op| match *op {
+            WriteOp::PutKeyValue(rev, ref kv) if rev.encode_to_vec() == key => {
+                Some(kv.encode_to_vec())
+            }
+            WriteOp::PutEncodedKeyValue(rev, ref value) if rev.encode_to_vec() == key => {
+                Some(value.clone())
+            }
+            _ => None,
+        })
+    }
+}
+
+impl<T> XlineStorageOps for BufferedView<'_, '_, T>
+where
+    T: XlineStorageOps,
+{
+    fn write_op(&self, _op: WriteOp) -> Result<(), ExecuteError> {
+        Err(ExecuteError::DbError(
+            "cannot write to a buffered view".to_owned(),
+        ))
+    }
+
+    fn write_ops(&self, _ops: Vec<WriteOp>) -> Result<(), ExecuteError> {
+        Err(ExecuteError::DbError(
+            "cannot write to a buffered view".to_owned(),
+        ))
+    }
+
+    fn get_value<K>(&self, table: &'static str, key: K) -> Result<Option<Vec<u8>>, ExecuteError>
+    where
+        K: AsRef<[u8]> + std::fmt::Debug,
+    {
+        if table == KV_TABLE {
+            if let Some(value) = self.buffered_value(key.as_ref()) {
+                return Ok(Some(value));
+            }
+        }
+        self.txn_db.get_value(table, key)
+    }
+
+    fn get_values<K>(
+        &self,
+        table: &'static str,
+        keys: &[K],
+    ) -> Result<Vec<Option<Vec<u8>>>, ExecuteError>
+    where
+        K: AsRef<[u8]> + std::fmt::Debug,
+    {
+        if table != KV_TABLE || self.ops.is_empty() {
+            return self.txn_db.get_values(table, keys);
+        }
+        let mut values: Vec<_> = keys
+            .iter()
+            .map(|key| self.buffered_value(key.as_ref()))
+            .collect();
+        let missing: Vec<&[u8]> = keys
+            .iter()
+            .zip(&values)
+            .filter(|&(_key, value)| value.is_none())
+            .map(|(key, _value)| key.as_ref())
+            .collect();
+        if missing.is_empty() {
+            return Ok(values);
+        }
+        let mut fetched = self.txn_db.get_values(table, &missing)?.into_iter();
+        for value in values.iter_mut().filter(|value| value.is_none()) {
+            *value = fetched.next().flatten();
+        }
+        Ok(values)
+    }
+}
+
 /// Sync requests
 impl KvStore {
     /// Sync kv requests
@@ -896,28 +1333,61 @@ impl KvStore {
         warn!("after sync: {wrapper:?}");
 
         let next_revision = revision_gen.get().overflow_add(1);
+        // Write operations of the request are buffered and flushed in one batch
+        // after the whole request is synced successfully
+        let mut ops = Vec::new();
 
         #[allow(clippy::wildcard_enum_match_arm)]
-        let (events, execute_response): (_, Option<ResponseWrapper>) = match *wrapper {
+        let synced = match *wrapper {
             RequestWrapper::RangeRequest(ref req) => {
                 self.sync_range(txn_db, index, req, to_execute)
             }
-            RequestWrapper::PutRequest(ref req) => {
-                self.sync_put(txn_db, index, req, next_revision, &mut 0, to_execute)
-            }
-            RequestWrapper::DeleteRangeRequest(ref req) => {
-                self.sync_delete_range(txn_db, index, req, next_revision, &mut 0, to_execute)
-            }
-            RequestWrapper::TxnRequest(ref req) => {
-                self.sync_txn(txn_db, index, req, next_revision, &mut 0, to_execute)
-            }
+            RequestWrapper::PutRequest(ref req) => self.sync_put(
+                txn_db,
+                index,
+                req,
+                next_revision,
+                &mut 0,
+                to_execute,
+                &mut ops,
+            ),
+            RequestWrapper::DeleteRangeRequest(ref req) => self.sync_delete_range(
+                txn_db,
+                index,
+                req,
+                next_revision,
+                &mut 0,
+                to_execute,
+                &mut ops,
+            ),
+            RequestWrapper::TxnRequest(ref req) => self.sync_txn(
+                txn_db,
+                index,
+                req,
+                next_revision,
+                &mut 0,
+                to_execute,
+                &mut ops,
+            ),
             RequestWrapper::CompactionRequest(ref req) => self.sync_compaction(req, to_execute),
             _ => unreachable!("Other request should not be sent to this store"),
-        }?;
+        }
+        .and_then(|synced| self.flush_ops(txn_db, &mut ops).map(|()| synced));
+        let (events, execute_response): (_, Option<ResponseWrapper>) = match synced {
+            Ok(synced) => synced,
+            Err(e) => {
+                // The buffered ops are dropped, the revisions registered in the
+                // index by the failed request must go with them
+                index.rollback(next_revision);
+                return Err(e);
+            }
+        };
 
         let sync_response = if events.is_empty() {
             SyncResponse::new(revision_gen.get())
         } else {
+            self.update_leases(&events);
+            self.evict_cached(&events);
             self.notify_updates(next_revision, events);
             SyncResponse::new(revision_gen.next())
         };
@@ -946,7 +1416,40 @@ impl KvStore {
         ))
     }
 
+    /// Update the leases attached to the keys mutated by the events, it's
+    /// called after the whole request is synced, so that a failed request
+    /// leaves the leases untouched
+    fn update_leases(&self, events: &[Event]) {
+        for event in events {
+            let Some(ref kv) = event.kv else {
+                continue;
+            };
+            let old_lease = self.get_lease(&kv.key);
+            if old_lease != 0 {
+                self.detach(old_lease, kv.key.as_slice())
+                    .unwrap_or_else(|e| warn!("Failed to detach lease from a key, error: {:?}", e));
+            }
+            if event.r#type() == EventType::Put && kv.lease != 0 {
+                self.attach(kv.lease, kv.key.as_slice())
+                    .unwrap_or_else(|e| warn!("unexpected error from lease Attach: {e}"));
+            }
+        }
+    }
+
+    /// Flush buffered write operations into the transaction
+    fn flush_ops<T>(&self, txn_db: &T, ops: &mut Vec<WriteOp<'_>>) -> Result<(), ExecuteError>
+    where
+        T: XlineStorageOps,
+    {
+        if ops.is_empty() {
+            return Ok(());
+        }
+        self.inner.db.account_puts(ops);
+        txn_db.write_ops(std::mem::take(ops))
+    }
+
     /// Sync `PutRequest`
+    #[allow(clippy::too_many_arguments)] // the buffered ops are threaded through nested txns
     fn sync_put<T>(
         &self,
         txn_db: &T,
@@ -955,22 +1458,41 @@ impl KvStore {
         revision: i64,
         sub_revision: &mut i64,
         to_execute: bool,
+        ops: &mut Vec<WriteOp<'_>>,
     ) -> Result<(Vec<Event>, Option<ResponseWrapper>), ExecuteError>
     where
         T: XlineStorageOps,
     {
-        let (new_rev, prev_rev_opt) =
-            index.register_revision(req.key.clone(), revision, *sub_revision);
+        // The lease may have been revoked after the put was executed, check it
+        // again before anything is mutated
+        if req.lease != 0 && self.lease_collection.look_up(req.lease).is_none() {
+            return Err(ExecuteError::LeaseNotFound(req.lease));
+        }
+        // Resolve the previous kv before registering a new revision, so that a
+        // failed `ignore_value`/`ignore_lease` put leaves the index untouched.
+        // It is also attached to the event if any watcher asks for `prev_kv`,
+        // otherwise it's only read when the put needs it.
+        let prev_rev = index
+            .current_rev(&req.key)
+            .filter(|key_rev| !key_rev.is_deleted())
+            .map(|key_rev| key_rev.as_revision());
+        let view = BufferedView::new(txn_db, ops);
+        let prev_kv = if req.ignore_lease || req.ignore_value || self.inner.has_prev_kv_watchers() {
+            KvStoreInner::get_values(&view, &prev_rev.into_iter().collect::<Vec<_>>())?.pop()
+        } else {
+            None
+        };
+        if prev_kv.is_none() && (req.ignore_lease || req.ignore_value) {
+            return Err(ExecuteError::KeyNotFound);
+        }
         let execute_resp = to_execute
             .then(|| {
-                self.generate_put_resp(
-                    req,
-                    txn_db,
-                    prev_rev_opt.map(|key_rev| key_rev.as_revision()),
-                )
-                .map(|(resp, _)| resp.into())
+                self.generate_put_resp(req, &view, prev_rev)
+                    .map(|(resp, _)| resp.into())
             })
             .transpose()?;
+        let (new_rev, _prev_rev) =
+            index.register_revision(req.key.clone(), revision, *sub_revision);
 
         let mut kv = KeyValue {
             key: req.key.clone(),
@@ -981,12 +1503,7 @@ impl KvStore {
             lease: req.lease,
         };
 
-        if req.ignore_lease || req.ignore_value {
-            let prev_rev = prev_rev_opt
-                .map(|key_rev| key_rev.as_revision())
-                .ok_or(ExecuteError::KeyNotFound)?;
-            let prev_kv = KvStoreInner::get_values(txn_db, &[prev_rev])?.pop();
-            let prev = prev_kv.as_ref().ok_or(ExecuteError::KeyNotFound)?;
+        if let Some(ref prev) = prev_kv {
             if req.ignore_lease {
                 kv.lease = prev.lease;
             }
@@ -995,30 +1512,24 @@ impl KvStore {
             }
         };
 
-        let old_lease = self.get_lease(&kv.key);
-        if old_lease != 0 {
-            self.detach(old_lease, kv.key.as_slice())
-                .unwrap_or_else(|e| warn!("Failed to detach lease from a key, error: {:?}", e));
-        }
-        if req.lease != 0 {
-            self.attach(req.lease, kv.key.as_slice())
-                .unwrap_or_else(|e| warn!("unexpected error from lease Attach: {e}"));
-        }
-
-        txn_db.write_op(WriteOp::PutKeyValue(new_rev.as_revision(), kv.clone()))?;
+        ops.push(WriteOp::PutEncodedKeyValue(
+            new_rev.as_revision(),
+            self.value_compression.encode(&kv),
+        ));
         *sub_revision = sub_revision.overflow_add(1);
 
         let events = vec![Event {
             #[allow(clippy::as_conversions)] // This cast is always valid
             r#type: EventType::Put as i32,
             kv: Some(kv),
-            prev_kv: None,
+            prev_kv,
         }];
 
         Ok((events, execute_resp))
     }
 
     /// Sync `DeleteRangeRequest`
+    #[allow(clippy::too_many_arguments)] // the buffered ops are threaded through nested txns
     fn sync_delete_range<T>(
         &self,
         txn_db: &T,
@@ -1027,30 +1538,69 @@ impl KvStore {
         revision: i64,
         sub_revision: &mut i64,
         to_execute: bool,
+        ops: &mut Vec<WriteOp<'_>>,
     ) -> Result<(Vec<Event>, Option<ResponseWrapper>), ExecuteError>
     where
         T: XlineStorageOps,
     {
-        let execute_resp = to_execute
-            .then(|| self.generate_delete_range_resp(req, txn_db, index))
-            .transpose()?
-            .map(Into::into);
+        // The deleted kvs are attached to the events if any watcher asks for
+        // `prev_kv`, so they are loaded once for both the events and the response
+        let capture_prev_kvs = self.inner.has_prev_kv_watchers();
+        let prev_kvs = ((to_execute && req.prev_kv) || capture_prev_kvs)
+            .then(|| {
+                KvStoreInner::get_range(
+                    &BufferedView::new(txn_db, ops),
+                    index,
+                    &req.key,
+                    &req.range_end,
+                    0,
+                )
+            })
+            .transpose()?;
 
         let keys = Self::delete_keys(
-            txn_db,
             index,
             &req.key,
             &req.range_end,
             revision,
             sub_revision,
-        )?;
+            self.inner.db.explicit_tombstones(),
+            ops,
+        );
+        if let Some(ref kvs) = prev_kvs {
+            if kvs.len() != keys.len() {
+                return Err(ExecuteError::DbError(format!(
+                    "index doesn't match with db, {} keys are deleted but {} kvs are found",
+                    keys.len(),
+                    kvs.len()
+                )));
+            }
+        }
+        let execute_resp = to_execute.then(|| {
+            DeleteRangeResponse {
+                header: Some(self.header_gen.gen_header()),
+                deleted: keys.len().numeric_cast(),
+                prev_kvs: if req.prev_kv {
+                    prev_kvs.clone().unwrap_or_default()
+                } else {
+                    vec![]
+                },
+            }
+            .into()
+        });
 
-        Self::detach_leases(&keys, &self.lease_collection);
+        let mut events = Self::new_deletion_events(revision, keys);
+        if capture_prev_kvs {
+            for (event, prev_kv) in events.iter_mut().zip(prev_kvs.into_iter().flatten()) {
+                event.prev_kv = Some(prev_kv);
+            }
+        }
 
-        Ok((Self::new_deletion_events(revision, keys), execute_resp))
+        Ok((events, execute_resp))
     }
 
     /// Sync `TxnRequest`
+    #[allow(clippy::too_many_arguments)] // the buffered ops are threaded through nested txns
     fn sync_txn<T>(
         &self,
         txn_db: &T,
@@ -1059,35 +1609,46 @@ impl KvStore {
         revision: i64,
         sub_revision: &mut i64,
         to_execute: bool,
+        ops: &mut Vec<WriteOp<'_>>,
     ) -> Result<(Vec<Event>, Option<ResponseWrapper>), ExecuteError>
     where
         T: XlineStorageOps,
     {
         request.check_revision(self.compacted_revision(), self.revision())?;
-        let success = request
-            .compare
-            .iter()
-            .all(|compare| Self::check_compare(txn_db, index, compare));
+        let success = self.check_compares(&BufferedView::new(txn_db, ops), index, &request.compare);
         tracing::warn!("txn success: {success}");
         let requests = if success {
-            request.success.iter()
+            &request.success
         } else {
-            request.failure.iter()
+            &request.failure
         };
+        // The leases may have been revoked after the txn was executed, check the
+        // puts of the branch before any op mutates the index or the leases
+        self.check_put_leases(&BufferedView::new(txn_db, ops), index, requests)?;
 
         let (events, resps): (Vec<_>, Vec<_>) = requests
+            .iter()
             .filter_map(|op| op.request.as_ref())
             .map(|req| match *req {
-                Request::RequestRange(ref r) => self.sync_range(txn_db, index, r, to_execute),
+                // Reads inside a txn observe the buffered writes of the preceding ops
+                Request::RequestRange(ref r) => {
+                    self.sync_range(&BufferedView::new(txn_db, ops), index, r, to_execute)
+                }
                 Request::RequestTxn(ref r) => {
-                    self.sync_txn(txn_db, index, r, revision, sub_revision, to_execute)
+                    self.sync_txn(txn_db, index, r, revision, sub_revision, to_execute, ops)
                 }
                 Request::RequestPut(ref r) => {
-                    self.sync_put(txn_db, index, r, revision, sub_revision, to_execute)
-                }
-                Request::RequestDeleteRange(ref r) => {
-                    self.sync_delete_range(txn_db, index, r, revision, sub_revision, to_execute)
+                    self.sync_put(txn_db, index, r, revision, sub_revision, to_execute, ops)
                 }
+                Request::RequestDeleteRange(ref r) => self.sync_delete_range(
+                    txn_db,
+                    index,
+                    r,
+                    revision,
+                    sub_revision,
+                    to_execute,
+                    ops,
+                ),
             })
             .collect::<Result<Vec<_>, _>>()?
             .into_iter()
@@ -1109,6 +1670,47 @@ impl KvStore {
         Ok((events.into_iter().flatten().collect(), resp))
     }
 
+    /// Check that the leases of the put ops exist, including the puts of the
+    /// nested txns
+    ///
+    /// Like etcd, the branch of a nested txn is chosen by its compares against
+    /// the store before the ops of the outer txn. The branch is checked again
+    /// when it is synced, so a nested branch chosen otherwise is still checked.
+    fn check_put_leases<T>(
+        &self,
+        txn_db: &T,
+        index: &dyn IndexOperate,
+        ops: &[RequestOp],
+    ) -> Result<(), ExecuteError>
+    where
+        T: XlineStorageOps,
+    {
+        for op in ops {
+            match op.request {
+                Some(Request::RequestPut(ref req))
+                    if req.lease != 0 && !self.lease_collection.contains_lease(req.lease) =>
+                {
+                    return Err(ExecuteError::LeaseNotFound(req.lease));
+                }
+                Some(Request::RequestTxn(ref req)) => {
+                    let branch = if self.check_compares(txn_db, index, &req.compare) {
+                        &req.success
+                    } else {
+                        &req.failure
+                    };
+                    self.check_put_leases(txn_db, index, branch)?;
+                }
+                Some(
+                    Request::RequestRange(_)
+                    | Request::RequestPut(_)
+                    | Request::RequestDeleteRange(_),
+                )
+                | None => {}
+            }
+        }
+        Ok(())
+    }
+
     /// Sync `CompactionRequest` and return if kvstore is changed
     fn sync_compaction(
         &self,
@@ -1127,14 +1729,15 @@ impl KvStore {
             .map(|key_rev| key_rev.as_revision().encode_to_vec())
             .collect::<Vec<Vec<_>>>();
         // Given that the Xline uses a lim-tree database with smaller write amplification as the storage backend ,  does using progressive compaction really good at improving performance?
+        let mut reclaimed = 0_u64;
         for revision_chunk in target_revisions.chunks(1000) {
-            if let Err(e) = self.compact(revision_chunk) {
-                panic!("failed to compact revision chunk {revision_chunk:?} due to {e}");
-            }
-        }
-        if let Err(e) = self.compact_finished(revision) {
-            panic!("failed to set finished compact revision {revision:?} due to {e}");
+            reclaimed = reclaimed.overflow_add(self.compact(revision_chunk)?);
         }
+        debug!(
+            "compaction at revision {revision} removed {} revisions, reclaimed {reclaimed} bytes",
+            target_revisions.len()
+        );
+        self.compact_finished(revision, reclaimed)?;
 
         self.inner.db.write_ops(ops)?;
 
@@ -1168,65 +1771,76 @@ impl KvStore {
             .collect()
     }
 
-    /// Mark deletion for keys
-    fn mark_deletions<'a>(
-        revisions: &[(Revision, Revision)],
-        keys: &[Vec<u8>],
-    ) -> (Vec<WriteOp<'a>>, Vec<(Vec<u8>, KeyRevision)>) {
-        assert_eq!(keys.len(), revisions.len(), "Index doesn't match with DB");
-        keys.iter()
-            .zip(revisions.iter())
-            .map(|(key, &(_, new_rev))| {
-                let del_kv = KeyValue {
-                    key: key.clone(),
-                    mod_revision: new_rev.revision(),
-                    ..KeyValue::default()
-                };
-
-                let key_revision = (
-                    del_kv.key.clone(),
-                    KeyRevision::new(
-                        del_kv.create_revision,
-                        del_kv.version,
-                        new_rev.revision(),
-                        new_rev.sub_revision(),
-                    ),
-                );
-                (WriteOp::PutKeyValue(new_rev, del_kv), key_revision)
-            })
-            .unzip()
+    /// Mark deletion for a key, return the write operation of its tombstone
+    ///
+    /// A tombstone keeps the create revision and the version of the last live
+    /// revision of the key in the kv table until it's compacted. They are not
+    /// returned to the clients, the delete events keep the form of etcd. It's
+    /// written as the bare deletion of etcd unless `explicit_tombstones` is
+    /// set by the committed cluster version.
+    fn mark_deletion<'a>(
+        key: &[u8],
+        last_rev: KeyRevision,
+        new_rev: Revision,
+        explicit_tombstones: bool,
+    ) -> WriteOp<'a> {
+        let tombstone = Tombstone {
+            key: key.to_vec(),
+            revision: new_rev.revision(),
+            create_revision: last_rev.create_revision,
+            version: last_rev.version,
+        };
+        let value = if explicit_tombstones {
+            tombstone.encode()
+        } else {
+            tombstone.to_deletion_kv().encode_to_vec()
+        };
+        WriteOp::PutEncodedKeyValue(new_rev, value)
     }
 
-    /// Delete keys from index and detach them in lease collection, return all
-    /// the write operations and events
-    pub(crate) fn delete_keys<T>(
-        txn_db: &T,
+    /// Delete keys from index and buffer the deletion write operations into
+    /// `ops`, return all the deleted keys
+    pub(crate) fn delete_keys<'a>(
         index: &dyn IndexOperate,
         key: &[u8],
         range_end: &[u8],
         revision: i64,
         sub_revision: &mut i64,
-    ) -> Result<Vec<Vec<u8>>, ExecuteError>
-    where
-        T: XlineStorageOps,
-    {
-        let (revisions, keys) = index.delete(key, range_end, revision, *sub_revision);
-        let (del_ops, key_revisions) = Self::mark_deletions(&revisions, &keys);
-
-        index.insert(key_revisions);
+        explicit_tombstones: bool,
+        ops: &mut Vec<WriteOp<'a>>,
+    ) -> Vec<Vec<u8>> {
+        // The tombstones are registered in the index by `delete_each` while the
+        // range is walked, with dense sub revisions starting from `sub_revision`
+        // in key order
+        let mut keys = Vec::new();
+        index.delete_each(
+            key,
+            range_end,
+            revision,
+            *sub_revision,
+            &mut |key, last_rev, new_rev| {
+                ops.push(Self::mark_deletion(
+                    key,
+                    last_rev,
+                    new_rev,
+                    explicit_tombstones,
+                ));
+                keys.push(key.to_vec());
+            },
+        );
 
-        *sub_revision = sub_revision.overflow_add(del_ops.len().numeric_cast());
-        for op in del_ops {
-            txn_db.write_op(op)?;
-        }
+        *sub_revision = sub_revision.overflow_add(keys.len().numeric_cast());
 
-        Ok(keys)
+        keys
     }
 
     /// Detaches the leases
     pub(crate) fn detach_leases(keys: &[Vec<u8>], lease_collection: &LeaseCollection) {
         for k in keys {
             let lease_id = lease_collection.get_lease(k);
+            if lease_id == 0 {
+                continue;
+            }
             lease_collection
                 .detach(lease_id, k)
                 .unwrap_or_else(|e| warn!("Failed to detach lease from a key, error: {:?}", e));
@@ -1250,17 +1864,18 @@ impl KvStore {
 mod test {
     use std::time::Duration;
 
+    use tempfile::TempDir;
     use test_macros::abort_on_panic;
     use tokio::{runtime::Handle, task::block_in_place};
     use utils::{
-        config::EngineConfig,
+        config::{DurabilityConfig, EngineConfig},
         task_manager::{tasks::TaskName, TaskManager},
     };
 
     use super::*;
     use crate::{
         revision_number::RevisionNumberGenerator,
-        rpc::{Request as UniRequest, RequestOp},
+        rpc::{Request as UniRequest, Response},
         storage::{
             compact::{compact_bg_task, COMPACT_CHANNEL_SIZE},
             db::DB,
@@ -1324,6 +1939,10 @@ mod test {
     }
 
     fn init_empty_store(db: Arc<DB>) -> StoreWrapper {
+        init_empty_store_with_cache(db, 0)
+    }
+
+    fn init_empty_store_with_cache(db: Arc<DB>, kv_cache_capacity: usize) -> StoreWrapper {
         let task_manager = Arc::new(TaskManager::new());
         let (compact_tx, compact_rx) = flume::bounded(COMPACT_CHANNEL_SIZE);
         let (kv_update_tx, kv_update_rx) = flume::bounded(CHANNEL_SIZE);
@@ -1333,13 +1952,16 @@ mod test {
         let kv_store_inner = Arc::new(KvStoreInner::new(Arc::clone(&index), db));
         let storage = Arc::new(KvStore::new(
             Arc::clone(&kv_store_inner),
-            header_gen,
+            Arc::clone(&header_gen),
             kv_update_tx,
             compact_tx,
             lease_collection,
+            ValueCompression::default(),
+            kv_cache_capacity,
         ));
         let _watcher = KvWatcher::new_arc(
             kv_store_inner,
+            header_gen.general_revision_arc(),
             kv_update_rx,
             Duration::from_millis(10),
             &task_manager,
@@ -1448,6 +2070,201 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    #[abort_on_panic]
+    async fn test_kv_cache_is_evicted_on_mutation() -> Result<(), ExecuteError> {
+        let db = DB::open(&EngineConfig::Memory)?;
+        let store = init_empty_store_with_cache(db, 16);
+        let get = |store: &StoreWrapper| -> Result<Vec<KeyValue>, ExecuteError> {
+            let txn_db = store.inner.db.transaction();
+            let index = store.inner.index.state();
+            let request = RangeRequest {
+                key: "a".into(),
+                ..Default::default()
+            };
+            Ok(store.execute_range(&txn_db, &index, &request)?.kvs)
+        };
+        let is_cached = |store: &StoreWrapper| {
+            store
+                .kv_cache
+                .as_ref()
+                .unwrap()
+                .lock()
+                .contains(b"a".as_slice())
+        };
+
+        for value in ["1", "2"] {
+            let req = RequestWrapper::from(PutRequest {
+                key: "a".into(),
+                value: value.into(),
+                ..Default::default()
+            });
+            exe_as_and_flush(&store, &req)?;
+            assert!(!is_cached(&store));
+            assert_eq!(get(&store)?[0].value, value.as_bytes());
+            assert!(is_cached(&store));
+            assert_eq!(get(&store)?[0].value, value.as_bytes());
+        }
+
+        let req = RequestWrapper::from(DeleteRangeRequest {
+            key: "a".into(),
+            ..Default::default()
+        });
+        exe_as_and_flush(&store, &req)?;
+        assert!(!is_cached(&store));
+        assert!(get(&store)?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[abort_on_panic]
+    async fn test_txn_sub_revisions_follow_request_order() -> Result<(), ExecuteError> {
+        let db = DB::open(&EngineConfig::Memory)?;
+        let (store, _rev) = init_store(db)?;
+        let del_c = RequestWrapper::from(DeleteRangeRequest {
+            key: "c".into(),
+            ..Default::default()
+        });
+        exe_as_and_flush(&store, &del_c)?;
+        let txn = RequestWrapper::from(TxnRequest {
+            compare: vec![],
+            success: vec![
+                RequestOp {
+                    request: Some(UniRequest::RequestDeleteRange(DeleteRangeRequest {
+                        key: "a".into(),
+                        range_end: "e".into(),
+                        ..Default::default()
+                    })),
+                },
+                RequestOp {
+                    request: Some(UniRequest::RequestPut(PutRequest {
+                        key: "f".into(),
+                        value: "f".into(),
+                        ..Default::default()
+                    })),
+                },
+            ],
+            failure: vec![],
+        });
+        exe_as_and_flush(&store, &txn)?;
+
+        let revision = store.revision();
+        for (key, sub_revision) in [("a", 0), ("b", 1), ("d", 2), ("f", 3)] {
+            let key_rev = store.inner.index.current_rev(key.as_bytes()).unwrap();
+            assert_eq!(key_rev.as_revision(), Revision::new(revision, sub_revision));
+            assert_eq!(
+                store
+                    .inner
+                    .index
+                    .get_from_rev(key.as_bytes(), b"", revision),
+                vec![Revision::new(revision, sub_revision)],
+                "{key} should have exactly one revision in the txn"
+            );
+        }
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[abort_on_panic]
+    async fn test_delete_range_counts_without_prev_kvs() -> Result<(), ExecuteError> {
+        let db = DB::open(&EngineConfig::Memory)?;
+        let (store, _rev) = init_store(db)?;
+        let txn_db = store.inner.db.transaction();
+        let index = store.inner.index.state();
+        let mut request = DeleteRangeRequest {
+            key: "a".into(),
+            range_end: "z".into(),
+            ..Default::default()
+        };
+
+        let response = store.execute_delete_range(&txn_db, &index, &request)?;
+        assert_eq!(response.deleted, 5);
+        assert!(response.prev_kvs.is_empty());
+
+        request.prev_kv = true;
+        let response = store.execute_delete_range(&txn_db, &index, &request)?;
+        assert_eq!(response.deleted, 5);
+        assert_eq!(
+            response
+                .prev_kvs
+                .into_iter()
+                .map(|kv| kv.key)
+                .collect::<Vec<_>>(),
+            ["a", "b", "c", "d", "e"].map(|k| k.as_bytes().to_vec())
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[abort_on_panic]
+    async fn test_range_filter_with_limit() -> Result<(), ExecuteError> {
+        let db = DB::open(&EngineConfig::Memory)?;
+        let (store, _rev) = init_store(db)?;
+
+        let request = RangeRequest {
+            key: vec![0],
+            range_end: vec![0],
+            min_mod_revision: 4,
+            limit: 2,
+            keys_only: true,
+            ..Default::default()
+        };
+        let txn_db = store.inner.db.transaction();
+        let index = store.inner.index.state();
+        let response = store.execute_range(&txn_db, &index, &request)?;
+        assert_eq!(response.count, 6);
+        assert!(response.more);
+        assert_eq!(
+            response
+                .kvs
+                .iter()
+                .map(|kv| (kv.key.as_slice(), kv.value.as_slice()))
+                .collect::<Vec<_>>(),
+            vec![
+                (b"c".as_slice(), b"".as_slice()),
+                (b"d".as_slice(), b"".as_slice())
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[abort_on_panic]
+    async fn test_range_exceeds_max_bytes() -> Result<(), ExecuteError> {
+        let db = DB::open(&EngineConfig::Memory)?;
+        let (store, _rev) = init_store(db)?;
+        let txn_db = store.inner.db.transaction();
+        let index = store.inner.index.state();
+        let range = |limit: usize, max_bytes: usize| {
+            KvStoreInner::get_range_with_opts(
+                &txn_db,
+                None,
+                &index,
+                &[0],
+                &[0],
+                0,
+                limit,
+                false,
+                Some(max_bytes),
+                |_| {},
+            )
+        };
+        let (kvs, total) = range(0, usize::MAX)?;
+        assert_eq!(total, 6);
+        let size = kvs.iter().map(KeyValue::encoded_len).sum::<usize>();
+
+        assert!(range(0, size).is_ok());
+        assert!(
+            matches!(range(0, size - 1), Err(ExecuteError::RangeTooLarge(_))),
+            "the range should fail once it takes more than max bytes"
+        );
+        let (kvs, total) = range(2, size - 1)?;
+        assert_eq!(kvs.len(), 2, "a limited range should be kept in max bytes");
+        assert_eq!(total, 6);
+        Ok(())
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     #[abort_on_panic]
     async fn test_range_sort() -> Result<(), ExecuteError> {
@@ -1511,6 +2328,256 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    #[abort_on_panic]
+    async fn test_range_at_revision() -> Result<(), ExecuteError> {
+        let db = DB::open(&EngineConfig::Memory)?;
+        // revisions of "z": 7(z1) 8(z2) 9(z3)
+        let (store, _rev) = init_store(db)?;
+        let range_at = |revision: i64| RangeRequest {
+            key: "z".into(),
+            revision,
+            ..Default::default()
+        };
+
+        let txn_db = store.inner.db.transaction();
+        let index = store.inner.index.state();
+        for (revision, want) in [(6, None), (7, Some("z1")), (8, Some("z2")), (0, Some("z3"))] {
+            let response = store.execute_range(&txn_db, &index, &range_at(revision))?;
+            let got = response.kvs.first().map(|kv| kv.value.as_slice());
+            assert_eq!(got, want.map(str::as_bytes), "range at revision {revision}");
+        }
+        assert!(matches!(
+            store.execute_range(&txn_db, &index, &range_at(10)),
+            Err(ExecuteError::RevisionTooLarge(10, 9))
+        ));
+
+        let target_revisions = index_compact(&store, 8);
+        store.compact(target_revisions.as_ref())?;
+        store.update_compacted_revision(8);
+        assert!(matches!(
+            store.execute_range(&txn_db, &index, &range_at(7)),
+            Err(ExecuteError::RevisionCompacted(7, 8))
+        ));
+        let response = store.execute_range(&txn_db, &index, &range_at(8))?;
+        assert_eq!(response.kvs[0].value, b"z2");
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[abort_on_panic]
+    async fn test_put_requires_existing_lease() -> Result<(), ExecuteError> {
+        let db = DB::open(&EngineConfig::Memory)?;
+        let store = init_empty_store(db);
+        let put_with_lease = |lease| {
+            RequestWrapper::from(PutRequest {
+                key: "a".into(),
+                value: "1".into(),
+                lease,
+                ..Default::default()
+            })
+        };
+        let txn_put = |lease| TxnRequest {
+            compare: vec![],
+            success: vec![RequestOp {
+                request: Some(UniRequest::RequestPut(PutRequest {
+                    key: "a".into(),
+                    value: "1".into(),
+                    lease,
+                    ..Default::default()
+                })),
+            }],
+            failure: vec![],
+        };
+        let txn_put_with_lease = |lease| RequestWrapper::from(txn_put(lease));
+
+        // the lease is checked when executing a put
+        assert!(matches!(
+            store.execute(&put_with_lease(1), None),
+            Err(ExecuteError::LeaseNotFound(1))
+        ));
+        let _expired = store.lease_collection.grant(2, 0, true);
+        assert!(matches!(
+            store.execute(&put_with_lease(2), None),
+            Err(ExecuteError::LeaseNotFound(2))
+        ));
+
+        // and checked again in after sync, nothing is written without the lease
+        let rev = store.revision();
+        assert!(matches!(
+            exe_as_and_flush(&store, &put_with_lease(1)),
+            Err(ExecuteError::LeaseNotFound(1))
+        ));
+        assert!(matches!(
+            exe_as_and_flush(&store, &txn_put_with_lease(1)),
+            Err(ExecuteError::LeaseNotFound(1))
+        ));
+        let nested_txn = RequestWrapper::from(TxnRequest {
+            compare: vec![],
+            success: vec![RequestOp {
+                request: Some(UniRequest::RequestTxn(txn_put(1))),
+            }],
+            failure: vec![],
+        });
+        assert!(matches!(
+            exe_as_and_flush(&store, &nested_txn),
+            Err(ExecuteError::LeaseNotFound(1))
+        ));
+        assert_eq!(store.revision(), rev);
+        assert!(store.inner.index.current_rev(b"a").is_none());
+
+        let _lease = store.lease_collection.grant(3, 10, false);
+        exe_as_and_flush(&store, &put_with_lease(3))?;
+        assert_eq!(store.lease_collection.get_lease(b"a"), 3);
+        let _revoked = store.lease_collection.revoke(3);
+        assert!(matches!(
+            exe_as_and_flush(&store, &put_with_lease(3)),
+            Err(ExecuteError::LeaseNotFound(3))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[abort_on_panic]
+    async fn test_lease_attachment_follows_every_mutation() -> Result<(), ExecuteError> {
+        let db = DB::open(&EngineConfig::Memory)?;
+        let store = init_empty_store(db);
+        let _lease1 = store.lease_collection.grant(1, 10, false);
+        let _lease2 = store.lease_collection.grant(2, 10, false);
+        let put = |key: &str, lease| PutRequest {
+            key: key.into(),
+            value: "v".into(),
+            lease,
+            ..Default::default()
+        };
+        let txn = |ops: Vec<UniRequest>| {
+            RequestWrapper::from(TxnRequest {
+                compare: vec![],
+                success: ops
+                    .into_iter()
+                    .map(|request| RequestOp {
+                        request: Some(request),
+                    })
+                    .collect(),
+                failure: vec![],
+            })
+        };
+        let attached = |lease| {
+            store
+                .lease_collection
+                .look_up(lease)
+                .map(|l| l.keys())
+                .unwrap_or_default()
+        };
+
+        exe_as_and_flush(&store, &put("a", 1).into())?;
+        exe_as_and_flush(&store, &put("b", 1).into())?;
+        assert_eq!(attached(1), vec![b"a".to_vec(), b"b".to_vec()]);
+
+        // overwritten with another lease or without a lease
+        exe_as_and_flush(&store, &put("a", 2).into())?;
+        exe_as_and_flush(&store, &put("b", 0).into())?;
+        assert!(attached(1).is_empty());
+        assert_eq!(attached(2), vec![b"a".to_vec()]);
+
+        // deleted in a txn branch
+        exe_as_and_flush(
+            &store,
+            &txn(vec![
+                UniRequest::RequestPut(put("c", 1)),
+                UniRequest::RequestDeleteRange(DeleteRangeRequest {
+                    key: "a".into(),
+                    ..Default::default()
+                }),
+            ]),
+        )?;
+        assert_eq!(attached(1), vec![b"c".to_vec()]);
+        assert!(attached(2).is_empty());
+
+        // a txn putting with a revoked lease changes nothing
+        let _revoked = store.lease_collection.revoke(2);
+        assert!(matches!(
+            exe_as_and_flush(
+                &store,
+                &txn(vec![
+                    UniRequest::RequestPut(put("d", 1)),
+                    UniRequest::RequestPut(put("e", 2)),
+                ]),
+            ),
+            Err(ExecuteError::LeaseNotFound(2))
+        ));
+        assert_eq!(attached(1), vec![b"c".to_vec()]);
+        assert!(store.inner.index.current_rev(b"d").is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[abort_on_panic]
+    async fn test_put_ignore_value_and_ignore_lease() -> Result<(), ExecuteError> {
+        let db = DB::open(&EngineConfig::Memory)?;
+        let store = init_empty_store(db);
+        let _lease = store.lease_collection.grant(1, 10, false);
+        let ignore_value_req = RequestWrapper::from(PutRequest {
+            key: "a".into(),
+            ignore_value: true,
+            ..Default::default()
+        });
+        let ignore_lease_req = RequestWrapper::from(PutRequest {
+            key: "a".into(),
+            value: "2".into(),
+            ignore_lease: true,
+            ..Default::default()
+        });
+
+        assert!(matches!(
+            exe_as_and_flush(&store, &ignore_value_req),
+            Err(ExecuteError::KeyNotFound)
+        ));
+        assert!(matches!(
+            exe_as_and_flush(&store, &ignore_lease_req),
+            Err(ExecuteError::KeyNotFound)
+        ));
+
+        let put_req = RequestWrapper::from(PutRequest {
+            key: "a".into(),
+            value: "1".into(),
+            lease: 1,
+            ..Default::default()
+        });
+        exe_as_and_flush(&store, &put_req)?;
+        exe_as_and_flush(&store, &ignore_lease_req)?;
+        assert_eq!(store.lease_collection.get_lease(b"a"), 1);
+        exe_as_and_flush(&store, &ignore_value_req)?;
+        assert_eq!(store.lease_collection.get_lease(b"a"), 0);
+
+        let range_req = RangeRequest {
+            key: "a".into(),
+            ..Default::default()
+        };
+        let txn_db = store.inner.db.transaction();
+        let index = store.inner.index.state();
+        let response = store.execute_range(&txn_db, &index, &range_req)?;
+        assert_eq!(response.kvs.len(), 1);
+        assert_eq!(response.kvs[0].value, b"2");
+        assert_eq!(response.kvs[0].lease, 0);
+        assert_eq!(response.kvs[0].version, 3);
+
+        let del_req = RequestWrapper::from(DeleteRangeRequest {
+            key: "a".into(),
+            ..Default::default()
+        });
+        exe_as_and_flush(&store, &del_req)?;
+        assert!(matches!(
+            exe_as_and_flush(&store, &ignore_value_req),
+            Err(ExecuteError::KeyNotFound)
+        ));
+
+        Ok(())
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     #[abort_on_panic]
     async fn test_recover() -> Result<(), ExecuteError> {
@@ -1548,6 +2615,135 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    #[abort_on_panic]
+    async fn test_recover_after_crash_during_after_sync() -> Result<(), ExecuteError> {
+        let db = DB::open(&EngineConfig::Memory)?;
+        let store = init_empty_store(Arc::clone(&db));
+        let put = |key: &str| {
+            RequestWrapper::from(PutRequest {
+                key: key.into(),
+                value: b"value".to_vec(),
+                ..Default::default()
+            })
+        };
+
+        // crash after the db write, before the index is updated
+        {
+            let txn_db = store.db().transaction();
+            let index = store.index();
+            let index_state = index.state();
+            let rev_gen_state = store.revision.state();
+            let _res = store.after_sync(&put("a"), &txn_db, &index_state, &rev_gen_state, false)?;
+            txn_db.commit().unwrap();
+        }
+        // crash before the db write
+        {
+            let txn_db = store.db().transaction();
+            let index = store.index();
+            let index_state = index.state();
+            let rev_gen_state = store.revision.state();
+            let _res = store.after_sync(&put("b"), &txn_db, &index_state, &rev_gen_state, false)?;
+        }
+        assert!(store.index().get(b"a", b"", 0).is_empty());
+
+        let new_store = init_empty_store(db);
+        new_store.recover().await?;
+        let range = |key: &str| -> Result<RangeResponse, ExecuteError> {
+            let txn_db = new_store.inner.db.transaction();
+            let index = new_store.inner.index.state();
+            new_store.execute_range(
+                &txn_db,
+                &index,
+                &RangeRequest {
+                    key: key.into(),
+                    ..Default::default()
+                },
+            )
+        };
+        let res = range("a")?;
+        assert_eq!(res.kvs.len(), 1);
+        assert_eq!(res.kvs[0].mod_revision, 2);
+        assert!(range("b")?.kvs.is_empty());
+        assert_eq!(new_store.revision(), 2);
+
+        Ok(())
+    }
+
+    /// The engine leaves the writes to the OS by default, so a power failure
+    /// may lose the last applied entries, the engine then recovers to an
+    /// earlier applied index and the entries after it are applied again from
+    /// the curp log
+    #[tokio::test(flavor = "multi_thread")]
+    #[abort_on_panic]
+    async fn os_buffered_writes_lost_by_a_crash_should_be_applied_again_from_the_log(
+    ) -> Result<(), ExecuteError> {
+        let dir = TempDir::with_prefix("/tmp/test_os_buffered_writes_lost_by_a_crash").unwrap();
+        let db = DB::open(&EngineConfig::RocksDB(dir.path().join("origin")))?;
+        db.set_durability(DurabilityConfig::OsBuffered)?;
+        let store = init_empty_store(Arc::clone(&db));
+        let log: Vec<_> = (1..=10_u64)
+            .map(|i| {
+                if i % 4 == 0 {
+                    RequestWrapper::from(DeleteRangeRequest {
+                        key: format!("key{}", i % 3).into(),
+                        ..Default::default()
+                    })
+                } else {
+                    RequestWrapper::from(PutRequest {
+                        key: format!("key{}", i % 3).into(),
+                        value: i.to_le_bytes().to_vec(),
+                        ..Default::default()
+                    })
+                }
+            })
+            .collect();
+        // apply the log entry at the index like the command executor does
+        let apply = |store: &StoreWrapper, index: u64| -> Result<(), ExecuteError> {
+            let txn_db = store.db().transaction();
+            txn_db.write_op(WriteOp::PutAppliedIndex(index))?;
+            let index_state = store.index().state();
+            let rev_gen_state = store.revision.state();
+            let entry = log
+                .get(index.overflow_sub(1).numeric_cast::<usize>())
+                .unwrap_or_else(|| unreachable!("the index is in the log"));
+            let _res = store.after_sync(entry, &txn_db, &index_state, &rev_gen_state, false)?;
+            txn_db.commit().unwrap();
+            index_state.commit();
+            rev_gen_state.commit();
+            Ok(())
+        };
+
+        for index in 1..=6 {
+            apply(&store, index)?;
+        }
+        // the writes up to index 6 are all the OS flushed before the power failure,
+        // the checkpoint of the engine is what is left on the disk
+        let on_disk = db.get_snapshot(dir.path().join("on_disk"))?;
+        for index in 7..=10 {
+            apply(&store, index)?;
+        }
+
+        let recovered_db = DB::open(&EngineConfig::RocksDB(dir.path().join("recovered")))?;
+        recovered_db.reset(Some(on_disk)).await?;
+        let recovered_store = init_empty_store(Arc::clone(&recovered_db));
+        recovered_store.recover().await?;
+        // curp restarts from the persisted applied index and applies the rest of its log
+        let last_applied = recovered_db.persisted_applied_index()?;
+        assert_eq!(last_applied, 6);
+        for index in last_applied.overflow_add(1)..=10 {
+            apply(&recovered_store, index)?;
+        }
+
+        assert_eq!(recovered_store.revision(), store.revision());
+        assert_eq!(recovered_db.persisted_applied_index()?, 10);
+        assert_eq!(recovered_db.hash()?, db.hash()?);
+        drop(recovered_store);
+        drop(store);
+        dir.close().unwrap();
+        Ok(())
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     #[abort_on_panic]
     async fn test_txn() -> Result<(), ExecuteError> {
@@ -1605,6 +2801,322 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    #[abort_on_panic]
+    async fn test_txn_compare_over_range() -> Result<(), ExecuteError> {
+        let db = DB::open(&EngineConfig::Memory)?;
+        let (store, _rev) = init_store(db)?;
+        let compare = |key: &str,
+                       range_end: &[u8],
+                       target: CompareTarget,
+                       result: CompareResult,
+                       target_union: TargetUnion| Compare {
+            result: result as i32,
+            target: target as i32,
+            key: key.into(),
+            range_end: range_end.to_vec(),
+            target_union: Some(target_union),
+        };
+
+        let txn_db = store.inner.db.transaction();
+        let index = store.inner.index.state();
+        let cases = [
+            // every key in the range has to match
+            (
+                compare(
+                    "a",
+                    b"c",
+                    CompareTarget::Version,
+                    CompareResult::Equal,
+                    TargetUnion::Version(1),
+                ),
+                true,
+            ),
+            (
+                compare(
+                    "a",
+                    &[0],
+                    CompareTarget::Version,
+                    CompareResult::Equal,
+                    TargetUnion::Version(1),
+                ),
+                false,
+            ),
+            (
+                compare(
+                    "a",
+                    &[0],
+                    CompareTarget::Mod,
+                    CompareResult::Less,
+                    TargetUnion::ModRevision(100),
+                ),
+                true,
+            ),
+            (
+                compare(
+                    "a",
+                    b"f",
+                    CompareTarget::Lease,
+                    CompareResult::Equal,
+                    TargetUnion::Lease(0),
+                ),
+                true,
+            ),
+            (
+                compare(
+                    "b",
+                    b"e",
+                    CompareTarget::Value,
+                    CompareResult::Greater,
+                    TargetUnion::Value("a".into()),
+                ),
+                true,
+            ),
+            (
+                compare(
+                    "a",
+                    b"e",
+                    CompareTarget::Value,
+                    CompareResult::Greater,
+                    TargetUnion::Value("a".into()),
+                ),
+                false,
+            ),
+            // a range without keys compares with an empty key-value, except for values
+            (
+                compare(
+                    "f",
+                    b"z",
+                    CompareTarget::Version,
+                    CompareResult::Equal,
+                    TargetUnion::Version(0),
+                ),
+                true,
+            ),
+            (
+                compare(
+                    "f",
+                    b"z",
+                    CompareTarget::Value,
+                    CompareResult::NotEqual,
+                    TargetUnion::Value("a".into()),
+                ),
+                false,
+            ),
+        ];
+        for (cmp, expected) in cases {
+            assert_eq!(
+                KvStore::check_compare(&txn_db, &index, &cmp),
+                expected,
+                "{cmp:?}"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[abort_on_panic]
+    async fn test_txn_compare_over_chunks_of_range() -> Result<(), ExecuteError> {
+        let db = DB::open(&EngineConfig::Memory)?;
+        let store = init_empty_store(db);
+        let keys = RANGE_CHUNK_SIZE.overflow_add(1);
+        for i in 0..keys {
+            let value = if i == RANGE_CHUNK_SIZE { "w" } else { "v" };
+            let req = RequestWrapper::from(PutRequest {
+                key: format!("k{i:05}").into_bytes(),
+                value: value.into(),
+                ..Default::default()
+            });
+            exe_as_and_flush(&store, &req)?;
+        }
+        let compare = |range_end: String| Compare {
+            result: CompareResult::Equal as i32,
+            target: CompareTarget::Value as i32,
+            key: b"k".to_vec(),
+            range_end: range_end.into_bytes(),
+            target_union: Some(TargetUnion::Value("v".into())),
+        };
+
+        let txn_db = store.inner.db.transaction();
+        let index = store.inner.index.state();
+        assert!(KvStore::check_compare(
+            &txn_db,
+            &index,
+            &compare(format!("k{RANGE_CHUNK_SIZE:05}"))
+        ));
+        assert!(
+            !KvStore::check_compare(&txn_db, &index, &compare("l".to_owned())),
+            "the key in the last chunk should fail the compare"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[abort_on_panic]
+    async fn test_cas_through_kv_cache() -> Result<(), ExecuteError> {
+        let db = DB::open(&EngineConfig::Memory)?;
+        let store = init_empty_store_with_cache(db, 16);
+        let cas = |expected: &str, new: &str| {
+            RequestWrapper::from(TxnRequest {
+                compare: vec![Compare {
+                    result: CompareResult::Equal as i32,
+                    target: CompareTarget::Value as i32,
+                    key: "a".into(),
+                    range_end: vec![],
+                    target_union: Some(TargetUnion::Value(expected.into())),
+                }],
+                success: vec![RequestOp {
+                    request: Some(UniRequest::RequestPut(PutRequest {
+                        key: "a".into(),
+                        value: new.into(),
+                        ..Default::default()
+                    })),
+                }],
+                failure: vec![],
+            })
+        };
+        let get_value = || -> Result<Vec<u8>, ExecuteError> {
+            let txn_db = store.inner.db.transaction();
+            let index = store.inner.index.state();
+            let request = RangeRequest {
+                key: "a".into(),
+                ..Default::default()
+            };
+            let mut resp = store.execute_range(&txn_db, &index, &request)?;
+            Ok(resp.kvs.pop().map(|kv| kv.value).unwrap_or_default())
+        };
+
+        // a missing key never matches a value
+        exe_as_and_flush(&store, &cas("", "0"))?;
+        assert!(get_value()?.is_empty());
+
+        exe_as_and_flush(
+            &store,
+            &RequestWrapper::from(PutRequest {
+                key: "a".into(),
+                value: "1".into(),
+                ..Default::default()
+            }),
+        )?;
+        exe_as_and_flush(&store, &cas("1", "2"))?;
+        assert_eq!(get_value()?, b"2");
+        // swapping from a stale value fails
+        exe_as_and_flush(&store, &cas("1", "3"))?;
+        assert_eq!(get_value()?, b"2");
+        exe_as_and_flush(&store, &cas("2", "3"))?;
+        assert_eq!(get_value()?, b"3");
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[abort_on_panic]
+    async fn test_txn_range_observes_buffered_writes() -> Result<(), ExecuteError> {
+        let db = DB::open(&EngineConfig::Memory)?;
+        let store = init_empty_store(db);
+        let txn_req = RequestWrapper::from(TxnRequest {
+            compare: vec![],
+            success: vec![
+                RequestOp {
+                    request: Some(Request::RequestPut(PutRequest {
+                        key: "a".into(),
+                        value: "1".into(),
+                        ..Default::default()
+                    })),
+                },
+                RequestOp {
+                    request: Some(Request::RequestRange(RangeRequest {
+                        key: "a".into(),
+                        ..Default::default()
+                    })),
+                },
+            ],
+            failure: vec![],
+        });
+
+        let txn_db = store.db().transaction();
+        let index = store.index();
+        let index_state = index.state();
+        let rev_gen_state = store.revision.state();
+        let (_sync_res, cmd_res) =
+            store.after_sync(&txn_req, &txn_db, &index_state, &rev_gen_state, true)?;
+        let ResponseWrapper::TxnResponse(txn_resp) = cmd_res.unwrap().into_inner() else {
+            panic!("expect a txn response");
+        };
+        let Some(Response::ResponseRange(ref range_resp)) = txn_resp.responses[1].response else {
+            panic!("expect a range response");
+        };
+        assert_eq!(range_resp.kvs.len(), 1);
+        assert_eq!(range_resp.kvs[0].value, b"1");
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[abort_on_panic]
+    async fn test_txn_failed_halfway_leaves_nothing_behind() -> Result<(), ExecuteError> {
+        let db = DB::open(&EngineConfig::Memory)?;
+        let store = init_empty_store(db);
+        // the second put fails as there is no previous value to keep
+        let txn_req = RequestWrapper::from(TxnRequest {
+            compare: vec![],
+            success: vec![
+                RequestOp {
+                    request: Some(Request::RequestPut(PutRequest {
+                        key: "a".into(),
+                        value: "1".into(),
+                        ..Default::default()
+                    })),
+                },
+                RequestOp {
+                    request: Some(Request::RequestPut(PutRequest {
+                        key: "b".into(),
+                        ignore_value: true,
+                        ..Default::default()
+                    })),
+                },
+            ],
+            failure: vec![],
+        });
+        let put_req = RequestWrapper::from(PutRequest {
+            key: "c".into(),
+            value: "2".into(),
+            ..Default::default()
+        });
+
+        // the commands of a batch share the transaction and the index state
+        let txn_db = store.db().transaction();
+        let index = store.index();
+        let index_state = index.state();
+        let rev_gen_state = store.revision.state();
+        assert!(matches!(
+            store.after_sync(&txn_req, &txn_db, &index_state, &rev_gen_state, false),
+            Err(ExecuteError::KeyNotFound)
+        ));
+        let _res = store.after_sync(&put_req, &txn_db, &index_state, &rev_gen_state, false)?;
+        txn_db.commit().unwrap();
+        index_state.commit();
+        rev_gen_state.commit();
+
+        assert!(store.inner.index.get(b"a", b"", 0).is_empty());
+        assert_eq!(store.revision(), 2);
+        let txn_db = store.db().transaction();
+        let index_state = index.state();
+        let range_all = RangeRequest {
+            key: vec![0],
+            range_end: vec![0],
+            ..Default::default()
+        };
+        let res = store.execute_range(&txn_db, &index_state, &range_all)?;
+        assert_eq!(res.kvs.len(), 1);
+        assert_eq!(res.kvs[0].key, b"c");
+        assert_eq!(res.kvs[0].mod_revision, 2);
+
+        Ok(())
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     #[abort_on_panic]
     async fn test_kv_store_index_available() {
@@ -1711,7 +3223,23 @@ mod test {
         );
 
         let target_revisions = index_compact(&store, 5);
-        store.compact(target_revisions.as_ref())?;
+        assert!(store.compact(target_revisions.as_ref())? > 0);
+        assert_eq!(
+            store.compact(target_revisions.as_ref())?,
+            0,
+            "revisions should have been reclaimed"
+        );
+        assert!(
+            store.inner.index.current_rev(b"a").is_none(),
+            "tombstone of a should be removed from index"
+        );
+        assert!(
+            store
+                .db()
+                .get_value(KV_TABLE, Revision::new(5, 0).encode_to_vec())?
+                .is_none(),
+            "tombstone of a should be removed from db"
+        );
         assert!(
             KvStoreInner::get_range(&txn_db, &index, b"a", b"", 2)
                 .unwrap()
@@ -1741,6 +3269,287 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    #[abort_on_panic]
+    async fn test_compaction_records_reclaimed_bytes() -> Result<(), ExecuteError> {
+        let db = DB::open(&EngineConfig::Memory)?;
+        let store = init_empty_store(db);
+        for value in ["1", "2"] {
+            let req = RequestWrapper::from(PutRequest {
+                key: "a".into(),
+                value: value.into(),
+                ..Default::default()
+            });
+            exe_as_and_flush(&store, &req)?;
+        }
+        assert!(store.reclaimed_bytes(3).is_none());
+
+        let req = RequestWrapper::from(CompactionRequest {
+            revision: 3,
+            physical: true,
+        });
+        exe_as_and_flush(&store, &req)?;
+        assert!(store
+            .reclaimed_bytes(3)
+            .is_some_and(|reclaimed| reclaimed > 0));
+        assert!(
+            store.reclaimed_bytes(2).is_none(),
+            "only the last compaction should be recorded"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[abort_on_panic]
+    async fn test_size_in_use_follows_kv_table() -> Result<(), ExecuteError> {
+        let db = DB::open(&EngineConfig::Memory)?;
+        let kv_table_size = |db: &DB| -> Result<u64, ExecuteError> {
+            Ok(db
+                .get_all(KV_TABLE)?
+                .iter()
+                .map(|(k, v)| k.len().overflow_add(v.len()).numeric_cast::<u64>())
+                .sum())
+        };
+        let (store, _rev_gen) = init_store(Arc::clone(&db))?;
+        exe_as_and_flush(
+            &store,
+            &RequestWrapper::from(DeleteRangeRequest {
+                key: "a".into(),
+                range_end: "c".into(),
+                ..Default::default()
+            }),
+        )?;
+        assert_ne!(db.size_in_use(), 0);
+        assert_eq!(db.size_in_use(), kv_table_size(&db)?);
+
+        let target_revisions = index_compact(&store, 10);
+        store.compact(target_revisions.as_ref())?;
+        assert_eq!(db.size_in_use(), kv_table_size(&db)?);
+
+        let size = db.size_in_use();
+        db.restore_record_sizes(HashMap::new());
+        let new_store = init_empty_store(Arc::clone(&db));
+        new_store.recover().await?;
+        assert_eq!(db.size_in_use(), size);
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[abort_on_panic]
+    async fn test_hash_kv_should_not_depend_on_compaction_progress() -> Result<(), ExecuteError> {
+        let db = DB::open(&EngineConfig::Memory)?;
+        let store = init_empty_store(db);
+        // sample requests: (a, 1) (b, 2) (a, 3) (del a) (c, 4) (d, 5)
+        // their revisions:     2      3      4       5      6      7
+        let put = |key: &str, value: &str| {
+            RequestWrapper::from(PutRequest {
+                key: key.into(),
+                value: value.into(),
+                ..Default::default()
+            })
+        };
+        let requests = vec![
+            put("a", "1"),
+            put("b", "2"),
+            put("a", "3"),
+            RequestWrapper::from(DeleteRangeRequest {
+                key: "a".into(),
+                ..Default::default()
+            }),
+            put("c", "4"),
+            put("d", "5"),
+        ];
+        for req in requests {
+            exe_as_and_flush(&store, &req)?;
+        }
+
+        let (hash, _, rev) = store.hash_kv(0)?;
+        assert_eq!(rev, 7);
+        assert_ne!(store.hash_kv(6)?.0, hash);
+
+        // compact at the tombstone of a, then at the creation of c
+        for compact_rev in [5, 6] {
+            store.update_compacted_revision(compact_rev);
+            let (before, compacted, _) = store.hash_kv(7)?;
+            assert_eq!(compacted, compact_rev);
+            let target_revisions = index_compact(&store, compact_rev);
+            let _n = store.compact(target_revisions.as_ref())?;
+            let (after, _, _) = store.hash_kv(7)?;
+            assert_eq!(before, after, "compact at {compact_rev}");
+        }
+        assert!(matches!(
+            store.hash_kv(4),
+            Err(ExecuteError::RevisionCompacted(4, 6))
+        ));
+        assert!(matches!(
+            store.hash_kv(8),
+            Err(ExecuteError::RevisionTooLarge(8, 7))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[abort_on_panic]
+    async fn test_tombstone_keeps_the_lifetime_of_the_deleted_key() -> Result<(), ExecuteError> {
+        let db = DB::open(&EngineConfig::Memory)?;
+        // revisions of "z": 7(z1) 8(z2) 9(z3)
+        let (store, _rev) = init_store(Arc::clone(&db))?;
+        db.set_explicit_tombstones(true);
+        exe_as_and_flush(
+            &store,
+            &RequestWrapper::from(DeleteRangeRequest {
+                key: "z".into(),
+                ..Default::default()
+            }),
+        )?;
+        let del_rev = store.revision();
+        assert_eq!(del_rev, 10);
+
+        let raw = db
+            .get_value(KV_TABLE, Revision::new(del_rev, 0).encode_to_vec())?
+            .unwrap();
+        let KvRecord::Tombstone(tombstone) = compression::decode_record(&raw)? else {
+            panic!("the deletion should be written as a tombstone");
+        };
+        assert_eq!(
+            tombstone,
+            Tombstone {
+                key: b"z".to_vec(),
+                revision: 10,
+                create_revision: 7,
+                version: 3,
+            }
+        );
+
+        // watchers still see an etcd style deletion
+        let events = store
+            .inner
+            .get_event_from_revision(&[KeyRange::new_one_key("z")], del_rev)?;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].r#type, EventType::Delete as i32);
+        assert_eq!(events[0].kv, Some(tombstone.to_deletion_kv()));
+
+        // the key stays deleted after the recovery
+        let new_store = init_empty_store(Arc::clone(&db));
+        new_store.recover().await?;
+        let range_req = RangeRequest {
+            key: "z".into(),
+            ..Default::default()
+        };
+        let txn_db = new_store.inner.db.transaction();
+        let index = new_store.inner.index.state();
+        let res = new_store.execute_range(&txn_db, &index, &range_req)?;
+        assert!(res.kvs.is_empty());
+
+        // the compaction removes the tombstone
+        let target_revisions = index_compact(&store, del_rev);
+        let _n = store.compact(target_revisions.as_ref())?;
+        assert!(db
+            .get_value(KV_TABLE, Revision::new(del_rev, 0).encode_to_vec())?
+            .is_none());
+
+        // the deletion is etcd style until every member can read the tombstones
+        db.set_explicit_tombstones(false);
+        for req in [
+            RequestWrapper::from(PutRequest {
+                key: "z".into(),
+                value: "z4".into(),
+                ..Default::default()
+            }),
+            RequestWrapper::from(DeleteRangeRequest {
+                key: "z".into(),
+                ..Default::default()
+            }),
+        ] {
+            exe_as_and_flush(&store, &req)?;
+        }
+        let raw = db
+            .get_value(KV_TABLE, Revision::new(12, 0).encode_to_vec())?
+            .unwrap();
+        // the lifetime of a key deleted the etcd way is unknown
+        assert_eq!(
+            compression::decode_record(&raw)?,
+            KvRecord::Tombstone(Tombstone {
+                key: b"z".to_vec(),
+                revision: 12,
+                create_revision: 0,
+                version: 0,
+            })
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[abort_on_panic]
+    async fn test_migrate_to_cold_tier() -> Result<(), ExecuteError> {
+        let dir = TempDir::with_prefix("/tmp/test_migrate_to_cold_tier").unwrap();
+        let db = DB::open(&EngineConfig::Memory)?;
+        db.attach_cold_tier(dir.path(), None)?;
+        // revisions of "z": 7(z1) 8(z2) 9(z3)
+        let (store, _rev) = init_store(Arc::clone(&db))?;
+        let hash = store.hash_kv(0)?.0;
+        let db_hash = db.hash()?;
+
+        // only z1 is superseded before revision 9
+        assert_eq!(store.migrate_to_cold_tier(0)?, 1);
+        assert_eq!(store.migrate_to_cold_tier(0)?, 0);
+        assert_eq!(db.cold_tier().unwrap().migrated_revision()?, 9);
+        assert_eq!(db.get_all(KV_TABLE)?.len(), 7);
+        assert_eq!(store.hash_kv(0)?.0, hash);
+        assert_eq!(db.hash()?, db_hash);
+
+        let txn_db = db.transaction();
+        let index = store.inner.index.state();
+        let range_at = |revision: i64| RangeRequest {
+            key: "z".into(),
+            revision,
+            ..Default::default()
+        };
+        let response = store.execute_range(&txn_db, &index, &range_at(7))?;
+        assert_eq!(response.kvs[0].value, b"z1");
+        let response = store.execute_range(&txn_db, &index, &range_at(8))?;
+        let prev_kv = store.inner.get_prev_kv(&response.kvs[0]).unwrap();
+        assert_eq!(prev_kv.value, b"z1");
+        let events = store
+            .inner
+            .get_event_from_revision(&[KeyRange::new_one_key("z")], 7)?;
+        assert_eq!(events.len(), 3);
+
+        // the migrated revisions are indexed again after the recovery
+        let new_store = init_empty_store(Arc::clone(&db));
+        new_store.recover().await?;
+        assert_eq!(new_store.revision(), store.revision());
+        let new_index = new_store.inner.index.state();
+        let response = new_store.execute_range(&txn_db, &new_index, &range_at(7))?;
+        assert_eq!(response.kvs[0].value, b"z1");
+        assert_eq!(new_store.hash_kv(0)?.0, hash);
+
+        // z2 is superseded before revision 10 after z4 is put
+        exe_as_and_flush(
+            &store,
+            &RequestWrapper::from(PutRequest {
+                key: "z".into(),
+                value: "z4".into(),
+                ..Default::default()
+            }),
+        )?;
+        assert_eq!(store.migrate_to_cold_tier(0)?, 1);
+        let response = store.execute_range(&db.transaction(), &index, &range_at(8))?;
+        assert_eq!(response.kvs[0].value, b"z2");
+
+        // the compacted revisions are removed from the cold tier as well
+        let target_revisions = index_compact(&store, 9);
+        store.compact(target_revisions.as_ref())?;
+        let cold_values = db.cold_tier().unwrap().get_values(&target_revisions)?;
+        assert!(cold_values.iter().all(Option::is_none));
+
+        Ok(())
+    }
+
     #[test]
     fn check_revision_will_return_correct_error_type() {
         let request = TxnRequest {
@@ -1,33 +1,53 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{HashMap, HashSet, VecDeque},
     hash::Hash,
     sync::{
-        atomic::{AtomicI64, Ordering},
+        atomic::{AtomicI64, AtomicUsize, Ordering},
         Arc,
     },
     time::Duration,
 };
 
+use clippy_utilities::OverflowArithmetic;
 use itertools::Itertools;
 use parking_lot::RwLock;
+use prost::Message;
 use tokio::{
     sync::mpsc::{self, error::TrySendError},
     time::sleep,
 };
 use tracing::{debug, warn};
 use utils::{
+    interval_map::{Interval, IntervalMap},
     parking_lot_lock::RwLockMap,
     task_manager::{tasks::TaskName, Listener, TaskManager},
     write_vec,
 };
-use xlineapi::command::KeyRange;
+use xlineapi::{command::KeyRange, interval::BytesAffine};
 
-use super::kv_store::KvStoreInner;
-use crate::rpc::{Event, KeyValue};
+use super::kv_store::{KvStoreInner, PrevKvWatcher};
+use crate::{
+    revision_number::RevisionNumberGenerator,
+    rpc::{Event, EventType, FilterType, KeyValue},
+};
 
 /// Watch ID
 pub(crate) type WatchId = i64;
 
+/// Max number of the recent revisions whose events are kept in memory
+const RECENT_EVENTS_CAPACITY: usize = 0x1000; // 4096
+
+/// Max encoded size of the recent events kept in memory
+const RECENT_EVENTS_MAX_BYTES: usize = 0x400_0000; // 64 MiB
+
+/// Max number of the watch events queued for a watcher
+///
+/// The dispatcher never waits for a watcher. A watcher whose queue is full is
+/// a slow watcher, it's detached from the dispatcher as a victim and resynced
+/// from the history by `sync_victims_task`. A victim whose history has been
+/// compacted meanwhile is canceled.
+const WATCHER_QUEUE_CAPACITY: usize = 128;
+
 /// Watch ID generator
 #[derive(Debug)]
 pub(crate) struct WatchIdGenerator(AtomicI64);
@@ -47,18 +67,19 @@ impl WatchIdGenerator {
 /// Watcher
 #[derive(Debug)]
 struct Watcher {
-    /// Key Range
-    key_range: KeyRange,
+    /// Key ranges, the events of them in one revision are notified together
+    key_ranges: Vec<KeyRange>,
     /// Watch ID
     watch_id: WatchId,
-    /// Start revision of this watcher
+    /// Start revision of this watcher, it moves past the notified revisions so
+    /// the events are replayed from it when the watcher catches up
     start_rev: i64,
-    /// Event filters
-    filters: Vec<i32>,
+    /// Types of the events that are filtered out
+    filtered_types: Vec<i32>,
     /// Stop notify
     stop_notify: Arc<event_listener::Event>,
-    /// Sender of watch event
-    event_tx: mpsc::Sender<WatchEvent>,
+    /// Queue of watch events
+    queue: WatcherQueue,
     /// Compacted flag
     compacted: bool,
     /// TODO: remove it when https://github.com/xline-kv/Xline/issues/491 has been closed
@@ -83,7 +104,7 @@ impl Hash for Watcher {
 impl Watcher {
     /// New `WatcherInner`
     fn new(
-        key_range: KeyRange,
+        key_ranges: Vec<KeyRange>,
         watch_id: WatchId,
         start_rev: i64,
         filters: Vec<i32>,
@@ -92,12 +113,17 @@ impl Watcher {
         compacted: bool,
     ) -> Self {
         Self {
-            key_range,
+            // an empty range never matches any key, so it is not indexed
+            key_ranges: key_ranges
+                .into_iter()
+                .filter(|key_range| !key_range.is_empty())
+                .unique()
+                .collect(),
             watch_id,
             start_rev,
-            filters,
+            filtered_types: filters.into_iter().filter_map(filtered_type).collect(),
             stop_notify,
-            event_tx,
+            queue: WatcherQueue::new(event_tx),
             compacted,
             notified_set: HashSet::new(),
         }
@@ -108,15 +134,15 @@ impl Watcher {
         self.watch_id
     }
 
-    /// Get key range
-    fn key_range(&self) -> &KeyRange {
-        &self.key_range
+    /// Get key ranges
+    fn key_ranges(&self) -> &[KeyRange] {
+        &self.key_ranges
     }
 
     /// filter out events
     fn filter_events(&self, mut events: Vec<Event>) -> Vec<Event> {
         events.retain(|event| {
-            self.filters.iter().all(|filter| filter != &event.r#type)
+            !self.filtered_types.contains(&event.r#type)
                 && (event.kv.as_ref().map_or(false, |kv| {
                     kv.mod_revision >= self.start_rev
                         && !self.notified_set.contains(&kv.mod_revision)
@@ -138,6 +164,7 @@ impl Watcher {
             events,
             revision,
             compacted: self.compacted,
+            pending: None,
         };
         if !self.compacted
             && (revision < self.start_rev
@@ -147,9 +174,12 @@ impl Watcher {
             return Ok(());
         };
 
-        match self.event_tx.try_send(watch_event) {
+        match self.queue.try_send(watch_event) {
             Ok(()) => {
                 let _ignore = self.notified_set.insert(revision);
+                if !self.compacted {
+                    self.start_rev = self.start_rev.max(revision.overflow_add(1));
+                }
                 Ok(())
             }
             Err(TrySendError::Closed(_)) => {
@@ -168,6 +198,88 @@ impl Watcher {
     }
 }
 
+/// Bounded queue of the watch events of a watcher
+///
+/// The events are forwarded to the stream of the watcher by a task of the
+/// queue, so a stalled stream only stalls the queues of its own watchers.
+#[derive(Debug)]
+struct WatcherQueue {
+    /// Sender of the queue
+    tx: mpsc::Sender<WatchEvent>,
+    /// Number of the events that are queued or not handled by the stream yet
+    pending: Arc<AtomicUsize>,
+}
+
+impl WatcherQueue {
+    /// Create a queue forwarding the events to `event_tx`
+    fn new(event_tx: mpsc::Sender<WatchEvent>) -> Self {
+        let (tx, mut rx) = mpsc::channel::<WatchEvent>(WATCHER_QUEUE_CAPACITY);
+        let _handle = tokio::spawn(async move {
+            while let Some(watch_event) = rx.recv().await {
+                if event_tx.send(watch_event).await.is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            tx,
+            pending: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Try to queue a watch event, it's given back if the queue is full or
+    /// the stream is closed
+    fn try_send(&self, mut watch_event: WatchEvent) -> Result<(), TrySendError<WatchEvent>> {
+        watch_event.pending = Some(PendingEvent::new(Arc::clone(&self.pending)));
+        self.tx.try_send(watch_event).map_err(|e| match e {
+            TrySendError::Full(mut watch_event) => {
+                watch_event.pending = None;
+                TrySendError::Full(watch_event)
+            }
+            TrySendError::Closed(mut watch_event) => {
+                watch_event.pending = None;
+                TrySendError::Closed(watch_event)
+            }
+        })
+    }
+
+    /// Return if all the queued events have been handled by the stream
+    fn is_empty(&self) -> bool {
+        self.pending.load(Ordering::Acquire) == 0
+    }
+}
+
+/// Counts a watch event as pending until the stream handles and drops it
+#[derive(Debug)]
+struct PendingEvent(Arc<AtomicUsize>);
+
+impl PendingEvent {
+    /// Count a new pending event
+    fn new(pending: Arc<AtomicUsize>) -> Self {
+        let _prev = pending.fetch_add(1, Ordering::AcqRel);
+        Self(pending)
+    }
+}
+
+impl Drop for PendingEvent {
+    fn drop(&mut self) {
+        let _prev = self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Get the type of the events dropped by a filter, unknown filters are ignored
+/// like etcd
+fn filtered_type(filter: i32) -> Option<i32> {
+    match FilterType::try_from(filter) {
+        Ok(FilterType::Noput) => Some(EventType::Put.into()),
+        Ok(FilterType::Nodelete) => Some(EventType::Delete.into()),
+        Err(_) => {
+            warn!(filter, "unknown watch filter is ignored");
+            None
+        }
+    }
+}
+
 /// KV watcher
 #[derive(Debug)]
 pub(crate) struct KvWatcher {
@@ -175,41 +287,84 @@ pub(crate) struct KvWatcher {
     kv_store_inner: Arc<KvStoreInner>,
     /// Watch indexes
     watcher_map: Arc<RwLock<WatcherMap>>,
+    /// Revision of the KV store, the kv updates no later than it are committed
+    revision: Arc<RevisionNumberGenerator>,
+    /// Revision of the last kv updates dispatched to the watchers
+    dispatched_revision: AtomicI64,
 }
 
 /// Store all watchers
 #[derive(Debug)]
 struct WatcherMap {
-    /// Index for watchers
-    index: HashMap<KeyRange, HashSet<WatchId>>,
+    /// Index for watchers, a key only needs to look up the ranges overlapping it
+    index: IntervalMap<BytesAffine, HashSet<WatchId>>,
     /// All watchers
     watchers: HashMap<WatchId, Watcher>,
     /// Victims
     victims: HashMap<Watcher, (i64, Vec<Event>)>,
+    /// Events of the recently dispatched kv updates
+    recent: RecentEvents,
 }
 
 impl WatcherMap {
     /// Create a new `WatcherMap`
     fn new() -> Self {
         Self {
-            index: HashMap::new(),
+            index: IntervalMap::new(),
             watchers: HashMap::new(),
             victims: HashMap::new(),
+            recent: RecentEvents::new(RECENT_EVENTS_CAPACITY, RECENT_EVENTS_MAX_BYTES),
         }
     }
 
     /// Insert a new watcher to the map and create. Internally, it will create a index for this watcher.
     fn register(&mut self, watcher: Watcher) {
-        let key_range = watcher.key_range().clone();
         let watch_id = watcher.watch_id();
+        for key_range in watcher.key_ranges() {
+            assert!(
+                self.index
+                    .entry(key_range.clone().into())
+                    .or_insert(HashSet::new())
+                    .insert(watch_id),
+                "can't insert a watcher to index twice"
+            );
+        }
         assert!(
             self.watchers.insert(watch_id, watcher).is_none(),
             "can't insert a watcher to watchers twice"
         );
-        assert!(
-            self.index.entry(key_range).or_default().insert(watch_id),
-            "can't insert a watcher to index twice"
-        );
+    }
+
+    /// Remove a watcher from the index
+    fn unindex(&mut self, watcher: &Watcher) {
+        for key_range in watcher.key_ranges() {
+            let interval = key_range.clone().into();
+            let Some(watch_ids) = self.index.get_mut(&interval) else {
+                unreachable!("watch_ids should exist")
+            };
+            assert!(
+                watch_ids.remove(&watcher.watch_id()),
+                "no such watcher in index"
+            );
+            if watch_ids.is_empty() {
+                assert!(
+                    self.index.remove(&interval).is_some(),
+                    "watch_ids should exist"
+                );
+            }
+        }
+    }
+
+    /// Get the watchers whose key ranges contain the key, a watcher is yielded
+    /// once for every of its ranges containing the key
+    fn watchers_of_key(&self, key: &[u8]) -> impl Iterator<Item = WatchId> + '_ {
+        let mut end = key.to_vec();
+        end.push(0);
+        let interval = Interval::new(BytesAffine::new_key(key), BytesAffine::new_key(end));
+        self.index
+            .find_all_overlap(&interval)
+            .into_iter()
+            .flat_map(|(_, watch_ids)| watch_ids.iter().copied())
     }
 
     /// Move a watcher to victims, the `watch_id` must be valid.
@@ -218,24 +373,13 @@ impl WatcherMap {
         let Some(watcher) = self.watchers.remove(&watch_id) else {
             unreachable!("watcher should exist")
         };
-        let Some(watch_ids) = self.index.get_mut(watcher.key_range()) else {
-            unreachable!("watch_ids should exist")
-        };
-        assert!(
-            watch_ids.remove(&watcher.watch_id()),
-            "no such watcher in index"
-        );
-        if watch_ids.is_empty() {
-            assert!(
-                self.index.remove(&watcher.key_range).is_some(),
-                "watch_ids should exist"
-            );
-        }
+        self.unindex(&watcher);
         let watch_event = WatchEvent {
             id: watch_id,
             revision: updates.0,
             events: updates.1,
             compacted: false,
+            pending: None,
         };
         assert!(
             self.victims
@@ -248,23 +392,7 @@ impl WatcherMap {
     /// Remove a watcher
     fn remove(&mut self, watch_id: WatchId) {
         if let Some(watcher) = self.watchers.remove(&watch_id) {
-            let key_range = watcher.key_range();
-            let is_empty = {
-                let Some(watch_ids) = self.index.get_mut(watcher.key_range()) else {
-                    unreachable!("watch_ids should exist")
-                };
-                assert!(
-                    watch_ids.remove(&watcher.watch_id()),
-                    "no such watcher in index"
-                );
-                watch_ids.is_empty()
-            };
-            if is_empty {
-                assert!(
-                    self.index.remove(key_range).is_some(),
-                    "watch_ids should exist"
-                );
-            }
+            self.unindex(&watcher);
         } else {
             self.victims = self
                 .victims
@@ -275,21 +403,103 @@ impl WatcherMap {
     }
 }
 
+/// Bounded buffer of the events of the recently dispatched kv updates, the
+/// watchers starting from a recent revision are served from it instead of
+/// rebuilding the events from the index and the DB
+#[derive(Debug)]
+struct RecentEvents {
+    /// Kv updates in the order of revisions
+    updates: VecDeque<(i64, Vec<Event>)>,
+    /// All the kv updates since this revision are in the buffer, `None` if no
+    /// updates are dispatched yet
+    first_revision: Option<i64>,
+    /// Encoded size of the buffered events
+    size: usize,
+    /// Max number of buffered revisions
+    capacity: usize,
+    /// Max encoded size of the buffered events
+    max_bytes: usize,
+}
+
+impl RecentEvents {
+    /// New `RecentEvents`
+    fn new(capacity: usize, max_bytes: usize) -> Self {
+        Self {
+            updates: VecDeque::new(),
+            first_revision: None,
+            size: 0,
+            capacity,
+            max_bytes,
+        }
+    }
+
+    /// Buffer the events of a dispatched kv update, the oldest ones are
+    /// evicted once the buffer is full. The updates after the committed
+    /// revision are never evicted, they can't be replayed from the index yet.
+    fn push(&mut self, (revision, events): (i64, Vec<Event>), committed: i64) {
+        if events.is_empty() {
+            return;
+        }
+        let _ignore = self.first_revision.get_or_insert(revision);
+        self.size = events.iter().fold(self.size, |size, event| {
+            size.overflow_add(event.encoded_len())
+        });
+        self.updates.push_back((revision, events));
+        while self.updates.len() > self.capacity || self.size > self.max_bytes {
+            match self.updates.front() {
+                Some(&(oldest, _)) if oldest <= committed => {}
+                _ => break,
+            }
+            let Some((oldest, events)) = self.updates.pop_front() else {
+                unreachable!("the buffer is not empty")
+            };
+            self.size = events.iter().fold(self.size, |size, event| {
+                size.overflow_sub(event.encoded_len())
+            });
+            self.first_revision = Some(oldest.overflow_add(1));
+        }
+    }
+
+    /// Return if all the events since the revision are in the buffer
+    fn covers(&self, revision: i64) -> bool {
+        self.first_revision.is_some_and(|first| revision >= first)
+    }
+
+    /// Get the buffered events of the key ranges since the revision
+    fn events_since(&self, revision: i64, key_ranges: &[KeyRange]) -> Vec<Event> {
+        let start = self.updates.partition_point(|&(rev, _)| rev < revision);
+        self.updates
+            .range(start..)
+            .flat_map(|(_, events)| events)
+            .filter(|event| {
+                event
+                    .kv
+                    .as_ref()
+                    .is_some_and(|kv| key_ranges.iter().any(|r| r.contains_key(&kv.key)))
+            })
+            .cloned()
+            .collect()
+    }
+}
+
 /// Operations of KV watcher
 #[allow(clippy::arithmetic_side_effects, clippy::indexing_slicing)] // Introduced by mockall::automock
 #[cfg_attr(test, mockall::automock)]
 #[async_trait::async_trait]
 pub(crate) trait KvWatcherOps {
-    /// Create a watch to KV store
+    /// Create a watch to KV store, returns the revision the watcher is created at,
+    /// the watcher receives every event after it, besides the history from a
+    /// non-zero `start_rev`. The events of one revision in all the key ranges
+    /// are sent in one `WatchEvent`.
     fn watch(
         &self,
         id: WatchId,
-        key_range: KeyRange,
+        key_ranges: Vec<KeyRange>,
         start_rev: i64,
         filters: Vec<i32>,
         stop_notify: Arc<event_listener::Event>,
         event_tx: mpsc::Sender<WatchEvent>,
-    );
+    ) -> i64;
 
     /// Cancel a watch from KV store
     fn cancel(&self, id: WatchId);
@@ -297,8 +507,20 @@ pub(crate) trait KvWatcherOps {
     /// Get Prev `KeyValue` of a `KeyValue`
     fn get_prev_kv(&self, kv: &KeyValue) -> Option<KeyValue>;
 
+    /// Register a watcher asking for `prev_kv`, the previous kvs are attached to
+    /// the events while the registration is alive
+    fn register_prev_kv(&self) -> PrevKvWatcher;
+
     /// Get compacted revision from backend store
     fn compacted_revision(&self) -> i64;
+
+    /// Check if a watcher has sent all of its events, i.e. it is not a victim
+    /// and the stream has handled all of its queued events
+    fn is_synced(&self, id: WatchId) -> bool;
+
+    /// Get the revision of the last kv updates dispatched to the watchers, the events no later
+    /// than it have been queued for all the watchers that are not victims
+    fn dispatched_revision(&self) -> i64;
 }
 
 #[async_trait::async_trait]
@@ -306,15 +528,16 @@ impl KvWatcherOps for KvWatcher {
     fn watch(
         &self,
         id: WatchId,
-        key_range: KeyRange,
+        key_ranges: Vec<KeyRange>,
         start_rev: i64,
         filters: Vec<i32>,
         stop_notify: Arc<event_listener::Event>,
         event_tx: mpsc::Sender<WatchEvent>,
-    ) {
-        let compacted = start_rev != 0 && start_rev < self.compacted_revision();
+    ) -> i64 {
+        // a negative start revision can never be served, report it as compacted like etcd
+        let compacted = start_rev < 0 || (start_rev != 0 && start_rev < self.compacted_revision());
         let mut watcher = Watcher::new(
-            key_range.clone(),
+            key_ranges,
             id,
             start_rev,
             filters,
@@ -322,48 +545,47 @@ impl KvWatcherOps for KvWatcher {
             event_tx,
             compacted,
         );
-        let mut watcher_map_w = self.watcher_map.write();
         if compacted {
             debug!("The revision {watcher:?} required has been compacted");
             if let Err(TrySendError::Full(watch_event)) = watcher.notify((0, vec![])) {
                 assert!(
-                    watcher_map_w
+                    self.watcher_map
+                        .write()
                         .victims
                         .insert(watcher, (watch_event.revision, watch_event.events))
                         .is_none(),
                     "can't insert a watcher to victims twice"
                 );
             };
-            return;
+            return self.revision.get();
         }
 
-        let initial_events = if start_rev == 0 {
-            vec![]
-        } else {
-            self.kv_store_inner
-                .get_event_from_revision(key_range, start_rev)
-                .unwrap_or_else(|e| {
-                    warn!("failed to get initial events for watcher: {:?}", e);
-                    vec![]
-                })
-        };
-        if !initial_events.is_empty() {
-            let last_revision = get_last_revision(&initial_events);
-            if let Err(TrySendError::Full(watch_event)) =
-                watcher.notify((last_revision, initial_events))
-            {
-                assert!(
-                    watcher_map_w
-                        .victims
-                        .insert(watcher, (watch_event.revision, watch_event.events))
-                        .is_none(),
-                    "can't insert a watcher to victims twice"
-                );
-                return;
-            };
+        // Most of the history is replayed without blocking the dispatcher, only
+        // the events committed meanwhile are replayed while holding the lock
+        let replayed = self.replay_history(&mut watcher);
+        let mut watcher_map_w = self.watcher_map.write();
+        // no kv updates can be dispatched until the watcher is registered, so it
+        // receives all the updates after this revision either from the history
+        // or from the dispatcher
+        let revision = self.revision.get();
+        if watcher.start_rev == 0 {
+            watcher.start_rev = revision.overflow_add(1);
+        }
+        if let Err(TrySendError::Full(watch_event)) =
+            replayed.and_then(|()| self.catch_up(&mut watcher, &watcher_map_w, revision))
+        {
+            assert!(
+                watcher_map_w
+                    .victims
+                    .insert(watcher, (watch_event.revision, watch_event.events))
+                    .is_none(),
+                "can't insert a watcher to victims twice"
+            );
+            return revision;
         }
         debug!("register watcher: {:?}", watcher);
         watcher_map_w.register(watcher);
+        revision
     }
 
     fn cancel(&self, watch_id: WatchId) {
@@ -374,15 +596,32 @@ impl KvWatcherOps for KvWatcher {
         self.kv_store_inner.get_prev_kv(kv)
     }
 
+    fn register_prev_kv(&self) -> PrevKvWatcher {
+        self.kv_store_inner.register_prev_kv_watcher()
+    }
+
     fn compacted_revision(&self) -> i64 {
         self.kv_store_inner.compacted_revision()
     }
+
+    fn is_synced(&self, id: WatchId) -> bool {
+        self.watcher_map
+            .read()
+            .watchers
+            .get(&id)
+            .is_some_and(|watcher| watcher.queue.is_empty())
+    }
+
+    fn dispatched_revision(&self) -> i64 {
+        self.dispatched_revision.load(Ordering::Acquire)
+    }
 }
 
 impl KvWatcher {
     /// Create a new `Arc<KvWatcher>`
     pub(crate) fn new_arc(
         kv_store_inner: Arc<KvStoreInner>,
+        revision: Arc<RevisionNumberGenerator>,
         kv_update_rx: flume::Receiver<(i64, Vec<Event>)>,
         sync_victims_interval: Duration,
         task_manager: &TaskManager,
@@ -391,6 +630,8 @@ impl KvWatcher {
         let kv_watcher = Arc::new(Self {
             kv_store_inner,
             watcher_map,
+            revision,
+            dispatched_revision: AtomicI64::new(0),
         });
         task_manager.spawn(TaskName::SyncVictims, |n| {
             Self::sync_victims_task(Arc::clone(&kv_watcher), sync_victims_interval, n)
@@ -451,27 +692,42 @@ impl KvWatcher {
                         "can't insert a watcher to new_victims twice"
                     );
                 } else {
-                    let mut watcher_map_w = kv_watcher.watcher_map.write();
-                    let initial_events = kv_watcher
-                        .kv_store_inner
-                        .get_event_from_revision(watcher.key_range.clone(), watcher.start_rev)
-                        .unwrap_or_else(|e| {
-                            warn!("failed to get initial events for watcher: {:?}", e);
-                            vec![]
-                        });
-                    if !initial_events.is_empty() {
-                        let last_revision = get_last_revision(&initial_events);
-                        if let Err(TrySendError::Full(watch_event)) =
-                            watcher.notify((last_revision, initial_events))
-                        {
+                    if watcher.compacted {
+                        // the cancel has been sent
+                        continue;
+                    }
+                    if watcher.start_rev < kv_watcher.compacted_revision() {
+                        // the history the watcher still needs was compacted while it was a victim
+                        debug!(
+                            watch_id = watcher.watch_id(),
+                            "cancel the victim watcher whose history has been compacted"
+                        );
+                        watcher.compacted = true;
+                        if let Err(TrySendError::Full(watch_event)) = watcher.notify((0, vec![])) {
                             assert!(
                                 new_victims
                                     .insert(watcher, (watch_event.revision, watch_event.events))
                                     .is_none(),
                                 "can't insert a watcher to new_victims twice"
                             );
-                            break;
-                        };
+                        }
+                        continue;
+                    }
+                    // the same as `watch`, only the events committed during
+                    // the first replay are replayed while holding the lock
+                    let replayed = kv_watcher.replay_history(&mut watcher);
+                    let mut watcher_map_w = kv_watcher.watcher_map.write();
+                    let revision = kv_watcher.revision.get();
+                    if let Err(TrySendError::Full(watch_event)) = replayed
+                        .and_then(|()| kv_watcher.catch_up(&mut watcher, &watcher_map_w, revision))
+                    {
+                        assert!(
+                            new_victims
+                                .insert(watcher, (watch_event.revision, watch_event.events))
+                                .is_none(),
+                            "can't insert a watcher to new_victims twice"
+                        );
+                        continue;
                     }
                     debug!(
                         watch_id = watcher.watch_id(),
@@ -488,27 +744,85 @@ impl KvWatcher {
         }
     }
 
+    /// Replay the events from the start revision of a watcher up to the
+    /// committed revision, a watcher starting from 0 needs no history
+    fn replay(
+        &self,
+        watcher: &mut Watcher,
+        committed: i64,
+    ) -> Result<(), TrySendError<WatchEvent>> {
+        if watcher.start_rev == 0 || watcher.start_rev > committed {
+            return Ok(());
+        }
+        let mut events = self
+            .kv_store_inner
+            .get_event_from_revision(watcher.key_ranges(), watcher.start_rev)
+            .unwrap_or_else(|e| {
+                warn!("failed to get initial events for watcher: {:?}", e);
+                vec![]
+            });
+        // the later revisions may be partially written to the index
+        events.retain(|event| {
+            event
+                .kv
+                .as_ref()
+                .is_some_and(|kv| kv.mod_revision <= committed)
+        });
+        if events.is_empty() {
+            return Ok(());
+        }
+        let last_revision = get_last_revision(&events);
+        watcher.notify((last_revision, events))
+    }
+
+    /// Replay the history that is no longer in the recent events to a watcher
+    /// without holding the lock of the `watcher_map`
+    fn replay_history(&self, watcher: &mut Watcher) -> Result<(), TrySendError<WatchEvent>> {
+        if self.watcher_map.read().recent.covers(watcher.start_rev) {
+            return Ok(());
+        }
+        self.replay(watcher, self.revision.get())
+    }
+
+    /// Replay the history and the recent events to a watcher before it is
+    /// registered, the lock of the `watcher_map` must be held so no kv updates
+    /// are dispatched meanwhile
+    fn catch_up(
+        &self,
+        watcher: &mut Watcher,
+        watcher_map: &WatcherMap,
+        committed: i64,
+    ) -> Result<(), TrySendError<WatchEvent>> {
+        // the recent events always cover the revisions after the committed one
+        if !watcher_map.recent.covers(watcher.start_rev) {
+            self.replay(watcher, committed)?;
+        }
+        let events = watcher_map
+            .recent
+            .events_since(watcher.start_rev, watcher.key_ranges());
+        if events.is_empty() {
+            return Ok(());
+        }
+        let last_revision = get_last_revision(&events);
+        watcher.notify((last_revision, events))
+    }
+
     /// Handle KV store updates
+    ///
+    /// The committed events are received once and matched against the index of
+    /// the watchers, then pushed to the queues of the matched watchers. A
+    /// watcher whose queue is full is moved to the victims.
     fn handle_kv_updates(&self, (revision, all_events): (i64, Vec<Event>)) {
         self.watcher_map.map_write(|mut watcher_map_w| {
             let mut watcher_events: HashMap<WatchId, Vec<Event>> = HashMap::new();
-            for event in all_events {
-                let watch_ids = watcher_map_w
-                    .index
-                    .iter()
-                    .filter_map(|(k, v)| {
-                        k.contains_key(
-                            &event
-                                .kv
-                                .as_ref()
-                                .unwrap_or_else(|| panic!("Receive Event with empty kv"))
-                                .key,
-                        )
-                        .then_some(v)
-                    })
-                    .flatten()
-                    .copied()
-                    .collect_vec();
+            for event in &all_events {
+                let key = &event
+                    .kv
+                    .as_ref()
+                    .unwrap_or_else(|| panic!("Receive Event with empty kv"))
+                    .key;
+                // a key in several ranges of a watcher is notified to it once
+                let watch_ids = watcher_map_w.watchers_of_key(key).unique().collect_vec();
                 for watch_id in watch_ids {
                     watcher_events
                         .entry(watch_id)
@@ -526,6 +840,10 @@ impl KvWatcher {
                         .move_to_victim(watch_id, (watch_event.revision, watch_event.events));
                 }
             }
+            watcher_map_w
+                .recent
+                .push((revision, all_events), self.revision.get());
+            self.dispatched_revision.store(revision, Ordering::Release);
         });
     }
 }
@@ -540,6 +858,8 @@ pub(crate) struct WatchEvent {
     revision: i64,
     /// Compacted WatchEvent
     compacted: bool,
+    /// Keeps the event counted in the queue of its watcher until it's dropped
+    pending: Option<PendingEvent>,
 }
 
 impl std::fmt::Debug for WatchEvent {
@@ -574,6 +894,18 @@ impl WatchEvent {
     pub(crate) fn compacted(&self) -> bool {
         self.compacted
     }
+
+    /// Append the events of a later `WatchEvent` of the same watcher, the
+    /// `other` is given back if it belongs to another watcher or any of them is
+    /// compacted
+    pub(crate) fn merge(&mut self, mut other: WatchEvent) -> Result<(), WatchEvent> {
+        if self.compacted || other.compacted || self.id != other.id {
+            return Err(other);
+        }
+        self.events.append(&mut other.events);
+        self.revision = self.revision.max(other.revision);
+        Ok(())
+    }
 }
 
 /// Get the last revision of a event slice
@@ -601,10 +933,10 @@ mod test {
     use super::*;
     use crate::{
         header_gen::HeaderGenerator,
-        rpc::PutRequest,
+        rpc::{DeleteRangeRequest, PutRequest, Request as UniRequest, RequestOp, TxnRequest},
         storage::{
-            compact::COMPACT_CHANNEL_SIZE, db::DB, index::Index, lease_store::LeaseCollection,
-            KvStore,
+            compact::COMPACT_CHANNEL_SIZE, compression::ValueCompression, db::DB, index::Index,
+            lease_store::LeaseCollection, KvStore,
         },
     };
 
@@ -618,14 +950,17 @@ mod test {
         let kv_store_inner = Arc::new(KvStoreInner::new(index, db));
         let store = Arc::new(KvStore::new(
             Arc::clone(&kv_store_inner),
-            header_gen,
+            Arc::clone(&header_gen),
             kv_update_tx,
             compact_tx,
             lease_collection,
+            ValueCompression::default(),
+            0,
         ));
         let sync_victims_interval = Duration::from_millis(10);
         let kv_watcher = KvWatcher::new_arc(
             kv_store_inner,
+            header_gen.general_revision_arc(),
             kv_update_rx,
             sync_victims_interval,
             task_manager,
@@ -643,7 +978,7 @@ mod test {
         let stop_notify = Arc::new(event_listener::Event::new());
         kv_watcher.watch(
             123,
-            KeyRange::new_one_key("foo"),
+            vec![KeyRange::new_one_key("foo")],
             10,
             vec![],
             stop_notify,
@@ -688,13 +1023,14 @@ mod test {
     async fn test_victim() {
         let task_manager = Arc::new(TaskManager::new());
         let (store, kv_watcher) = init_empty_store(&task_manager);
-        // response channel with capacity 1, so it will be full easily, then we can trigger victim
+        // response channel with capacity 1, so the queue of the watcher will be
+        // full easily, then we can trigger victim
         let (event_tx, mut event_rx) = mpsc::channel(1);
         let stop_notify = Arc::new(event_listener::Event::new());
 
         kv_watcher.watch(
             123,
-            KeyRange::new_one_key("foo"),
+            vec![KeyRange::new_one_key("foo")],
             0,
             vec![],
             stop_notify,
@@ -708,14 +1044,14 @@ mod test {
                     let val = event.kv.as_ref().unwrap().value[0];
                     assert_eq!(val, expect);
                     expect += 1;
-                    if val == 99 {
+                    if val == 255 {
                         break 'outer;
                     }
                 }
             }
         });
 
-        for i in 0..100_u8 {
+        for i in 0..=255_u8 {
             put(store.as_ref(), "foo", vec![i]);
         }
         handle.await.unwrap();
@@ -732,7 +1068,7 @@ mod test {
         let stop_notify = Arc::new(event_listener::Event::new());
         kv_watcher.watch(
             1,
-            KeyRange::new_one_key("foo"),
+            vec![KeyRange::new_one_key("foo")],
             0,
             vec![],
             stop_notify,
@@ -747,19 +1083,485 @@ mod test {
         task_manager.shutdown(true).await;
     }
 
-    fn put(store: &KvStore, key: impl Into<Vec<u8>>, value: impl Into<Vec<u8>>) {
-        let req = RequestWrapper::from(PutRequest {
-            key: key.into(),
-            value: value.into(),
+    #[tokio::test(flavor = "multi_thread")]
+    #[abort_on_panic]
+    async fn watcher_should_replay_history_then_live_events_once() {
+        let task_manager = Arc::new(TaskManager::new());
+        let (store, kv_watcher) = init_empty_store(&task_manager);
+        for i in 0..3_u8 {
+            put(&store, "foo", vec![i]);
+        }
+        let (event_tx, mut event_rx) = mpsc::channel(128);
+        kv_watcher.watch(
+            1,
+            vec![KeyRange::new_one_key("foo")],
+            3,
+            vec![],
+            Arc::new(event_listener::Event::new()),
+            event_tx,
+        );
+        put(&store, "foo", vec![3]);
+
+        let mut revisions = vec![];
+        while revisions.len() < 3 {
+            let event = timeout(Duration::from_secs(3), event_rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            revisions.extend(
+                event
+                    .events
+                    .iter()
+                    .map(|e| e.kv.as_ref().unwrap().mod_revision),
+            );
+        }
+        assert_eq!(revisions, [3, 4, 5]);
+        assert_eq!(
+            kv_watcher
+                .watcher_map
+                .read()
+                .watchers
+                .get(&1)
+                .unwrap()
+                .start_rev,
+            6
+        );
+        assert!(timeout(Duration::from_millis(100), event_rx.recv())
+            .await
+            .is_err());
+        drop(store);
+        task_manager.shutdown(true).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[abort_on_panic]
+    async fn watcher_should_receive_dispatched_but_uncommitted_updates() {
+        let task_manager = Arc::new(TaskManager::new());
+        let (store, kv_watcher) = init_empty_store(&task_manager);
+        put(&store, "foo", vec![0]);
+        // revision 3 is dispatched before it is committed
+        kv_watcher.handle_kv_updates((
+            3,
+            vec![Event {
+                kv: Some(KeyValue {
+                    key: b"foo".to_vec(),
+                    mod_revision: 3,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+        ));
+
+        for (watch_id, start_rev, expected) in [(1, 0, vec![3]), (2, 2, vec![2, 3])] {
+            let (event_tx, mut event_rx) = mpsc::channel(128);
+            let revision = kv_watcher.watch(
+                watch_id,
+                vec![KeyRange::new_one_key("foo")],
+                start_rev,
+                vec![],
+                Arc::new(event_listener::Event::new()),
+                event_tx,
+            );
+            assert_eq!(revision, 2);
+            let mut revisions = vec![];
+            while revisions.len() < expected.len() {
+                let event = timeout(Duration::from_secs(3), event_rx.recv())
+                    .await
+                    .unwrap()
+                    .unwrap();
+                revisions.extend(
+                    event
+                        .events
+                        .iter()
+                        .map(|e| e.kv.as_ref().unwrap().mod_revision),
+                );
+            }
+            assert_eq!(revisions, expected);
+        }
+        drop(store);
+        task_manager.shutdown(true).await;
+    }
+
+    #[test]
+    fn recent_events_should_only_evict_committed_revisions() {
+        let event = |key: &str, mod_revision: i64| Event {
+            kv: Some(KeyValue {
+                key: key.into(),
+                mod_revision,
+                ..Default::default()
+            }),
             ..Default::default()
+        };
+        let revisions = |events: Vec<Event>| {
+            events
+                .iter()
+                .map(|e| e.kv.as_ref().unwrap().mod_revision)
+                .collect_vec()
+        };
+        let mut recent = RecentEvents::new(2, usize::MAX);
+        assert!(!recent.covers(2));
+        for revision in 2..5 {
+            recent.push(
+                (revision, vec![event("a", revision), event("b", revision)]),
+                1,
+            );
+        }
+        // none of them is committed
+        assert!(recent.covers(2));
+
+        recent.push((5, vec![event("a", 5)]), 3);
+        assert!(!recent.covers(3));
+        assert!(recent.covers(4));
+        assert_eq!(
+            revisions(recent.events_since(0, &[KeyRange::new("a", "c")])),
+            [4, 4, 5]
+        );
+        assert_eq!(
+            revisions(recent.events_since(5, &[KeyRange::new_one_key("a")])),
+            [5]
+        );
+
+        let mut recent = RecentEvents::new(usize::MAX, event("a", 2).encoded_len());
+        recent.push((2, vec![event("a", 2)]), 2);
+        recent.push((3, vec![event("a", 3)]), 2);
+        assert!(!recent.covers(2));
+        assert!(recent.covers(3));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[abort_on_panic]
+    async fn victim_should_be_canceled_when_its_history_is_compacted() {
+        let task_manager = Arc::new(TaskManager::new());
+        let (store, kv_watcher) = init_empty_store(&task_manager);
+        // capacity 1, so the watcher becomes a victim once its queue is full
+        let (event_tx, mut event_rx) = mpsc::channel(1);
+        kv_watcher.watch(
+            1,
+            vec![KeyRange::new_one_key("foo")],
+            0,
+            vec![],
+            Arc::new(event_listener::Event::new()),
+            event_tx,
+        );
+        let puts = WATCHER_QUEUE_CAPACITY + 8;
+        for _ in 0..puts {
+            put(&store, "foo", "bar");
+        }
+        sleep(Duration::from_millis(100)).await;
+        let last_revision = i64::try_from(puts).unwrap() + 1;
+        store.update_compacted_revision(last_revision);
+
+        let mut revision = 2;
+        loop {
+            let event = timeout(Duration::from_secs(3), event_rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            if event.compacted() {
+                break;
+            }
+            assert_eq!(event.revision(), revision);
+            revision += 1;
+        }
+        // the revisions after the victim were not sent before they were compacted
+        assert!(revision <= last_revision);
+        assert!(!kv_watcher.is_synced(1));
+        drop(store);
+        task_manager.shutdown(true).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[abort_on_panic]
+    async fn events_should_carry_prev_kv_captured_in_sync() {
+        let task_manager = Arc::new(TaskManager::new());
+        let (store, kv_watcher) = init_empty_store(&task_manager);
+        put(&store, "foo", "bar");
+        let (event_tx, mut event_rx) = mpsc::channel(128);
+        let prev_kv = kv_watcher.register_prev_kv();
+        kv_watcher.watch(
+            1,
+            vec![KeyRange::new_one_key("foo")],
+            0,
+            vec![],
+            Arc::new(event_listener::Event::new()),
+            event_tx,
+        );
+        put(&store, "foo", "baz");
+        delete(&store, "foo");
+        put(&store, "foo", "qux");
+        // no previous kv is captured once no watcher asks for it
+        drop(prev_kv);
+        put(&store, "foo", "quux");
+
+        let mut prev_values = vec![];
+        while prev_values.len() < 4 {
+            let event = timeout(Duration::from_secs(3), event_rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            prev_values.extend(
+                event
+                    .events
+                    .into_iter()
+                    .map(|e| e.prev_kv.map(|kv| kv.value)),
+            );
+        }
+        assert_eq!(
+            prev_values,
+            [Some(b"bar".to_vec()), Some(b"baz".to_vec()), None, None]
+        );
+        drop(store);
+        task_manager.shutdown(true).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[abort_on_panic]
+    async fn replay_should_only_send_events_not_sent_yet() {
+        let task_manager = Arc::new(TaskManager::new());
+        let (store, kv_watcher) = init_empty_store(&task_manager);
+        put(&store, "foo", "1");
+        put(&store, "foo", "2");
+        let (event_tx, mut event_rx) = mpsc::channel(128);
+        let mut watcher = Watcher::new(
+            vec![KeyRange::new_one_key("foo")],
+            1,
+            2,
+            vec![],
+            Arc::new(event_listener::Event::new()),
+            event_tx,
+            false,
+        );
+        kv_watcher
+            .replay(&mut watcher, kv_watcher.revision.get())
+            .unwrap();
+        put(&store, "foo", "3");
+        kv_watcher
+            .replay(&mut watcher, kv_watcher.revision.get())
+            .unwrap();
+        kv_watcher
+            .replay(&mut watcher, kv_watcher.revision.get())
+            .unwrap();
+
+        let revisions: Vec<_> = [event_rx.recv().await, event_rx.recv().await]
+            .into_iter()
+            .map(|e| e.unwrap().revision())
+            .collect();
+        assert_eq!(revisions, [3, 4]);
+        assert!(event_rx.try_recv().is_err());
+        drop(store);
+        task_manager.shutdown(true).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[abort_on_panic]
+    async fn revision_should_not_be_split_across_ranges() {
+        let task_manager = Arc::new(TaskManager::new());
+        let (store, kv_watcher) = init_empty_store(&task_manager);
+        let key_ranges = vec![
+            KeyRange::new_one_key("a"),
+            KeyRange::new("a", "b"),
+            KeyRange::new("x", "z"),
+        ];
+        let stop_notify = Arc::new(event_listener::Event::new());
+        let (event_tx, mut event_rx) = mpsc::channel(128);
+        kv_watcher.watch(
+            1,
+            key_ranges.clone(),
+            0,
+            vec![],
+            Arc::clone(&stop_notify),
+            event_tx,
+        );
+        put(&store, "m", "1");
+        let txn = RequestWrapper::from(TxnRequest {
+            compare: vec![],
+            success: ["a", "m", "y"]
+                .into_iter()
+                .map(|key| RequestOp {
+                    request: Some(UniRequest::RequestPut(PutRequest {
+                        key: key.into(),
+                        value: "2".into(),
+                        ..Default::default()
+                    })),
+                })
+                .collect(),
+            failure: vec![],
         });
+        exe_and_flush(&store, &txn);
+        let keys = |event: WatchEvent| {
+            event
+                .events
+                .into_iter()
+                .map(|e| e.kv.unwrap().key)
+                .collect_vec()
+        };
+        let event = timeout(Duration::from_secs(3), event_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.revision(), 3);
+        assert_eq!(keys(event), [b"a".to_vec(), b"y".to_vec()]);
+
+        // the history of the ranges is replayed in one event as well
+        let (event_tx, mut event_rx) = mpsc::channel(128);
+        kv_watcher.watch(2, key_ranges, 3, vec![], stop_notify, event_tx);
+        let event = timeout(Duration::from_secs(3), event_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.revision(), 3);
+        assert_eq!(keys(event), [b"a".to_vec(), b"y".to_vec()]);
+        drop(store);
+        task_manager.shutdown(true).await;
+    }
+
+    #[tokio::test]
+    async fn watcher_map_should_find_watchers_by_key() {
+        let (event_tx, _event_rx) = mpsc::channel(1);
+        let mut watcher_map = WatcherMap::new();
+        let ranges = [
+            KeyRange::new_one_key("foo"),
+            KeyRange::new("foo", KeyRange::get_prefix("foo")),
+            KeyRange::new("foo", vec![0]),
+            KeyRange::new(vec![0], vec![0]),
+            KeyRange::new("b", "a"),
+        ];
+        for (id, key_range) in (0..).zip(ranges) {
+            watcher_map.register(Watcher::new(
+                vec![key_range],
+                id,
+                0,
+                vec![],
+                Arc::new(event_listener::Event::new()),
+                event_tx.clone(),
+                false,
+            ));
+        }
+        let find = |key: &str| {
+            watcher_map
+                .watchers_of_key(key.as_bytes())
+                .sorted()
+                .collect_vec()
+        };
+        assert_eq!(find("foo"), [0, 1, 2, 3]);
+        assert_eq!(find("foobar"), [1, 2, 3]);
+        assert_eq!(find("fop"), [2, 3]);
+        assert_eq!(find("a"), [3]);
+
+        for id in 0..5 {
+            watcher_map.remove(id);
+        }
+        assert!(watcher_map.index.is_empty());
+        assert!(watcher_map.watchers.is_empty());
+    }
+
+    #[test]
+    fn merge_should_append_events_of_the_same_watcher() {
+        let watch_event = |id: WatchId, revision: i64, compacted: bool| WatchEvent {
+            id,
+            events: vec![Event {
+                kv: Some(KeyValue {
+                    mod_revision: revision,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+            revision,
+            compacted,
+            pending: None,
+        };
+        let mut batched = watch_event(1, 2, false);
+        assert!(batched.merge(watch_event(1, 3, false)).is_ok());
+        assert_eq!(batched.revision(), 3);
+        assert_eq!(get_last_revision(&batched.events), 3);
+        assert_eq!(batched.events.len(), 2);
+
+        assert!(batched.merge(watch_event(2, 4, false)).is_err());
+        assert!(batched.merge(watch_event(1, 4, true)).is_err());
+        assert_eq!(batched.events.len(), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[abort_on_panic]
+    async fn watcher_should_drop_filtered_events() {
+        let task_manager = Arc::new(TaskManager::new());
+        let (store, kv_watcher) = init_empty_store(&task_manager);
+        let stop_notify = Arc::new(event_listener::Event::new());
+        let (no_put_tx, mut no_put_rx) = mpsc::channel(128);
+        let (no_delete_tx, mut no_delete_rx) = mpsc::channel(128);
+        let (unknown_tx, mut unknown_rx) = mpsc::channel(128);
+        for (id, filters, event_tx) in [
+            (1, vec![i32::from(FilterType::Noput)], no_put_tx),
+            (2, vec![i32::from(FilterType::Nodelete)], no_delete_tx),
+            (3, vec![42], unknown_tx),
+        ] {
+            kv_watcher.watch(
+                id,
+                vec![KeyRange::new_one_key("foo")],
+                0,
+                filters,
+                Arc::clone(&stop_notify),
+                event_tx,
+            );
+        }
+        put(&store, "foo", "bar");
+        delete(&store, "foo");
+        put(&store, "foo", "baz");
+
+        let no_put = recv_event_types(&mut no_put_rx, 1).await;
+        let no_delete = recv_event_types(&mut no_delete_rx, 2).await;
+        let unknown = recv_event_types(&mut unknown_rx, 3).await;
+        assert_eq!(no_put, vec![EventType::Delete]);
+        assert_eq!(no_delete, vec![EventType::Put, EventType::Put]);
+        assert_eq!(
+            unknown,
+            vec![EventType::Put, EventType::Delete, EventType::Put]
+        );
+        drop(store);
+        task_manager.shutdown(true).await;
+    }
+
+    async fn recv_event_types(rx: &mut mpsc::Receiver<WatchEvent>, n: usize) -> Vec<EventType> {
+        let mut types = vec![];
+        while types.len() < n {
+            let event = timeout(Duration::from_secs(3), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            types.extend(event.events.iter().map(Event::r#type));
+        }
+        types
+    }
+
+    fn delete(store: &KvStore, key: impl Into<Vec<u8>>) {
+        exe_and_flush(
+            store,
+            &RequestWrapper::from(DeleteRangeRequest {
+                key: key.into(),
+                ..Default::default()
+            }),
+        );
+    }
+
+    fn put(store: &KvStore, key: impl Into<Vec<u8>>, value: impl Into<Vec<u8>>) {
+        exe_and_flush(
+            store,
+            &RequestWrapper::from(PutRequest {
+                key: key.into(),
+                value: value.into(),
+                ..Default::default()
+            }),
+        );
+    }
+
+    fn exe_and_flush(store: &KvStore, req: &RequestWrapper) {
         let txn = store.db().transaction();
         let index = store.index();
         let index_state = index.state();
         let rev_gen = store.revision_gen();
         let rev_gen_state = rev_gen.state();
         store
-            .after_sync(&req, &txn, &index_state, &rev_gen_state, false)
+            .after_sync(req, &txn, &index_state, &rev_gen_state, false)
             .unwrap();
         txn.commit().unwrap();
         index_state.commit();
@@ -4,6 +4,8 @@ use std::{
     time::{Duration, Instant},
 };
 
+use itertools::Itertools;
+
 /// Lease
 #[derive(Debug, Clone)]
 pub(crate) struct Lease {
@@ -31,9 +33,10 @@ impl Lease {
         }
     }
 
-    /// Return keys of lease
+    /// Return keys of lease in ascending order, so that every member handles
+    /// them in the same order
     pub(crate) fn keys(&self) -> Vec<Vec<u8>> {
-        self.keys_set.iter().cloned().collect()
+        self.keys_set.iter().cloned().sorted().collect()
     }
 
     /// Convert into keys
@@ -74,6 +77,17 @@ impl Lease {
         }
     }
 
+    /// Set the remaining ttl checkpointed through consensus, a zero remaining
+    /// ttl clears the checkpoint
+    pub(crate) fn checkpoint(&mut self, remaining_ttl: Duration) {
+        self.remaining_ttl = remaining_ttl;
+    }
+
+    /// Check if the remaining ttl of the lease is checkpointed
+    pub(crate) fn has_checkpoint(&self) -> bool {
+        self.remaining_ttl > Duration::from_secs(0)
+    }
+
     /// Refresh expiry and return new expiry
     pub(crate) fn refresh(&mut self, extend: Duration) -> Instant {
         let new_expiry = Instant::now().add(extend).add(self.remaining_ttl());
@@ -1,26 +1,42 @@
 use std::{
     collections::{BTreeMap, HashMap},
-    ops::RangeBounds,
+    ops::{Add, RangeBounds},
     time::{Duration, Instant},
 };
 
 use clippy_utilities::NumericCast;
+use event_listener::{Event, EventListener};
 use itertools::Itertools;
 use parking_lot::RwLock;
 use utils::parking_lot_lock::RwLockMap;
 use xlineapi::execute_error::ExecuteError;
 
 use super::{lease_queue::LeaseQueue, Lease};
-use crate::rpc::PbLease;
+use crate::rpc::{LeaseCheckpoint, PbLease};
+
+/// Interval to retry revoking an expired lease, the revocation of an expired
+/// lease may fail, e.g. the proposal is dropped during a leader change, the
+/// lease will be found expired again after this interval if it still exists
+const EXPIRED_LEASE_RETRY_INTERVAL: Duration = Duration::from_secs(3);
 
 /// Collection of lease related data
+///
+/// It is shared by the kv, lease and auth stores. The key to lease index is
+/// guarded by its own lock so that the lookups on the kv path do not contend
+/// with lease renewals and the expiry scan. When both locks are needed,
+/// `inner` is always locked before `item_map`.
 #[derive(Debug)]
-#[cfg_attr(test, derive(Default))]
 pub(crate) struct LeaseCollection {
     /// Inner data of `LeaseCollection`
     inner: RwLock<LeaseCollectionInner>,
+    /// key to lease id
+    item_map: RwLock<BTreeMap<Vec<u8>, i64>>,
     /// Min lease ttl
     min_ttl: i64,
+    /// Max lease ttl
+    max_ttl: i64,
+    /// Notified when the nearest expiry may become earlier
+    expiry_event: Event,
 }
 
 #[derive(Debug)]
@@ -29,8 +45,6 @@ pub(crate) struct LeaseCollection {
 struct LeaseCollectionInner {
     /// lease id to lease
     lease_map: HashMap<i64, Lease>,
-    /// key to lease id
-    item_map: BTreeMap<Vec<u8>, i64>,
     /// lease queue
     expired_queue: LeaseQueue,
 }
@@ -41,19 +55,46 @@ impl LeaseCollection {
         Self {
             inner: RwLock::new(LeaseCollectionInner {
                 lease_map: HashMap::new(),
-                item_map: BTreeMap::new(),
                 expired_queue: LeaseQueue::new(),
             }),
+            item_map: RwLock::new(BTreeMap::new()),
             min_ttl,
+            max_ttl: i64::MAX,
+            expiry_event: Event::new(),
         }
     }
 
+    /// Set the max lease ttl, it is never less than the min lease ttl
+    pub(crate) fn with_max_ttl(mut self, max_ttl: i64) -> Self {
+        self.max_ttl = max_ttl.max(self.min_ttl);
+        self
+    }
+
+    /// Get the ttl of a lease granted with the requested ttl
+    pub(crate) fn granted_ttl(&self, ttl: i64) -> i64 {
+        ttl.clamp(self.min_ttl, self.max_ttl)
+    }
+
+    /// Get the nearest expiry of the leases
+    pub(crate) fn next_expiry(&self) -> Option<Instant> {
+        self.inner.read().expired_queue.peek().copied()
+    }
+
+    /// Listen to the changes that may make the nearest expiry earlier
+    pub(crate) fn listen_expiry(&self) -> EventListener {
+        self.expiry_event.listen()
+    }
+
     /// Find expired leases
+    ///
+    /// The expired leases are kept in the queue with a retry deadline until
+    /// they are revoked, revoked leases are removed from the queue lazily.
     pub(crate) fn find_expired_leases(&self) -> Vec<i64> {
         let mut expired_leases = vec![];
         let mut inner = self.inner.write();
+        let now = Instant::now();
         while let Some(expiry) = inner.expired_queue.peek() {
-            if *expiry <= Instant::now() {
+            if *expiry <= now {
                 #[allow(clippy::unwrap_used)] // queue.peek() returns Some
                 let id = inner.expired_queue.pop().unwrap();
                 if inner.lease_map.contains_key(&id) {
@@ -63,10 +104,15 @@ impl LeaseCollection {
                 break;
             }
         }
+        let retry_at = now.add(EXPIRED_LEASE_RETRY_INTERVAL);
+        for &id in &expired_leases {
+            let _ignore = inner.expired_queue.insert(id, retry_at);
+        }
         expired_leases
     }
 
-    /// Renew lease
+    /// Renew lease, the lease restarts from its ttl so a checkpointed remaining
+    /// ttl is cleared
     pub(crate) fn renew(&self, lease_id: i64) -> Result<i64, ExecuteError> {
         let mut inner = self.inner.write();
         let (expiry, ttl) = {
@@ -76,6 +122,7 @@ impl LeaseCollection {
             if lease.expired() {
                 return Err(ExecuteError::LeaseExpired(lease_id));
             }
+            lease.checkpoint(Duration::ZERO);
             let expiry = lease.refresh(Duration::default());
             let ttl = lease.ttl().as_secs().numeric_cast();
             (expiry, ttl)
@@ -91,7 +138,7 @@ impl LeaseCollection {
             return Err(ExecuteError::LeaseNotFound(lease_id));
         };
         lease.insert_key(key.clone());
-        let _ignore = inner.item_map.insert(key, lease_id);
+        let _ignore = self.item_map.write().insert(key, lease_id);
         Ok(())
     }
 
@@ -102,13 +149,13 @@ impl LeaseCollection {
             return Err(ExecuteError::LeaseNotFound(lease_id));
         };
         lease.remove_key(key);
-        let _ignore = inner.item_map.remove(key);
+        let _ignore = self.item_map.write().remove(key);
         Ok(())
     }
 
     /// Get lease id by given key
     pub(crate) fn get_lease(&self, key: &[u8]) -> i64 {
-        self.inner.read().item_map.get(key).copied().unwrap_or(0)
+        self.item_map.read().get(key).copied().unwrap_or(0)
     }
 
     /// Get lease id by given key
@@ -116,9 +163,8 @@ impl LeaseCollection {
     where
         R: RangeBounds<Vec<u8>>,
     {
-        self.inner
+        self.item_map
             .read()
-            .item_map
             .range(range)
             .map(|(_, lease)| *lease)
             .collect()
@@ -149,7 +195,22 @@ impl LeaseCollection {
 
     /// Grant a lease
     pub(crate) fn grant(&self, lease_id: i64, ttl: i64, is_leader: bool) -> PbLease {
-        let mut lease = Lease::new(lease_id, ttl.max(self.min_ttl).numeric_cast());
+        let lease = Lease::new(lease_id, self.granted_ttl(ttl).numeric_cast());
+        self.insert(lease, is_leader)
+    }
+
+    /// Restore a persisted lease, along with its checkpointed remaining ttl
+    pub(crate) fn restore(&self, lease: &PbLease, is_leader: bool) {
+        let mut restored = Lease::new(lease.id, self.granted_ttl(lease.ttl).numeric_cast());
+        if lease.remaining_ttl > 0 && lease.remaining_ttl < lease.ttl {
+            restored.checkpoint(Duration::from_secs(lease.remaining_ttl.numeric_cast()));
+        }
+        let _ignore = self.insert(restored, is_leader);
+    }
+
+    /// Insert a lease, it is scheduled to expire on the leader
+    fn insert(&self, mut lease: Lease, is_leader: bool) -> PbLease {
+        let lease_id = lease.id();
         self.inner.map_write(|mut inner| {
             if is_leader {
                 let expiry = lease.refresh(Duration::ZERO);
@@ -159,6 +220,9 @@ impl LeaseCollection {
             }
             let _ignore = inner.lease_map.insert(lease_id, lease.clone());
         });
+        if is_leader {
+            let _ignore = self.expiry_event.notify(usize::MAX);
+        }
         PbLease {
             id: lease.id(),
             ttl: lease.ttl().as_secs().numeric_cast(),
@@ -166,11 +230,61 @@ impl LeaseCollection {
         }
     }
 
+    /// Checkpoint the remaining ttl of a lease, returns the lease to persist
+    ///
+    /// The expiry is left unchanged, the remaining ttl takes effect when the
+    /// lease is scheduled again, i.e. when a node is promoted or recovers.
+    pub(crate) fn checkpoint(&self, lease_id: i64, remaining_ttl: i64) -> Option<PbLease> {
+        let mut inner = self.inner.write();
+        let lease = inner.lease_map.get_mut(&lease_id)?;
+        let remaining_ttl = remaining_ttl.clamp(0, lease.ttl().as_secs().numeric_cast());
+        lease.checkpoint(Duration::from_secs(remaining_ttl.numeric_cast()));
+        Some(PbLease {
+            id: lease_id,
+            ttl: lease.ttl().as_secs().numeric_cast(),
+            remaining_ttl,
+        })
+    }
+
+    /// Get the remaining ttls of the leases scheduled to expire, the leases
+    /// whose remaining ttls have not changed since the last checkpoint are
+    /// skipped
+    pub(crate) fn checkpoints(&self) -> Vec<LeaseCheckpoint> {
+        self.inner
+            .read()
+            .lease_map
+            .values()
+            .filter(|l| !l.expired())
+            .filter_map(|l| {
+                // a zero remaining ttl clears the checkpoint, so it is rounded up
+                let remaining = l
+                    .remaining()
+                    .saturating_add(Duration::from_nanos(999_999_999))
+                    .as_secs()
+                    .max(1);
+                let changed = remaining != l.remaining_ttl().as_secs();
+                (remaining < l.ttl().as_secs() && changed).then(|| LeaseCheckpoint {
+                    id: l.id(),
+                    remaining_ttl: remaining.numeric_cast(),
+                })
+            })
+            .sorted_by_key(|cp| cp.id)
+            .collect()
+    }
+
     /// Revokes a lease
     pub(crate) fn revoke(&self, lease_id: i64) -> Option<Lease> {
         self.inner.write().lease_map.remove(&lease_id)
     }
 
+    /// Remove all leases and the keys attached to them
+    pub(crate) fn clear(&self) {
+        let mut inner = self.inner.write();
+        inner.lease_map.clear();
+        inner.expired_queue.clear();
+        self.item_map.write().clear();
+    }
+
     /// Demote current node
     pub(crate) fn demote(&self) {
         let mut inner = self.inner.write();
@@ -189,6 +303,8 @@ impl LeaseCollection {
         for (lease_id, expiry) in pairs {
             let _ignore = inner.expired_queue.insert(lease_id, expiry);
         }
+        drop(inner);
+        let _ignore = self.expiry_event.notify(usize::MAX);
     }
 }
 
@@ -203,4 +319,114 @@ mod test {
         assert!(l.is_some());
         assert_eq!(l.unwrap().ttl(), Duration::from_secs(3));
     }
+
+    #[test]
+    fn test_grant_more_than_max_ttl() {
+        let c = LeaseCollection::new(3).with_max_ttl(60);
+        assert_eq!(c.granted_ttl(2), 3);
+        assert_eq!(c.granted_ttl(10), 10);
+        assert_eq!(c.granted_ttl(100), 60);
+        let lease = c.grant(1, 100, false);
+        assert_eq!(lease.ttl, 60);
+        assert_eq!(c.look_up(1).unwrap().ttl(), Duration::from_secs(60));
+
+        // the min ttl takes precedence
+        let c = LeaseCollection::new(3).with_max_ttl(1);
+        assert_eq!(c.granted_ttl(100), 3);
+    }
+
+    #[tokio::test]
+    async fn test_grant_should_notify_expiry_listeners() {
+        let c = LeaseCollection::new(0);
+        let listener = c.listen_expiry();
+        let _lease = c.grant(1, 10, false);
+        assert!(c.next_expiry().is_none());
+        let _lease = c.grant(2, 10, true);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(10), listener)
+                .await
+                .is_ok(),
+            "granting a lease on the leader should notify the listeners"
+        );
+        assert!(c
+            .next_expiry()
+            .is_some_and(|expiry| expiry > Instant::now()));
+    }
+
+    #[test]
+    fn test_expired_lease_should_be_found_again_until_revoked() {
+        let c = LeaseCollection::new(0);
+        let _lease = c.grant(1, 0, true);
+        assert_eq!(c.find_expired_leases(), vec![1]);
+        // the retry deadline has not been reached
+        assert!(c.find_expired_leases().is_empty());
+        assert!(c.inner.read().expired_queue.peek().is_some());
+
+        // retry the revocation once the deadline is reached
+        let _ignore = c.inner.write().expired_queue.update(1, Instant::now());
+        assert_eq!(c.find_expired_leases(), vec![1]);
+
+        let _ignore = c.revoke(1);
+        let _ignore = c.inner.write().expired_queue.update(1, Instant::now());
+        assert!(c.find_expired_leases().is_empty());
+        assert!(c.inner.read().expired_queue.peek().is_none());
+    }
+
+    #[test]
+    fn test_key_lookup_should_not_wait_for_lease_lock() {
+        let c = LeaseCollection::new(0);
+        let _pb = c.grant(1, 60, true);
+        c.attach(1, b"foo".to_vec()).unwrap();
+
+        let _guard = c.inner.write();
+        assert_eq!(c.get_lease(b"foo"), 1);
+        assert_eq!(c.get_lease(b"bar"), 0);
+        assert_eq!(c.get_lease_by_range(b"a".to_vec()..b"z".to_vec()), vec![1]);
+    }
+
+    #[test]
+    fn test_checkpoints_should_skip_unchanged_leases() {
+        let c = LeaseCollection::new(0);
+        let _pb = c.grant(1, 60, true);
+        let _pb = c.grant(2, 60, false);
+        // no time has elapsed since the grant, and the leases on the followers
+        // are not scheduled
+        assert!(c.checkpoints().is_empty());
+
+        {
+            let mut inner = c.inner.write();
+            let lease = inner.lease_map.get_mut(&1).unwrap();
+            lease.checkpoint(Duration::from_secs(30));
+            let _expiry = lease.refresh(Duration::ZERO);
+        }
+        assert!(c.checkpoints().is_empty());
+        let pb = c.checkpoint(1, 0).unwrap();
+        assert_eq!(pb.remaining_ttl, 0);
+        assert_eq!(
+            c.checkpoints(),
+            vec![LeaseCheckpoint {
+                id: 1,
+                remaining_ttl: 30
+            }]
+        );
+        assert!(c.checkpoint(3, 30).is_none());
+    }
+
+    #[test]
+    fn test_only_leader_should_expire_leases() {
+        let c = LeaseCollection::new(0);
+        let _pb = c.grant(1, 1, false);
+        assert!(c.next_expiry().is_none());
+
+        let extend = Duration::from_secs(10);
+        c.promote(extend);
+        assert!(c
+            .next_expiry()
+            .is_some_and(|expiry| expiry > Instant::now().add(extend)));
+        assert!(c.find_expired_leases().is_empty());
+
+        c.demote();
+        assert!(c.next_expiry().is_none());
+        assert!(c.look_up(1).is_some_and(|l| !l.expired()));
+    }
 }
@@ -13,11 +13,11 @@ use std::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use clippy_utilities::OverflowArithmetic;
-use engine::TransactionApi;
+use event_listener::EventListener;
 use log::debug;
 use parking_lot::RwLock;
 use prost::Message;
@@ -31,15 +31,14 @@ pub(crate) use self::{lease::Lease, lease_collection::LeaseCollection};
 use super::{
     db::{WriteOp, DB},
     index::IndexOperate,
-    storage_api::XlineStorageOps,
 };
 use crate::{
     header_gen::HeaderGenerator,
     revision_number::RevisionNumberGeneratorState,
     rpc::{
-        Event, LeaseGrantRequest, LeaseGrantResponse, LeaseLeasesRequest, LeaseLeasesResponse,
-        LeaseRevokeRequest, LeaseRevokeResponse, LeaseStatus, PbLease, RequestWrapper,
-        ResponseHeader, ResponseWrapper,
+        Event, LeaseCheckpoint, LeaseCheckpointRequest, LeaseCheckpointResponse, LeaseGrantRequest,
+        LeaseGrantResponse, LeaseLeasesRequest, LeaseLeasesResponse, LeaseRevokeRequest,
+        LeaseRevokeResponse, LeaseStatus, PbLease, RequestWrapper, ResponseHeader, ResponseWrapper,
     },
     storage::KvStore,
 };
@@ -96,26 +95,23 @@ impl LeaseStore {
     }
 
     /// sync a lease request
-    pub(crate) fn after_sync<T, I>(
+    pub(crate) fn after_sync<I>(
         &self,
         request: &RequestWrapper,
         revision_gen: &RevisionNumberGeneratorState<'_>,
-        txn_db: &T,
         index: &I,
     ) -> Result<(SyncResponse, Vec<WriteOp>), ExecuteError>
     where
-        T: XlineStorageOps + TransactionApi,
         I: IndexOperate,
     {
         let next_revision = revision_gen.get().overflow_add(1);
-        let updated = self.sync_request(request, next_revision, txn_db, index)?;
+        let (updated, ops) = self.sync_request(request, next_revision, index)?;
         let rev = if updated {
             revision_gen.next()
         } else {
             revision_gen.get()
         };
-        // TODO: return only a `SyncResponse`
-        Ok((SyncResponse::new(rev), vec![]))
+        Ok((SyncResponse::new(rev), ops))
     }
 
     /// Get lease by id
@@ -133,6 +129,21 @@ impl LeaseStore {
         self.lease_collection.find_expired_leases()
     }
 
+    /// Get the nearest expiry of the leases
+    pub(crate) fn next_expiry(&self) -> Option<Instant> {
+        self.lease_collection.next_expiry()
+    }
+
+    /// Listen to the changes that may make the nearest expiry earlier
+    pub(crate) fn listen_expiry(&self) -> EventListener {
+        self.lease_collection.listen_expiry()
+    }
+
+    /// Get the remaining ttls of the leases to checkpoint
+    pub(crate) fn checkpoints(&self) -> Vec<LeaseCheckpoint> {
+        self.lease_collection.checkpoints()
+    }
+
     /// Keep alive a lease
     pub(crate) fn keep_alive(&self, lease_id: i64) -> Result<i64, ExecuteError> {
         self.lease_collection.renew(lease_id)
@@ -143,10 +154,13 @@ impl LeaseStore {
         self.header_gen.gen_header()
     }
 
-    /// Demote current node
+    /// Demote current node, the lease ids reserved by the requests this node
+    /// executed speculatively are released, as those requests may never be
+    /// synced once the node is no longer the leader
     pub(crate) fn demote(&self) {
         self.lease_collection.demote();
         self.is_primary.store(false, Ordering::Release);
+        self.release_unsynced();
     }
 
     /// Promote current node
@@ -155,15 +169,33 @@ impl LeaseStore {
         self.lease_collection.promote(extend);
     }
 
-    /// Recover data form persistent storage
+    /// Recover data form persistent storage, the leases in memory are
+    /// replaced and the keys are attached again when the kv store recovers
+    ///
+    /// The deadlines of leases are not persisted, so the recovered leases
+    /// restart from their last checkpointed remaining ttl, or from their ttl
+    /// if they are not checkpointed. On the leader they are scheduled to
+    /// expire, otherwise they would never be revoked while this node stays
+    /// the leader. A lease is revoked by the expiry task of the leader once
+    /// it expires, whether any key is still attached to it or not, and a
+    /// lease without keys is kept until then as keys may be put with it
+    /// later.
     pub(crate) fn recover(&self) -> Result<(), ExecuteError> {
         let leases = self.get_all()?;
+        let is_primary = self.is_primary();
+        self.lease_collection.clear();
+        self.release_unsynced();
         for lease in leases {
-            let _ignore = self.lease_collection.grant(lease.id, lease.ttl, false);
+            self.lease_collection.restore(&lease, is_primary);
         }
         Ok(())
     }
 
+    /// Check if a lease exists
+    pub(crate) fn contains_lease(&self, lease_id: i64) -> bool {
+        self.lease_collection.contains_lease(lease_id)
+    }
+
     /// Check whether the current lease storage is primary or not
     pub(crate) fn is_primary(&self) -> bool {
         self.is_primary.load(Ordering::Relaxed)
@@ -184,6 +216,12 @@ impl LeaseStore {
         let _ignore = self.sync_event.notify(usize::MAX);
     }
 
+    /// Release the lease ids of all unsynced requests
+    fn release_unsynced(&self) {
+        self.unsynced_cache.write().clear();
+        let _ignore = self.sync_event.notify(usize::MAX);
+    }
+
     /// Wait for the lease id to be removed from the cache
     pub(crate) async fn wait_synced(&self, lease_id: i64) {
         loop {
@@ -218,6 +256,13 @@ impl LeaseStore {
                 debug!("Receive LeaseLeasesRequest {:?}", req);
                 Ok(self.handle_lease_leases_request(req).into())
             }
+            RequestWrapper::LeaseCheckpointRequest(ref req) => {
+                debug!("Receive LeaseCheckpointRequest {:?}", req);
+                Ok(LeaseCheckpointResponse {
+                    header: Some(self.header_gen.gen_header()),
+                }
+                .into())
+            }
             _ => unreachable!("Other request should not be sent to this store"),
         };
         res
@@ -234,16 +279,18 @@ impl LeaseStore {
         if req.ttl > MAX_LEASE_TTL {
             return Err(ExecuteError::LeaseTtlTooLarge(req.ttl));
         }
-        if self.lease_collection.contains_lease(req.id) {
+        // a lease granted by a concurrent request is not in the collection until
+        // it is synced
+        if self.lease_collection.contains_lease(req.id)
+            || !self.unsynced_cache.write().insert(req.id)
+        {
             return Err(ExecuteError::LeaseAlreadyExists(req.id));
         }
 
-        _ = self.unsynced_cache.write().insert(req.id);
-
         Ok(LeaseGrantResponse {
             header: Some(self.header_gen.gen_header()),
             id: req.id,
-            ttl: req.ttl,
+            ttl: self.lease_collection.granted_ttl(req.ttl),
             error: String::new(),
         })
     }
@@ -264,11 +311,13 @@ impl LeaseStore {
         }
     }
 
-    /// Handle `LeaseRevokeRequest`
+    /// Handle `LeaseLeasesRequest`, expired leases that are waiting to be
+    /// revoked are not listed
     fn handle_lease_leases_request(&self, _req: LeaseLeasesRequest) -> LeaseLeasesResponse {
         let leases = self
             .leases()
             .into_iter()
+            .filter(|lease| !lease.expired())
             .map(|lease| LeaseStatus { id: lease.id() })
             .collect();
 
@@ -278,48 +327,59 @@ impl LeaseStore {
         }
     }
 
-    /// Sync `RequestWithToken`
-    fn sync_request<T, I>(
+    /// Sync `RequestWithToken`, return whether the kv store is updated and
+    /// the write operations to flush
+    fn sync_request<I>(
         &self,
         wrapper: &RequestWrapper,
         revision: i64,
-        txn_db: &T,
         index: &I,
-    ) -> Result<bool, ExecuteError>
+    ) -> Result<(bool, Vec<WriteOp>), ExecuteError>
     where
-        T: XlineStorageOps + TransactionApi,
         I: IndexOperate,
     {
         #[allow(clippy::wildcard_enum_match_arm)]
-        let updated = match *wrapper {
+        let res = match *wrapper {
             RequestWrapper::LeaseGrantRequest(ref req) => {
                 debug!("Sync LeaseGrantRequest {:?}", req);
-                self.sync_lease_grant_request(req, txn_db)?;
-                false
+                (false, vec![self.sync_lease_grant_request(req)?])
             }
             RequestWrapper::LeaseRevokeRequest(ref req) => {
                 debug!("Sync LeaseRevokeRequest {:?}", req);
-                self.sync_lease_revoke_request(req, revision, txn_db, index)?
+                self.sync_lease_revoke_request(req, revision, index)?
             }
             RequestWrapper::LeaseLeasesRequest(ref req) => {
                 debug!("Sync LeaseLeasesRequest {:?}", req);
-                false
+                (false, vec![])
+            }
+            RequestWrapper::LeaseCheckpointRequest(ref req) => {
+                debug!("Sync LeaseCheckpointRequest {:?}", req);
+                (false, self.sync_lease_checkpoint_request(req))
             }
             _ => unreachable!("Other request should not be sent to this store"),
         };
-        Ok(updated)
+        Ok(res)
     }
 
-    /// Sync `LeaseGrantRequest`
-    fn sync_lease_grant_request<T: XlineStorageOps>(
-        &self,
-        req: &LeaseGrantRequest,
-        txn_db: &T,
-    ) -> Result<(), ExecuteError> {
+    /// Sync `LeaseGrantRequest`, an existing lease is never overwritten
+    fn sync_lease_grant_request(&self, req: &LeaseGrantRequest) -> Result<WriteOp, ExecuteError> {
+        if self.lease_collection.contains_lease(req.id) {
+            return Err(ExecuteError::LeaseAlreadyExists(req.id));
+        }
         let lease = self
             .lease_collection
             .grant(req.id, req.ttl, self.is_primary());
-        txn_db.write_op(WriteOp::PutLease(lease))
+        Ok(WriteOp::PutLease(lease))
+    }
+
+    /// Sync `LeaseCheckpointRequest`, the checkpoints of the leases that have
+    /// been revoked are ignored
+    fn sync_lease_checkpoint_request(&self, req: &LeaseCheckpointRequest) -> Vec<WriteOp> {
+        req.checkpoints
+            .iter()
+            .filter_map(|cp| self.lease_collection.checkpoint(cp.id, cp.remaining_ttl))
+            .map(WriteOp::PutLease)
+            .collect()
     }
 
     /// Get all `PbLease`
@@ -338,19 +398,17 @@ impl LeaseStore {
 
     /// Sync `LeaseRevokeRequest`
     #[allow(clippy::trivially_copy_pass_by_ref)] // we can only get a reference in the caller
-    fn sync_lease_revoke_request<T, I>(
+    fn sync_lease_revoke_request<I>(
         &self,
         req: &LeaseRevokeRequest,
         revision: i64,
-        txn_db: &T,
         index: &I,
-    ) -> Result<bool, ExecuteError>
+    ) -> Result<(bool, Vec<WriteOp>), ExecuteError>
     where
-        T: XlineStorageOps + TransactionApi,
         I: IndexOperate,
     {
         let mut updates = Vec::new();
-        txn_db.write_op(WriteOp::DeleteLease(req.id))?;
+        let mut ops = vec![WriteOp::DeleteLease(req.id)];
 
         let del_keys = match self.lease_collection.look_up(req.id) {
             Some(l) => l.keys(),
@@ -359,12 +417,19 @@ impl LeaseStore {
 
         if del_keys.is_empty() {
             let _ignore = self.lease_collection.revoke(req.id);
-            return Ok(false);
+            return Ok((false, ops));
         }
 
         for (key, mut sub_revision) in del_keys.iter().zip(0..) {
-            let deleted =
-                KvStore::delete_keys(txn_db, index, key, &[], revision, &mut sub_revision)?;
+            let deleted = KvStore::delete_keys(
+                index,
+                key,
+                &[],
+                revision,
+                &mut sub_revision,
+                self.db.explicit_tombstones(),
+                &mut ops,
+            );
             KvStore::detach_leases(&deleted, &self.lease_collection);
             let mut del_event = KvStore::new_deletion_events(revision, deleted);
             updates.append(&mut del_event);
@@ -376,7 +441,7 @@ impl LeaseStore {
             "Failed to send updates to KV watcher"
         );
 
-        Ok(true)
+        Ok((true, ops))
     }
 }
 
@@ -384,6 +449,7 @@ impl LeaseStore {
 mod test {
     use std::{error::Error, time::Duration};
 
+    use engine::TransactionApi;
     use test_macros::abort_on_panic;
     use utils::config::EngineConfig;
 
@@ -448,10 +514,71 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    #[abort_on_panic]
+    async fn test_leases_should_not_list_expired_leases() -> Result<(), Box<dyn Error>> {
+        let db = DB::open(&EngineConfig::Memory)?;
+        let index = Index::new();
+        let (lease_store, rev_gen) = init_store(db);
+        let rev_gen_state = rev_gen.state();
+
+        let req1 = RequestWrapper::from(LeaseGrantRequest { ttl: 10, id: 1 });
+        let req2 = RequestWrapper::from(LeaseGrantRequest { ttl: 0, id: 2 });
+        let req3 = RequestWrapper::from(LeaseLeasesRequest {});
+        let _ignore1 = exe_and_sync_req(&lease_store, index.state(), &req1, &rev_gen_state)?;
+        let _ignore2 = exe_and_sync_req(&lease_store, index.state(), &req2, &rev_gen_state)?;
+        assert!(lease_store.look_up(2).unwrap().expired());
+
+        let resp = exe_and_sync_req(&lease_store, index.state(), &req3, &rev_gen_state)?;
+        let ResponseWrapper::LeaseLeasesResponse(leases) = resp else {
+            panic!("wrong response type: {resp:?}");
+        };
+        let ids: Vec<_> = leases.leases.iter().map(|status| status.id).collect();
+        assert_eq!(ids, vec![1]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[abort_on_panic]
+    async fn test_grant_existing_lease_should_fail() -> Result<(), Box<dyn Error>> {
+        let db = DB::open(&EngineConfig::Memory)?;
+        let index = Index::new();
+        let (lease_store, rev_gen) = init_store(db);
+        let rev_gen_state = rev_gen.state();
+
+        let req = RequestWrapper::from(LeaseGrantRequest { ttl: 10, id: 1 });
+        let _ignore = lease_store.execute(&req)?;
+        // the lease granted by the first request is not synced yet
+        assert!(matches!(
+            lease_store.execute(&req),
+            Err(ExecuteError::LeaseAlreadyExists(1))
+        ));
+        let (_ignore, ops) = lease_store.after_sync(&req, &rev_gen_state, &index)?;
+        lease_store.db.write_ops(ops)?;
+        lease_store.mark_lease_synced(&req);
+
+        assert!(matches!(
+            lease_store.execute(&req),
+            Err(ExecuteError::LeaseAlreadyExists(1))
+        ));
+        assert!(matches!(
+            lease_store.after_sync(&req, &rev_gen_state, &index),
+            Err(ExecuteError::LeaseAlreadyExists(1))
+        ));
+
+        // the id reserved by a request that is never synced is released on demotion
+        let req = RequestWrapper::from(LeaseGrantRequest { ttl: 10, id: 2 });
+        let _ignore = lease_store.execute(&req)?;
+        lease_store.demote();
+        assert!(lease_store.execute(&req).is_ok());
+
+        Ok(())
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn test_lease_sync() -> Result<(), Box<dyn Error>> {
         let db = DB::open(&EngineConfig::Memory)?;
-        let txn = db.transaction();
         let index = Index::new();
         let (lease_store, rev_gen) = init_store(Arc::clone(&db));
         let rev_gen_state = rev_gen.state();
@@ -467,7 +594,7 @@ mod test {
             "the future should block until the lease is synced"
         );
 
-        let (_ignore, ops) = lease_store.after_sync(&req1, &rev_gen_state, &txn, &index)?;
+        let (_ignore, ops) = lease_store.after_sync(&req1, &rev_gen_state, &index)?;
         lease_store.db.write_ops(ops)?;
         lease_store.mark_lease_synced(&req1);
 
@@ -488,7 +615,7 @@ mod test {
             "the future should block until the lease is synced"
         );
 
-        let (_ignore, ops) = lease_store.after_sync(&req2, &rev_gen_state, &txn, &index)?;
+        let (_ignore, ops) = lease_store.after_sync(&req2, &rev_gen_state, &index)?;
         lease_store.db.write_ops(ops)?;
         lease_store.mark_lease_synced(&req2);
 
@@ -526,6 +653,69 @@ mod test {
         assert!(!lease1.keys().is_empty());
         assert!(lease2.keys().is_empty()); // keys will be recovered when recover kv store
 
+        // the leases missing in persistent storage are dropped
+        let _lease = store.lease_collection.grant(2, 10, false);
+        store.recover()?;
+        assert!(store.look_up(1).unwrap().keys().is_empty());
+        assert!(store.look_up(2).is_none());
+
+        // the leases recovered on the leader are going to expire
+        assert!(store
+            .lease_collection
+            .next_expiry()
+            .is_some_and(|expiry| expiry > Instant::now()));
+        // the leases without keys are kept until they expire
+        assert!(store.lease_collection.find_expired_leases().is_empty());
+        assert!(store.look_up(1).is_some_and(|l| !l.expired()));
+        new_store.demote();
+        new_store.recover()?;
+        assert!(new_store.lease_collection.next_expiry().is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[abort_on_panic]
+    async fn test_checkpoint_should_survive_leader_change() -> Result<(), ExecuteError> {
+        let db = DB::open(&EngineConfig::Memory)?;
+        let index = Index::new();
+        let (store, rev_gen) = init_store(Arc::clone(&db));
+        let rev_gen_state = rev_gen.state();
+        store.demote();
+
+        let req1 = RequestWrapper::from(LeaseGrantRequest { ttl: 60, id: 1 });
+        let _ignore1 = exe_and_sync_req(&store, index.state(), &req1, &rev_gen_state)?;
+        let req2 = RequestWrapper::from(LeaseCheckpointRequest {
+            checkpoints: vec![
+                LeaseCheckpoint {
+                    id: 1,
+                    remaining_ttl: 5,
+                },
+                // the revoked leases are skipped
+                LeaseCheckpoint {
+                    id: 2,
+                    remaining_ttl: 5,
+                },
+            ],
+        });
+        let _ignore2 = exe_and_sync_req(&store, index.state(), &req2, &rev_gen_state)?;
+        assert!(store.look_up(2).is_none());
+
+        // the new leader restarts the lease from its checkpointed remaining ttl
+        store.promote(Duration::ZERO);
+        assert!(store.look_up(1).unwrap().remaining() <= Duration::from_secs(5));
+
+        // so does a restarted member
+        let (new_store, _) = init_store(db);
+        new_store.recover()?;
+        assert!(new_store.look_up(1).unwrap().remaining() <= Duration::from_secs(5));
+
+        // a renewed lease restarts from its ttl
+        let _ttl = store.keep_alive(1)?;
+        let lease = store.look_up(1).unwrap();
+        assert!(!lease.has_checkpoint());
+        assert!(lease.remaining() > Duration::from_secs(5));
+
         Ok(())
     }
 
@@ -547,7 +737,8 @@ mod test {
     ) -> Result<ResponseWrapper, ExecuteError> {
         let cmd_res = ls.execute(req)?;
         let txn = ls.db.transaction();
-        let (_ignore, _ops) = ls.after_sync(req, rev_gen, &txn, &index)?;
+        let (_ignore, ops) = ls.after_sync(req, rev_gen, &index)?;
+        txn.write_ops(ops)?;
         txn.commit()
             .map_err(|e| ExecuteError::DbError(e.to_string()))?;
         index.commit();
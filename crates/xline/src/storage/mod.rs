@@ -2,8 +2,12 @@
 pub(crate) mod alarm_store;
 /// Storage for Auth
 pub(crate) mod auth_store;
+/// Cold tier module
+pub(crate) mod cold_tier;
 /// Compact module
 pub(super) mod compact;
+/// Value compression module
+pub(crate) mod compression;
 /// Database module
 pub mod db;
 /// Index module
@@ -14,6 +18,8 @@ pub(crate) mod kv_store;
 pub(crate) mod kvwatcher;
 /// Storage for lease
 pub(crate) mod lease_store;
+/// Persistence pipeline module
+pub(crate) mod pipeline;
 /// Revision module
 pub(crate) mod revision;
 /// Storage API
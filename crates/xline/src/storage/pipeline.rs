@@ -0,0 +1,725 @@
+use std::{
+    cmp,
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, OnceLock,
+    },
+};
+
+use clippy_utilities::{NumericCast, OverflowArithmetic};
+use engine::{Engine, EngineError, StorageOps, TransactionApi, View, ViewApi, WriteOperation};
+use itertools::{EitherOrBoth, Itertools};
+use parking_lot::{Condvar, Mutex, RwLock};
+use utils::{config::default_log_entries_cap, table_names::XLINE_TABLES};
+
+use super::db::{record_write_error, DB};
+
+/// Number of the batches queued in the pipeline, the apply waits for the
+/// pipeline when it's full
+///
+/// It bounds the memory of the pending mutations. A batch may carry any number
+/// of log entries, the entries not persisted yet are bounded by the max lag of
+/// the pipeline instead.
+pub(crate) const PIPELINE_CAPACITY: usize = 128;
+
+/// Run a closure that may block while the pipeline is busy
+///
+/// The apply and the reads of the `DB` run on the workers of the runtime, so
+/// a worker hands over its other tasks before it blocks. A current thread
+/// runtime, like the one of the tests, is blocked as it has no other worker.
+#[cfg(not(madsim))]
+pub(super) fn block_in_place<R>(f: impl FnOnce() -> R) -> R {
+    use tokio::runtime::{Handle, RuntimeFlavor};
+
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(f)
+        }
+        _ => f(),
+    }
+}
+
+/// The batches are persisted in place on madsim, nothing blocks for long
+#[cfg(madsim)]
+pub(super) fn block_in_place<R>(f: impl FnOnce() -> R) -> R {
+    f()
+}
+
+/// The mutations of the keys of the tables, `None` deletes the key
+type Mutations = HashMap<String, HashMap<Vec<u8>, Option<Vec<u8>>>>;
+
+/// A batch of mutations to be persisted
+#[derive(Debug)]
+struct Batch {
+    /// Sequence of the batch, in the order of submission
+    seq: u64,
+    /// The mutations of the batch
+    mutations: Mutations,
+    /// The applied index of the commands of the batch, if it's put by the batch
+    applied_index: Option<u64>,
+}
+
+/// The progress of the persistence
+#[derive(Debug, Default)]
+struct Progress {
+    /// Sequence of the last persisted batch
+    seq: u64,
+    /// The applied index persisted in the engine by the batches
+    applied_index: u64,
+}
+
+/// The state shared by the pipeline and its worker
+#[derive(Debug, Default)]
+struct Shared {
+    /// The mutations not persisted yet, along with the sequence of the last
+    /// batch mutating each key
+    pending: RwLock<HashMap<String, HashMap<Vec<u8>, (u64, Option<Vec<u8>>)>>>,
+    /// The progress of the persisted batches
+    persisted: Mutex<Progress>,
+    /// Notified when a batch is persisted
+    persisted_cond: Condvar,
+}
+
+impl Shared {
+    /// Remove the pending mutations of the persisted batch, unless they are
+    /// overwritten by a later batch
+    fn remove_persisted(&self, batch: &Batch) {
+        let mut pending = self.pending.write();
+        for (table, kvs) in &batch.mutations {
+            let Some(pending_kvs) = pending.get_mut(table) else {
+                continue;
+            };
+            for key in kvs.keys() {
+                if pending_kvs
+                    .get(key)
+                    .is_some_and(|&(seq, _)| seq == batch.seq)
+                {
+                    let _prev = pending_kvs.remove(key);
+                }
+            }
+        }
+    }
+}
+
+/// Persists the batches to the engine
+#[derive(Debug)]
+struct Writer {
+    /// The engine of the `DB`
+    engine: Arc<Engine>,
+    /// The persistent write error of the `DB`
+    write_failure: Arc<OnceLock<String>>,
+}
+
+impl Writer {
+    /// Persist a batch and mark it persisted
+    ///
+    /// The batches after a failed one are not persisted, the storage is
+    /// read-only then and they are applied again from the log after restart.
+    /// Their mutations stay pending, so the reads keep consistent with the
+    /// index in memory.
+    fn persist(&self, shared: &Shared, batch: Batch) {
+        if self.write_failure.get().is_none() {
+            let ops = batch.mutations.iter().flat_map(|(table, kvs)| {
+                kvs.iter().map(move |(key, value)| match *value {
+                    Some(ref v) => WriteOperation::new_put(table, key.clone(), v.clone()),
+                    None => WriteOperation::new_delete(table, key),
+                })
+            });
+            // the batch is synced or not by the durability of the engine
+            match self.engine.write_multi(ops, false) {
+                Ok(()) => {
+                    shared.remove_persisted(&batch);
+                    if let Some(index) = batch.applied_index {
+                        shared.persisted.lock().applied_index = index;
+                    }
+                }
+                Err(e) => record_write_error(&self.write_failure, &e),
+            }
+        }
+        let mut persisted = shared.persisted.lock();
+        persisted.seq = batch.seq;
+        let _woken = shared.persisted_cond.notify_all();
+    }
+}
+
+/// The persistence pipeline of the `DB`
+///
+/// The apply of the commands submits the mutations of a transaction as a
+/// batch instead of writing them to the engine, and a worker persists the
+/// batches in the order of submission, so the apply is not blocked by the
+/// latency of the disk unless the pipeline is full. The mutations are pending
+/// until they are persisted and the reads of the `DB` see them first.
+///
+/// A batch carries the applied index of its commands, so the engine is always
+/// at an applied index after a crash and the rest of the log is applied again.
+/// Curp only keeps the log entries within its `log_entries_cap` behind the
+/// applied index it knows, so the apply also waits when the applied index of a
+/// batch is more than the max lag ahead of the persisted one.
+#[derive(Debug)]
+pub(crate) struct Pipeline {
+    /// The state shared with the worker
+    shared: Arc<Shared>,
+    /// The max number of the log entries applied but not persisted
+    max_lag: AtomicU64,
+    /// Sequence of the last submitted batch, it's locked during a submission
+    /// so the batches are queued in the order of their sequences
+    submitted: Mutex<u64>,
+    /// Sender of the batches to the worker
+    #[cfg(not(madsim))]
+    batch_tx: flume::Sender<Batch>,
+    /// The batches are persisted in place as madsim is single threaded
+    #[cfg(madsim)]
+    writer: Writer,
+}
+
+impl Pipeline {
+    /// Create a new `Pipeline` of the engine at the applied index, and start
+    /// its worker
+    pub(super) fn new(
+        engine: Arc<Engine>,
+        write_failure: Arc<OnceLock<String>>,
+        applied_index: u64,
+    ) -> std::io::Result<Self> {
+        let shared = Arc::new(Shared {
+            persisted: Mutex::new(Progress {
+                seq: 0,
+                applied_index,
+            }),
+            ..Shared::default()
+        });
+        let writer = Writer {
+            engine,
+            write_failure,
+        };
+        #[cfg(not(madsim))]
+        let batch_tx = {
+            let (batch_tx, batch_rx) = flume::bounded::<Batch>(PIPELINE_CAPACITY);
+            let worker_shared = Arc::clone(&shared);
+            // the worker exits after the batches are drained when the pipeline is dropped
+            let _handle = std::thread::Builder::new()
+                .name("persistence".to_owned())
+                .spawn(move || {
+                    for batch in batch_rx {
+                        writer.persist(&worker_shared, batch);
+                    }
+                })?;
+            batch_tx
+        };
+        Ok(Self {
+            shared,
+            max_lag: AtomicU64::new(default_log_entries_cap().numeric_cast()),
+            submitted: Mutex::new(0),
+            #[cfg(not(madsim))]
+            batch_tx,
+            #[cfg(madsim)]
+            writer,
+        })
+    }
+
+    /// Set the max number of the log entries applied but not persisted, it
+    /// must not exceed the number of the log entries kept by curp
+    pub(super) fn set_max_lag(&self, max_lag: u64) {
+        self.max_lag.store(max_lag, Ordering::Relaxed);
+    }
+
+    /// Submit the mutations as a batch, it blocks until there is room in the
+    /// pipeline and the persisted applied index is within the max lag of the
+    /// applied index of the batch
+    pub(super) fn submit(&self, mutations: Mutations, applied_index: Option<u64>) {
+        if mutations.values().all(HashMap::is_empty) {
+            return;
+        }
+        let seq = self.enqueue(mutations, applied_index);
+        if let Some(index) = applied_index {
+            self.wait_lag(seq, index);
+        }
+    }
+
+    /// Queue the mutations as a batch, returns the sequence of the batch
+    fn enqueue(&self, mutations: Mutations, applied_index: Option<u64>) -> u64 {
+        let mut submitted = self.submitted.lock();
+        let seq = submitted.overflow_add(1);
+        *submitted = seq;
+        {
+            let mut pending = self.shared.pending.write();
+            for (table, kvs) in &mutations {
+                let pending_kvs = pending.entry(table.clone()).or_default();
+                for (key, value) in kvs {
+                    let _prev = pending_kvs.insert(key.clone(), (seq, value.clone()));
+                }
+            }
+        }
+        let batch = Batch {
+            seq,
+            mutations,
+            applied_index,
+        };
+        // it blocks when the pipeline is full, so the apply is slowed down to the
+        // speed of the disk instead of queuing without a bound
+        #[cfg(not(madsim))]
+        match self.batch_tx.try_send(batch) {
+            Ok(()) => {}
+            Err(flume::TrySendError::Full(batch)) => {
+                block_in_place(|| self.batch_tx.send(batch)).unwrap_or_else(|_e| {
+                    unreachable!("the worker of the pipeline exits only after it's dropped")
+                });
+            }
+            Err(flume::TrySendError::Disconnected(_batch)) => {
+                unreachable!("the worker of the pipeline exits only after it's dropped")
+            }
+        }
+        #[cfg(madsim)]
+        self.writer.persist(&self.shared, batch);
+        seq
+    }
+
+    /// Wait until the persisted applied index is within the max lag of the
+    /// applied index of the batch, so the log entries between them are still
+    /// kept by curp when it learns the applied index
+    ///
+    /// It stops waiting once the batch itself is handled, the persisted applied
+    /// index is behind after a reset of the `DB` or a failed batch.
+    fn wait_lag(&self, seq: u64, applied_index: u64) {
+        let lowest = applied_index.saturating_sub(self.max_lag.load(Ordering::Relaxed));
+        let caught_up =
+            |progress: &Progress| progress.seq >= seq || progress.applied_index >= lowest;
+        let mut persisted = self.shared.persisted.lock();
+        if caught_up(&persisted) {
+            return;
+        }
+        block_in_place(|| {
+            while !caught_up(&persisted) {
+                self.shared.persisted_cond.wait(&mut persisted);
+            }
+        });
+    }
+
+    /// Wait until all the submitted batches are persisted
+    pub(super) fn wait_persisted(&self) {
+        let target = *self.submitted.lock();
+        let mut persisted = self.shared.persisted.lock();
+        if persisted.seq >= target {
+            return;
+        }
+        block_in_place(|| {
+            while persisted.seq < target {
+                self.shared.persisted_cond.wait(&mut persisted);
+            }
+        });
+    }
+
+    /// Get the pending mutation of the key, `None` if the key is not pending
+    pub(super) fn get(&self, table: &str, key: &[u8]) -> Option<Option<Vec<u8>>> {
+        self.shared
+            .pending
+            .read()
+            .get(table)?
+            .get(key)
+            .map(|&(_, ref value)| value.clone())
+    }
+
+    /// Apply the pending mutations of the keys with the prefix to the
+    /// key-value pairs read from the engine, in the order of the keys
+    ///
+    /// The engine is read while the pending mutations are locked, so the
+    /// mutations persisted meanwhile are not missed by both.
+    pub(super) fn overlay_prefix(
+        &self,
+        table: &str,
+        prefix: &[u8],
+        read: impl FnOnce() -> Result<Vec<(Vec<u8>, Vec<u8>)>, EngineError>,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, EngineError> {
+        let pending = self.shared.pending.read();
+        let kvs = read()?;
+        let Some(pending_kvs) = pending.get(table).filter(|kvs| !kvs.is_empty()) else {
+            return Ok(kvs);
+        };
+        Ok(overlay(
+            kvs,
+            pending_kvs
+                .iter()
+                .map(|(key, &(_, ref value))| (key, value)),
+            prefix,
+        ))
+    }
+
+    /// Take a view of the engine along with the pending mutations, the pending
+    /// mutations are locked meanwhile like [`Pipeline::overlay_prefix`]
+    pub(super) fn view<'e>(&self, view: impl FnOnce() -> View<'e>) -> PipelinedView<'e> {
+        let pending = self.shared.pending.read();
+        let view = view();
+        let pending = pending
+            .iter()
+            .filter(|&(_, kvs)| !kvs.is_empty())
+            .map(|(table, kvs)| {
+                let kvs = kvs
+                    .iter()
+                    .map(|(key, &(_, ref value))| (key.clone(), value.clone()))
+                    .collect();
+                (table.clone(), kvs)
+            })
+            .collect();
+        PipelinedView { view, pending }
+    }
+}
+
+impl Drop for Pipeline {
+    fn drop(&mut self) {
+        self.wait_persisted();
+    }
+}
+
+/// Apply the mutations of the keys with the prefix to the key-value pairs, in
+/// the order of the keys
+fn overlay<'a>(
+    kvs: Vec<(Vec<u8>, Vec<u8>)>,
+    mutations: impl Iterator<Item = (&'a Vec<u8>, &'a Option<Vec<u8>>)>,
+    prefix: &[u8],
+) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut kvs: HashMap<_, _> = kvs.into_iter().collect();
+    for (key, value) in mutations.filter(|&(key, _)| key.starts_with(prefix)) {
+        let _prev = match *value {
+            Some(ref v) => kvs.insert(key.clone(), v.clone()),
+            None => kvs.remove(key),
+        };
+    }
+    let mut kvs: Vec<_> = kvs.into_iter().collect();
+    kvs.sort_by(|kv1, kv2| kv1.0.cmp(&kv2.0));
+    kvs
+}
+
+/// A view of the `DB`, which sees the mutations pending when it's taken, so
+/// it's not behind the reads of the `DB` while the batches are not persisted
+pub(crate) struct PipelinedView<'e> {
+    /// The view of the engine
+    view: View<'e>,
+    /// The pending mutations of the tables, in the order of the keys
+    pending: HashMap<String, BTreeMap<Vec<u8>, Option<Vec<u8>>>>,
+}
+
+impl std::fmt::Debug for PipelinedView<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PipelinedView")
+            .field("pending", &self.pending)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ViewApi for PipelinedView<'_> {
+    fn get(&self, table: &str, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>, EngineError> {
+        match self
+            .pending
+            .get(table)
+            .and_then(|kvs| kvs.get(key.as_ref()))
+        {
+            Some(value) => Ok(value.clone()),
+            None => self.view.get(table, key),
+        }
+    }
+
+    fn iter(
+        &self,
+        table: &str,
+    ) -> Result<Box<dyn Iterator<Item = Result<(Vec<u8>, Vec<u8>), EngineError>> + '_>, EngineError>
+    {
+        let pairs = self.view.iter(table)?;
+        let Some(pending_kvs) = self.pending.get(table) else {
+            return Ok(pairs);
+        };
+        #[allow(clippy::pattern_type_mismatch)] // can't be fixed
+        let pairs = pairs
+            .merge_join_by(pending_kvs.iter(), |pair, &(key, _)| match *pair {
+                Ok((ref k, _)) => k.cmp(key),
+                Err(_) => cmp::Ordering::Less,
+            })
+            .filter_map(|either| match either {
+                EitherOrBoth::Left(pair) => Some(pair),
+                EitherOrBoth::Right((key, value)) | EitherOrBoth::Both(_, (key, value)) => {
+                    value.as_ref().map(|v| Ok((key.clone(), v.clone())))
+                }
+            });
+        Ok(Box::new(pairs))
+    }
+
+    fn last(&self, table: &str) -> Result<Option<(Vec<u8>, Vec<u8>)>, EngineError> {
+        let Some(pending_kvs) = self.pending.get(table) else {
+            return self.view.last(table);
+        };
+        let last = match self.view.last(table)? {
+            // the last key of the engine is deleted by a pending mutation, the
+            // table is scanned then
+            Some((key, _)) if pending_kvs.get(&key).is_some_and(Option::is_none) => {
+                return self.iter(table)?.last().transpose();
+            }
+            Some((key, value)) => {
+                let value = pending_kvs.get(&key).cloned().flatten().unwrap_or(value);
+                Some((key, value))
+            }
+            None => None,
+        };
+        let last_put = pending_kvs
+            .iter()
+            .rev()
+            .find_map(|(key, value)| value.as_ref().map(|v| (key.clone(), v.clone())));
+        Ok(match (last, last_put) {
+            (Some(last), Some(put)) => Some(if put.0 > last.0 { put } else { last }),
+            (last, put) => last.or(put),
+        })
+    }
+}
+
+/// A transaction of the `DB`, its mutations are submitted to the pipeline of
+/// the `DB` as a batch when it's committed
+#[derive(Debug)]
+pub(crate) struct PipelinedTransaction<'db> {
+    /// The `DB` of the transaction
+    db: &'db DB,
+    /// The mutations of the transaction
+    state: RwLock<Mutations>,
+}
+
+impl<'db> PipelinedTransaction<'db> {
+    /// Create a new `PipelinedTransaction`
+    pub(super) fn new(db: &'db DB) -> Self {
+        Self {
+            db,
+            state: RwLock::new(Mutations::new()),
+        }
+    }
+
+    /// Write an op to the state of the transaction
+    fn write_op(state: &mut Mutations, op: WriteOperation<'_>) -> Result<(), EngineError> {
+        let (table, key, value) = match op {
+            WriteOperation::Put { table, key, value } => (table, key, Some(value)),
+            WriteOperation::Delete { table, key } => (table, key.to_vec(), None),
+            WriteOperation::DeleteRange { .. } => {
+                return Err(EngineError::InvalidArgument(
+                    "delete range is not supported in a transaction".to_owned(),
+                ))
+            }
+        };
+        if !XLINE_TABLES.contains(&table) {
+            return Err(EngineError::TableNotFound(table.to_owned()));
+        }
+        let _prev = state
+            .entry(table.to_owned())
+            .or_default()
+            .insert(key, value);
+        Ok(())
+    }
+}
+
+impl StorageOps for PipelinedTransaction<'_> {
+    fn write(&self, op: WriteOperation<'_>, _sync: bool) -> Result<(), EngineError> {
+        Self::write_op(&mut self.state.write(), op)
+    }
+
+    fn write_multi<'a, Ops>(&self, ops: Ops, _sync: bool) -> Result<(), EngineError>
+    where
+        Ops: IntoIterator<Item = WriteOperation<'a>>,
+    {
+        let mut state = self.state.write();
+        for op in ops {
+            Self::write_op(&mut state, op)?;
+        }
+        Ok(())
+    }
+
+    fn get(&self, table: &str, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>, EngineError> {
+        if let Some(value) = self
+            .state
+            .read()
+            .get(table)
+            .and_then(|kvs| kvs.get(key.as_ref()))
+        {
+            return Ok(value.clone());
+        }
+        self.db.get(table, key)
+    }
+
+    #[allow(clippy::indexing_slicing)] // the indexes are taken from the keys
+    fn get_multi(
+        &self,
+        table: &str,
+        keys: &[impl AsRef<[u8]>],
+    ) -> Result<Vec<Option<Vec<u8>>>, EngineError> {
+        let state = self.state.read();
+        let Some(kvs) = state.get(table).filter(|kvs| !kvs.is_empty()) else {
+            return self.db.get_multi(table, keys);
+        };
+        let mut values = vec![None; keys.len()];
+        let mut missing = Vec::new();
+        for (i, key) in keys.iter().enumerate() {
+            match kvs.get(key.as_ref()) {
+                Some(value) => values[i] = value.clone(),
+                None => missing.push(i),
+            }
+        }
+        let missing_keys: Vec<_> = missing.iter().map(|&i| keys[i].as_ref()).collect();
+        for (i, value) in missing
+            .into_iter()
+            .zip(self.db.get_multi(table, &missing_keys)?)
+        {
+            values[i] = value;
+        }
+        Ok(values)
+    }
+
+    fn get_prefix(
+        &self,
+        table: &str,
+        prefix: &[u8],
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, EngineError> {
+        let kvs = self.db.get_prefix(table, prefix)?;
+        let state = self.state.read();
+        let Some(state_kvs) = state.get(table).filter(|kvs| !kvs.is_empty()) else {
+            return Ok(kvs);
+        };
+        Ok(overlay(kvs, state_kvs.iter(), prefix))
+    }
+}
+
+impl TransactionApi for PipelinedTransaction<'_> {
+    fn commit(self) -> Result<(), EngineError> {
+        self.db.submit(self.state.into_inner())
+    }
+
+    fn rollback(&self) -> Result<(), EngineError> {
+        self.state.write().clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use utils::{config::EngineConfig, table_names::META_TABLE};
+
+    use super::*;
+    use crate::server::command::APPLIED_INDEX_KEY;
+
+    #[test]
+    fn committed_transactions_should_be_read_before_persisted() -> Result<(), EngineError> {
+        let db = DB::open(&EngineConfig::Memory).unwrap();
+        for i in 0..10_u8 {
+            let txn = db.transaction();
+            txn.write_multi(
+                [
+                    WriteOperation::new_put(META_TABLE, b"key".to_vec(), vec![i]),
+                    WriteOperation::new_put(META_TABLE, vec![i], vec![i]),
+                ],
+                false,
+            )?;
+            assert_eq!(txn.get(META_TABLE, b"key")?, Some(vec![i]));
+            txn.commit()?;
+            assert_eq!(db.get(META_TABLE, b"key")?, Some(vec![i]));
+        }
+
+        let txn = db.transaction();
+        txn.write(WriteOperation::new_delete(META_TABLE, &[3]), false)?;
+        txn.write(
+            WriteOperation::new_put(META_TABLE, vec![10], vec![10]),
+            false,
+        )?;
+        assert_eq!(
+            txn.get_multi(META_TABLE, &[vec![2], vec![3], vec![10]])?,
+            vec![Some(vec![2]), None, Some(vec![10])]
+        );
+        let prefix: Vec<_> = txn
+            .get_prefix(META_TABLE, &[])?
+            .into_iter()
+            .map(|(k, _)| k)
+            .collect();
+        assert_eq!(prefix.len(), 11);
+        assert!(!prefix.contains(&vec![3]));
+        assert_eq!(db.get(META_TABLE, [3])?, Some(vec![3]));
+        txn.rollback()?;
+        txn.commit()?;
+
+        // the pending batches are read along with the persisted ones
+        let kvs = db.get_all(META_TABLE).unwrap();
+        assert_eq!(kvs.len(), 11);
+        assert!(kvs.contains(&(b"key".to_vec(), vec![9])));
+        Ok(())
+    }
+
+    #[test]
+    fn views_should_see_the_pending_batches() -> Result<(), EngineError> {
+        let db = DB::open(&EngineConfig::Memory).unwrap();
+        for i in 0..10_u8 {
+            let txn = db.transaction();
+            txn.write(WriteOperation::new_put(META_TABLE, vec![i], vec![i]), false)?;
+            txn.commit()?;
+        }
+        let txn = db.transaction();
+        txn.write_multi(
+            [
+                WriteOperation::new_delete(META_TABLE, &[9]),
+                WriteOperation::new_put(META_TABLE, vec![3], vec![30]),
+            ],
+            false,
+        )?;
+        txn.commit()?;
+
+        let view = db.view();
+        assert_eq!(view.get(META_TABLE, [3])?, Some(vec![30]));
+        assert_eq!(view.get(META_TABLE, [9])?, None);
+        assert_eq!(view.last(META_TABLE)?, Some((vec![8], vec![8])));
+        let keys: Vec<_> = view
+            .iter(META_TABLE)?
+            .map(|pair| pair.map(|(k, _)| k))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(keys, (0..9_u8).map(|i| vec![i]).collect::<Vec<_>>());
+        Ok(())
+    }
+
+    #[test]
+    fn applied_index_should_be_persisted_within_the_max_lag() -> Result<(), EngineError> {
+        let db = DB::open(&EngineConfig::Memory).unwrap();
+        db.set_max_unpersisted_entries(0);
+        for i in 1..=10_u64 {
+            let txn = db.transaction();
+            txn.write(
+                WriteOperation::new_put(
+                    META_TABLE,
+                    APPLIED_INDEX_KEY.as_bytes().to_vec(),
+                    i.to_le_bytes().to_vec(),
+                ),
+                false,
+            )?;
+            txn.commit()?;
+            // nothing is left behind when no lag is allowed
+            assert_eq!(db.persisted_applied_index().unwrap(), i);
+        }
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn full_pipeline_should_not_stall_the_worker() -> Result<(), EngineError> {
+        let db = DB::open(&EngineConfig::Memory).unwrap();
+        let count = PIPELINE_CAPACITY.overflow_mul(2);
+        // the only worker is handed over when the pipeline is full
+        let ticker = tokio::spawn(async { tokio::task::yield_now().await });
+        for i in 0..count {
+            let txn = db.transaction();
+            txn.write(
+                WriteOperation::new_put(META_TABLE, i.to_le_bytes().to_vec(), vec![]),
+                false,
+            )?;
+            txn.commit()?;
+        }
+        assert_eq!(db.get_all(META_TABLE).unwrap().len(), count);
+        ticker.await.unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_table_should_not_be_written() {
+        let db = DB::open(&EngineConfig::Memory).unwrap();
+        let txn = db.transaction();
+        assert!(matches!(
+            txn.write(WriteOperation::new_put("foo", vec![1], vec![1]), false),
+            Err(EngineError::TableNotFound(_))
+        ));
+    }
+}
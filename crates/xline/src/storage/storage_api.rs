@@ -7,7 +7,8 @@ pub(crate) trait XlineStorageOps {
     /// Write an operation to the transaction
     fn write_op(&self, op: WriteOp) -> Result<(), ExecuteError>;
 
-    /// Write a batch of operations to the transaction
+    /// Write a batch of operations to the transaction, the operations of a
+    /// batch are applied atomically, either all or none of them
     fn write_ops(&self, ops: Vec<WriteOp>) -> Result<(), ExecuteError>;
 
     /// Get values by keys from storage
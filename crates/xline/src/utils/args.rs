@@ -1,29 +1,40 @@
 use std::{collections::HashMap, env, path::PathBuf, time::Duration};
 
 use anyhow::Result;
-use clap::Parser;
+use clap::{ArgAction, Parser};
 use tokio::fs;
 use utils::{
     config::{
-        default_batch_max_size, default_batch_timeout, default_candidate_timeout_ticks,
+        default_backup_interval, default_backup_retention, default_batch_max_size,
+        default_batch_timeout, default_candidate_timeout_ticks,
         default_client_id_keep_alive_interval, default_client_wait_synced_timeout,
-        default_cmd_workers, default_compact_batch_size, default_compact_sleep_interval,
-        default_compact_timeout, default_follower_timeout_ticks, default_gc_interval,
-        default_heartbeat_interval, default_initial_retry_timeout, default_log_entries_cap,
-        default_log_level, default_max_retry_timeout, default_metrics_enable, default_metrics_path,
-        default_metrics_port, default_metrics_push_endpoint, default_metrics_push_protocol,
-        default_propose_timeout, default_quota, default_range_retry_timeout, default_retry_count,
-        default_rotation, default_rpc_timeout, default_server_wait_synced_timeout,
-        default_sync_victims_interval, default_watch_progress_notify_interval, AuthConfig,
-        AutoCompactConfig, ClientConfig, ClusterConfig, CompactConfig, CurpConfigBuilder,
-        EngineConfig, InitialClusterState, LevelConfig, LogConfig, MetricsConfig,
-        MetricsPushProtocol, RotationConfig, ServerTimeout, StorageConfig, TlsConfig, TraceConfig,
-        XlineServerConfig,
+        default_cmd_workers, default_cold_tier_interval, default_cold_tier_threshold,
+        default_compact_batch_size, default_compact_sleep_interval, default_compact_timeout,
+        default_corrupt_check_interval, default_follower_timeout_ticks, default_gc_interval,
+        default_heartbeat_interval, default_initial_retry_timeout,
+        default_lease_checkpoint_interval, default_log_entries_cap, default_log_level,
+        default_max_key_bytes, default_max_lease_ttl, default_max_range_bytes,
+        default_max_retry_timeout, default_max_value_bytes, default_metrics_enable,
+        default_metrics_path, default_metrics_port, default_metrics_push_endpoint,
+        default_metrics_push_protocol, default_min_lease_ttl, default_propose_timeout,
+        default_quota, default_range_retry_timeout, default_retry_count, default_rotation,
+        default_rpc_timeout, default_s3_region, default_server_wait_synced_timeout,
+        default_strict_reconfig_check, default_sync_victims_interval, default_wal_sync_max_delay,
+        default_watch_batch_interval, default_watch_flow_control_window,
+        default_watch_progress_notify_interval, AuthConfig, AutoCompactConfig, BackupConfig,
+        BackupTarget, ClientConfig, ClusterConfig, ColdTierConfig, CompactConfig,
+        CompressionConfig, CurpConfigBuilder, DurabilityConfig, EngineConfig, InitialClusterState,
+        LevelConfig, LogConfig, MetricsConfig, MetricsPushProtocol, RotationConfig, S3BackupConfig,
+        ServerTimeout, StorageConfig, StreamTokenExpiry, TlsConfig, TraceConfig,
+        ValueCompressionCodec, XlineServerConfig,
     },
-    parse_batch_bytes, parse_duration, parse_log_file, parse_log_level, parse_members,
-    parse_metrics_push_protocol, parse_rotation, parse_state, ConfigFileError,
+    parse_batch_bytes, parse_compression, parse_durability, parse_duration, parse_log_file,
+    parse_log_level, parse_members, parse_metrics_push_protocol, parse_rotation, parse_state,
+    parse_stream_token_expiry, parse_value_compression_codec, ConfigFileError,
 };
 
+use super::discovery::discover_members;
+
 /// Xline server config path env name
 const XLINE_SERVER_CONFIG_ENV: &str = "XLINE_SERVER_CONFIG";
 /// default xline server config path
@@ -50,8 +61,12 @@ pub struct ServerArgs {
     #[clap(long, num_args = 1.., value_delimiter = ',')]
     client_advertise_urls: Vec<String>,
     /// Cluster peers. eg: node1=192.168.x.x:8080,192.168.x.x:8081,node2=192.168.x.x:8083
-    #[clap(long, value_parser = parse_members)]
-    members: HashMap<String, Vec<String>>,
+    #[clap(long, value_parser = parse_members, required_unless_present = "discovery_srv")]
+    members: Option<HashMap<String, Vec<String>>>,
+    /// Domain whose SRV records `_xline-server._tcp.<domain>` and
+    /// `_xline-server-ssl._tcp.<domain>` list the peer urls of the cluster peers
+    #[clap(long, conflicts_with = "members")]
+    discovery_srv: Option<String>,
     /// If node is leader
     #[clap(long)]
     is_leader: bool,
@@ -61,6 +76,9 @@ pub struct ServerArgs {
     /// Public key used to verify the token
     #[clap(long)]
     auth_public_key: Option<PathBuf>,
+    /// What to do with a watch or lease keep alive stream once its token expires, 'revalidate' or 'cancel' [default: cancel]
+    #[clap(long, value_parser = parse_stream_token_expiry)]
+    stream_token_expiry: Option<StreamTokenExpiry>,
     /// Open jaeger offline
     #[clap(long)]
     jaeger_offline: bool,
@@ -130,6 +148,9 @@ pub struct ServerArgs {
     /// Number of log entries to keep in memory
     #[clap(long, default_value_t = default_log_entries_cap())]
     log_entries_cap: usize,
+    /// Reject the membership changes that leave the cluster without a quorum of active voters
+    #[clap(long, default_value_t = default_strict_reconfig_check(), action = ArgAction::Set)]
+    strict_reconfig_check: bool,
     /// Curp client wait synced timeout [default: 2s]
     #[clap(long, value_parser = parse_duration)]
     client_wait_synced_timeout: Option<Duration>,
@@ -151,6 +172,9 @@ pub struct ServerArgs {
     /// How often should the gc task run [default: 20s]
     #[clap(long, value_parser = parse_duration)]
     gc_interval: Option<Duration>,
+    /// Max delay to batch more log appends into one fsync [default: 0s, only the concurrent appends]
+    #[clap(long, value_parser = parse_duration)]
+    wal_sync_max_delay: Option<Duration>,
     /// Range request retry timeout [default: 2s]
     #[clap(long, value_parser = parse_duration)]
     range_retry_timeout: Option<Duration>,
@@ -163,6 +187,21 @@ pub struct ServerArgs {
     /// How often should watch progress notify send a response [default: 600s]
     #[clap(long, value_parser = parse_duration)]
     watch_progress_notify_interval: Option<Duration>,
+    /// How long should the events of a watcher be coalesced into one response [default: 0s, disabled]
+    #[clap(long, value_parser = parse_duration)]
+    watch_batch_interval: Option<Duration>,
+    /// Min ttl of a granted lease, the election timeout is always the lower bound [default: 0s]
+    #[clap(long, value_parser = parse_duration)]
+    min_lease_ttl: Option<Duration>,
+    /// Max ttl of a granted lease [default: 9000000000s]
+    #[clap(long, value_parser = parse_duration)]
+    max_lease_ttl: Option<Duration>,
+    /// How often should the leader compare the kv hashes of the members [default: 0s, disabled]
+    #[clap(long, value_parser = parse_duration)]
+    corrupt_check_interval: Option<Duration>,
+    /// How often should the leader checkpoint the remaining ttls of the leases [default: 5m, 0s disables it]
+    #[clap(long, value_parser = parse_duration)]
+    lease_checkpoint_interval: Option<Duration>,
     /// Storage engine
     #[clap(long)]
     storage_engine: String,
@@ -195,6 +234,89 @@ pub struct ServerArgs {
     /// Quota
     #[clap(long)]
     quota: Option<u64>,
+    /// Compress values whose encoded size reaches this threshold in bytes [default: disabled]
+    #[clap(long)]
+    value_compression_threshold: Option<usize>,
+    /// Codec to compress the values reaching the threshold, one of lz4 or zstd [default: lz4]
+    #[clap(long, value_parser = parse_value_compression_codec)]
+    value_compression_codec: Option<ValueCompressionCodec>,
+    /// Max size of a key in bytes [default: 64KB]
+    #[clap(long)]
+    max_key_bytes: Option<usize>,
+    /// Max size of a value in bytes [default: 1.5MB]
+    #[clap(long)]
+    max_value_bytes: Option<usize>,
+    /// Number of hot keys whose latest value is cached in memory [default: 0, disabled]
+    #[clap(long, default_value_t = 0)]
+    kv_cache_capacity: usize,
+    /// Max size of the kvs of a range result in bytes [default: 256MB]
+    #[clap(long)]
+    max_range_bytes: Option<usize>,
+    /// Max size of the event responses of a watch stream not sent to the client yet in bytes
+    /// [default: 64MB]
+    #[clap(long)]
+    watch_flow_control_window: Option<usize>,
+    /// Only reject the requests on the members whose data are corrupted under the corrupt alarm
+    #[clap(long)]
+    isolate_corrupted_member: bool,
+    /// Path of the file of the keys to encrypt the stored values, one `<id>:<hex key>` per line,
+    /// the largest id is used to encrypt [default: disabled]
+    #[clap(long)]
+    encryption_key_file: Option<PathBuf>,
+    /// Directory to back up the storage to periodically, e.g. a mounted bucket of an object
+    /// storage [default: disabled]
+    #[clap(long)]
+    backup_dir: Option<PathBuf>,
+    /// Bucket of an S3-compatible object storage to back up the storage to periodically, the
+    /// credentials are read from `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY` [default: disabled]
+    #[clap(long, conflicts_with = "backup_dir")]
+    backup_s3_bucket: Option<String>,
+    /// Endpoint of the object storage of the backup bucket, e.g. `http://minio:9000`
+    /// [default: AWS S3]
+    #[clap(long)]
+    backup_s3_endpoint: Option<String>,
+    /// Region of the backup bucket [default: us-east-1]
+    #[clap(long)]
+    backup_s3_region: Option<String>,
+    /// Prefix of the paths of the backups in the bucket [default: none]
+    #[clap(long)]
+    backup_s3_prefix: Option<String>,
+    /// Interval between two backups [default: 1h]
+    #[clap(long, value_parser = parse_duration)]
+    backup_interval: Option<Duration>,
+    /// Number of the latest full backups to keep [default: 24]
+    #[clap(long)]
+    backup_retention: Option<usize>,
+    /// Number of the incremental backups taken between two full backups [default: 0]
+    #[clap(long)]
+    backup_increments: Option<usize>,
+    /// Path of the snapshot to restore the storage from at startup, ignored if the storage
+    /// is not empty [default: disabled]
+    #[clap(long)]
+    restore_from: Option<PathBuf>,
+    /// Restore from the snapshot without checking its hash, e.g. a snapshot copied from a data dir
+    #[clap(long)]
+    skip_restore_hash_check: bool,
+    /// Block compression of the tables of the storage engine, eg: kv=zstd:3,lease=lz4, the
+    /// codec is one of none, snappy, lz4 or zstd, followed by an optional level
+    #[clap(long, value_parser = parse_compression)]
+    table_compression: Option<HashMap<String, CompressionConfig>>,
+    /// Directory of the cold tier to migrate the old history to, e.g. a slower disk or a
+    /// mounted bucket of an object storage, the engine must be rocksdb [default: disabled]
+    #[clap(long)]
+    cold_tier_dir: Option<PathBuf>,
+    /// The revisions superseded more than this number of revisions ago are migrated to the
+    /// cold tier [default: 100000]
+    #[clap(long)]
+    cold_tier_threshold: Option<i64>,
+    /// Interval between two migrations to the cold tier [default: 10m]
+    #[clap(long, value_parser = parse_duration)]
+    cold_tier_interval: Option<Duration>,
+    /// Durability of the writes to the storage engine, one of fsync-per-batch, os-buffered or
+    /// fsync-interval followed by an optional interval, eg: fsync-interval:100ms, the lost
+    /// writes are applied again from the log of curp after a failure [default: os-buffered]
+    #[clap(long, value_parser = parse_durability)]
+    durability: Option<DurabilityConfig>,
     /// Server ca certificate path, used to verify client certificate
     #[clap(long)]
     peer_ca_cert_path: Option<PathBuf>,
@@ -213,6 +335,20 @@ pub struct ServerArgs {
     /// Client private key path
     #[clap(long)]
     client_key_path: Option<PathBuf>,
+    /// Ca certificate path of the peer-to-peer CURP traffic, used to verify the certificates
+    /// of the other members
+    #[clap(long)]
+    curp_ca_cert_path: Option<PathBuf>,
+    /// Certificate path of the peer-to-peer CURP traffic, the server certificate is used if
+    /// it's not set
+    #[clap(long)]
+    curp_cert_path: Option<PathBuf>,
+    /// Private key path of the peer-to-peer CURP traffic
+    #[clap(long)]
+    curp_key_path: Option<PathBuf>,
+    /// Require the other members to present certificates issued for the host of their peer urls
+    #[clap(long)]
+    curp_client_cert_auth: bool,
 }
 
 #[allow(clippy::too_many_lines)] // will be refactored in #604
@@ -233,7 +369,51 @@ impl From<ServerArgs> for XlineServerConfig {
             &_ => unreachable!("xline only supports memory and rocksdb engine"),
         };
 
-        let storage = StorageConfig::new(engine, args.quota.unwrap_or_else(default_quota));
+        let backup_target = args
+            .backup_s3_bucket
+            .map(|bucket| {
+                BackupTarget::S3(S3BackupConfig::new(
+                    bucket,
+                    args.backup_s3_endpoint,
+                    args.backup_s3_region.unwrap_or_else(default_s3_region),
+                    args.backup_s3_prefix.unwrap_or_default(),
+                ))
+            })
+            .or_else(|| args.backup_dir.map(BackupTarget::Dir));
+        let storage = StorageConfig::new(
+            engine,
+            args.quota.unwrap_or_else(default_quota),
+            args.value_compression_threshold,
+            args.max_key_bytes.unwrap_or_else(default_max_key_bytes),
+            args.max_value_bytes.unwrap_or_else(default_max_value_bytes),
+            args.kv_cache_capacity,
+            args.isolate_corrupted_member,
+        )
+        .with_value_compression_codec(args.value_compression_codec.unwrap_or_default())
+        .with_encryption_key_file(args.encryption_key_file)
+        .with_backup(backup_target.map(|target| {
+            BackupConfig::new(
+                target,
+                args.backup_interval.unwrap_or_else(default_backup_interval),
+                args.backup_retention
+                    .unwrap_or_else(default_backup_retention),
+            )
+            .with_increments(args.backup_increments.unwrap_or_default())
+        }))
+        .with_restore_from(args.restore_from)
+        .with_skip_restore_hash_check(args.skip_restore_hash_check)
+        .with_compression(args.table_compression.unwrap_or_default())
+        .with_cold_tier(args.cold_tier_dir.map(|dir| {
+            ColdTierConfig::new(
+                dir,
+                args.cold_tier_threshold
+                    .unwrap_or_else(default_cold_tier_threshold),
+                args.cold_tier_interval
+                    .unwrap_or_else(default_cold_tier_interval),
+            )
+        }))
+        .with_durability(args.durability.unwrap_or_default())
+        .with_max_range_bytes(args.max_range_bytes.unwrap_or_else(default_max_range_bytes));
         let Ok(curp_config) = CurpConfigBuilder::default()
             .heartbeat_interval(
                 args.heartbeat_interval
@@ -251,6 +431,11 @@ impl From<ServerArgs> for XlineServerConfig {
             .engine_cfg(curp_engine)
             .gc_interval(args.gc_interval.unwrap_or_else(default_gc_interval))
             .cmd_workers(args.cmd_workers)
+            .strict_reconfig_check(args.strict_reconfig_check)
+            .wal_sync_max_delay(
+                args.wal_sync_max_delay
+                    .unwrap_or_else(default_wal_sync_max_delay),
+            )
             .build()
         else {
             panic!("failed to create curp config")
@@ -277,6 +462,14 @@ impl From<ServerArgs> for XlineServerConfig {
                 .unwrap_or_else(default_sync_victims_interval),
             args.watch_progress_notify_interval
                 .unwrap_or_else(default_watch_progress_notify_interval),
+            args.watch_batch_interval
+                .unwrap_or_else(default_watch_batch_interval),
+            args.min_lease_ttl.unwrap_or_else(default_min_lease_ttl),
+            args.max_lease_ttl.unwrap_or_else(default_max_lease_ttl),
+            args.corrupt_check_interval
+                .unwrap_or_else(default_corrupt_check_interval),
+            args.lease_checkpoint_interval
+                .unwrap_or_else(default_lease_checkpoint_interval),
         );
         let initial_cluster_state = args.initial_cluster_state.unwrap_or_default();
         let cluster = ClusterConfig::new(
@@ -285,12 +478,17 @@ impl From<ServerArgs> for XlineServerConfig {
             args.peer_advertise_urls,
             args.client_listen_urls,
             args.client_advertise_urls,
-            args.members,
+            args.members.unwrap_or_default(),
             args.is_leader,
             curp_config,
             client_config,
             server_timeout,
             initial_cluster_state,
+        )
+        .with_discovery_srv(args.discovery_srv)
+        .with_watch_flow_control_window(
+            args.watch_flow_control_window
+                .unwrap_or_else(default_watch_flow_control_window),
         );
         let log = LogConfig::new(args.log_file, args.log_rotate, args.log_level);
         let trace = TraceConfig::new(
@@ -299,7 +497,11 @@ impl From<ServerArgs> for XlineServerConfig {
             args.jaeger_output_dir,
             args.jaeger_level,
         );
-        let auth = AuthConfig::new(args.auth_public_key, args.auth_private_key);
+        let auth = AuthConfig::new(
+            args.auth_public_key,
+            args.auth_private_key,
+            args.stream_token_expiry.unwrap_or_default(),
+        );
         let auto_compactor_cfg = if let Some(mode) = args.auto_compact_mode {
             match mode.as_str() {
                 "periodic" => {
@@ -334,6 +536,12 @@ impl From<ServerArgs> for XlineServerConfig {
             args.client_ca_cert_path,
             args.client_cert_path,
             args.client_key_path,
+        )
+        .with_curp_tls(
+            args.curp_ca_cert_path,
+            args.curp_cert_path,
+            args.curp_key_path,
+            args.curp_client_cert_auth,
         );
         let metrics = MetricsConfig::new(
             args.metrics_enable,
@@ -354,15 +562,26 @@ impl From<ServerArgs> for XlineServerConfig {
 /// Return error if parse failed
 #[inline]
 pub async fn parse_config() -> Result<XlineServerConfig> {
-    if env::args_os().len() == 1 {
+    let config: XlineServerConfig = if env::args_os().len() == 1 {
         let path = env::var(XLINE_SERVER_CONFIG_ENV)
             .unwrap_or_else(|_| DEFAULT_XLINE_SERVER_CONFIG_PATH.to_owned());
         let config_file = fs::read_to_string(&path)
             .await
             .map_err(|err| ConfigFileError::FileError(path, err))?;
-        Ok(toml::from_str(&config_file)?)
+        toml::from_str(&config_file)?
     } else {
-        let server_args: ServerArgs = ServerArgs::parse();
-        Ok(server_args.into())
-    }
+        ServerArgs::parse().into()
+    };
+    let Some(domain) = config.cluster().discovery_srv().as_deref() else {
+        return Ok(config);
+    };
+    let cluster = config.cluster();
+    let local_peer_urls = if cluster.peer_advertise_urls().is_empty() {
+        cluster.peer_listen_urls()
+    } else {
+        cluster.peer_advertise_urls()
+    };
+    let members = discover_members(domain, cluster.name(), local_peer_urls).await?;
+    let cluster = cluster.clone().with_peers(members);
+    Ok(config.with_cluster(cluster))
 }
@@ -0,0 +1,111 @@
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+};
+
+use anyhow::{bail, Result};
+use hickory_resolver::{error::ResolveErrorKind, proto::rr::rdata::SRV, TokioAsyncResolver};
+use tracing::{debug, info};
+
+/// Services whose SRV records list the peer endpoints of the members, with the
+/// scheme of the endpoints
+const PEER_SERVICES: [(&str, &str); 2] = [
+    ("_xline-server-ssl._tcp", "https"),
+    ("_xline-server._tcp", "http"),
+];
+
+/// Discover the initial members from the SRV records of the domain
+///
+/// The peer endpoints of the members are listed by the SRV records of
+/// `_xline-server-ssl._tcp.<domain>` (https) and `_xline-server._tcp.<domain>`
+/// (http), which are resolved with the system resolver configuration. A
+/// member is named after the first label of its target, e.g. `xline-0` for
+/// `xline-0.xline.default.svc.cluster.local`, except the local member, which
+/// is found by its peer urls and keeps the given name.
+///
+/// # Errors
+///
+/// Return error if the records can not be resolved or do not contain the
+/// local member
+pub(crate) async fn discover_members(
+    domain: &str,
+    name: &str,
+    local_peer_urls: &[String],
+) -> Result<HashMap<String, Vec<String>>> {
+    let resolver = TokioAsyncResolver::tokio_from_system_conf()?;
+    let local_addrs = resolve_urls(local_peer_urls).await;
+    let mut members: HashMap<String, Vec<String>> = HashMap::new();
+    for (service, scheme) in PEER_SERVICES {
+        let records = match resolver.srv_lookup(format!("{service}.{domain}")).await {
+            Ok(lookup) => lookup.iter().cloned().collect(),
+            Err(e) if matches!(*e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => vec![],
+            Err(e) => return Err(e.into()),
+        };
+        for record in records {
+            let (target, url) = peer_url(scheme, &record);
+            let is_local = local_peer_urls.contains(&url)
+                || resolve_urls(&[url.clone()])
+                    .await
+                    .iter()
+                    .any(|addr| local_addrs.contains(addr));
+            let member_name = if is_local {
+                name
+            } else {
+                target.split('.').next().unwrap_or(&target)
+            };
+            debug!("discovered member {member_name} at {url}");
+            members.entry(member_name.to_owned()).or_default().push(url);
+        }
+    }
+    if !members.contains_key(name) {
+        bail!("the SRV records of {domain} do not contain the peer urls of {name}");
+    }
+    info!("discovered initial members from {domain}: {members:?}");
+    Ok(members)
+}
+
+/// Get the target of a SRV record without the trailing dot and the peer url
+/// it points to
+fn peer_url(scheme: &str, record: &SRV) -> (String, String) {
+    let target = record.target().to_utf8().trim_end_matches('.').to_owned();
+    let url = format!("{scheme}://{target}:{}", record.port());
+    (target, url)
+}
+
+/// Resolve the socket addresses of urls, ignoring the unresolvable ones
+async fn resolve_urls(urls: &[String]) -> HashSet<SocketAddr> {
+    let mut addrs = HashSet::new();
+    for url in urls {
+        let host = url.split_once("://").map_or(url.as_str(), |(_, host)| host);
+        if let Ok(resolved) = real_tokio::net::lookup_host(host).await {
+            addrs.extend(resolved);
+        }
+    }
+    addrs
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use hickory_resolver::proto::rr::Name;
+
+    use super::*;
+
+    #[test]
+    fn test_peer_url_should_trim_the_root_label() {
+        let fqdn = SRV::new(10, 100, 2380, Name::from_str("node0.xline.svc.").unwrap());
+        assert_eq!(
+            peer_url("https", &fqdn),
+            (
+                "node0.xline.svc".to_owned(),
+                "https://node0.xline.svc:2380".to_owned()
+            )
+        );
+        let relative = SRV::new(10, 100, 2381, Name::from_str("node1").unwrap());
+        assert_eq!(
+            peer_url("http", &relative),
+            ("node1".to_owned(), "http://node1:2381".to_owned())
+        );
+    }
+}
@@ -1,5 +1,7 @@
 /// Xline command line arguments
 mod args;
+/// Discovery of the initial members
+mod discovery;
 /// Xline tracing init
 mod trace;
 
@@ -2,8 +2,8 @@ use std::{error::Error, iter, path::PathBuf};
 
 use test_macros::abort_on_panic;
 use utils::config::{
-    AuthConfig, ClusterConfig, CompactConfig, LogConfig, MetricsConfig, StorageConfig, TlsConfig,
-    TraceConfig, XlineServerConfig,
+    AuthConfig, ClusterConfig, CompactConfig, LogConfig, MetricsConfig, StorageConfig,
+    StreamTokenExpiry, TlsConfig, TraceConfig, XlineServerConfig,
 };
 use xline_test_utils::{
     enable_auth, set_user, types::kv::RangeOptions, Client, ClientOptions, Cluster,
@@ -222,7 +222,11 @@ fn configs_with_auth(size: usize) -> Vec<XlineServerConfig> {
             StorageConfig::default(),
             LogConfig::default(),
             TraceConfig::default(),
-            AuthConfig::new(auth_public_key, auth_private_key),
+            AuthConfig::new(
+                auth_public_key,
+                auth_private_key,
+                StreamTokenExpiry::default(),
+            ),
             CompactConfig::default(),
             TlsConfig::default(),
             MetricsConfig::default(),
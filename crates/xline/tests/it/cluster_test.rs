@@ -1,7 +1,12 @@
-use std::{error::Error, time::Duration};
+use std::{collections::HashMap, error::Error, path::Path, time::Duration};
 
 use test_macros::abort_on_panic;
 use tokio::{net::TcpListener, time::sleep};
+use utils::config::{
+    AuthConfig, ClientConfig, ClusterConfig, CompactConfig, CurpConfig, CurpConfigBuilder,
+    EngineConfig, InitialClusterState, ServerTimeout, StorageConfig, TlsConfig,
+};
+use xline::server::XlineServer;
 use xline_client::{Client, ClientOptions};
 use xline_test_utils::Cluster;
 
@@ -87,3 +92,177 @@ async fn xline_update_node() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+#[tokio::test(flavor = "multi_thread")]
+#[abort_on_panic]
+async fn xline_add_node_with_used_peer_urls_should_fail() -> Result<(), Box<dyn Error>> {
+    let mut cluster = Cluster::new(3).await;
+    cluster.start().await;
+    let mut cluster_client = cluster.client().await.cluster_client();
+    let list_res = cluster_client.member_list(false).await?;
+    let used_peer_urls = list_res.members[0].peer_ur_ls.clone();
+    assert!(cluster_client
+        .member_add(used_peer_urls, false)
+        .await
+        .is_err());
+    assert!(cluster_client
+        .member_add(Vec::<String>::new(), false)
+        .await
+        .is_err());
+    let list_res = cluster_client.member_list(false).await?;
+    assert_eq!(list_res.members.len(), 3);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[abort_on_panic]
+async fn xline_join_without_member_add_should_fail() -> Result<(), Box<dyn Error>> {
+    let mut cluster = Cluster::new(3).await;
+    cluster.start().await;
+    let new_node_peer_listener = TcpListener::bind("0.0.0.0:0").await?;
+    let new_node_peer_url = format!("http://{}", new_node_peer_listener.local_addr()?);
+    let mut peers: HashMap<_, _> = (0..3)
+        .map(|i| (format!("server{i}"), vec![cluster.get_peer_url(i)]))
+        .collect();
+    _ = peers.insert("server3".to_owned(), vec![new_node_peer_url.clone()]);
+    let cluster_config = ClusterConfig::new(
+        "server3".to_owned(),
+        vec![new_node_peer_url.clone()],
+        vec![new_node_peer_url],
+        vec![],
+        vec![],
+        peers,
+        false,
+        CurpConfig::default(),
+        ClientConfig::default(),
+        ServerTimeout::default(),
+        InitialClusterState::Existing,
+    );
+    let result = XlineServer::new(
+        cluster_config,
+        StorageConfig::default(),
+        CompactConfig::default(),
+        AuthConfig::default(),
+        TlsConfig::default(),
+    )
+    .await;
+    assert!(result.is_err());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[abort_on_panic]
+async fn xline_client_should_follow_removed_member() -> Result<(), Box<dyn Error>> {
+    let mut cluster = Cluster::new(3).await;
+    cluster.start().await;
+    let first_client_url = cluster.get_client_url(0);
+    let client = Client::connect(
+        [first_client_url.clone()],
+        ClientOptions::default().with_auto_sync_interval(Duration::from_millis(100)),
+    )
+    .await?;
+    let mut cluster_client = client.cluster_client();
+    let list_res = cluster_client.member_list(false).await?;
+    let remove_id = list_res
+        .members
+        .iter()
+        .find(|m| m.client_ur_ls.contains(&first_client_url))
+        .unwrap()
+        .id;
+    // wait for the client to sync the endpoints of all the members
+    sleep(Duration::from_millis(500)).await;
+    let remove_res = cluster_client.member_remove(remove_id).await?;
+    assert!(remove_res.members.iter().all(|m| m.id != remove_id));
+    sleep(Duration::from_millis(500)).await;
+
+    let mut maintenance_client = client.maintenance_client();
+    for _ in 0..10 {
+        let status = maintenance_client.status().await?;
+        assert_ne!(status.header.unwrap().member_id, remove_id);
+    }
+    Ok(())
+}
+
+/// Config of a member whose curp storage is in the data dir
+fn rocks_cluster_config(
+    name: &str,
+    peer_url: &str,
+    mut peers: HashMap<String, Vec<String>>,
+    data_dir: &Path,
+) -> ClusterConfig {
+    _ = peers.insert(name.to_owned(), vec![peer_url.to_owned()]);
+    let curp_config = CurpConfigBuilder::default()
+        .engine_cfg(EngineConfig::RocksDB(data_dir.to_path_buf()))
+        .build()
+        .unwrap();
+    ClusterConfig::new(
+        name.to_owned(),
+        vec![peer_url.to_owned()],
+        vec![peer_url.to_owned()],
+        vec![],
+        vec![],
+        peers,
+        false,
+        curp_config,
+        ClientConfig::default(),
+        ServerTimeout::default(),
+        InitialClusterState::New,
+    )
+}
+
+async fn new_server(cluster_config: ClusterConfig) -> anyhow::Result<XlineServer> {
+    XlineServer::new(
+        cluster_config,
+        StorageConfig::default(),
+        CompactConfig::default(),
+        AuthConfig::default(),
+        TlsConfig::default(),
+    )
+    .await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[abort_on_panic]
+async fn xline_restart_should_keep_member_identity() -> Result<(), Box<dyn Error>> {
+    let data_dir = tempfile::tempdir()?;
+    let peer_url = format!(
+        "http://{}",
+        TcpListener::bind("0.0.0.0:0").await?.local_addr()?
+    );
+    let config = rocks_cluster_config("server0", &peer_url, HashMap::new(), data_dir.path());
+    drop(new_server(config.clone()).await?);
+    // the data dir is reused by the same member
+    drop(new_server(config).await?);
+
+    // the data dir is reused by another member
+    let other_url = format!(
+        "http://{}",
+        TcpListener::bind("0.0.0.0:0").await?.local_addr()?
+    );
+    let config = rocks_cluster_config("server1", &peer_url, HashMap::new(), data_dir.path());
+    assert!(new_server(config).await.is_err());
+    let config = rocks_cluster_config("server0", &other_url, HashMap::new(), data_dir.path());
+    assert!(new_server(config).await.is_err());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[abort_on_panic]
+async fn xline_restart_with_data_dir_of_another_cluster_should_fail() -> Result<(), Box<dyn Error>>
+{
+    let mut cluster = Cluster::new(3).await;
+    cluster.start().await;
+    let data_dir = tempfile::tempdir()?;
+    let peer_url = format!(
+        "http://{}",
+        TcpListener::bind("0.0.0.0:0").await?.local_addr()?
+    );
+    let peers = HashMap::from([("server0".to_owned(), vec![cluster.get_peer_url(0)])]);
+    let config = rocks_cluster_config("server3", &peer_url, peers, data_dir.path());
+    // server0 does not know server3, so the member is bootstrapped in a new cluster
+    drop(new_server(config.clone()).await?);
+
+    // the peers of the recovered membership are in another cluster
+    assert!(new_server(config).await.is_err());
+    Ok(())
+}
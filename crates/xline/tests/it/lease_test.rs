@@ -1,8 +1,10 @@
 use std::{error::Error, time::Duration};
 
+use futures::stream;
 use test_macros::abort_on_panic;
 use tracing::info;
 use xline_test_utils::{types::kv::PutOptions, Client, ClientOptions, Cluster};
+use xlineapi::{EventType, LeaseClient, LeaseKeepAliveRequest};
 
 #[tokio::test(flavor = "multi_thread")]
 #[abort_on_panic]
@@ -35,6 +37,49 @@ async fn test_lease_expired() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+#[abort_on_panic]
+async fn test_lease_expired_should_notify_watchers_of_all_members() -> Result<(), Box<dyn Error>> {
+    let mut cluster = Cluster::new(3).await;
+    cluster.start().await;
+    let client = cluster.client().await;
+
+    let mut streams = vec![];
+    for i in 0..3 {
+        let mut watch_client =
+            Client::connect(vec![cluster.get_client_url(i)], ClientOptions::default())
+                .await?
+                .watch_client();
+        let (watcher, stream) = watch_client.watch("foo", None).await?;
+        streams.push((watcher, stream));
+    }
+
+    let lease_id = client.lease_client().grant(1, None).await?.id;
+    let _ = client
+        .kv_client()
+        .put(
+            "foo",
+            "bar",
+            Some(PutOptions::default().with_lease(lease_id)),
+        )
+        .await?;
+
+    #[allow(clippy::as_conversions)] // this cast is always safe
+    let (put, delete) = (EventType::Put as i32, EventType::Delete as i32);
+    for (_watcher, mut stream) in streams {
+        let mut types = vec![];
+        while types.len() < 2 {
+            let res = tokio::time::timeout(Duration::from_secs(10), stream.message())
+                .await??
+                .unwrap();
+            types.extend(res.events.iter().map(|e| e.r#type));
+        }
+        assert_eq!(types, vec![put, delete]);
+    }
+
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 #[abort_on_panic]
 async fn test_lease_keep_alive() -> Result<(), Box<dyn Error>> {
@@ -85,3 +130,109 @@ async fn test_lease_keep_alive() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+#[tokio::test(flavor = "multi_thread")]
+#[abort_on_panic]
+async fn test_lease_keep_alive_should_multiplex_leases() -> Result<(), Box<dyn Error>> {
+    let mut cluster = Cluster::new(3).await;
+    cluster.start().await;
+    let non_leader_ep = cluster.get_client_url(1);
+    let client = cluster.client().await;
+
+    let lease1 = client.lease_client().grant(60, None).await?.id;
+    let lease2 = client.lease_client().grant(30, None).await?.id;
+    let revoked = client.lease_client().grant(60, None).await?.id;
+    let _resp = client.lease_client().revoke(revoked).await?;
+
+    let mut lease_client = LeaseClient::connect(non_leader_ep).await?;
+    let requests = [lease1, revoked, lease2, lease1].map(|id| LeaseKeepAliveRequest { id });
+    let mut responses = lease_client
+        .lease_keep_alive(stream::iter(requests))
+        .await?
+        .into_inner();
+    let mut results = vec![];
+    while let Some(resp) = responses.message().await? {
+        results.push((resp.id, resp.ttl));
+    }
+    assert_eq!(
+        results,
+        vec![(lease1, 60), (revoked, 0), (lease2, 30), (lease1, 60)]
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[abort_on_panic]
+async fn test_lease_keep_alive_should_be_forwarded_to_new_leader() -> Result<(), Box<dyn Error>> {
+    let mut cluster = Cluster::new(3).await;
+    cluster.start().await;
+    let urls: Vec<_> = (0..3).map(|idx| cluster.get_client_url(idx)).collect();
+    let client = cluster.client().await;
+    let lease_id = client.lease_client().grant(60, None).await?.id;
+    let leader = client.maintenance_client().status().await?.leader;
+
+    let mut followers = vec![];
+    for url in urls {
+        let mut maintenance_client = Client::connect(vec![url.clone()], ClientOptions::default())
+            .await?
+            .maintenance_client();
+        let member_id = maintenance_client.status().await?.header.unwrap().member_id;
+        if member_id != leader {
+            followers.push((url, member_id, maintenance_client));
+        }
+    }
+    let (forward_url, _, mut forward_maintenance) = followers.remove(0);
+    let (_, target, _) = followers.remove(0);
+
+    let mut lease_client = LeaseClient::connect(forward_url).await?;
+    let (tx, rx) = futures::channel::mpsc::unbounded();
+    let mut responses = lease_client.lease_keep_alive(rx).await?.into_inner();
+    tx.unbounded_send(LeaseKeepAliveRequest { id: lease_id })?;
+    let resp = responses.message().await?.unwrap();
+    assert_eq!((resp.id, resp.ttl), (lease_id, 60));
+
+    let _resp = client.maintenance_client().move_leader(target).await?;
+    for _ in 0..50 {
+        if forward_maintenance.status().await?.leader == target {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    // the request the former leader did not respond to is sent to the new leader
+    tx.unbounded_send(LeaseKeepAliveRequest { id: lease_id })?;
+    let resp = tokio::time::timeout(Duration::from_secs(10), responses.message())
+        .await??
+        .unwrap();
+    assert_eq!((resp.id, resp.ttl), (lease_id, 60));
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[abort_on_panic]
+async fn test_lease_time_to_live_with_attached_keys() -> Result<(), Box<dyn Error>> {
+    let mut cluster = Cluster::new(3).await;
+    cluster.start().await;
+    let client = cluster.client().await;
+    let mut lease_client = client.lease_client();
+    let kv_client = client.kv_client();
+
+    let lease_id = lease_client.grant(60, None).await?.id;
+    for key in ["b", "a", "c"] {
+        let _ = kv_client
+            .put(key, "v", Some(PutOptions::default().with_lease(lease_id)))
+            .await?;
+    }
+    let res = lease_client.time_to_live(lease_id, true).await?;
+    assert_eq!(res.keys, vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]);
+    let res = lease_client.time_to_live(lease_id, false).await?;
+    assert!(res.keys.is_empty());
+
+    let _ = kv_client.delete("a", None).await?;
+    let res = lease_client.time_to_live(lease_id, true).await?;
+    assert_eq!(res.keys, vec![b"b".to_vec(), b"c".to_vec()]);
+
+    Ok(())
+}
@@ -1,11 +1,18 @@
-use std::{path::PathBuf, time::Duration};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use test_macros::abort_on_panic;
 use tokio::io::AsyncWriteExt;
+use utils::config::XlineServerConfig;
 #[cfg(test)]
 use xline::restore::restore;
 use xline_client::error::XlineClientError;
-use xline_test_utils::{Client, ClientOptions, Cluster};
+use xline_test_utils::{
+    types::kv::{TxnOp, TxnRequest},
+    Client, ClientOptions, Cluster,
+};
 use xlineapi::{execute_error::ExecuteError, AlarmAction, AlarmType};
 
 #[tokio::test(flavor = "multi_thread")]
@@ -20,24 +27,9 @@ async fn test_snapshot_and_restore() -> Result<(), Box<dyn std::error::Error>> {
         .cloned()
         .map(Cluster::default_rocks_config_with_path)
         .collect();
-    {
-        let mut cluster = Cluster::new_rocks(3).await;
-        cluster.start().await;
-        let client = cluster.client().await.kv_client();
-        let _ignore = client.put("key", "value", None).await?;
-        tokio::time::sleep(Duration::from_millis(100)).await; // TODO: use `propose_index` and remove this sleep after we finished our client.
-        let mut maintenance_client =
-            Client::connect(vec![cluster.get_client_url(0)], ClientOptions::default())
-                .await?
-                .maintenance_client();
-        let mut stream = maintenance_client.snapshot().await?;
-        let mut snapshot = tokio::fs::File::create(&snapshot_path).await?;
-        while let Some(chunk) = stream.message().await? {
-            snapshot.write_all(chunk.blob.as_slice()).await?;
-        }
-    }
+    save_snapshot(&snapshot_path).await?;
     for restore_dir in restore_dirs {
-        restore(&snapshot_path, &restore_dir).await?;
+        let _summary = restore(&snapshot_path, &restore_dir, None).await?;
     }
     let mut new_cluster = Cluster::new_with_configs(restore_cluster_configs).await;
     new_cluster.start().await;
@@ -50,6 +42,65 @@ async fn test_snapshot_and_restore() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+#[abort_on_panic]
+async fn members_should_restore_from_snapshot_at_startup() -> Result<(), Box<dyn std::error::Error>>
+{
+    let dir = PathBuf::from("/tmp/members_should_restore_from_snapshot_at_startup");
+    tokio::fs::create_dir_all(&dir).await?;
+    let snapshot_path = dir.join("snapshot");
+    save_snapshot(&snapshot_path).await?;
+    let restore_cluster_configs = (0..3)
+        .map(|i| {
+            let config = Cluster::default_rocks_config_with_path(dir.join(format!("data_{i}")));
+            XlineServerConfig::new(
+                config.cluster().clone(),
+                config
+                    .storage()
+                    .clone()
+                    .with_restore_from(Some(snapshot_path.clone())),
+                config.log().clone(),
+                config.trace().clone(),
+                config.auth().clone(),
+                *config.compact(),
+                config.tls().clone(),
+                config.metrics().clone(),
+            )
+        })
+        .collect();
+    let mut new_cluster = Cluster::new_with_configs(restore_cluster_configs).await;
+    new_cluster.start().await;
+    let client = new_cluster.client().await.kv_client();
+    let res = client.range("key", None).await?;
+    assert_eq!(res.kvs.len(), 1);
+    assert_eq!(res.kvs[0].value, b"value");
+    // the restored members go on from the revision of the snapshot
+    let restored_revision = res.kvs[0].mod_revision;
+    let res = client.put("key", "new_value", None).await?;
+    assert!(res.header.unwrap().revision > restored_revision);
+    tokio::fs::remove_dir_all(&dir).await?;
+    Ok(())
+}
+
+/// Put a key to a new cluster and save its snapshot to the path
+async fn save_snapshot(snapshot_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let mut cluster = Cluster::new_rocks(3).await;
+    cluster.start().await;
+    let client = cluster.client().await.kv_client();
+    let _ignore = client.put("key", "value", None).await?;
+    tokio::time::sleep(Duration::from_millis(100)).await; // TODO: use `propose_index` and remove this sleep after we finished our client.
+    let mut maintenance_client =
+        Client::connect(vec![cluster.get_client_url(0)], ClientOptions::default())
+            .await?
+            .maintenance_client();
+    let mut stream = maintenance_client.snapshot().await?;
+    let mut snapshot = tokio::fs::File::create(snapshot_path).await?;
+    while let Some(chunk) = stream.message().await? {
+        snapshot.write_all(chunk.blob.as_slice()).await?;
+    }
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 #[abort_on_panic]
 async fn leader_should_detect_no_space_alarm() {
@@ -98,6 +149,98 @@ async fn test_alarm(idx: usize) {
     assert!(!res.alarms.is_empty());
 }
 
+#[tokio::test(flavor = "multi_thread")]
+#[abort_on_panic]
+async fn exceeding_quota_should_reject_puts_before_proposed() {
+    let configs = (0..3)
+        .map(|_| Cluster::default_quota_config(8 * 1024))
+        .collect();
+    let mut cluster = Cluster::new_with_configs(configs).await;
+    cluster.start().await;
+    let mut etcd_client = etcd_client::Client::connect([cluster.get_client_url(0)], None)
+        .await
+        .unwrap();
+
+    let mut rejected = None;
+    for i in 0..100u8 {
+        if let Err(err) = etcd_client.put(vec![i], vec![i; 1024], None).await {
+            let etcd_client::Error::GRpcStatus(status) = err else {
+                panic!("unexpected error: {err}");
+            };
+            assert!(status.message().contains("database space exceeded"));
+            rejected = Some(i);
+            break;
+        }
+    }
+    let rejected = rejected.expect("the quota should be exceeded");
+    assert!(rejected > 0, "the first put should fit in the quota");
+    // the rejected put is never proposed
+    let res = etcd_client.get(vec![rejected], None).await.unwrap();
+    assert!(res.kvs().is_empty());
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let res = etcd_client
+        .alarm(
+            etcd_client::AlarmAction::Get,
+            etcd_client::AlarmType::None,
+            None,
+        )
+        .await
+        .unwrap();
+    assert!(!res.alarms().is_empty());
+    // reads, deletes and compactions are still served
+    let stored = vec![rejected - 1];
+    let res = etcd_client.get(stored.clone(), None).await.unwrap();
+    assert_eq!(res.kvs().len(), 1);
+    let res = etcd_client.delete(stored, None).await.unwrap();
+    assert_eq!(res.deleted(), 1);
+    let revision = res.header().unwrap().revision();
+    let _resp = etcd_client.compact(revision, None).await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[abort_on_panic]
+async fn test_active_alarm_should_limit_requests() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cluster = Cluster::new(3).await;
+    cluster.start().await;
+    let client = cluster.client().await;
+    let k_client = client.kv_client();
+    let mut m_client = client.maintenance_client();
+    let _resp = k_client.put("key", "value", None).await?;
+
+    let _resp = m_client
+        .alarm(AlarmAction::Activate, 0, AlarmType::Nospace)
+        .await?;
+    let err = k_client.put("key", "value", None).await.unwrap_err();
+    assert!(matches!(
+        err,
+        XlineClientError::ExecuteError(ExecuteError::Nospace)
+    ));
+    // requests that take no more space are still served
+    let read_only_txn = TxnRequest::new().and_then(&[TxnOp::range("key", None)][..]);
+    let res = k_client.txn(read_only_txn).await?;
+    assert!(res.succeeded);
+    let res = k_client.delete("key", None).await?;
+    assert_eq!(res.deleted, 1);
+
+    let _resp = m_client
+        .alarm(AlarmAction::Activate, 0, AlarmType::Corrupt)
+        .await?;
+    let err = k_client.range("key", None).await.unwrap_err();
+    assert!(matches!(
+        err,
+        XlineClientError::ExecuteError(ExecuteError::DbError(_))
+    ));
+
+    for alarm in [AlarmType::Corrupt, AlarmType::Nospace] {
+        let _resp = m_client.alarm(AlarmAction::Deactivate, 0, alarm).await?;
+    }
+    let res = m_client.alarm(AlarmAction::Get, 0, AlarmType::None).await?;
+    assert!(res.alarms.is_empty());
+    let _resp = k_client.put("key", "value", None).await?;
+
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 #[abort_on_panic]
 async fn test_status() -> Result<(), Box<dyn std::error::Error>> {
@@ -117,3 +260,102 @@ async fn test_status() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[tokio::test(flavor = "multi_thread")]
+#[abort_on_panic]
+async fn test_move_leader() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cluster = Cluster::new(3).await;
+    cluster.start().await;
+    let client = cluster.client().await;
+    let mut maintenance_client = client.maintenance_client();
+    let leader = maintenance_client.status().await?.leader;
+    let target = client
+        .cluster_client()
+        .member_list(false)
+        .await?
+        .members
+        .into_iter()
+        .map(|member| member.id)
+        .find(|&id| id != leader)
+        .unwrap();
+
+    let _resp = maintenance_client.move_leader(target).await?;
+    // the connected member may learn the new leader later
+    let mut new_leader = leader;
+    for _ in 0..50 {
+        new_leader = maintenance_client.status().await?.leader;
+        if new_leader == target {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(new_leader, target);
+
+    assert!(maintenance_client.move_leader(1234).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[abort_on_panic]
+async fn test_defragment() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cluster = Cluster::new_rocks(3).await;
+    cluster.start().await;
+    let client = cluster.client().await;
+    for i in 0..100 {
+        let _resp = client
+            .kv_client()
+            .put(format!("key{i}"), vec![0; 1024], None)
+            .await?;
+    }
+    for i in 0..50 {
+        let _resp = client.kv_client().delete(format!("key{i}"), None).await?;
+    }
+    let mut maintenance_client = client.maintenance_client();
+    let res = maintenance_client.defragment().await?;
+    assert!(res.header.is_some());
+
+    let res = client.kv_client().range("key0", None).await?;
+    assert!(res.kvs.is_empty());
+    let res = client.kv_client().range("key99", None).await?;
+    assert_eq!(res.kvs.len(), 1);
+    let res = maintenance_client.status().await?;
+    assert!(res.db_size_in_use <= res.db_size);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[abort_on_panic]
+async fn test_hash_kv_should_be_same_on_all_members() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cluster = Cluster::new(3).await;
+    cluster.start().await;
+    let client = cluster.client().await;
+    for i in 0..10 {
+        let _resp = client
+            .kv_client()
+            .put(format!("key{i}"), "value", None)
+            .await?;
+    }
+    let revision = client
+        .kv_client()
+        .put("key0", "new value", None)
+        .await?
+        .header
+        .unwrap()
+        .revision;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let mut hashes = vec![];
+    for i in 0..3 {
+        let mut maintenance_client =
+            Client::connect(vec![cluster.get_client_url(i)], ClientOptions::default())
+                .await?
+                .maintenance_client();
+        let res = maintenance_client.hash_kv(revision).await?;
+        hashes.push((res.hash, res.compact_revision));
+    }
+    assert!(hashes.windows(2).all(|w| w[0] == w[1]), "{hashes:?}");
+
+    Ok(())
+}
@@ -74,6 +74,18 @@ async fn test_certificate_authenticate() {
     assert!(res.is_ok());
 }
 
+#[tokio::test(flavor = "multi_thread")]
+#[abort_on_panic]
+async fn test_peer_mtls() {
+    let mut cluster = Cluster::new_with_configs(peer_mtls_configs(3)).await;
+    cluster.start().await;
+
+    // the client listener is independent from the peer listener
+    let client = cluster.client().await;
+    let res = client.kv_client().put("foo", "bar", None).await;
+    assert!(res.is_ok());
+}
+
 fn configs_with_tls_config(size: usize, tls_config: TlsConfig) -> Vec<XlineServerConfig> {
     iter::repeat(tls_config)
         .map(|tls_config| {
@@ -136,3 +148,15 @@ fn mtls_configs(size: usize) -> Vec<XlineServerConfig> {
         ),
     )
 }
+
+fn peer_mtls_configs(size: usize) -> Vec<XlineServerConfig> {
+    configs_with_tls_config(
+        size,
+        TlsConfig::default().with_curp_tls(
+            Some(PathBuf::from("../../fixtures/ca.crt")),
+            Some(PathBuf::from("../../fixtures/server.crt")),
+            Some(PathBuf::from("../../fixtures/server.key")),
+            true,
+        ),
+    )
+}
@@ -43,6 +43,9 @@ impl From<&RequestWrapper> for u8 {
             RequestWrapper::LeaseRevokeRequest(_) => 23,
             RequestWrapper::LeaseLeasesRequest(_) => 24,
             RequestWrapper::AlarmRequest(_) => 25,
+            RequestWrapper::LeaseCheckpointRequest(_) => 26,
+            RequestWrapper::DowngradeRequest(_) => 27,
+            RequestWrapper::ClusterVersionSetRequest(_) => 28,
         }
     }
 }
@@ -89,11 +92,17 @@ impl RequestClassifier for RequestWrapper {
             RequestWrapper::LeaseGrantRequest(_)
                 | RequestWrapper::LeaseRevokeRequest(_)
                 | RequestWrapper::LeaseLeasesRequest(_)
+                | RequestWrapper::LeaseCheckpointRequest(_)
         )
     }
     #[inline]
     fn is_alarm_backend(&self) -> bool {
-        matches!(self, RequestWrapper::AlarmRequest(_))
+        matches!(
+            self,
+            RequestWrapper::AlarmRequest(_)
+                | RequestWrapper::DowngradeRequest(_)
+                | RequestWrapper::ClusterVersionSetRequest(_)
+        )
     }
     #[inline]
     fn is_put(&self) -> bool {
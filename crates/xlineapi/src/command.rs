@@ -11,8 +11,11 @@ use prost::Message;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    classifier::RequestClassifier, execute_error::ExecuteError, AuthInfo, PbCommand,
-    PbCommandResponse, PbKeyRange, PbSyncResponse, RequestWrapper, ResponseWrapper,
+    classifier::RequestClassifier,
+    execute_error::ExecuteError,
+    key_util::{self, UNBOUNDED},
+    AuthInfo, PbCommand, PbCommandResponse, PbKeyRange, PbSyncResponse, RequestWrapper,
+    ResponseWrapper,
 };
 
 /// The curp client trait object on the command of xline
@@ -20,11 +23,6 @@ use crate::{
 /// TODO: use `type CurpClient = impl ClientApi<...>` when `type_alias_impl_trait` stabilized
 pub type CurpClient = dyn ClientApi<Error = tonic::Status, Cmd = Command> + Sync + Send + 'static;
 
-/// Range start and end to get all keys
-const UNBOUNDED: &[u8] = &[0_u8];
-/// Range end to get one key
-const ONE_KEY: &[u8] = &[];
-
 thread_local! {
     /// A global cache to store conflict rules. The rules will not change, so it's safe to use a global cache.
     static CONFLICT_RULES_CACHE: RefCell<HashMap<(u8, u8), bool>> = RefCell::new(HashMap::new());
@@ -45,9 +43,15 @@ impl KeyRange {
     pub fn new(start: impl Into<Vec<u8>>, end: impl Into<Vec<u8>>) -> Self {
         let key_vec = start.into();
         let range_end_vec = end.into();
+        if key_util::is_single_key(&range_end_vec) {
+            // `[0]` is a valid single key here rather than the unbounded start
+            return Self {
+                key: Bound::Included(key_vec.clone()),
+                range_end: Bound::Included(key_vec),
+            };
+        }
         let range_end = match range_end_vec.as_slice() {
             UNBOUNDED => Bound::Unbounded,
-            ONE_KEY => Bound::Included(key_vec.clone()),
             _ => Bound::Excluded(range_end_vec),
         };
         let key = match key_vec.as_slice() {
@@ -134,21 +138,21 @@ impl KeyRange {
     /// Get end of range with prefix
     ///
     /// User will provide a start key when prefix is true, we need calculate the end key of `KeyRange`
-    #[allow(clippy::indexing_slicing)] // end[i] is always valid
     #[must_use]
     #[inline]
     pub fn get_prefix(key: impl AsRef<[u8]>) -> Vec<u8> {
-        let key = key.as_ref();
-        let mut end = key.to_vec();
-        for i in (0..key.len()).rev() {
-            if key[i] < 0xFF {
-                end[i] = end[i].wrapping_add(1);
-                end.truncate(i.wrapping_add(1));
-                return end;
-            }
+        key_util::prefix_end(key.as_ref())
+    }
+
+    /// Return if `KeyRange` cannot contain any key, e.g. the end is less than the start
+    #[must_use]
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        match (&self.key, &self.range_end) {
+            (Bound::Included(start), Bound::Included(end)) => start > end,
+            (Bound::Included(start), Bound::Excluded(end)) => start >= end,
+            _ => false,
         }
-        // next prefix does not exist (e.g., 0xffff);
-        vec![0]
     }
 
     /// unpack `KeyRange` to tuple
@@ -328,8 +332,7 @@ impl ConflictCheck for Command {
             (true, is_compaction, is_compaction)
         )(t_req, o_req);
         if let Some(first_step_res) = first_step {
-            CONFLICT_RULES_CACHE
-                .with_borrow_mut(|x| x.insert((t_req.into(), o_req.into()), first_step_res));
+            CONFLICT_RULES_CACHE.with_borrow_mut(|x| x.insert(cache_key, first_step_res));
         }
         first_step
             .or_else(|| {
@@ -339,6 +342,14 @@ impl ConflictCheck for Command {
                     |x, y| x.is_conflict_with_rev(y.revision)
                 )(t_req, o_req)
             })
+            .or_else(|| {
+                // a range reading a revision that will be compacted must not be reordered
+                swap_map!(
+                    RequestWrapper::RangeRequest,
+                    RequestWrapper::CompactionRequest,
+                    |x, y| x.revision > 0 && x.revision < y.revision
+                )(t_req, o_req)
+            })
             // the fallback map
             .or_else(|| {
                 let this_lease_ids = t_req.leases().into_iter().collect::<HashSet<_>>();
@@ -420,16 +431,14 @@ impl Command {
         self.auth_info = Some(auth_info)
     }
 
-    /// need check quota
+    /// need check quota, i.e. the request takes more space
     #[must_use]
     #[inline]
     pub fn need_check_quota(&self) -> bool {
         matches!(
             self.request,
-            RequestWrapper::LeaseGrantRequest(_)
-                | RequestWrapper::PutRequest(_)
-                | RequestWrapper::TxnRequest(_)
-        )
+            RequestWrapper::LeaseGrantRequest(_) | RequestWrapper::PutRequest(_)
+        ) || matches!(self.request, RequestWrapper::TxnRequest(ref req) if req.has_put())
     }
 }
 
@@ -622,6 +631,33 @@ mod test {
         assert!(!kr4.contains_key(b"e"));
     }
 
+    #[test]
+    fn test_key_range_agrees_with_key_util() {
+        let keys: &[&[u8]] = &[&[], &[0], &[0, 0], &[1], &[0xFE], &[0xFF], &[0xFF, 0xFF]];
+        for key in keys.iter().filter(|k| !k.is_empty()) {
+            for range_end in keys {
+                let range = KeyRange::new(*key, *range_end);
+                assert_eq!(
+                    range.is_empty(),
+                    key_util::is_empty_range(key, range_end),
+                    "key: {key:?}, range_end: {range_end:?}"
+                );
+                for target in keys.iter().filter(|k| !k.is_empty()) {
+                    assert_eq!(
+                        range.contains_key(target),
+                        key_util::range_contains(key, range_end, target),
+                        "key: {key:?}, range_end: {range_end:?}, target: {target:?}"
+                    );
+                }
+                let pb = PbKeyRange::from(range);
+                assert_eq!(
+                    (pb.key.as_slice(), pb.range_end.as_slice()),
+                    (*key, *range_end)
+                );
+            }
+        }
+    }
+
     #[test]
     fn test_cache_should_work() {
         let cmd1 = Command::new(RequestWrapper::AuthStatusRequest(AuthStatusRequest {
@@ -700,6 +736,45 @@ mod test {
         assert!(cmd6.is_conflict(&lease_leases_cmd)); // lease read and write
     }
 
+    #[test]
+    fn test_command_conflict_only_on_overlapping_keys() {
+        let put_cmd = |key: &str| {
+            Command::new(RequestWrapper::PutRequest(PutRequest {
+                key: key.into(),
+                value: b"value".to_vec(),
+                ..Default::default()
+            }))
+        };
+        let range_cmd = |key: &str, range_end: &[u8], revision: i64| {
+            Command::new(RequestWrapper::RangeRequest(RangeRequest {
+                key: key.into(),
+                range_end: range_end.to_vec(),
+                revision,
+                ..Default::default()
+            }))
+        };
+        let compaction_cmd = Command::new(RequestWrapper::CompactionRequest(CompactionRequest {
+            revision: 5,
+            physical: false,
+        }));
+
+        // puts without a lease only conflict on the same key
+        assert!(!put_cmd("a").is_conflict(&put_cmd("b")));
+        assert!(put_cmd("a").is_conflict(&put_cmd("a")));
+        // range end semantics
+        assert!(put_cmd("b").is_conflict(&range_cmd("a", b"c", 0)));
+        assert!(!put_cmd("c").is_conflict(&range_cmd("a", b"c", 0)));
+        assert!(put_cmd("z").is_conflict(&range_cmd("a", &[0], 0)));
+        assert!(put_cmd("a").is_conflict(&range_cmd("\0", &[0], 0)));
+        // an empty range touches no key
+        assert!(!put_cmd("b").is_conflict(&range_cmd("c", b"a", 0)));
+        assert!(range_cmd("c", b"a", 0).keys().is_empty());
+        // ranges reading compacted revisions conflict with the compaction
+        assert!(range_cmd("a", b"", 3).is_conflict(&compaction_cmd));
+        assert!(!range_cmd("a", b"", 5).is_conflict(&compaction_cmd));
+        assert!(!compaction_cmd.is_conflict(&range_cmd("a", b"", 0)));
+    }
+
     fn generate_txn_command(
         compare: Vec<Compare>,
         success: Vec<RequestOp>,
@@ -782,6 +857,37 @@ mod test {
         assert!(!compaction_cmd_2.is_conflict(&txn_cmd_3));
     }
 
+    #[test]
+    fn only_requests_taking_more_space_need_check_quota() {
+        let put_op = RequestOp {
+            request: Some(Request::RequestPut(PutRequest::default())),
+        };
+        let delete_op = RequestOp {
+            request: Some(Request::RequestDeleteRange(DeleteRangeRequest::default())),
+        };
+        let txn_with_put = TxnRequest {
+            compare: vec![],
+            success: vec![delete_op.clone()],
+            failure: vec![put_op],
+        };
+        let txn_without_put = TxnRequest {
+            compare: vec![],
+            success: vec![delete_op],
+            failure: vec![],
+        };
+        for (request, need_check) in [
+            (RequestWrapper::from(PutRequest::default()), true),
+            (RequestWrapper::from(LeaseGrantRequest::default()), true),
+            (RequestWrapper::from(txn_with_put), true),
+            (RequestWrapper::from(txn_without_put), false),
+            (RequestWrapper::from(DeleteRangeRequest::default()), false),
+            (RequestWrapper::from(RangeRequest::default()), false),
+            (RequestWrapper::from(CompactionRequest::default()), false),
+        ] {
+            assert_eq!(Command::new(request).need_check_quota(), need_check);
+        }
+    }
+
     #[test]
     fn command_serialization_is_ok() {
         let cmd = Command::new(RequestWrapper::PutRequest(PutRequest::default()));
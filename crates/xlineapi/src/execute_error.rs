@@ -99,6 +99,25 @@ pub enum ExecuteError {
     /// no space left in quota
     #[error("no space left in quota")]
     Nospace,
+
+    /// Key exceeds the max key size
+    #[error("key is too large")]
+    KeyTooLarge,
+    /// Request writes a value exceeding the max value size
+    #[error("request is too large")]
+    RequestTooLarge,
+    /// Range result exceeds the max range size
+    #[error("range result exceeds the limit of {0} bytes")]
+    RangeTooLarge(u64),
+    /// A maintenance operation of the storage is running, e.g. defragment
+    #[error("the storage is busy with a maintenance operation")]
+    StorageBusy,
+    /// The data of the cluster is corrupted
+    #[error("corrupt cluster: {0}")]
+    Corrupt(String),
+    /// The storage of the member is read-only after a persistent write error
+    #[error("storage is read-only: {0}")]
+    ReadOnly(String),
 }
 
 impl From<PbExecuteError> for ExecuteError {
@@ -140,6 +159,12 @@ impl From<PbExecuteError> for ExecuteError {
             PbExecuteError::DbError(e) => ExecuteError::DbError(e),
             PbExecuteError::PermissionDenied(_) => ExecuteError::PermissionDenied,
             PbExecuteError::Nospace(_) => ExecuteError::Nospace,
+            PbExecuteError::KeyTooLarge(_) => ExecuteError::KeyTooLarge,
+            PbExecuteError::RequestTooLarge(_) => ExecuteError::RequestTooLarge,
+            PbExecuteError::RangeTooLarge(b) => ExecuteError::RangeTooLarge(b),
+            PbExecuteError::StorageBusy(_) => ExecuteError::StorageBusy,
+            PbExecuteError::Corrupt(e) => ExecuteError::Corrupt(e),
+            PbExecuteError::ReadOnly(e) => ExecuteError::ReadOnly(e),
         }
     }
 }
@@ -192,6 +217,12 @@ impl From<ExecuteError> for PbExecuteError {
             ExecuteError::DbError(e) => PbExecuteError::DbError(e),
             ExecuteError::PermissionDenied => PbExecuteError::PermissionDenied(()),
             ExecuteError::Nospace => PbExecuteError::Nospace(()),
+            ExecuteError::KeyTooLarge => PbExecuteError::KeyTooLarge(()),
+            ExecuteError::RequestTooLarge => PbExecuteError::RequestTooLarge(()),
+            ExecuteError::RangeTooLarge(b) => PbExecuteError::RangeTooLarge(b),
+            ExecuteError::StorageBusy => PbExecuteError::StorageBusy(()),
+            ExecuteError::Corrupt(e) => PbExecuteError::Corrupt(e),
+            ExecuteError::ReadOnly(e) => PbExecuteError::ReadOnly(e),
         }
     }
 }
@@ -310,6 +341,21 @@ impl From<ExecuteError> for tonic::Status {
             }
             ExecuteError::TokenNotProvided => (tonic::Code::InvalidArgument, err.to_string()),
             ExecuteError::DbError(_) => (tonic::Code::Internal, err.to_string()),
+            ExecuteError::KeyTooLarge => (
+                tonic::Code::InvalidArgument,
+                "etcdserver: key is too large".to_owned(),
+            ),
+            ExecuteError::RequestTooLarge => (
+                tonic::Code::InvalidArgument,
+                "etcdserver: request is too large".to_owned(),
+            ),
+            ExecuteError::RangeTooLarge(_) => (tonic::Code::ResourceExhausted, err.to_string()),
+            ExecuteError::StorageBusy => (tonic::Code::Unavailable, err.to_string()),
+            ExecuteError::Corrupt(_) => (
+                tonic::Code::DataLoss,
+                "etcdserver: corrupt cluster".to_owned(),
+            ),
+            ExecuteError::ReadOnly(_) => (tonic::Code::Unavailable, err.to_string()),
         };
 
         tonic::Status::new(code, message)
@@ -0,0 +1,183 @@
+//! Binary-safe helpers for keys and the `key`/`range_end` pairs of requests
+//!
+//! Every request touching a range of keys follows the etcd convention:
+//! * an empty `range_end` selects the single key `key`;
+//! * a `range_end` of `[0]` selects every key that is no less than `key`, so
+//!   `key == [0] && range_end == [0]` selects the whole keyspace;
+//! * otherwise the range is the half-open interval `[key, range_end)`.
+
+use std::cmp::Ordering;
+
+/// Range start and end to get all keys
+pub const UNBOUNDED: &[u8] = &[0_u8];
+/// Range end to get one key
+pub const ONE_KEY: &[u8] = &[];
+
+/// Get the smallest key that is greater than every key with the given prefix
+///
+/// Trailing `0xFF` bytes are dropped before the last byte is increased, if the
+/// prefix is empty or consists only of `0xFF` bytes, no such key exists and
+/// `[0]` is returned, which means the range has no upper bound.
+#[allow(clippy::indexing_slicing)] // end[i] is always valid
+#[must_use]
+#[inline]
+pub fn prefix_end(prefix: &[u8]) -> Vec<u8> {
+    let mut end = prefix.to_vec();
+    for i in (0..prefix.len()).rev() {
+        if prefix[i] < 0xFF {
+            end[i] = end[i].wrapping_add(1);
+            end.truncate(i.wrapping_add(1));
+            return end;
+        }
+    }
+    // next prefix does not exist (e.g., 0xffff);
+    UNBOUNDED.to_vec()
+}
+
+/// Return if the range selects only `key`
+#[must_use]
+#[inline]
+pub fn is_single_key(range_end: &[u8]) -> bool {
+    range_end == ONE_KEY
+}
+
+/// Return if the range has no upper bound
+#[must_use]
+#[inline]
+pub fn is_unbounded_end(range_end: &[u8]) -> bool {
+    range_end == UNBOUNDED
+}
+
+/// Return if the range selects the whole keyspace
+#[must_use]
+#[inline]
+pub fn is_whole_keyspace(key: &[u8], range_end: &[u8]) -> bool {
+    key == UNBOUNDED && range_end == UNBOUNDED
+}
+
+/// Return if the range cannot select any key, e.g. `range_end` is not greater
+/// than `key`
+#[must_use]
+#[inline]
+pub fn is_empty_range(key: &[u8], range_end: &[u8]) -> bool {
+    !is_single_key(range_end) && !is_unbounded_end(range_end) && key >= range_end
+}
+
+/// Return if `target` is selected by the range
+#[must_use]
+#[inline]
+pub fn range_contains(key: &[u8], range_end: &[u8], target: &[u8]) -> bool {
+    if is_single_key(range_end) {
+        return key == target;
+    }
+    key <= target && (is_unbounded_end(range_end) || target < range_end)
+}
+
+/// Compare two range ends as upper bounds, the unbounded end is greater than any
+/// other end
+#[must_use]
+#[inline]
+pub fn cmp_range_end(a: &[u8], b: &[u8]) -> Ordering {
+    match (is_unbounded_end(a), is_unbounded_end(b)) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        (false, false) => a.cmp(b),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Bytes that exercise the boundaries of a key
+    const ALPHABET: [u8; 4] = [0x00, 0x01, 0xFE, 0xFF];
+
+    /// All keys no longer than `max_len` built from `ALPHABET`
+    fn all_keys(max_len: usize) -> Vec<Vec<u8>> {
+        let mut keys = vec![vec![]];
+        let mut last = vec![vec![]];
+        for _ in 0..max_len {
+            last = last
+                .iter()
+                .flat_map(|k: &Vec<u8>| {
+                    ALPHABET.iter().map(move |b| {
+                        let mut next = k.clone();
+                        next.push(*b);
+                        next
+                    })
+                })
+                .collect();
+            keys.extend(last.iter().cloned());
+        }
+        keys
+    }
+
+    #[test]
+    fn test_prefix_end() {
+        assert_eq!(prefix_end(b"foo"), b"fop");
+        assert_eq!(prefix_end(&[b'a', 0xFF]), b"b");
+        assert_eq!(prefix_end(&[0x01, 0xFF, 0xFF]), vec![0x02]);
+        assert_eq!(prefix_end(&[0xFF, 0xFE]), vec![0xFF, 0xFF]);
+        assert_eq!(prefix_end(&[0xFF, 0xFF]), UNBOUNDED);
+        assert_eq!(prefix_end(&[]), UNBOUNDED);
+    }
+
+    #[test]
+    fn test_prefix_end_selects_exactly_the_prefixed_keys() {
+        let keys = all_keys(4);
+        for prefix in all_keys(3).into_iter().filter(|p| !p.is_empty()) {
+            let end = prefix_end(&prefix);
+            for key in keys.iter().filter(|k| !k.is_empty()) {
+                assert_eq!(
+                    range_contains(&prefix, &end, key),
+                    key.starts_with(&prefix),
+                    "prefix: {prefix:?}, end: {end:?}, key: {key:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_range_predicates_match_model() {
+        let keys = all_keys(2);
+        for key in &keys {
+            for range_end in &keys {
+                let empty = is_empty_range(key, range_end);
+                let mut selected_any = false;
+                for target in keys.iter().filter(|k| !k.is_empty()) {
+                    let expected = if range_end.is_empty() {
+                        target == key
+                    } else if range_end.as_slice() == UNBOUNDED {
+                        target >= key
+                    } else {
+                        target >= key && target < range_end
+                    };
+                    assert_eq!(
+                        range_contains(key, range_end, target),
+                        expected,
+                        "key: {key:?}, range_end: {range_end:?}, target: {target:?}"
+                    );
+                    selected_any |= expected;
+                }
+                if empty {
+                    assert!(!selected_any, "key: {key:?}, range_end: {range_end:?}");
+                }
+                if is_whole_keyspace(key, range_end) {
+                    assert!(keys
+                        .iter()
+                        .filter(|t| !t.is_empty())
+                        .all(|t| range_contains(key, range_end, t)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_cmp_range_end() {
+        assert_eq!(cmp_range_end(UNBOUNDED, UNBOUNDED), Ordering::Equal);
+        assert_eq!(cmp_range_end(UNBOUNDED, &[0xFF, 0xFF]), Ordering::Greater);
+        assert_eq!(cmp_range_end(b"a", UNBOUNDED), Ordering::Less);
+        assert_eq!(cmp_range_end(b"a", b"b"), Ordering::Less);
+    }
+}
@@ -176,6 +176,7 @@ pub mod classifier;
 pub mod command;
 pub mod execute_error;
 pub mod interval;
+pub mod key_util;
 pub mod request_validation;
 
 mod etcdserverpb {
@@ -231,6 +232,7 @@ pub use self::{
         cluster_client::ClusterClient,
         cluster_server::{Cluster, ClusterServer},
         compare::{CompareResult, CompareTarget, TargetUnion},
+        downgrade_request::DowngradeAction,
         kv_client::KvClient,
         kv_server::{Kv, KvServer},
         lease_client::LeaseClient,
@@ -241,6 +243,7 @@ pub use self::{
         request_op::Request,
         response_op::Response,
         watch_client::WatchClient,
+        watch_create_request::FilterType,
         watch_request::RequestUnion,
         watch_server::{Watch, WatchServer},
         AlarmMember, AlarmRequest, AlarmResponse, AlarmType, AuthDisableRequest,
@@ -254,18 +257,20 @@ pub use self::{
         AuthUserGetRequest, AuthUserGetResponse, AuthUserGrantRoleRequest,
         AuthUserGrantRoleResponse, AuthUserListRequest, AuthUserListResponse,
         AuthUserRevokeRoleRequest, AuthUserRevokeRoleResponse, AuthenticateRequest,
-        AuthenticateResponse, CompactionRequest, CompactionResponse, Compare, DefragmentRequest,
-        DefragmentResponse, DeleteRangeRequest, DeleteRangeResponse, DowngradeRequest,
-        DowngradeResponse, HashKvRequest, HashKvResponse, HashRequest, HashResponse,
-        LeaseGrantRequest, LeaseGrantResponse, LeaseKeepAliveRequest, LeaseKeepAliveResponse,
-        LeaseLeasesRequest, LeaseLeasesResponse, LeaseRevokeRequest, LeaseRevokeResponse,
-        LeaseStatus, LeaseTimeToLiveRequest, LeaseTimeToLiveResponse, Member, MemberAddRequest,
-        MemberAddResponse, MemberListRequest, MemberListResponse, MemberPromoteRequest,
-        MemberPromoteResponse, MemberRemoveRequest, MemberRemoveResponse, MemberUpdateRequest,
-        MemberUpdateResponse, MoveLeaderRequest, MoveLeaderResponse, PutRequest, PutResponse,
-        RangeRequest, RangeResponse, RequestOp, ResponseHeader, ResponseOp, SnapshotRequest,
-        SnapshotResponse, StatusRequest, StatusResponse, TxnRequest, TxnResponse,
-        WatchCancelRequest, WatchCreateRequest, WatchProgressRequest, WatchRequest, WatchResponse,
+        AuthenticateResponse, ClusterVersionSetRequest, ClusterVersionSetResponse,
+        CompactionRequest, CompactionResponse, Compare, DefragmentRequest, DefragmentResponse,
+        DeleteRangeRequest, DeleteRangeResponse, DowngradeRequest, DowngradeResponse,
+        HashKvRequest, HashKvResponse, HashRequest, HashResponse, LeaseCheckpoint,
+        LeaseCheckpointRequest, LeaseCheckpointResponse, LeaseGrantRequest, LeaseGrantResponse,
+        LeaseKeepAliveRequest, LeaseKeepAliveResponse, LeaseLeasesRequest, LeaseLeasesResponse,
+        LeaseRevokeRequest, LeaseRevokeResponse, LeaseStatus, LeaseTimeToLiveRequest,
+        LeaseTimeToLiveResponse, Member, MemberAddRequest, MemberAddResponse, MemberListRequest,
+        MemberListResponse, MemberPromoteRequest, MemberPromoteResponse, MemberRemoveRequest,
+        MemberRemoveResponse, MemberUpdateRequest, MemberUpdateResponse, MoveLeaderRequest,
+        MoveLeaderResponse, PutRequest, PutResponse, RangeRequest, RangeResponse, RequestOp,
+        ResponseHeader, ResponseOp, SnapshotRequest, SnapshotResponse, StatusRequest,
+        StatusResponse, TxnRequest, TxnResponse, WatchCancelRequest, WatchCreateRequest,
+        WatchProgressRequest, WatchRequest, WatchResponse,
     },
     leasepb::Lease as PbLease,
     mvccpb::{event::EventType, Event, KeyValue},
@@ -311,7 +316,10 @@ impl ResponseWrapper {
             ResponseWrapper::LeaseGrantResponse(ref mut resp) => &mut resp.header,
             ResponseWrapper::LeaseRevokeResponse(ref mut resp) => &mut resp.header,
             ResponseWrapper::LeaseLeasesResponse(ref mut resp) => &mut resp.header,
+            ResponseWrapper::LeaseCheckpointResponse(ref mut resp) => &mut resp.header,
             ResponseWrapper::AlarmResponse(ref mut resp) => &mut resp.header,
+            ResponseWrapper::DowngradeResponse(ref mut resp) => &mut resp.header,
+            ResponseWrapper::ClusterVersionSetResponse(ref mut resp) => &mut resp.header,
         };
         if let Some(ref mut header) = *header {
             header.revision = revision;
@@ -328,12 +336,26 @@ pub trait CommandAttr {
     fn leases(&self) -> Vec<i64>;
 }
 
+/// Key ranges that can contain keys, an empty range touches nothing so it
+/// never conflicts with other ranges
+fn non_empty_ranges(ranges: impl IntoIterator<Item = KeyRange>) -> Vec<KeyRange> {
+    ranges
+        .into_iter()
+        .filter(|range| !range.is_empty())
+        .collect()
+}
+
+/// The lease attached by a put, `0` means no lease is attached
+fn attached_lease(lease: i64) -> Option<i64> {
+    (lease != 0).then_some(lease)
+}
+
 impl CommandAttr for RangeRequest {
     fn keys(&self) -> Vec<KeyRange> {
-        vec![KeyRange::new(
+        non_empty_ranges([KeyRange::new(
             self.key.as_slice(),
             self.range_end.as_slice(),
-        )]
+        )])
     }
 
     fn leases(&self) -> Vec<i64> {
@@ -347,16 +369,16 @@ impl CommandAttr for PutRequest {
     }
 
     fn leases(&self) -> Vec<i64> {
-        vec![self.lease]
+        attached_lease(self.lease).into_iter().collect()
     }
 }
 
 impl CommandAttr for DeleteRangeRequest {
     fn keys(&self) -> Vec<KeyRange> {
-        vec![KeyRange::new(
+        non_empty_ranges([KeyRange::new(
             self.key.as_slice(),
             self.range_end.as_slice(),
-        )]
+        )])
     }
 
     fn leases(&self) -> Vec<i64> {
@@ -393,7 +415,7 @@ impl CommandAttr for TxnRequest {
             }
         }
 
-        keys
+        non_empty_ranges(keys)
     }
 
     fn leases(&self) -> Vec<i64> {
@@ -407,7 +429,7 @@ impl CommandAttr for TxnRequest {
         {
             match *op {
                 Request::RequestPut(ref req) => {
-                    leases.push(req.lease);
+                    leases.extend(attached_lease(req.lease));
                 }
                 Request::RequestTxn(ref req) => leases.append(&mut req.leases()),
                 Request::RequestDeleteRange(_) | Request::RequestRange(_) => {}
@@ -439,6 +461,9 @@ impl RequestWrapper {
             RequestWrapper::TxnRequest(ref req) => req.leases(),
             RequestWrapper::LeaseGrantRequest(ref req) => vec![req.id],
             RequestWrapper::LeaseRevokeRequest(ref req) => vec![req.id],
+            RequestWrapper::LeaseCheckpointRequest(ref req) => {
+                req.checkpoints.iter().map(|cp| cp.id).collect()
+            }
             _ => vec![],
         }
     }
@@ -539,7 +564,10 @@ impl_from_requests!(
     LeaseGrantRequest,
     LeaseRevokeRequest,
     LeaseLeasesRequest,
-    AlarmRequest
+    LeaseCheckpointRequest,
+    AlarmRequest,
+    DowngradeRequest,
+    ClusterVersionSetRequest
 );
 
 impl_from_responses!(
@@ -568,7 +596,10 @@ impl_from_responses!(
     LeaseGrantResponse,
     LeaseRevokeResponse,
     LeaseLeasesResponse,
-    AlarmResponse
+    LeaseCheckpointResponse,
+    AlarmResponse,
+    DowngradeResponse,
+    ClusterVersionSetResponse
 );
 
 impl From<RequestOp> for RequestWrapper {
@@ -631,6 +662,22 @@ impl TxnRequest {
         self.success.iter().all(read_only_checker) && self.failure.iter().all(read_only_checker)
     }
 
+    /// Checks whether a given `TxnRequest` contains any put request.
+    pub fn has_put(&self) -> bool {
+        let put_checker = |req: &RequestOp| {
+            if let Some(ref request) = req.request {
+                match request {
+                    Request::RequestPut(_) => true,
+                    Request::RequestRange(_) | Request::RequestDeleteRange(_) => false,
+                    Request::RequestTxn(req) => req.has_put(),
+                }
+            } else {
+                false
+            }
+        };
+        self.success.iter().any(put_checker) || self.failure.iter().any(put_checker)
+    }
+
     /// Checks whether a given `TxnRequest` is serializable or not.
     pub fn is_serializable(&self) -> bool {
         let serializable_checker = |req: &RequestOp| {
@@ -1076,6 +1123,37 @@ mod test {
         assert!(!read_write_nested_txn_req.is_read_only());
     }
 
+    #[test]
+    fn txn_request_has_put_should_success() {
+        let delete_txn_req = TxnRequest {
+            compare: vec![],
+            success: vec![RequestOp {
+                request: Some(Request::RequestDeleteRange(DeleteRangeRequest::default())),
+            }],
+            failure: vec![RequestOp {
+                request: Some(Request::RequestRange(RangeRequest::default())),
+            }],
+        };
+
+        assert!(!delete_txn_req.has_put());
+
+        let nested_put_txn_req = TxnRequest {
+            compare: vec![],
+            success: vec![],
+            failure: vec![RequestOp {
+                request: Some(Request::RequestTxn(TxnRequest {
+                    compare: vec![],
+                    success: vec![RequestOp {
+                        request: Some(Request::RequestPut(PutRequest::default())),
+                    }],
+                    failure: vec![],
+                })),
+            }],
+        };
+
+        assert!(nested_put_txn_req.has_put());
+    }
+
     #[test]
     fn txn_request_is_serializable_should_success() {
         let serializable_req = Some(Request::RequestRange(RangeRequest {
@@ -20,6 +20,60 @@ pub trait RequestValidator {
     fn validation(&self) -> Result<(), ValidationError>;
 }
 
+/// Limits of the size of keys and values written by a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeLimits {
+    /// Max size of a key in bytes
+    max_key_bytes: usize,
+    /// Max size of a value in bytes
+    max_value_bytes: usize,
+}
+
+impl SizeLimits {
+    /// New `SizeLimits`
+    #[inline]
+    #[must_use]
+    pub fn new(max_key_bytes: usize, max_value_bytes: usize) -> Self {
+        Self {
+            max_key_bytes,
+            max_value_bytes,
+        }
+    }
+}
+
+/// Trait for checking the size of keys and values written by a request
+pub trait SizeValidator {
+    /// Check the request against the size limits
+    fn check_size(&self, limits: SizeLimits) -> Result<(), ValidationError>;
+}
+
+impl SizeValidator for PutRequest {
+    fn check_size(&self, limits: SizeLimits) -> Result<(), ValidationError> {
+        if self.key.len() > limits.max_key_bytes {
+            return Err(ValidationError::KeyTooLarge);
+        }
+        if self.value.len() > limits.max_value_bytes {
+            return Err(ValidationError::ValueTooLarge);
+        }
+
+        Ok(())
+    }
+}
+
+impl SizeValidator for TxnRequest {
+    fn check_size(&self, limits: SizeLimits) -> Result<(), ValidationError> {
+        for op in self.success.iter().chain(self.failure.iter()) {
+            match op.request {
+                Some(Request::RequestPut(ref r)) => r.check_size(limits)?,
+                Some(Request::RequestTxn(ref r)) => r.check_size(limits)?,
+                Some(Request::RequestRange(_) | Request::RequestDeleteRange(_)) | None => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
 impl RequestValidator for RangeRequest {
     fn validation(&self) -> Result<(), ValidationError> {
         if self.key.is_empty() {
@@ -288,6 +342,12 @@ pub enum ValidationError {
     /// Permission not given
     #[error("permission not given")]
     PermissionNotGiven,
+    /// Key exceeds the max key size
+    #[error("key is too large")]
+    KeyTooLarge,
+    /// Value exceeds the max value size
+    #[error("value is too large")]
+    ValueTooLarge,
 }
 
 // The etcd client relies on GRPC error messages for error type interpretation.
@@ -334,6 +394,15 @@ impl From<ValidationError> for tonic::Status {
                 tonic::Code::InvalidArgument,
                 "etcdserver: permission not given".to_owned(),
             ),
+            ValidationError::KeyTooLarge => (
+                tonic::Code::InvalidArgument,
+                "etcdserver: key is too large".to_owned(),
+            ),
+            // etcd clients recognize oversized writes by this message
+            ValidationError::ValueTooLarge => (
+                tonic::Code::InvalidArgument,
+                "etcdserver: request is too large".to_owned(),
+            ),
             ValidationError::RequestNotProvided | ValidationError::PasswordEmpty => {
                 (tonic::Code::InvalidArgument, err.to_string())
             }
@@ -482,6 +551,50 @@ mod test {
         run_test(testcases);
     }
 
+    #[test]
+    fn oversized_put_should_have_correct_error_msg() {
+        let limits = SizeLimits::new(4, 8);
+        let put = |key: &str, value: &str| PutRequest {
+            key: key.into(),
+            value: value.into(),
+            ..Default::default()
+        };
+        assert!(put("k", "v").check_size(limits).is_ok());
+        assert_eq!(
+            put("large", "v").check_size(limits),
+            Err(ValidationError::KeyTooLarge)
+        );
+        assert_eq!(
+            put("k", "large value").check_size(limits),
+            Err(ValidationError::ValueTooLarge)
+        );
+
+        let nested = TxnRequest {
+            compare: vec![],
+            success: vec![],
+            failure: vec![RequestOp {
+                request: Some(Request::RequestPut(put("k", "large value"))),
+            }],
+        };
+        let txn = TxnRequest {
+            compare: vec![],
+            success: vec![RequestOp {
+                request: Some(Request::RequestPut(put("k", "v"))),
+            }],
+            failure: vec![RequestOp {
+                request: Some(Request::RequestTxn(nested)),
+            }],
+        };
+        assert_eq!(txn.check_size(limits), Err(ValidationError::ValueTooLarge));
+
+        let key_status = tonic::Status::from(ValidationError::KeyTooLarge);
+        assert_eq!(key_status.code(), tonic::Code::InvalidArgument);
+        assert_eq!(key_status.message(), "etcdserver: key is too large");
+        let value_status = tonic::Status::from(ValidationError::ValueTooLarge);
+        assert_eq!(value_status.code(), tonic::Code::InvalidArgument);
+        assert_eq!(value_status.message(), "etcdserver: request is too large");
+    }
+
     #[test]
     fn invalid_user_add_request_should_have_correct_error_msg() {
         let testcases = vec![
@@ -1,10 +1,12 @@
 use std::{fs::File, io::Write, path::PathBuf};
 
-use clap::{arg, ArgMatches, Command};
+use clap::{arg, value_parser, ArgMatches, Command};
 use xline_client::{
     error::{Result, XlineClientError},
+    types::kv::RangeOptions,
     Client,
 };
+use xlineapi::key_util::UNBOUNDED;
 
 /// Definition of `snapshot` command
 pub(crate) fn command() -> Command {
@@ -13,7 +15,11 @@ pub(crate) fn command() -> Command {
         .subcommand(
             Command::new("save")
                 .about("save snapshot")
-                .arg(arg!(<filename> "save snapshot to the give filename")),
+                .arg(arg!(<filename> "save snapshot to the give filename"))
+                .arg(
+                    arg!(--since <REVISION> "Only save the revisions after the given revision")
+                        .value_parser(value_parser!(i64)),
+                ),
         )
 }
 
@@ -22,6 +28,9 @@ pub(crate) async fn execute(client: &mut Client, matches: &ArgMatches) -> Result
     if let Some(("save", sub_matches)) = matches.subcommand() {
         let filename = sub_matches.get_one::<String>("filename").expect("required");
         let path = PathBuf::from(filename);
+        if let Some(&since) = sub_matches.get_one::<i64>("since") {
+            return save_increment(client, since, filename, path).await;
+        }
         let mut resp = client.maintenance_client().snapshot().await?;
 
         if path.exists() || path.is_dir() {
@@ -45,3 +54,26 @@ pub(crate) async fn execute(client: &mut Client, matches: &ArgMatches) -> Result
 
     Ok(())
 }
+
+/// Save the revisions after `since` to the file as an incremental backup
+async fn save_increment(client: &Client, since: i64, filename: &str, path: PathBuf) -> Result<()> {
+    if path.exists() || path.is_dir() {
+        eprintln!("file exist: {filename}");
+        return Ok(());
+    }
+
+    let options = RangeOptions::default()
+        .with_range_end(UNBOUNDED)
+        .with_count_only(true);
+    let end = client
+        .kv_client()
+        .range(UNBOUNDED, Some(options))
+        .await?
+        .header
+        .map_or(0, |header| header.revision);
+    let mut file = File::create(path).map_err(|err| XlineClientError::IoError(err.to_string()))?;
+    let count = client.watch_client().backup(since, end, &mut file).await?;
+
+    println!("{count} key-values of revisions ({since}, {end}] saved to: {filename}");
+    Ok(())
+}
@@ -0,0 +1,67 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use clap::{arg, value_parser, ArgMatches, Command};
+use serde::Serialize;
+use xline::bulk_import::{bulk_import, DEFAULT_IMPORT_BATCH_SIZE};
+
+use crate::printer::Printer;
+
+/// Definition of `import` command
+pub(crate) fn command() -> Command {
+    Command::new("import")
+        .about("Imports key-values into the data directory of a new xline member")
+        .arg(arg!(<filename> "Path to a file of length-delimited protobuf encoded key-values sorted by the keys"))
+        .arg(arg!(--"data-dir" <DATA_DIR> "Path to the output data directory"))
+        .arg(
+            arg!(--"batch-size" <BATCH_SIZE> "Number of key-values written with one revision")
+                .value_parser(value_parser!(usize))
+                .default_value(DEFAULT_IMPORT_BATCH_SIZE.to_string()),
+        )
+}
+
+/// Execute the command
+pub(crate) async fn execute(matches: &ArgMatches) -> Result<()> {
+    let source = matches.get_one::<String>("filename").expect("required");
+    let data_dir = matches.get_one::<String>("data-dir").expect("required");
+    let batch_size = *matches.get_one::<usize>("batch-size").expect("required");
+    handle_import(source.clone(), data_dir, batch_size).await
+}
+
+/// Import summary
+#[derive(Debug, Serialize)]
+struct Summary {
+    /// Number of imported key-value pairs
+    count: u64,
+    /// Last revision written by the import
+    revision: i64,
+}
+
+/// handle import key-values to data dir
+#[inline]
+async fn handle_import<P: AsRef<Path> + Send + 'static, D: Into<PathBuf>>(
+    source: P,
+    data_dir: D,
+    batch_size: usize,
+) -> Result<()> {
+    let data_dir = data_dir.into();
+    let summary =
+        tokio::task::spawn_blocking(move || bulk_import(source, data_dir, batch_size)).await??;
+    Summary {
+        count: summary.count,
+        revision: summary.revision,
+    }
+    .print();
+    Ok(())
+}
+
+impl Printer for Summary {
+    fn simple(&self) {
+        println!("{}, {}", self.count, self.revision);
+    }
+
+    fn field(&self) {
+        println!("Keys : {}", self.count);
+        println!("Revision : {}", self.revision);
+    }
+}
@@ -1,2 +1,4 @@
+/// Import command
+pub(super) mod import;
 /// Snapshot command
 pub(super) mod snapshot;
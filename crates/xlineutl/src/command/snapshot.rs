@@ -1,15 +1,22 @@
 use std::{
     hash::Hasher,
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use clap::{arg, ArgMatches, Command};
-use engine::{Engine, EngineType, StorageEngine};
+use engine::{Engine, EngineType, KeyProvider, StaticKeyProvider, StorageEngine};
 use serde::Serialize;
 use tempfile::tempdir;
-use utils::table_names::{KV_TABLE, XLINE_TABLES};
-use xline::storage::Revision;
+use utils::{
+    parse_members,
+    table_names::{KV_TABLE, XLINE_TABLES},
+};
+use xline::{
+    restore::{apply_increment, restore, write_membership, Membership},
+    storage::Revision,
+};
 
 use crate::printer::Printer;
 
@@ -19,9 +26,33 @@ pub(crate) fn command() -> Command {
         .about("Manages xline node snapshots")
         .subcommand(
             Command::new("restore")
-                .about("Restores an xline member snapshot to an xline directory")
+                .about("Restores an xline member snapshot to the data directory of a new member")
                 .arg(arg!(<filename> "Path to the snapshot file"))
-                .arg(arg!(--"data-dir" <DATA_DIR> "Path to the output data directory")),
+                .arg(arg!(--"data-dir" <DATA_DIR> "Path to the output data directory"))
+                .arg(
+                    arg!(--name <NAME> "Name of the member in the new cluster")
+                        .requires("initial-cluster"),
+                )
+                .arg(
+                    arg!(--"initial-cluster" <INITIAL_CLUSTER> "Peer urls of the members of the new cluster, e.g. node1=url1,node2=url2")
+                        .requires("name"),
+                )
+                .arg(
+                    arg!(--"client-urls" <CLIENT_URLS> "Client urls advertised by the member, separated by commas")
+                        .requires("name"),
+                )
+                .arg(
+                    arg!(--"curp-dir" <CURP_DIR> "Path to the curp data directory of the member, defaults to <DATA_DIR>/curp")
+                        .requires("name"),
+                )
+                .arg(arg!(--"encryption-key-file" <ENCRYPTION_KEY_FILE> "Path to the keys the values of the snapshot are encrypted with")),
+        )
+        .subcommand(
+            Command::new("apply")
+                .about("Applies an incremental backup onto a restored xline data directory")
+                .arg(arg!(<filename> "Path to the incremental backup file"))
+                .arg(arg!(--"data-dir" <DATA_DIR> "Path to the restored data directory"))
+                .arg(arg!(--"encryption-key-file" <ENCRYPTION_KEY_FILE> "Path to the keys the values of the restored data directory are encrypted with")),
         )
         .subcommand(
             Command::new("status")
@@ -36,7 +67,24 @@ pub(crate) async fn execute(matches: &ArgMatches) -> Result<()> {
         Some(("restore", sub_matches)) => {
             let snapshot_path = sub_matches.get_one::<String>("filename").expect("required");
             let data_dir = sub_matches.get_one::<String>("data-dir").expect("required");
-            handle_restore(snapshot_path, data_dir).await?;
+            let membership = sub_matches
+                .get_one::<String>("name")
+                .map(|name| membership_of(name, sub_matches))
+                .transpose()?;
+            let key_provider = key_provider_of(sub_matches)?;
+            handle_restore(snapshot_path, data_dir, key_provider).await?;
+            if let Some(membership) = membership {
+                let curp_dir = sub_matches
+                    .get_one::<String>("curp-dir")
+                    .map_or_else(|| PathBuf::from(data_dir).join("curp"), PathBuf::from);
+                write_membership(curp_dir, &membership)?;
+            }
+        }
+        Some(("apply", sub_matches)) => {
+            let source = sub_matches.get_one::<String>("filename").expect("required");
+            let data_dir = sub_matches.get_one::<String>("data-dir").expect("required");
+            let key_provider = key_provider_of(sub_matches)?;
+            handle_apply(source.clone(), data_dir, key_provider).await?;
         }
         Some(("status", sub_matches)) => {
             let snapshot_path = sub_matches.get_one::<String>("filename").expect("required");
@@ -48,19 +96,80 @@ pub(crate) async fn execute(matches: &ArgMatches) -> Result<()> {
     Ok(())
 }
 
+/// Build the membership of the new cluster from the args
+fn membership_of(name: &str, matches: &ArgMatches) -> Result<Membership> {
+    let initial_cluster = matches
+        .get_one::<String>("initial-cluster")
+        .expect("required by name");
+    let initial_cluster =
+        parse_members(initial_cluster).map_err(|e| anyhow!("invalid initial cluster: {e}"))?;
+    let client_urls = matches
+        .get_one::<String>("client-urls")
+        .map(|urls| urls.split(',').map(str::to_owned).collect())
+        .unwrap_or_default();
+    Ok(Membership::new(
+        name.to_owned(),
+        initial_cluster,
+        client_urls,
+    ))
+}
+
+/// Load the encryption keys from the key file of the args
+fn key_provider_of(matches: &ArgMatches) -> Result<Option<Arc<dyn KeyProvider>>> {
+    matches
+        .get_one::<String>("encryption-key-file")
+        .map(|path| {
+            let provider = StaticKeyProvider::from_file(path)
+                .map_err(|e| anyhow!("cannot load encryption keys from {path}: {e}"))?;
+            Ok(Arc::new(provider) as Arc<dyn KeyProvider>)
+        })
+        .transpose()
+}
+
 /// handle restore snapshot to data dir
 #[inline]
 async fn handle_restore<P: AsRef<Path>, D: Into<PathBuf>>(
     snapshot_path: P,
     data_dir: D,
+    key_provider: Option<Arc<dyn KeyProvider>>,
 ) -> Result<()> {
-    let restore_rocks_engine = Engine::new(EngineType::Rocks(data_dir.into()), &XLINE_TABLES)?;
-    restore_rocks_engine
-        .apply_snapshot_from_file(snapshot_path, &XLINE_TABLES)
-        .await?;
+    let summary = restore(snapshot_path, data_dir, key_provider).await?;
+    RestoreSummary {
+        count: summary.count,
+        revision: summary.revision,
+    }
+    .print();
     Ok(())
 }
 
+/// handle apply incremental backup to data dir
+#[inline]
+async fn handle_apply<P: AsRef<Path> + Send + 'static, D: Into<PathBuf>>(
+    source: P,
+    data_dir: D,
+    key_provider: Option<Arc<dyn KeyProvider>>,
+) -> Result<()> {
+    let data_dir = data_dir.into();
+    let summary =
+        tokio::task::spawn_blocking(move || apply_increment(source, data_dir, key_provider))
+            .await??;
+    RestoreSummary {
+        count: summary.count,
+        revision: summary.revision,
+    }
+    .print();
+    Ok(())
+}
+
+/// Restore summary
+#[derive(Debug, Serialize)]
+struct RestoreSummary {
+    /// Number of keys alive at the restored revision
+    count: u64,
+    /// Revision of the restored store
+    revision: i64,
+}
+
 /// Snapshot status
 #[derive(Debug, Default, Serialize)]
 struct Status {
@@ -111,6 +220,17 @@ async fn handle_status<P: AsRef<Path>>(snapshot_path: P) -> Result<()> {
     Ok(())
 }
 
+impl Printer for RestoreSummary {
+    fn simple(&self) {
+        println!("{}, {}", self.count, self.revision);
+    }
+
+    fn field(&self) {
+        println!("Keys : {}", self.count);
+        println!("Revision : {}", self.revision);
+    }
+}
+
 impl Printer for Status {
     fn simple(&self) {
         println!(
@@ -157,7 +157,7 @@
 
 use anyhow::Result;
 use clap::{arg, Command};
-use command::snapshot;
+use command::{import, snapshot};
 use printer::{set_printer_type, PrinterType};
 
 /// Command definitions and parsers
@@ -180,6 +180,7 @@ fn cli() -> Command {
                 .default_value("SIMPLE"),
         )
         .subcommand(snapshot::command())
+        .subcommand(import::command())
 }
 
 #[tokio::main]
@@ -196,8 +197,10 @@ async fn main() -> Result<()> {
         _ => unreachable!("already checked by clap"),
     };
     set_printer_type(printer_type);
-    if let Some(("snapshot", sub_matches)) = matches.subcommand() {
-        snapshot::execute(sub_matches).await?;
+    match matches.subcommand() {
+        Some(("snapshot", sub_matches)) => snapshot::execute(sub_matches).await?,
+        Some(("import", sub_matches)) => import::execute(sub_matches).await?,
+        _ => {}
     }
     Ok(())
 }